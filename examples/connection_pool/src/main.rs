@@ -0,0 +1,70 @@
+//! Times reads from a local `MockPds` with different connection settings, to show what the pool
+//! and HTTP/2 options of `ClientBuilder` are worth.
+
+use bisky::atproto::{Client, ClientBuilder};
+use bisky::mock::{MockPds, MOCK_DID, MOCK_HANDLE, MOCK_PASSWORD};
+use serde_json::{json, Value};
+use std::time::Instant;
+
+const REQUESTS: usize = 2000;
+const CONCURRENCY: usize = 32;
+
+async fn client(pds: &MockPds, configure: impl Fn(&mut ClientBuilder)) -> Client {
+    let mut builder = ClientBuilder::default();
+    builder.service(pds.url().clone());
+    configure(&mut builder);
+    builder.login(MOCK_HANDLE, MOCK_PASSWORD).await.unwrap()
+}
+
+/// Requests per second over `REQUESTS` reads, `CONCURRENCY` at a time
+async fn throughput(client: Client, rkey: &str) -> f64 {
+    let start = Instant::now();
+    let tasks = (0..CONCURRENCY)
+        .map(|_| {
+            let mut client = client.clone();
+            let rkey = rkey.to_string();
+            tokio::spawn(async move {
+                for _ in 0..REQUESTS / CONCURRENCY {
+                    client
+                        .repo_get_record::<Value>(MOCK_DID, "app.bsky.feed.post", &rkey)
+                        .await
+                        .unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    (REQUESTS / CONCURRENCY * CONCURRENCY) as f64 / start.elapsed().as_secs_f64()
+}
+
+#[tokio::main]
+async fn main() {
+    let pds = MockPds::start().await;
+    let post = json!({ "text": "hello", "createdAt": "2023-01-01T00:00:00Z" });
+    pds.insert_record(MOCK_DID, "app.bsky.feed.post", Some("post"), &post);
+
+    // Over loopback a single HTTP/2 connection is usually slower than a pool of HTTP/1.1 ones,
+    // which gain from running on several threads; it is over real networks that sharing one
+    // connection pays off
+    let configs: [(&str, Box<dyn Fn(&mut ClientBuilder)>); 3] = [
+        ("HTTP/1.1, default pool", Box::new(|_| {})),
+        (
+            "HTTP/1.1, no idle connections kept",
+            Box::new(|builder| {
+                builder.pool_max_idle_per_host(0);
+            }),
+        ),
+        (
+            "HTTP/2 prior knowledge",
+            Box::new(|builder| {
+                builder.http2_prior_knowledge(true).http2_adaptive_window(true);
+            }),
+        ),
+    ];
+    for (name, configure) in configs {
+        let client = client(&pds, configure).await;
+        println!("{name}: {:.0} requests/s", throughput(client, "post").await);
+    }
+}
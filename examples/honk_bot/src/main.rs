@@ -2,9 +2,9 @@ use bisky::atproto::{Client, ClientBuilder, UserSession};
 use bisky::bluesky::Bluesky;
 use bisky::lexicon::app::bsky::feed::{ImagesEmbed, ReplyRef};
 use bisky::lexicon::app::bsky::embed::{Image};
-use bisky::lexicon::app::bsky::notification::{Notification, NotificationRecord};
+use bisky::lexicon::app::bsky::notification::{Notification, NotificationReason, NotificationRecord};
 use bisky::lexicon::com::atproto::repo::StrongRef;
-use bisky::lexicon::app::bsky::feed::Post;
+use bisky::lexicon::app::bsky::feed::PostBuilder;
 use bisky::lexicon::app::bsky::notification::NotificationRecord::Post as NotificationPost;
 use bisky::lexicon::app::bsky::feed::Embeds;
 use bisky::storage::File;
@@ -63,7 +63,7 @@ async fn main() {
         let mut me = bsky.me().unwrap();
         let notifications = me.list_notifications(10).await.unwrap();
         me.update_seen().await.unwrap();
-        let mentions =  notifications.into_iter().filter(|n| (n.reason == "mention" && n.is_read == false)).collect::<Vec<Notification<NotificationRecord>>>();
+        let mentions =  notifications.into_iter().filter(|n| (n.reason == NotificationReason::Mention && n.is_read == false)).collect::<Vec<Notification<NotificationRecord>>>();
         if !mentions.is_empty(){
             println!("Mentions\n{:#?}", mentions);
         }
@@ -101,13 +101,14 @@ async fn main() {
                 let images_embed = ImagesEmbed{images: vec!(image)};
                 let embed = Embeds::Images(images_embed);
         
-                me.post(Post {
-                    rust_type: Some("app.bsky.feed.post".to_string()),
-                    text: "HONK".to_string(),
-                    created_at: chrono::Utc::now(),
-                    embed: Some(embed),
-                    reply: Some(resp_reply_ref),
-                })
+                me.post(
+                    PostBuilder::default()
+                        .text("HONK")
+                        .embed(embed)
+                        .reply(resp_reply_ref)
+                        .build()
+                        .unwrap(),
+                )
                 .await
                 .unwrap();
 
@@ -143,13 +144,13 @@ async fn main() {
                     21_i32..=i32::MAX => "This cannot be! These acts befit a god more than a mortal",
                 };
         
-                me.post(Post {
-                    rust_type: Some("app.bsky.feed.post".to_string()),
-                    text: format!("You rolled a {roll}.\n{msg}"),
-                    created_at: chrono::Utc::now(),
-                    embed: None,
-                    reply: Some(resp_reply_ref),
-                })
+                me.post(
+                    PostBuilder::default()
+                        .text(format!("You rolled a {roll}.\n{msg}"))
+                        .reply(resp_reply_ref)
+                        .build()
+                        .unwrap(),
+                )
                 .await
                 .unwrap();
 
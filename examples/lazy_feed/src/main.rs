@@ -0,0 +1,177 @@
+//! Times decoding a 100 item timeline page in full against decoding it lazily and then only the
+//! 5 items a keyword filter picks out, and checks that both ways decode those items the same.
+
+use bisky::lexicon::app::bsky::feed::{GetTimelineOutput, LazyFeedOutput};
+use serde_json::{json, Value};
+use std::time::Instant;
+
+const ITEMS: usize = 100;
+const ROUNDS: usize = 200;
+const KEYWORD: &str = "rustacean";
+
+/// A timeline page shaped like one from the AppView, with images, links, quotes, labels and
+/// reposts, where every 20th post mentions `KEYWORD`
+fn timeline() -> String {
+    let author = |i: usize| {
+        json!({
+            "did": format!("did:plc:author{i}"),
+            "handle": format!("author{i}.bsky.social"),
+            "displayName": format!("Author {i}"),
+            "avatar": format!("https://cdn.bsky.app/img/avatar/plain/did:plc:author{i}/bafkrei{i}@jpeg"),
+            "viewer": { "muted": false, "blockedBy": false },
+            "labels": [],
+            "createdAt": "2023-04-01T00:00:00.000Z",
+        })
+    };
+    let feed = (0..ITEMS)
+        .map(|i| {
+            let uri = format!("at://did:plc:author{i}/app.bsky.feed.post/3k{i:011}");
+            let text = match i % 20 {
+                0 => format!("Post {i}: another {KEYWORD} joins the crab community"),
+                _ => format!("Post {i}: {}", "just some words about the day ".repeat(8)),
+            };
+            let (record_embed, embed) = match i % 3 {
+                0 => (
+                    json!({
+                        "$type": "app.bsky.embed.images",
+                        "images": [{
+                            "alt": "A photo",
+                            "image": {
+                                "$type": "blob",
+                                "ref": { "$link": format!("bafkreiimage{i}") },
+                                "mimeType": "image/jpeg",
+                                "size": 123456,
+                            },
+                            "aspectRatio": { "width": 1200, "height": 800 },
+                        }],
+                    }),
+                    json!({
+                        "$type": "app.bsky.embed.images#view",
+                        "images": [{
+                            "thumb": format!("https://cdn.bsky.app/img/feed_thumbnail/plain/{i}@jpeg"),
+                            "fullsize": format!("https://cdn.bsky.app/img/feed_fullsize/plain/{i}@jpeg"),
+                            "alt": "A photo",
+                            "aspectRatio": { "width": 1200, "height": 800 },
+                        }],
+                    }),
+                ),
+                1 => (
+                    json!({
+                        "$type": "app.bsky.embed.external",
+                        "external": {
+                            "uri": format!("https://example.com/articles/{i}"),
+                            "title": format!("Article {i}"),
+                            "description": "An article about something or other".repeat(3),
+                        },
+                    }),
+                    json!({
+                        "$type": "app.bsky.embed.external#view",
+                        "external": {
+                            "uri": format!("https://example.com/articles/{i}"),
+                            "title": format!("Article {i}"),
+                            "description": "An article about something or other".repeat(3),
+                            "thumb": format!("https://cdn.bsky.app/img/feed_thumbnail/plain/x{i}@jpeg"),
+                        },
+                    }),
+                ),
+                _ => (
+                    json!({
+                        "$type": "app.bsky.embed.record",
+                        "record": { "uri": format!("at://did:plc:quoted/app.bsky.feed.post/{i}"), "cid": "bafyquoted" },
+                    }),
+                    json!({
+                        "$type": "app.bsky.embed.record#view",
+                        "record": {
+                            "$type": "app.bsky.embed.record#viewRecord",
+                            "uri": format!("at://did:plc:quoted/app.bsky.feed.post/{i}"),
+                            "cid": "bafyquoted",
+                            "author": author(1000 + i),
+                            "value": {
+                                "$type": "app.bsky.feed.post",
+                                "text": "The quoted post, which says quite a lot".repeat(4),
+                                "createdAt": "2024-05-01T00:00:00.000Z",
+                            },
+                            "labels": [],
+                            "likeCount": 40,
+                            "indexedAt": "2024-05-01T00:00:01.000Z",
+                        },
+                    }),
+                ),
+            };
+            let mut item = json!({
+                "post": {
+                    "uri": uri,
+                    "cid": format!("bafyreipost{i}"),
+                    "author": author(i),
+                    "record": {
+                        "$type": "app.bsky.feed.post",
+                        "text": text,
+                        "createdAt": "2024-06-01T12:00:00.000Z",
+                        "langs": ["en"],
+                        "embed": record_embed,
+                        "facets": [{
+                            "index": { "byteStart": 0, "byteEnd": 4 },
+                            "features": [{ "$type": "app.bsky.richtext.facet#tag", "tag": "post" }],
+                        }],
+                    },
+                    "embed": embed,
+                    "replyCount": i,
+                    "repostCount": 2 * i,
+                    "likeCount": 3 * i,
+                    "quoteCount": 1,
+                    "indexedAt": "2024-06-01T12:00:01.000Z",
+                    "viewer": { "threadMuted": false, "embeddingDisabled": false },
+                    "labels": [],
+                },
+            });
+            if i % 7 == 0 {
+                item["reason"] = json!({
+                    "$type": "app.bsky.feed.defs#reasonRepost",
+                    "by": author(2000 + i),
+                    "indexedAt": "2024-06-01T13:00:00.000Z",
+                });
+            }
+            item
+        })
+        .collect::<Vec<Value>>();
+    json!({ "cursor": "next", "feed": feed }).to_string()
+}
+
+fn main() {
+    let page = timeline();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let output: GetTimelineOutput = serde_json::from_str(&page).unwrap();
+        let matches = output
+            .feed
+            .iter()
+            .filter(|item| item.post.record.text.contains(KEYWORD))
+            .count();
+        assert_eq!(matches, 5);
+    }
+    let eager = start.elapsed() / ROUNDS as u32;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let output: LazyFeedOutput = serde_json::from_str(&page).unwrap();
+        let matches = output
+            .feed
+            .iter()
+            .filter(|item| item.text.contains(KEYWORD))
+            .map(|item| item.decode_full().unwrap())
+            .count();
+        assert_eq!(matches, 5);
+    }
+    let lazy = start.elapsed() / ROUNDS as u32;
+
+    let eager_items = serde_json::from_str::<GetTimelineOutput>(&page).unwrap().feed;
+    let lazy_items = serde_json::from_str::<LazyFeedOutput>(&page).unwrap().feed;
+    for (eager, lazy) in eager_items.iter().zip(&lazy_items) {
+        assert_eq!(format!("{eager:?}"), format!("{:?}", lazy.decode_full().unwrap()));
+    }
+
+    println!("{} byte page of {ITEMS} items", page.len());
+    println!("Decoding every item: {eager:?}");
+    println!("Decoding 5 items lazily: {lazy:?}");
+}
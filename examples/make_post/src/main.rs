@@ -1,6 +1,6 @@
 use bisky::atproto::{Client, ClientBuilder, UserSession};
 use bisky::bluesky::Bluesky;
-use bisky::lexicon::app::bsky::feed::Post;
+use bisky::lexicon::app::bsky::feed::PostBuilder;
 use bisky::storage::{File, Storage as _};
 use clap::Parser;
 use std::path::PathBuf;
@@ -37,10 +37,7 @@ async fn main() {
         bsky
             .me()
             .unwrap()
-            .post(Post {
-                text: args.post_text,
-                created_at: chrono::Utc::now(),
-            })
+            .post(PostBuilder::default().text(args.post_text).build().unwrap())
             .await
             .unwrap()
     );
@@ -1,6 +1,6 @@
 use bisky::atproto::{Client, ClientBuilder, UserSession};
 use bisky::bluesky::Bluesky;
-use bisky::lexicon::app::bsky::feed::{Post, Embeds, ImagesEmbed};
+use bisky::lexicon::app::bsky::feed::{PostBuilder, Embeds, ImagesEmbed};
 use bisky::lexicon::app::bsky::embed::{Image};
 
 use bisky::storage::{File, Storage as _};
@@ -50,12 +50,13 @@ async fn main() {
         bsky
             .me()
             .unwrap()
-            .post(Post {
-                rust_type: Some("app.bsky.feed.post".to_string()),
-                text: args.post_text,
-                created_at: chrono::Utc::now(),
-                embed: Some(embed),
-            })
+            .post(
+                PostBuilder::default()
+                    .text(args.post_text)
+                    .embed(embed)
+                    .build()
+                    .unwrap()
+            )
             .await
             .unwrap()
     );
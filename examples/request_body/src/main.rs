@@ -0,0 +1,104 @@
+//! Counts the memory a `Client` allocates to send a record of about 1MB, with and without having
+//! to refresh its session and send the record again. The record is created by a transport that
+//! answers without reading the request, so what is counted is the client's own work.
+
+use async_trait::async_trait;
+use bisky::atproto::{ClientBuilder, XrpcTransport};
+use bisky::errors::BiskyError;
+use bisky::mock::{MockPds, MOCK_DID, MOCK_HANDLE, MOCK_PASSWORD};
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const RECORD_SIZE: usize = 1024 * 1024;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Answers createRecord itself, the first time with an expired token if `expire` is set, and
+/// sends everything else on to the mock
+struct CreateRecord {
+    expire: AtomicBool,
+}
+
+#[async_trait]
+impl XrpcTransport for CreateRecord {
+    async fn execute(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, BiskyError> {
+        if !request.url().path().ends_with("com.atproto.repo.createRecord") {
+            return Ok(http.execute(request).await?);
+        }
+        let (status, body) = match self.expire.swap(false, Ordering::Relaxed) {
+            true => (400, json!({ "error": "ExpiredToken", "message": "Token has expired" })),
+            false => (200, json!({ "uri": "at://did:plc:alice/app.bsky.feed.post/a", "cid": "b" })),
+        };
+        Ok(http::Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .unwrap()
+            .into())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let pds = MockPds::start().await;
+    let record = json!({
+        "$type": "app.bsky.feed.post",
+        "text": "a".repeat(RECORD_SIZE),
+        "createdAt": "2023-01-01T00:00:00Z",
+    });
+
+    for expire in [false, true] {
+        let transport = Arc::new(CreateRecord {
+            expire: AtomicBool::new(expire),
+        });
+        let mut client = ClientBuilder::default()
+            .service(pds.url().clone())
+            .transport(transport)
+            .login(MOCK_HANDLE, MOCK_PASSWORD)
+            .await
+            .unwrap();
+
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        client
+            .repo_create_record::<Value, _>(MOCK_DID, "app.bsky.feed.post", &record)
+            .await
+            .unwrap();
+        let allocated = ALLOCATED.load(Ordering::Relaxed) - before;
+        println!(
+            "{}: {:.1}MB allocated for a {:.1}MB record",
+            match expire {
+                false => "Sent once",
+                true => "Sent again after a refresh",
+            },
+            allocated as f64 / 1e6,
+            RECORD_SIZE as f64 / 1e6,
+        );
+    }
+}
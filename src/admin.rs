@@ -0,0 +1,182 @@
+//! com.atproto.admin, for the administrator of a PDS. These endpoints authenticate with HTTP
+//! basic auth as `admin` with the PDS admin password instead of a user session.
+
+use crate::atproto::check_status;
+use crate::errors::{ApiError, BiskyError};
+use crate::lexicon::com::atproto::admin::{
+    AccountInvites, AccountView, DeleteAccount, GetAccountInfosOutput, StatusAttr, SubjectStatus,
+    UpdateSubjectStatus,
+};
+use crate::lexicon::com::atproto::moderation::ReportSubject;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A client for the admin endpoints of one PDS
+#[derive(Debug, Clone)]
+pub struct AdminClient {
+    service: reqwest::Url,
+    password: String,
+}
+
+impl AdminClient {
+    /// `service` is the PDS, `password` its `PDS_ADMIN_PASSWORD`
+    pub fn new(service: reqwest::Url, password: &str) -> Self {
+        Self {
+            service,
+            password: password.to_string(),
+        }
+    }
+
+    /// The service this client talks to
+    pub fn service(&self) -> &reqwest::Url {
+        &self.service
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<String, BiskyError> {
+        let response = request
+            .basic_auth("admin", Some(&self.password))
+            .send()
+            .await?;
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED => Err(BiskyError::BadCredentials),
+            reqwest::StatusCode::BAD_REQUEST => Err(BiskyError::ApiError(
+                ApiError::from_response(response).await,
+            )),
+            _ => Ok(check_status(response).await?.text().await?),
+        }
+    }
+
+    async fn xrpc_get<D: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<D, BiskyError> {
+        let request = reqwest::Client::new()
+            .get(self.service.join(&format!("xrpc/{path}")).unwrap())
+            .query(query);
+        Ok(serde_json::from_str(&self.send(request).await?)?)
+    }
+
+    /// Post `body`, returning the response body for the caller to parse if it needs it
+    async fn xrpc_post<D: Serialize>(&self, path: &str, body: &D) -> Result<String, BiskyError> {
+        let request = reqwest::Client::new()
+            .post(self.service.join(&format!("xrpc/{path}")).unwrap())
+            .json(body);
+        self.send(request).await
+    }
+
+    ///com.atproto.admin.updateSubjectStatus
+    /// Apply or lift a takedown of an account, record or blob, or deactivate an account. `None`
+    /// leaves that status as it is.
+    pub async fn update_subject_status(
+        &self,
+        subject: &ReportSubject,
+        takedown: Option<StatusAttr>,
+        deactivated: Option<StatusAttr>,
+    ) -> Result<SubjectStatus, BiskyError> {
+        let text = self
+            .xrpc_post(
+                "com.atproto.admin.updateSubjectStatus",
+                &UpdateSubjectStatus {
+                    subject,
+                    takedown,
+                    deactivated,
+                },
+            )
+            .await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Take down `subject`, with `reference` recording why, e.g. a moderation event id
+    pub async fn takedown(
+        &self,
+        subject: &ReportSubject,
+        reference: Option<&str>,
+    ) -> Result<SubjectStatus, BiskyError> {
+        let takedown = StatusAttr {
+            applied: true,
+            reference: reference.map(str::to_string),
+        };
+        self.update_subject_status(subject, Some(takedown), None)
+            .await
+    }
+
+    /// Lift a takedown of `subject`
+    pub async fn reverse_takedown(
+        &self,
+        subject: &ReportSubject,
+    ) -> Result<SubjectStatus, BiskyError> {
+        let takedown = StatusAttr {
+            applied: false,
+            reference: None,
+        };
+        self.update_subject_status(subject, Some(takedown), None)
+            .await
+    }
+
+    ///com.atproto.admin.getSubjectStatus
+    /// The status of the account `did`, or of the record `uri` or the blob `blob` in it
+    pub async fn get_subject_status(
+        &self,
+        did: Option<&str>,
+        uri: Option<&str>,
+        blob: Option<&str>,
+    ) -> Result<SubjectStatus, BiskyError> {
+        let query = [("did", did), ("uri", uri), ("blob", blob)]
+            .into_iter()
+            .filter_map(|(k, v)| v.map(|v| (k, v)))
+            .collect::<Vec<_>>();
+        self.xrpc_get("com.atproto.admin.getSubjectStatus", &query)
+            .await
+    }
+
+    ///com.atproto.admin.getAccountInfo
+    pub async fn get_account_info(&self, did: &str) -> Result<AccountView, BiskyError> {
+        self.xrpc_get("com.atproto.admin.getAccountInfo", &[("did", did)])
+            .await
+    }
+
+    ///com.atproto.admin.getAccountInfos
+    pub async fn get_account_infos(&self, dids: &[&str]) -> Result<Vec<AccountView>, BiskyError> {
+        let query = dids.iter().map(|did| ("dids", *did)).collect::<Vec<_>>();
+        let response = self
+            .xrpc_get::<GetAccountInfosOutput>("com.atproto.admin.getAccountInfos", &query)
+            .await?;
+        Ok(response.infos)
+    }
+
+    ///com.atproto.admin.disableAccountInvites
+    pub async fn disable_account_invites(
+        &self,
+        account: &str,
+        note: Option<&str>,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post(
+            "com.atproto.admin.disableAccountInvites",
+            &AccountInvites { account, note },
+        )
+        .await?;
+        Ok(())
+    }
+
+    ///com.atproto.admin.enableAccountInvites
+    pub async fn enable_account_invites(
+        &self,
+        account: &str,
+        note: Option<&str>,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post(
+            "com.atproto.admin.enableAccountInvites",
+            &AccountInvites { account, note },
+        )
+        .await?;
+        Ok(())
+    }
+
+    ///com.atproto.admin.deleteAccount
+    /// Delete the account `did` and everything in its repo, which cannot be undone
+    pub async fn delete_account(&self, did: &str) -> Result<(), BiskyError> {
+        self.xrpc_post("com.atproto.admin.deleteAccount", &DeleteAccount { did })
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,588 @@
+//! Export an account's posts as JSON lines, with a manifest of the blobs they use, e.g. to keep
+//! a copy before deleting the account, and its followers and follows as CSV or JSON.
+
+use crate::atproto::ClientBuilder;
+use crate::atproto::{ChunkOptions, Client};
+use crate::bsky::BskyClient;
+use crate::errors::BiskyError;
+use crate::identity::resolve_did;
+use crate::lexicon::app::bsky::actor::ProfileView;
+use crate::lexicon::app::bsky::feed::{FeedReason, MAX_GET_POSTS};
+use crate::lexicon::app::bsky::graph::{GetFollowersOutput, GetFollowsOutput};
+use crate::lexicon::com::atproto::repo::{Blob, Record};
+use crate::storage::Storage;
+use crate::types::{AtUri, Datetime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Where an archive got to, saved after every page
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveCursor {
+    phase: Phase,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Phase {
+    #[default]
+    Posts,
+    Reposts,
+    Done,
+}
+
+/// What `BskyClient::bsky_archive_posts` writes, and where it keeps its place
+pub struct ArchiveOptions<'a> {
+    /// Only posts made, or reposts made, at or after this time
+    pub since: Option<Datetime>,
+    /// Only posts made, or reposts made, before this time
+    pub until: Option<Datetime>,
+    pub include_replies: bool,
+    pub include_reposts: bool,
+    /// Where to write a JSON object per line for every blob the archived posts use
+    pub manifest: Option<&'a mut (dyn Write + Send)>,
+    /// Download the blobs into this directory, each named by its CID. Those already there are
+    /// left alone.
+    pub blob_dir: Option<PathBuf>,
+    /// Where to keep the cursor, so that an archive that was interrupted picks up where it
+    /// stopped. A page that was being written when it stopped is written again.
+    pub storage: Option<&'a dyn Storage<ArchiveCursor, Error = BiskyError>>,
+}
+
+impl Default for ArchiveOptions<'_> {
+    fn default() -> Self {
+        Self {
+            since: None,
+            until: None,
+            include_replies: true,
+            include_reposts: false,
+            manifest: None,
+            blob_dir: None,
+            storage: None,
+        }
+    }
+}
+
+/// How much `BskyClient::bsky_archive_posts` wrote in this run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveReport {
+    pub posts: usize,
+    pub reposts: usize,
+    /// Entries written to the manifest
+    pub blobs: usize,
+}
+
+/// How `BskyClient::bsky_export_graph` writes the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// A header, then a row of `relation,did,handle,displayName,indexedAt` per account, where
+    /// the relation is `follower` or `follow`
+    Csv,
+    /// An object with the `followers` and `follows` arrays of profiles
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphRelation {
+    Followers,
+    Follows,
+}
+
+/// A line of the archive. For a repost the uri, cid and record are those of the post that was
+/// reposted.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedPost<'a> {
+    uri: &'a str,
+    cid: &'a str,
+    record: &'a Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repost_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    like_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quote_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reposted_at: Option<Datetime>,
+}
+
+/// A line of the manifest
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestEntry<'a> {
+    cid: &'a str,
+    mime_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+    /// The post that uses it
+    post: &'a str,
+}
+
+/// A post view with its record kept as it was sent
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPostView {
+    uri: String,
+    cid: String,
+    record: Value,
+    reply_count: Option<usize>,
+    repost_count: Option<usize>,
+    like_count: Option<usize>,
+    quote_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPosts {
+    posts: Vec<RawPostView>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFeedItem {
+    post: RawPostView,
+    reason: Option<FeedReason>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawFeed {
+    feed: Vec<RawFeedItem>,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepostRecord {
+    created_at: Datetime,
+    subject: RepostSubject,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepostSubject {
+    uri: String,
+}
+
+struct Entry {
+    post: RawPostView,
+    /// When the post or repost was made
+    at: Option<Datetime>,
+    reposted: bool,
+}
+
+impl BskyClient {
+    /// Write the posts of `actor`, a DID or handle, to `writer` as one JSON object per line,
+    /// newest first, with the record as it is stored and the like, repost, reply and quote
+    /// counts when the AppView has them.
+    ///
+    /// The logged in user's own posts are read from their repo, so that none are missed, and
+    /// hydrated through the AppView. Anyone else's are read from their author feed.
+    pub async fn bsky_archive_posts(
+        &mut self,
+        actor: &str,
+        writer: &mut (dyn Write + Send),
+        mut options: ArchiveOptions<'_>,
+    ) -> Result<ArchiveReport, BiskyError> {
+        let did = match actor.starts_with("did:") {
+            true => actor.to_string(),
+            false => self.identity_resolve_handle(actor).await?,
+        };
+        let own = self.session_did().is_ok_and(|own| own == did);
+
+        let mut state = match options.storage {
+            // Nothing stored yet reads as an error, most likely a missing file
+            Some(storage) => storage.get().await.unwrap_or_default(),
+            None => ArchiveCursor::default(),
+        };
+        let mut report = ArchiveReport::default();
+        let mut blob_sources = HashMap::new();
+
+        while state.phase != Phase::Done {
+            let (entries, cursor) = match (own, state.phase) {
+                (true, Phase::Posts) => self.own_posts_page(&did, state.cursor.take()).await?,
+                (true, _) => self.own_reposts_page(&did, state.cursor.take()).await?,
+                (false, _) => {
+                    let (replies, since) = (options.include_replies, options.since);
+                    self.author_feed_page(&did, state.cursor.take(), replies, since)
+                        .await?
+                }
+            };
+
+            for entry in entries {
+                // Without a readable time a post is only in an unbounded range
+                let in_range = match entry.at {
+                    Some(at) => {
+                        options.since.is_none_or(|since| at >= since)
+                            && options.until.is_none_or(|until| at < until)
+                    }
+                    None => options.since.is_none() && options.until.is_none(),
+                };
+                let is_reply = entry.post.record.get("reply").is_some();
+                if !in_range
+                    || (is_reply && !options.include_replies)
+                    || (entry.reposted && !options.include_reposts)
+                {
+                    continue;
+                }
+
+                let post = &entry.post;
+                serde_json::to_writer(
+                    &mut *writer,
+                    &ArchivedPost {
+                        uri: &post.uri,
+                        cid: &post.cid,
+                        record: &post.record,
+                        reply_count: post.reply_count,
+                        repost_count: post.repost_count,
+                        like_count: post.like_count,
+                        quote_count: post.quote_count,
+                        reposted_at: entry.reposted.then_some(entry.at).flatten(),
+                    },
+                )?;
+                writer.write_all(b"\n")?;
+                match entry.reposted {
+                    true => report.reposts += 1,
+                    false => report.posts += 1,
+                }
+
+                for blob in Blob::find_all(&post.record) {
+                    if let Some(manifest) = options.manifest.as_mut() {
+                        serde_json::to_writer(
+                            &mut **manifest,
+                            &ManifestEntry {
+                                cid: blob.cid(),
+                                mime_type: blob.mime_type(),
+                                size: blob.size(),
+                                post: &post.uri,
+                            },
+                        )?;
+                        manifest.write_all(b"\n")?;
+                        report.blobs += 1;
+                    }
+                    if let Some(dir) = &options.blob_dir {
+                        let path = dir.join(blob.cid());
+                        if !tokio::fs::try_exists(&path).await? {
+                            let author = post.uri.parse::<AtUri>()?.authority;
+                            let data = self
+                                .download_blob(&mut blob_sources, &author, blob.cid())
+                                .await?;
+                            tokio::fs::write(&path, data).await?;
+                        }
+                    }
+                }
+            }
+            writer.flush()?;
+            if let Some(manifest) = options.manifest.as_mut() {
+                manifest.flush()?;
+            }
+
+            state.cursor = cursor;
+            if state.cursor.is_none() {
+                state.phase = match state.phase {
+                    Phase::Posts if own && options.include_reposts => Phase::Reposts,
+                    _ => Phase::Done,
+                };
+            }
+            if let Some(storage) = options.storage {
+                storage.set(Some(&state)).await?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn own_posts_page(
+        &mut self,
+        did: &str,
+        cursor: Option<String>,
+    ) -> Result<(Vec<Entry>, Option<String>), BiskyError> {
+        let (records, cursor) = self
+            .repo_list_records::<Value>(did, "app.bsky.feed.post", 100, false, cursor)
+            .await?;
+        // Starting over without a cursor would repeat the first page
+        let cursor = cursor.filter(|_| !records.is_empty());
+        let uris = records.iter().map(|r| r.uri.as_str()).collect::<Vec<_>>();
+        let mut views = self.raw_posts(&uris).await?;
+
+        let entries = records
+            .into_iter()
+            .map(|record: Record<Value>| {
+                let at = record.value["createdAt"]
+                    .as_str()
+                    .and_then(|at| at.parse().ok());
+                let post = match views.remove(&record.uri) {
+                    // The record is taken from the repo, which has it as it is stored
+                    Some(view) => RawPostView {
+                        record: record.value,
+                        ..view
+                    },
+                    None => RawPostView {
+                        uri: record.uri,
+                        cid: record.cid,
+                        record: record.value,
+                        reply_count: None,
+                        repost_count: None,
+                        like_count: None,
+                        quote_count: None,
+                    },
+                };
+                Entry {
+                    post,
+                    at,
+                    reposted: false,
+                }
+            })
+            .collect();
+        Ok((entries, cursor))
+    }
+
+    /// Reposts of posts that no longer exist are left out
+    async fn own_reposts_page(
+        &mut self,
+        did: &str,
+        cursor: Option<String>,
+    ) -> Result<(Vec<Entry>, Option<String>), BiskyError> {
+        let (records, cursor) = self
+            .repo_list_records::<RepostRecord>(did, "app.bsky.feed.repost", 100, false, cursor)
+            .await?;
+        let cursor = cursor.filter(|_| !records.is_empty());
+        let uris = records
+            .iter()
+            .map(|r| r.value.subject.uri.as_str())
+            .collect::<Vec<_>>();
+        let mut views = self.raw_posts(&uris).await?;
+
+        let entries = records
+            .iter()
+            .filter_map(|record| {
+                Some(Entry {
+                    post: views.remove(&record.value.subject.uri)?,
+                    at: Some(record.value.created_at),
+                    reposted: true,
+                })
+            })
+            .collect();
+        Ok((entries, cursor))
+    }
+
+    async fn author_feed_page(
+        &mut self,
+        did: &str,
+        cursor: Option<String>,
+        include_replies: bool,
+        since: Option<Datetime>,
+    ) -> Result<(Vec<Entry>, Option<String>), BiskyError> {
+        let filter = match include_replies {
+            true => "posts_with_replies",
+            false => "posts_no_replies",
+        };
+        let mut query = vec![("actor", did), ("limit", "100"), ("filter", filter)];
+        if let Some(cursor) = &cursor {
+            query.push(("cursor", cursor));
+        }
+        let page = self
+            .xrpc_get::<RawFeed>("app.bsky.feed.getAuthorFeed", Some(&query))
+            .await?;
+
+        // An empty page is the last
+        let mut past_since = page.feed.is_empty();
+        let entries = page
+            .feed
+            .into_iter()
+            .map(|item| {
+                let (at, reposted) = match &item.reason {
+                    Some(FeedReason::Repost(reason)) => (Some(reason.indexed_at), true),
+                    _ => (
+                        item.post.record["createdAt"]
+                            .as_str()
+                            .and_then(|at| at.parse().ok()),
+                        false,
+                    ),
+                };
+                // The feed is newest first, so nothing after this is in range
+                past_since |= at.zip(since).is_some_and(|(at, since)| at < since);
+                Entry {
+                    post: item.post,
+                    at,
+                    reposted,
+                }
+            })
+            .collect();
+        let cursor = match past_since {
+            true => None,
+            false => page.cursor,
+        };
+        Ok((entries, cursor))
+    }
+
+    /// The views of the posts at `uris` that the AppView has, by uri
+    async fn raw_posts(
+        &mut self,
+        uris: &[&str],
+    ) -> Result<HashMap<String, RawPostView>, BiskyError> {
+        let mut views = HashMap::new();
+        let outputs = self
+            .chunked_query::<RawPosts>(
+                "app.bsky.feed.getPosts",
+                &[],
+                "uris",
+                uris,
+                MAX_GET_POSTS,
+                ChunkOptions::default(),
+            )
+            .await?;
+        for output in outputs {
+            views.extend(
+                output?
+                    .posts
+                    .into_iter()
+                    .map(|post| (post.uri.clone(), post)),
+            );
+        }
+        Ok(views)
+    }
+
+    /// Download a blob from the PDS of `did`, which is this client's own for its own blobs
+    async fn download_blob(
+        &mut self,
+        sources: &mut HashMap<String, Client>,
+        did: &str,
+        cid: &str,
+    ) -> Result<Vec<u8>, BiskyError> {
+        if self.session_did().is_ok_and(|own| own == did) {
+            return self.sync_get_blob(did, cid).await;
+        }
+        if !sources.contains_key(did) {
+            let pds = resolve_did(did)
+                .await?
+                .pds_endpoint()
+                .map(str::to_string)
+                .ok_or_else(|| BiskyError::NoPdsEndpoint(did.to_string()))?;
+            let source = ClientBuilder::default().service(pds).build()?;
+            sources.insert(did.to_string(), source);
+        }
+        sources.get_mut(did).unwrap().sync_get_blob(did, cid).await
+    }
+
+    /// Write the followers and then the follows of `actor` to `writer` as `format`, a page at a
+    /// time so that accounts with very many of either don't have to fit in memory. Rate limits
+    /// are waited out. `progress` is called after each page with how many accounts of that
+    /// relation have been written.
+    pub async fn bsky_export_graph(
+        &mut self,
+        actor: &str,
+        format: GraphFormat,
+        writer: &mut (dyn Write + Send),
+        mut progress: impl FnMut(GraphRelation, usize),
+    ) -> Result<(), BiskyError> {
+        match format {
+            GraphFormat::Csv => writer.write_all(b"relation,did,handle,displayName,indexedAt\n")?,
+            GraphFormat::Json => writer.write_all(b"{")?,
+        }
+
+        for relation in [GraphRelation::Followers, GraphRelation::Follows] {
+            if format == GraphFormat::Json {
+                match relation {
+                    GraphRelation::Followers => writer.write_all(b"\"followers\":[")?,
+                    GraphRelation::Follows => writer.write_all(b",\"follows\":[")?,
+                }
+            }
+
+            let mut written = 0;
+            let mut cursor = None;
+            loop {
+                let (profiles, next_cursor) = self.graph_page(actor, relation, cursor).await?;
+                let done = profiles.is_empty() || next_cursor.is_none();
+                for profile in &profiles {
+                    match format {
+                        GraphFormat::Csv => write_csv_row(writer, relation, profile)?,
+                        GraphFormat::Json => {
+                            if written > 0 {
+                                writer.write_all(b",")?;
+                            }
+                            serde_json::to_writer(&mut *writer, profile)?;
+                        }
+                    }
+                    written += 1;
+                }
+                writer.flush()?;
+                progress(relation, written);
+                if done {
+                    break;
+                }
+                cursor = next_cursor;
+            }
+
+            if format == GraphFormat::Json {
+                writer.write_all(b"]")?;
+            }
+        }
+
+        if format == GraphFormat::Json {
+            writer.write_all(b"}\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    async fn graph_page(
+        &mut self,
+        actor: &str,
+        relation: GraphRelation,
+        cursor: Option<String>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        let mut query = vec![("actor", actor), ("limit", "100")];
+        if let Some(cursor) = &cursor {
+            query.push(("cursor", cursor));
+        }
+        match relation {
+            GraphRelation::Followers => {
+                let page = self
+                    .xrpc_get_backoff::<GetFollowersOutput>(
+                        "app.bsky.graph.getFollowers",
+                        Some(&query),
+                    )
+                    .await?;
+                Ok((page.followers, page.cursor))
+            }
+            GraphRelation::Follows => {
+                let page = self
+                    .xrpc_get_backoff::<GetFollowsOutput>("app.bsky.graph.getFollows", Some(&query))
+                    .await?;
+                Ok((page.follows, page.cursor))
+            }
+        }
+    }
+}
+
+fn write_csv_row(
+    writer: &mut (dyn Write + Send),
+    relation: GraphRelation,
+    profile: &ProfileView,
+) -> Result<(), BiskyError> {
+    let relation = match relation {
+        GraphRelation::Followers => "follower",
+        GraphRelation::Follows => "follow",
+    };
+    let fields = [
+        relation,
+        &profile.did,
+        &profile.handle,
+        profile.display_name.as_deref().unwrap_or_default(),
+        profile.indexed_at.as_deref().unwrap_or_default(),
+    ];
+    let row = fields.map(csv_field).join(",");
+    writeln!(writer, "{row}")?;
+    Ok(())
+}
+
+/// Quote a field if it has a comma, quote or line break in it, as display names can
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
@@ -1,22 +1,62 @@
-use crate::errors::{ApiError, BiskyError};
-use crate::lexicon::app::bsky::actor::ProfileView;
-use crate::lexicon::app::bsky::feed::{
-    GetLikesLike, GetLikesOutput, GetPostThreadOutput, ThreadViewPostEnum,
+use crate::errors::{ApiError, ApiErrorKind, BiskyError};
+use crate::identity::{map_resolve_error, normalize_handle};
+#[cfg(feature = "bsky")]
+use crate::lexicon::app::bsky::actor::Profile;
+use crate::lexicon::com::atproto::identity::{
+    RecommendedDidCredentials, ResolveHandleOutput, SignPlcOperation, SignPlcOperationOutput,
+    SubmitPlcOperation, UpdateHandle,
 };
-use crate::lexicon::app::bsky::graph::{GetFollowersOutput, GetFollowsOutput};
-use crate::lexicon::app::bsky::notification::{
-    ListNotificationsOutput, Notification, NotificationCount, UpdateSeen,
+use crate::lexicon::com::atproto::label::{Label, QueryLabelsOutput};
+use crate::lexicon::com::atproto::moderation::{
+    labeler_proxy, CreateReport, CreateReportOutput, ReasonType, ReportSubject,
 };
-use crate::lexicon::com::atproto::repo::{CreateRecord, ListRecordsOutput, Record};
-use crate::lexicon::com::atproto::server::{CreateUserSession, RefreshUserSession};
+use crate::lexicon::com::atproto::repo::{
+    ApplyWrites, ApplyWritesOutput, CreateRecord, DeleteRecord, DescribeRepoOutput,
+    ListMissingBlobsOutput, ListRecordsOutput, MissingBlob, PutRecord, Record, StrongRef, WriteOp,
+};
+#[cfg(feature = "bsky")]
+use crate::lexicon::com::atproto::repo::{BlobOutput, CreateRecordOutput};
+use crate::lexicon::com::atproto::server::{
+    CheckAccountStatusOutput, CreateAccount, CreateAccountOutput, CreateUserSession,
+    DescribeServerOutput, GetServiceAuthOutput, RefreshUserSession,
+};
+use crate::lexicon::com::atproto::sync::{ListBlobsOutput, ListReposOutput};
+#[cfg(feature = "ozone")]
+use crate::lexicon::tools::ozone::moderation::{
+    EmitEvent, ModEvent, ModEventView, QueryEventsOutput, QueryStatusesOutput, RecordViewDetail,
+    RepoViewDetail, SubjectStatusView,
+};
+use crate::retry::{ExponentialBackoff, Failure, RetryDecision, RetryPolicy};
 use crate::storage::Storage;
-use chrono::{DateTime, Utc};
+use crate::types::{AtUri, IntoUrl};
+use bytes::Bytes;
 use derive_builder::Builder;
+use futures::{Stream, StreamExt};
+use parking_lot::RwLock;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
 use std::collections::VecDeque;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How often a paginated walk retries a rate limited page, doubling the wait from one second
+pub(crate) const MAX_RATE_LIMIT_RETRIES: usize = 6;
+
+/// The public Bluesky AppView, which answers read-only queries without a session
+pub const PUBLIC_APPVIEW: &str = "https://public.api.bsky.app";
+
+/// How large a response `Client` reads into memory unless told otherwise, 16 MiB
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+/// How long a client waits to connect unless `ClientBuilder::connect_timeout` says otherwise
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a client's connections send TCP keepalives unless `ClientBuilder::tcp_keepalive`
+/// says otherwise, which keeps pooled connections from being dropped by NATs while idle
+pub const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Default, Deserialize, Clone, Serialize)]
 pub struct Jwt {
@@ -31,6 +71,28 @@ pub struct UserSession {
     pub jwt: Jwt,
 }
 
+impl UserSession {
+    /// A session from tokens obtained elsewhere, such as by a frontend that did the login
+    pub fn new(did: &str, handle: &str, access_jwt: &str, refresh_jwt: &str) -> Self {
+        Self {
+            did: did.to_string(),
+            handle: handle.to_string(),
+            jwt: Jwt {
+                access: access_jwt.to_string(),
+                refresh: refresh_jwt.to_string(),
+            },
+        }
+    }
+
+    pub fn access_jwt(&self) -> &str {
+        &self.jwt.access
+    }
+
+    pub fn refresh_jwt(&self) -> &str {
+        &self.jwt.refresh
+    }
+}
+
 impl From<CreateUserSession> for UserSession {
     fn from(create: CreateUserSession) -> Self {
         Self {
@@ -58,59 +120,614 @@ impl From<RefreshUserSession> for UserSession {
 }
 pub trait StorableSession: Storage<UserSession, Error = BiskyError> {}
 
+/// What a `Client` reports about the requests it sends, e.g. to export them as metrics. Every
+/// method does nothing unless implemented.
+pub trait Metrics: Send + Sync {
+    /// A response to the XRPC method `nsid` came back with `status` and its body of `bytes`
+    /// bytes was read, `duration` after the request was sent. Responses that are streamed
+    /// rather than read, such as repo downloads, aren't reported.
+    fn on_request_complete(&self, _nsid: &str, _status: u16, _duration: Duration, _bytes: usize) {}
+
+    /// A request to `nsid` got no response, e.g. because it timed out
+    fn on_request_failed(&self, _nsid: &str, _duration: Duration, _error: &reqwest::Error) {}
+
+    /// A response was an XRPC error, including those the client deals with itself such as an
+    /// expired token. Its `context` says which call it was.
+    fn on_api_error(&self, _error: &ApiError) {}
+
+    /// The session was refreshed, or failed to be
+    fn on_token_refresh(&self, _result: Result<(), &BiskyError>) {}
+}
+
+/// Sends the requests of a `Client` in place of its HTTP client, e.g. to record them or to
+/// answer them from a recording
+#[async_trait::async_trait]
+pub trait XrpcTransport: Send + Sync {
+    /// Answer `request`, sending it with `http` if it goes to the network at all
+    async fn execute(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, BiskyError>;
+}
+
+/// Put on the responses `Client::send` receives, so that reading them reports to its `Metrics`.
+/// It also carries the XRPC method, as a response an `XrpcTransport` made up has no url.
+pub(crate) struct Sent {
+    pub(crate) nsid: String,
+    started: Instant,
+    pub(crate) metrics: Option<Arc<dyn Metrics>>,
+}
+
+impl Sent {
+    fn complete(&self, status: reqwest::StatusCode, bytes: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.on_request_complete(&self.nsid, status.as_u16(), self.started.elapsed(), bytes);
+        }
+    }
+}
+
+/// The XRPC method a url calls, or its whole path if it isn't an XRPC url
+pub(crate) fn nsid_of(url: &reqwest::Url) -> &str {
+    let path = url.path();
+    path.split_once("/xrpc/").map_or(path, |(_, nsid)| nsid)
+}
+
+/// Cheap to clone, e.g. to make calls from several tasks at once. The clones share the HTTP
+/// client and the session, so a token one of them refreshes is used by all of them, and
+/// logging in with one logs them all in. A client for another account is built separately.
 #[derive(Clone, Builder)]
 pub struct Client {
-    #[builder(default = r#"reqwest::Url::parse("https://bsky.social").unwrap()"#)]
+    /// `https://bsky.social` unless set
+    #[builder(
+        setter(custom),
+        field(
+            type = "Option<Result<reqwest::Url, String>>",
+            build = r#"self.service.clone().transpose()?.unwrap_or_else(|| reqwest::Url::parse("https://bsky.social").unwrap())"#
+        )
+    )]
     service: reqwest::Url,
     #[builder(default, setter(strip_option))]
     storage: Option<Arc<dyn StorableSession>>,
-    #[builder(default, setter(custom))]
-    pub session: Option<UserSession>,
+    /// Shared by every clone, so that a session one clone refreshes is used by all of them
+    #[builder(
+        setter(custom),
+        field(
+            type = "Option<UserSession>",
+            build = "Arc::new(SharedSession::new(self.session.clone()))"
+        )
+    )]
+    session: Arc<SharedSession>,
+    #[builder(setter(skip))]
+    rate_limit: Option<RateLimitInfo>,
+    /// Set up with the builder's `http_client`, `timeout`, `user_agent` and `proxy`
+    #[builder(
+        setter(custom),
+        field(type = "HttpOptions", build = "self.http.build()?")
+    )]
+    http: reqwest::Client,
+    /// The AppView the PDS forwards app.bsky requests to, as a service reference such as
+    /// `did:web:api.bsky.app#bsky_appview`, instead of the one it picks itself
+    #[builder(default, setter(into, strip_option))]
+    appview: Option<String>,
+    /// The labelers whose labels views should carry, sent as `atproto-accept-labelers`
+    #[builder(default)]
+    labelers: Vec<String>,
+    /// The largest response read into memory, `DEFAULT_MAX_RESPONSE_SIZE` unless set and
+    /// unlimited with `None`. Blob and repo downloads aren't limited, and records read as they
+    /// arrive are limited one at a time.
+    #[builder(default = "Some(DEFAULT_MAX_RESPONSE_SIZE)")]
+    max_response_size: Option<usize>,
+    /// Told about every request the client sends and every time it refreshes the session
+    #[builder(default, setter(strip_option))]
+    metrics: Option<Arc<dyn Metrics>>,
+    /// Sends the requests in place of the HTTP client
+    #[builder(default, setter(strip_option))]
+    transport: Option<Arc<dyn XrpcTransport>>,
+    /// Which failed calls are tried again. Without one only paginated walks and bulk
+    /// procedures retry, and only when rate limited.
+    #[builder(default, setter(strip_option))]
+    retry_policy: Option<Arc<dyn RetryPolicy>>,
+}
+
+/// The session of a client and its clones
+struct SharedSession {
+    session: RwLock<Option<UserSession>>,
+    /// Held while refreshing, so that clones whose token expired at once refresh it only once
+    refreshing: futures::lock::Mutex<()>,
+}
+
+impl SharedSession {
+    fn new(session: Option<UserSession>) -> Self {
+        Self {
+            session: RwLock::new(session),
+            refreshing: futures::lock::Mutex::new(()),
+        }
+    }
+
+    fn get(&self) -> Option<UserSession> {
+        self.session.read().clone()
+    }
+
+    fn access_jwt(&self) -> Option<String> {
+        self.session.read().as_ref().map(|s| s.jwt.access.clone())
+    }
+}
+
+/// What `ClientBuilder` builds the HTTP client from
+#[derive(Clone)]
+struct HttpOptions {
+    client: Option<reqwest::Client>,
+    timeout: Option<Duration>,
+    user_agent: Option<String>,
+    proxy: Option<reqwest::Proxy>,
+    connect_timeout: Option<Duration>,
+    /// `None` for reqwest's default of 90 seconds
+    pool_idle_timeout: Option<Option<Duration>>,
+    pool_max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    http2_prior_knowledge: bool,
+    http2_adaptive_window: bool,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            client: None,
+            timeout: None,
+            user_agent: None,
+            proxy: None,
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            http2_prior_knowledge: false,
+            http2_adaptive_window: false,
+        }
+    }
+}
+
+impl HttpOptions {
+    fn build(&self) -> Result<reqwest::Client, String> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(pool_idle_timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(pool_idle_timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        builder
+            .tcp_keepalive(self.tcp_keepalive)
+            .http2_adaptive_window(self.http2_adaptive_window)
+            .build()
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// How `Client::chunked_query` makes its calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// How many calls may run at once
+    pub concurrency: usize,
+    pub on_error: ChunkErrors,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 1,
+            on_error: ChunkErrors::FailFast,
+        }
+    }
+}
+
+/// What `Client::chunked_query` does when a call fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkErrors {
+    /// Start no more calls and return the error of the first chunk that failed
+    FailFast,
+    /// Make every call, returning each chunk's error in its place
+    PerChunk,
+}
+
+/// The rate limit the PDS reported on the last response, from its `ratelimit-*` headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitInfo {
+    pub limit: u32,
+    pub remaining: u32,
+    /// When the window resets, as a unix timestamp
+    pub reset: i64,
+    /// The quota and window from `ratelimit-policy`, if the PDS sent a readable one
+    pub policy: Option<RateLimitPolicy>,
+}
+
+impl RateLimitInfo {
+    /// `None` unless the limit, remaining and reset headers are all there and numbers
+    pub(crate) fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+        Some(Self {
+            limit: header("ratelimit-limit")?.trim().parse().ok()?,
+            remaining: header("ratelimit-remaining")?.trim().parse().ok()?,
+            reset: header("ratelimit-reset")?.trim().parse().ok()?,
+            policy: header("ratelimit-policy").and_then(RateLimitPolicy::parse),
+        })
+    }
+}
+
+impl fmt::Display for RateLimitInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} points left, resets at {}",
+            self.remaining, self.limit, self.reset
+        )
+    }
+}
+
+/// A quota of points per window, such as `5000;w=3600`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    pub limit: u32,
+    pub window: Duration,
+}
+
+impl RateLimitPolicy {
+    /// The first policy of a `ratelimit-policy` header, which may list several
+    fn parse(header: &str) -> Option<Self> {
+        let mut params = header.split(',').next()?.split(';');
+        let limit = params.next()?.trim().parse().ok()?;
+        let window = params
+            .filter_map(|param| param.trim().strip_prefix("w="))
+            .next()?
+            .parse()
+            .ok()?;
+        Some(Self {
+            limit,
+            window: Duration::from_secs(window),
+        })
+    }
+}
+
+/// The points each kind of write costs against the PDS write rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteCosts {
+    pub create: u32,
+    pub update: u32,
+    pub delete: u32,
+}
+
+impl Default for WriteCosts {
+    fn default() -> Self {
+        Self {
+            create: 3,
+            update: 2,
+            delete: 1,
+        }
+    }
+}
+
+impl WriteCosts {
+    pub fn cost(&self, op: &WriteOp) -> u32 {
+        match op {
+            WriteOp::Create { .. } => self.create,
+            WriteOp::Update { .. } => self.update,
+            WriteOp::Delete { .. } => self.delete,
+        }
+    }
 }
 
 impl ClientBuilder {
+    /// The PDS or other service to talk to, as a `Url` or a string
+    pub fn service(&mut self, service: impl IntoUrl) -> &mut Self {
+        self.service = Some(service.into_url().map_err(|e| e.to_string()));
+        self
+    }
+
+    /// Send every request with `client`, in place of one built from `timeout`, `user_agent`,
+    /// `proxy` and the connection options
+    pub fn http_client(&mut self, client: reqwest::Client) -> &mut Self {
+        self.http.client = Some(client);
+        self
+    }
+
+    /// Give up on a request that takes longer than `timeout`. No limit by default.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.http.timeout = Some(timeout);
+        self
+    }
+
+    pub fn user_agent(&mut self, user_agent: &str) -> &mut Self {
+        self.http.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Send requests through an HTTP proxy. By default the system's proxy settings are used.
+    pub fn proxy(&mut self, proxy: reqwest::Proxy) -> &mut Self {
+        self.http.proxy = Some(proxy);
+        self
+    }
+
+    /// Give up on connecting after `timeout`, `DEFAULT_CONNECT_TIMEOUT` unless set, or never
+    /// with `None`. Where a host has both IPv6 and IPv4 addresses, the connector falls back from
+    /// one to the other by itself after a short delay.
+    pub fn connect_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.http.connect_timeout = timeout;
+        self
+    }
+
+    /// Close pooled connections left idle for `timeout`, or never with `None`. 90 seconds
+    /// unless set.
+    pub fn pool_idle_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.http.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections to each host, with 0 making a new connection for
+    /// every request. Unlimited unless set.
+    pub fn pool_max_idle_per_host(&mut self, max: usize) -> &mut Self {
+        self.http.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Send TCP keepalives on idle connections every `interval`, `DEFAULT_TCP_KEEPALIVE` unless
+    /// set, or never with `None`
+    pub fn tcp_keepalive(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.http.tcp_keepalive = interval;
+        self
+    }
+
+    /// Speak HTTP/2 from the start instead of HTTP/1.1, so that requests share one connection
+    /// rather than each taking one from the pool. Only for a service known to support it
+    /// without TLS negotiation, since one that doesn't will fail every request. Off by default.
+    pub fn http2_prior_knowledge(&mut self, enabled: bool) -> &mut Self {
+        self.http.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// Size HTTP/2 flow control windows from the measured bandwidth and delay of the connection,
+    /// which suits large responses. Off by default.
+    pub fn http2_adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        self.http.http2_adaptive_window = enabled;
+        self
+    }
+
+    /// Build the client and log in to its service
+    pub async fn login(&self, identifier: &str, password: &str) -> Result<Client, BiskyError> {
+        let mut client = self.build()?;
+        let service = client.service.clone();
+        client.login(&service, identifier, password).await?;
+        Ok(client)
+    }
+
     pub fn session(&mut self, session: Option<UserSession>) -> &mut Self {
-        self.session = Some(session);
+        self.session = session;
         self
     }
     pub async fn session_from_storage<T: StorableSession + 'static>(
         &mut self,
         storage: T,
     ) -> &mut Self {
-        let session = storage.get().await.ok();
-        self.session = Some(session);
+        self.session = storage.get().await.ok();
         self.storage = Some(Some(Arc::new(storage)));
         self
     }
 }
 
 trait GetService {
-    fn get_service(&self) -> &reqwest::Url;
-    fn access_token(&self) -> Result<&str, BiskyError>;
+    fn access_token(&self) -> Result<String, BiskyError>;
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder;
 }
 
 impl GetService for Client {
-    fn get_service(&self) -> &reqwest::Url {
-        &self.service
+    /// A request for the XRPC method `path`, with the headers every request carries
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = self.service.join(&format!("xrpc/{path}")).unwrap();
+        let mut request = self.http.request(method, url);
+        if !self.labelers.is_empty() {
+            request = request.header("atproto-accept-labelers", self.labelers.join(", "));
+        }
+        request
     }
 
-    fn access_token(&self) -> Result<&str, BiskyError> {
-        match &self.session {
-            Some(s) => Ok(&s.jwt.access),
-            None => Err(BiskyError::MissingSession),
+    fn access_token(&self) -> Result<String, BiskyError> {
+        self.session.access_jwt().ok_or(BiskyError::MissingSession)
+    }
+}
+
+/// Make a call until it succeeds or the policy gives up on it, which without a policy is after
+/// the first try. A macro since the futures of async closures that borrow the client can't be
+/// shown to be `Send`.
+macro_rules! retrying {
+    ($policy:expr, $nsid:expr, $procedure:expr, $call:expr) => {{
+        let policy: Option<Arc<dyn RetryPolicy>> = $policy;
+        let mut attempt = 0;
+        loop {
+            let error = match $call.await {
+                Ok(value) => break Ok(value),
+                Err(error) => error,
+            };
+            attempt += 1;
+            match retry_delay(policy.as_deref(), $nsid, $procedure, attempt, &error) {
+                Some(after) => tokio::time::sleep(after).await,
+                None => break Err(error),
+            }
         }
+    }};
+}
+
+fn retry_delay(
+    policy: Option<&dyn RetryPolicy>,
+    nsid: &str,
+    procedure: bool,
+    attempt: u32,
+    error: &BiskyError,
+) -> Option<Duration> {
+    let failure = Failure {
+        nsid,
+        procedure,
+        attempt,
+        error,
+    };
+    match policy?.decide(&failure) {
+        RetryDecision::Retry(after) => Some(after),
+        RetryDecision::GiveUp => None,
     }
 }
 
 impl Client {
-    ///Update session and put it in storage if Storage is Some
+    /// A client for `service` that uses `session` as it is, without asking `storage` for one.
+    /// The session is written to `storage` if there is one, and refreshed like any other.
+    pub async fn from_session(
+        service: impl IntoUrl,
+        session: UserSession,
+        storage: Option<Arc<dyn StorableSession>>,
+    ) -> Result<Self, BiskyError> {
+        let mut builder = ClientBuilder::default();
+        builder.service(service);
+        if let Some(storage) = storage {
+            builder.storage(storage);
+        }
+        let mut client = builder.build()?;
+        client.update_session(Some(session)).await?;
+        Ok(client)
+    }
+
+    /// The current session, e.g. to hand its tokens on when shutting down
+    pub fn into_session(self) -> Option<UserSession> {
+        self.session.get()
+    }
+
+    /// The current session, which every clone of this client shares
+    pub fn session(&self) -> Option<UserSession> {
+        self.session.get()
+    }
+
+    /// The service this client talks to
+    pub fn service(&self) -> &reqwest::Url {
+        &self.service
+    }
+
+    /// Point the client at another service, keeping the session
+    pub(crate) fn set_service(&mut self, service: reqwest::Url) {
+        self.service = service;
+    }
+
+    /// The `atproto-proxy` for a request to `path` when no other proxy is asked for
+    fn appview_for(&self, path: &str) -> Option<String> {
+        self.appview
+            .clone()
+            .filter(|_| path.starts_with("app.bsky."))
+    }
+
+    /// The rate limit reported by the last response that carried one
+    pub fn last_rate_limit(&self) -> Option<RateLimitInfo> {
+        self.rate_limit
+    }
+
+    /// Keep the rate limit `response` reports, and fail if it is a 429
+    fn observe_rate_limit(&mut self, response: &reqwest::Response) -> Result<(), BiskyError> {
+        let info = RateLimitInfo::from_headers(response.headers());
+        if info.is_some() {
+            self.rate_limit = info;
+        }
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            // Its body is never read
+            if let Some(sent) = response.extensions().get::<Sent>() {
+                sent.complete(response.status(), 0);
+            }
+            return Err(BiskyError::RateLimited(info));
+        }
+        Ok(())
+    }
+
+    /// Send `request` through the transport if there is one, timing it for `metrics`
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, BiskyError> {
+        let request = request.build()?;
+        let nsid = nsid_of(request.url()).to_string();
+        let started = Instant::now();
+        let result = match &self.transport {
+            Some(transport) => transport.execute(&self.http, request).await,
+            None => self.http.execute(request).await.map_err(BiskyError::from),
+        };
+        match result {
+            Ok(mut response) => {
+                response.extensions_mut().insert(Sent {
+                    nsid,
+                    started,
+                    metrics: self.metrics.clone(),
+                });
+                Ok(response)
+            }
+            Err(e) => {
+                if let (Some(metrics), BiskyError::ReqwestError(e)) = (&self.metrics, &e) {
+                    metrics.on_request_failed(&nsid, started.elapsed(), e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// The JSON body of `response`, read up to `max_response_size`
+    async fn read_json<D: DeserializeOwned>(
+        &self,
+        response: reqwest::Response,
+    ) -> Result<D, BiskyError> {
+        read_json(response, self.max_response_size).await
+    }
+
+    async fn read_text(&self, response: reqwest::Response) -> Result<String, BiskyError> {
+        let body = read_body(response, self.max_response_size).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// The handle of the logged in user
+    pub fn handle(&self) -> Option<String> {
+        self.session
+            .session
+            .read()
+            .as_ref()
+            .map(|s| s.handle.clone())
+    }
+
+    /// The DID of the logged in user
+    #[cfg(any(feature = "bsky", feature = "ozone"))]
+    pub(crate) fn session_did(&self) -> Result<String, BiskyError> {
+        match &*self.session.session.read() {
+            Some(s) => Ok(s.did.clone()),
+            None => Err(BiskyError::MissingSession),
+        }
+    }
+
+    ///Update session and put it in storage if Storage is Some. Every clone of this client gets
+    ///the new session.
     pub async fn update_session(&mut self, session: Option<UserSession>) -> Result<(), BiskyError> {
-        self.session = session;
+        *self.session.session.write() = session.clone();
 
         // Store updated session if storage is provided
         if let Some(storage) = &mut self.storage {
             storage
-                .set(self.session.as_ref())
+                .set(session.as_ref())
                 .await
                 .map_err(|e| BiskyError::StorageError(e.to_string()))?;
         }
@@ -119,11 +736,13 @@ impl Client {
 
     pub async fn login(
         &mut self,
-        service: &reqwest::Url,
+        service: impl IntoUrl,
         identifier: &str,
         password: &str,
     ) -> Result<(), BiskyError> {
-        let response = reqwest::Client::new()
+        let service = service.into_url()?;
+        let request = self
+            .http
             .post(
                 service
                     .join("xrpc/com.atproto.server.createSession")
@@ -136,37 +755,50 @@ impl Client {
                     "password": password,
                 })
                 .to_string(),
-            )
-            .send()
-            .await?;
+            );
+        let response = self.send(request).await?;
 
         if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            // Read for the metrics, which count it like any other error
+            ApiError::from_response(response).await;
             return Err(BiskyError::BadCredentials);
         } else if response.status() == reqwest::StatusCode::BAD_REQUEST {
-            return Err(BiskyError::ApiError(response.json::<ApiError>().await?));
+            return Err(BiskyError::ApiError(
+                ApiError::from_response(response).await,
+            ));
         };
 
-        let user_session: UserSession = response.json::<CreateUserSession>().await?.into();
+        let user_session: UserSession = self.read_json::<CreateUserSession>(response).await?.into();
 
         self.update_session(Some(user_session)).await?;
         Ok(())
     }
 
-    async fn xrpc_refresh_token(&mut self) -> Result<(), BiskyError> {
-        let Some(session) = &self.session else{
+    /// Refresh the session after `stale` was rejected as expired, unless another clone already
+    /// refreshed it
+    async fn xrpc_refresh_token(&mut self, stale: &str) -> Result<(), BiskyError> {
+        let session = self.session.clone();
+        let _refreshing = session.refreshing.lock().await;
+        if session.access_jwt().is_some_and(|access| access != stale) {
+            return Ok(());
+        }
+        let result = self.refresh_session().await;
+        if let Some(metrics) = &self.metrics {
+            metrics.on_token_refresh(result.as_ref().map(|_| ()));
+        }
+        result
+    }
+
+    async fn refresh_session(&mut self) -> Result<(), BiskyError> {
+        let Some(session) = self.session.get() else {
             return Err(BiskyError::MissingSession);
         };
-        let response = reqwest::Client::new()
-            .post(
-                self.service
-                    .join("xrpc/com.atproto.server.refreshSession")
-                    .unwrap(),
-            )
-            .header("authorization", format!("Bearer {}", session.jwt.refresh))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<RefreshUserSession>()
+        let request = self
+            .request(reqwest::Method::POST, "com.atproto.server.refreshSession")
+            .header("authorization", format!("Bearer {}", session.jwt.refresh));
+        let response = self.send(request).await?;
+        let response = self
+            .read_json::<RefreshUserSession>(check_status(response).await?)
             .await?;
 
         let session = response.into();
@@ -185,167 +817,785 @@ impl Client {
         &mut self,
         path: &str,
         query: Option<&[(&str, &str)]>,
+    ) -> Result<D, BiskyError> {
+        self.xrpc_get_proxied(path, query, None).await
+    }
+
+    /// Like `xrpc_get`, but with `proxy` set the PDS forwards the request to that service,
+    /// e.g. `CHAT_PROXY`
+    pub(crate) async fn xrpc_get_proxied<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        path: &str,
+        query: Option<&[(&str, &str)]>,
+        proxy: Option<&str>,
+    ) -> Result<D, BiskyError> {
+        retrying!(
+            self.retry_policy.clone(),
+            path,
+            false,
+            self.xrpc_get_proxied_once(path, query, proxy)
+        )
+    }
+
+    async fn xrpc_get_proxied_once<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        path: &str,
+        query: Option<&[(&str, &str)]>,
+        proxy: Option<&str>,
     ) -> Result<D, BiskyError> {
         fn make_request<T: GetService>(
             self_: &T,
+            token: &str,
             path: &str,
             query: &Option<&[(&str, &str)]>,
-        ) -> Result<reqwest::RequestBuilder, BiskyError> {
-            let mut request = reqwest::Client::new()
-                .get(self_.get_service().join(&format!("xrpc/{path}")).unwrap())
-                .header("authorization", format!("Bearer {}", self_.access_token()?));
+            proxy: Option<&str>,
+        ) -> reqwest::RequestBuilder {
+            let mut request = self_
+                .request(reqwest::Method::GET, path)
+                .header("authorization", format!("Bearer {token}"));
 
             if let Some(query) = query {
                 request = request.query(query);
             }
+            if let Some(proxy) = proxy {
+                request = request.header("atproto-proxy", proxy);
+            }
 
-            Ok(request)
+            request
         }
 
-        let mut response = make_request(self, path, &query)?.send().await?;
+        let appview = self.appview_for(path);
+        let proxy = proxy.or(appview.as_deref());
+        let token = self.access_token()?;
+        let mut response = self
+            .send(make_request(self, &token, path, &query, proxy))
+            .await?;
 
         if response.status() == reqwest::StatusCode::BAD_REQUEST {
-            let error = response.json::<ApiError>().await?;
-            if error.error == "ExpiredToken" {
-                self.xrpc_refresh_token().await?;
-                response = make_request(self, path, &query)?.send().await?;
+            let error = ApiError::from_response(response).await;
+            if error.kind() == ApiErrorKind::ExpiredToken {
+                self.xrpc_refresh_token(&token).await?;
+                let token = self.access_token()?;
+                response = self
+                    .send(make_request(self, &token, path, &query, proxy))
+                    .await?;
             } else {
-                return Err(BiskyError::ApiError(error));
+                return Err(map_scope_error(error));
             }
         }
-        // let text: String = response.error_for_status()?.text().await?;
-        // println!("Text\n\n{:#?}\n\n", text);
-        // let json = serde_json::from_str(&text)?;
+        if proxy.is_some() && response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(map_scope_error(ApiError::from_response(response).await));
+        }
 
-        let json: D = response.error_for_status()?.json().await?;
-        // println!("Response\n\n{:#?}\n\n", json);
+        self.observe_rate_limit(&response)?;
+        let json: D = self.read_json(check_status(response).await?).await?;
         Ok(json)
     }
 
-    pub(crate) async fn xrpc_post<D1: Serialize, D2: DeserializeOwned>(
+    /// Like `xrpc_get`, but without a session the request is sent unauthenticated, for queries
+    /// a public AppView such as `PUBLIC_APPVIEW` answers for anyone
+    pub(crate) async fn xrpc_get_public<D: DeserializeOwned + std::fmt::Debug>(
         &mut self,
         path: &str,
-        body: &D1,
-    ) -> Result<D2, BiskyError> {
-        let body = serde_json::to_string(body)?;
-
-        fn make_request<T: GetService>(
-            self_: &T,
-            path: &str,
-            body: &str,
-        ) -> Result<reqwest::RequestBuilder, BiskyError> {
-            println!("BODY: {:#?}", body);
+        query: Option<&[(&str, &str)]>,
+    ) -> Result<D, BiskyError> {
+        if self.session.access_jwt().is_some() {
+            return self.xrpc_get(path, query).await;
+        }
 
-            let req = reqwest::Client::new()
-                .post(self_.get_service().join(&format!("xrpc/{path}")).unwrap())
-                .header("content-type", "application/json")
-                .header("authorization", format!("Bearer {}", self_.access_token()?))
-                .body(body.to_string());
+        retrying!(
+            self.retry_policy.clone(),
+            path,
+            false,
+            self.xrpc_get_public_once(path, query)
+        )
+    }
 
-            println!("REQ: {:#?}", req);
-            Ok(req)
+    async fn xrpc_get_public_once<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        path: &str,
+        query: Option<&[(&str, &str)]>,
+    ) -> Result<D, BiskyError> {
+        let mut request = self.request(reqwest::Method::GET, path);
+        if let Some(query) = query {
+            request = request.query(query);
         }
 
-        let mut response = make_request(self, path, &body)?.send().await?;
-
+        let response = self.send(request).await?;
         if response.status() == reqwest::StatusCode::BAD_REQUEST {
-            let error = response.json::<ApiError>().await?;
-            if error.error == "ExpiredToken" {
-                self.xrpc_refresh_token().await?;
-                response = make_request(self, path, &body)?.send().await?;
-            } else {
-                return Err(BiskyError::ApiError(error));
-            }
+            return Err(BiskyError::ApiError(
+                ApiError::from_response(response).await,
+            ));
         }
-        let text: String = response.error_for_status()?.text().await?;
-        println!("Text\n\n{:#?}\n\n", text);
-        let json = serde_json::from_str(&text)?;
-        // let json = response.error_for_status()?.json::<D2>().await?;
 
-        Ok(json)
+        self.observe_rate_limit(&response)?;
+        self.read_json(check_status(response).await?).await
     }
 
-    pub(crate) async fn xrpc_post_binary<D2: DeserializeOwned>(
+    /// A GET whose response is binary, such as a blob or CAR file. Sent with the session if
+    /// there is one, since the sync endpoints work either way. Not limited by
+    /// `max_response_size`, as blobs and repos can be large.
+    pub(crate) async fn xrpc_get_bytes(
         &mut self,
         path: &str,
-        body: &[u8],
-        mime_type: &str,
-    ) -> Result<D2, BiskyError> {
+        query: &[(&str, &str)],
+    ) -> Result<Vec<u8>, BiskyError> {
+        let response = self.xrpc_get_response(path, query).await?;
+        read_body(response, None).await
+    }
+
+    /// Like `xrpc_get_bytes`, but the successful response is left to be read as it arrives
+    pub(crate) async fn xrpc_get_response(
+        &mut self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<reqwest::Response, BiskyError> {
         fn make_request<T: GetService>(
             self_: &T,
+            token: Option<&str>,
             path: &str,
-            body: &[u8],
-            mime_type: &str,
-        ) -> Result<reqwest::RequestBuilder, BiskyError> {
-            Ok(reqwest::Client::new()
-                .post(self_.get_service().join(&format!("xrpc/{path}")).unwrap())
-                .header("content-type", mime_type)
-                .header("authorization", format!("Bearer {}", self_.access_token()?))
-                .body(body.to_vec()))
+            query: &[(&str, &str)],
+        ) -> reqwest::RequestBuilder {
+            let mut request = self_.request(reqwest::Method::GET, path).query(query);
+            if let Some(token) = token {
+                request = request.header("authorization", format!("Bearer {token}"));
+            }
+            request
         }
 
-        let mut response = make_request(self, path, body, mime_type)?.send().await?;
-
+        let token = self.access_token().ok();
+        let mut response = self
+            .send(make_request(self, token.as_deref(), path, query))
+            .await?;
         if response.status() == reqwest::StatusCode::BAD_REQUEST {
-            let error = response.json::<ApiError>().await?;
-            if error.error == "ExpiredToken" {
-                self.xrpc_refresh_token().await?;
-                response = make_request(self, path, body, mime_type)?.send().await?;
+            let error = ApiError::from_response(response).await;
+            if let (ApiErrorKind::ExpiredToken, Some(token)) = (error.kind(), &token) {
+                self.xrpc_refresh_token(token).await?;
+                let token = self.access_token()?;
+                response = self
+                    .send(make_request(self, Some(&token), path, query))
+                    .await?;
             } else {
                 return Err(BiskyError::ApiError(error));
             }
         }
-        let text: String = response.error_for_status()?.text().await?;
-        println!("Text\n\n{:#?}\n\n", text);
-        let json = serde_json::from_str(&text)?;
-        // let json = response.error_for_status()?.json::<D2>().await?;
 
-        Ok(json)
+        self.observe_rate_limit(&response)?;
+        check_status(response).await
     }
-    pub(crate) async fn xrpc_post_no_response<D1: Serialize>(
+
+    pub(crate) async fn xrpc_post<D1: Serialize, D2: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        body: &D1,
+    ) -> Result<D2, BiskyError> {
+        self.xrpc_post_proxied(path, body, None).await
+    }
+
+    /// Like `xrpc_post`, but with `proxy` set the PDS forwards the request to that service,
+    /// e.g. `CHAT_PROXY`
+    pub(crate) async fn xrpc_post_proxied<D1: Serialize, D2: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        body: &D1,
+        proxy: Option<&str>,
+    ) -> Result<D2, BiskyError> {
+        let body = json_body(body)?;
+        retrying!(
+            self.retry_policy.clone(),
+            path,
+            true,
+            self.xrpc_post_proxied_once(path, &body, proxy)
+        )
+    }
+
+    async fn xrpc_post_proxied_once<D2: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        body: &Bytes,
+        proxy: Option<&str>,
+    ) -> Result<D2, BiskyError> {
+        fn make_request<T: GetService>(
+            self_: &T,
+            token: &str,
+            path: &str,
+            body: &Bytes,
+            proxy: Option<&str>,
+        ) -> reqwest::RequestBuilder {
+            let mut req = self_
+                .request(reqwest::Method::POST, path)
+                .header("content-type", "application/json")
+                .header("authorization", format!("Bearer {token}"))
+                .body(body.clone());
+            if let Some(proxy) = proxy {
+                req = req.header("atproto-proxy", proxy);
+            }
+            req
+        }
+
+        let appview = self.appview_for(path);
+        let proxy = proxy.or(appview.as_deref());
+        let token = self.access_token()?;
+        let mut response = self
+            .send(make_request(self, &token, path, body, proxy))
+            .await?;
+
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            let error = ApiError::from_response(response).await;
+            if error.kind() == ApiErrorKind::ExpiredToken {
+                self.xrpc_refresh_token(&token).await?;
+                let token = self.access_token()?;
+                response = self
+                    .send(make_request(self, &token, path, body, proxy))
+                    .await?;
+            } else {
+                return Err(map_scope_error(error));
+            }
+        }
+        if proxy.is_some() && response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(map_scope_error(ApiError::from_response(response).await));
+        }
+        self.observe_rate_limit(&response)?;
+        self.read_json(check_status(response).await?).await
+    }
+
+    pub(crate) async fn xrpc_post_binary<D2: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        body: &[u8],
+        mime_type: &str,
+    ) -> Result<D2, BiskyError> {
+        let body = Bytes::copy_from_slice(body);
+        retrying!(
+            self.retry_policy.clone(),
+            path,
+            true,
+            self.xrpc_post_binary_once(path, &body, mime_type)
+        )
+    }
+
+    async fn xrpc_post_binary_once<D2: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        body: &Bytes,
+        mime_type: &str,
+    ) -> Result<D2, BiskyError> {
+        fn make_request<T: GetService>(
+            self_: &T,
+            token: &str,
+            path: &str,
+            body: &Bytes,
+            mime_type: &str,
+        ) -> reqwest::RequestBuilder {
+            self_
+                .request(reqwest::Method::POST, path)
+                .header("content-type", mime_type)
+                .header("authorization", format!("Bearer {token}"))
+                .body(body.clone())
+        }
+
+        let token = self.access_token()?;
+        let mut response = self
+            .send(make_request(self, &token, path, body, mime_type))
+            .await?;
+
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            let error = ApiError::from_response(response).await;
+            if error.kind() == ApiErrorKind::ExpiredToken {
+                self.xrpc_refresh_token(&token).await?;
+                let token = self.access_token()?;
+                response = self
+                    .send(make_request(self, &token, path, body, mime_type))
+                    .await?;
+            } else {
+                return Err(map_scope_error(error));
+            }
+        }
+        self.observe_rate_limit(&response)?;
+        let text = self.read_text(check_status(response).await?).await?;
+        // Procedures like importRepo answer with nothing, which `()` reads as null
+        let json = serde_json::from_str(if text.is_empty() { "null" } else { &text })?;
+
+        Ok(json)
+    }
+    pub(crate) async fn xrpc_post_no_response<D1: Serialize>(
         &mut self,
         path: &str,
         body: &D1,
     ) -> Result<(), BiskyError> {
-        let body = serde_json::to_string(body)?;
+        let body = json_body(body)?;
+        retrying!(
+            self.retry_policy.clone(),
+            path,
+            true,
+            self.xrpc_post_no_response_once(path, &body)
+        )
+    }
 
+    async fn xrpc_post_no_response_once(
+        &mut self,
+        path: &str,
+        body: &Bytes,
+    ) -> Result<(), BiskyError> {
         fn make_request<T: GetService>(
             self_: &T,
+            token: &str,
             path: &str,
-            body: &str,
-        ) -> Result<reqwest::RequestBuilder, BiskyError> {
-            Ok(reqwest::Client::new()
-                .post(self_.get_service().join(&format!("xrpc/{path}")).unwrap())
+            body: &Bytes,
+        ) -> reqwest::RequestBuilder {
+            self_
+                .request(reqwest::Method::POST, path)
                 .header("content-type", "application/json")
-                .header("authorization", format!("Bearer {}", self_.access_token()?))
-                .body(body.to_string()))
+                .header("authorization", format!("Bearer {token}"))
+                .body(body.clone())
         }
 
-        let mut response = make_request(self, path, &body)?.send().await?;
+        let token = self.access_token()?;
+        let mut response = self.send(make_request(self, &token, path, body)).await?;
 
         if response.status() == reqwest::StatusCode::BAD_REQUEST {
-            let error = response.json::<ApiError>().await?;
-            if error.error == "ExpiredToken" {
-                self.xrpc_refresh_token().await?;
-                response = make_request(self, path, &body)?.send().await?;
+            let error = ApiError::from_response(response).await;
+            if error.kind() == ApiErrorKind::ExpiredToken {
+                self.xrpc_refresh_token(&token).await?;
+                let token = self.access_token()?;
+                response = self.send(make_request(self, &token, path, body)).await?;
             } else {
-                return Err(BiskyError::ApiError(error));
+                return Err(map_scope_error(error));
             }
         }
-        let text: String = response.error_for_status()?.text().await?;
-        match text.is_empty() {
+        self.observe_rate_limit(&response)?;
+        let text = self.read_text(check_status(response).await?).await?;
+        // Some procedures answer with an empty body, others with a JSON object we don't need
+        match text.is_empty() || serde_json::from_str::<serde_json::Map<_, _>>(&text).is_ok() {
             true => Ok(()),
             false => Err(BiskyError::UnexpectedResponse(text)),
         }
     }
 }
 
+/// `body` as the JSON of a request, serialized once and shared by every attempt to send it
+fn json_body<D: Serialize>(body: &D) -> Result<Bytes, BiskyError> {
+    Ok(serde_json::to_vec(body)?.into())
+}
+
+/// One page of a cursor-paginated query
+pub(crate) trait Page: DeserializeOwned + std::fmt::Debug {
+    type Item;
+
+    fn into_parts(self) -> (Vec<Self::Item>, Option<String>);
+}
+
+macro_rules! impl_page {
+    ($output:ty, $field:ident, $item:ty) => {
+        impl Page for $output {
+            type Item = $item;
+
+            fn into_parts(self) -> (Vec<Self::Item>, Option<String>) {
+                (self.$field, self.cursor)
+            }
+        }
+    };
+}
+#[cfg(feature = "bsky")]
+pub(crate) use impl_page;
+
+impl_page!(ListMissingBlobsOutput, blobs, MissingBlob);
+
+/// An app password without DM access can't be used for proxied chat requests
+pub(crate) fn map_scope_error(error: ApiError) -> BiskyError {
+    if error.message.contains("Bad token scope") {
+        BiskyError::InsufficientScope(error.message)
+    } else {
+        BiskyError::ApiError(error)
+    }
+}
+
+pub(crate) fn is_rate_limited(e: &BiskyError) -> bool {
+    matches!(e, BiskyError::RateLimited(_))
+        || matches!(e, BiskyError::ReqwestError(e) if e.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS))
+        || matches!(e, BiskyError::ApiError(e) if e.status() == Some(429))
+}
+
+/// Whether trying again later might work: rate limits, server errors and dropped connections
+pub(crate) fn is_transient(e: &BiskyError) -> bool {
+    match e {
+        BiskyError::ReqwestError(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status().is_some_and(|status| {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                })
+        }
+        BiskyError::ApiError(e) => e
+            .status()
+            .is_some_and(|status| status >= 500 || status == 429),
+        BiskyError::RateLimited(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether the session can't be used, so that no other call with it will succeed either
+pub(crate) fn is_auth_error(e: &BiskyError) -> bool {
+    match e {
+        BiskyError::BadCredentials
+        | BiskyError::MissingSession
+        | BiskyError::InsufficientScope(_) => true,
+        BiskyError::ApiError(e) => {
+            e.status() == Some(401)
+                || matches!(
+                    e.kind(),
+                    ApiErrorKind::ExpiredToken
+                        | ApiErrorKind::InvalidToken
+                        | ApiErrorKind::AuthRequired
+                        | ApiErrorKind::AccountTakedown
+                        | ApiErrorKind::AccountDeactivated
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Below this many rate limit points left per call `batched` may run at once, it runs one call
+/// at a time
+const BATCH_LOW_POINTS_PER_CALL: u32 = 10;
+
+/// The body of `response`, failing as soon as it turns out to be longer than `limit`
+pub(crate) async fn read_body(
+    mut response: reqwest::Response,
+    limit: Option<usize>,
+) -> Result<Vec<u8>, BiskyError> {
+    let sent = response.extensions_mut().remove::<Sent>();
+    let status = response.status();
+    let body = read_limited(response, limit).await;
+    if let Some(sent) = sent {
+        sent.complete(status, body.as_ref().map_or(0, Vec::len));
+    }
+    body
+}
+
+async fn read_limited(
+    mut response: reqwest::Response,
+    limit: Option<usize>,
+) -> Result<Vec<u8>, BiskyError> {
+    let Some(limit) = limit else {
+        return Ok(response.bytes().await?.to_vec());
+    };
+    let too_large = |size| BiskyError::ResponseTooLarge { size, limit };
+    if let Some(size) = response.content_length() {
+        if size > limit as u64 {
+            return Err(too_large(size as usize));
+        }
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > limit {
+            return Err(too_large(body.len()));
+        }
+    }
+    Ok(body)
+}
+
+pub(crate) async fn read_json<D: DeserializeOwned>(
+    response: reqwest::Response,
+    limit: Option<usize>,
+) -> Result<D, BiskyError> {
+    Ok(serde_json::from_slice(&read_body(response, limit).await?)?)
+}
+
+/// `response` if it succeeded, otherwise the `ApiError` it carries
+pub(crate) async fn check_status(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, BiskyError> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(ApiError::from_response(response).await.into())
+    }
+}
+
+impl Client {
+    /// `xrpc_get` that waits and retries when rate limited, for long paginated walks
+    pub(crate) async fn xrpc_get_backoff<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        path: &str,
+        query: Option<&[(&str, &str)]>,
+    ) -> Result<D, BiskyError> {
+        retrying!(
+            Some(self.backoff_policy()),
+            path,
+            false,
+            self.xrpc_get_proxied_once(path, query, None)
+        )
+    }
+
+    /// `xrpc_post_no_response` that waits and retries when rate limited, for bulk procedures
+    #[cfg(feature = "bsky")]
+    pub(crate) async fn xrpc_post_no_response_backoff<D1: Serialize>(
+        &mut self,
+        path: &str,
+        body: &D1,
+    ) -> Result<(), BiskyError> {
+        let body = json_body(body)?;
+        retrying!(
+            Some(self.backoff_policy()),
+            path,
+            true,
+            self.xrpc_post_no_response_once(path, &body)
+        )
+    }
+
+    /// The client's retry policy, or else one that retries rate limited calls
+    fn backoff_policy(&self) -> Arc<dyn RetryPolicy> {
+        self.retry_policy
+            .clone()
+            .unwrap_or_else(|| Arc::new(ExponentialBackoff::rate_limits()))
+    }
+
+    /// Fetch up to `limit` items from a cursor-paginated query, `page_size` at a time
+    pub(crate) async fn xrpc_get_pages<P: Page>(
+        &mut self,
+        path: &str,
+        query: &[(&str, &str)],
+        mut limit: usize,
+        page_size: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<P::Item>, Option<String>), BiskyError> {
+        let mut items = Vec::new();
+        let mut response_cursor = cursor.map(str::to_string);
+
+        while limit > 0 {
+            let query_limit = std::cmp::min(limit, page_size).to_string();
+            let mut page_query = query.to_vec();
+            page_query.push(("limit", &query_limit));
+
+            if let Some(cursor) = response_cursor.as_ref() {
+                page_query.push(("cursor", cursor));
+            }
+
+            let (mut page, cursor) = self
+                .xrpc_get_backoff::<P>(path, Some(&page_query))
+                .await?
+                .into_parts();
+
+            if page.is_empty() {
+                // caller requested more records than are available
+                break;
+            }
+
+            limit = limit.saturating_sub(page.len());
+
+            response_cursor = cursor;
+            items.append(&mut page);
+
+            if response_cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok((items, response_cursor))
+    }
+}
+
+impl Client {
+    /// Run `call` for every item of `items`, up to `concurrency` at a time, with the results in
+    /// the order of `items`, e.g. to fetch many threads:
+    /// `client.batched(8, uris, async |client, uri| client.repo_get_record(...).await)`.
+    ///
+    /// Each call gets a clone of this client, which shares its session, and the rate limit the
+    /// calls report becomes this client's.
+    /// Calls start one at a time once few points are left, and not at all when none are, until
+    /// the limit resets. A call whose token was rejected is run again once the calls running
+    /// finish if one of them refreshed the session meanwhile, and ends the batch otherwise. Any
+    /// other authentication error, such as a taken down account, ends it at once, dropping the
+    /// calls still running.
+    pub async fn batched<T: Clone, R>(
+        &mut self,
+        concurrency: usize,
+        items: impl IntoIterator<Item = T>,
+        call: impl AsyncFn(&mut Client, T) -> Result<R, BiskyError>,
+    ) -> Result<Vec<Result<R, BiskyError>>, BiskyError> {
+        let concurrency = concurrency.max(1);
+        let call = &call;
+        let mut items = items.into_iter().enumerate();
+        let mut retries = VecDeque::new();
+        // Failed to authenticate, to be retried or returned once the calls running finish
+        let mut unauthenticated = Vec::new();
+        let mut results = Vec::new();
+        let mut idle = Vec::new();
+        let mut running = futures::stream::FuturesUnordered::new();
+
+        loop {
+            let slots = match self.rate_limit {
+                _ if !unauthenticated.is_empty() => 0,
+                Some(rate_limit) if rate_limit.remaining == 0 => 0,
+                Some(rate_limit)
+                    if rate_limit.remaining
+                        < BATCH_LOW_POINTS_PER_CALL.saturating_mul(concurrency as u32) =>
+                {
+                    1
+                }
+                _ => concurrency,
+            };
+            while running.len() < slots {
+                let Some((index, item)) = retries.pop_front().or_else(|| items.next()) else {
+                    break;
+                };
+                if index == results.len() {
+                    results.push(None);
+                }
+                let mut client = idle.pop().unwrap_or_else(|| self.clone());
+                running.push(async move {
+                    let started = client.session.access_jwt();
+                    let result = call(&mut client, item.clone()).await;
+                    (index, item, started, client, result)
+                });
+            }
+
+            let Some((index, item, started, client, result)) = running.next().await else {
+                if !unauthenticated.is_empty() {
+                    for (index, item, started, error) in unauthenticated.drain(..) {
+                        if started == self.session.access_jwt() {
+                            return Err(error);
+                        }
+                        retries.push_back((index, item));
+                    }
+                    continue;
+                }
+                match self.rate_limit {
+                    Some(rate_limit) if slots == 0 => {
+                        let wait =
+                            (rate_limit.reset - chrono::Utc::now().timestamp()).max(1) as u64;
+                        tokio::time::sleep(Duration::from_secs(wait)).await;
+                        // Unknown until the next call reports it
+                        self.rate_limit = None;
+                        continue;
+                    }
+                    _ => break,
+                }
+            };
+
+            if client.rate_limit.is_some() {
+                self.rate_limit = client.rate_limit;
+            }
+            idle.push(client);
+            match result {
+                // What a call gets for refreshing with a token another call already used
+                Err(BiskyError::ApiError(error))
+                    if matches!(
+                        error.kind(),
+                        ApiErrorKind::InvalidToken | ApiErrorKind::ExpiredToken
+                    ) =>
+                {
+                    unauthenticated.push((index, item, started, error.into()));
+                }
+                Err(error) if is_auth_error(&error) => return Err(error),
+                result => results[index] = Some(result),
+            }
+        }
+
+        // Every call has finished
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Query `nsid` for any number of `items`, passed as the repeated `param`, in calls of at
+    /// most `max_items` each along with the `query` every call takes. The outputs come back one
+    /// per call, in the order of `items`. The calls are made with `batched`, so they share this
+    /// client's session and rate limit.
+    ///
+    /// With `ChunkErrors::FailFast` the first failure is returned; otherwise each chunk gets its
+    /// own result.
+    pub async fn chunked_query<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        nsid: &str,
+        query: &[(&str, &str)],
+        param: &str,
+        items: &[&str],
+        max_items: usize,
+        options: ChunkOptions,
+    ) -> Result<Vec<Result<D, BiskyError>>, BiskyError> {
+        let fail_fast = options.on_error == ChunkErrors::FailFast;
+        // Owned, as the futures of a closure that borrows can't be shown to be Send
+        let call = Arc::new(ChunkedCall {
+            nsid: nsid.to_string(),
+            query: query
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            param: param.to_string(),
+            fail_fast,
+            failed: AtomicBool::new(false),
+        });
+        let chunks = items
+            .chunks(max_items.max(1))
+            .map(|chunk| {
+                let chunk = chunk
+                    .iter()
+                    .map(|item| item.to_string())
+                    .collect::<Vec<_>>();
+                (call.clone(), chunk)
+            })
+            .collect::<Vec<_>>();
+
+        let outputs = self
+            .batched(
+                options.concurrency,
+                chunks,
+                async |client, (call, chunk): (Arc<ChunkedCall>, Vec<String>)| {
+                    // Skipped once a call failed fast
+                    if call.fail_fast && call.failed.load(Ordering::Relaxed) {
+                        return Ok(None);
+                    }
+                    let mut query = call
+                        .query
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_str()))
+                        .collect::<Vec<_>>();
+                    query.extend(
+                        chunk
+                            .iter()
+                            .map(|item| (call.param.as_str(), item.as_str())),
+                    );
+                    let output = client.xrpc_get::<D>(&call.nsid, Some(&query)).await;
+                    if output.is_err() {
+                        call.failed.store(true, Ordering::Relaxed);
+                    }
+                    output.map(Some)
+                },
+            )
+            .await?;
+
+        let mut results = Vec::with_capacity(outputs.len());
+        for output in outputs {
+            // Chunks start in order, so any skipped come after the one that failed
+            match output {
+                Ok(Some(output)) => results.push(Ok(output)),
+                Ok(None) => {}
+                Err(e) if fail_fast => return Err(e),
+                Err(e) => results.push(Err(e)),
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// What each call of `Client::chunked_query` sends, besides its chunk
+struct ChunkedCall {
+    nsid: String,
+    query: Vec<(String, String)>,
+    param: String,
+    fail_fast: bool,
+    failed: AtomicBool,
+}
+
 pub struct RecordStream<'a, D: DeserializeOwned> {
     client: &'a mut Client,
     repo: &'a str,
     collection: &'a str,
-    queue: VecDeque<Record<D>>,
+    page: Option<RecordPage>,
     cursor: String,
+    record: PhantomData<D>,
 }
 
 #[derive(Debug)]
@@ -361,94 +1611,271 @@ impl From<BiskyError> for StreamError {
 }
 
 impl<'a, D: DeserializeOwned + std::fmt::Debug> RecordStream<'a, D> {
+    /// The next record, decoded as soon as it arrives rather than once its whole page has
     pub async fn next(&mut self) -> Result<Record<D>, StreamError> {
-        if let Some(record) = self.queue.pop_front() {
-            Ok(record)
-        } else {
-            loop {
-                let (records, cursor) = self
-                    .client
-                    .repo_list_records(
-                        self.repo,
-                        self.collection,
-                        100,
-                        true,
-                        Some(self.cursor.clone()),
-                    )
-                    .await?;
+        loop {
+            if let Some(page) = &mut self.page {
+                if let Some(record) = page.next().await? {
+                    return Ok(record);
+                }
+                if page.records == 0 {
+                    tokio::time::sleep(Duration::from_secs(15)).await;
+                } else if let Some(cursor) = page.cursor() {
+                    self.cursor = cursor.to_string();
+                } else {
+                    return Err(StreamError::NoCursor);
+                }
+            }
 
-                let mut records = VecDeque::from(records);
-                if let Some(first_record) = records.pop_front() {
-                    if let Some(cursor) = cursor {
-                        self.cursor = cursor;
-                    } else {
-                        return Err(StreamError::NoCursor);
-                    }
+            let page = self
+                .client
+                .repo_list_records_page(self.repo, self.collection, true, Some(&self.cursor))
+                .await?;
+            self.page = Some(page);
+        }
+    }
+}
 
-                    self.queue.append(&mut records);
-                    return Ok(first_record);
-                } else {
-                    tokio::time::sleep(Duration::from_secs(15)).await;
+/// A listRecords page read as it arrives, each record decoded once all of it is in
+struct RecordPage {
+    response: reqwest::Response,
+    splitter: RecordSplitter,
+    sent: Option<Sent>,
+    /// How many bytes and records have been read
+    read: usize,
+    records: usize,
+    /// Why reading stopped, held back until the records read before it have been returned
+    failed: Option<BiskyError>,
+}
+
+impl RecordPage {
+    fn new(mut response: reqwest::Response, limit: Option<usize>) -> Self {
+        Self {
+            sent: response.extensions_mut().remove::<Sent>(),
+            response,
+            splitter: RecordSplitter {
+                limit,
+                ..Default::default()
+            },
+            read: 0,
+            records: 0,
+            failed: None,
+        }
+    }
+
+    /// The next record of the page, or `None` once it has all been read
+    async fn next<D: DeserializeOwned>(&mut self) -> Result<Option<Record<D>>, BiskyError> {
+        loop {
+            if let Some(record) = self.splitter.records.pop_front() {
+                self.records += 1;
+                return Ok(Some(serde_json::from_slice(&record)?));
+            }
+            if let Some(e) = self.failed.take() {
+                return Err(e);
+            }
+            let Some(chunk) = self.response.chunk().await? else {
+                if let Some(sent) = self.sent.take() {
+                    sent.complete(self.response.status(), self.read);
                 }
+                return match self.splitter.done {
+                    true => Ok(None),
+                    false => Err(BiskyError::UnexpectedResponse(
+                        "listRecords response ended early".to_string(),
+                    )),
+                };
+            };
+            self.read += chunk.len();
+            if let Err(e) = self.splitter.feed(&chunk) {
+                self.failed = Some(e);
             }
         }
     }
+
+    /// Where the next page starts, once this one has been read
+    fn cursor(&self) -> Option<&str> {
+        self.splitter.cursor.as_deref()
+    }
 }
 
-pub struct NotificationStream<'a, D: DeserializeOwned> {
-    client: &'a mut Client,
-    limit: usize,
-    seen_at: Option<&'a str>,
-    // collection: &'a str,
-    queue: VecDeque<Notification<D>>,
-    cursor: String,
+/// Picks the records and cursor out of a listRecords response as its bytes arrive, keeping
+/// no more of it than the record being read and those read but not yet decoded
+#[derive(Default)]
+struct RecordSplitter {
+    /// How deeply nested the next byte is, with 1 the members of the response
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Whether the next string among the members of the response is a key
+    expect_key: bool,
+    reading_key: bool,
+    key: Vec<u8>,
+    in_records: bool,
+    /// A record or the cursor as far as it has been read, and how deeply it is nested
+    value: Option<(Vec<u8>, usize)>,
+    /// The longest a record may be
+    limit: Option<usize>,
+    records: VecDeque<Vec<u8>>,
+    cursor: Option<String>,
+    /// Whether the end of the response has been read
+    done: bool,
 }
 
-impl<'a, D: DeserializeOwned + std::fmt::Debug> NotificationStream<'a, D> {
-    pub async fn next(&mut self) -> Result<Notification<D>, StreamError> {
-        if let Some(notification) = self.queue.pop_front() {
-            Ok(notification)
-        } else {
-            loop {
-                let (notifications, cursor) = self
-                    .client
-                    .bsky_list_notifications(self.limit, self.seen_at, Some(self.cursor.as_ref()))
-                    .await?;
+impl RecordSplitter {
+    fn feed(&mut self, chunk: &[u8]) -> Result<(), BiskyError> {
+        for &byte in chunk {
+            self.byte(byte)?;
+        }
+        match &self.value {
+            Some((value, _)) => self.check_size(value),
+            None => Ok(()),
+        }
+    }
 
-                let mut notifications = VecDeque::from(notifications);
-                if let Some(first_notification) = notifications.pop_front() {
-                    if let Some(cursor) = cursor {
-                        self.cursor = cursor;
-                    } else {
-                        return Err(StreamError::NoCursor);
-                    }
+    fn check_size(&self, value: &[u8]) -> Result<(), BiskyError> {
+        match self.limit {
+            Some(limit) if value.len() > limit => Err(BiskyError::ResponseTooLarge {
+                size: value.len(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
 
-                    self.queue.append(&mut notifications);
-                    return Ok(first_notification);
-                } else {
-                    tokio::time::sleep(Duration::from_secs(15)).await;
+    fn byte(&mut self, byte: u8) -> Result<(), BiskyError> {
+        if self.in_string {
+            match &mut self.value {
+                Some((value, _)) => value.push(byte),
+                None if self.reading_key && (byte != b'"' || self.escaped) => self.key.push(byte),
+                None => {}
+            }
+            if self.escaped {
+                self.escaped = false;
+            } else if byte == b'\\' {
+                self.escaped = true;
+            } else if byte == b'"' {
+                self.in_string = false;
+                self.reading_key = false;
+                self.end_value()?;
+            }
+            return Ok(());
+        }
+
+        if matches!(byte, b',' | b'}' | b']') || byte.is_ascii_whitespace() {
+            // The end of a number, `true`, `false` or `null`
+            self.end_value()?;
+        }
+        match byte {
+            _ if byte.is_ascii_whitespace() => {}
+            b'[' if self.depth == 1 && !self.expect_key && self.key == b"records" => {
+                self.in_records = true;
+                self.depth += 1;
+            }
+            b'{' | b'[' => {
+                self.add(byte);
+                self.depth += 1;
+                if self.depth == 1 {
+                    self.expect_key = true;
+                }
+            }
+            b'}' | b']' => {
+                self.depth = self.depth.checked_sub(1).ok_or_else(|| {
+                    BiskyError::UnexpectedResponse("Malformed listRecords response".to_string())
+                })?;
+                if let Some((value, _)) = &mut self.value {
+                    value.push(byte);
                 }
+                if self.depth == 1 {
+                    self.in_records = false;
+                }
+                self.done = self.depth == 0;
+                self.end_value()?;
+            }
+            b'"' if self.depth == 1 && self.expect_key => {
+                self.in_string = true;
+                self.reading_key = true;
+                self.key.clear();
             }
+            b'"' => {
+                self.in_string = true;
+                self.add(byte);
+            }
+            b':' if self.depth == 1 => self.expect_key = false,
+            b',' if self.depth == 1 => self.expect_key = true,
+            // Between records
+            b',' if self.value.is_none() => {}
+            _ => self.add(byte),
         }
+        Ok(())
+    }
+
+    /// Add `byte` to the value being read, or start reading one if a record or the cursor
+    /// starts here
+    fn add(&mut self, byte: u8) {
+        match &mut self.value {
+            Some((value, _)) => value.push(byte),
+            None => {
+                let wanted = match self.depth {
+                    1 => !self.expect_key && self.key == b"cursor",
+                    2 => self.in_records,
+                    _ => false,
+                };
+                if wanted {
+                    self.value = Some((vec![byte], self.depth));
+                }
+            }
+        }
+    }
+
+    /// Finish reading the value if it ended with the last byte
+    fn end_value(&mut self) -> Result<(), BiskyError> {
+        match self.value.take() {
+            Some((value, depth)) if depth != self.depth => self.value = Some((value, depth)),
+            Some((value, 1)) => self.cursor = serde_json::from_slice(&value)?,
+            Some((value, _)) => {
+                self.check_size(&value)?;
+                self.records.push_back(value);
+            }
+            None => {}
+        }
+        Ok(())
     }
 }
+
 impl Client {
-    // pub async fn repo_get_record<D: DeserializeOwned + std::fmt::Debug>(
-    //     &mut self,
-    //     repo: &str,
-    //     collection: &str,
-    //     rkey: Option<&str>,
-    // ) -> Result<Record<D>, BiskyError> {
-    //     let mut query = vec![("repo", repo), ("collection", collection)];
-
-    //     if let Some(rkey) = rkey {
-    //         query.push(("rkey", rkey));
-    //     }
-
-    //     self.xrpc_get("com.atproto.repo.getRecord", Some(&query))
-    //         .await
-    // }
+    ///com.atproto.repo.getRecord
+    ///
+    /// `D` is the record type. For collections this crate doesn't model, `serde_json::Value`
+    /// takes any record, and `Box<serde_json::value::RawValue>` keeps it as the JSON text
+    /// without parsing it.
+    pub async fn repo_get_record<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        rkey: &str,
+    ) -> Result<Record<D>, BiskyError> {
+        let query = [("repo", repo), ("collection", collection), ("rkey", rkey)];
 
+        self.xrpc_get("com.atproto.repo.getRecord", Some(&query))
+            .await
+    }
+
+    /// The current version of the record at `uri`, to like, repost, reply to or quote when only
+    /// its uri is known
+    pub async fn strong_ref_for(&mut self, uri: &AtUri) -> Result<StrongRef, BiskyError> {
+        let record = self
+            .repo_get_record::<serde_json::Value>(
+                &uri.authority,
+                uri.require_collection()?,
+                uri.require_rkey()?,
+            )
+            .await?;
+        StrongRef::try_from(&record)
+    }
+
+    ///com.atproto.repo.listRecords
+    ///
+    /// Up to `limit` records, fetched a page at a time, with the cursor to continue from. Takes
+    /// the same record types as `repo_get_record`.
     pub async fn repo_list_records<D: DeserializeOwned + std::fmt::Debug>(
         &mut self,
         repo: &str,
@@ -470,276 +1897,1451 @@ impl Client {
                 ("limit", &query_limit),
             ]);
 
-            if let Some(cursor) = cursor.as_ref() {
-                query.push(("cursor", cursor));
-            }
+            if let Some(cursor) = cursor.as_ref() {
+                query.push(("cursor", cursor));
+            }
+
+            let mut response = self
+                .xrpc_get::<ListRecordsOutput<D>>("com.atproto.repo.listRecords", Some(&query))
+                .await?;
+
+            if response.records.is_empty() {
+                // caller requested more records than are available
+                break;
+            }
+
+            limit -= response.records.len();
+
+            cursor = response.cursor.take();
+            records.append(&mut response.records);
+
+            if cursor.is_none() {
+                // Starting over without a cursor would repeat the first page
+                break;
+            }
+        }
+
+        Ok((records, cursor))
+    }
+
+    /// Every record in `collection`, oldest first with `reverse`. Each record is decoded as soon
+    /// as it arrives, so that a page of large records is never held in memory all at once.
+    pub fn repo_list_records_stream<'a, D: DeserializeOwned + 'a>(
+        &'a mut self,
+        repo: &'a str,
+        collection: &'a str,
+        reverse: bool,
+    ) -> impl Stream<Item = Result<Record<D>, BiskyError>> + 'a {
+        struct State<'a> {
+            client: &'a mut Client,
+            page: Option<RecordPage>,
+            cursor: Option<String>,
+            done: bool,
+        }
+
+        let state = State {
+            client: self,
+            page: None,
+            cursor: None,
+            done: false,
+        };
+
+        futures::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(page) = &mut state.page {
+                    match page.next().await {
+                        Ok(Some(record)) => return Some((Ok(record), state)),
+                        Ok(None) => {
+                            state.cursor = page.cursor().map(str::to_string);
+                            state.done = page.records == 0 || state.cursor.is_none();
+                            state.page = None;
+                        }
+                        Err(e) => {
+                            state.done = true;
+                            state.page = None;
+                            return Some((Err(e), state));
+                        }
+                    }
+                }
+                if state.done {
+                    return None;
+                }
+
+                let page = state
+                    .client
+                    .repo_list_records_page(repo, collection, reverse, state.cursor.as_deref())
+                    .await;
+                match page {
+                    Ok(page) => state.page = Some(page),
+                    Err(e) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Start reading a page of up to 100 records from listRecords
+    async fn repo_list_records_page(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        reverse: bool,
+        cursor: Option<&str>,
+    ) -> Result<RecordPage, BiskyError> {
+        let reverse = reverse.to_string();
+        let mut query = Vec::from([
+            ("repo", repo),
+            ("collection", collection),
+            ("reverse", &reverse),
+            ("limit", "100"),
+        ]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        let response = self
+            .xrpc_get_response("com.atproto.repo.listRecords", &query)
+            .await?;
+        Ok(RecordPage::new(response, self.max_response_size))
+    }
+
+    pub async fn repo_create_record<D: DeserializeOwned, S: Serialize>(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        record: S,
+    ) -> Result<D, BiskyError> {
+        self.xrpc_post(
+            "com.atproto.repo.createRecord",
+            &CreateRecord {
+                repo,
+                collection,
+                rkey: None,
+                record,
+            },
+        )
+        .await
+    }
+
+    /// Create a record under `rkey`, which fails if the collection already has a record there
+    pub async fn repo_create_record_with_rkey<D: DeserializeOwned, S: Serialize>(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        rkey: &str,
+        record: S,
+    ) -> Result<D, BiskyError> {
+        self.xrpc_post(
+            "com.atproto.repo.createRecord",
+            &CreateRecord {
+                repo,
+                collection,
+                rkey: Some(rkey),
+                record,
+            },
+        )
+        .await
+    }
+
+    pub async fn repo_put_record<D: DeserializeOwned, S: Serialize>(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        rkey: &str,
+        record: S,
+        swap_record: Option<&str>,
+    ) -> Result<D, BiskyError> {
+        self.xrpc_post(
+            "com.atproto.repo.putRecord",
+            &PutRecord {
+                repo,
+                collection,
+                rkey,
+                record,
+                swap_record,
+            },
+        )
+        .await
+    }
+
+    pub async fn repo_apply_writes(
+        &mut self,
+        repo: &str,
+        writes: &[WriteOp],
+    ) -> Result<ApplyWritesOutput, BiskyError> {
+        self.xrpc_post(
+            "com.atproto.repo.applyWrites",
+            &ApplyWrites { repo, writes },
+        )
+        .await
+    }
+
+    /// The raw app.bsky.actor.profile record of `repo`, for backups and migrations
+    #[cfg(feature = "bsky")]
+    pub async fn repo_get_profile_record(
+        &mut self,
+        repo: &str,
+    ) -> Result<Record<Profile>, BiskyError> {
+        self.repo_get_record(repo, "app.bsky.actor.profile", "self")
+            .await
+    }
+
+    /// Write the logged in user's app.bsky.actor.profile record as is. With `swap_record`, the
+    /// write only succeeds if the current record still has that cid.
+    #[cfg(feature = "bsky")]
+    pub async fn repo_put_profile_record(
+        &mut self,
+        profile: &Profile,
+        swap_record: Option<&str>,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.session_did()?;
+        self.repo_put_record(&did, "app.bsky.actor.profile", "self", profile, swap_record)
+            .await
+    }
+
+    /// Copy the avatar and banner of `profile` from `did`'s repo on `source` to the logged in
+    /// user's PDS, pointing the profile at the new blobs. Used when restoring a profile onto a
+    /// different PDS, which does not have the old blobs.
+    #[cfg(feature = "bsky")]
+    pub async fn rehost_profile_blobs(
+        &mut self,
+        source: &mut Client,
+        did: &str,
+        profile: &mut Profile,
+    ) -> Result<(), BiskyError> {
+        for blob in [profile.avatar.as_mut(), profile.banner.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            let bytes = source.sync_get_blob(did, blob.cid()).await?;
+            let output: BlobOutput = self.repo_upload_blob(&bytes, blob.mime_type()).await?;
+            *blob = output.blob;
+        }
+        Ok(())
+    }
+
+    ///com.atproto.sync.getBlob
+    /// Works without a session, blobs are public
+    pub async fn sync_get_blob(&mut self, did: &str, cid: &str) -> Result<Vec<u8>, BiskyError> {
+        self.xrpc_get_bytes("com.atproto.sync.getBlob", &[("did", did), ("cid", cid)])
+            .await
+    }
+
+    pub async fn repo_delete_record(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        rkey: &str,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response(
+            "com.atproto.repo.deleteRecord",
+            &DeleteRecord {
+                repo,
+                collection,
+                rkey,
+            },
+        )
+        .await
+    }
+
+    pub async fn repo_upload_blob<D: DeserializeOwned>(
+        &mut self,
+        blob: &[u8],
+        mime_type: &str,
+    ) -> Result<D, BiskyError> {
+        self.xrpc_post_binary("com.atproto.repo.uploadBlob", blob, mime_type)
+            .await
+    }
+
+    pub async fn repo_stream_records<'a, D: DeserializeOwned + std::fmt::Debug>(
+        &'a mut self,
+        repo: &'a str,
+        collection: &'a str,
+    ) -> Result<RecordStream<'a, D>, StreamError> {
+        let (_, cursor) = self
+            .repo_list_records::<D>(repo, collection, 1, false, None)
+            .await?;
+
+        if let Some(cursor) = cursor {
+            Ok(RecordStream {
+                client: self,
+                repo,
+                collection,
+                page: None,
+                cursor,
+                record: PhantomData,
+            })
+        } else {
+            Err(StreamError::NoCursor)
+        }
+    }
+    ///com.atproto.identity.resolveHandle
+    /// Works without a session. The handle may start with `@` and is matched case-insensitively.
+    pub async fn identity_resolve_handle(&mut self, handle: &str) -> Result<String, BiskyError> {
+        let handle = normalize_handle(handle);
+        let response = self
+            .xrpc_get_public::<ResolveHandleOutput>(
+                "com.atproto.identity.resolveHandle",
+                Some(&[("handle", handle.as_str())]),
+            )
+            .await
+            .map_err(|e| match e {
+                BiskyError::ApiError(e) => map_resolve_error(e),
+                e => e,
+            })?;
+        Ok(response.did)
+    }
+
+    ///com.atproto.identity.updateHandle
+    /// Change the logged in user's handle and update the stored session to match. A custom
+    /// domain must already resolve to the user's DID, otherwise the error is
+    /// `BiskyError::HandleVerificationFailed`.
+    pub async fn identity_update_handle(&mut self, handle: &str) -> Result<(), BiskyError> {
+        let handle = normalize_handle(handle);
+        self.xrpc_post_no_response(
+            "com.atproto.identity.updateHandle",
+            &UpdateHandle { handle: &handle },
+        )
+        .await
+        .map_err(|e| match e {
+            BiskyError::ApiError(e) => match e.error.as_str() {
+                "HandleNotAvailable" => BiskyError::HandleNotAvailable(e.message),
+                "UnsupportedDomain" => BiskyError::HandleVerificationFailed(e.message),
+                _ if e.message.contains("did not resolve") => {
+                    BiskyError::HandleVerificationFailed(e.message)
+                }
+                _ => BiskyError::ApiError(e),
+            },
+            e => e,
+        })?;
+
+        let mut session = self.session.get();
+        if let Some(session) = &mut session {
+            session.handle = handle;
+        }
+        self.update_session(session).await
+    }
+
+    ///com.atproto.identity.requestPlcOperationSignature
+    /// Emails the account a code for `identity_sign_plc_operation`
+    pub async fn identity_request_plc_operation_signature(&mut self) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response(
+            "com.atproto.identity.requestPlcOperationSignature",
+            &json!({}),
+        )
+        .await
+    }
+
+    ///com.atproto.identity.getRecommendedDidCredentials
+    /// The rotation keys, handle, signing key and PDS this server wants in the DID document
+    pub async fn identity_get_recommended_did_credentials(
+        &mut self,
+    ) -> Result<RecommendedDidCredentials, BiskyError> {
+        self.xrpc_get("com.atproto.identity.getRecommendedDidCredentials", None)
+            .await
+    }
+
+    ///com.atproto.identity.signPlcOperation
+    /// Sign a change to the DID document with the code from
+    /// `identity_request_plc_operation_signature`. Nothing here is retried, and errors are
+    /// passed on as the server sent them.
+    pub async fn identity_sign_plc_operation(
+        &mut self,
+        token: &str,
+        credentials: &RecommendedDidCredentials,
+    ) -> Result<serde_json::Value, BiskyError> {
+        // Not through `xrpc_post`, which would retry as the retry policy says
+        let response: SignPlcOperationOutput = self
+            .xrpc_post_proxied_once(
+                "com.atproto.identity.signPlcOperation",
+                &json_body(&SignPlcOperation { token, credentials })?,
+                None,
+            )
+            .await?;
+        Ok(response.operation)
+    }
+
+    /// Sign a DID document update that moves the account to `pds_endpoint` and, if given,
+    /// `handle`, keeping the current keys. This client must be logged in to the current PDS.
+    ///
+    /// The steps are:
+    /// 1. `identity_request_plc_operation_signature` emails the code used as `token`
+    /// 2. this signs the operation
+    /// 3. `identity_submit_plc_operation` on a client logged in to the new PDS submits it, since
+    ///    a PDS only accepts operations that point at itself
+    ///
+    /// See `migration::Migration` for the whole move.
+    pub async fn identity_sign_pds_update(
+        &mut self,
+        token: &str,
+        pds_endpoint: &str,
+        handle: Option<&str>,
+    ) -> Result<serde_json::Value, BiskyError> {
+        let credentials = RecommendedDidCredentials {
+            also_known_as: handle.map(|h| vec![format!("at://{}", normalize_handle(h))]),
+            services: Some(serde_json::Map::from_iter([(
+                "atproto_pds".to_string(),
+                json!({
+                    "type": "AtprotoPersonalDataServer",
+                    "endpoint": pds_endpoint,
+                }),
+            )])),
+            ..Default::default()
+        };
+
+        self.identity_sign_plc_operation(token, &credentials).await
+    }
+
+    ///com.atproto.identity.submitPlcOperation
+    /// Sent once whatever the retry policy, as an operation must not be submitted twice
+    pub async fn identity_submit_plc_operation(
+        &mut self,
+        operation: &serde_json::Value,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_once(
+            "com.atproto.identity.submitPlcOperation",
+            &json_body(&SubmitPlcOperation { operation })?,
+        )
+        .await
+    }
+
+    ///com.atproto.server.describeServer
+    pub async fn server_describe_server(&mut self) -> Result<DescribeServerOutput, BiskyError> {
+        self.xrpc_get_public("com.atproto.server.describeServer", None)
+            .await
+    }
+
+    ///com.atproto.server.getServiceAuth
+    /// A token that lets the service `aud` act as the logged in user, optionally only for the
+    /// method `lxm`
+    pub async fn server_get_service_auth(
+        &mut self,
+        aud: &str,
+        lxm: Option<&str>,
+    ) -> Result<String, BiskyError> {
+        let mut query = Vec::from([("aud", aud)]);
+        if let Some(lxm) = lxm {
+            query.push(("lxm", lxm));
+        }
+        let response = self
+            .xrpc_get::<GetServiceAuthOutput>("com.atproto.server.getServiceAuth", Some(&query))
+            .await?;
+        Ok(response.token)
+    }
+
+    ///com.atproto.server.createAccount
+    /// Create an account on this client's service and log in to it. `service_auth` is needed
+    /// when bringing an existing DID, see `server_get_service_auth`.
+    pub async fn server_create_account(
+        &mut self,
+        account: &CreateAccount,
+        service_auth: Option<&str>,
+    ) -> Result<(), BiskyError> {
+        let mut request = self
+            .request(reqwest::Method::POST, "com.atproto.server.createAccount")
+            .json(account);
+        if let Some(token) = service_auth {
+            request = request.header("authorization", format!("Bearer {token}"));
+        }
+
+        let response = self.send(request).await?;
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(BiskyError::ApiError(
+                ApiError::from_response(response).await,
+            ));
+        }
+        let created = self
+            .read_json::<CreateAccountOutput>(check_status(response).await?)
+            .await?;
+
+        self.update_session(Some(UserSession {
+            did: created.did,
+            handle: created.handle,
+            jwt: Jwt {
+                access: created.access_jwt,
+                refresh: created.refresh_jwt,
+            },
+        }))
+        .await
+    }
+
+    ///com.atproto.server.checkAccountStatus
+    pub async fn server_check_account_status(
+        &mut self,
+    ) -> Result<CheckAccountStatusOutput, BiskyError> {
+        self.xrpc_get("com.atproto.server.checkAccountStatus", None)
+            .await
+    }
+
+    ///com.atproto.server.activateAccount
+    pub async fn server_activate_account(&mut self) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response("com.atproto.server.activateAccount", &json!({}))
+            .await
+    }
+
+    ///com.atproto.server.deactivateAccount
+    pub async fn server_deactivate_account(&mut self) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response("com.atproto.server.deactivateAccount", &json!({}))
+            .await
+    }
+
+    ///com.atproto.sync.getRepo
+    /// The whole repo of `did` as a CAR file
+    pub async fn sync_get_repo(&mut self, did: &str) -> Result<Vec<u8>, BiskyError> {
+        self.xrpc_get_bytes("com.atproto.sync.getRepo", &[("did", did)])
+            .await
+    }
+
+    ///com.atproto.sync.getBlocks
+    /// The blocks `cids` of the repo of `did` as a CAR file, without roots
+    pub async fn sync_get_blocks(
+        &mut self,
+        did: &str,
+        cids: &[&str],
+    ) -> Result<Vec<u8>, BiskyError> {
+        let mut query = vec![("did", did)];
+        query.extend(cids.iter().map(|cid| ("cids", *cid)));
+        self.xrpc_get_bytes("com.atproto.sync.getBlocks", &query)
+            .await
+    }
+
+    ///com.atproto.sync.listBlobs
+    /// A page of the cids of the blobs in the repo of `did`, only those added since the revision
+    /// `since` if given. Works without a session.
+    pub async fn sync_list_blobs(
+        &mut self,
+        did: &str,
+        since: Option<&str>,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<ListBlobsOutput, BiskyError> {
+        let limit = limit.to_string();
+        let mut query = vec![("did", did), ("limit", limit.as_str())];
+        if let Some(since) = since {
+            query.push(("since", since));
+        }
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+        self.xrpc_get_public("com.atproto.sync.listBlobs", Some(&query))
+            .await
+    }
 
-            let mut response = self
-                .xrpc_get::<ListRecordsOutput<D>>("com.atproto.repo.listRecords", Some(&query))
+    /// Download every blob in the repo of `did`, or those added since the revision `since`,
+    /// `concurrency` at a time. `dest` is given each blob by cid as it arrives, in no particular
+    /// order.
+    ///
+    /// Rate limits, server errors and dropped connections are retried a few times, waiting
+    /// longer each time. Blobs that still fail are returned with their error rather than stopping
+    /// the rest; an error listing the blobs stops the backfill.
+    pub async fn sync_backfill_blobs(
+        &mut self,
+        did: &str,
+        since: Option<&str>,
+        concurrency: usize,
+        mut dest: impl FnMut(&str, Vec<u8>),
+    ) -> Result<Vec<(String, BiskyError)>, BiskyError> {
+        let mut failed = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = self
+                .sync_list_blobs(did, since, 1000, cursor.as_deref())
                 .await?;
 
-            if response.records.is_empty() {
-                // caller requested more records than are available
-                break;
+            let mut downloads = futures::stream::iter(page.cids)
+                .map(|cid| {
+                    let mut client = self.clone();
+                    async move {
+                        let mut delay = Duration::from_secs(1);
+                        let mut retries = 0;
+                        loop {
+                            match client.sync_get_blob(did, &cid).await {
+                                Err(e) if is_transient(&e) && retries < MAX_RATE_LIMIT_RETRIES => {
+                                    tokio::time::sleep(delay).await;
+                                    delay *= 2;
+                                    retries += 1;
+                                }
+                                result => return (cid, result),
+                            }
+                        }
+                    }
+                })
+                .buffer_unordered(concurrency.max(1));
+            while let Some((cid, result)) = downloads.next().await {
+                match result {
+                    Ok(bytes) => dest(&cid, bytes),
+                    Err(e) => failed.push((cid, e)),
+                }
             }
 
-            limit -= response.records.len();
+            match page.cursor {
+                Some(next) if cursor.as_ref() != Some(&next) => cursor = Some(next),
+                _ => return Ok(failed),
+            }
+        }
+    }
 
-            cursor = response.cursor.take();
-            records.append(&mut response.records);
+    ///com.atproto.sync.listRepos
+    /// A page of the repos the PDS or relay hosts, with the head and revision of each. Works
+    /// without a session.
+    pub async fn sync_list_repos(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<ListReposOutput, BiskyError> {
+        let limit = limit.to_string();
+        let mut query = vec![("limit", limit.as_str())];
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+        self.xrpc_get_public("com.atproto.sync.listRepos", Some(&query))
+            .await
+    }
+
+    ///com.atproto.repo.importRepo
+    /// Replace the logged in user's repo with a CAR file from `sync_get_repo`
+    pub async fn repo_import_repo(&mut self, car: &[u8]) -> Result<(), BiskyError> {
+        self.xrpc_post_binary(
+            "com.atproto.repo.importRepo",
+            car,
+            "application/vnd.ipld.car",
+        )
+        .await
+    }
+
+    ///com.atproto.label.queryLabels
+    /// A single page of the labels on subjects matching `uri_patterns`, which are exact uris or
+    /// prefixes ending in `*` such as `at://did:plc:abc/*`. With `sources`, only labels from those
+    /// labelers. Works without a session, so a client pointed at a labeler's service endpoint
+    /// can query it directly.
+    pub async fn label_query_labels(
+        &mut self,
+        uri_patterns: &[&str],
+        sources: &[&str],
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<Label>, Option<String>), BiskyError> {
+        let limit = std::cmp::min(limit, 250).to_string();
+        let mut query = uri_patterns
+            .iter()
+            .map(|pattern| ("uriPatterns", *pattern))
+            .chain(sources.iter().map(|source| ("sources", *source)))
+            .collect::<Vec<_>>();
+        query.push(("limit", &limit));
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
         }
 
-        Ok((records, cursor))
+        let response = self
+            .xrpc_get_public::<QueryLabelsOutput>("com.atproto.label.queryLabels", Some(&query))
+            .await?;
+        Ok((response.labels, response.cursor))
     }
 
-    pub async fn repo_create_record<D: DeserializeOwned, S: Serialize>(
+    /// com.atproto.label.queryLabels for every matching label, following the cursor until it
+    /// runs out
+    pub async fn label_query_all_labels(
         &mut self,
-        repo: &str,
-        collection: &str,
-        record: S,
-    ) -> Result<D, BiskyError> {
+        uri_patterns: &[&str],
+        sources: &[&str],
+    ) -> Result<Vec<Label>, BiskyError> {
+        let mut labels = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (mut page, next_cursor) = self
+                .label_query_labels(uri_patterns, sources, 250, cursor.as_deref())
+                .await?;
+            let done = page.is_empty() || next_cursor.is_none();
+            labels.append(&mut page);
+            if done {
+                return Ok(labels);
+            }
+            cursor = next_cursor;
+        }
+    }
+
+    ///com.atproto.moderation.createReport
+    /// Report to the moderation service the PDS forwards to, by default Bluesky's
+    pub async fn moderation_create_report(
+        &mut self,
+        subject: ReportSubject,
+        reason_type: ReasonType,
+        reason: Option<&str>,
+    ) -> Result<CreateReportOutput, BiskyError> {
         self.xrpc_post(
-            "com.atproto.repo.createRecord",
-            &CreateRecord {
-                repo,
-                collection,
-                record,
+            "com.atproto.moderation.createReport",
+            &CreateReport {
+                reason_type,
+                reason,
+                subject,
             },
         )
         .await
     }
 
-    pub async fn repo_upload_blob<D: DeserializeOwned>(
+    ///com.atproto.moderation.createReport
+    /// Report to the labeler run by `labeler_did`, through the PDS
+    pub async fn moderation_create_report_to(
         &mut self,
-        blob: &[u8],
-        mime_type: &str,
-    ) -> Result<D, BiskyError> {
-        self.xrpc_post_binary("com.atproto.repo.uploadBlob", blob, mime_type)
+        labeler_did: &str,
+        subject: ReportSubject,
+        reason_type: ReasonType,
+        reason: Option<&str>,
+    ) -> Result<CreateReportOutput, BiskyError> {
+        self.xrpc_post_proxied(
+            "com.atproto.moderation.createReport",
+            &CreateReport {
+                reason_type,
+                reason,
+                subject,
+            },
+            Some(&labeler_proxy(labeler_did)),
+        )
+        .await
+    }
+
+    ///com.atproto.repo.describeRepo
+    /// Works without a session
+    pub async fn repo_describe_repo(
+        &mut self,
+        repo: &str,
+    ) -> Result<DescribeRepoOutput, BiskyError> {
+        self.xrpc_get_public("com.atproto.repo.describeRepo", Some(&[("repo", repo)]))
             .await
     }
 
-    pub async fn repo_stream_records<'a, D: DeserializeOwned + std::fmt::Debug>(
-        &'a mut self,
-        repo: &'a str,
-        collection: &'a str,
-    ) -> Result<RecordStream<'a, D>, StreamError> {
-        let (_, cursor) = self
-            .repo_list_records::<D>(repo, collection, 1, false, None)
+    ///com.atproto.repo.listMissingBlobs
+    /// Blobs the logged in user's records reference that this PDS does not have
+    pub async fn repo_list_missing_blobs(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<MissingBlob>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<ListMissingBlobsOutput>(
+            "com.atproto.repo.listMissingBlobs",
+            &[],
+            limit,
+            1000,
+            cursor,
+        )
+        .await
+    }
+
+    ///tools.ozone.moderation.queryStatuses
+    /// The moderation statuses of the subjects `labeler_did`'s Ozone knows about, optionally
+    /// only those about `subject` (a DID or at:// uri) or in `review_state`, e.g.
+    /// `tools.ozone.moderation.defs#reviewOpen`. The session must be a moderator of the labeler.
+    #[cfg(feature = "ozone")]
+    pub async fn ozone_query_statuses(
+        &mut self,
+        labeler_did: &str,
+        subject: Option<&str>,
+        review_state: Option<&str>,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<SubjectStatusView>, Option<String>), BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = vec![("limit", limit.as_str())];
+        if let Some(subject) = subject {
+            query.push(("subject", subject));
+        }
+        if let Some(review_state) = review_state {
+            query.push(("reviewState", review_state));
+        }
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        let response = self
+            .xrpc_get_proxied::<QueryStatusesOutput>(
+                "tools.ozone.moderation.queryStatuses",
+                Some(&query),
+                Some(&labeler_proxy(labeler_did)),
+            )
             .await?;
+        Ok((response.subject_statuses, response.cursor))
+    }
 
+    ///tools.ozone.moderation.queryEvents
+    /// The moderation events on `labeler_did`'s Ozone, newest first, optionally only those about
+    /// `subject` and of `types`, see `ModEvent::event_type`
+    #[cfg(feature = "ozone")]
+    pub async fn ozone_query_events(
+        &mut self,
+        labeler_did: &str,
+        subject: Option<&str>,
+        types: &[&str],
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ModEventView>, Option<String>), BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = types.iter().map(|t| ("types", *t)).collect::<Vec<_>>();
+        query.push(("limit", &limit));
+        if let Some(subject) = subject {
+            query.push(("subject", subject));
+        }
         if let Some(cursor) = cursor {
-            Ok(RecordStream {
-                client: self,
-                repo,
-                collection,
-                queue: VecDeque::new(),
-                cursor,
-            })
-        } else {
-            Err(StreamError::NoCursor)
+            query.push(("cursor", cursor));
         }
+
+        let response = self
+            .xrpc_get_proxied::<QueryEventsOutput>(
+                "tools.ozone.moderation.queryEvents",
+                Some(&query),
+                Some(&labeler_proxy(labeler_did)),
+            )
+            .await?;
+        Ok((response.events, response.cursor))
     }
-    /// Get the user's notification count. Can take a date to mark them as seen
-    pub async fn bsky_get_notification_count(
+
+    ///tools.ozone.moderation.emitEvent
+    /// Take a moderation action on `subject` as the logged in moderator, e.g. apply labels with
+    /// `ModEvent::Label` or close its reports with `ModEvent::Acknowledge`
+    #[cfg(feature = "ozone")]
+    pub async fn ozone_emit_event(
         &mut self,
-        seen_at: Option<&str>,
-    ) -> Result<NotificationCount, BiskyError> {
-        let mut query = Vec::new();
+        labeler_did: &str,
+        event: &ModEvent,
+        subject: &ReportSubject,
+        subject_blob_cids: &[&str],
+    ) -> Result<ModEventView, BiskyError> {
+        let created_by = self.session_did()?;
+        self.xrpc_post_proxied(
+            "tools.ozone.moderation.emitEvent",
+            &EmitEvent {
+                event,
+                subject,
+                subject_blob_cids,
+                created_by: &created_by,
+            },
+            Some(&labeler_proxy(labeler_did)),
+        )
+        .await
+    }
 
-        if let Some(seen_at) = seen_at {
-            query.push(("seen_at", seen_at));
+    ///tools.ozone.moderation.getRecord
+    #[cfg(feature = "ozone")]
+    pub async fn ozone_get_record(
+        &mut self,
+        labeler_did: &str,
+        uri: &str,
+        cid: Option<&str>,
+    ) -> Result<RecordViewDetail, BiskyError> {
+        let mut query = vec![("uri", uri)];
+        if let Some(cid) = cid {
+            query.push(("cid", cid));
         }
-        let res = self
-            .xrpc_get::<NotificationCount>("app.bsky.notification.getUnreadCount", Some(&query))
-            .await?;
-        Ok(res)
+        self.xrpc_get_proxied(
+            "tools.ozone.moderation.getRecord",
+            Some(&query),
+            Some(&labeler_proxy(labeler_did)),
+        )
+        .await
     }
 
-    pub async fn bsky_list_notifications<D: DeserializeOwned + std::fmt::Debug>(
+    ///tools.ozone.moderation.getRepo
+    #[cfg(feature = "ozone")]
+    pub async fn ozone_get_repo(
         &mut self,
-        mut limit: usize,
-        seen_at: Option<&str>,
-        cursor: Option<&str>,
-    ) -> Result<(Vec<Notification<D>>, Option<String>), BiskyError> {
-        let mut notifications = Vec::new();
-        let mut response_cursor = None;
+        labeler_did: &str,
+        did: &str,
+    ) -> Result<RepoViewDetail, BiskyError> {
+        self.xrpc_get_proxied(
+            "tools.ozone.moderation.getRepo",
+            Some(&[("did", did)]),
+            Some(&labeler_proxy(labeler_did)),
+        )
+        .await
+    }
 
-        while limit > 0 {
-            let query_limit = std::cmp::min(limit, 100).to_string();
-            let mut query = Vec::from([("limit", query_limit.as_ref())]);
+    /// The session DID, if `uri` points into the logged in user's repo
+    #[cfg(feature = "bsky")]
+    pub(crate) fn own_repo_did(&self, uri: &AtUri) -> Result<String, BiskyError> {
+        let Some(session) = self.session.get() else {
+            return Err(BiskyError::MissingSession);
+        };
+        if uri.authority != session.did && uri.authority != session.handle {
+            return Err(BiskyError::NotOwnRecord(uri.to_string()));
+        }
+        Ok(session.did.clone())
+    }
 
-            if let Some(cursor) = cursor {
-                query.push(("cursor", cursor));
+    /// Delete the record at `uri`, checking that it belongs to `collection`
+    #[cfg(feature = "bsky")]
+    pub(crate) async fn delete_record_at(
+        &mut self,
+        uri: &AtUri,
+        collection: &str,
+    ) -> Result<(), BiskyError> {
+        if uri.require_collection()? != collection {
+            return Err(BiskyError::InvalidAtUri(format!(
+                "{uri} is not an {collection} record"
+            )));
+        }
+        self.repo_delete_record(&uri.authority, collection, uri.require_rkey()?)
+            .await
+    }
+
+    /// Page through a collection until a record matching `predicate` is found
+    #[cfg(feature = "bsky")]
+    pub(crate) async fn find_subject_record<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        repo: &str,
+        collection: &str,
+        predicate: impl Fn(&D) -> bool,
+    ) -> Result<Option<Record<D>>, BiskyError> {
+        let mut cursor = None;
+        loop {
+            let (records, next_cursor) = self
+                .repo_list_records::<D>(repo, collection, 100, false, cursor)
+                .await?;
+            if records.is_empty() || next_cursor.is_none() {
+                return Ok(records.into_iter().find(|r| predicate(&r.value)));
             }
-            if let Some(seen_at) = seen_at {
-                query.push(("seenAt", seen_at));
+            if let Some(record) = records.into_iter().find(|r| predicate(&r.value)) {
+                return Ok(Some(record));
             }
+            cursor = next_cursor;
+        }
+    }
+}
 
-            let mut response = self
-                .xrpc_get::<ListNotificationsOutput<D>>(
-                    "app.bsky.notification.listNotifications",
-                    Some(&query),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::{ExponentialBackoff, NoRetry};
+    use serde_json::{json, Value};
+    use std::collections::VecDeque;
+
+    fn rate_limit(headers: &[(&'static str, &str)]) -> Option<RateLimitInfo> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    reqwest::header::HeaderName::from_static(name),
+                    reqwest::header::HeaderValue::from_str(value).unwrap(),
                 )
-                .await?;
+            })
+            .collect();
+        RateLimitInfo::from_headers(&headers)
+    }
 
-            if response.notifications.is_empty() {
-                // caller requested more records than are available
-                break;
-            }
+    #[test]
+    fn rate_limit_headers() {
+        let all = [
+            ("ratelimit-limit", "5000"),
+            ("ratelimit-remaining", "4997"),
+            ("ratelimit-reset", "1730800000"),
+            ("ratelimit-policy", "5000;w=3600"),
+        ];
+        let hour = Some(RateLimitPolicy {
+            limit: 5000,
+            window: Duration::from_secs(3600),
+        });
+        assert_eq!(
+            rate_limit(&all),
+            Some(RateLimitInfo {
+                limit: 5000,
+                remaining: 4997,
+                reset: 1730800000,
+                policy: hour,
+            })
+        );
 
-            limit -= response.notifications.len();
+        // Any of the three numbers missing is no rate limit at all
+        for missing in 0..3 {
+            let mut headers = all.to_vec();
+            headers.remove(missing);
+            assert_eq!(rate_limit(&headers), None, "without {}", all[missing].0);
+        }
+        assert_eq!(rate_limit(&[]), None);
 
-            response_cursor = response.cursor.take();
-            notifications.append(&mut response.notifications);
+        // As is any of them not being a number
+        for (i, garbage) in [(0, "lots"), (1, "-3"), (1, "4997.5"), (2, "soon"), (2, "")] {
+            let mut headers = all.to_vec();
+            headers[i].1 = garbage;
+            assert_eq!(rate_limit(&headers), None, "{}: {garbage:?}", all[i].0);
+        }
+        // Padding is fine
+        let mut headers = all.to_vec();
+        headers[1].1 = " 4997 ";
+        assert_eq!(rate_limit(&headers).unwrap().remaining, 4997);
+
+        // Without a readable policy, the rest is still read
+        let policy = |policy: &'static str| {
+            let mut headers = all.to_vec();
+            headers[3].1 = policy;
+            rate_limit(&headers).unwrap().policy
+        };
+        assert_eq!(rate_limit(&all[..3]).unwrap().policy, None);
+        for garbage in [
+            "",
+            "5000",
+            "5000;q=1",
+            "w=3600",
+            "lots;w=3600",
+            "5000;w=hour",
+            ";;;",
+        ] {
+            assert_eq!(policy(garbage), None, "{garbage:?}");
         }
+        // Of several, the first is taken, with its window wherever it is among the parameters
+        assert_eq!(policy("5000;w=3600, 100;w=60"), hour);
+        assert_eq!(policy("5000; pk=abc; w=3600"), hour);
+    }
 
-        Ok((notifications, response_cursor))
+    /// Records and the cursor `RecordSplitter` picks out of `body` fed `chunk` bytes at a time
+    fn split(
+        body: &str,
+        chunk: usize,
+        limit: Option<usize>,
+    ) -> Result<(Vec<Value>, Option<String>, bool), BiskyError> {
+        let mut splitter = RecordSplitter {
+            limit,
+            ..Default::default()
+        };
+        for chunk in body.as_bytes().chunks(chunk) {
+            splitter.feed(chunk)?;
+        }
+        let records = splitter
+            .records
+            .iter()
+            .map(|record| serde_json::from_slice(record).unwrap())
+            .collect();
+        Ok((records, splitter.cursor, splitter.done))
     }
 
-    pub async fn bsky_update_seen(&mut self, seen_at: DateTime<Utc>) -> Result<(), BiskyError> {
-        self.xrpc_post_no_response("app.bsky.notification.updateSeen", &UpdateSeen { seen_at })
-            .await
+    /// Split `body` every way from one byte at a time to all at once, checking against serde
+    fn assert_splits(body: &str) {
+        let expected: Value = serde_json::from_str(body).unwrap();
+        let records = expected["records"].as_array().unwrap();
+        let cursor = expected["cursor"].as_str().map(str::to_string);
+        for chunk in 1..=body.len() {
+            let (split_records, split_cursor, done) = split(body, chunk, None).unwrap();
+            assert_eq!(&split_records, records, "chunks of {chunk}");
+            assert_eq!(split_cursor, cursor, "chunks of {chunk}");
+            assert!(done, "chunks of {chunk}");
+        }
     }
 
-    pub async fn bsky_stream_notifications<'a, D: DeserializeOwned + std::fmt::Debug>(
-        &'a mut self,
-        seen_at: Option<&'a str>,
-    ) -> Result<NotificationStream<'a, D>, StreamError> {
-        let (_, cursor) = self
-            .bsky_list_notifications::<D>(usize::MAX, seen_at, None)
-            .await?;
+    const RECORD_A: &str = r#"{"uri":"at://did:plc:alice/app.bsky.feed.post/3k2a","cid":"bafyreia","value":{"$type":"app.bsky.feed.post","text":"quote \"records\": [1, {2}] and a backslash \\","createdAt":"2024-01-01T00:00:00.000Z"}}"#;
+    const RECORD_B: &str = r#"{"uri":"at://did:plc:alice/app.bsky.feed.post/3k2b","cid":"bafyreib","value":{"text":"ends in \\","langs":["en"],"n":-1.5e3,"ok":true,"none":null,"cursor":"not this one"}}"#;
 
-        if let Some(cursor) = cursor {
-            Ok(NotificationStream {
-                client: self,
-                queue: VecDeque::new(),
-                cursor,
-                limit: usize::MAX,
-                seen_at,
-            })
-        } else {
-            Err(StreamError::NoCursor)
-        }
+    #[test]
+    fn records_split_with_cursor_after() {
+        assert_splits(&format!(
+            r#"{{"records":[{RECORD_A},{RECORD_B}],"cursor":"3k2b"}}"#
+        ));
     }
-    ///app.bsky.feed.getLikes
-    pub async fn bsky_get_likes(
-        &mut self,
-        uri: &str,
-        mut limit: usize,
-        cursor: Option<&str>,
-    ) -> Result<(Vec<GetLikesLike>, Option<String>), BiskyError> {
-        let mut likes = Vec::new();
-        let mut response_cursor = None;
 
-        while limit > 0 {
-            let query_limit = std::cmp::min(limit, 100).to_string();
-            let mut query = Vec::from([("uri", uri), ("limit", query_limit.as_str())]);
+    #[test]
+    fn records_split_with_cursor_before() {
+        assert_splits(&format!(
+            r#"{{"cursor":"3k2b","records":[{RECORD_A},{RECORD_B}]}}"#
+        ));
+    }
 
-            if let Some(cursor) = cursor {
-                query.push(("cursor", cursor));
-            }
+    #[test]
+    fn records_split_with_whitespace_and_other_members() {
+        assert_splits(&format!(
+            "{{\n  \"extra\": {{\"records\": [\"no\"]}},\n  \"records\": [\n    {RECORD_A} ,\n    {RECORD_B}\n  ],\n  \"cursor\" : \"a \\\"quoted\\\" cursor\"\n}}\n"
+        ));
+    }
 
-            let mut response = self
-                .xrpc_get::<GetLikesOutput>("app.bsky.feed.getLikes", Some(&query))
-                .await?;
+    #[test]
+    fn records_split_without_cursor() {
+        assert_splits(&format!(r#"{{"records":[{RECORD_A}]}}"#));
+        assert_splits(&format!(r#"{{"records":[{RECORD_B}],"cursor":null}}"#));
+        assert_splits(r#"{"records":[]}"#);
+    }
 
-            if response.likes.is_empty() {
-                // caller requested more records than are available
-                break;
-            }
+    #[test]
+    fn records_split_mid_escape() {
+        // A chunk that ends right after a backslash, so the quote it escapes starts the next one
+        let body = format!(r#"{{"records":[{RECORD_A}],"cursor":"x"}}"#);
+        let at = body.find(r#"\""#).unwrap() + 1;
+        let mut splitter = RecordSplitter::default();
+        splitter.feed(&body.as_bytes()[..at]).unwrap();
+        splitter.feed(&body.as_bytes()[at..]).unwrap();
+        assert!(splitter.done);
+        assert_eq!(splitter.records.len(), 1);
+        assert_eq!(splitter.records[0], RECORD_A.as_bytes());
+        assert_eq!(splitter.cursor.as_deref(), Some("x"));
+    }
 
-            limit -= response.likes.len();
+    #[test]
+    fn records_split_truncated() {
+        let body = format!(r#"{{"records":[{RECORD_A},{RECORD_B}],"cursor":"3k2b"}}"#);
+        let (records, _, done) = split(&body[..body.len() - 1], 1, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(!done);
+        let (records, _, done) = split(&body[..body.len() / 2], 1, None).unwrap();
+        assert!(records.len() < 2);
+        assert!(!done);
+    }
 
-            response_cursor = response.cursor.take();
-            likes.append(&mut response.likes);
+    #[test]
+    fn records_split_size_limit() {
+        let body = format!(r#"{{"records":[{RECORD_A},{RECORD_B}],"cursor":"3k2b"}}"#);
+        let largest = RECORD_A.len().max(RECORD_B.len());
+        for chunk in [1, 7, body.len()] {
+            // The limit is per record, not for the whole response
+            let (records, _, _) = split(&body, chunk, Some(largest)).unwrap();
+            assert_eq!(records.len(), 2);
+
+            match split(&body, chunk, Some(largest - 1)) {
+                Err(BiskyError::ResponseTooLarge { size, limit }) => {
+                    assert_eq!(limit, largest - 1);
+                    assert!(size > limit);
+                }
+                other => panic!("chunks of {chunk}: {other:?}"),
+            }
         }
+    }
 
-        Ok((likes, response_cursor))
+    /// Answers listRecords with `body`, in one page
+    struct OnePage(String);
+
+    #[async_trait::async_trait]
+    impl XrpcTransport for OnePage {
+        async fn execute(
+            &self,
+            _: &reqwest::Client,
+            _: reqwest::Request,
+        ) -> Result<reqwest::Response, BiskyError> {
+            Ok(http::Response::builder()
+                .header("content-type", "application/json")
+                .body(self.0.clone())
+                .unwrap()
+                .into())
+        }
     }
 
-    ///app.bsky.graph.getFollows
-    pub async fn bsky_get_follows(
-        &mut self,
-        actor: &str,
-        mut limit: usize,
-        cursor: Option<&str>,
-    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
-        let mut follows = Vec::new();
-        let mut response_cursor = None;
+    /// Answers listRecords with a body sent in chunks through the sender it is made with
+    struct Chunked(parking_lot::Mutex<Option<hyper::Body>>);
 
-        while limit > 0 {
-            let query_limit = std::cmp::min(limit, 100).to_string();
-            let mut query = Vec::from([("actor", actor), ("limit", &query_limit)]);
+    impl Chunked {
+        fn new() -> (Arc<Self>, hyper::body::Sender) {
+            let (sender, body) = hyper::Body::channel();
+            (Arc::new(Self(parking_lot::Mutex::new(Some(body)))), sender)
+        }
+    }
 
-            if let Some(cursor) = cursor {
-                query.push(("cursor", cursor));
-            }
+    #[async_trait::async_trait]
+    impl XrpcTransport for Chunked {
+        async fn execute(
+            &self,
+            _: &reqwest::Client,
+            _: reqwest::Request,
+        ) -> Result<reqwest::Response, BiskyError> {
+            let body = self.0.lock().take().expect("one page");
+            Ok(http::Response::builder()
+                .header("content-type", "application/json")
+                .body(body)
+                .unwrap()
+                .into())
+        }
+    }
 
-            let mut response = self
-                .xrpc_get::<GetFollowsOutput>("app.bsky.graph.getFollows", Some(&query))
-                .await?;
+    async fn stream_records(
+        body: String,
+        max_response_size: usize,
+    ) -> Vec<Result<Record<Value>, BiskyError>> {
+        let mut client = ClientBuilder::default()
+            .transport(Arc::new(OnePage(body)))
+            .max_response_size(Some(max_response_size))
+            .build()
+            .unwrap();
+        client
+            .repo_list_records_stream::<Value>("did:plc:alice", "app.bsky.feed.post", false)
+            .collect()
+            .await
+    }
 
-            if response.follows.is_empty() {
-                // caller requested more records than are available
-                break;
+    #[tokio::test]
+    async fn records_stream_size_limit() {
+        let body = format!(r#"{{"records":[{RECORD_A},{RECORD_B}]}}"#);
+        let largest = RECORD_A.len().max(RECORD_B.len());
+
+        // A page larger than the limit is fine as long as each record fits
+        let records = stream_records(body.clone(), largest).await;
+        assert!(body.len() > largest);
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(Result::is_ok));
+
+        let records = stream_records(body, RECORD_A.len().min(RECORD_B.len()) - 1).await;
+        assert_eq!(records.len(), 1);
+        assert!(matches!(
+            records[0],
+            Err(BiskyError::ResponseTooLarge { .. })
+        ));
+
+        // The records before one that's too large still come through, even in the same chunk
+        assert!(RECORD_B.len() < RECORD_A.len());
+        let body = format!(r#"{{"records":[{RECORD_B},{RECORD_A}]}}"#);
+        let records = stream_records(body, RECORD_B.len()).await;
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].as_ref().unwrap().uri,
+            "at://did:plc:alice/app.bsky.feed.post/3k2b"
+        );
+        assert!(matches!(
+            records[1],
+            Err(BiskyError::ResponseTooLarge { .. })
+        ));
+
+        // Each record is yielded as soon as it has arrived, with the rest of the body held back
+        let (transport, mut sender) = Chunked::new();
+        let mut client = ClientBuilder::default()
+            .transport(transport)
+            .max_response_size(Some(largest))
+            .build()
+            .unwrap();
+        let stream =
+            client.repo_list_records_stream::<Value>("did:plc:alice", "app.bsky.feed.post", false);
+        futures::pin_mut!(stream);
+        let (first, rest) = RECORD_A.split_at(RECORD_A.len() / 2);
+        let (release, held) = tokio::sync::oneshot::channel::<()>();
+        let sending = tokio::spawn(async move {
+            for chunk in [r#"{"records":["#, first, rest, ","] {
+                sender.send_data(chunk.into()).await.unwrap();
             }
+            held.await.unwrap();
+            sender
+                .send_data(format!("{RECORD_B}]}}").into())
+                .await
+                .unwrap();
+        });
+        let record = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("yielded before the body ends")
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.uri, "at://did:plc:alice/app.bsky.feed.post/3k2a");
+        release.send(()).unwrap();
+        let records = stream.collect::<Vec<_>>().await;
+        sending.await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].as_ref().unwrap().uri,
+            "at://did:plc:alice/app.bsky.feed.post/3k2b"
+        );
+    }
 
-            limit -= response.follows.len();
+    /// A status, headers and body to answer with
+    type Response = (u16, &'static [(&'static str, &'static str)], Value);
 
-            response_cursor = response.cursor.take();
-            follows.append(&mut response.follows);
+    /// Answers each request with the next of its responses, keeping the query of each
+    #[derive(Default)]
+    struct Scripted {
+        responses: parking_lot::Mutex<VecDeque<Response>>,
+        queries: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl Scripted {
+        fn new(responses: impl IntoIterator<Item = Response>) -> Arc<Self> {
+            Arc::new(Self {
+                responses: parking_lot::Mutex::new(responses.into_iter().collect()),
+                queries: Default::default(),
+            })
         }
 
-        Ok((follows, response_cursor))
+        fn queries(&self) -> Vec<String> {
+            self.queries.lock().clone()
+        }
     }
 
-    ///app.bsky.graph.getFollowers
-    pub async fn bsky_get_followers(
-        &mut self,
-        actor: &str,
-        mut limit: usize,
-        cursor: Option<&str>,
-    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
-        let mut followers = Vec::new();
-        let mut response_cursor = None;
+    #[async_trait::async_trait]
+    impl XrpcTransport for Scripted {
+        async fn execute(
+            &self,
+            _: &reqwest::Client,
+            request: reqwest::Request,
+        ) -> Result<reqwest::Response, BiskyError> {
+            self.queries
+                .lock()
+                .push(request.url().query().unwrap_or_default().to_string());
+            let (status, headers, body) = self.responses.lock().pop_front().unwrap();
+            let mut response = http::Response::builder()
+                .status(status)
+                .header("content-type", "application/json");
+            for (name, value) in headers {
+                response = response.header(*name, *value);
+            }
+            Ok(response.body(body.to_string()).unwrap().into())
+        }
+    }
 
-        while limit > 0 {
-            let query_limit = std::cmp::min(limit, 100).to_string();
-            let mut query = Vec::from([("actor", actor), ("limit", &query_limit)]);
+    fn scripted_client(transport: &Arc<Scripted>, policy: Option<Arc<dyn RetryPolicy>>) -> Client {
+        let mut builder = ClientBuilder::default();
+        builder
+            .transport(transport.clone())
+            .session(Some(UserSession::new(
+                "did:plc:alice",
+                "alice.test",
+                "access",
+                "refresh",
+            )));
+        if let Some(policy) = policy {
+            builder.retry_policy(policy);
+        }
+        builder.build().unwrap()
+    }
 
-            if let Some(cursor) = cursor.as_ref() {
-                query.push(("cursor", cursor));
-            }
+    fn no_wait() -> ExponentialBackoff {
+        ExponentialBackoff {
+            first_delay: Duration::ZERO,
+            max_retries: 2,
+            ..Default::default()
+        }
+    }
 
-            let mut response = self
-                .xrpc_get::<GetFollowersOutput>("app.bsky.graph.getFollowers", Some(&query))
-                .await?;
+    const OK: Response = (200, &[], Value::Null);
 
-            if response.followers.is_empty() {
-                // caller requested more records than are available
-                break;
-            }
+    fn failed(status: u16, error: &str) -> Response {
+        (status, &[], json!({"error": error, "message": ""}))
+    }
 
-            limit -= response.followers.len();
+    #[tokio::test]
+    async fn retries_follow_the_policy() {
+        let outage = || {
+            Scripted::new([
+                failed(503, "UpstreamFailure"),
+                failed(502, "BadGateway"),
+                failed(500, "InternalServerError"),
+                OK,
+            ])
+        };
+        let get = |client: &mut Client| {
+            let mut client = client.clone();
+            async move { client.xrpc_get::<Value>("com.example.get", None).await }
+        };
 
-            response_cursor = response.cursor.take();
-            followers.append(&mut response.followers);
+        // Up to the policy's retries
+        let transport = outage();
+        let result = get(&mut scripted_client(&transport, Some(Arc::new(no_wait())))).await;
+        assert!(matches!(result, Err(BiskyError::ApiError(e)) if e.status() == Some(500)));
+        assert_eq!(transport.queries().len(), 3);
+
+        let transport = Scripted::new([failed(503, "UpstreamFailure"), OK]);
+        let mut client = scripted_client(&transport, Some(Arc::new(no_wait())));
+        assert_eq!(get(&mut client).await.unwrap(), Value::Null);
+        assert_eq!(transport.queries().len(), 2);
+
+        // Not at all without a policy, or with NoRetry
+        for policy in [None, Some(Arc::new(NoRetry) as Arc<dyn RetryPolicy>)] {
+            let transport = outage();
+            assert!(get(&mut scripted_client(&transport, policy)).await.is_err());
+            assert_eq!(transport.queries().len(), 1);
         }
 
-        Ok((followers, response_cursor))
+        // A procedure that may have taken effect isn't sent twice, but a rate limited one is
+        let transport = Scripted::new([failed(503, "UpstreamFailure"), OK]);
+        let mut client = scripted_client(&transport, Some(Arc::new(no_wait())));
+        assert!(client
+            .xrpc_post::<_, Value>("com.example.post", &json!({}))
+            .await
+            .is_err());
+        assert_eq!(transport.queries().len(), 1);
+
+        let transport = Scripted::new([failed(429, "RateLimitExceeded"), OK]);
+        let mut client = scripted_client(&transport, Some(Arc::new(no_wait())));
+        client
+            .xrpc_post::<_, Value>("com.example.post", &json!({}))
+            .await
+            .unwrap();
+        assert_eq!(transport.queries().len(), 2);
     }
 
-    ///app.bsky.feed.getPostThread
-    pub async fn bsky_get_post_thread(
-        &mut self,
-        uri: &str,
-    ) -> Result<ThreadViewPostEnum, BiskyError> {
-        let query = Vec::from([("uri", uri)]);
+    #[tokio::test]
+    async fn pages_retry_rate_limits() {
+        use crate::lexicon::com::atproto::repo::ListMissingBlobsOutput;
 
-        let response = self
-            .xrpc_get::<GetPostThreadOutput>("app.bsky.feed.getPostThread", Some(&query))
-            .await?;
+        let page = |cids: &[&str], cursor: Option<&str>| {
+            let blobs = cids
+                .iter()
+                .map(|cid| json!({"cid": cid, "recordUri": "at://did:plc:alice/a/b"}))
+                .collect::<Vec<_>>();
+            (200, &[] as &[_], json!({"blobs": blobs, "cursor": cursor}))
+        };
+        let rate_limited = (
+            429,
+            &[
+                ("ratelimit-limit", "3000"),
+                ("ratelimit-remaining", "0"),
+                ("ratelimit-reset", "0"),
+            ][..],
+            json!({"error": "RateLimitExceeded", "message": ""}),
+        );
+        let script = || {
+            Scripted::new([
+                page(&["a", "b"], Some("2")),
+                rate_limited.clone(),
+                rate_limited.clone(),
+                page(&["c"], None),
+            ])
+        };
 
-        Ok(response.thread)
+        // The page that was rate limited is asked for again from the same cursor
+        let transport = script();
+        let mut client = scripted_client(&transport, Some(Arc::new(no_wait())));
+        let (blobs, cursor) = client
+            .xrpc_get_pages::<ListMissingBlobsOutput>(
+                "com.atproto.repo.listMissingBlobs",
+                &[],
+                10,
+                2,
+                None,
+            )
+            .await
+            .unwrap();
+        let cids = blobs
+            .iter()
+            .map(|blob| blob.cid.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(cids, ["a", "b", "c"]);
+        assert_eq!(cursor, None);
+        assert_eq!(
+            transport.queries(),
+            [
+                "limit=2",
+                "limit=2&cursor=2",
+                "limit=2&cursor=2",
+                "limit=2&cursor=2"
+            ]
+        );
+        assert_eq!(client.last_rate_limit().unwrap().remaining, 0);
+
+        // Giving up ends the walk
+        let transport = script();
+        let mut client = scripted_client(&transport, Some(Arc::new(NoRetry)));
+        let result = client
+            .xrpc_get_pages::<ListMissingBlobsOutput>(
+                "com.atproto.repo.listMissingBlobs",
+                &[],
+                10,
+                2,
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(BiskyError::RateLimited(Some(_)))));
+        assert_eq!(transport.queries().len(), 2);
+
+        // Without a policy, a server error isn't retried even when paginating
+        let transport = Scripted::new([failed(503, "UpstreamFailure"), OK]);
+        let mut client = scripted_client(&transport, None);
+        let result = client
+            .xrpc_get_pages::<ListMissingBlobsOutput>(
+                "com.atproto.repo.listMissingBlobs",
+                &[],
+                10,
+                2,
+                None,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(transport.queries().len(), 1);
     }
 }
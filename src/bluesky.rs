@@ -1,56 +1,91 @@
-use crate::atproto::{Client, NotificationStream, RecordStream, StreamError};
+use crate::atproto::{RecordStream, StreamError};
+use crate::bsky::{BskyClient, NotificationStream};
 use crate::errors::BiskyError;
-use crate::lexicon::app::bsky::actor::{ProfileView, ProfileViewDetailed};
-use crate::lexicon::app::bsky::feed::{GetLikesLike, Post, ThreadViewPostEnum};
+use crate::lexicon::app::bsky::actor::{ProfileUpdate, ProfileView, ProfileViewDetailed};
+use crate::lexicon::app::bsky::feed::{
+    FeedViewPost, GeneratorView, GetLikesLike, Post, ThreadOptions, ThreadViewPostEnum,
+};
 use crate::lexicon::app::bsky::notification::{
     Notification, NotificationCount, NotificationRecord,
 };
 use crate::lexicon::com::atproto::repo::{BlobOutput, CreateRecordOutput, Record};
-use chrono::Utc;
+use crate::types::{AtUri, Datetime};
 pub struct Bluesky {
-    client: Client,
+    client: BskyClient,
 }
 
 impl Bluesky {
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    pub fn new(client: impl Into<BskyClient>) -> Self {
+        Self {
+            client: client.into(),
+        }
     }
 
-    pub fn user(&mut self, username: &str) -> Result<BlueskyUser, BiskyError> {
-        let Some(_session) = &self.client.session else{
+    pub fn user(&mut self, username: &str) -> Result<BlueskyUser<'_>, BiskyError> {
+        if self.client.session().is_none() {
             return Err(BiskyError::MissingSession);
-        };
+        }
         Ok(BlueskyUser {
             client: &mut self.client,
             username: username.to_string(),
         })
     }
 
-    pub fn me(&mut self) -> Result<BlueskyMe, BiskyError> {
-        let Some(session) = &self.client.session else{
+    pub fn me(&mut self) -> Result<BlueskyMe<'_>, BiskyError> {
+        let Some(session) = self.client.session() else {
             return Err(BiskyError::MissingSession);
         };
         Ok(BlueskyMe {
-            username: session.did.to_string(),
+            username: session.did,
             client: &mut self.client,
         })
     }
 }
 
 pub struct BlueskyMe<'a> {
-    client: &'a mut Client,
+    client: &'a mut BskyClient,
     username: String,
 }
 
 impl<'a> BlueskyMe<'a> {
     /// Post a new Post to your skyline
     pub async fn post(&mut self, post: Post) -> Result<CreateRecordOutput, BiskyError> {
+        post.validate()?;
         self.client
             .repo_create_record(&self.username, "app.bsky.feed.post", &post)
             .await
     }
-    /// Get the notifications for the user
-    ///app.bsky.notification.listNotifications#
+    /// Edit your profile, see `BskyClient::bsky_update_profile`
+    pub async fn update_profile(
+        &mut self,
+        update: impl FnOnce(&mut ProfileUpdate),
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        self.client.bsky_update_profile(update).await
+    }
+
+    /// Post `text` with an image, see `BskyClient::bsky_post_image`
+    pub async fn post_image(
+        &mut self,
+        text: &str,
+        image: &[u8],
+        mime_type: &str,
+        alt: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        self.client
+            .bsky_post_image(text, image, mime_type, alt)
+            .await
+    }
+
+    /// Post text of any length as a reply chain, see `BskyClient::bsky_post_thread`
+    pub async fn post_thread(
+        &mut self,
+        text: &str,
+        options: &ThreadOptions,
+    ) -> Result<Vec<CreateRecordOutput>, BiskyError> {
+        self.client.bsky_post_thread(text, options).await
+    }
+    /// Get the number of unread notifications for the user
+    ///app.bsky.notification.getUnreadCount
     pub async fn get_notification_count(
         &mut self,
         seen_at: Option<&str>,
@@ -71,12 +106,12 @@ impl<'a> BlueskyMe<'a> {
 
     pub async fn stream_notifications(
         &mut self,
-    ) -> Result<NotificationStream<Notification<NotificationRecord>>, StreamError> {
+    ) -> Result<NotificationStream<'_, Notification<NotificationRecord>>, StreamError> {
         self.client.bsky_stream_notifications(None).await
     }
     /// Tell Bsky when the notifications were seen, marking them as old
     pub async fn update_seen(&mut self) -> Result<(), BiskyError> {
-        self.client.bsky_update_seen(Utc::now()).await
+        self.client.bsky_update_seen(Datetime::now()).await
     }
 
     /// Upload a Blob(Image) for use in a Bsky Post later
@@ -88,23 +123,82 @@ impl<'a> BlueskyMe<'a> {
         self.client.repo_upload_blob(blob, mime_type).await
     }
 
+    /// Read posts from a custom feed generator
+    pub async fn get_feed(
+        &mut self,
+        feed_uri: &AtUri,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<FeedViewPost>, Option<String>), BiskyError> {
+        self.client.bsky_get_feed(feed_uri, limit, cursor).await
+    }
+
     pub async fn get_post_thread(&mut self, uri: &str) -> Result<ThreadViewPostEnum, BiskyError> {
         self.client.bsky_get_post_thread(uri).await
     }
+
+    /// Delete one of your posts, along with its threadgate and postgate
+    pub async fn delete_post(&mut self, uri: &AtUri) -> Result<(), BiskyError> {
+        self.client.bsky_delete_post(uri).await
+    }
+
+    /// Like a post
+    pub async fn like(&mut self, uri: &str, cid: &str) -> Result<CreateRecordOutput, BiskyError> {
+        self.client.bsky_like(uri, cid).await
+    }
+
+    /// Remove your like from a post. Returns false if you had not liked it
+    pub async fn unlike(&mut self, post_uri: &str) -> Result<bool, BiskyError> {
+        self.client.bsky_unlike_post(post_uri).await
+    }
+
+    /// Follow an account, unless you already do
+    pub async fn follow(&mut self, did: &str) -> Result<CreateRecordOutput, BiskyError> {
+        self.client.bsky_follow(did).await
+    }
+
+    /// Unfollow an account. Returns false if you did not follow it
+    pub async fn unfollow(&mut self, did: &str) -> Result<bool, BiskyError> {
+        self.client.bsky_unfollow(did).await
+    }
+
+    /// Block an account, unless you already do
+    pub async fn block(&mut self, did: &str) -> Result<CreateRecordOutput, BiskyError> {
+        self.client.bsky_block(did).await
+    }
+
+    /// Unblock an account. Returns false if you did not block it
+    pub async fn unblock(&mut self, did: &str) -> Result<bool, BiskyError> {
+        self.client.bsky_unblock(did).await
+    }
+
+    /// Mute an account. Unlike blocks, mutes are private
+    pub async fn mute(&mut self, actor: &str) -> Result<(), BiskyError> {
+        self.client.bsky_mute_actor(actor).await
+    }
+
+    pub async fn unmute(&mut self, actor: &str) -> Result<(), BiskyError> {
+        self.client.bsky_unmute_actor(actor).await
+    }
+
+    /// Repost a post
+    pub async fn repost(&mut self, uri: &str, cid: &str) -> Result<CreateRecordOutput, BiskyError> {
+        self.client.bsky_repost(uri, cid).await
+    }
+
+    /// Remove your repost of a post. Returns false if you had not reposted it
+    pub async fn unrepost(&mut self, post_uri: &str) -> Result<bool, BiskyError> {
+        self.client.bsky_unrepost_post(post_uri).await
+    }
 }
 pub struct BlueskyUser<'a> {
-    client: &'a mut Client,
+    client: &'a mut BskyClient,
     username: String,
 }
 
 impl BlueskyUser<'_> {
     pub async fn get_profile(&mut self) -> Result<ProfileViewDetailed, BiskyError> {
-        self.client
-            .xrpc_get(
-                "app.bsky.actor.getProfile",
-                Some(&[("actor", &self.username)]),
-            )
-            .await
+        self.client.bsky_get_profile(&self.username).await
     }
     pub async fn get_likes(
         &mut self,
@@ -146,6 +240,18 @@ impl BlueskyUser<'_> {
     //         .await
     // }
 
+    /// The feed generators published by this user
+    pub async fn get_feeds(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<Vec<GeneratorView>, BiskyError> {
+        self.client
+            .bsky_get_actor_feeds(&self.username, limit, cursor)
+            .await
+            .map(|l| l.0)
+    }
+
     pub async fn list_posts(&mut self) -> Result<Vec<Record<Post>>, BiskyError> {
         self.client
             .repo_list_records(
@@ -159,7 +265,7 @@ impl BlueskyUser<'_> {
             .map(|l| l.0)
     }
 
-    pub async fn stream_posts(&mut self) -> Result<RecordStream<Post>, StreamError> {
+    pub async fn stream_posts(&mut self) -> Result<RecordStream<'_, Post>, StreamError> {
         self.client
             .repo_stream_records(&self.username, "app.bsky.feed.post")
             .await
@@ -0,0 +1,226 @@
+//! A bot that answers mentions and replies, polling notifications the same way as
+//! `BskyClient::bsky_notification_stream`.
+
+use crate::bsky::{poll_notifications, BskyClient, NotificationPollState};
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::feed::{PostBuilder, PostView, ReplyRef};
+use crate::lexicon::app::bsky::notification::{Notification, NotificationReason};
+use crate::lexicon::com::atproto::repo::{CreateRecordOutput, StrongRef};
+use crate::storage::Storage;
+use futures::future::BoxFuture;
+use futures::lock::Mutex;
+use futures::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a `MentionBot` handler gets for each mention or reply
+pub struct MentionContext {
+    pub notification: Notification<serde_json::Value>,
+    /// The post that mentioned or replied to the bot
+    pub post: PostView,
+    /// The root of the thread `post` is in, which is `post` itself if it isn't a reply
+    pub root: StrongRef,
+    client: Arc<Mutex<BskyClient>>,
+}
+
+impl MentionContext {
+    /// The bot's client, for anything beyond `reply` and `like`
+    pub fn client(&self) -> Arc<Mutex<BskyClient>> {
+        self.client.clone()
+    }
+
+    /// Reply to the post in the same thread
+    pub async fn reply(&self, text: &str) -> Result<CreateRecordOutput, BiskyError> {
+        let mut builder = PostBuilder::default();
+        builder.text(text).reply(ReplyRef {
+            root: self.root.clone(),
+            parent: (&self.post).try_into()?,
+        });
+        builder.send(&mut *self.client.lock().await).await
+    }
+
+    /// Like the post
+    pub async fn like(&self) -> Result<CreateRecordOutput, BiskyError> {
+        self.client
+            .lock()
+            .await
+            .bsky_like(&self.post.uri, &self.post.cid)
+            .await
+    }
+}
+
+type Handler =
+    Box<dyn Fn(MentionContext) -> BoxFuture<'static, Result<(), BiskyError>> + Send + Sync>;
+
+/// Polls notifications and hands every mention and reply to a handler, one at a time.
+///
+/// Where it left off is kept in the storage given to `storage`, after each notification, so a
+/// restarted bot doesn't handle anything twice. Notifications are marked as seen once handled
+/// successfully. A handler that fails or panics is reported to `on_error` and its notification
+/// is not retried.
+pub struct MentionBot<'a> {
+    client: Arc<Mutex<BskyClient>>,
+    handler: Option<Handler>,
+    on_error: Box<dyn FnMut(BiskyError) + Send + 'a>,
+    storage: Option<&'a dyn Storage<NotificationPollState, Error = BiskyError>>,
+    poll_interval: Duration,
+}
+
+impl<'a> MentionBot<'a> {
+    /// A bot that polls every 30 seconds and ignores errors unless given `on_error`
+    pub fn new(client: impl Into<BskyClient>) -> Self {
+        Self {
+            client: Arc::new(Mutex::new(client.into())),
+            handler: None,
+            on_error: Box::new(|_| {}),
+            storage: None,
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+
+    pub fn on_mention<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(MentionContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BiskyError>> + Send + 'static,
+    {
+        self.handler = Some(Box::new(move |ctx| Box::pin(handler(ctx))));
+        self
+    }
+
+    /// Called with every error, from polling as well as from the handler
+    pub fn on_error(mut self, on_error: impl FnMut(BiskyError) + Send + 'a) -> Self {
+        self.on_error = Box::new(on_error);
+        self
+    }
+
+    pub fn storage(
+        mut self,
+        storage: &'a dyn Storage<NotificationPollState, Error = BiskyError>,
+    ) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// The bot's client, shared with the handlers
+    pub fn client(&self) -> Arc<Mutex<BskyClient>> {
+        self.client.clone()
+    }
+
+    /// Poll and handle notifications. Only returns if no handler was set.
+    pub async fn run(mut self) {
+        let Some(handler) = self.handler.take() else {
+            return;
+        };
+
+        let mut poll = Some(match self.storage {
+            // Nothing stored yet reads as an error, most likely a missing file
+            Some(storage) => storage.get().await.unwrap_or_default(),
+            None => NotificationPollState::default(),
+        });
+        let mut polled = false;
+
+        loop {
+            if polled {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+            polled = true;
+
+            // What has been handled, as opposed to `poll`, which is what has been fetched
+            let mut handled = poll.clone().unwrap_or_default();
+            let new = {
+                let mut client = self.client.lock().await;
+                poll_notifications::<serde_json::Value>(&mut client, &mut poll, self.storage).await
+            };
+            let new = match new {
+                Ok(new) => new,
+                Err(e) => {
+                    (self.on_error)(e);
+                    continue;
+                }
+            };
+
+            let mentions = new
+                .iter()
+                .filter(|n| {
+                    matches!(
+                        n.reason,
+                        NotificationReason::Mention | NotificationReason::Reply
+                    )
+                })
+                .map(|n| n.uri.as_str())
+                .collect::<Vec<_>>();
+            let posts = match self.client.lock().await.bsky_get_posts(&mentions).await {
+                Ok(posts) => posts,
+                Err(e) => {
+                    (self.on_error)(e);
+                    // Fetch the same notifications again next time
+                    poll = Some(handled);
+                    continue;
+                }
+            };
+            let mut posts = posts
+                .into_iter()
+                .map(|post| (post.uri.clone(), post))
+                .collect::<std::collections::HashMap<_, _>>();
+
+            for notification in new {
+                handled.mark(&notification);
+                let is_mention = matches!(
+                    notification.reason,
+                    NotificationReason::Mention | NotificationReason::Reply
+                );
+                // Deleted posts are left out by getPosts
+                if let Some(post) = posts.remove(&notification.uri).filter(|_| is_mention) {
+                    let indexed_at = notification.indexed_at;
+                    let root = match &post.record.reply {
+                        Some(reply) => reply.root.clone(),
+                        None => match StrongRef::try_from(&post) {
+                            Ok(root) => root,
+                            Err(e) => {
+                                (self.on_error)(e);
+                                continue;
+                            }
+                        },
+                    };
+                    let ctx = MentionContext {
+                        notification,
+                        post,
+                        root,
+                        client: self.client.clone(),
+                    };
+
+                    match AssertUnwindSafe(handler(ctx)).catch_unwind().await {
+                        Ok(Ok(())) => {
+                            let seen = self.client.lock().await.bsky_update_seen(indexed_at).await;
+                            if let Err(e) = seen {
+                                (self.on_error)(e);
+                            }
+                        }
+                        Ok(Err(e)) => (self.on_error)(e),
+                        Err(panic) => {
+                            let message = panic
+                                .downcast_ref::<&str>()
+                                .map(|s| s.to_string())
+                                .or_else(|| panic.downcast_ref::<String>().cloned())
+                                .unwrap_or_default();
+                            (self.on_error)(BiskyError::HandlerPanicked(message))
+                        }
+                    }
+                }
+
+                if let Some(storage) = self.storage {
+                    if let Err(e) = storage.set(Some(&handled)).await {
+                        (self.on_error)(e);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,3153 @@
+//! The Bluesky app on top of the protocol: `BskyClient` adds the app.bsky and chat.bsky queries
+//! and procedures, and the helpers built on them, to the com.atproto calls of `Client`
+
+use crate::atproto::{impl_page, ChunkOptions, Client, ClientBuilder, Page, StreamError};
+#[cfg(feature = "cache")]
+use crate::cache::{is_profile_of, BskyCache};
+use crate::errors::{ApiErrorKind, BiskyError};
+use crate::identity::resolve_did;
+use crate::lexicon::app::bsky::actor::{
+    AdultContentPref, GetPreferencesOutput, GetProfilesOutput, GetSuggestionsOutput,
+    HiddenPostsPref, MutedWord, MutedWordTarget, MutedWordsPref, PreferenceKind, Preferences,
+    Profile, ProfileUpdate, ProfileView, ProfileViewBasic, ProfileViewDetailed, PutPreferences,
+    SearchActorsOutput, SearchActorsTypeaheadOutput, ThreadSort, ThreadViewPref, Visibility,
+    MAX_GET_PROFILES,
+};
+use crate::lexicon::app::bsky::embed::Image;
+use crate::lexicon::app::bsky::feed::{
+    split_thread_text, AltTextPolicy, Attachment, AuthorFeed, BlockedPost, Embeds, FeedViewPost,
+    FullThread, GeneratorView, GetActorFeedsOutput, GetActorLikesOutput, GetFeedGeneratorOutput,
+    GetFeedGeneratorsOutput, GetFeedOutput, GetLikesLike, GetLikesOutput, GetListFeedOutput,
+    GetPostThreadOutput, GetPostsOutput, GetQuotesOutput, GetRepostedByOutput,
+    GetSuggestedFeedsOutput, GetTimelineOutput, ImagesEmbed, Interaction, LazyFeedOutput,
+    LazyFeedViewPost, Like, NotFoundPost, Post, PostBuilder, PostEngagement, PostView, Postgate,
+    PostgateEmbeddingRule, ReplyRef, Repost, SendInteractions, ThreadOptions, ThreadViewPost,
+    ThreadViewPostEnum, Threadgate, ThreadgateRule, UnrolledThread, MAX_GET_FEED_GENERATORS,
+    MAX_GET_POSTS,
+};
+use crate::lexicon::app::bsky::graph::{
+    Block, CreatedStarterPack, Follow, FollowGraph, GetBlocksOutput, GetFollowersOutput,
+    GetFollowsOutput, GetKnownFollowersOutput, GetListOutput, GetListsOutput, GetMutesOutput,
+    GetRelationshipsOutput, GetStarterPackOutput, GetStarterPacksOutput,
+    GetSuggestedFollowsByActorOutput, List, ListBlock, ListItem, ListPurpose, ListView, MuteActor,
+    MuteActorList, MuteThread, RelationshipEnum, StarterPack, StarterPackFeed, StarterPackView,
+    StarterPackViewBasic, StarterPacksPageOutput, MAX_GET_RELATIONSHIPS, MAX_GET_STARTER_PACKS,
+};
+use crate::lexicon::app::bsky::labeler::{GetServicesOutput, LabelerViewEnum};
+use crate::lexicon::app::bsky::notification::{
+    ListNotificationsOutput, Notification, NotificationCount, NotificationReason, PushPlatform,
+    PutNotificationPreferences, RegisterPush, UpdateSeen,
+};
+#[cfg(feature = "unspecced")]
+use crate::lexicon::app::bsky::unspecced::{
+    GetConfigOutput, GetPopularFeedGeneratorsOutput, GetTaggedSuggestionsOutput,
+    GetTrendingTopicsOutput, TaggedSuggestion, TrendingTopic,
+};
+use crate::lexicon::com::atproto::moderation::{
+    CreateReportOutput, ReasonType, RepoRef, ReportSubject,
+};
+use crate::lexicon::com::atproto::repo::{BlobOutput, CreateRecordOutput, Record, StrongRef};
+use crate::storage::Storage;
+use crate::types::{base32_sortable, AtUri, Datetime};
+use futures::Stream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "cache")]
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How deep `bsky_unroll_thread` asks getPostThread to go, the most the AppView allows
+const UNROLL_THREAD_DEPTH: usize = 1000;
+
+/// How many of the author's records `bsky_unroll_thread` looks through past the end of the
+/// chain for a post that carries it on from a deleted one
+const UNROLL_GAP_SCAN: usize = 100;
+
+/// A `Client` that also speaks the Bluesky app's lexicons. It derefs to the `Client` it wraps,
+/// so the com.atproto calls, the session and the builder options are all still there.
+#[derive(Clone)]
+pub struct BskyClient {
+    client: Client,
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<BskyCache>>,
+    alt_text_policy: AltTextPolicy,
+}
+
+impl BskyClient {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            #[cfg(feature = "cache")]
+            cache: None,
+            alt_text_policy: AltTextPolicy::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> Client {
+        self.client
+    }
+
+    /// Answer `bsky_get_profile`, `bsky_get_profiles` and `bsky_get_posts` from `cache` where
+    /// it can, sharing it with the other clients it is set on
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, cache: Arc<BskyCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Check every post this client creates against `policy` before creating it
+    pub fn with_alt_text_policy(mut self, policy: AltTextPolicy) -> Self {
+        self.alt_text_policy = policy;
+        self
+    }
+
+    pub fn alt_text_policy(&self) -> &AltTextPolicy {
+        &self.alt_text_policy
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn cache(&self) -> Option<&Arc<BskyCache>> {
+        self.cache.as_ref()
+    }
+
+    /// Drop the cached profile of `actor` after a write that changed it
+    fn invalidate_actor(&self, _actor: &str) {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            cache.invalidate_actor(_actor);
+        }
+    }
+
+    /// Drop the cached post at `uri` after a write that changed it
+    fn invalidate_post(&self, _uri: &str) {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            cache.invalidate_post(_uri);
+        }
+    }
+}
+
+impl From<Client> for BskyClient {
+    fn from(client: Client) -> Self {
+        Self::new(client)
+    }
+}
+
+impl Deref for BskyClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl DerefMut for BskyClient {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+
+pub struct NotificationStream<'a, D: DeserializeOwned> {
+    client: &'a mut BskyClient,
+    limit: usize,
+    seen_at: Option<&'a str>,
+    // collection: &'a str,
+    queue: VecDeque<Notification<D>>,
+    cursor: String,
+}
+
+impl<'a, D: DeserializeOwned + std::fmt::Debug> NotificationStream<'a, D> {
+    pub async fn next(&mut self) -> Result<Notification<D>, StreamError> {
+        if let Some(notification) = self.queue.pop_front() {
+            Ok(notification)
+        } else {
+            loop {
+                let (notifications, cursor) = self
+                    .client
+                    .bsky_list_notifications(self.limit, self.seen_at, Some(self.cursor.as_ref()))
+                    .await?;
+
+                let mut notifications = VecDeque::from(notifications);
+                if let Some(first_notification) = notifications.pop_front() {
+                    if let Some(cursor) = cursor {
+                        self.cursor = cursor;
+                    } else {
+                        return Err(StreamError::NoCursor);
+                    }
+
+                    self.queue.append(&mut notifications);
+                    return Ok(first_notification);
+                } else {
+                    tokio::time::sleep(Duration::from_secs(15)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Where `BskyClient::bsky_notification_stream` left off, so a restarted bot doesn't answer the
+/// same notifications twice
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct NotificationPollState {
+    /// When the newest notification yielded so far was indexed
+    pub seen_at: Option<Datetime>,
+    /// The `uri reason` keys of the notifications indexed exactly at `seen_at`
+    pub seen: Vec<String>,
+}
+
+impl NotificationPollState {
+    fn key<D>(notification: &Notification<D>) -> String {
+        format!("{} {}", notification.uri, notification.reason)
+    }
+
+    fn is_new<D>(&self, notification: &Notification<D>) -> bool {
+        match self.seen_at {
+            Some(seen_at) if notification.indexed_at == seen_at => {
+                !self.seen.contains(&Self::key(notification))
+            }
+            Some(seen_at) => notification.indexed_at > seen_at,
+            None => true,
+        }
+    }
+
+    pub(crate) fn mark<D>(&mut self, notification: &Notification<D>) {
+        if self.seen_at != Some(notification.indexed_at) {
+            self.seen_at = Some(notification.indexed_at);
+            self.seen.clear();
+        }
+        self.seen.push(Self::key(notification));
+    }
+}
+
+/// Follow the cursor of a feed query, yielding its items one at a time until the feed runs out
+/// or reaches items older than `stop_at`
+fn feed_stream<'a, P: Page<Item = FeedViewPost> + 'a>(
+    client: &'a mut Client,
+    path: &'static str,
+    query: Vec<(&'static str, String)>,
+    stop_at: Option<Datetime>,
+) -> impl Stream<Item = Result<FeedViewPost, BiskyError>> + 'a {
+    page_stream::<P>(client, path, query, move |item| {
+        stop_at.is_some_and(|stop_at| item.sort_at() < stop_at)
+    })
+}
+
+/// The reply to a post of an unrolled thread that carries it on: the earliest one by `author`
+fn next_in_chain(replies: Vec<ThreadViewPostEnum>, author: &str) -> Option<ThreadViewPost> {
+    replies
+        .into_iter()
+        .filter_map(|reply| match reply {
+            ThreadViewPostEnum::ThreadViewPost(node) if node.post.author.did == author => {
+                Some(*node)
+            }
+            _ => None,
+        })
+        .min_by_key(|node| node.post.record.created_at)
+}
+
+/// What `bsky_watch_post` has already yielded: the DIDs that liked and reposted the post, and
+/// the uris of its replies and quotes
+#[derive(Default)]
+struct WatchedPost {
+    likes: HashSet<String>,
+    reposts: HashSet<String>,
+    replies: HashSet<String>,
+    quotes: HashSet<String>,
+}
+
+/// What is new on the post at `uri` since `seen`, which is only updated once everything has
+/// been read. `None` if the post was deleted.
+async fn poll_post(
+    client: &mut BskyClient,
+    uri: &str,
+    seen: &mut WatchedPost,
+) -> Result<Option<Vec<PostEngagement>>, BiskyError> {
+    let node = match client
+        .bsky_get_post_thread_depth(uri, Some(1), Some(0))
+        .await
+    {
+        Ok(ThreadViewPostEnum::ThreadViewPost(node)) => node,
+        Ok(ThreadViewPostEnum::NotFoundPost(_)) => return Ok(None),
+        Err(BiskyError::ApiError(e)) if e.kind() == ApiErrorKind::NotFound => return Ok(None),
+        Ok(_) => {
+            return Err(BiskyError::UnexpectedResponse(format!(
+                "{uri} can't be seen"
+            )))
+        }
+        Err(e) => return Err(e),
+    };
+    let mut replies = node
+        .replies
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|reply| match reply {
+            ThreadViewPostEnum::ThreadViewPost(reply)
+                if !seen.replies.contains(&reply.post.uri) =>
+            {
+                Some(reply.post)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    replies.sort_by_key(|reply| reply.record.created_at);
+
+    let likes = unseen_pages::<GetLikesOutput>(client, "app.bsky.feed.getLikes", uri, |like| {
+        (!seen.likes.contains(&like.actor.did)).then(|| like.actor.did.clone())
+    })
+    .await?;
+    let reposts =
+        unseen_pages::<GetRepostedByOutput>(client, "app.bsky.feed.getRepostedBy", uri, |actor| {
+            (!seen.reposts.contains(&actor.did)).then(|| actor.did.clone())
+        })
+        .await?;
+    let quotes = unseen_pages::<GetQuotesOutput>(client, "app.bsky.feed.getQuotes", uri, |post| {
+        (!seen.quotes.contains(&post.uri)).then(|| post.uri.clone())
+    })
+    .await?;
+
+    let mut new = Vec::new();
+    for reply in replies {
+        seen.replies.insert(reply.uri.clone());
+        new.push(PostEngagement::NewReply(reply));
+    }
+    for (did, like) in likes {
+        seen.likes.insert(did);
+        new.push(PostEngagement::NewLike(like.actor));
+    }
+    for (did, actor) in reposts {
+        seen.reposts.insert(did);
+        new.push(PostEngagement::NewRepost(actor));
+    }
+    for (uri, quote) in quotes {
+        seen.quotes.insert(uri);
+        new.push(PostEngagement::NewQuote(quote));
+    }
+    Ok(Some(new))
+}
+
+/// The items of a newest-first paginated query about `uri` that `unseen` gives a key for, read
+/// until a page has one it doesn't, oldest first
+async fn unseen_pages<P: Page>(
+    client: &mut Client,
+    path: &str,
+    uri: &str,
+    unseen: impl Fn(&P::Item) -> Option<String>,
+) -> Result<Vec<(String, P::Item)>, BiskyError> {
+    let mut new = Vec::new();
+    let mut keys = HashSet::new();
+    let mut cursor = None;
+    loop {
+        let mut query = Vec::from([("uri", uri), ("limit", "100")]);
+        if let Some(cursor) = cursor.as_deref() {
+            query.push(("cursor", cursor));
+        }
+        let (page, next_cursor) = client
+            .xrpc_get_backoff::<P>(path, Some(&query))
+            .await?
+            .into_parts();
+        let done = page.is_empty() || next_cursor.is_none();
+        let mut reached_seen = false;
+        for item in page {
+            match unseen(&item) {
+                // A page can repeat an item of the one before if the list changed between them
+                Some(key) if keys.insert(key.clone()) => new.push((key, item)),
+                Some(_) => {}
+                None => reached_seen = true,
+            }
+        }
+        if done || reached_seen {
+            break;
+        }
+        cursor = next_cursor;
+    }
+    new.reverse();
+    Ok(new)
+}
+
+/// Move the posts of a getPostThread response into `thread`, queueing the posts whose replies
+/// were cut off
+fn collect_thread(
+    node: ThreadViewPostEnum,
+    thread: &mut FullThread,
+    seen: &mut HashSet<String>,
+    pending: &mut VecDeque<String>,
+    max_posts: usize,
+) {
+    match node {
+        ThreadViewPostEnum::ThreadViewPost(node) => {
+            let ThreadViewPost { post, replies, .. } = *node;
+            if !seen.insert(post.uri.clone()) {
+                return;
+            }
+            if thread.posts.len() >= max_posts {
+                thread.truncated = true;
+                return;
+            }
+            match replies {
+                Some(replies) => {
+                    for reply in replies {
+                        collect_thread(reply, thread, seen, pending, max_posts);
+                    }
+                }
+                None if post.reply_count.unwrap_or_default() > 0 => {
+                    pending.push_back(post.uri.clone())
+                }
+                None => {}
+            }
+            thread.posts.push(post);
+        }
+        ThreadViewPostEnum::NotFoundPost(NotFoundPost { uri, .. })
+        | ThreadViewPostEnum::BlockedPost(BlockedPost { uri, .. }) => {
+            if seen.insert(uri.clone()) {
+                thread.missing.push(uri);
+            }
+        }
+        ThreadViewPostEnum::Unknown(_) => {}
+    }
+}
+
+/// Map the error codes of the feed queries to their own variants
+fn map_feed_error(error: BiskyError) -> BiskyError {
+    match error {
+        BiskyError::ApiError(e) => match e.error.as_str() {
+            "UnknownFeed" => BiskyError::UnknownFeed(e.message),
+            "UnknownList" => BiskyError::UnknownList(e.message),
+            "BlockedActor" => BiskyError::BlockedActor(e.message),
+            "BlockedByActor" => BiskyError::BlockedByActor(e.message),
+            _ => BiskyError::ApiError(e),
+        },
+        e => e,
+    }
+}
+
+/// A TID for `key`, from a 64-bit FNV-1a hash of it, which unlike std's hasher is the same in
+/// every build. The timestamp is kept within 2^40 microseconds, about 13 days, after the start
+/// of 2023, since a TID far in the future could be taken for a bad clock, leaving 50 bits of the
+/// hash.
+fn idempotent_rkey(key: &str) -> String {
+    const START_OF_2023: u64 = 1_672_531_200_000_000;
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    let micros = START_OF_2023 + ((hash >> 10) & ((1 << 40) - 1));
+    base32_sortable(micros << 10 | (hash & 1023), 13)
+}
+
+/// Only the error code is trusted. A PDS that reports the conflict with another code fails the
+/// call rather than having its message guessed at.
+fn is_already_exists(error: &BiskyError) -> bool {
+    matches!(error, BiskyError::ApiError(e) if e.kind() == ApiErrorKind::RecordAlreadyExists)
+}
+
+impl_page!(GetTimelineOutput, feed, FeedViewPost);
+impl_page!(AuthorFeed, feed, FeedViewPost);
+impl_page!(GetFeedOutput, feed, FeedViewPost);
+impl_page!(GetActorLikesOutput, feed, FeedViewPost);
+impl_page!(GetListFeedOutput, feed, FeedViewPost);
+impl_page!(LazyFeedOutput, feed, LazyFeedViewPost);
+impl_page!(GetActorFeedsOutput, feeds, GeneratorView);
+impl_page!(GetSuggestedFeedsOutput, feeds, GeneratorView);
+impl_page!(SearchActorsOutput, actors, ProfileView);
+impl_page!(GetSuggestionsOutput, actors, ProfileView);
+impl_page!(GetLikesOutput, likes, GetLikesLike);
+impl_page!(GetRepostedByOutput, reposted_by, ProfileView);
+impl_page!(GetQuotesOutput, posts, PostView);
+impl_page!(GetFollowsOutput, follows, ProfileView);
+impl_page!(GetFollowersOutput, followers, ProfileView);
+impl_page!(GetKnownFollowersOutput, followers, ProfileView);
+impl_page!(GetBlocksOutput, blocks, ProfileView);
+impl_page!(GetMutesOutput, mutes, ProfileView);
+impl_page!(GetListsOutput, lists, ListView);
+impl_page!(StarterPacksPageOutput, starter_packs, StarterPackViewBasic);
+#[cfg(feature = "unspecced")]
+impl_page!(GetPopularFeedGeneratorsOutput, feeds, GeneratorView);
+
+impl<D: DeserializeOwned + std::fmt::Debug> Page for ListNotificationsOutput<D> {
+    type Item = Notification<D>;
+
+    fn into_parts(self) -> (Vec<Self::Item>, Option<String>) {
+        (self.notifications, self.cursor)
+    }
+}
+
+/// Follow the cursor of a paginated query, yielding its items one at a time until it runs out
+/// or `stop` returns true for an item
+fn page_stream<'a, P: Page + 'a>(
+    client: &'a mut Client,
+    path: &'static str,
+    query: Vec<(&'static str, String)>,
+    stop: impl Fn(&P::Item) -> bool + 'a,
+) -> impl Stream<Item = Result<P::Item, BiskyError>> + 'a
+where
+    P::Item: 'a,
+{
+    struct State<'a, T, F> {
+        client: &'a mut Client,
+        stop: F,
+        query: Vec<(&'static str, String)>,
+        queue: VecDeque<T>,
+        cursor: Option<String>,
+        done: bool,
+    }
+
+    let state = State {
+        client,
+        stop,
+        query,
+        queue: VecDeque::new(),
+        cursor: None,
+        done: false,
+    };
+
+    futures::stream::unfold(state, move |mut state| async move {
+        loop {
+            if let Some(item) = state.queue.pop_front() {
+                if (state.stop)(&item) {
+                    return None;
+                }
+                return Some((Ok(item), state));
+            }
+            if state.done {
+                return None;
+            }
+
+            let mut query = state
+                .query
+                .iter()
+                .map(|(k, v)| (*k, v.as_str()))
+                .collect::<Vec<_>>();
+            query.push(("limit", "100"));
+            if let Some(cursor) = state.cursor.as_ref() {
+                query.push(("cursor", cursor));
+            }
+
+            // The cursor only advances once a page has been fetched, so a refresh-and-retry
+            // inside xrpc_get can never skip or repeat a page.
+            match state.client.xrpc_get_backoff::<P>(path, Some(&query)).await {
+                Ok(page) => {
+                    let (items, cursor) = page.into_parts();
+                    state.done = items.is_empty() || cursor.is_none();
+                    state.cursor = cursor;
+                    state.queue.extend(items);
+                }
+                Err(e) => {
+                    state.done = true;
+                    return Some((Err(map_feed_error(e)), state));
+                }
+            }
+        }
+    })
+}
+
+/// Fetch the notifications newer than `poll`, oldest first, and move `poll` past them. `poll` is
+/// loaded from `storage`, or from the server's seenAt, the first time round.
+pub(crate) async fn poll_notifications<D: DeserializeOwned + std::fmt::Debug>(
+    client: &mut BskyClient,
+    poll: &mut Option<NotificationPollState>,
+    storage: Option<&dyn Storage<NotificationPollState, Error = BiskyError>>,
+) -> Result<Vec<Notification<D>>, BiskyError> {
+    let mut state = match poll.take() {
+        Some(state) => state,
+        None => match storage {
+            // Nothing stored yet reads as an error, most likely a missing file
+            Some(storage) => storage.get().await.unwrap_or_default(),
+            None => NotificationPollState::default(),
+        },
+    };
+
+    let mut new = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let page = match client
+            .bsky_list_notifications_page::<D>(100, cursor.as_deref(), None, &[])
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                // Leave `poll` where it was, so the next poll fetches these pages again
+                *poll = Some(state);
+                return Err(e);
+            }
+        };
+        if state.seen_at.is_none() && state.seen.is_empty() {
+            state.seen_at = page.seen_at;
+        }
+
+        let count = page.notifications.len();
+        let before = new.len();
+        new.extend(page.notifications.into_iter().filter(|n| state.is_new(n)));
+        // Without anything seen, only start from the latest page rather than all of history
+        if new.len() - before < count || state.seen_at.is_none() || page.cursor.is_none() {
+            break;
+        }
+        cursor = page.cursor;
+    }
+
+    new.sort_by_key(|n| n.indexed_at);
+    for notification in &new {
+        state.mark(notification);
+    }
+    *poll = Some(state);
+    Ok(new)
+}
+
+impl BskyClient {
+    /// Appeal a label `labeler_did` put on the logged in user's account or one of their records.
+    /// The appeal goes to that labeler, since only it can take the label back.
+    pub async fn bsky_appeal_label(
+        &mut self,
+        subject: ReportSubject,
+        labeler_did: &str,
+        note: Option<&str>,
+    ) -> Result<CreateReportOutput, BiskyError> {
+        self.moderation_create_report_to(labeler_did, subject, ReasonType::Appeal, note)
+            .await
+    }
+
+    /// Report a post, with an optional note for the moderators
+    pub async fn bsky_report_post(
+        &mut self,
+        uri: &str,
+        cid: &str,
+        reason_type: ReasonType,
+        note: Option<&str>,
+    ) -> Result<CreateReportOutput, BiskyError> {
+        let subject = ReportSubject::Record(StrongRef::new(uri, cid)?);
+        self.moderation_create_report(subject, reason_type, note)
+            .await
+    }
+
+    /// Report an account, with an optional note for the moderators
+    pub async fn bsky_report_account(
+        &mut self,
+        did: &str,
+        reason_type: ReasonType,
+        note: Option<&str>,
+    ) -> Result<CreateReportOutput, BiskyError> {
+        let subject = ReportSubject::Repo(RepoRef {
+            did: did.to_string(),
+        });
+        self.moderation_create_report(subject, reason_type, note)
+            .await
+    }
+
+    ///app.bsky.labeler.getServices
+    /// The labeler services run by `dids`. With `detailed`, each view carries the labeler's
+    /// policies, including the definitions needed to interpret its labels. Works without a
+    /// session
+    pub async fn bsky_get_labeler_services(
+        &mut self,
+        dids: &[&str],
+        detailed: bool,
+    ) -> Result<Vec<LabelerViewEnum>, BiskyError> {
+        let detailed = detailed.to_string();
+        let mut query = dids.iter().map(|did| ("dids", *did)).collect::<Vec<_>>();
+        query.push(("detailed", &detailed));
+
+        let response = self
+            .xrpc_get_public::<GetServicesOutput>("app.bsky.labeler.getServices", Some(&query))
+            .await?;
+        Ok(response.views)
+    }
+
+    /// Parse a bsky.app link into an at:// uri, resolving a handle in the link to its DID
+    pub async fn resolve_bsky_url(&mut self, url: &str) -> Result<AtUri, BiskyError> {
+        let mut uri = AtUri::from_bsky_url(url)?;
+        if !uri.has_did_authority() {
+            uri.authority = self.identity_resolve_handle(&uri.authority).await?;
+        }
+        Ok(uri)
+    }
+
+    ///app.bsky.notification.getUnreadCount
+    /// Get the user's unread notification count. `seen_at` counts from that date instead of
+    /// from when the notifications were last marked as seen.
+    pub async fn bsky_get_notification_count(
+        &mut self,
+        seen_at: Option<&str>,
+    ) -> Result<NotificationCount, BiskyError> {
+        let mut query = Vec::new();
+
+        if let Some(seen_at) = seen_at {
+            query.push(("seenAt", seen_at));
+        }
+        let res = self
+            .xrpc_get::<NotificationCount>("app.bsky.notification.getUnreadCount", Some(&query))
+            .await?;
+        Ok(res)
+    }
+
+    pub async fn bsky_list_notifications<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        limit: usize,
+        seen_at: Option<&str>,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<Notification<D>>, Option<String>), BiskyError> {
+        let mut query = Vec::new();
+        if let Some(seen_at) = seen_at {
+            query.push(("seenAt", seen_at));
+        }
+        self.xrpc_get_pages::<ListNotificationsOutput<D>>(
+            "app.bsky.notification.listNotifications",
+            &query,
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.notification.listNotifications
+    /// A single page, along with when the notifications were last seen. An empty `reasons`
+    /// returns every kind of notification.
+    pub async fn bsky_list_notifications_page<D: DeserializeOwned + std::fmt::Debug>(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+        seen_at: Option<&str>,
+        reasons: &[NotificationReason],
+    ) -> Result<ListNotificationsOutput<D>, BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let reasons = reasons.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let mut query = Vec::from([("limit", limit.as_str())]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+        if let Some(seen_at) = seen_at {
+            query.push(("seenAt", seen_at));
+        }
+        query.extend(reasons.iter().map(|reason| ("reasons", reason.as_str())));
+
+        self.xrpc_get("app.bsky.notification.listNotifications", Some(&query))
+            .await
+    }
+
+    ///app.bsky.notification.updateSeen
+    pub async fn bsky_update_seen(&mut self, seen_at: Datetime) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response("app.bsky.notification.updateSeen", &UpdateSeen { seen_at })
+            .await
+    }
+
+    ///app.bsky.notification.putPreferences
+    /// Turn priority notifications, which only come from followed accounts, on or off
+    pub async fn bsky_put_notification_priority(
+        &mut self,
+        priority: bool,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response(
+            "app.bsky.notification.putPreferences",
+            &PutNotificationPreferences { priority },
+        )
+        .await
+    }
+
+    /// Whether priority notifications are on. The setting is only reported alongside
+    /// listNotifications, so this fetches a single notification; `None` if the server left it
+    /// out.
+    pub async fn bsky_get_notification_priority(&mut self) -> Result<Option<bool>, BiskyError> {
+        Ok(self
+            .bsky_list_notifications_page::<serde_json::Value>(1, None, None, &[])
+            .await?
+            .priority)
+    }
+
+    ///app.bsky.notification.registerPush
+    /// Register an APNs or FCM `token` with the push service `service_did`, e.g.
+    /// `did:web:api.bsky.app`. The PDS forwards the call to that service itself, so this needs
+    /// no proxying.
+    pub async fn bsky_register_push(
+        &mut self,
+        service_did: &str,
+        token: &str,
+        platform: PushPlatform,
+        app_id: &str,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response(
+            "app.bsky.notification.registerPush",
+            &RegisterPush {
+                service_did,
+                token,
+                platform,
+                app_id,
+            },
+        )
+        .await
+    }
+
+    pub async fn bsky_stream_notifications<'a, D: DeserializeOwned + std::fmt::Debug>(
+        &'a mut self,
+        seen_at: Option<&'a str>,
+    ) -> Result<NotificationStream<'a, D>, StreamError> {
+        let (_, cursor) = self
+            .bsky_list_notifications::<D>(usize::MAX, seen_at, None)
+            .await?;
+
+        if let Some(cursor) = cursor {
+            Ok(NotificationStream {
+                client: self,
+                queue: VecDeque::new(),
+                cursor,
+                limit: usize::MAX,
+                seen_at,
+            })
+        } else {
+            Err(StreamError::NoCursor)
+        }
+    }
+    /// Poll listNotifications every `poll_interval`, yielding each new notification once,
+    /// oldest first. Where the stream left off is kept in `storage`, if given; without anything
+    /// stored it starts after the notifications last marked as seen. With `mark_seen`, updateSeen
+    /// is called once a batch has been yielded.
+    ///
+    /// Errors are yielded and the stream carries on polling, so it only ends when dropped.
+    pub fn bsky_notification_stream<'a, D: DeserializeOwned + std::fmt::Debug + 'a>(
+        &'a mut self,
+        poll_interval: Duration,
+        mark_seen: bool,
+        storage: Option<&'a dyn Storage<NotificationPollState, Error = BiskyError>>,
+    ) -> impl Stream<Item = Result<Notification<D>, BiskyError>> + 'a {
+        struct State<'a, T> {
+            client: &'a mut BskyClient,
+            storage: Option<&'a dyn Storage<NotificationPollState, Error = BiskyError>>,
+            poll: Option<NotificationPollState>,
+            queue: VecDeque<Notification<T>>,
+            /// Whether a batch has been yielded but not yet stored and marked as seen
+            unsaved: bool,
+            polled: bool,
+        }
+
+        let state = State {
+            client: self,
+            storage,
+            poll: None,
+            queue: VecDeque::new(),
+            unsaved: false,
+            polled: false,
+        };
+
+        futures::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(notification) = state.queue.pop_front() {
+                    return Some((Ok(notification), state));
+                }
+
+                if state.unsaved {
+                    state.unsaved = false;
+                    let poll = state.poll.clone().unwrap_or_default();
+                    if let Some(storage) = state.storage {
+                        if let Err(e) = storage.set(Some(&poll)).await {
+                            return Some((Err(e), state));
+                        }
+                    }
+                    if let (true, Some(seen_at)) = (mark_seen, poll.seen_at) {
+                        if let Err(e) = state.client.bsky_update_seen(seen_at).await {
+                            return Some((Err(e), state));
+                        }
+                    }
+                }
+
+                if state.polled {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                state.polled = true;
+
+                match poll_notifications(state.client, &mut state.poll, state.storage).await {
+                    Ok(new) => {
+                        state.unsaved = !new.is_empty();
+                        state.queue.extend(new);
+                    }
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    ///app.bsky.feed.getLikes
+    pub async fn bsky_get_likes(
+        &mut self,
+        uri: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<GetLikesLike>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetLikesOutput>(
+            "app.bsky.feed.getLikes",
+            &[("uri", uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.feed.getRepostedBy
+    pub async fn bsky_get_reposted_by(
+        &mut self,
+        uri: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetRepostedByOutput>(
+            "app.bsky.feed.getRepostedBy",
+            &[("uri", uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.feed.getQuotes
+    pub async fn bsky_get_quotes(
+        &mut self,
+        uri: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<PostView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetQuotesOutput>(
+            "app.bsky.feed.getQuotes",
+            &[("uri", uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    /// Poll the post at `uri` every `poll_interval`, yielding each like, repost, direct reply
+    /// and quote once, oldest first within a poll. What the post already has is yielded by the
+    /// first poll. Likes, reposts and quotes are read newest first until a page reaches ones
+    /// already seen, so a later poll costs a request for each unless a lot is happening.
+    ///
+    /// Errors are yielded and the stream carries on polling, without losing what the failed poll
+    /// had found. Once the post is deleted, `PostDeleted` is yielded and the stream ends.
+    pub fn bsky_watch_post<'a>(
+        &'a mut self,
+        uri: &'a str,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<PostEngagement, BiskyError>> + 'a {
+        struct State<'a> {
+            client: &'a mut BskyClient,
+            seen: WatchedPost,
+            queue: VecDeque<PostEngagement>,
+            polled: bool,
+            deleted: bool,
+        }
+
+        let state = State {
+            client: self,
+            seen: WatchedPost::default(),
+            queue: VecDeque::new(),
+            polled: false,
+            deleted: false,
+        };
+
+        futures::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(event) = state.queue.pop_front() {
+                    return Some((Ok(event), state));
+                }
+                if state.deleted {
+                    return None;
+                }
+
+                if state.polled {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                state.polled = true;
+
+                match poll_post(state.client, uri, &mut state.seen).await {
+                    Ok(Some(new)) => state.queue.extend(new),
+                    Ok(None) => {
+                        state.deleted = true;
+                        return Some((Ok(PostEngagement::PostDeleted), state));
+                    }
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    ///app.bsky.graph.getFollows
+    pub async fn bsky_get_follows(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetFollowsOutput>(
+            "app.bsky.graph.getFollows",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.getFollows
+    /// A single page, along with the profile of `actor`
+    pub async fn bsky_get_follows_page(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<GetFollowsOutput, BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = Vec::from([("actor", actor), ("limit", &limit)]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        self.xrpc_get("app.bsky.graph.getFollows", Some(&query))
+            .await
+    }
+
+    /// app.bsky.graph.getFollows as a stream over every followed account, backing off when
+    /// rate limited
+    pub fn bsky_get_follows_stream(
+        &mut self,
+        actor: &str,
+    ) -> impl Stream<Item = Result<ProfileView, BiskyError>> + '_ {
+        page_stream::<GetFollowsOutput>(
+            self,
+            "app.bsky.graph.getFollows",
+            vec![("actor", actor.to_string())],
+            |_| false,
+        )
+    }
+
+    ///app.bsky.graph.getFollowers
+    pub async fn bsky_get_followers(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetFollowersOutput>(
+            "app.bsky.graph.getFollowers",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.getFollowers
+    /// A single page, along with the profile of `actor`
+    pub async fn bsky_get_followers_page(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<GetFollowersOutput, BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = Vec::from([("actor", actor), ("limit", &limit)]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        self.xrpc_get("app.bsky.graph.getFollowers", Some(&query))
+            .await
+    }
+
+    /// app.bsky.graph.getFollowers as a stream over every follower, backing off when rate
+    /// limited
+    pub fn bsky_get_followers_stream(
+        &mut self,
+        actor: &str,
+    ) -> impl Stream<Item = Result<ProfileView, BiskyError>> + '_ {
+        page_stream::<GetFollowersOutput>(
+            self,
+            "app.bsky.graph.getFollowers",
+            vec![("actor", actor.to_string())],
+            |_| false,
+        )
+    }
+
+    ///app.bsky.graph.getList
+    /// The list and up to `limit` of its members, following the cursor as often as needed
+    pub async fn bsky_get_list(
+        &mut self,
+        list_uri: &str,
+        mut limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<GetListOutput, BiskyError> {
+        let mut cursor = cursor.map(str::to_string);
+        let mut output: Option<GetListOutput> = None;
+
+        loop {
+            let query_limit = std::cmp::min(limit, 100).to_string();
+            let mut query = Vec::from([("list", list_uri), ("limit", &query_limit)]);
+            if let Some(cursor) = cursor.as_ref() {
+                query.push(("cursor", cursor));
+            }
+
+            let mut page = self
+                .xrpc_get_backoff::<GetListOutput>("app.bsky.graph.getList", Some(&query))
+                .await?;
+            limit = limit.saturating_sub(page.items.len());
+            let done = page.items.is_empty() || page.cursor.is_none() || limit == 0;
+            cursor = page.cursor.take();
+
+            match output.as_mut() {
+                Some(output) => output.items.append(&mut page.items),
+                None => output = Some(page),
+            }
+            if done {
+                break;
+            }
+        }
+
+        // The loop runs at least once
+        let mut output = output.unwrap();
+        output.cursor = cursor;
+        Ok(output)
+    }
+
+    ///app.bsky.graph.getLists
+    /// The lists created by `actor`
+    pub async fn bsky_get_lists(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ListView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetListsOutput>(
+            "app.bsky.graph.getLists",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.getListMutes
+    /// The lists the logged in user has muted
+    pub async fn bsky_get_list_mutes(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ListView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetListsOutput>(
+            "app.bsky.graph.getListMutes",
+            &[],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.getListBlocks
+    /// The lists the logged in user has blocked
+    pub async fn bsky_get_list_blocks(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ListView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetListsOutput>(
+            "app.bsky.graph.getListBlocks",
+            &[],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.getSuggestedFollowsByActor
+    /// Accounts to follow after following `actor`
+    pub async fn bsky_get_suggested_follows_by_actor(
+        &mut self,
+        actor: &str,
+    ) -> Result<GetSuggestedFollowsByActorOutput, BiskyError> {
+        self.xrpc_get(
+            "app.bsky.graph.getSuggestedFollowsByActor",
+            Some(&[("actor", actor)]),
+        )
+        .await
+    }
+
+    /// Walk the follow graph breadth first from `seed_dids`, `depth` follows deep, until
+    /// `max_accounts` accounts have been reached. Waits `delay` between requests on top of
+    /// the rate limit backoff.
+    pub async fn bsky_expand_follow_graph(
+        &mut self,
+        seed_dids: &[&str],
+        depth: usize,
+        max_accounts: usize,
+        delay: Duration,
+    ) -> Result<FollowGraph, BiskyError> {
+        let mut graph = FollowGraph::default();
+        let mut visited = seed_dids
+            .iter()
+            .map(|did| did.to_string())
+            .collect::<HashSet<_>>();
+        let mut frontier = visited.iter().cloned().collect::<Vec<_>>();
+
+        'walk: for _ in 0..depth {
+            let mut next = Vec::new();
+            for did in frontier {
+                let mut cursor: Option<String> = None;
+                loop {
+                    let (follows, next_cursor) = self
+                        .xrpc_get_pages::<GetFollowsOutput>(
+                            "app.bsky.graph.getFollows",
+                            &[("actor", &did)],
+                            100,
+                            100,
+                            cursor.as_deref(),
+                        )
+                        .await?;
+                    tokio::time::sleep(delay).await;
+
+                    for follow in follows {
+                        if !graph.accounts.contains_key(&follow.did)
+                            && graph.accounts.len() >= max_accounts
+                        {
+                            graph.truncated = true;
+                            break 'walk;
+                        }
+                        graph.follows.push((did.clone(), follow.did.clone()));
+                        if visited.insert(follow.did.clone()) {
+                            next.push(follow.did.clone());
+                        }
+                        graph.accounts.entry(follow.did.clone()).or_insert(follow);
+                    }
+
+                    match next_cursor {
+                        Some(next_cursor) => cursor = Some(next_cursor),
+                        None => break,
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        Ok(graph)
+    }
+
+    ///app.bsky.graph.getStarterPack
+    pub async fn bsky_get_starter_pack(
+        &mut self,
+        uri: &str,
+    ) -> Result<StarterPackView, BiskyError> {
+        let response = self
+            .xrpc_get::<GetStarterPackOutput>(
+                "app.bsky.graph.getStarterPack",
+                Some(&[("starterPack", uri)]),
+            )
+            .await?;
+
+        Ok(response.starter_pack)
+    }
+
+    ///app.bsky.graph.getStarterPacks
+    /// Any number of starter packs, 25 per request
+    pub async fn bsky_get_starter_packs(
+        &mut self,
+        uris: &[&str],
+    ) -> Result<Vec<StarterPackViewBasic>, BiskyError> {
+        let mut starter_packs = Vec::with_capacity(uris.len());
+
+        let outputs = self
+            .chunked_query::<GetStarterPacksOutput>(
+                "app.bsky.graph.getStarterPacks",
+                &[],
+                "uris",
+                uris,
+                MAX_GET_STARTER_PACKS,
+                ChunkOptions::default(),
+            )
+            .await?;
+        for output in outputs {
+            starter_packs.append(&mut output?.starter_packs);
+        }
+
+        Ok(starter_packs)
+    }
+
+    ///app.bsky.graph.getActorStarterPacks
+    /// The starter packs created by `actor`
+    pub async fn bsky_get_actor_starter_packs(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<StarterPackViewBasic>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<StarterPacksPageOutput>(
+            "app.bsky.graph.getActorStarterPacks",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.searchStarterPacks
+    pub async fn bsky_search_starter_packs(
+        &mut self,
+        q: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<StarterPackViewBasic>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<StarterPacksPageOutput>(
+            "app.bsky.graph.searchStarterPacks",
+            &[("q", q)],
+            limit,
+            25,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.muteActor
+    /// Mutes are private to the logged in user and kept by the server, not in the repo. Backs
+    /// off when rate limited, so it can be called in a loop.
+    pub async fn bsky_mute_actor(&mut self, actor: &str) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_backoff("app.bsky.graph.muteActor", &MuteActor { actor })
+            .await?;
+        self.invalidate_actor(actor);
+        Ok(())
+    }
+
+    ///app.bsky.graph.unmuteActor
+    pub async fn bsky_unmute_actor(&mut self, actor: &str) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_backoff("app.bsky.graph.unmuteActor", &MuteActor { actor })
+            .await?;
+        self.invalidate_actor(actor);
+        Ok(())
+    }
+
+    ///app.bsky.graph.getMutes
+    /// The accounts the logged in user has muted
+    pub async fn bsky_get_mutes(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetMutesOutput>("app.bsky.graph.getMutes", &[], limit, 100, cursor)
+            .await
+    }
+
+    ///app.bsky.graph.muteThread
+    /// `root` is the uri of the thread's root post
+    pub async fn bsky_mute_thread(&mut self, root: &str) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_backoff("app.bsky.graph.muteThread", &MuteThread { root })
+            .await
+    }
+
+    ///app.bsky.graph.unmuteThread
+    pub async fn bsky_unmute_thread(&mut self, root: &str) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_backoff("app.bsky.graph.unmuteThread", &MuteThread { root })
+            .await
+    }
+
+    ///app.bsky.graph.muteActorList
+    /// Privately mute every account on a list. This is server state, not a record; compare
+    /// `bsky_block_list`.
+    pub async fn bsky_mute_list(&mut self, list_uri: &str) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_backoff(
+            "app.bsky.graph.muteActorList",
+            &MuteActorList { list: list_uri },
+        )
+        .await
+    }
+
+    ///app.bsky.graph.unmuteActorList
+    pub async fn bsky_unmute_list(&mut self, list_uri: &str) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response_backoff(
+            "app.bsky.graph.unmuteActorList",
+            &MuteActorList { list: list_uri },
+        )
+        .await
+    }
+
+    /// Every follower of `actor`, starting from `cursor` if resuming. `progress` is called after
+    /// each page with the number of followers collected and the cursor to resume from, so it
+    /// can be saved in case the collection is interrupted. Also returns the last cursor seen.
+    pub async fn bsky_collect_followers(
+        &mut self,
+        actor: &str,
+        cursor: Option<&str>,
+        mut progress: impl FnMut(usize, Option<&str>),
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        let mut followers = Vec::new();
+        let mut cursor = cursor.map(str::to_string);
+
+        loop {
+            let mut query = Vec::from([("actor", actor), ("limit", "100")]);
+            if let Some(cursor) = cursor.as_ref() {
+                query.push(("cursor", cursor));
+            }
+
+            let mut page = self
+                .xrpc_get_backoff::<GetFollowersOutput>("app.bsky.graph.getFollowers", Some(&query))
+                .await?;
+            let done = page.followers.is_empty() || page.cursor.is_none();
+            followers.append(&mut page.followers);
+
+            if page.cursor.is_some() {
+                cursor = page.cursor;
+            }
+            progress(followers.len(), cursor.as_deref());
+            if done {
+                break;
+            }
+        }
+
+        Ok((followers, cursor))
+    }
+
+    ///app.bsky.graph.getKnownFollowers
+    /// Followers of `actor` that the logged in user also follows
+    pub async fn bsky_get_known_followers(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetKnownFollowersOutput>(
+            "app.bsky.graph.getKnownFollowers",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.graph.getRelationships
+    /// How `actor` relates to each of `others`, at most 30 at a time
+    pub async fn bsky_get_relationships(
+        &mut self,
+        actor: &str,
+        others: &[&str],
+    ) -> Result<Vec<RelationshipEnum>, BiskyError> {
+        let mut relationships = Vec::with_capacity(others.len());
+
+        let outputs = self
+            .chunked_query::<GetRelationshipsOutput>(
+                "app.bsky.graph.getRelationships",
+                &[("actor", actor)],
+                "others",
+                others,
+                MAX_GET_RELATIONSHIPS,
+                ChunkOptions::default(),
+            )
+            .await?;
+        for output in outputs {
+            relationships.append(&mut output?.relationships);
+        }
+
+        Ok(relationships)
+    }
+
+    ///app.bsky.actor.getProfile
+    /// `actor` may be a handle or a DID
+    pub async fn bsky_get_profile(
+        &mut self,
+        actor: &str,
+    ) -> Result<ProfileViewDetailed, BiskyError> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = self.cache.clone() {
+            let profile = cache
+                .profiles(&[actor], |mut actors| async move {
+                    let profile = self
+                        .xrpc_get("app.bsky.actor.getProfile", Some(&[("actor", actor)]))
+                        .await?;
+                    Ok(vec![(actors.remove(0), profile)])
+                })
+                .await?;
+            return Ok(profile.into_iter().flatten().next().unwrap());
+        }
+        self.xrpc_get("app.bsky.actor.getProfile", Some(&[("actor", actor)]))
+            .await
+    }
+
+    ///app.bsky.actor.searchActors
+    pub async fn bsky_search_actors(
+        &mut self,
+        q: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<SearchActorsOutput>(
+            "app.bsky.actor.searchActors",
+            &[("q", q)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.actor.searchActorsTypeahead
+    /// Works without a session against a public AppView, see `PUBLIC_APPVIEW`
+    pub async fn bsky_search_actors_typeahead(
+        &mut self,
+        q: &str,
+        limit: usize,
+    ) -> Result<Vec<ProfileViewBasic>, BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let response = self
+            .xrpc_get_public::<SearchActorsTypeaheadOutput>(
+                "app.bsky.actor.searchActorsTypeahead",
+                Some(&[("q", q), ("limit", &limit)]),
+            )
+            .await?;
+
+        Ok(response.actors)
+    }
+
+    ///app.bsky.actor.getSuggestions
+    /// One page of suggested follows, keeping the `rec_id` of the batch
+    pub async fn bsky_get_suggestions(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<GetSuggestionsOutput, BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = Vec::from([("limit", limit.as_str())]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        self.xrpc_get("app.bsky.actor.getSuggestions", Some(&query))
+            .await
+    }
+
+    ///app.bsky.actor.getSuggestions
+    /// Suggested follows across as many pages as it takes to reach `limit`
+    pub async fn bsky_get_all_suggestions(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetSuggestionsOutput>(
+            "app.bsky.actor.getSuggestions",
+            &[],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.actor.getPreferences
+    pub async fn bsky_get_preferences(&mut self) -> Result<Preferences, BiskyError> {
+        let response = self
+            .xrpc_get::<GetPreferencesOutput>("app.bsky.actor.getPreferences", None)
+            .await?;
+
+        Ok(response.preferences)
+    }
+
+    ///app.bsky.actor.putPreferences
+    /// Replaces every preference, so `preferences` should come from `bsky_get_preferences`
+    pub async fn bsky_put_preferences(
+        &mut self,
+        preferences: &Preferences,
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response(
+            "app.bsky.actor.putPreferences",
+            &PutPreferences { preferences },
+        )
+        .await
+    }
+
+    /// Read the preferences, change them, and write them back with every other preference
+    /// object untouched, e.g. `|prefs| prefs.get_mut::<AdultContentPref>().enabled = true`
+    pub async fn bsky_update_preferences(
+        &mut self,
+        update: impl FnOnce(&mut Preferences),
+    ) -> Result<Preferences, BiskyError> {
+        let mut preferences = self.bsky_get_preferences().await?;
+        update(&mut preferences);
+        self.bsky_put_preferences(&preferences).await?;
+        Ok(preferences)
+    }
+
+    /// Set how content carrying `label` is shown, for the label from `labeler_did` or from
+    /// every labeler with `None`. A labeler must be one the user subscribes to.
+    pub async fn bsky_set_label_preference(
+        &mut self,
+        labeler_did: Option<&str>,
+        label: &str,
+        visibility: Visibility,
+    ) -> Result<(), BiskyError> {
+        let mut preferences = self.bsky_get_preferences().await?;
+        if let Some(did) = labeler_did {
+            if !preferences.has_labeler(did) {
+                return Err(BiskyError::UnknownLabeler(did.to_string()));
+            }
+        }
+        preferences.set_label_visibility(labeler_did, label, visibility);
+        self.bsky_put_preferences(&preferences).await
+    }
+
+    /// Turn adult content on or off, which gates every adult only label
+    pub async fn bsky_set_adult_content(&mut self, enabled: bool) -> Result<(), BiskyError> {
+        self.bsky_update_preferences(|prefs| prefs.get_mut::<AdultContentPref>().enabled = enabled)
+            .await?;
+        Ok(())
+    }
+
+    /// The logged in user's muted words
+    pub async fn bsky_list_muted_words(&mut self) -> Result<Vec<MutedWord>, BiskyError> {
+        let preferences = self.bsky_get_preferences().await?;
+        Ok(preferences
+            .get::<MutedWordsPref>()
+            .map(|pref| pref.items.clone())
+            .unwrap_or_default())
+    }
+
+    /// Mute a word, or update the targets and expiry of an already muted word
+    pub async fn bsky_add_muted_word(
+        &mut self,
+        word: &str,
+        targets: &[MutedWordTarget],
+        expires_at: Option<Datetime>,
+    ) -> Result<(), BiskyError> {
+        self.bsky_update_preferences(|prefs| {
+            prefs
+                .get_mut::<MutedWordsPref>()
+                .add(word, targets, expires_at);
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Unmute a word. Returns false if it was not muted, in which case nothing is written.
+    pub async fn bsky_remove_muted_word(&mut self, word: &str) -> Result<bool, BiskyError> {
+        let mut preferences = self.bsky_get_preferences().await?;
+        let mut removed = false;
+        for pref in preferences
+            .0
+            .iter_mut()
+            .filter_map(MutedWordsPref::from_pref_mut)
+        {
+            removed |= pref.remove(word);
+        }
+        if removed {
+            self.bsky_put_preferences(&preferences).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Hide a post from the logged in user's threads. Returns false if it was already hidden,
+    /// in which case nothing is written.
+    pub async fn bsky_hide_post(&mut self, uri: &str) -> Result<bool, BiskyError> {
+        let mut preferences = self.bsky_get_preferences().await?;
+        let hidden = preferences.get_mut::<HiddenPostsPref>().hide(uri);
+        if hidden {
+            self.bsky_put_preferences(&preferences).await?;
+        }
+        Ok(hidden)
+    }
+
+    /// Unhide a post. Returns false if it was not hidden, in which case nothing is written.
+    pub async fn bsky_unhide_post(&mut self, uri: &str) -> Result<bool, BiskyError> {
+        let mut preferences = self.bsky_get_preferences().await?;
+        let mut unhidden = false;
+        for pref in preferences
+            .0
+            .iter_mut()
+            .filter_map(HiddenPostsPref::from_pref_mut)
+        {
+            unhidden |= pref.unhide(uri);
+        }
+        if unhidden {
+            self.bsky_put_preferences(&preferences).await?;
+        }
+        Ok(unhidden)
+    }
+
+    /// The logged in user's thread view preference, the app's defaults if they never set one
+    pub async fn bsky_get_thread_view_pref(&mut self) -> Result<ThreadViewPref, BiskyError> {
+        let preferences = self.bsky_get_preferences().await?;
+        Ok(preferences
+            .get::<ThreadViewPref>()
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    /// Set how replies are ordered in threads
+    pub async fn bsky_set_thread_view_pref(
+        &mut self,
+        sort: ThreadSort,
+        prioritize_followed_users: bool,
+    ) -> Result<(), BiskyError> {
+        self.bsky_update_preferences(|prefs| {
+            let pref = prefs.get_mut::<ThreadViewPref>();
+            pref.sort = Some(sort);
+            pref.prioritize_followed_users = Some(prioritize_followed_users);
+        })
+        .await?;
+        Ok(())
+    }
+
+    ///app.bsky.actor.getProfiles
+    /// Fetches any number of profiles, 25 per request. The result lines up with `actors`, with
+    /// `None` for accounts the server left out because they are deleted, suspended or unknown.
+    pub async fn bsky_get_profiles(
+        &mut self,
+        actors: &[&str],
+    ) -> Result<Vec<Option<ProfileViewDetailed>>, BiskyError> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = self.cache.clone() {
+            return cache
+                .profiles(actors, |missing| async move {
+                    let missing = missing.iter().map(String::as_str).collect::<Vec<_>>();
+                    let outputs = self
+                        .chunked_query::<serde_json::Value>(
+                            "app.bsky.actor.getProfiles",
+                            &[],
+                            "actors",
+                            &missing,
+                            MAX_GET_PROFILES,
+                            ChunkOptions::default(),
+                        )
+                        .await?;
+                    let mut found = Vec::new();
+                    for (chunk, output) in missing.chunks(MAX_GET_PROFILES).zip(outputs) {
+                        let serde_json::Value::Array(profiles) = output?["profiles"].take() else {
+                            continue;
+                        };
+                        for profile in profiles {
+                            if let Some(actor) = chunk.iter().find(|a| is_profile_of(&profile, a)) {
+                                found.push((actor.to_string(), profile));
+                            }
+                        }
+                    }
+                    Ok(found)
+                })
+                .await;
+        }
+
+        let mut profiles = Vec::with_capacity(actors.len());
+
+        let outputs = self
+            .chunked_query::<GetProfilesOutput>(
+                "app.bsky.actor.getProfiles",
+                &[],
+                "actors",
+                actors,
+                MAX_GET_PROFILES,
+                ChunkOptions::default(),
+            )
+            .await?;
+        for (chunk, output) in actors.chunks(MAX_GET_PROFILES).zip(outputs) {
+            let mut response = output?.profiles;
+
+            for actor in chunk {
+                let found = response.iter().position(|profile| {
+                    profile.did == *actor || profile.handle.eq_ignore_ascii_case(actor)
+                });
+                profiles.push(found.map(|i| response.swap_remove(i)));
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    ///app.bsky.feed.getPosts
+    /// Posts that were deleted or can't be seen are left out
+    pub async fn bsky_get_posts(&mut self, uris: &[&str]) -> Result<Vec<PostView>, BiskyError> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = self.cache.clone() {
+            let posts = cache
+                .posts(uris, |missing| async move {
+                    let missing = missing.iter().map(String::as_str).collect::<Vec<_>>();
+                    let outputs = self
+                        .chunked_query::<serde_json::Value>(
+                            "app.bsky.feed.getPosts",
+                            &[],
+                            "uris",
+                            &missing,
+                            MAX_GET_POSTS,
+                            ChunkOptions::default(),
+                        )
+                        .await?;
+                    let mut found = Vec::new();
+                    for output in outputs {
+                        let serde_json::Value::Array(posts) = output?["posts"].take() else {
+                            continue;
+                        };
+                        for post in posts {
+                            if let Some(uri) = post["uri"].as_str() {
+                                found.push((uri.to_string(), post));
+                            }
+                        }
+                    }
+                    Ok(found)
+                })
+                .await?;
+            return Ok(posts.into_iter().flatten().collect());
+        }
+
+        let mut posts = Vec::with_capacity(uris.len());
+        let outputs = self
+            .chunked_query::<GetPostsOutput>(
+                "app.bsky.feed.getPosts",
+                &[],
+                "uris",
+                uris,
+                MAX_GET_POSTS,
+                ChunkOptions::default(),
+            )
+            .await?;
+        for output in outputs {
+            posts.append(&mut output?.posts);
+        }
+        Ok(posts)
+    }
+
+    ///app.bsky.feed.getPostThread
+    pub async fn bsky_get_post_thread(
+        &mut self,
+        uri: &str,
+    ) -> Result<ThreadViewPostEnum, BiskyError> {
+        self.bsky_get_post_thread_depth(uri, None, None).await
+    }
+
+    ///app.bsky.feed.getPostThread with explicit reply depth and parent height
+    pub async fn bsky_get_post_thread_depth(
+        &mut self,
+        uri: &str,
+        depth: Option<usize>,
+        parent_height: Option<usize>,
+    ) -> Result<ThreadViewPostEnum, BiskyError> {
+        let depth = depth.map(|d| d.to_string());
+        let parent_height = parent_height.map(|h| h.to_string());
+        let mut query = Vec::from([("uri", uri)]);
+        if let Some(depth) = depth.as_ref() {
+            query.push(("depth", depth));
+        }
+        if let Some(parent_height) = parent_height.as_ref() {
+            query.push(("parentHeight", parent_height));
+        }
+
+        let response = self
+            .xrpc_get::<GetPostThreadOutput>("app.bsky.feed.getPostThread", Some(&query))
+            .await?;
+
+        Ok(response.thread)
+    }
+
+    /// Fetch a whole thread from its root, following up wherever getPostThread cut the replies
+    /// off, until the thread is exhausted or `max_posts` posts have been gathered
+    pub async fn bsky_fetch_full_thread(
+        &mut self,
+        uri: &str,
+        max_posts: usize,
+    ) -> Result<FullThread, BiskyError> {
+        // Any post in the thread knows its root
+        let root = match self
+            .bsky_get_post_thread_depth(uri, Some(0), Some(0))
+            .await?
+        {
+            ThreadViewPostEnum::ThreadViewPost(node) => node
+                .post
+                .record
+                .reply
+                .as_ref()
+                .map_or_else(|| node.post.uri.clone(), |reply| reply.root.uri.to_string()),
+            _ => {
+                return Err(BiskyError::UnexpectedResponse(format!(
+                    "{uri} is not a post"
+                )))
+            }
+        };
+
+        let mut thread = FullThread {
+            root: root.clone(),
+            ..Default::default()
+        };
+        let mut seen = HashSet::new();
+        let mut pending = VecDeque::from([root]);
+
+        while let Some(uri) = pending.pop_front() {
+            if thread.posts.len() >= max_posts {
+                thread.truncated = true;
+                break;
+            }
+            let node = self
+                .bsky_get_post_thread_depth(&uri, Some(1000), Some(0))
+                .await?;
+            collect_thread(node, &mut thread, &mut seen, &mut pending, max_posts);
+        }
+
+        thread.posts.sort_by_key(|post| post.record.created_at);
+        Ok(thread)
+    }
+
+    /// The posts the author of `first_post_uri` made in a row, each replying to the one before,
+    /// starting from it: a thread written as a series of posts. Their replies to other people
+    /// are left out, and getPostThread is called again wherever it cut the replies off. Where
+    /// the chain ends, the author's next posts are looked through for one carrying it on from a
+    /// post they deleted, which is noted as a gap.
+    pub async fn bsky_unroll_thread(
+        &mut self,
+        first_post_uri: &str,
+    ) -> Result<UnrolledThread, BiskyError> {
+        let mut node = self.unroll_node(first_post_uri).await?;
+        let author = node.post.author.did.clone();
+        let root = node
+            .post
+            .record
+            .reply
+            .as_ref()
+            .map_or_else(|| node.post.uri.clone(), |reply| reply.root.uri.to_string());
+
+        let mut unrolled = UnrolledThread::default();
+        let mut seen = HashSet::new();
+        let mut after_gap = false;
+        while seen.insert(node.post.uri.clone()) {
+            if after_gap {
+                unrolled.gaps.push(unrolled.posts.len());
+            }
+            let uri = node.post.uri.clone();
+            let replies = match node.replies {
+                Some(replies) => replies,
+                None if node.post.reply_count.unwrap_or_default() > 0 => {
+                    self.unroll_node(&uri).await?.replies.unwrap_or_default()
+                }
+                None => Vec::new(),
+            };
+            unrolled.posts.push(node.post);
+
+            (node, after_gap) = match next_in_chain(replies, &author) {
+                Some(next) => (next, false),
+                None => match self.find_after_gap(&author, &root, &uri, &seen).await? {
+                    Some(next) => (self.unroll_node(&next).await?, true),
+                    None => break,
+                },
+            };
+        }
+        Ok(unrolled)
+    }
+
+    async fn unroll_node(&mut self, uri: &str) -> Result<ThreadViewPost, BiskyError> {
+        match self
+            .bsky_get_post_thread_depth(uri, Some(UNROLL_THREAD_DEPTH), Some(0))
+            .await?
+        {
+            ThreadViewPostEnum::ThreadViewPost(node) => Ok(*node),
+            _ => Err(BiskyError::UnexpectedResponse(format!(
+                "{uri} is not a post"
+            ))),
+        }
+    }
+
+    /// The uri of the first post by `author` after `after` in their repo that is in the thread
+    /// under `root` and replies to a post of theirs that was deleted
+    async fn find_after_gap(
+        &mut self,
+        author: &str,
+        root: &str,
+        after: &str,
+        seen: &HashSet<String>,
+    ) -> Result<Option<String>, BiskyError> {
+        let rkey = after.parse::<AtUri>()?.require_rkey()?.to_string();
+        let collection = "app.bsky.feed.post";
+        let (records, _) = if self.session_did().is_ok_and(|own| own == author) {
+            self.repo_list_records::<serde_json::Value>(
+                author,
+                collection,
+                UNROLL_GAP_SCAN,
+                true,
+                Some(rkey),
+            )
+            .await?
+        } else {
+            let pds = resolve_did(author)
+                .await?
+                .pds_endpoint()
+                .map(str::to_string)
+                .ok_or_else(|| BiskyError::NoPdsEndpoint(author.to_string()))?;
+            ClientBuilder::default()
+                .service(pds)
+                .build()?
+                .repo_list_records::<serde_json::Value>(
+                    author,
+                    collection,
+                    UNROLL_GAP_SCAN,
+                    true,
+                    Some(rkey),
+                )
+                .await?
+        };
+
+        for record in records {
+            let Some(reply) = record
+                .value
+                .get("reply")
+                .and_then(|reply| serde_json::from_value::<ReplyRef>(reply.clone()).ok())
+            else {
+                continue;
+            };
+            let parent = reply.parent.uri.to_string();
+            if reply.root.uri.to_string() != root
+                || reply.parent.uri.authority != author
+                || seen.contains(&parent)
+            {
+                continue;
+            }
+            // A reply to one of the author's replies to someone else is still there
+            if self.bsky_get_posts(&[&parent]).await?.is_empty() {
+                return Ok(Some(record.uri));
+            }
+        }
+        Ok(None)
+    }
+
+    ///app.bsky.feed.getTimeline
+    pub async fn bsky_get_timeline(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<FeedViewPost>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetTimelineOutput>(
+            "app.bsky.feed.getTimeline",
+            &[],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    /// `bsky_get_timeline` with the items left undecoded beyond their uri, author, time and
+    /// text, for `LazyFeedViewPost::decode_full` to decode those that are wanted
+    pub async fn bsky_get_timeline_lazy(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<LazyFeedViewPost>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<LazyFeedOutput>("app.bsky.feed.getTimeline", &[], limit, 100, cursor)
+            .await
+    }
+
+    /// app.bsky.feed.getTimeline as a stream that follows the cursor, stopping at items older
+    /// than `stop_at`
+    pub fn bsky_get_timeline_stream(
+        &mut self,
+        stop_at: Option<Datetime>,
+    ) -> impl Stream<Item = Result<FeedViewPost, BiskyError>> + '_ {
+        feed_stream::<GetTimelineOutput>(self, "app.bsky.feed.getTimeline", Vec::new(), stop_at)
+    }
+
+    ///app.bsky.feed.getAuthorFeed
+    pub async fn bsky_get_author_feed(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<FeedViewPost>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<AuthorFeed>(
+            "app.bsky.feed.getAuthorFeed",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+        .map_err(map_feed_error)
+    }
+
+    /// app.bsky.feed.getAuthorFeed as a stream that follows the cursor, stopping at items older
+    /// than `stop_at`
+    pub fn bsky_get_author_feed_stream(
+        &mut self,
+        actor: &str,
+        stop_at: Option<Datetime>,
+    ) -> impl Stream<Item = Result<FeedViewPost, BiskyError>> + '_ {
+        feed_stream::<AuthorFeed>(
+            self,
+            "app.bsky.feed.getAuthorFeed",
+            vec![("actor", actor.to_string())],
+            stop_at,
+        )
+    }
+
+    /// app.bsky.feed.getFeed as a stream that follows the cursor, stopping at items older than
+    /// `stop_at`
+    pub fn bsky_get_feed_stream(
+        &mut self,
+        feed_uri: &AtUri,
+        stop_at: Option<Datetime>,
+    ) -> impl Stream<Item = Result<FeedViewPost, BiskyError>> + '_ {
+        feed_stream::<GetFeedOutput>(
+            self,
+            "app.bsky.feed.getFeed",
+            vec![("feed", feed_uri.to_string())],
+            stop_at,
+        )
+    }
+
+    /// app.bsky.feed.getListFeed as a stream that follows the cursor, stopping at items older
+    /// than `stop_at`
+    pub fn bsky_get_list_feed_stream(
+        &mut self,
+        list_uri: &AtUri,
+        stop_at: Option<Datetime>,
+    ) -> impl Stream<Item = Result<FeedViewPost, BiskyError>> + '_ {
+        feed_stream::<GetListFeedOutput>(
+            self,
+            "app.bsky.feed.getListFeed",
+            vec![("list", list_uri.to_string())],
+            stop_at,
+        )
+    }
+
+    /// app.bsky.feed.getActorLikes as a stream that follows the cursor, stopping at items older
+    /// than `stop_at`
+    pub fn bsky_get_actor_likes_stream(
+        &mut self,
+        actor: &str,
+        stop_at: Option<Datetime>,
+    ) -> impl Stream<Item = Result<FeedViewPost, BiskyError>> + '_ {
+        feed_stream::<GetActorLikesOutput>(
+            self,
+            "app.bsky.feed.getActorLikes",
+            vec![("actor", actor.to_string())],
+            stop_at,
+        )
+    }
+
+    ///app.bsky.feed.getFeed
+    pub async fn bsky_get_feed(
+        &mut self,
+        feed_uri: &AtUri,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<FeedViewPost>, Option<String>), BiskyError> {
+        let feed_uri = feed_uri.to_string();
+        self.xrpc_get_pages::<GetFeedOutput>(
+            "app.bsky.feed.getFeed",
+            &[("feed", &feed_uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+        .map_err(map_feed_error)
+    }
+
+    /// `bsky_get_feed` with the items left undecoded, as `bsky_get_timeline_lazy` does
+    pub async fn bsky_get_feed_lazy(
+        &mut self,
+        feed_uri: &AtUri,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<LazyFeedViewPost>, Option<String>), BiskyError> {
+        let feed_uri = feed_uri.to_string();
+        self.xrpc_get_pages::<LazyFeedOutput>(
+            "app.bsky.feed.getFeed",
+            &[("feed", &feed_uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+        .map_err(map_feed_error)
+    }
+
+    ///app.bsky.feed.getFeedGenerator
+    pub async fn bsky_get_feed_generator(
+        &mut self,
+        feed_uri: &AtUri,
+    ) -> Result<GetFeedGeneratorOutput, BiskyError> {
+        let feed_uri = feed_uri.to_string();
+        self.xrpc_get(
+            "app.bsky.feed.getFeedGenerator",
+            Some(&[("feed", feed_uri.as_str())]),
+        )
+        .await
+    }
+
+    ///app.bsky.feed.getFeedGenerators
+    pub async fn bsky_get_feed_generators(
+        &mut self,
+        feed_uris: &[AtUri],
+    ) -> Result<Vec<GeneratorView>, BiskyError> {
+        let feed_uris = feed_uris.iter().map(AtUri::to_string).collect::<Vec<_>>();
+        let feed_uris = feed_uris.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let mut feeds = Vec::with_capacity(feed_uris.len());
+        let outputs = self
+            .chunked_query::<GetFeedGeneratorsOutput>(
+                "app.bsky.feed.getFeedGenerators",
+                &[],
+                "feeds",
+                &feed_uris,
+                MAX_GET_FEED_GENERATORS,
+                ChunkOptions::default(),
+            )
+            .await?;
+        for output in outputs {
+            feeds.append(&mut output?.feeds);
+        }
+        Ok(feeds)
+    }
+
+    ///app.bsky.feed.getActorFeeds
+    pub async fn bsky_get_actor_feeds(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<GeneratorView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetActorFeedsOutput>(
+            "app.bsky.feed.getActorFeeds",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.feed.getSuggestedFeeds
+    pub async fn bsky_get_suggested_feeds(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<GeneratorView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetSuggestedFeedsOutput>(
+            "app.bsky.feed.getSuggestedFeeds",
+            &[],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.unspecced.getPopularFeedGenerators
+    #[cfg(feature = "unspecced")]
+    pub async fn bsky_get_popular_feed_generators(
+        &mut self,
+        query: Option<&str>,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<GeneratorView>, Option<String>), BiskyError> {
+        let query = query.map(|q| ("query", q)).into_iter().collect::<Vec<_>>();
+        self.xrpc_get_pages::<GetPopularFeedGeneratorsOutput>(
+            "app.bsky.unspecced.getPopularFeedGenerators",
+            &query,
+            limit,
+            100,
+            cursor,
+        )
+        .await
+    }
+
+    ///app.bsky.unspecced.getTrendingTopics
+    /// Returns the trending topics and the suggested feeds
+    #[cfg(feature = "unspecced")]
+    pub async fn bsky_get_trending_topics(
+        &mut self,
+        limit: usize,
+    ) -> Result<(Vec<TrendingTopic>, Vec<TrendingTopic>), BiskyError> {
+        let limit = std::cmp::min(limit, 25).to_string();
+        let mut query = vec![("limit", limit.as_str())];
+        let viewer = self.session().map(|s| s.did);
+        if let Some(viewer) = &viewer {
+            query.push(("viewer", viewer.as_str()));
+        }
+
+        let response = self
+            .xrpc_get::<GetTrendingTopicsOutput>(
+                "app.bsky.unspecced.getTrendingTopics",
+                Some(&query),
+            )
+            .await?;
+        Ok((response.topics, response.suggested))
+    }
+
+    ///app.bsky.unspecced.getTaggedSuggestions
+    #[cfg(feature = "unspecced")]
+    pub async fn bsky_get_tagged_suggestions(
+        &mut self,
+    ) -> Result<Vec<TaggedSuggestion>, BiskyError> {
+        let response = self
+            .xrpc_get::<GetTaggedSuggestionsOutput>("app.bsky.unspecced.getTaggedSuggestions", None)
+            .await?;
+        Ok(response.suggestions)
+    }
+
+    ///app.bsky.unspecced.getConfig
+    #[cfg(feature = "unspecced")]
+    pub async fn bsky_get_config(&mut self) -> Result<GetConfigOutput, BiskyError> {
+        self.xrpc_get("app.bsky.unspecced.getConfig", None).await
+    }
+
+    ///app.bsky.feed.getActorLikes
+    /// Only works for the logged in user's own account
+    pub async fn bsky_get_actor_likes(
+        &mut self,
+        actor: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<FeedViewPost>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetActorLikesOutput>(
+            "app.bsky.feed.getActorLikes",
+            &[("actor", actor)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+        .map_err(map_feed_error)
+    }
+
+    ///app.bsky.feed.getListFeed
+    pub async fn bsky_get_list_feed(
+        &mut self,
+        list_uri: &AtUri,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<FeedViewPost>, Option<String>), BiskyError> {
+        let list_uri = list_uri.to_string();
+        self.xrpc_get_pages::<GetListFeedOutput>(
+            "app.bsky.feed.getListFeed",
+            &[("list", &list_uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+        .map_err(map_feed_error)
+    }
+
+    /// `bsky_get_list_feed` with the items left undecoded, as `bsky_get_timeline_lazy` does
+    pub async fn bsky_get_list_feed_lazy(
+        &mut self,
+        list_uri: &AtUri,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<LazyFeedViewPost>, Option<String>), BiskyError> {
+        let list_uri = list_uri.to_string();
+        self.xrpc_get_pages::<LazyFeedOutput>(
+            "app.bsky.feed.getListFeed",
+            &[("list", &list_uri)],
+            limit,
+            100,
+            cursor,
+        )
+        .await
+        .map_err(map_feed_error)
+    }
+
+    ///app.bsky.feed.sendInteractions
+    pub async fn bsky_send_interactions(
+        &mut self,
+        interactions: &[Interaction],
+    ) -> Result<(), BiskyError> {
+        self.xrpc_post_no_response(
+            "app.bsky.feed.sendInteractions",
+            &SendInteractions { interactions },
+        )
+        .await
+    }
+
+    ///app.bsky.feed.post
+    /// Validate a post, check it against the alt text policy and create it in the logged in
+    /// user's repo
+    pub async fn bsky_create_post(
+        &mut self,
+        post: &Post,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        post.validate()?;
+        self.alt_text_policy.check(post)?;
+        let did = self.session_did()?;
+        let created = self
+            .repo_create_record(&did, "app.bsky.feed.post", post)
+            .await?;
+        if let Some(reply) = &post.reply {
+            self.invalidate_post(&reply.parent.uri.to_string());
+        }
+        Ok(created)
+    }
+
+    /// Create a post whose rkey is derived from `key`, so that creating it again with the same
+    /// key, e.g. when a job is retried after a crash, returns the post made the first time
+    /// rather than posting twice. The post made the first time is returned as it is, even if
+    /// `post` differs from it.
+    pub async fn bsky_create_post_idempotent(
+        &mut self,
+        key: &str,
+        post: &Post,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        post.validate()?;
+        self.alt_text_policy.check(post)?;
+        let did = self.session_did()?;
+        let rkey = idempotent_rkey(key);
+        match self
+            .repo_create_record_with_rkey(&did, "app.bsky.feed.post", &rkey, post)
+            .await
+        {
+            Ok(created) => {
+                if let Some(reply) = &post.reply {
+                    self.invalidate_post(&reply.parent.uri.to_string());
+                }
+                Ok(created)
+            }
+            Err(e) if is_already_exists(&e) => {
+                let existing = self
+                    .repo_get_record::<serde_json::Value>(&did, "app.bsky.feed.post", &rkey)
+                    .await?;
+                Ok(CreateRecordOutput {
+                    cid: existing.cid,
+                    uri: existing.uri,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reply to the post at `post_uri` with `text`, in the same thread
+    pub async fn bsky_reply(
+        &mut self,
+        post_uri: &AtUri,
+        text: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let mut builder = PostBuilder::default();
+        builder.text(text);
+        self.bsky_reply_with(post_uri, builder).await
+    }
+
+    /// Reply to the post at `post_uri` with the post `builder` builds, which can carry media and
+    /// facets. The post is read from its author's repo, so the reply refers to its current cid,
+    /// and the root of the reply is the root of the post, or the post itself if it isn't a reply.
+    /// Fails with `BiskyError::ReplyTargetNotFound` if the post no longer exists.
+    pub async fn bsky_reply_with(
+        &mut self,
+        post_uri: &AtUri,
+        mut builder: PostBuilder,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        if post_uri.require_collection()? != "app.bsky.feed.post" {
+            return Err(BiskyError::InvalidAtUri(format!(
+                "{post_uri} is not a post"
+            )));
+        }
+        let target = match self
+            .repo_get_record::<Post>(
+                &post_uri.authority,
+                "app.bsky.feed.post",
+                post_uri.require_rkey()?,
+            )
+            .await
+        {
+            Ok(target) => target,
+            Err(BiskyError::ApiError(e)) if e.kind() == ApiErrorKind::RecordNotFound => {
+                return Err(BiskyError::ReplyTargetNotFound(post_uri.to_string()));
+            }
+            Err(e) => return Err(e),
+        };
+        let parent = StrongRef::new(&target.uri, &target.cid)?;
+        let root = match target.value.reply {
+            Some(reply) => reply.root,
+            None => parent.clone(),
+        };
+        builder.reply(ReplyRef { root, parent });
+        self.bsky_create_post(&builder.build()?).await
+    }
+
+    /// Post `text` with one image, uploaded as it is. The text and the `AltTextPolicy` are
+    /// checked before uploading, so that a post that would be refused uploads nothing. To fit
+    /// images under the upload limit or add several, use `PostBuilder::add_image`.
+    pub async fn bsky_post_image(
+        &mut self,
+        text: &str,
+        image: &[u8],
+        mime_type: &str,
+        alt: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let mut builder = PostBuilder::default();
+        builder.text(text);
+        builder.validate()?;
+        if matches!(self.alt_text_policy, AltTextPolicy::Require) && alt.trim().is_empty() {
+            return Err(BiskyError::MissingAltText(vec![Attachment::Image(0)]));
+        }
+
+        let output: BlobOutput = self.repo_upload_blob(image, mime_type).await?;
+        builder.embed(Embeds::Images(ImagesEmbed {
+            images: vec![Image {
+                image: output.blob,
+                alt: alt.to_string(),
+                aspect_ratio: None,
+            }],
+        }));
+        builder.send(self).await
+    }
+
+    /// Post text of any length as a thread, each post replying to the one before it. If a post
+    /// fails the error is `BiskyError::ThreadInterrupted`, carrying the posts made so far.
+    pub async fn bsky_post_thread(
+        &mut self,
+        text: &str,
+        options: &ThreadOptions,
+    ) -> Result<Vec<CreateRecordOutput>, BiskyError> {
+        let segments = split_thread_text(text, options.counters);
+        let total = segments.len();
+        let mut created: Vec<CreateRecordOutput> = Vec::with_capacity(total);
+        let mut root: Option<StrongRef> = None;
+
+        for segment in segments {
+            let result = async {
+                let mut builder = PostBuilder::default();
+                builder.text(segment);
+                for lang in &options.langs {
+                    builder.lang(lang);
+                }
+                if let (Some(root), Some(parent)) = (&root, created.last()) {
+                    builder.reply(ReplyRef {
+                        root: root.clone(),
+                        parent: parent.try_into()?,
+                    });
+                }
+                builder.send(self).await
+            }
+            .await;
+
+            match result {
+                Ok(post) => {
+                    if root.is_none() {
+                        root = StrongRef::try_from(&post).ok();
+                    }
+                    created.push(post);
+                }
+                Err(e) => {
+                    return Err(BiskyError::ThreadInterrupted {
+                        created,
+                        total,
+                        source: Box::new(e),
+                    })
+                }
+            }
+        }
+
+        Ok(created)
+    }
+
+    ///app.bsky.feed.like
+    pub async fn bsky_like(
+        &mut self,
+        uri: &str,
+        cid: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.session_did()?;
+        let created = self
+            .repo_create_record(
+                &did,
+                "app.bsky.feed.like",
+                Like {
+                    created_at: Datetime::now(),
+                    subject: StrongRef::new(uri, cid)?,
+                },
+            )
+            .await?;
+        self.invalidate_post(uri);
+        Ok(created)
+    }
+
+    /// Delete a like record by its own uri
+    pub async fn bsky_unlike(&mut self, like_uri: &AtUri) -> Result<(), BiskyError> {
+        self.delete_record_at(like_uri, "app.bsky.feed.like").await
+    }
+
+    /// Remove the logged in user's like from a post, scanning their likes to find it.
+    /// Returns false if the post was not liked.
+    pub async fn bsky_unlike_post(&mut self, post_uri: &str) -> Result<bool, BiskyError> {
+        let did = self.session_did()?;
+        let post_uri: AtUri = post_uri.parse()?;
+        let Some(like) = self
+            .find_subject_record::<Like>(&did, "app.bsky.feed.like", |l| l.subject.uri == post_uri)
+            .await?
+        else {
+            return Ok(false);
+        };
+        self.bsky_unlike(&like.uri.parse()?).await?;
+        self.invalidate_post(&post_uri.to_string());
+        Ok(true)
+    }
+
+    ///app.bsky.graph.follow
+    /// Follow an account. If the logged in user already follows it, the existing follow record
+    /// is returned instead of creating a duplicate.
+    pub async fn bsky_follow(&mut self, did: &str) -> Result<CreateRecordOutput, BiskyError> {
+        let profile = self.bsky_get_profile(did).await?;
+        if let Some(following) = profile.viewer.and_then(|v| v.following) {
+            let uri: AtUri = following.parse()?;
+            let record = self
+                .repo_get_record::<Follow>(
+                    &uri.authority,
+                    "app.bsky.graph.follow",
+                    uri.require_rkey()?,
+                )
+                .await?;
+            return Ok(CreateRecordOutput {
+                cid: record.cid,
+                uri: record.uri,
+            });
+        }
+        self.bsky_create_follow(did).await
+    }
+
+    ///app.bsky.graph.follow
+    /// Create a follow record without checking for an existing one
+    pub async fn bsky_create_follow(
+        &mut self,
+        did: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let own_did = self.session_did()?;
+        let created = self
+            .repo_create_record(
+                &own_did,
+                "app.bsky.graph.follow",
+                Follow {
+                    created_at: Datetime::now(),
+                    subject: did.to_string(),
+                },
+            )
+            .await?;
+        self.invalidate_actor(did);
+        self.invalidate_actor(&own_did);
+        Ok(created)
+    }
+
+    /// Unfollow an account, finding the follow record through the profile's viewer state or by
+    /// scanning the logged in user's follows. Returns false if the account was not followed.
+    pub async fn bsky_unfollow(&mut self, did: &str) -> Result<bool, BiskyError> {
+        let own_did = self.session_did()?;
+        let following = self
+            .bsky_get_profile(did)
+            .await?
+            .viewer
+            .and_then(|v| v.following);
+
+        let uri = match following {
+            Some(uri) => uri,
+            None => match self
+                .find_subject_record::<Follow>(&own_did, "app.bsky.graph.follow", |f| {
+                    f.subject == did
+                })
+                .await?
+            {
+                Some(record) => record.uri,
+                None => return Ok(false),
+            },
+        };
+
+        self.delete_record_at(&uri.parse()?, "app.bsky.graph.follow")
+            .await?;
+        self.invalidate_actor(did);
+        self.invalidate_actor(&own_did);
+        Ok(true)
+    }
+
+    ///app.bsky.graph.block
+    /// Block an account. If the logged in user already blocks it, the existing block record is
+    /// returned instead of creating a duplicate.
+    pub async fn bsky_block(&mut self, did: &str) -> Result<CreateRecordOutput, BiskyError> {
+        let own_did = self.session_did()?;
+        let profile = self.bsky_get_profile(did).await?;
+        if let Some(blocking) = profile.viewer.and_then(|v| v.blocking) {
+            let uri: AtUri = blocking.parse()?;
+            let record = self
+                .repo_get_record::<Block>(
+                    &uri.authority,
+                    "app.bsky.graph.block",
+                    uri.require_rkey()?,
+                )
+                .await?;
+            return Ok(CreateRecordOutput {
+                cid: record.cid,
+                uri: record.uri,
+            });
+        }
+        let created = self
+            .repo_create_record(
+                &own_did,
+                "app.bsky.graph.block",
+                Block {
+                    created_at: Datetime::now(),
+                    subject: did.to_string(),
+                },
+            )
+            .await?;
+        self.invalidate_actor(did);
+        Ok(created)
+    }
+
+    /// Unblock an account, finding the block record through the profile's viewer state or by
+    /// scanning the logged in user's blocks. Returns false if the account was not blocked.
+    pub async fn bsky_unblock(&mut self, did: &str) -> Result<bool, BiskyError> {
+        let own_did = self.session_did()?;
+        let blocking = self
+            .bsky_get_profile(did)
+            .await?
+            .viewer
+            .and_then(|v| v.blocking);
+
+        let uri = match blocking {
+            Some(uri) => uri,
+            None => match self
+                .find_subject_record::<Block>(&own_did, "app.bsky.graph.block", |b| {
+                    b.subject == did
+                })
+                .await?
+            {
+                Some(record) => record.uri,
+                None => return Ok(false),
+            },
+        };
+
+        self.delete_record_at(&uri.parse()?, "app.bsky.graph.block")
+            .await?;
+        self.invalidate_actor(did);
+        Ok(true)
+    }
+
+    ///app.bsky.graph.getBlocks
+    /// The accounts the logged in user blocks
+    pub async fn bsky_get_blocks(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<ProfileView>, Option<String>), BiskyError> {
+        self.xrpc_get_pages::<GetBlocksOutput>("app.bsky.graph.getBlocks", &[], limit, 100, cursor)
+            .await
+    }
+
+    ///app.bsky.graph.starterpack
+    /// Create a starter pack together with the reference list of its accounts. Change the
+    /// accounts later with `bsky_add_to_list` and `bsky_remove_from_list` on the list.
+    pub async fn bsky_create_starter_pack(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        dids: &[&str],
+        feed_uris: &[&str],
+    ) -> Result<CreatedStarterPack, BiskyError> {
+        let own_did = self.session_did()?;
+        let list = self
+            .bsky_create_list(name, ListPurpose::Referencelist, None)
+            .await?;
+
+        for did in dids {
+            self.repo_create_record::<CreateRecordOutput, _>(
+                &own_did,
+                "app.bsky.graph.listitem",
+                ListItem {
+                    subject: did.to_string(),
+                    list: list.uri.clone(),
+                    created_at: Datetime::now(),
+                },
+            )
+            .await?;
+        }
+
+        let starter_pack = self
+            .repo_create_record(
+                &own_did,
+                "app.bsky.graph.starterpack",
+                StarterPack {
+                    name: name.to_string(),
+                    description: description.map(str::to_string),
+                    list: list.uri.clone(),
+                    feeds: feed_uris
+                        .iter()
+                        .map(|uri| StarterPackFeed {
+                            uri: uri.to_string(),
+                        })
+                        .collect(),
+                    created_at: Datetime::now(),
+                },
+            )
+            .await?;
+
+        Ok(CreatedStarterPack { list, starter_pack })
+    }
+
+    ///app.bsky.graph.listblock
+    /// Publicly block every account on a list by creating a listblock record; compare
+    /// `bsky_mute_list`. An existing block of the list is returned instead of a duplicate.
+    pub async fn bsky_block_list(
+        &mut self,
+        list_uri: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let own_did = self.session_did()?;
+        if let Some(block) = self.find_list_block(list_uri).await? {
+            return Ok(CreateRecordOutput {
+                cid: block.cid,
+                uri: block.uri,
+            });
+        }
+        self.repo_create_record(
+            &own_did,
+            "app.bsky.graph.listblock",
+            ListBlock {
+                subject: list_uri.to_string(),
+                created_at: Datetime::now(),
+            },
+        )
+        .await
+    }
+
+    /// Delete the logged in user's listblock record for a list. Returns false if the list was
+    /// not blocked.
+    pub async fn bsky_unblock_list(&mut self, list_uri: &str) -> Result<bool, BiskyError> {
+        let Some(block) = self.find_list_block(list_uri).await? else {
+            return Ok(false);
+        };
+        self.delete_record_at(&block.uri.parse()?, "app.bsky.graph.listblock")
+            .await?;
+        Ok(true)
+    }
+
+    /// The listblock record for a list, through the list's viewer state or by scanning the
+    /// logged in user's listblocks
+    async fn find_list_block(
+        &mut self,
+        list_uri: &str,
+    ) -> Result<Option<Record<ListBlock>>, BiskyError> {
+        let own_did = self.session_did()?;
+        let blocked = self
+            .bsky_get_list(list_uri, 1, None)
+            .await?
+            .list
+            .viewer
+            .and_then(|v| v.blocked);
+
+        match blocked {
+            Some(uri) => {
+                let uri: AtUri = uri.parse()?;
+                self.repo_get_record(
+                    &uri.authority,
+                    "app.bsky.graph.listblock",
+                    uri.require_rkey()?,
+                )
+                .await
+                .map(Some)
+            }
+            None => {
+                self.find_subject_record::<ListBlock>(&own_did, "app.bsky.graph.listblock", |b| {
+                    b.subject == list_uri
+                })
+                .await
+            }
+        }
+    }
+
+    ///app.bsky.graph.list
+    pub async fn bsky_create_list(
+        &mut self,
+        name: &str,
+        purpose: ListPurpose,
+        description: Option<&str>,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.session_did()?;
+        self.repo_create_record(
+            &did,
+            "app.bsky.graph.list",
+            List {
+                purpose,
+                name: name.to_string(),
+                description: description.map(str::to_string),
+                avatar: None,
+                created_at: Datetime::now(),
+            },
+        )
+        .await
+    }
+
+    ///app.bsky.graph.listitem
+    /// Add an account to one of the logged in user's lists. If it is already on the list, the
+    /// existing list item is returned, or `BiskyError::AlreadyListed` if `error_if_listed`.
+    pub async fn bsky_add_to_list(
+        &mut self,
+        list_uri: &str,
+        did: &str,
+        error_if_listed: bool,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let own_did = self.session_did()?;
+        if let Some(item) = self
+            .find_subject_record::<ListItem>(&own_did, "app.bsky.graph.listitem", |i| {
+                i.list == list_uri && i.subject == did
+            })
+            .await?
+        {
+            if error_if_listed {
+                return Err(BiskyError::AlreadyListed {
+                    did: did.to_string(),
+                    list: list_uri.to_string(),
+                });
+            }
+            return Ok(CreateRecordOutput {
+                cid: item.cid,
+                uri: item.uri,
+            });
+        }
+
+        self.repo_create_record(
+            &own_did,
+            "app.bsky.graph.listitem",
+            ListItem {
+                subject: did.to_string(),
+                list: list_uri.to_string(),
+                created_at: Datetime::now(),
+            },
+        )
+        .await
+    }
+
+    /// Remove an account from one of the logged in user's lists, scanning their list items to
+    /// find it. Returns false if it was not on the list.
+    pub async fn bsky_remove_from_list(
+        &mut self,
+        list_uri: &str,
+        did: &str,
+    ) -> Result<bool, BiskyError> {
+        let own_did = self.session_did()?;
+        let Some(item) = self
+            .find_subject_record::<ListItem>(&own_did, "app.bsky.graph.listitem", |i| {
+                i.list == list_uri && i.subject == did
+            })
+            .await?
+        else {
+            return Ok(false);
+        };
+        self.delete_record_at(&item.uri.parse()?, "app.bsky.graph.listitem")
+            .await?;
+        Ok(true)
+    }
+
+    ///app.bsky.feed.repost
+    pub async fn bsky_repost(
+        &mut self,
+        uri: &str,
+        cid: &str,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.session_did()?;
+        let created = self
+            .repo_create_record(
+                &did,
+                "app.bsky.feed.repost",
+                Repost {
+                    created_at: Datetime::now(),
+                    subject: StrongRef::new(uri, cid)?,
+                },
+            )
+            .await?;
+        self.invalidate_post(uri);
+        Ok(created)
+    }
+
+    /// Delete a repost record by its own uri
+    pub async fn bsky_delete_repost(&mut self, repost_uri: &AtUri) -> Result<(), BiskyError> {
+        self.delete_record_at(repost_uri, "app.bsky.feed.repost")
+            .await
+    }
+
+    /// Remove the logged in user's repost of a post, scanning their reposts to find it.
+    /// Returns false if the post was not reposted.
+    pub async fn bsky_unrepost_post(&mut self, post_uri: &str) -> Result<bool, BiskyError> {
+        let did = self.session_did()?;
+        let post_uri: AtUri = post_uri.parse()?;
+        let Some(repost) = self
+            .find_subject_record::<Repost>(&did, "app.bsky.feed.repost", |r| {
+                r.subject.uri == post_uri
+            })
+            .await?
+        else {
+            return Ok(false);
+        };
+        self.bsky_delete_repost(&repost.uri.parse()?).await?;
+        self.invalidate_post(&post_uri.to_string());
+        Ok(true)
+    }
+
+    /// Delete one of the logged in user's posts, along with the threadgate and postgate records
+    /// that share its rkey
+    pub async fn bsky_delete_post(&mut self, uri: &AtUri) -> Result<(), BiskyError> {
+        let did = self.own_repo_did(uri)?;
+
+        self.delete_record_at(uri, "app.bsky.feed.post").await?;
+        self.invalidate_post(&uri.to_string());
+
+        let rkey = uri.require_rkey()?;
+        for collection in ["app.bsky.feed.threadgate", "app.bsky.feed.postgate"] {
+            match self.repo_delete_record(&did, collection, rkey).await {
+                Err(BiskyError::ApiError(e)) if e.kind() == ApiErrorKind::RecordNotFound => {}
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    ///app.bsky.feed.threadgate
+    /// Limit who can reply to one of the logged in user's posts. An empty `rules` means nobody
+    /// but the author can reply. The threadgate always shares the post's rkey.
+    pub async fn bsky_set_threadgate(
+        &mut self,
+        post_uri: &AtUri,
+        rules: &[ThreadgateRule],
+        hidden_replies: &[AtUri],
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.own_repo_did(post_uri)?;
+        if post_uri.require_collection()? != "app.bsky.feed.post" {
+            return Err(BiskyError::InvalidAtUri(format!(
+                "{post_uri} is not an app.bsky.feed.post record"
+            )));
+        }
+
+        self.repo_put_record(
+            &did,
+            "app.bsky.feed.threadgate",
+            post_uri.require_rkey()?,
+            Threadgate {
+                post: post_uri.to_string(),
+                allow: Some(rules.to_vec()),
+                created_at: Datetime::now(),
+                hidden_replies: hidden_replies.iter().map(AtUri::to_string).collect(),
+            },
+            None,
+        )
+        .await
+    }
+
+    /// Remove the threadgate from one of the logged in user's posts
+    pub async fn bsky_clear_threadgate(&mut self, post_uri: &AtUri) -> Result<(), BiskyError> {
+        let did = self.own_repo_did(post_uri)?;
+        self.repo_delete_record(&did, "app.bsky.feed.threadgate", post_uri.require_rkey()?)
+            .await
+    }
+
+    ///app.bsky.feed.postgate
+    /// Detach one of the logged in user's posts from a post quoting it
+    pub async fn bsky_detach_quote(
+        &mut self,
+        my_post_uri: &AtUri,
+        quoting_post_uri: &AtUri,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let quoting_post_uri = quoting_post_uri.to_string();
+        self.update_postgate(my_post_uri, |postgate| {
+            if !postgate.detached_embedding_uris.contains(&quoting_post_uri) {
+                postgate.detached_embedding_uris.push(quoting_post_uri);
+            }
+        })
+        .await
+    }
+
+    /// Stop anyone from quoting one of the logged in user's posts
+    pub async fn bsky_disable_embedding(
+        &mut self,
+        post_uri: &AtUri,
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        self.update_postgate(post_uri, |postgate| {
+            postgate.embedding_rules = vec![PostgateEmbeddingRule::Disable];
+        })
+        .await
+    }
+
+    /// Read-modify-write the postgate of a post. The postgate always shares the post's rkey, and
+    /// is swapped against the cid we read so concurrent changes aren't lost.
+    async fn update_postgate(
+        &mut self,
+        post_uri: &AtUri,
+        update: impl FnOnce(&mut Postgate),
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.own_repo_did(post_uri)?;
+        if post_uri.require_collection()? != "app.bsky.feed.post" {
+            return Err(BiskyError::InvalidAtUri(format!(
+                "{post_uri} is not an app.bsky.feed.post record"
+            )));
+        }
+        let rkey = post_uri.require_rkey()?;
+
+        let (mut postgate, swap) = match self
+            .repo_get_record::<Postgate>(&did, "app.bsky.feed.postgate", rkey)
+            .await
+        {
+            Ok(record) => (record.value, Some(record.cid)),
+            Err(BiskyError::ApiError(e)) if e.kind() == ApiErrorKind::RecordNotFound => (
+                Postgate {
+                    post: post_uri.to_string(),
+                    created_at: Datetime::now(),
+                    detached_embedding_uris: Vec::new(),
+                    embedding_rules: Vec::new(),
+                },
+                None,
+            ),
+            Err(e) => return Err(e),
+        };
+
+        update(&mut postgate);
+
+        self.repo_put_record(
+            &did,
+            "app.bsky.feed.postgate",
+            rkey,
+            postgate,
+            swap.as_deref(),
+        )
+        .await
+    }
+
+    /// Read, modify and write back the logged in user's profile, creating it if there is none.
+    /// New avatars and banners are uploaded before the write. The write only succeeds if the
+    /// profile was not changed in the meantime, otherwise the error is an `InvalidSwap` ApiError.
+    pub async fn bsky_update_profile(
+        &mut self,
+        update: impl FnOnce(&mut ProfileUpdate),
+    ) -> Result<CreateRecordOutput, BiskyError> {
+        let did = self.session_did()?;
+
+        let (profile, swap) = match self.repo_get_profile_record(&did).await {
+            Ok(record) => (record.value, Some(record.cid)),
+            Err(BiskyError::ApiError(e)) if e.kind() == ApiErrorKind::RecordNotFound => (
+                Profile {
+                    rust_type: Some("app.bsky.actor.profile".to_string()),
+                    ..Default::default()
+                },
+                None,
+            ),
+            Err(e) => return Err(e),
+        };
+
+        let mut edit = ProfileUpdate::new(profile);
+        update(&mut edit);
+
+        let avatar = edit.avatar.take();
+        let banner = edit.banner.take();
+        let mut profile = edit.into_profile();
+        if let Some((bytes, mime_type)) = avatar {
+            let output: BlobOutput = self.repo_upload_blob(&bytes, &mime_type).await?;
+            profile.avatar = Some(output.blob);
+        }
+        if let Some((bytes, mime_type)) = banner {
+            let output: BlobOutput = self.repo_upload_blob(&bytes, &mime_type).await?;
+            profile.banner = Some(output.blob);
+        }
+
+        let written = self
+            .repo_put_record(
+                &did,
+                "app.bsky.actor.profile",
+                "self",
+                profile,
+                swap.as_deref(),
+            )
+            .await?;
+        self.invalidate_actor(&did);
+        Ok(written)
+    }
+}
@@ -0,0 +1,894 @@
+//! Bulk record writes that batch through applyWrites and pace themselves under the PDS write
+//! rate limit.
+
+use crate::atproto::{is_auth_error, Client, RateLimitInfo, WriteCosts};
+use crate::bsky::BskyClient;
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::graph::{Follow, ListItem, RelationshipEnum};
+use crate::lexicon::com::atproto::label::Label;
+use crate::lexicon::com::atproto::repo::{
+    CreateRecordOutput, Record, WriteOp, WriteResult, MAX_APPLY_WRITES,
+};
+use crate::types::{AtUri, Datetime};
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How a bulk write spends the write rate limit
+#[derive(Debug, Clone)]
+pub struct BulkPolicy {
+    /// The budget the writes draw on. Clones of a policy share it, so bulk writes running at
+    /// the same time with clones of one policy stay under the limit together.
+    pub budget: Arc<RateBudget>,
+    /// Writes per applyWrites call, at most 200
+    pub batch_size: usize,
+}
+
+impl Default for BulkPolicy {
+    fn default() -> Self {
+        Self {
+            budget: Arc::new(RateBudget::default()),
+            batch_size: 50,
+        }
+    }
+}
+
+/// What happened to one DID of a bulk write
+#[derive(Debug)]
+pub enum BulkOutcome {
+    Created(CreateRecordOutput),
+    Deleted,
+    /// Nothing to do, e.g. already followed or not followed
+    Skipped,
+    Failed(BiskyError),
+}
+
+/// Which followers `BskyClient::bsky_follow_back` follows, and how
+#[derive(Debug, Clone, Default)]
+pub struct FollowBackOptions {
+    /// Skip accounts created less recently than this, or that don't say when they were created
+    pub min_account_age: Option<Duration>,
+    /// Skip accounts with any of these labels, e.g. `spam`
+    pub skip_labels: Vec<String>,
+    /// Report who would be followed without following anyone
+    pub dry_run: bool,
+    pub policy: BulkPolicy,
+}
+
+/// What `BskyClient::bsky_follow_back` did, by DID. Accounts followed before the call, in an
+/// earlier run or otherwise, are left out.
+#[derive(Debug, Default)]
+pub struct FollowBackReport {
+    /// Newly followed, or on a dry run, those that would have been
+    pub followed: Vec<String>,
+    /// Filtered out, or found to be followed already
+    pub skipped: Vec<String>,
+    pub errored: Vec<(String, BiskyError)>,
+}
+
+/// How `BskyClient::bsky_sync_list` brings a list in line
+#[derive(Debug, Clone, Default)]
+pub struct ListSyncOptions {
+    /// Report what would change without writing anything
+    pub dry_run: bool,
+    pub policy: BulkPolicy,
+}
+
+/// What `BskyClient::bsky_sync_list` did, by DID, or on a dry run, what it would have done
+#[derive(Debug, Default)]
+pub struct ListSyncReport {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// DIDs listed more than once, whose extra list items were deleted. A DID that was also
+    /// removed is only in `removed`.
+    pub deduplicated: Vec<String>,
+    /// How many of the desired DIDs were already listed
+    pub unchanged: usize,
+    pub errored: Vec<(String, BiskyError)>,
+}
+
+/// What `Client::repo_purge_collection` did
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    /// Records the filter was run on
+    pub scanned: usize,
+    /// The uris of the records deleted, or on a dry run, those that would have been
+    pub deleted: Vec<String>,
+    pub failed: Vec<(String, BiskyError)>,
+}
+
+/// Write points to spend against the PDS write rate limits, which allow 5000 points an hour and
+/// 35000 a day by default. Each window is a token bucket that refills evenly over it, and is
+/// brought down to what the PDS reports is left whenever a response says so, or back up when
+/// the PDS has started a new window.
+///
+/// Drawing more than is left waits for the points rather than failing. One budget can be shared
+/// by any number of bulk writes, in `BulkPolicy`, and by the caller's own writes.
+#[derive(Debug)]
+pub struct RateBudget {
+    costs: WriteCosts,
+    buckets: Mutex<[Bucket; 2]>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    /// At least an hour
+    window: Duration,
+    points: f64,
+    last: Instant,
+    /// When the window the PDS last reported resets, as a unix timestamp
+    reset: Option<i64>,
+}
+
+impl Default for RateBudget {
+    fn default() -> Self {
+        Self::new(5000, 35000)
+    }
+}
+
+impl RateBudget {
+    pub fn new(points_per_hour: u32, points_per_day: u32) -> Self {
+        Self {
+            costs: WriteCosts::default(),
+            buckets: Mutex::new([
+                Bucket::new(points_per_hour, Duration::from_secs(60 * 60)),
+                Bucket::new(points_per_day, Duration::from_secs(24 * 60 * 60)),
+            ]),
+        }
+    }
+
+    /// Price writes at `costs` instead of the PDS's 3 for a create, 2 for an update and 1 for
+    /// a delete
+    pub fn with_costs(mut self, costs: WriteCosts) -> Self {
+        self.costs = costs;
+        self
+    }
+
+    pub fn costs(&self) -> WriteCosts {
+        self.costs
+    }
+
+    /// The points `ops` cost
+    pub fn cost(&self, ops: &[WriteOp]) -> u32 {
+        ops.iter().map(|op| self.costs.cost(op)).sum()
+    }
+
+    /// The points that can be drawn now without waiting
+    pub fn available(&self) -> u32 {
+        let mut buckets = self.buckets.lock();
+        buckets
+            .iter_mut()
+            .map(|bucket| {
+                bucket.refill();
+                bucket.points
+            })
+            .fold(f64::INFINITY, f64::min) as u32
+    }
+
+    /// Wait until `points` are left in every window, then spend them
+    pub async fn acquire(&self, points: u32) {
+        let points = f64::from(points);
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock();
+                let wait = buckets
+                    .iter_mut()
+                    .map(|bucket| bucket.wait_for(points))
+                    .max()
+                    .unwrap_or_default();
+                if wait.is_zero() {
+                    for bucket in buckets.iter_mut() {
+                        bucket.points -= points;
+                    }
+                    return;
+                }
+                wait
+            };
+            // Another draw may take the points first, so look again after waiting
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take in the rate limit a write response reported. Limits with a window shorter than an
+    /// hour, like the global one reads report, say nothing about writes and are ignored, and one
+    /// without a policy is taken to be hourly.
+    pub fn observe(&self, rate_limit: &RateLimitInfo) {
+        let window = rate_limit
+            .policy
+            .map_or(Duration::from_secs(60 * 60), |policy| policy.window);
+        let mut buckets = self.buckets.lock();
+        let Some(bucket) = buckets
+            .iter_mut()
+            .rev()
+            .find(|bucket| window >= bucket.window)
+        else {
+            return;
+        };
+        bucket.refill();
+        let remaining = f64::from(rate_limit.remaining).min(bucket.capacity);
+        if bucket.reset.is_some_and(|reset| reset < rate_limit.reset) {
+            // A new window, which the older responses of other draws can't speak for
+            bucket.points = remaining;
+        } else {
+            bucket.points = bucket.points.min(remaining);
+        }
+        bucket.reset = Some(
+            bucket
+                .reset
+                .map_or(rate_limit.reset, |reset| reset.max(rate_limit.reset)),
+        );
+    }
+}
+
+impl Bucket {
+    fn new(capacity: u32, window: Duration) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            window,
+            points: f64::from(capacity),
+            last: Instant::now(),
+            reset: None,
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let per_second = self.capacity / self.window.as_secs_f64();
+        self.points = (self.points + now.duration_since(self.last).as_secs_f64() * per_second)
+            .min(self.capacity);
+        self.last = now;
+        if self
+            .reset
+            .is_some_and(|reset| reset <= Utc::now().timestamp())
+        {
+            self.points = self.capacity;
+            self.reset = None;
+        }
+    }
+
+    /// How long until `points` are left, which is never longer than until the PDS resets the
+    /// window
+    fn wait_for(&mut self, points: f64) -> Duration {
+        self.refill();
+        if self.points >= points {
+            return Duration::ZERO;
+        }
+        let per_second = self.capacity / self.window.as_secs_f64();
+        let refilled = Duration::from_secs_f64((points - self.points) / per_second);
+        match self.reset {
+            Some(reset) => {
+                let until_reset = (reset - Utc::now().timestamp()).max(1) as u64;
+                refilled.min(Duration::from_secs(until_reset))
+            }
+            None => refilled,
+        }
+    }
+}
+
+impl BskyClient {
+    /// Follow every DID in `dids` that the logged in user doesn't already follow
+    pub async fn bsky_bulk_follow(
+        &mut self,
+        dids: &[&str],
+        policy: &BulkPolicy,
+    ) -> Result<Vec<(String, BulkOutcome)>, BiskyError> {
+        let own_did = self.session_did()?;
+        let following = self.following_uris(&own_did, dids).await?;
+
+        let mut writes = Vec::new();
+        let mut skipped = Vec::new();
+        for did in dids {
+            if following.iter().any(|(d, _)| d == did) {
+                skipped.push(did.to_string());
+                continue;
+            }
+            let follow = Follow {
+                created_at: Datetime::now(),
+                subject: did.to_string(),
+            };
+            writes.push((
+                did.to_string(),
+                WriteOp::create("app.bsky.graph.follow", &follow)?,
+            ));
+        }
+
+        let mut report = self.bulk_apply(&own_did, writes, policy).await?;
+        report.extend(skipped.into_iter().map(|did| (did, BulkOutcome::Skipped)));
+        Ok(report)
+    }
+
+    /// Unfollow every DID in `dids` that the logged in user follows
+    pub async fn bsky_bulk_unfollow(
+        &mut self,
+        dids: &[&str],
+        policy: &BulkPolicy,
+    ) -> Result<Vec<(String, BulkOutcome)>, BiskyError> {
+        let own_did = self.session_did()?;
+        let following = self.following_uris(&own_did, dids).await?;
+
+        let mut writes = Vec::new();
+        let mut report = Vec::new();
+        for did in dids {
+            match following.iter().find(|(d, _)| d == did) {
+                Some((_, uri)) => {
+                    let uri: AtUri = uri.parse()?;
+                    writes.push((
+                        did.to_string(),
+                        WriteOp::delete("app.bsky.graph.follow", uri.require_rkey()?),
+                    ));
+                }
+                None => report.push((did.to_string(), BulkOutcome::Skipped)),
+            }
+        }
+
+        let mut applied = self.bulk_apply(&own_did, writes, policy).await?;
+        applied.append(&mut report);
+        Ok(applied)
+    }
+
+    /// Add every DID in `dids` that is not already on one of the logged in user's lists
+    pub async fn bsky_bulk_add_to_list(
+        &mut self,
+        list_uri: &str,
+        dids: &[&str],
+        policy: &BulkPolicy,
+    ) -> Result<Vec<(String, BulkOutcome)>, BiskyError> {
+        let own_did = self.session_did()?;
+
+        let mut listed = HashSet::new();
+        let mut cursor = None;
+        loop {
+            let (records, next_cursor) = self
+                .repo_list_records::<ListItem>(
+                    &own_did,
+                    "app.bsky.graph.listitem",
+                    100,
+                    false,
+                    cursor,
+                )
+                .await?;
+            let done = records.is_empty() || next_cursor.is_none();
+            listed.extend(
+                records
+                    .into_iter()
+                    .filter(|r| r.value.list == list_uri)
+                    .map(|r| r.value.subject),
+            );
+            if done {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        let mut writes = Vec::new();
+        let mut skipped = Vec::new();
+        for did in dids {
+            if listed.contains(*did) {
+                skipped.push(did.to_string());
+                continue;
+            }
+            let item = ListItem {
+                subject: did.to_string(),
+                list: list_uri.to_string(),
+                created_at: Datetime::now(),
+            };
+            writes.push((
+                did.to_string(),
+                WriteOp::create("app.bsky.graph.listitem", &item)?,
+            ));
+        }
+
+        let mut report = self.bulk_apply(&own_did, writes, policy).await?;
+        report.extend(skipped.into_iter().map(|did| (did, BulkOutcome::Skipped)));
+        Ok(report)
+    }
+
+    /// Make the members of `list_uri`, one of the logged in user's lists, exactly `desired`:
+    /// add the DIDs that are missing and delete the list items of the rest, along with all but
+    /// the oldest item of a DID listed more than once. The writes go out in applyWrites batches
+    /// paced by the options' policy.
+    ///
+    /// The members are read from the list item records in the repo rather than from getList, so
+    /// that items the AppView hasn't caught up with are counted and a sync run right after
+    /// another changes nothing.
+    pub async fn bsky_sync_list(
+        &mut self,
+        list_uri: &str,
+        desired: &HashSet<String>,
+        options: &ListSyncOptions,
+    ) -> Result<ListSyncReport, BiskyError> {
+        let own_did = self.session_did()?;
+        if list_uri.parse::<AtUri>()?.authority != own_did {
+            return Err(BiskyError::NotOwnRecord(list_uri.to_string()));
+        }
+
+        // The rkeys of each member's items. Record keys are TIDs, so the oldest sorts first.
+        let mut members = HashMap::<String, Vec<String>>::new();
+        let mut cursor = None;
+        loop {
+            let (records, next_cursor) = self
+                .repo_list_records::<ListItem>(
+                    &own_did,
+                    "app.bsky.graph.listitem",
+                    100,
+                    false,
+                    cursor,
+                )
+                .await?;
+            let done = records.is_empty() || next_cursor.is_none();
+            for record in records {
+                if record.value.list != list_uri {
+                    continue;
+                }
+                let uri = record.uri.parse::<AtUri>()?;
+                members
+                    .entry(record.value.subject)
+                    .or_default()
+                    .push(uri.require_rkey()?.to_string());
+            }
+            if done {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        let mut missing = desired
+            .iter()
+            .filter(|did| !members.contains_key(*did))
+            .collect::<Vec<_>>();
+        missing.sort();
+
+        let mut report = ListSyncReport::default();
+        let mut writes = Vec::new();
+        let mut members = members.into_iter().collect::<Vec<_>>();
+        members.sort();
+        for (did, mut rkeys) in members {
+            rkeys.sort();
+            let extra = match desired.contains(&did) {
+                true => {
+                    report.unchanged += 1;
+                    if rkeys.len() > 1 {
+                        report.deduplicated.push(did.clone());
+                    }
+                    &rkeys[1..]
+                }
+                false => {
+                    report.removed.push(did.clone());
+                    &rkeys[..]
+                }
+            };
+            for rkey in extra {
+                writes.push((
+                    did.clone(),
+                    WriteOp::delete("app.bsky.graph.listitem", rkey),
+                ));
+            }
+        }
+
+        for did in missing {
+            let item = ListItem {
+                subject: did.clone(),
+                list: list_uri.to_string(),
+                created_at: Datetime::now(),
+            };
+            report.added.push(did.clone());
+            writes.push((
+                did.clone(),
+                WriteOp::create("app.bsky.graph.listitem", &item)?,
+            ));
+        }
+
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        let mut failed = HashSet::new();
+        for (did, outcome) in self.bulk_apply(&own_did, writes, &options.policy).await? {
+            if let BulkOutcome::Failed(e) = outcome {
+                if failed.insert(did.clone()) {
+                    report.errored.push((did, e));
+                }
+            }
+        }
+        for dids in [
+            &mut report.added,
+            &mut report.removed,
+            &mut report.deduplicated,
+        ] {
+            dids.retain(|did| !failed.contains(did));
+        }
+        Ok(report)
+    }
+
+    /// Follow every follower of the logged in user that the user doesn't follow and that passes
+    /// the filters of `options`.
+    ///
+    /// Who the user follows is read from the follow records in their repo rather than from the
+    /// AppView, which may not have caught up with recent follows, so a run that is interrupted
+    /// can be run again without following anyone twice.
+    pub async fn bsky_follow_back(
+        &mut self,
+        options: &FollowBackOptions,
+    ) -> Result<FollowBackReport, BiskyError> {
+        let own_did = self.session_did()?;
+
+        let mut following = HashSet::new();
+        let mut cursor = None;
+        loop {
+            let (records, next_cursor) = self
+                .repo_list_records::<Follow>(&own_did, "app.bsky.graph.follow", 100, false, cursor)
+                .await?;
+            let done = records.is_empty() || next_cursor.is_none();
+            following.extend(records.into_iter().map(|r| r.value.subject));
+            if done {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        let mut followers = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = self
+                .bsky_get_followers_page(&own_did, 100, cursor.as_deref())
+                .await?;
+            let done = page.followers.is_empty() || page.cursor.is_none();
+            followers.extend(page.followers);
+            if done {
+                break;
+            }
+            cursor = page.cursor;
+        }
+
+        let mut report = FollowBackReport::default();
+        let mut seen = HashSet::new();
+        let mut candidates = Vec::new();
+        for follower in followers {
+            if following.contains(&follower.did) || !seen.insert(follower.did.clone()) {
+                continue;
+            }
+            if has_label(&follower.labels, &options.skip_labels) {
+                report.skipped.push(follower.did);
+            } else {
+                candidates.push(follower.did);
+            }
+        }
+
+        if let Some(min_age) = options.min_account_age {
+            let dids = candidates.iter().map(String::as_str).collect::<Vec<_>>();
+            let profiles = self.bsky_get_profiles(&dids).await?;
+            let now = Utc::now();
+            let (old_enough, too_new) = candidates
+                .into_iter()
+                .zip(profiles)
+                .partition::<Vec<_>, _>(|(_, profile)| {
+                    profile.as_ref().is_some_and(|profile| {
+                        !has_label(&profile.labels, &options.skip_labels)
+                            && profile.created_at.is_some_and(|created_at| {
+                                (now - *created_at).to_std().is_ok_and(|age| age >= min_age)
+                            })
+                    })
+                });
+            candidates = old_enough.into_iter().map(|(did, _)| did).collect();
+            report
+                .skipped
+                .extend(too_new.into_iter().map(|(did, _)| did));
+        }
+
+        if options.dry_run {
+            report.followed = candidates;
+            return Ok(report);
+        }
+
+        let dids = candidates.iter().map(String::as_str).collect::<Vec<_>>();
+        for (did, outcome) in self.bsky_bulk_follow(&dids, &options.policy).await? {
+            match outcome {
+                BulkOutcome::Created(_) => report.followed.push(did),
+                BulkOutcome::Deleted | BulkOutcome::Skipped => report.skipped.push(did),
+                BulkOutcome::Failed(e) => report.errored.push((did, e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// The (DID, follow uri) pairs of the accounts in `dids` that `own_did` follows
+    async fn following_uris(
+        &mut self,
+        own_did: &str,
+        dids: &[&str],
+    ) -> Result<Vec<(String, String)>, BiskyError> {
+        Ok(self
+            .bsky_get_relationships(own_did, dids)
+            .await?
+            .into_iter()
+            .filter_map(|r| match r {
+                RelationshipEnum::Relationship(r) => r.following.map(|uri| (r.did, uri)),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+impl Client {
+    /// Delete every record of the logged in user's `collection` that `filter` accepts, e.g.
+    /// likes older than a date, in applyWrites calls paced by `policy`. Deletes are the cheapest
+    /// writes, so a batch size of `MAX_APPLY_WRITES` makes sense here. A dry run lists what
+    /// would be deleted without writing anything.
+    pub async fn repo_purge_collection(
+        &mut self,
+        collection: &str,
+        filter: impl Fn(&Record<Value>) -> bool,
+        policy: &BulkPolicy,
+        dry_run: bool,
+    ) -> Result<PurgeReport, BiskyError> {
+        let own_did = self.session_did()?;
+        let mut report = PurgeReport::default();
+
+        let mut matched = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (records, next_cursor) = self
+                .repo_list_records::<Value>(&own_did, collection, 100, false, cursor)
+                .await?;
+            let done = records.is_empty() || next_cursor.is_none();
+            report.scanned += records.len();
+            matched.extend(records.into_iter().filter(&filter).map(|r| r.uri));
+            if done {
+                break;
+            }
+            cursor = next_cursor;
+        }
+
+        if dry_run {
+            report.deleted = matched;
+            return Ok(report);
+        }
+
+        let mut writes = Vec::with_capacity(matched.len());
+        for uri in matched {
+            let rkey = uri.parse::<AtUri>()?.require_rkey()?.to_string();
+            writes.push((uri, WriteOp::delete(collection, &rkey)));
+        }
+        for (uri, outcome) in self.bulk_apply(&own_did, writes, policy).await? {
+            match outcome {
+                BulkOutcome::Failed(e) => report.failed.push((uri, e)),
+                _ => report.deleted.push(uri),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Apply `writes` in batches, paced by `policy`. A batch the PDS turns down as invalid is
+    /// retried one write at a time so that a single bad write only fails itself. Any other
+    /// failure, such as a timeout, a rate limit or an expired session, ends the bulk write with
+    /// its error, since the batch may have been written or the writes after it would fail too.
+    async fn bulk_apply(
+        &mut self,
+        repo: &str,
+        writes: Vec<(String, WriteOp)>,
+        policy: &BulkPolicy,
+    ) -> Result<Vec<(String, BulkOutcome)>, BiskyError> {
+        let budget = &policy.budget;
+        let batch_size = policy.batch_size.clamp(1, MAX_APPLY_WRITES);
+        let mut report = Vec::with_capacity(writes.len());
+
+        for batch in writes.chunks(batch_size) {
+            let ops = batch.iter().map(|(_, op)| op.clone()).collect::<Vec<_>>();
+            budget.acquire(budget.cost(&ops)).await;
+
+            let result = self.repo_apply_writes(repo, &ops).await;
+            self.observe_budget(budget);
+            match result {
+                Ok(output) => {
+                    let mut results = output.results.into_iter();
+                    for (did, op) in batch {
+                        report.push((did.clone(), outcome(op, results.next())));
+                    }
+                }
+                // applyWrites is atomic, so nothing in a rejected batch was written. Retried
+                // points are not refunded, which keeps the budget on the safe side.
+                Err(e) if is_rejected(&e) => {
+                    for (did, op) in batch {
+                        let op = std::slice::from_ref(op);
+                        budget.acquire(budget.cost(op)).await;
+                        let result = self.repo_apply_writes(repo, op).await;
+                        self.observe_budget(budget);
+                        let outcome = match result {
+                            Ok(output) => outcome(&op[0], output.results.into_iter().next()),
+                            Err(e) => BulkOutcome::Failed(e),
+                        };
+                        report.push((did.clone(), outcome));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Tell `budget` the rate limit of the last write, if the PDS reported one
+    fn observe_budget(&self, budget: &RateBudget) {
+        if let Some(rate_limit) = self.last_rate_limit() {
+            budget.observe(&rate_limit);
+        }
+    }
+}
+
+fn has_label(labels: &[Label], names: &[String]) -> bool {
+    labels
+        .iter()
+        .any(|label| !label.neg && names.iter().any(|name| name == label.val.as_str()))
+}
+
+/// Whether the PDS refused the writes as they were, such as for an invalid record, rather than
+/// failing to take them or not letting the user write
+fn is_rejected(e: &BiskyError) -> bool {
+    match e {
+        BiskyError::ApiError(error) => {
+            error.status().is_some_and(|status| {
+                (400..500).contains(&status) && !matches!(status, 401 | 403 | 429)
+            }) && !is_auth_error(e)
+        }
+        _ => false,
+    }
+}
+
+/// Some PDS versions leave the results out, which is only expected for deletes
+fn outcome(op: &WriteOp, result: Option<WriteResult>) -> BulkOutcome {
+    match result {
+        Some(WriteResult::Create { uri, cid } | WriteResult::Update { uri, cid }) => {
+            BulkOutcome::Created(CreateRecordOutput { cid, uri })
+        }
+        Some(WriteResult::Delete {}) => BulkOutcome::Deleted,
+        Some(WriteResult::Unknown(value)) => {
+            BulkOutcome::Failed(BiskyError::UnexpectedResponse(value.to_string()))
+        }
+        None => match op {
+            WriteOp::Delete { .. } => BulkOutcome::Deleted,
+            _ => BulkOutcome::Failed(BiskyError::UnexpectedResponse(
+                "applyWrites returned no result".to_string(),
+            )),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atproto::RateLimitPolicy;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// What a write response with `ratelimit-*` headers reports
+    fn rate_limit(remaining: u32, reset_in: i64, window: Option<Duration>) -> RateLimitInfo {
+        RateLimitInfo {
+            limit: 5000,
+            remaining,
+            reset: Utc::now().timestamp() + reset_in,
+            policy: window.map(|window| RateLimitPolicy {
+                limit: 5000,
+                window,
+            }),
+        }
+    }
+
+    #[test]
+    fn costs() {
+        let ops = [
+            WriteOp::create("app.bsky.feed.like", &serde_json::json!({})).unwrap(),
+            WriteOp::Update {
+                collection: "app.bsky.actor.profile".to_string(),
+                rkey: "self".to_string(),
+                value: serde_json::json!({}),
+            },
+            WriteOp::delete("app.bsky.feed.like", "3kdfsvlcf5c2a"),
+        ];
+        let budget = RateBudget::default();
+        assert_eq!(budget.cost(&ops), 3 + 2 + 1);
+        assert_eq!(budget.cost(&ops[2..]), 1);
+
+        let budget = RateBudget::default().with_costs(WriteCosts {
+            create: 10,
+            update: 5,
+            delete: 0,
+        });
+        assert_eq!(budget.cost(&ops), 15);
+    }
+
+    #[test]
+    fn header_driven_refills() {
+        let budget = RateBudget::new(5000, 35000);
+        assert_eq!(budget.available(), 5000);
+
+        // Brought down to what the PDS says is left, in the hourly window by default
+        budget.observe(&rate_limit(100, 600, None));
+        assert_eq!(budget.available(), 100);
+        // An older response from the same window can't raise it
+        budget.observe(&rate_limit(300, 600, Some(HOUR)));
+        assert_eq!(budget.available(), 100);
+        // A new window can
+        budget.observe(&rate_limit(4000, 4200, Some(HOUR)));
+        assert_eq!(budget.available(), 4000);
+        // Never above the budget's own capacity
+        budget.observe(&rate_limit(9000, 7800, Some(HOUR)));
+        assert_eq!(budget.available(), 5000);
+
+        // The daily window is its own bucket
+        budget.observe(&rate_limit(50, 60_000, Some(DAY)));
+        assert_eq!(budget.available(), 50);
+
+        // Reads report a shorter window, which says nothing about writes
+        let budget = RateBudget::new(5000, 35000);
+        budget.observe(&rate_limit(0, 60, Some(Duration::from_secs(5 * 60))));
+        assert_eq!(budget.available(), 5000);
+
+        // Full again once the window the PDS reported has reset
+        budget.observe(&rate_limit(0, -1, None));
+        assert_eq!(budget.available(), 5000);
+    }
+
+    #[tokio::test]
+    async fn exhausted_budget_waits() {
+        // 10 points a second
+        let budget = RateBudget::new(36_000, 1_000_000);
+        budget.acquire(36_000).await;
+        assert_eq!(budget.available(), 0);
+
+        let started = Instant::now();
+        budget.acquire(5).await;
+        let waited = started.elapsed();
+        assert!(
+            waited >= Duration::from_millis(400) && waited < Duration::from_secs(5),
+            "{waited:?}"
+        );
+
+        // No longer than until the PDS resets the window, at a second's resolution
+        let budget = RateBudget::new(3600, 35000);
+        budget.observe(&rate_limit(0, 1, None));
+        let started = Instant::now();
+        budget.acquire(1000).await;
+        let waited = started.elapsed();
+        assert!(
+            waited >= Duration::from_millis(900) && waited < Duration::from_secs(5),
+            "{waited:?}"
+        );
+        assert!(budget.available() >= 3600 - 1000);
+    }
+
+    #[tokio::test]
+    async fn concurrent_drawdown() {
+        // 10000 points a second, with 1000 left
+        let budget = Arc::new(RateBudget::new(36_000_000, u32::MAX));
+        budget.acquire(36_000_000 - 1000).await;
+
+        let started = Instant::now();
+        let draws = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                tokio::spawn(async move {
+                    budget.acquire(250).await;
+                    started.elapsed()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut waited = Vec::new();
+        for draw in draws {
+            waited.push(draw.await.unwrap());
+        }
+
+        // Only what was left is handed out at once, then 250 points every 25ms as it refills
+        waited.sort();
+        assert!(waited[3] < Duration::from_millis(20), "{waited:?}");
+        for (i, waited) in waited[4..].iter().enumerate() {
+            let refilled = Duration::from_millis(25 * (i as u64 + 1));
+            assert!(*waited >= refilled - Duration::from_millis(5), "{waited:?}");
+        }
+        assert!(budget.available() < 1000);
+    }
+}
@@ -0,0 +1,330 @@
+//! An in-memory read-through cache for the profiles and posts a `BskyClient` looks up. Set one
+//! with `BskyClient::with_cache`; clones of the client share it. Entries are kept as the JSON
+//! the server sent and deserialized on every hit.
+
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::actor::ProfileViewDetailed;
+use crate::lexicon::app::bsky::feed::PostView;
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How much a `BskyCache` holds and for how long
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Profiles kept at most, the least recently used going first
+    pub profiles: usize,
+    /// Posts kept at most, the least recently used going first
+    pub posts: usize,
+    /// How long an entry is used before it is fetched again
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            profiles: 1000,
+            posts: 1000,
+            ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// How often lookups were answered from the cache, counting each actor or uri asked for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub profile_hits: u64,
+    pub profile_misses: u64,
+    pub post_hits: u64,
+    pub post_misses: u64,
+}
+
+/// Profiles keyed by the DID or handle they were asked for, and posts keyed by uri.
+///
+/// Concurrent misses for the same key are fetched once. Entries are dropped when the client
+/// they are shared with makes a write that changes them, such as following an account or
+/// liking a post, but changes made elsewhere only show once the entry expires.
+pub struct BskyCache {
+    ttl: Duration,
+    profiles: Table,
+    posts: Table,
+}
+
+impl BskyCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            ttl: config.ttl,
+            profiles: Table::new(config.profiles),
+            posts: Table::new(config.posts),
+        }
+    }
+
+    /// Forget the profile of `actor`, a DID or handle, however it was looked up
+    pub fn invalidate_actor(&self, actor: &str) {
+        let key = actor_key(actor);
+        self.profiles
+            .entries
+            .lock()
+            .retain(|k, profile| k != key && !is_profile_of(profile, &key));
+    }
+
+    /// Forget the post at `uri`
+    pub fn invalidate_post(&self, uri: &str) {
+        self.posts.entries.lock().remove(uri);
+    }
+
+    /// Forget everything, e.g. after switching accounts, since views carry the viewer's state
+    pub fn clear(&self) {
+        self.profiles.entries.lock().clear();
+        self.posts.entries.lock().clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            profile_hits: self.profiles.hits.load(Ordering::Relaxed),
+            profile_misses: self.profiles.misses.load(Ordering::Relaxed),
+            post_hits: self.posts.hits.load(Ordering::Relaxed),
+            post_misses: self.posts.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The profiles of `actors`, with `load` fetching those not cached. `load` gets the actors
+    /// it has to fetch, with handles in lowercase, and returns the profiles it found, each with
+    /// the actor it is for.
+    pub(crate) async fn profiles<F, Fut>(
+        &self,
+        actors: &[&str],
+        load: F,
+    ) -> Result<Vec<Option<ProfileViewDetailed>>, BiskyError>
+    where
+        F: FnOnce(Vec<String>) -> Fut,
+        Fut: Future<Output = Result<Vec<(String, Value)>, BiskyError>>,
+    {
+        let keys = actors.iter().map(|actor| actor_key(actor)).collect();
+        self.profiles.get(keys, self.ttl, load).await
+    }
+
+    /// The posts at `uris`, with `load` fetching those not cached, as for `profiles`
+    pub(crate) async fn posts<F, Fut>(
+        &self,
+        uris: &[&str],
+        load: F,
+    ) -> Result<Vec<Option<PostView>>, BiskyError>
+    where
+        F: FnOnce(Vec<String>) -> Fut,
+        Fut: Future<Output = Result<Vec<(String, Value)>, BiskyError>>,
+    {
+        let keys = uris.iter().map(|uri| uri.to_string()).collect();
+        self.posts.get(keys, self.ttl, load).await
+    }
+}
+
+/// Handles are case insensitive, DIDs aren't
+fn actor_key(actor: &str) -> String {
+    if actor.starts_with("did:") {
+        actor.to_string()
+    } else {
+        actor.to_ascii_lowercase()
+    }
+}
+
+/// Whether `profile` is that of `actor`, a DID or lowercase handle
+pub(crate) fn is_profile_of(profile: &Value, actor: &str) -> bool {
+    profile["did"] == actor
+        || profile["handle"]
+            .as_str()
+            .is_some_and(|handle| handle.eq_ignore_ascii_case(actor))
+}
+
+struct Table {
+    entries: Mutex<Lru<Value>>,
+    /// Held while a key is fetched, so that others missing it wait for that fetch
+    loading: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Table {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Lru::new(capacity)),
+            loading: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    async fn get<V, F, Fut>(
+        &self,
+        keys: Vec<String>,
+        ttl: Duration,
+        load: F,
+    ) -> Result<Vec<Option<V>>, BiskyError>
+    where
+        V: DeserializeOwned,
+        F: FnOnce(Vec<String>) -> Fut,
+        Fut: Future<Output = Result<Vec<(String, Value)>, BiskyError>>,
+    {
+        let mut values = self.lookup(&keys, ttl);
+        let mut missing = keys
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            self.hits.fetch_add(keys.len() as u64, Ordering::Relaxed);
+            return deserialize(values);
+        }
+
+        // Always locked in the same order, so that overlapping lookups can't deadlock
+        missing.sort();
+        missing.dedup();
+        let locks = {
+            let mut loading = self.loading.lock();
+            missing
+                .iter()
+                .map(|key| loading.entry(key.clone()).or_default().clone())
+                .collect::<Vec<_>>()
+        };
+        let mut guards = Vec::with_capacity(locks.len());
+        for lock in locks {
+            guards.push(lock.lock_owned().await);
+        }
+
+        // Another lookup may have fetched some of them while this one waited
+        let mut fetched = HashMap::new();
+        let mut to_load = Vec::new();
+        for (key, value) in missing.iter().zip(self.lookup(&missing, ttl)) {
+            match value {
+                Some(value) => {
+                    fetched.insert(key.clone(), value);
+                }
+                None => to_load.push(key.clone()),
+            }
+        }
+        let loaded = if to_load.is_empty() {
+            Ok(())
+        } else {
+            load(to_load.clone()).await.map(|loaded| {
+                let mut entries = self.entries.lock();
+                for (key, value) in loaded {
+                    entries.insert(key.clone(), value.clone());
+                    fetched.insert(key, value);
+                }
+            })
+        };
+
+        {
+            let mut loading = self.loading.lock();
+            for key in &missing {
+                loading.remove(key);
+            }
+        }
+        drop(guards);
+        loaded?;
+
+        let misses = keys.iter().filter(|key| to_load.contains(key)).count();
+        self.misses.fetch_add(misses as u64, Ordering::Relaxed);
+        self.hits
+            .fetch_add((keys.len() - misses) as u64, Ordering::Relaxed);
+
+        for (value, key) in values.iter_mut().zip(&keys) {
+            if value.is_none() {
+                *value = fetched.get(key).cloned();
+            }
+        }
+        deserialize(values)
+    }
+
+    fn lookup(&self, keys: &[String], ttl: Duration) -> Vec<Option<Value>> {
+        let mut entries = self.entries.lock();
+        keys.iter().map(|key| entries.get(key, ttl)).collect()
+    }
+}
+
+fn deserialize<V: DeserializeOwned>(
+    values: Vec<Option<Value>>,
+) -> Result<Vec<Option<V>>, BiskyError> {
+    values
+        .into_iter()
+        .map(|value| Ok(value.map(serde_json::from_value).transpose()?))
+        .collect()
+}
+
+/// Evicts the entry used longest ago once full. Eviction scans every entry, which is cheap
+/// next to the request a miss makes.
+struct Lru<V> {
+    capacity: usize,
+    entries: HashMap<String, Entry<V>>,
+    clock: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    fetched: Instant,
+    used: u64,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str, ttl: Duration) -> Option<V> {
+        let entry = self.entries.get_mut(key)?;
+        if entry.fetched.elapsed() > ttl {
+            self.entries.remove(key);
+            return None;
+        }
+        self.clock += 1;
+        entry.used = self.clock;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                fetched: Instant::now(),
+                used: self.clock,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&str, &V) -> bool) {
+        self.entries.retain(|key, entry| keep(key, &entry.value));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,216 @@
+//! Reading CAR v1 files, the block format of repo exports from sync.getRepo and of the `blocks`
+//! of firehose commits, and decoding the DAG-CBOR records in them.
+
+use crate::dagcbor;
+use crate::errors::BiskyError;
+use crate::lexicon::com::atproto::sync::{Commit, RepoOp, RepoOpAction};
+use cid::Cid;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The most a header or block may claim to be, so a corrupt length can't exhaust memory
+const MAX_SECTION_LEN: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CarHeader {
+    pub version: u64,
+    /// For a repo export, the CID of the signed commit
+    pub roots: Vec<Cid>,
+}
+
+pub(crate) fn car_error(message: impl Into<String>) -> BiskyError {
+    BiskyError::CborError(message.into())
+}
+
+/// Reads the blocks of a CAR file one at a time
+pub struct CarReader<R> {
+    reader: R,
+    header: CarHeader,
+}
+
+impl<R: AsyncRead + Unpin> CarReader<R> {
+    /// Read the header of the CAR file `reader` starts with
+    pub async fn new(mut reader: R) -> Result<Self, BiskyError> {
+        let section = read_section(&mut reader)
+            .await?
+            .ok_or_else(|| car_error("Empty CAR file"))?;
+        let header: CarHeader =
+            serde_ipld_dagcbor::from_slice(&section).map_err(|e| car_error(e.to_string()))?;
+        if header.version != 1 {
+            return Err(car_error(format!(
+                "Unsupported CAR version {}",
+                header.version
+            )));
+        }
+        Ok(Self { reader, header })
+    }
+
+    pub fn header(&self) -> &CarHeader {
+        &self.header
+    }
+
+    /// The next block, or `None` at the end of the file
+    pub async fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>, BiskyError> {
+        let Some(section) = read_section(&mut self.reader).await? else {
+            return Ok(None);
+        };
+        let mut data = section.as_slice();
+        let cid = Cid::read_bytes(&mut data).map_err(|e| car_error(e.to_string()))?;
+        Ok(Some((cid, data.to_vec())))
+    }
+
+    /// Every remaining block, by CID
+    pub async fn read_all(mut self) -> Result<HashMap<Cid, Vec<u8>>, BiskyError> {
+        let mut blocks = HashMap::new();
+        while let Some((cid, data)) = self.next_block().await? {
+            blocks.insert(cid, data);
+        }
+        Ok(blocks)
+    }
+}
+
+/// The header and blocks of a whole CAR file held in memory
+pub async fn read_car(bytes: &[u8]) -> Result<(CarHeader, HashMap<Cid, Vec<u8>>), BiskyError> {
+    let reader = CarReader::new(bytes).await?;
+    let header = reader.header().clone();
+    Ok((header, reader.read_all().await?))
+}
+
+/// A length-prefixed section, `None` at a clean end of file
+async fn read_section<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>, BiskyError> {
+    let mut len = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = match reader.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && shift == 0 => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        len |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            if len > MAX_SECTION_LEN {
+                return Err(car_error(format!(
+                    "CAR section of {len} bytes is too large"
+                )));
+            }
+            let mut section = vec![0; len as usize];
+            reader.read_exact(&mut section).await?;
+            return Ok(Some(section));
+        }
+    }
+    Err(car_error("Invalid CAR section length"))
+}
+
+/// A record created or updated by a commit
+#[derive(Debug)]
+pub struct CommitRecord<'a> {
+    pub op: &'a RepoOp,
+    /// The record's DAG-CBOR bytes
+    pub bytes: Vec<u8>,
+}
+
+impl CommitRecord<'_> {
+    /// The record as `T`, which is `serde_json::Value`, a record type such as `Post`, or
+    /// `KnownRecord` for whichever record type it is
+    pub fn value<T: DeserializeOwned>(&self) -> Result<T, BiskyError> {
+        dagcbor::from_slice(&self.bytes)
+    }
+}
+
+/// The records `commit` created or updated, in the order of its ops. Ops whose block the commit
+/// left out, as it does when `too_big` is set, are skipped.
+pub async fn commit_records(commit: &Commit) -> Result<Vec<CommitRecord<'_>>, BiskyError> {
+    let (_, blocks) = read_car(&commit.blocks).await?;
+    Ok(commit
+        .ops
+        .iter()
+        .filter(|op| matches!(op.action, RepoOpAction::Create | RepoOpAction::Update))
+        .filter_map(|op| {
+            let bytes = blocks.get(op.cid.as_ref()?)?.clone();
+            Some(CommitRecord { op, bytes })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A CAR file of an empty map and `{"text": "hi"}`, with the second as its root
+    const TWO_BLOCKS: &[u8] = include_bytes!("../tests/fixtures/two-blocks.car");
+    const EMPTY_MAP: &str = "bafyreigbtj4x7ip5legnfznufuopl4sg4knzc2cof6duas4b3q2fy6swua";
+    const TEXT_HI: &str = "bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom";
+
+    #[tokio::test]
+    async fn header_and_blocks() {
+        let mut reader = CarReader::new(TWO_BLOCKS).await.unwrap();
+        assert_eq!(reader.header().version, 1);
+        assert_eq!(reader.header().roots, [Cid::from_str(TEXT_HI).unwrap()]);
+
+        let (cid, data) = reader.next_block().await.unwrap().unwrap();
+        assert_eq!(cid.to_string(), EMPTY_MAP);
+        assert_eq!(data, [0xa0]);
+
+        let (cid, data) = reader.next_block().await.unwrap().unwrap();
+        assert_eq!(cid.to_string(), TEXT_HI);
+        let value: serde_json::Value = dagcbor::from_slice(&data).unwrap();
+        assert_eq!(value, serde_json::json!({ "text": "hi" }));
+
+        assert!(reader.next_block().await.unwrap().is_none());
+        assert!(reader.next_block().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn read_whole_car() {
+        let (header, blocks) = read_car(TWO_BLOCKS).await.unwrap();
+        assert_eq!(header.roots.len(), 1);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[&header.roots[0]], b"\xa1\x64text\x62hi");
+    }
+
+    #[tokio::test]
+    async fn truncated() {
+        assert!(matches!(
+            CarReader::new(&b""[..]).await,
+            Err(BiskyError::CborError(_))
+        ));
+        // Within the header
+        assert!(matches!(
+            CarReader::new(&TWO_BLOCKS[..20]).await,
+            Err(BiskyError::IoError(_))
+        ));
+
+        // Within the last block, and within the length of one
+        let last = TWO_BLOCKS.len() - 1;
+        assert!(matches!(
+            read_car(&TWO_BLOCKS[..last]).await,
+            Err(BiskyError::IoError(_))
+        ));
+        let mut car = TWO_BLOCKS.to_vec();
+        car.push(0x80);
+        assert!(matches!(read_car(&car).await, Err(BiskyError::IoError(_))));
+    }
+
+    #[tokio::test]
+    async fn bad_sections() {
+        // A length far beyond `MAX_SECTION_LEN`
+        let mut car = TWO_BLOCKS.to_vec();
+        car.extend([0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(matches!(
+            read_car(&car).await,
+            Err(BiskyError::CborError(_))
+        ));
+
+        // Version 2 in place of 1, the last byte of the header
+        let mut car = TWO_BLOCKS.to_vec();
+        car[TWO_BLOCKS[0] as usize] = 0x02;
+        assert!(matches!(
+            CarReader::new(car.as_slice()).await,
+            Err(BiskyError::CborError(e)) if e.contains("version 2")
+        ));
+    }
+}
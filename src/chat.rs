@@ -0,0 +1,371 @@
+//! chat.bsky direct messages, sent through the PDS to the chat service
+
+use crate::bsky::BskyClient;
+use crate::errors::{ApiErrorKind, BiskyError};
+use crate::lexicon::chat::bsky::convo::{
+    ConvoInput, ConvoStatus, ConvoView, DeleteMessageForSelf, DeletedMessageView, GetConvoOutput,
+    GetLogOutput, GetMessagesOutput, LeaveConvoOutput, ListConvosOutput, LogEvent, MessageInput,
+    MessageView, MessageViewEnum, SendMessage, SendMessageBatch, SendMessageBatchOutput,
+    CHAT_PROXY, MAX_CONVO_MEMBERS, MAX_SEND_MESSAGE_BATCH,
+};
+use crate::storage::Storage;
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Where `BskyClient::chat_event_stream` left off in the chat log
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct ChatLogState {
+    pub cursor: Option<String>,
+}
+
+/// The chat service only tells these apart by message
+fn map_convo_error(error: BiskyError) -> BiskyError {
+    match error {
+        BiskyError::ApiError(e) => {
+            let message = e.message.to_lowercase();
+            if message.contains("incoming messages") || message.contains("disabled") {
+                BiskyError::MessagesDisabled(e.message)
+            } else if message.contains("block") {
+                BiskyError::BlockedActor(e.message)
+            } else if message.contains("not found") || e.kind() == ApiErrorKind::NotFound {
+                BiskyError::UnknownActor(e.message)
+            } else {
+                BiskyError::ApiError(e)
+            }
+        }
+        e => e,
+    }
+}
+
+impl BskyClient {
+    ///chat.bsky.convo.listConvos
+    /// A single page of the logged in user's conversations. Needs an app password with access
+    /// to direct messages, otherwise the error is `BiskyError::InsufficientScope`.
+    pub async fn chat_list_convos(
+        &mut self,
+        limit: usize,
+        cursor: Option<&str>,
+        unread_only: bool,
+        status: Option<ConvoStatus>,
+    ) -> Result<(Vec<ConvoView>, Option<String>), BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = Vec::from([("limit", limit.as_str())]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+        if unread_only {
+            query.push(("readState", "unread"));
+        }
+        match status {
+            Some(ConvoStatus::Request) => query.push(("status", "request")),
+            Some(ConvoStatus::Accepted) => query.push(("status", "accepted")),
+            Some(ConvoStatus::Unknown) | None => {}
+        }
+
+        let response = self
+            .xrpc_get_proxied::<ListConvosOutput>(
+                "chat.bsky.convo.listConvos",
+                Some(&query),
+                Some(CHAT_PROXY),
+            )
+            .await?;
+        Ok((response.convos, response.cursor))
+    }
+
+    ///chat.bsky.convo.getConvo
+    pub async fn chat_get_convo(&mut self, convo_id: &str) -> Result<ConvoView, BiskyError> {
+        let response = self
+            .xrpc_get_proxied::<GetConvoOutput>(
+                "chat.bsky.convo.getConvo",
+                Some(&[("convoId", convo_id)]),
+                Some(CHAT_PROXY),
+            )
+            .await?;
+        Ok(response.convo)
+    }
+
+    ///chat.bsky.convo.getConvoForMembers
+    /// The conversation between the logged in user and `members`, created if there is none yet.
+    /// Fails with `BiskyError::MessagesDisabled` if a member doesn't accept messages from the
+    /// user, `BiskyError::BlockedActor` if there is a block, and `BiskyError::UnknownActor` if
+    /// a member doesn't exist.
+    pub async fn chat_get_convo_for_members(
+        &mut self,
+        members: &[&str],
+    ) -> Result<ConvoView, BiskyError> {
+        if members.len() > MAX_CONVO_MEMBERS {
+            return Err(BiskyError::TooManyMembers(members.len()));
+        }
+        let query = members
+            .iter()
+            .map(|member| ("members", *member))
+            .collect::<Vec<_>>();
+
+        let response = self
+            .xrpc_get_proxied::<GetConvoOutput>(
+                "chat.bsky.convo.getConvoForMembers",
+                Some(&query),
+                Some(CHAT_PROXY),
+            )
+            .await
+            .map_err(map_convo_error)?;
+        Ok(response.convo)
+    }
+
+    ///chat.bsky.convo.getMessages
+    /// A single page of messages, newest first, with deleted ones as placeholders
+    pub async fn chat_get_messages(
+        &mut self,
+        convo_id: &str,
+        limit: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<MessageViewEnum>, Option<String>), BiskyError> {
+        let limit = std::cmp::min(limit, 100).to_string();
+        let mut query = Vec::from([("convoId", convo_id), ("limit", limit.as_str())]);
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+
+        let response = self
+            .xrpc_get_proxied::<GetMessagesOutput>(
+                "chat.bsky.convo.getMessages",
+                Some(&query),
+                Some(CHAT_PROXY),
+            )
+            .await?;
+        Ok((response.messages, response.cursor))
+    }
+
+    /// chat.bsky.convo.getMessages for the whole history of a conversation, newest first
+    pub async fn chat_get_all_messages(
+        &mut self,
+        convo_id: &str,
+    ) -> Result<Vec<MessageViewEnum>, BiskyError> {
+        let mut messages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (mut page, next_cursor) = self
+                .chat_get_messages(convo_id, 100, cursor.as_deref())
+                .await?;
+            let done = page.is_empty() || next_cursor.is_none();
+            messages.append(&mut page);
+            if done {
+                return Ok(messages);
+            }
+            cursor = next_cursor;
+        }
+    }
+
+    ///chat.bsky.convo.getLog
+    /// The events after `cursor`. Without a cursor there are no events, only the cursor to
+    /// start from.
+    pub async fn chat_get_log(&mut self, cursor: Option<&str>) -> Result<GetLogOutput, BiskyError> {
+        let query = cursor
+            .map(|c| ("cursor", c))
+            .into_iter()
+            .collect::<Vec<_>>();
+        self.xrpc_get_proxied("chat.bsky.convo.getLog", Some(&query), Some(CHAT_PROXY))
+            .await
+    }
+
+    /// Poll chat.bsky.convo.getLog every `poll_interval`, yielding events as they arrive. The
+    /// cursor is kept in `storage`, if given, once its events have been yielded; without one the
+    /// stream starts from now.
+    ///
+    /// Errors are yielded and the stream carries on polling, so it only ends when dropped.
+    pub fn chat_event_stream<'a>(
+        &'a mut self,
+        poll_interval: Duration,
+        storage: Option<&'a dyn Storage<ChatLogState, Error = BiskyError>>,
+    ) -> impl Stream<Item = Result<LogEvent, BiskyError>> + 'a {
+        struct State<'a> {
+            client: &'a mut BskyClient,
+            storage: Option<&'a dyn Storage<ChatLogState, Error = BiskyError>>,
+            log: Option<ChatLogState>,
+            queue: VecDeque<LogEvent>,
+            /// The cursor after the events in `queue`
+            next: Option<String>,
+            /// Whether the last poll came back empty, so the next one should wait
+            idle: bool,
+        }
+
+        let state = State {
+            client: self,
+            storage,
+            log: None,
+            queue: VecDeque::new(),
+            next: None,
+            idle: false,
+        };
+
+        futures::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(event) = state.queue.pop_front() {
+                    return Some((Ok(event), state));
+                }
+
+                let log = match state.log.take() {
+                    Some(log) => log,
+                    None => match state.storage {
+                        // Nothing stored yet reads as an error, most likely a missing file
+                        Some(storage) => storage.get().await.unwrap_or_default(),
+                        None => ChatLogState::default(),
+                    },
+                };
+                let log = state.log.insert(log);
+
+                if let Some(next) = state.next.take() {
+                    if log.cursor.as_ref() != Some(&next) {
+                        log.cursor = Some(next);
+                        if let Some(storage) = state.storage {
+                            if let Err(e) = storage.set(Some(log)).await {
+                                return Some((Err(e), state));
+                            }
+                        }
+                    }
+                }
+
+                if state.idle {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                // Until a poll succeeds with events, keep waiting between polls
+                state.idle = true;
+
+                let cursor = log.cursor.clone();
+                match state.client.chat_get_log(cursor.as_deref()).await {
+                    Ok(page) => {
+                        state.idle = page.logs.is_empty();
+                        state.next = page.cursor.or(cursor);
+                        state.queue.extend(page.logs);
+                    }
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    ///chat.bsky.convo.sendMessage
+    pub async fn chat_send_message(
+        &mut self,
+        convo_id: &str,
+        message: &MessageInput,
+    ) -> Result<MessageView, BiskyError> {
+        message.validate()?;
+        self.xrpc_post_proxied(
+            "chat.bsky.convo.sendMessage",
+            &SendMessage { convo_id, message },
+            Some(CHAT_PROXY),
+        )
+        .await
+        .map_err(map_convo_error)
+    }
+
+    ///chat.bsky.convo.sendMessageBatch
+    /// Send (convo id, message) pairs, `MAX_SEND_MESSAGE_BATCH` per call. Fails before sending
+    /// anything if a message is invalid.
+    pub async fn chat_send_message_batch(
+        &mut self,
+        messages: &[(&str, MessageInput)],
+    ) -> Result<Vec<MessageView>, BiskyError> {
+        for (_, message) in messages {
+            message.validate()?;
+        }
+
+        let mut sent = Vec::with_capacity(messages.len());
+        for chunk in messages.chunks(MAX_SEND_MESSAGE_BATCH) {
+            let items = chunk
+                .iter()
+                .map(|(convo_id, message)| SendMessage { convo_id, message })
+                .collect::<Vec<_>>();
+            let mut response = self
+                .xrpc_post_proxied::<_, SendMessageBatchOutput>(
+                    "chat.bsky.convo.sendMessageBatch",
+                    &SendMessageBatch { items },
+                    Some(CHAT_PROXY),
+                )
+                .await
+                .map_err(map_convo_error)?;
+            sent.append(&mut response.items);
+        }
+        Ok(sent)
+    }
+
+    ///chat.bsky.convo.updateRead
+    /// Mark the conversation as read up to `message_id`, or entirely
+    pub async fn chat_update_read(
+        &mut self,
+        convo_id: &str,
+        message_id: Option<&str>,
+    ) -> Result<ConvoView, BiskyError> {
+        self.chat_convo_procedure("chat.bsky.convo.updateRead", convo_id, message_id)
+            .await
+    }
+
+    ///chat.bsky.convo.deleteMessageForSelf
+    /// The other members still see the message
+    pub async fn chat_delete_message_for_self(
+        &mut self,
+        convo_id: &str,
+        message_id: &str,
+    ) -> Result<DeletedMessageView, BiskyError> {
+        self.xrpc_post_proxied(
+            "chat.bsky.convo.deleteMessageForSelf",
+            &DeleteMessageForSelf {
+                convo_id,
+                message_id,
+            },
+            Some(CHAT_PROXY),
+        )
+        .await
+    }
+
+    ///chat.bsky.convo.muteConvo
+    pub async fn chat_mute_convo(&mut self, convo_id: &str) -> Result<ConvoView, BiskyError> {
+        self.chat_convo_procedure("chat.bsky.convo.muteConvo", convo_id, None)
+            .await
+    }
+
+    ///chat.bsky.convo.unmuteConvo
+    pub async fn chat_unmute_convo(&mut self, convo_id: &str) -> Result<ConvoView, BiskyError> {
+        self.chat_convo_procedure("chat.bsky.convo.unmuteConvo", convo_id, None)
+            .await
+    }
+
+    ///chat.bsky.convo.leaveConvo
+    pub async fn chat_leave_convo(
+        &mut self,
+        convo_id: &str,
+    ) -> Result<LeaveConvoOutput, BiskyError> {
+        self.xrpc_post_proxied(
+            "chat.bsky.convo.leaveConvo",
+            &ConvoInput {
+                convo_id,
+                message_id: None,
+            },
+            Some(CHAT_PROXY),
+        )
+        .await
+    }
+
+    /// A chat procedure that takes a convo id and returns the updated conversation
+    async fn chat_convo_procedure(
+        &mut self,
+        path: &str,
+        convo_id: &str,
+        message_id: Option<&str>,
+    ) -> Result<ConvoView, BiskyError> {
+        let response = self
+            .xrpc_post_proxied::<_, GetConvoOutput>(
+                path,
+                &ConvoInput {
+                    convo_id,
+                    message_id,
+                },
+                Some(CHAT_PROXY),
+            )
+            .await?;
+        Ok(response.convo)
+    }
+}
@@ -0,0 +1,262 @@
+//! DAG-CBOR, the encoding records have in repos and on the firehose.
+//!
+//! Records are converted through the JSON form of the data model, so the types that read and
+//! write records over XRPC work unchanged: a CID link (tag 42) is `{"$link": "<cid>"}`, as in
+//! a blob's `ref`, and bytes are `{"$bytes": "<base64>"}`.
+
+use crate::errors::BiskyError;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use cid::Cid;
+use ipld_core::ipld::Ipld;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+// Bytes are written without padding, but read with or without
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+fn cbor_error<E: std::fmt::Display>(e: E) -> BiskyError {
+    BiskyError::CborError(e.to_string())
+}
+
+/// DAG-CBOR data in the JSON form of the data model
+pub fn ipld_to_json(ipld: Ipld) -> Value {
+    match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(b) => Value::Bool(b),
+        Ipld::Integer(i) => match i64::try_from(i) {
+            Ok(i) => i.into(),
+            Err(_) => Value::String(i.to_string()),
+        },
+        Ipld::Float(f) => json!(f),
+        Ipld::String(s) => Value::String(s),
+        Ipld::Bytes(b) => json!({ "$bytes": STANDARD_NO_PAD.encode(b) }),
+        Ipld::List(list) => Value::Array(list.into_iter().map(ipld_to_json).collect()),
+        Ipld::Map(map) => {
+            Value::Object(map.into_iter().map(|(k, v)| (k, ipld_to_json(v))).collect())
+        }
+        Ipld::Link(cid) => json!({ "$link": cid.to_string() }),
+    }
+}
+
+/// JSON in the data model's form as DAG-CBOR data. `$link` and `$bytes` objects become links
+/// and bytes; fails on anything that isn't a valid CID or base64.
+pub fn json_to_ipld(value: Value) -> Result<Ipld, BiskyError> {
+    Ok(match value {
+        Value::Null => Ipld::Null,
+        Value::Bool(b) => Ipld::Bool(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Ipld::Integer(i.into()),
+            None => match n.as_u64() {
+                Some(u) => Ipld::Integer(u.into()),
+                None => Ipld::Float(n.as_f64().unwrap_or_default()),
+            },
+        },
+        Value::String(s) => Ipld::String(s),
+        Value::Array(list) => Ipld::List(
+            list.into_iter()
+                .map(json_to_ipld)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => {
+            if map.len() == 1 {
+                match map.iter().next() {
+                    Some((k, Value::String(link))) if k == "$link" => {
+                        return Ok(Ipld::Link(Cid::from_str(link).map_err(cbor_error)?));
+                    }
+                    Some((k, Value::String(bytes))) if k == "$bytes" => {
+                        return Ok(Ipld::Bytes(BASE64.decode(bytes).map_err(cbor_error)?));
+                    }
+                    _ => {}
+                }
+            }
+            Ipld::Map(
+                map.into_iter()
+                    .map(|(k, v)| Ok((k, json_to_ipld(v)?)))
+                    .collect::<Result<_, BiskyError>>()?,
+            )
+        }
+    })
+}
+
+/// Decode DAG-CBOR into `T`, which is `serde_json::Value` or a record type such as `Post`
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BiskyError> {
+    let ipld: Ipld = serde_ipld_dagcbor::from_slice(bytes).map_err(cbor_error)?;
+    Ok(serde_json::from_value(ipld_to_json(ipld))?)
+}
+
+/// Encode `value` as DAG-CBOR, with its map keys in canonical order
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, BiskyError> {
+    let ipld = json_to_ipld(serde_json::to_value(value)?)?;
+    serde_ipld_dagcbor::to_vec(&ipld).map_err(cbor_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    /// The CID of DAG-CBOR `bytes`: CIDv1, dag-cbor, sha2-256
+    fn cid_of(bytes: &[u8]) -> Cid {
+        let hash = cid::multihash::Multihash::wrap(0x12, &Sha256::digest(bytes)).unwrap();
+        Cid::new_v1(0x71, hash)
+    }
+
+    /// The CID of an empty DAG-CBOR map, as any IPFS implementation computes it
+    const EMPTY_MAP_CID: &str = "bafyreigbtj4x7ip5legnfznufuopl4sg4knzc2cof6duas4b3q2fy6swua";
+
+    #[test]
+    fn known_cid() {
+        let bytes = to_vec(&json!({})).unwrap();
+        assert_eq!(bytes, [0xa0]);
+        assert_eq!(cid_of(&bytes).to_string(), EMPTY_MAP_CID);
+    }
+
+    #[test]
+    fn canonical_key_order() {
+        // Shorter keys first, then bytewise, whatever order the JSON had them in
+        let value = json!({
+            "createdAt": "2024-01-01T00:00:00.000Z",
+            "$type": "app.bsky.feed.post",
+            "text": "hi",
+        });
+        let mut expected = vec![0xa3];
+        expected.extend(b"\x64text\x62hi");
+        expected.extend(b"\x65$type\x72app.bsky.feed.post");
+        expected.extend(b"\x69createdAt\x78\x182024-01-01T00:00:00.000Z");
+        assert_eq!(to_vec(&value).unwrap(), expected);
+    }
+
+    #[test]
+    fn links_and_bytes() {
+        let value = json!({
+            "ref": { "$link": EMPTY_MAP_CID },
+            "sig": { "$bytes": "AQID" },
+        });
+        let bytes = to_vec(&value).unwrap();
+        // `ref` is a CID link, tag 42, and `sig` a byte string
+        let mut expected = vec![0xa2, 0x63];
+        expected.extend(b"ref");
+        expected.extend([0xd8, 0x2a, 0x58, 0x25, 0x00]);
+        expected.extend(Cid::from_str(EMPTY_MAP_CID).unwrap().to_bytes());
+        expected.extend([0x63]);
+        expected.extend(b"sig");
+        expected.extend([0x43, 1, 2, 3]);
+        assert_eq!(bytes, expected);
+
+        let decoded: Value = from_slice(&bytes).unwrap();
+        assert_eq!(
+            decoded,
+            json!({ "ref": { "$link": EMPTY_MAP_CID }, "sig": { "$bytes": "AQID" } })
+        );
+    }
+
+    #[test]
+    fn bad_link() {
+        assert!(matches!(
+            json_to_ipld(json!({ "$link": "not a cid" })),
+            Err(BiskyError::CborError(_))
+        ));
+    }
+
+    #[cfg(feature = "bsky")]
+    mod records {
+        use super::*;
+        use crate::lexicon::app::bsky::actor::Profile;
+        use crate::lexicon::app::bsky::embed::Image;
+        use crate::lexicon::app::bsky::feed::{Embeds, ImagesEmbed, Post, PostBuilder, ReplyRef};
+        use crate::lexicon::app::bsky::richtext::{ByteSlice, Facet, FacetFeature};
+        use crate::lexicon::com::atproto::label::KnownLabel;
+        use crate::lexicon::com::atproto::repo::{Blob, StrongRef};
+
+        fn round_trip<T: Serialize + DeserializeOwned>(record: &T) -> (Vec<u8>, T) {
+            let bytes = to_vec(record).unwrap();
+            let decoded: T = from_slice(&bytes).unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(record).unwrap()
+            );
+            // Encoding is deterministic, so the CID is too
+            assert_eq!(to_vec(&decoded).unwrap(), bytes);
+            (bytes, decoded)
+        }
+
+        #[test]
+        fn post_round_trip() {
+            let parent =
+                StrongRef::new("at://did:plc:alice/app.bsky.feed.post/3k2a", EMPTY_MAP_CID)
+                    .unwrap();
+            let mut builder = PostBuilder::default();
+            builder
+                .text("hello #rust")
+                .created_at("2024-01-01T00:00:00.000Z".parse().unwrap())
+                .lang("en")
+                .facets(vec![Facet {
+                    index: ByteSlice {
+                        byte_start: 6,
+                        byte_end: 11,
+                    },
+                    features: vec![FacetFeature::Tag {
+                        tag: "rust".to_string(),
+                    }],
+                }])
+                .embed(Embeds::Images(ImagesEmbed {
+                    images: vec![Image {
+                        image: Blob::new(EMPTY_MAP_CID, "image/png", 1234),
+                        alt: "a crab".to_string(),
+                        aspect_ratio: None,
+                    }],
+                }))
+                .reply(ReplyRef {
+                    root: parent.clone(),
+                    parent,
+                })
+                .self_label(KnownLabel::GraphicMedia);
+            let post = builder.build().unwrap();
+
+            let (bytes, decoded): (_, Post) = round_trip(&post);
+            assert_eq!(decoded.text, "hello #rust");
+            assert_eq!(decoded.created_at, post.created_at);
+            // The blob ref is a link, not a string
+            let link = [
+                &[0xd8, 0x2a, 0x58, 0x25, 0x00][..],
+                &cid_of(&[0xa0]).to_bytes(),
+            ]
+            .concat();
+            assert!(bytes.windows(link.len()).any(|window| window == link));
+        }
+
+        #[test]
+        fn profile_round_trip() {
+            let mut profile: Profile = serde_json::from_value(json!({
+                "$type": "app.bsky.actor.profile",
+                "displayName": "Alice",
+                "description": "Hi 👋",
+                "avatar": {
+                    "$type": "blob",
+                    "ref": { "$link": EMPTY_MAP_CID },
+                    "mimeType": "image/jpeg",
+                    "size": 5678,
+                },
+                "createdAt": "2024-01-01T00:00:00.000Z",
+                "someFutureField": { "nested": [1, 2, 3] },
+            }))
+            .unwrap();
+            profile.self_label(KnownLabel::Porn);
+
+            let (_, decoded): (_, Profile) = round_trip(&profile);
+            assert_eq!(decoded.display_name.as_deref(), Some("Alice"));
+            assert_eq!(decoded.avatar.unwrap().cid(), EMPTY_MAP_CID);
+            assert_eq!(
+                decoded.extra["someFutureField"],
+                json!({ "nested": [1, 2, 3] })
+            );
+        }
+    }
+}
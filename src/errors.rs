@@ -1,5 +1,12 @@
+use crate::atproto::{nsid_of, read_body, RateLimitInfo, Sent};
+#[cfg(feature = "bsky")]
+use crate::lexicon::app::bsky::feed::Attachment;
+use crate::lexicon::com::atproto::repo::CreateRecordOutput;
 use miette::Diagnostic;
 use serde::Deserialize;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
 use thiserror::Error;
 
 #[derive(Debug, Error, Diagnostic)]
@@ -8,8 +15,15 @@ pub enum BiskyError {
     BadCredentials,
     #[error("Unexpected Response: {0}")]
     UnexpectedResponse(String),
+    /// A response longer than the client's `max_response_size`. `size` is as much as was
+    /// read, or what the response said it would be.
+    #[error("Response is at least {size} bytes long, the limit is {limit}")]
+    ResponseTooLarge { size: usize, limit: usize },
     #[error("No Session Found! Did you forget to login?")]
     MissingSession,
+    /// A request a replayed recording has no response for
+    #[error("Unexpected request: {0}")]
+    UnexpectedRequest(String),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
@@ -18,13 +32,432 @@ pub enum BiskyError {
     ApiError(#[from] ApiError),
     #[error(transparent)]
     JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    ClientBuilderError(#[from] crate::atproto::ClientBuilderError),
+    /// A 429, with the rate limit the PDS reported alongside it
+    #[error("Rate Limited: {}", .0.map_or_else(|| "no rate limit headers".to_string(), |info| info.to_string()))]
+    RateLimited(Option<RateLimitInfo>),
     #[error("Storage Error: {0}")]
     StorageError(String),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    /// A link to a private, loopback or link-local address, which isn't fetched
+    #[error("Private Address: {0}")]
+    PrivateAddress(String),
+    #[error("Invalid AT URI: {0}")]
+    InvalidAtUri(String),
+    #[error("Invalid datetime: {0}")]
+    InvalidDatetime(String),
+    #[error("Not a bsky.app profile, post, feed, list or starter pack link: {0}")]
+    InvalidBskyUrl(String),
+    #[error("{0} does not belong to the logged in user's repo")]
+    NotOwnRecord(String),
+    /// The post being replied to doesn't exist, most likely because it was deleted
+    #[error("Reply Target Not Found: {0}")]
+    ReplyTargetNotFound(String),
+    #[error("Unknown Feed: {0}")]
+    UnknownFeed(String),
+    #[error("Unknown List: {0}")]
+    UnknownList(String),
+    #[error("Blocked Actor: {0}")]
+    BlockedActor(String),
+    #[error("Blocked By Actor: {0}")]
+    BlockedByActor(String),
+    #[error("Invalid language tag: {0}")]
+    InvalidLanguage(String),
+    #[error("Too many languages: {0}, a post can have at most 3")]
+    TooManyLanguages(usize),
+    #[error("Post is {graphemes} graphemes long, the limit is {limit}")]
+    PostTooLong { graphemes: usize, limit: usize },
+    #[error("Post is {bytes} bytes long, the limit is {limit}")]
+    PostTooLarge { bytes: usize, limit: usize },
+    #[error("Alt text of image {index} is {graphemes} graphemes long, the limit is {limit}")]
+    AltTextTooLong {
+        index: usize,
+        graphemes: usize,
+        limit: usize,
+    },
+    #[error("Too many images: {0}, a post can have at most 4")]
+    TooManyImages(usize),
+    #[error("Image is {bytes} bytes long, the limit is {limit}")]
+    ImageTooLarge { bytes: usize, limit: usize },
+    #[error("Invalid image: {0}")]
+    InvalidImage(String),
+    /// A post refused by `AltTextPolicy::Require`
+    #[cfg(feature = "bsky")]
+    #[error("Missing alt text on {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    MissingAltText(Vec<Attachment>),
+    #[error("Facet {byte_start}..{byte_end} is out of bounds of a {text_len} byte text")]
+    InvalidFacet {
+        byte_start: usize,
+        byte_end: usize,
+        text_len: usize,
+    },
+    #[error("Thread stopped after {} of {total} posts: {source}", created.len())]
+    ThreadInterrupted {
+        /// The posts that were created before the failure, in thread order
+        created: Vec<CreateRecordOutput>,
+        total: usize,
+        source: Box<BiskyError>,
+    },
+    #[error("{did} is already on list {list}")]
+    AlreadyListed { did: String, list: String },
+    #[error("Unsupported DID method: {0}")]
+    UnsupportedDid(String),
+    #[error("DNS Error: {0}")]
+    DnsError(String),
+    #[error("WebSocket Error: {0}")]
+    WebSocketError(String),
+    #[error("CBOR Error: {0}")]
+    CborError(String),
+    /// The cursor is ahead of the stream, most likely because it belongs to another relay
+    #[error("Future Cursor: {0}")]
+    FutureCursor(String),
+    #[error("Handler panicked: {0}")]
+    HandlerPanicked(String),
+    /// A subscriber of a `FirehoseHub` fell so far behind that the oldest events were dropped
+    #[error("Subscriber lagged and missed {0} events")]
+    SubscriberLagged(u64),
+    /// An error delivered to every subscriber of one source, such as a `FirehoseHub`
+    #[error(transparent)]
+    Shared(std::sync::Arc<BiskyError>),
+    #[error("Insufficient Scope: {0}. Direct messages need an app password that allows them")]
+    InsufficientScope(String),
+    #[error("Message is {graphemes} graphemes long, the limit is {limit}")]
+    MessageTooLong { graphemes: usize, limit: usize },
+    #[error("Message is {bytes} bytes long, the limit is {limit}")]
+    MessageTooLarge { bytes: usize, limit: usize },
+    #[error("Messages Disabled: {0}")]
+    MessagesDisabled(String),
+    #[error("Handle Not Found: {0}")]
+    HandleNotFound(String),
+    #[error("DID document of {0} names no PDS")]
+    NoPdsEndpoint(String),
+    #[error("Handle Not Available: {0}")]
+    HandleNotAvailable(String),
+    #[error("Handle Verification Failed: {0}")]
+    HandleVerificationFailed(String),
+    #[error("Unknown Actor: {0}")]
+    UnknownActor(String),
+    #[error("Not subscribed to labeler {0}")]
+    UnknownLabeler(String),
+    #[error("Too many members: {0}, a conversation can have at most 10")]
+    TooManyMembers(usize),
+    #[error(transparent)]
+    UninitializedField(#[from] derive_builder::UninitializedFieldError),
 }
 
-#[derive(Debug, Error, Deserialize)]
-#[error("Error: {error}, Message: {message}")]
+#[derive(Debug, Deserialize)]
 pub struct ApiError {
     pub error: String,
+    #[serde(default)]
     pub message: String,
+    /// The call that failed, for errors read from an HTTP response
+    #[serde(skip)]
+    pub context: Option<Box<XrpcContext>>,
+}
+
+/// How much of an error response is read
+const MAX_ERROR_SIZE: usize = 64 * 1024;
+
+/// Which call an `ApiError` came from and what the server answered
+#[derive(Debug, Clone)]
+pub struct XrpcContext {
+    /// The XRPC method, e.g. `com.atproto.repo.createRecord`
+    pub nsid: String,
+    pub status: u16,
+    /// The `x-request-id` of the response, to find the request in the server's logs
+    pub request_id: Option<String>,
+    pub rate_limit: Option<RateLimitInfo>,
+}
+
+impl ApiError {
+    pub fn kind(&self) -> ApiErrorKind {
+        ApiErrorKind::from(self.error.as_str())
+    }
+
+    /// The HTTP status of the response the error came from
+    pub fn status(&self) -> Option<u16> {
+        self.context.as_ref().map(|context| context.status)
+    }
+
+    /// The error a failed response carries. A body that isn't an XRPC error, such as a proxy's
+    /// error page, becomes the message of an error named after the status.
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let sent = response.extensions().get::<Sent>();
+        let metrics = sent.and_then(|sent| sent.metrics.clone());
+        let context = XrpcContext {
+            nsid: sent
+                .map_or_else(|| nsid_of(response.url()), |sent| &sent.nsid)
+                .to_string(),
+            status: status.as_u16(),
+            request_id: response
+                .headers()
+                .get("x-request-id")
+                .and_then(|id| id.to_str().ok())
+                .map(str::to_string),
+            rate_limit: RateLimitInfo::from_headers(response.headers()),
+        };
+
+        // Whatever is past the limit isn't worth keeping
+        let body = read_body(response, Some(MAX_ERROR_SIZE))
+            .await
+            .map(|body| String::from_utf8_lossy(&body).into_owned())
+            .unwrap_or_default();
+        let mut error = serde_json::from_str::<ApiError>(&body).unwrap_or_else(|_| ApiError {
+            error: status
+                .canonical_reason()
+                .unwrap_or("Unknown")
+                .replace(' ', ""),
+            message: body,
+            context: None,
+        });
+        error.context = Some(Box::new(context));
+        if let Some(metrics) = metrics {
+            metrics.on_api_error(&error);
+        }
+        error
+    }
+}
+
+impl fmt::Display for ApiError {
+    /// E.g. `com.atproto.repo.createRecord failed (400 InvalidRequest): record too large
+    /// [req-id abc123]`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(context) = &self.context else {
+            return write!(f, "Error: {}, Message: {}", self.error, self.message);
+        };
+        write!(
+            f,
+            "{} failed ({} {}): {}",
+            context.nsid, context.status, self.error, self.message
+        )?;
+        if let Some(request_id) = &context.request_id {
+            write!(f, " [req-id {request_id}]")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// The `error` code of an API error. New codes may be added, so matches need a fallback arm.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ApiErrorKind {
+    ExpiredToken,
+    InvalidToken,
+    AuthRequired,
+    /// The account has email two factor auth on, and the token sent by email is needed to log in
+    AuthFactorTokenRequired,
+    AccountTakedown,
+    AccountDeactivated,
+    RecordTakendown,
+    /// A repo taken down by its PDS, from the com.atproto endpoints
+    RepoTakendown,
+    RepoDeactivated,
+    /// A repo temporarily taken down by its PDS
+    RepoSuspended,
+    BlockedActor,
+    BlockedByActor,
+    RateLimitExceeded,
+    /// The record or repo changed since the cid a write was made against
+    InvalidSwap,
+    RecordNotFound,
+    /// A create asked for an rkey that a record already has
+    RecordAlreadyExists,
+    NotFound,
+    HandleNotFound,
+    BlobTooLarge,
+    InvalidRequest,
+    Other(String),
+}
+
+impl ApiErrorKind {
+    /// Whether asking again will fail the same way, because the account or record is gone or
+    /// blocked rather than the request having failed. A suspension may be lifted, so it counts
+    /// as not permanent, as does any other error.
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            Self::AccountTakedown
+                | Self::AccountDeactivated
+                | Self::RecordTakendown
+                | Self::RepoTakendown
+                | Self::RepoDeactivated
+                | Self::BlockedActor
+                | Self::BlockedByActor
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::ExpiredToken => "ExpiredToken",
+            Self::InvalidToken => "InvalidToken",
+            Self::AuthRequired => "AuthRequired",
+            Self::AuthFactorTokenRequired => "AuthFactorTokenRequired",
+            Self::AccountTakedown => "AccountTakedown",
+            Self::AccountDeactivated => "AccountDeactivated",
+            Self::RecordTakendown => "RecordTakendown",
+            Self::RepoTakendown => "RepoTakendown",
+            Self::RepoDeactivated => "RepoDeactivated",
+            Self::RepoSuspended => "RepoSuspended",
+            Self::BlockedActor => "BlockedActor",
+            Self::BlockedByActor => "BlockedByActor",
+            Self::RateLimitExceeded => "RateLimitExceeded",
+            Self::InvalidSwap => "InvalidSwap",
+            Self::RecordNotFound => "RecordNotFound",
+            Self::RecordAlreadyExists => "RecordAlreadyExists",
+            Self::NotFound => "NotFound",
+            Self::HandleNotFound => "HandleNotFound",
+            Self::BlobTooLarge => "BlobTooLarge",
+            Self::InvalidRequest => "InvalidRequest",
+            Self::Other(error) => error,
+        }
+    }
+}
+
+impl From<&str> for ApiErrorKind {
+    fn from(error: &str) -> Self {
+        match error {
+            "ExpiredToken" => Self::ExpiredToken,
+            "InvalidToken" => Self::InvalidToken,
+            "AuthRequired" => Self::AuthRequired,
+            "AuthFactorTokenRequired" => Self::AuthFactorTokenRequired,
+            "AccountTakedown" => Self::AccountTakedown,
+            "AccountDeactivated" => Self::AccountDeactivated,
+            "RecordTakendown" => Self::RecordTakendown,
+            "RepoTakendown" => Self::RepoTakendown,
+            "RepoDeactivated" => Self::RepoDeactivated,
+            "RepoSuspended" => Self::RepoSuspended,
+            "BlockedActor" => Self::BlockedActor,
+            "BlockedByActor" => Self::BlockedByActor,
+            "RateLimitExceeded" => Self::RateLimitExceeded,
+            "InvalidSwap" => Self::InvalidSwap,
+            "RecordNotFound" => Self::RecordNotFound,
+            "RecordAlreadyExists" => Self::RecordAlreadyExists,
+            "NotFound" => Self::NotFound,
+            "HandleNotFound" => Self::HandleNotFound,
+            "BlobTooLarge" => Self::BlobTooLarge,
+            "InvalidRequest" => Self::InvalidRequest,
+            _ => Self::Other(error.to_string()),
+        }
+    }
+}
+
+impl FromStr for ApiErrorKind {
+    type Err = Infallible;
+
+    fn from_str(error: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(error))
+    }
+}
+
+impl fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl BiskyError {
+    /// The kind of the API error behind this error, including the ones mapped to their own
+    /// variant such as `BlockedActor`
+    pub fn api_error_kind(&self) -> Option<ApiErrorKind> {
+        match self {
+            Self::ApiError(e) => Some(e.kind()),
+            Self::BlockedActor(_) => Some(ApiErrorKind::BlockedActor),
+            Self::BlockedByActor(_) => Some(ApiErrorKind::BlockedByActor),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every code `ApiErrorKind` has a variant for
+    const KNOWN: &[(&str, ApiErrorKind)] = &[
+        ("ExpiredToken", ApiErrorKind::ExpiredToken),
+        ("InvalidToken", ApiErrorKind::InvalidToken),
+        ("AuthRequired", ApiErrorKind::AuthRequired),
+        (
+            "AuthFactorTokenRequired",
+            ApiErrorKind::AuthFactorTokenRequired,
+        ),
+        ("AccountTakedown", ApiErrorKind::AccountTakedown),
+        ("AccountDeactivated", ApiErrorKind::AccountDeactivated),
+        ("RecordTakendown", ApiErrorKind::RecordTakendown),
+        ("RepoTakendown", ApiErrorKind::RepoTakendown),
+        ("RepoDeactivated", ApiErrorKind::RepoDeactivated),
+        ("RepoSuspended", ApiErrorKind::RepoSuspended),
+        ("BlockedActor", ApiErrorKind::BlockedActor),
+        ("BlockedByActor", ApiErrorKind::BlockedByActor),
+        ("RateLimitExceeded", ApiErrorKind::RateLimitExceeded),
+        ("InvalidSwap", ApiErrorKind::InvalidSwap),
+        ("RecordNotFound", ApiErrorKind::RecordNotFound),
+        ("RecordAlreadyExists", ApiErrorKind::RecordAlreadyExists),
+        ("NotFound", ApiErrorKind::NotFound),
+        ("HandleNotFound", ApiErrorKind::HandleNotFound),
+        ("BlobTooLarge", ApiErrorKind::BlobTooLarge),
+        ("InvalidRequest", ApiErrorKind::InvalidRequest),
+    ];
+
+    #[test]
+    fn error_codes() {
+        for (code, kind) in KNOWN {
+            let parsed = code.parse::<ApiErrorKind>().unwrap();
+            assert_eq!(parsed, *kind);
+            assert_eq!(parsed.to_string(), *code);
+            assert_eq!(parsed.to_string().parse::<ApiErrorKind>().unwrap(), parsed);
+        }
+
+        // Codes are case sensitive, and anything else is kept as it was sent
+        for code in ["UpstreamFailure", "expiredtoken", "Expired Token", ""] {
+            let parsed = code.parse::<ApiErrorKind>().unwrap();
+            assert_eq!(parsed, ApiErrorKind::Other(code.to_string()));
+            assert_eq!(parsed.to_string(), code);
+        }
+    }
+
+    #[test]
+    fn takedowns_and_blocks() {
+        let api_error = |code: &str| ApiError {
+            error: code.to_string(),
+            message: "Account has been suspended".to_string(),
+            context: None,
+        };
+        for (code, kind) in [
+            ("AccountTakedown", ApiErrorKind::AccountTakedown),
+            ("RecordTakendown", ApiErrorKind::RecordTakendown),
+            ("AccountDeactivated", ApiErrorKind::AccountDeactivated),
+            ("BlockedActor", ApiErrorKind::BlockedActor),
+            ("BlockedByActor", ApiErrorKind::BlockedByActor),
+        ] {
+            assert!(KNOWN.contains(&(code, kind.clone())));
+            let error = api_error(code);
+            assert_eq!(error.kind(), kind);
+            assert!(error.kind().is_permanent(), "{code}");
+            let error = BiskyError::ApiError(error);
+            assert_eq!(error.api_error_kind(), Some(kind));
+        }
+
+        let error = api_error("AccountSuspended");
+        assert_eq!(
+            error.kind(),
+            ApiErrorKind::Other("AccountSuspended".to_string())
+        );
+        assert!(!error.kind().is_permanent());
+        assert_eq!(
+            BiskyError::ApiError(error).api_error_kind(),
+            Some(ApiErrorKind::Other("AccountSuspended".to_string()))
+        );
+
+        // Blocks mapped to their own variant keep their kind
+        assert_eq!(
+            BiskyError::BlockedByActor("bob.test".to_string()).api_error_kind(),
+            Some(ApiErrorKind::BlockedByActor)
+        );
+    }
 }
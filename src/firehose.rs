@@ -0,0 +1,1142 @@
+//! Consumers of the websocket event streams, whose frames are a DAG-CBOR header followed by a
+//! DAG-CBOR body
+//!
+//! The streams reopen a dropped or idle connection from their cursor as `ReconnectPolicy` says,
+//! and yield a `Reconnected` event each time so consumers can log it.
+
+use crate::errors::{ApiError, BiskyError};
+use crate::lexicon::com::atproto::label::LabelStreamEvent;
+use crate::lexicon::com::atproto::sync::{RepoOpAction, RepoStreamEvent};
+use crate::storage::Storage;
+use crate::websocket::{Connection, Received, ReconnectPolicy};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Debug, Deserialize)]
+struct FrameHeader {
+    /// 1 for a message, -1 for an error
+    op: i64,
+    /// The message type, e.g. `#labels`, absent on errors
+    t: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorFrame {
+    error: String,
+    message: Option<String>,
+}
+
+/// One decoded frame, with its body left to be read by message type
+pub(crate) struct Frame<'a> {
+    pub(crate) t: String,
+    body: &'a [u8],
+}
+
+impl Frame<'_> {
+    pub(crate) fn body<T: DeserializeOwned>(&self) -> Result<T, BiskyError> {
+        serde_ipld_dagcbor::de::from_reader_once(self.body).map_err(cbor_error)
+    }
+}
+
+fn cbor_error<E: std::fmt::Display>(e: E) -> BiskyError {
+    BiskyError::CborError(e.to_string())
+}
+
+/// Split a binary frame into its header and body. Error frames come back as a `FutureCursor` or
+/// an `ApiError`.
+pub(crate) fn decode_frame(bytes: &[u8]) -> Result<Frame<'_>, BiskyError> {
+    let mut body = bytes;
+    let header: FrameHeader =
+        serde_ipld_dagcbor::de::from_reader_once(&mut body).map_err(cbor_error)?;
+
+    if header.op == -1 {
+        let error: ErrorFrame =
+            serde_ipld_dagcbor::de::from_reader_once(body).map_err(cbor_error)?;
+        let message = error.message.unwrap_or_default();
+        return Err(match error.error.as_str() {
+            "FutureCursor" => BiskyError::FutureCursor(message),
+            _ => BiskyError::ApiError(ApiError {
+                error: error.error,
+                message,
+                context: None,
+            }),
+        });
+    }
+
+    match header.t {
+        Some(t) if header.op == 1 => Ok(Frame { t, body }),
+        _ => Err(BiskyError::CborError(format!(
+            "Unexpected frame header, op {}",
+            header.op
+        ))),
+    }
+}
+
+/// The websocket url of `nsid` on `host`, which is a bare host name or an http(s) url such as a
+/// service endpoint from a DID document
+pub(crate) fn subscription_url(host: &str, nsid: &str) -> String {
+    let host = host.trim_end_matches('/');
+    let base = if let Some(rest) = host.strip_prefix("https://") {
+        format!("wss://{rest}")
+    } else if let Some(rest) = host.strip_prefix("http://") {
+        format!("ws://{rest}")
+    } else if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("wss://{host}")
+    };
+    format!("{base}/xrpc/{nsid}")
+}
+
+fn decode_label_event(bytes: &[u8]) -> Result<LabelStreamEvent, BiskyError> {
+    let frame = decode_frame(bytes)?;
+    Ok(match frame.t.as_str() {
+        "#labels" => LabelStreamEvent::Labels(frame.body()?),
+        "#info" => LabelStreamEvent::Info(frame.body()?),
+        _ => LabelStreamEvent::Unknown(frame.t),
+    })
+}
+
+/// `url` picking up after `cursor` if given
+fn with_cursor(url: &str, cursor: Option<i64>) -> String {
+    match cursor {
+        Some(cursor) => format!("{url}?cursor={cursor}"),
+        None => url.to_string(),
+    }
+}
+
+fn decode_repo_event(bytes: &[u8]) -> Result<RepoStreamEvent, BiskyError> {
+    let frame = decode_frame(bytes)?;
+    Ok(match frame.t.as_str() {
+        "#commit" => RepoStreamEvent::Commit(Box::new(frame.body()?)),
+        "#sync" => RepoStreamEvent::Sync(frame.body()?),
+        "#identity" => RepoStreamEvent::Identity(frame.body()?),
+        "#account" => RepoStreamEvent::Account(frame.body()?),
+        "#info" => RepoStreamEvent::Info(frame.body()?),
+        _ => RepoStreamEvent::Unknown(frame.t),
+    })
+}
+
+///com.atproto.sync.subscribeRepos
+/// The events of every repo `relay_host` carries, such as `bsky.network`, from just after
+/// `cursor` or live without one.
+///
+/// Errors, including the relay's error frames, are yielded and the stream carries on, except
+/// after a `FutureCursor` error frame, which means the cursor belongs to another relay.
+pub fn repo_stream(
+    relay_host: &str,
+    cursor: Option<i64>,
+    reconnect: ReconnectPolicy,
+) -> impl Stream<Item = Result<RepoStreamEvent, BiskyError>> {
+    struct State {
+        url: String,
+        cursor: Option<i64>,
+        connection: Connection,
+        done: bool,
+    }
+
+    let state = State {
+        url: subscription_url(relay_host, "com.atproto.sync.subscribeRepos"),
+        cursor,
+        connection: Connection::new(reconnect),
+        done: false,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if state.done {
+                return None;
+            }
+
+            let url = &state.url;
+            let cursor = state.cursor;
+            let bytes = match state.connection.next(|| Ok(with_cursor(url, cursor))).await {
+                Ok(Received::Message(Message::Binary(bytes))) => bytes,
+                Ok(Received::Message(_)) => continue,
+                Ok(Received::Reconnected(reconnection)) => {
+                    return Some((Ok(RepoStreamEvent::Reconnected(reconnection)), state))
+                }
+                Err(e) => return Some((Err(e), state)),
+            };
+
+            match decode_repo_event(&bytes) {
+                Ok(event) => {
+                    state.connection.reset_backoff();
+                    if let Some(seq) = event.seq() {
+                        state.cursor = Some(seq);
+                    }
+                    return Some((Ok(event), state));
+                }
+                Err(e @ BiskyError::FutureCursor(_)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+                // The relay closes the connection after an error frame
+                Err(e @ BiskyError::ApiError(_)) => {
+                    state.connection.drop_connection(e.to_string());
+                    return Some((Err(e), state));
+                }
+                Err(e) => return Some((Err(e), state)),
+            }
+        }
+    })
+}
+
+/// Where `resumable_repo_stream` left off
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct RepoStreamState {
+    pub cursor: Option<i64>,
+}
+
+/// How often `resumable_repo_stream` stores its cursor, whichever comes first
+#[derive(Debug, Clone, Copy)]
+pub struct CursorSavePolicy {
+    pub every_events: u64,
+    pub every: Duration,
+}
+
+impl Default for CursorSavePolicy {
+    fn default() -> Self {
+        Self {
+            every_events: 1000,
+            every: Duration::from_secs(10),
+        }
+    }
+}
+
+///com.atproto.sync.subscribeRepos
+/// Like `repo_stream`, but picking up from the cursor in `storage`, or live if nothing is stored
+/// yet.
+///
+/// An event counts as handled once the next one is asked for, and the cursor of the last handled
+/// event is stored as often as `policy` says, so after a restart up to that many events are
+/// yielded again. If the stored cursor is older than what the relay keeps, a
+/// `RepoStreamEvent::Gap` is yielded before the events it does have.
+pub fn resumable_repo_stream<'a>(
+    relay_host: &str,
+    storage: &'a dyn Storage<RepoStreamState, Error = BiskyError>,
+    policy: CursorSavePolicy,
+    reconnect: ReconnectPolicy,
+) -> impl Stream<Item = Result<RepoStreamEvent, BiskyError>> + 'a {
+    struct State<'a> {
+        url: String,
+        storage: &'a dyn Storage<RepoStreamState, Error = BiskyError>,
+        /// The cursor of the last event yielded, `None` until it has been loaded
+        cursor: Option<RepoStreamState>,
+        /// The cursor that was stored last
+        saved: Option<i64>,
+        unsaved_events: u64,
+        saved_at: Instant,
+        /// The cursor the current connection was opened with
+        resumed_from: Option<i64>,
+        connection: Connection,
+        done: bool,
+    }
+
+    let state = State {
+        url: subscription_url(relay_host, "com.atproto.sync.subscribeRepos"),
+        storage,
+        cursor: None,
+        saved: None,
+        unsaved_events: 0,
+        saved_at: Instant::now(),
+        resumed_from: None,
+        connection: Connection::new(reconnect),
+        done: false,
+    };
+
+    futures::stream::unfold(state, move |mut state| async move {
+        loop {
+            if state.done {
+                return None;
+            }
+
+            let cursor = match state.cursor.as_ref() {
+                Some(cursor) => cursor.cursor,
+                None => {
+                    // Nothing stored yet reads as an error, most likely a missing file
+                    let stored = state.storage.get().await.unwrap_or_default();
+                    state.saved = stored.cursor;
+                    state.cursor.insert(stored).cursor
+                }
+            };
+
+            if cursor != state.saved
+                && (state.unsaved_events >= policy.every_events
+                    || state.saved_at.elapsed() >= policy.every)
+            {
+                state.unsaved_events = 0;
+                state.saved_at = Instant::now();
+                state.saved = cursor;
+                if let Err(e) = state.storage.set(state.cursor.as_ref()).await {
+                    return Some((Err(e), state));
+                }
+            }
+
+            let url = &state.url;
+            let resumed_from = &mut state.resumed_from;
+            let next = state.connection.next(|| {
+                *resumed_from = cursor;
+                Ok(with_cursor(url, cursor))
+            });
+            let bytes = match next.await {
+                Ok(Received::Message(Message::Binary(bytes))) => bytes,
+                Ok(Received::Message(_)) => continue,
+                Ok(Received::Reconnected(reconnection)) => {
+                    return Some((Ok(RepoStreamEvent::Reconnected(reconnection)), state))
+                }
+                Err(e) => return Some((Err(e), state)),
+            };
+
+            match decode_repo_event(&bytes) {
+                Ok(RepoStreamEvent::Info(info)) if info.name == "OutdatedCursor" => {
+                    let event = match state.resumed_from {
+                        Some(cursor) => RepoStreamEvent::Gap { cursor },
+                        None => RepoStreamEvent::Info(info),
+                    };
+                    return Some((Ok(event), state));
+                }
+                Ok(event) => {
+                    state.connection.reset_backoff();
+                    if let Some(seq) = event.seq() {
+                        state.cursor = Some(RepoStreamState { cursor: Some(seq) });
+                        state.unsaved_events += 1;
+                    }
+                    return Some((Ok(event), state));
+                }
+                Err(e @ BiskyError::FutureCursor(_)) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+                // The relay closes the connection after an error frame
+                Err(e @ BiskyError::ApiError(_)) => {
+                    state.connection.drop_connection(e.to_string());
+                    return Some((Err(e), state));
+                }
+                Err(e) => return Some((Err(e), state)),
+            }
+        }
+    })
+}
+
+/// How many events a `RepoFilter` has seen and let through, to check it is cutting the volume
+#[derive(Debug, Default)]
+pub struct RepoFilterStats {
+    seen: AtomicU64,
+    yielded: AtomicU64,
+}
+
+impl RepoFilterStats {
+    pub fn seen(&self) -> u64 {
+        self.seen.load(Ordering::Relaxed)
+    }
+
+    pub fn yielded(&self) -> u64 {
+        self.yielded.load(Ordering::Relaxed)
+    }
+}
+
+/// Which repo stream events to keep, by repo, collection and action. Conditions compose, so an
+/// event is kept only if it meets all of them, and each condition left unset keeps everything.
+///
+/// A commit keeps only its ops that match, and is dropped when none do. The exception is a
+/// `too_big` commit from a wanted repo, which may leave its ops out, so it is kept to be fetched.
+/// Sync, identity and account events are kept for wanted repos, and the rest are always kept.
+#[derive(Debug, Clone, Default)]
+pub struct RepoFilter {
+    repos: Option<HashSet<String>>,
+    collections: Option<HashSet<String>>,
+    actions: Option<HashSet<RepoOpAction>>,
+    stats: Arc<RepoFilterStats>,
+}
+
+impl RepoFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep events of the repos `dids`
+    pub fn repos<S: Into<String>>(mut self, dids: impl IntoIterator<Item = S>) -> Self {
+        self.repos
+            .get_or_insert_with(HashSet::new)
+            .extend(dids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Keep ops on records in `collections`, such as `app.bsky.feed.post`
+    pub fn collections(mut self, collections: &[&str]) -> Self {
+        self.collections
+            .get_or_insert_with(HashSet::new)
+            .extend(collections.iter().map(|collection| collection.to_string()));
+        self
+    }
+
+    /// Keep ops that are one of `actions`
+    pub fn actions(mut self, actions: &[RepoOpAction]) -> Self {
+        self.actions
+            .get_or_insert_with(HashSet::new)
+            .extend(actions.iter().cloned());
+        self
+    }
+
+    /// The counts of this filter and its clones
+    pub fn stats(&self) -> Arc<RepoFilterStats> {
+        self.stats.clone()
+    }
+
+    fn wants_repo(&self, did: &str) -> bool {
+        self.repos.as_ref().is_none_or(|repos| repos.contains(did))
+    }
+
+    /// `event` with only the ops that match, or `None` if it should be dropped
+    pub fn apply(&self, event: RepoStreamEvent) -> Option<RepoStreamEvent> {
+        self.stats.seen.fetch_add(1, Ordering::Relaxed);
+        let event = match event {
+            RepoStreamEvent::Commit(mut commit) => {
+                if !self.wants_repo(&commit.repo) {
+                    return None;
+                }
+                if self.collections.is_some() || self.actions.is_some() {
+                    commit.ops.retain(|op| {
+                        let collection = op.path.split('/').next().unwrap_or_default();
+                        self.collections
+                            .as_ref()
+                            .is_none_or(|collections| collections.contains(collection))
+                            && self
+                                .actions
+                                .as_ref()
+                                .is_none_or(|actions| actions.contains(&op.action))
+                    });
+                    if commit.ops.is_empty() && !commit.too_big {
+                        return None;
+                    }
+                }
+                RepoStreamEvent::Commit(commit)
+            }
+            RepoStreamEvent::Sync(sync) if !self.wants_repo(&sync.did) => return None,
+            RepoStreamEvent::Identity(identity) if !self.wants_repo(&identity.did) => return None,
+            RepoStreamEvent::Account(account) if !self.wants_repo(&account.did) => return None,
+            event => event,
+        };
+        self.stats.yielded.fetch_add(1, Ordering::Relaxed);
+        Some(event)
+    }
+
+    /// `stream` with the events this filter drops left out. Errors are passed through.
+    pub fn filter_stream(
+        self,
+        stream: impl Stream<Item = Result<RepoStreamEvent, BiskyError>>,
+    ) -> impl Stream<Item = Result<RepoStreamEvent, BiskyError>> {
+        stream.filter_map(move |item| {
+            let item = match item {
+                Ok(event) => self.apply(event).map(Ok),
+                Err(e) => Some(Err(e)),
+            };
+            futures::future::ready(item)
+        })
+    }
+}
+
+/// How many events each worker of `for_each_concurrent_ordered` may have waiting before reading
+/// from the stream pauses
+const WORKER_QUEUE_LEN: usize = 64;
+
+/// How far `for_each_concurrent_ordered` is behind the stream
+#[derive(Debug, Default)]
+pub struct ProcessingLag {
+    received: AtomicI64,
+    processed: AtomicI64,
+    queued: AtomicUsize,
+}
+
+impl ProcessingLag {
+    /// The seq of the latest event read from the stream
+    pub fn received(&self) -> i64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// The highest seq of an event whose handler has finished
+    pub fn processed(&self) -> i64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// How many items are waiting for or in a handler
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// How many seqs processing is behind reading
+    pub fn lag(&self) -> i64 {
+        self.received() - self.processed()
+    }
+}
+
+/// Run `handler` on each item of `stream`, up to `workers` at a time, while handling the events
+/// of any one repo in the order they arrived. Events are spread over the workers by DID, and
+/// errors and events about the stream itself go to the first worker. Reading from the stream
+/// waits while the worker an event is bound for is too far behind.
+///
+/// The workers run concurrently on the calling task, so this suits handlers that spend their
+/// time waiting on I/O. Returns once the stream ends and every item has been handled.
+pub async fn for_each_concurrent_ordered<F, Fut>(
+    stream: impl Stream<Item = Result<RepoStreamEvent, BiskyError>>,
+    workers: usize,
+    lag: &ProcessingLag,
+    handler: F,
+) where
+    F: Fn(Result<RepoStreamEvent, BiskyError>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let workers = workers.max(1);
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..workers)
+        .map(|_| futures::channel::mpsc::channel(WORKER_QUEUE_LEN))
+        .unzip();
+
+    let read = async move {
+        let mut senders = senders;
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            let worker = match &item {
+                Ok(event) => {
+                    if let Some(seq) = event.seq() {
+                        lag.received.fetch_max(seq, Ordering::Relaxed);
+                    }
+                    event.did().map_or(0, |did| {
+                        let mut hasher = DefaultHasher::new();
+                        did.hash(&mut hasher);
+                        (hasher.finish() % workers as u64) as usize
+                    })
+                }
+                Err(_) => 0,
+            };
+            lag.queued.fetch_add(1, Ordering::Relaxed);
+            if futures::SinkExt::send(&mut senders[worker], item)
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        // Dropping the senders lets the workers finish their queues and stop
+    };
+
+    let handler = &handler;
+    let work = futures::future::join_all(receivers.into_iter().map(|mut receiver| async move {
+        while let Some(item) = receiver.next().await {
+            let seq = item.as_ref().ok().and_then(RepoStreamEvent::seq);
+            handler(item).await;
+            lag.queued.fetch_sub(1, Ordering::Relaxed);
+            if let Some(seq) = seq {
+                lag.processed.fetch_max(seq, Ordering::Relaxed);
+            }
+        }
+    }));
+
+    futures::future::join(read, work).await;
+}
+
+/// One repo stream shared by several consumers, each of which gets every event from when it
+/// subscribed
+///
+/// Subscribe first, then drive the hub with `run`, which reads the stream until it ends or every
+/// subscriber is gone. A subscriber that falls more than the hub's capacity behind misses the
+/// oldest events, and gets a `BiskyError::SubscriberLagged` saying how many in their place.
+pub struct FirehoseHub<S> {
+    stream: S,
+    sender: broadcast::Sender<Result<RepoStreamEvent, Arc<BiskyError>>>,
+}
+
+impl<S: Stream<Item = Result<RepoStreamEvent, BiskyError>>> FirehoseHub<S> {
+    /// Share `stream`, holding up to `capacity` events for subscribers that are behind
+    pub fn new(stream: S, capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { stream, sender }
+    }
+
+    /// The events of the stream, and its errors as `BiskyError::Shared`, from now on. Ends when
+    /// the hub stops.
+    pub fn subscribe(&self) -> impl Stream<Item = Result<RepoStreamEvent, BiskyError>> {
+        futures::stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            let item = match receiver.recv().await {
+                Ok(Ok(event)) => Ok(event),
+                Ok(Err(e)) => Err(BiskyError::Shared(e)),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    Err(BiskyError::SubscriberLagged(missed))
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            Some((item, receiver))
+        })
+    }
+
+    /// Pass the stream on to the subscribers until it ends or none are left
+    pub async fn run(self) {
+        let Self { stream, sender } = self;
+        futures::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            if sender.send(item.map_err(Arc::new)).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+///com.atproto.label.subscribeLabels
+/// The labels `labeler_host` publishes, from just after `cursor` or live without one. Each
+/// `#labels` event moves the cursor on, and a dropped connection is reopened from there.
+///
+/// Errors, including the labeler's error frames, are yielded and the stream carries on, so it
+/// only ends when dropped.
+pub fn label_stream(
+    labeler_host: &str,
+    cursor: Option<i64>,
+    reconnect: ReconnectPolicy,
+) -> impl Stream<Item = Result<LabelStreamEvent, BiskyError>> {
+    struct State {
+        url: String,
+        cursor: Option<i64>,
+        connection: Connection,
+    }
+
+    let state = State {
+        url: subscription_url(labeler_host, "com.atproto.label.subscribeLabels"),
+        cursor,
+        connection: Connection::new(reconnect),
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            let url = &state.url;
+            let cursor = state.cursor;
+            let bytes = match state.connection.next(|| Ok(with_cursor(url, cursor))).await {
+                Ok(Received::Message(Message::Binary(bytes))) => bytes,
+                Ok(Received::Message(_)) => continue,
+                Ok(Received::Reconnected(reconnection)) => {
+                    return Some((Ok(LabelStreamEvent::Reconnected(reconnection)), state))
+                }
+                Err(e) => return Some((Err(e), state)),
+            };
+
+            match decode_label_event(&bytes) {
+                Ok(event) => {
+                    state.connection.reset_backoff();
+                    if let LabelStreamEvent::Labels(labels) = &event {
+                        state.cursor = Some(labels.seq);
+                    }
+                    return Some((Ok(event), state));
+                }
+                // The labeler closes the connection after an error frame
+                Err(e @ (BiskyError::ApiError(_) | BiskyError::FutureCursor(_))) => {
+                    state.connection.drop_connection(e.to_string());
+                    return Some((Err(e), state));
+                }
+                Err(e) => return Some((Err(e), state)),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::Cid;
+    use ipld_core::ipld;
+    use ipld_core::ipld::Ipld;
+
+    const CAR: &[u8] = include_bytes!("../tests/fixtures/two-blocks.car");
+    const RECORD: &str = "bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom";
+    const COMMIT: &str = "bafyreigbtj4x7ip5legnfznufuopl4sg4knzc2cof6duas4b3q2fy6swua";
+
+    /// A frame as the relay sends it, the header's DAG-CBOR followed by the body's
+    fn frame(header: Ipld, body: Ipld) -> Vec<u8> {
+        let mut bytes = serde_ipld_dagcbor::to_vec(&header).unwrap();
+        bytes.extend(serde_ipld_dagcbor::to_vec(&body).unwrap());
+        bytes
+    }
+
+    fn cid(s: &str) -> Cid {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn commit_frame() {
+        let bytes = frame(
+            ipld!({"op": 1, "t": "#commit"}),
+            ipld!({
+                "seq": 42,
+                "tooBig": false,
+                "repo": "did:plc:alice",
+                "commit": Ipld::Link(cid(COMMIT)),
+                "rev": "3kdfsvlcf5c2a",
+                "since": null,
+                "blocks": Ipld::Bytes(CAR.to_vec()),
+                "ops": [
+                    {"action": "create", "path": "app.bsky.feed.post/3kdfsvlcf5c2a", "cid": Ipld::Link(cid(RECORD))},
+                    {"action": "delete", "path": "app.bsky.feed.like/3kdfsvlcf5c2b", "cid": null},
+                ],
+                "blobs": [],
+                "time": "2024-01-01T00:00:00.000Z",
+            }),
+        );
+
+        let RepoStreamEvent::Commit(commit) = decode_repo_event(&bytes).unwrap() else {
+            panic!("not a commit");
+        };
+        assert_eq!(commit.seq, 42);
+        assert_eq!(commit.repo, "did:plc:alice");
+        assert_eq!(commit.commit, cid(COMMIT));
+        assert_eq!(commit.since, None);
+        assert_eq!(commit.blocks, CAR);
+        assert_eq!(commit.prev_data, None);
+        assert_eq!(commit.ops.len(), 2);
+        assert!(matches!(commit.ops[0].action, RepoOpAction::Create));
+        assert_eq!(commit.ops[0].cid, Some(cid(RECORD)));
+        assert_eq!(
+            commit.ops[0].collection_rkey(),
+            Some(("app.bsky.feed.post", "3kdfsvlcf5c2a"))
+        );
+        assert!(matches!(commit.ops[1].action, RepoOpAction::Delete));
+        assert_eq!(commit.ops[1].cid, None);
+        assert!(commit.records_to_fetch().is_empty());
+    }
+
+    #[test]
+    fn too_big_commit_frame() {
+        // Without blocks, and with the ops that are left to fetch
+        let bytes = frame(
+            ipld!({"op": 1, "t": "#commit"}),
+            ipld!({
+                "seq": 43,
+                "tooBig": true,
+                "repo": "did:plc:alice",
+                "commit": Ipld::Link(cid(COMMIT)),
+                "rev": "3kdfsvlcf5c2c",
+                "since": "3kdfsvlcf5c2a",
+                "ops": [
+                    {"action": "update", "path": "app.bsky.actor.profile/self", "cid": Ipld::Link(cid(RECORD))},
+                ],
+                "time": "2024-01-01T00:00:00.000Z",
+            }),
+        );
+
+        let RepoStreamEvent::Commit(commit) = decode_repo_event(&bytes).unwrap() else {
+            panic!("not a commit");
+        };
+        assert!(commit.blocks.is_empty());
+        assert_eq!(commit.since.as_deref(), Some("3kdfsvlcf5c2a"));
+        let uris: Vec<_> = commit
+            .records_to_fetch()
+            .iter()
+            .map(|uri| uri.to_string())
+            .collect();
+        assert_eq!(uris, ["at://did:plc:alice/app.bsky.actor.profile/self"]);
+    }
+
+    #[test]
+    fn identity_frame() {
+        let bytes = frame(
+            ipld!({"op": 1, "t": "#identity"}),
+            ipld!({
+                "seq": 44,
+                "did": "did:plc:alice",
+                "time": "2024-01-01T00:00:00.000Z",
+                "handle": "alice.test",
+            }),
+        );
+
+        let event = decode_repo_event(&bytes).unwrap();
+        assert_eq!(event.seq(), Some(44));
+        let RepoStreamEvent::Identity(identity) = event else {
+            panic!("not an identity event");
+        };
+        assert_eq!(identity.did, "did:plc:alice");
+        assert_eq!(identity.handle.as_deref(), Some("alice.test"));
+    }
+
+    #[test]
+    fn error_frames() {
+        let bytes = frame(
+            ipld!({"op": -1}),
+            ipld!({"error": "ConsumerTooSlow", "message": "Stream consumer too slow"}),
+        );
+        match decode_repo_event(&bytes) {
+            Err(BiskyError::ApiError(e)) => {
+                assert_eq!(e.error, "ConsumerTooSlow");
+                assert_eq!(e.message, "Stream consumer too slow");
+            }
+            other => panic!("{other:?}"),
+        }
+
+        // Without a message
+        let bytes = frame(ipld!({"op": -1}), ipld!({"error": "FutureCursor"}));
+        match decode_repo_event(&bytes) {
+            Err(BiskyError::FutureCursor(message)) => assert_eq!(message, ""),
+            other => panic!("{other:?}"),
+        }
+
+        // Error frames also come through the label stream
+        let bytes = frame(
+            ipld!({"op": -1}),
+            ipld!({"error": "FutureCursor", "message": "Cursor in the future."}),
+        );
+        assert!(matches!(
+            decode_label_event(&bytes),
+            Err(BiskyError::FutureCursor(m)) if m == "Cursor in the future."
+        ));
+    }
+
+    #[test]
+    fn other_frames() {
+        // Message types this version doesn't know are passed on by name
+        let bytes = frame(
+            ipld!({"op": 1, "t": "#tombstone"}),
+            ipld!({"seq": 45, "did": "did:plc:alice"}),
+        );
+        assert!(matches!(
+            decode_repo_event(&bytes),
+            Ok(RepoStreamEvent::Unknown(t)) if t == "#tombstone"
+        ));
+
+        let bytes = frame(
+            ipld!({"op": 1, "t": "#info"}),
+            ipld!({"name": "OutdatedCursor"}),
+        );
+        assert!(matches!(
+            decode_repo_event(&bytes),
+            Ok(RepoStreamEvent::Info(info)) if info.name == "OutdatedCursor"
+        ));
+
+        // Neither a message nor an error
+        let bytes = frame(ipld!({"op": 2, "t": "#commit"}), ipld!({}));
+        assert!(matches!(
+            decode_repo_event(&bytes),
+            Err(BiskyError::CborError(_))
+        ));
+
+        // A body that isn't the message type's
+        let bytes = frame(ipld!({"op": 1, "t": "#identity"}), ipld!({"seq": "44"}));
+        assert!(matches!(
+            decode_repo_event(&bytes),
+            Err(BiskyError::CborError(_))
+        ));
+
+        // A header and nothing else
+        let bytes = serde_ipld_dagcbor::to_vec(&ipld!({"op": 1, "t": "#identity"})).unwrap();
+        assert!(matches!(
+            decode_repo_event(&bytes),
+            Err(BiskyError::CborError(_))
+        ));
+    }
+
+    #[test]
+    fn labels_frame() {
+        let bytes = frame(
+            ipld!({"op": 1, "t": "#labels"}),
+            ipld!({
+                "seq": 7,
+                "labels": [{
+                    "src": "did:plc:labeler",
+                    "uri": "at://did:plc:alice/app.bsky.feed.post/3kdfsvlcf5c2a",
+                    "val": "!warn",
+                    "cts": "2024-01-01T00:00:00.000Z",
+                    "sig": Ipld::Bytes(vec![1, 2, 3]),
+                    "ver": 1,
+                }],
+            }),
+        );
+        let Ok(LabelStreamEvent::Labels(labels)) = decode_label_event(&bytes) else {
+            panic!("not labels");
+        };
+        assert_eq!(labels.seq, 7);
+        assert_eq!(labels.labels[0].src, "did:plc:labeler");
+        assert_eq!(labels.labels[0].sig.as_deref(), Some(&[1, 2, 3][..]));
+        assert!(!labels.labels[0].neg);
+    }
+
+    #[test]
+    fn subscription_urls() {
+        let nsid = "com.atproto.sync.subscribeRepos";
+        assert_eq!(
+            subscription_url("bsky.network", nsid),
+            "wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos"
+        );
+        assert_eq!(
+            subscription_url("https://pds.example/", nsid),
+            "wss://pds.example/xrpc/com.atproto.sync.subscribeRepos"
+        );
+        assert_eq!(
+            subscription_url("http://localhost:2583", nsid),
+            "ws://localhost:2583/xrpc/com.atproto.sync.subscribeRepos"
+        );
+        assert_eq!(
+            with_cursor("wss://bsky.network/xrpc/x", Some(5)),
+            "wss://bsky.network/xrpc/x?cursor=5"
+        );
+    }
+
+    /// What the mock relay does on each connection, after sending up to so many events
+    enum Plan {
+        /// Close with a close frame
+        Close(usize),
+        /// Drop the connection without closing it
+        Reset(usize),
+        /// Send nothing more, leaving the connection open
+        Silent(usize),
+    }
+
+    /// A relay with the events `seqs` on `url`. Connections go as `plans` say in turn, and
+    /// after those stay open once the events run out.
+    struct MockRelay {
+        url: String,
+        /// The cursor each connection asked for
+        cursors: Arc<parking_lot::Mutex<Vec<Option<i64>>>>,
+    }
+
+    fn identity_event(seq: i64) -> Vec<u8> {
+        frame(
+            ipld!({"op": 1, "t": "#identity"}),
+            ipld!({"seq": seq, "did": format!("did:plc:user{seq}"), "time": "2024-01-01T00:00:00.000Z"}),
+        )
+    }
+
+    impl MockRelay {
+        async fn start(seqs: std::ops::RangeInclusive<i64>, plans: Vec<Plan>) -> Self {
+            use futures::SinkExt;
+            use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+            let cursors = Arc::new(parking_lot::Mutex::new(Vec::new()));
+            let mut plans = std::collections::VecDeque::from(plans);
+            let connections = cursors.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let mut cursor = None;
+                    // The handshake's error response is tungstenite's to size
+                    #[allow(clippy::result_large_err)]
+                    let callback = |request: &Request, response: Response| {
+                        cursor = request
+                            .uri()
+                            .query()
+                            .and_then(|query| query.strip_prefix("cursor=")?.parse::<i64>().ok());
+                        Ok(response)
+                    };
+                    let mut socket = tokio_tungstenite::accept_hdr_async(stream, callback)
+                        .await
+                        .unwrap();
+                    connections.lock().push(cursor);
+                    let plan = plans.pop_front().unwrap_or(Plan::Silent(usize::MAX));
+
+                    // As a relay does, starting from its oldest event if the cursor is older
+                    let from = match cursor {
+                        Some(cursor) if cursor < seqs.start() - 1 => {
+                            let info = frame(
+                                ipld!({"op": 1, "t": "#info"}),
+                                ipld!({"name": "OutdatedCursor", "message": "Requested cursor exceeded limit. Possibly missing events"}),
+                            );
+                            socket.send(Message::Binary(info)).await.unwrap();
+                            *seqs.start()
+                        }
+                        Some(cursor) => cursor + 1,
+                        None => *seqs.start(),
+                    };
+                    let (Plan::Close(count) | Plan::Reset(count) | Plan::Silent(count)) = plan;
+                    for seq in (from..=*seqs.end()).take(count) {
+                        socket
+                            .send(Message::Binary(identity_event(seq)))
+                            .await
+                            .unwrap();
+                    }
+                    match plan {
+                        Plan::Close(_) => socket.close(None).await.unwrap(),
+                        Plan::Reset(_) => drop(socket),
+                        Plan::Silent(_) => {
+                            // Held until the test ends
+                            tokio::spawn(async move {
+                                let _socket = socket;
+                                std::future::pending::<()>().await
+                            });
+                        }
+                    }
+                }
+            });
+            Self { url, cursors }
+        }
+
+        fn cursors(&self) -> Vec<Option<i64>> {
+            self.cursors.lock().clone()
+        }
+    }
+
+    /// Keeps the stream state in memory, as a file would between runs
+    #[derive(Default)]
+    struct MemoryStorage(parking_lot::Mutex<Option<RepoStreamState>>);
+
+    #[async_trait::async_trait]
+    impl Storage<RepoStreamState> for MemoryStorage {
+        type Error = BiskyError;
+
+        async fn set(&self, data: Option<&RepoStreamState>) -> Result<(), BiskyError> {
+            *self.0.lock() = data.cloned();
+            Ok(())
+        }
+
+        async fn get(&self) -> Result<RepoStreamState, BiskyError> {
+            self.0
+                .lock()
+                .clone()
+                .ok_or_else(|| BiskyError::StorageError("nothing stored".to_string()))
+        }
+    }
+
+    /// An event as its seq, and anything else the stream yields by name
+    fn describe(item: Result<RepoStreamEvent, BiskyError>) -> String {
+        match item {
+            Ok(RepoStreamEvent::Identity(identity)) => identity.seq.to_string(),
+            Ok(RepoStreamEvent::Gap { cursor }) => format!("gap after {cursor}"),
+            Ok(RepoStreamEvent::Reconnected(reconnection)) => format!(
+                "reconnected, attempt {} after {:?}: {}",
+                reconnection.attempt, reconnection.delay, reconnection.reason
+            ),
+            Ok(event) => format!("{event:?}"),
+            Err(e) => format!("error: {e}"),
+        }
+    }
+
+    /// What `stream` yields, until nothing comes for a while
+    async fn drain(stream: impl Stream<Item = Result<RepoStreamEvent, BiskyError>>) -> Vec<String> {
+        futures::pin_mut!(stream);
+        let mut seen = Vec::new();
+        while let Ok(Some(item)) =
+            tokio::time::timeout(Duration::from_millis(300), stream.next()).await
+        {
+            seen.push(describe(item));
+        }
+        seen
+    }
+
+    fn seqs(seqs: std::ops::RangeInclusive<i64>) -> Vec<String> {
+        seqs.map(|seq| seq.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn resumed_from_the_stored_cursor() {
+        let reconnect = ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        // Stored after every event
+        let policy = CursorSavePolicy {
+            every_events: 1,
+            every: Duration::from_secs(3600),
+        };
+        let storage = MemoryStorage::default();
+
+        // Live from the start, then stopped while waiting for the 13th event
+        let relay = MockRelay::start(1..=30, vec![Plan::Silent(12)]).await;
+        let first = drain(resumable_repo_stream(
+            &relay.url, &storage, policy, reconnect,
+        ))
+        .await;
+        assert_eq!(first, seqs(1..=12));
+        assert_eq!(storage.get().await.unwrap().cursor, Some(12));
+
+        // Started again, from the 13th, and reconnecting from the 17th when the relay hangs up
+        let relay = MockRelay::start(1..=30, vec![Plan::Close(4)]).await;
+        let second = drain(resumable_repo_stream(
+            &relay.url, &storage, policy, reconnect,
+        ))
+        .await;
+        assert_eq!(
+            second,
+            [
+                seqs(13..=16),
+                vec!["reconnected, attempt 1 after 10ms: Connection closed".to_string()],
+                seqs(17..=30)
+            ]
+            .concat()
+        );
+        assert_eq!(relay.cursors(), [Some(12), Some(16)]);
+        assert_eq!(storage.get().await.unwrap().cursor, Some(30));
+
+        // Every event once, over both runs
+        let mut all = [first, second]
+            .concat()
+            .iter()
+            .filter_map(|seen| seen.parse::<i64>().ok())
+            .collect::<Vec<_>>();
+        all.sort();
+        assert_eq!(all, (1..=30).collect::<Vec<_>>());
+
+        // A relay that no longer has events that old says so, and the gap is yielded first
+        let relay = MockRelay::start(41..=45, vec![]).await;
+        let third = drain(resumable_repo_stream(
+            &relay.url, &storage, policy, reconnect,
+        ))
+        .await;
+        assert_eq!(
+            third,
+            [vec!["gap after 30".to_string()], seqs(41..=45)].concat()
+        );
+        assert_eq!(relay.cursors(), [Some(30)]);
+        assert_eq!(storage.get().await.unwrap().cursor, Some(45));
+    }
+
+    #[tokio::test]
+    async fn reconnected_with_backoff() {
+        let reconnect = ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(15),
+            idle_timeout: Some(Duration::from_millis(200)),
+        };
+        // Closed, dropped at once, then gone quiet after two more events
+        let relay =
+            MockRelay::start(1..=7, vec![Plan::Close(3), Plan::Reset(0), Plan::Silent(2)]).await;
+        let stream = repo_stream(&relay.url, None, reconnect);
+        futures::pin_mut!(stream);
+        let mut seen = Vec::new();
+        while seen.last().map(String::as_str) != Some("7") {
+            let item = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                .await
+                .unwrap()
+                .unwrap();
+            seen.push(describe(item));
+        }
+
+        // Whether the dropped connection is read as an error or as the end of the stream is up
+        // to the socket, so only its reconnection is checked
+        let errors = seen.iter().filter(|seen| seen.starts_with("error: "));
+        assert!(errors.count() <= 1, "{seen:?}");
+        seen.retain(|seen| !seen.starts_with("error: "));
+        assert_eq!(seen.len(), 10, "{seen:?}");
+        assert_eq!(seen[..3], seqs(1..=3));
+        assert_eq!(
+            seen[3],
+            "reconnected, attempt 1 after 10ms: Connection closed"
+        );
+        // Doubled, up to the longest wait
+        assert!(
+            seen[4].starts_with("reconnected, attempt 2 after 15ms: "),
+            "{seen:?}"
+        );
+        assert_eq!(seen[5..7], seqs(4..=5));
+        // Back to the first wait once events came through, when the quiet connection is given up
+        assert_eq!(
+            seen[7],
+            "reconnected, attempt 1 after 10ms: Nothing received for 200ms"
+        );
+        assert_eq!(seen[8..], seqs(6..=7));
+        assert_eq!(relay.cursors(), [None, Some(3), Some(3), Some(5)]);
+    }
+}
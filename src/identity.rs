@@ -0,0 +1,815 @@
+//! Handle and DID resolution outside of XRPC: DNS, `.well-known` and DID documents.
+
+use crate::atproto::{
+    check_status, read_body, read_json, Client, DEFAULT_MAX_RESPONSE_SIZE, PUBLIC_APPVIEW,
+};
+use crate::errors::{ApiError, ApiErrorKind, BiskyError};
+use crate::lexicon::com::atproto::identity::ResolveHandleOutput;
+use crate::lexicon::com::atproto::repo::DescribeRepoOutput;
+use crate::types::IntoUrl;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where DID documents of `did:plc` identities are published
+pub const PLC_DIRECTORY: &str = "https://plc.directory";
+
+/// How much of a `.well-known/atproto-did` response is read
+const MAX_DID_SIZE: usize = 2048;
+
+/// A DID document, as far as atproto uses it
+#[derive(Debug, Clone, Deserialize)]
+pub struct DidDocument {
+    pub id: String,
+    /// `at://<handle>` uris the identity claims
+    #[serde(rename(deserialize = "alsoKnownAs"), default)]
+    pub also_known_as: Vec<String>,
+    #[serde(rename(deserialize = "verificationMethod"), default)]
+    pub verification_method: Vec<VerificationMethod>,
+    #[serde(default)]
+    pub service: Vec<DidService>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerificationMethod {
+    pub id: String,
+    #[serde(rename(deserialize = "type"))]
+    pub kind: String,
+    pub controller: String,
+    #[serde(rename(deserialize = "publicKeyMultibase"))]
+    pub public_key_multibase: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DidService {
+    pub id: String,
+    #[serde(rename(deserialize = "type"))]
+    pub kind: String,
+    #[serde(rename(deserialize = "serviceEndpoint"))]
+    pub service_endpoint: String,
+}
+
+impl DidDocument {
+    /// The handles the document claims, without the `at://` prefix
+    pub fn handles(&self) -> impl Iterator<Item = &str> {
+        self.also_known_as
+            .iter()
+            .filter_map(|aka| aka.strip_prefix("at://"))
+    }
+
+    /// The handle the document claims first, which is the one in use
+    pub fn handle(&self) -> Option<&str> {
+        self.handles().next()
+    }
+
+    /// The multibase public key the identity's repo commits are signed with
+    pub fn signing_key(&self) -> Option<&str> {
+        self.verification_method
+            .iter()
+            .find(|method| method.id.ends_with("#atproto"))
+            .and_then(|method| method.public_key_multibase.as_deref())
+    }
+
+    /// The endpoint of the identity's PDS
+    pub fn pds_endpoint(&self) -> Option<&str> {
+        self.service
+            .iter()
+            .find(|s| s.id.ends_with("#atproto_pds"))
+            .map(|s| s.service_endpoint.as_str())
+    }
+}
+
+/// Fetch the DID document of a `did:plc` from the PLC directory or of a `did:web` from its
+/// host. Needs no session and caches nothing.
+pub async fn resolve_did(did: &str) -> Result<DidDocument, BiskyError> {
+    let url = if did.starts_with("did:plc:") {
+        format!("{PLC_DIRECTORY}/{did}")
+    } else if let Some(id) = did.strip_prefix("did:web:") {
+        // `did:web:example.com%3A8080:user` lives at `https://example.com:8080/user/did.json`
+        let mut parts = id.split(':').map(|part| part.replace("%3A", ":"));
+        match parts.next().filter(|host| !host.is_empty()) {
+            Some(host) => {
+                let path = parts.collect::<Vec<_>>();
+                if path.is_empty() {
+                    format!("https://{host}/.well-known/did.json")
+                } else {
+                    format!("https://{host}/{}/did.json", path.join("/"))
+                }
+            }
+            None => return Err(BiskyError::UnsupportedDid(did.to_string())),
+        }
+    } else {
+        return Err(BiskyError::UnsupportedDid(did.to_string()));
+    };
+
+    let response = reqwest::get(url).await?.error_for_status()?;
+    read_json(response, Some(DEFAULT_MAX_RESPONSE_SIZE)).await
+}
+
+/// An operation in a `did:plc` history. Legacy `create` operations name the handle and PDS
+/// directly, later `plc_operation`s use `also_known_as` and `services`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PlcOperation {
+    /// `plc_operation`, `plc_tombstone` or the legacy `create`
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(rename = "alsoKnownAs", default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
+    #[serde(
+        rename = "rotationKeys",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub rotation_keys: Vec<String>,
+    /// Keyed by service id, e.g. `atproto_pds`
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub services: serde_json::Map<String, serde_json::Value>,
+    /// The CID of the operation this one follows, `None` for the first
+    pub prev: Option<String>,
+    /// Everything else, e.g. `verificationMethods`, `sig`, or a legacy `handle` and `service`
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl PlcOperation {
+    /// The handles after this operation, without the `at://` prefix
+    pub fn handles(&self) -> Vec<&str> {
+        match self.extra.get("handle").and_then(|h| h.as_str()) {
+            Some(handle) => vec![handle],
+            None => self
+                .also_known_as
+                .iter()
+                .filter_map(|aka| aka.strip_prefix("at://"))
+                .collect(),
+        }
+    }
+
+    /// The PDS after this operation
+    pub fn pds_endpoint(&self) -> Option<&str> {
+        match self.services.get("atproto_pds") {
+            Some(pds) => pds.get("endpoint")?.as_str(),
+            None => self.extra.get("service")?.as_str(),
+        }
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.kind == "plc_tombstone"
+    }
+}
+
+/// An entry of a PLC directory audit log
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PlcLogEntry {
+    pub did: String,
+    pub operation: PlcOperation,
+    pub cid: String,
+    /// Set for operations a rotation key later overrode, which are no longer in effect
+    pub nullified: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// The full operation history of a `did:plc` from the PLC directory, oldest first
+pub async fn plc_audit_log(did: &str) -> Result<Vec<PlcLogEntry>, BiskyError> {
+    if !did.starts_with("did:plc:") {
+        return Err(BiskyError::UnsupportedDid(did.to_string()));
+    }
+
+    let response = reqwest::get(format!("{PLC_DIRECTORY}/{did}/log/audit"))
+        .await?
+        .error_for_status()?;
+    read_json(response, Some(DEFAULT_MAX_RESPONSE_SIZE)).await
+}
+
+/// Handles are case-insensitive and often written with a leading `@`. Only one `@` is taken
+/// off, so `@@alice.test` stays invalid rather than passing for `alice.test`.
+pub fn normalize_handle(handle: &str) -> String {
+    let handle = handle.trim();
+    handle.strip_prefix('@').unwrap_or(handle).to_lowercase()
+}
+
+/// Resolve a handle through com.atproto.identity.resolveHandle on `service`, without a session,
+/// e.g. against `PUBLIC_APPVIEW`
+pub async fn resolve_handle(service: impl IntoUrl, handle: &str) -> Result<String, BiskyError> {
+    let service = service.into_url()?;
+    let handle = normalize_handle(handle);
+    let response = reqwest::Client::new()
+        .get(
+            service
+                .join("xrpc/com.atproto.identity.resolveHandle")
+                .unwrap(),
+        )
+        .query(&[("handle", handle.as_str())])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::BAD_REQUEST {
+        return Err(map_resolve_error(ApiError::from_response(response).await));
+    }
+
+    Ok(read_json::<ResolveHandleOutput>(
+        check_status(response).await?,
+        Some(DEFAULT_MAX_RESPONSE_SIZE),
+    )
+    .await?
+    .did)
+}
+
+/// Servers report an unknown handle either with its own error or as an invalid request
+pub(crate) fn map_resolve_error(error: ApiError) -> BiskyError {
+    if error.kind() == ApiErrorKind::HandleNotFound
+        || error.message.contains("Unable to resolve handle")
+    {
+        BiskyError::HandleNotFound(error.message)
+    } else {
+        BiskyError::ApiError(error)
+    }
+}
+
+/// Resolve a handle through `https://<handle>/.well-known/atproto-did`
+pub async fn resolve_handle_http(handle: &str) -> Result<Option<String>, BiskyError> {
+    let response = reqwest::get(format!("https://{handle}/.well-known/atproto-did")).await?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    // A DID is short, so anything longer isn't one
+    let body = match read_body(response, Some(MAX_DID_SIZE)).await {
+        Err(BiskyError::ResponseTooLarge { .. }) => return Ok(None),
+        body => body?,
+    };
+    let did = String::from_utf8_lossy(&body).trim().to_string();
+    Ok(did.starts_with("did:").then_some(did))
+}
+
+/// Resolve a handle through the `_atproto.<handle>` TXT record
+#[cfg(feature = "dns")]
+pub async fn resolve_handle_dns(handle: &str) -> Result<Option<String>, BiskyError> {
+    let resolver = hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()
+        .map_err(|e| BiskyError::DnsError(e.to_string()))?;
+    let lookup = match resolver.txt_lookup(format!("_atproto.{handle}.")).await {
+        Ok(lookup) => lookup,
+        Err(e)
+            if matches!(
+                e.kind(),
+                hickory_resolver::error::ResolveErrorKind::NoRecordsFound { .. }
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(BiskyError::DnsError(e.to_string())),
+    };
+
+    Ok(lookup.iter().find_map(|txt| {
+        let value = txt
+            .txt_data()
+            .iter()
+            .map(|part| String::from_utf8_lossy(part))
+            .collect::<String>();
+        value.strip_prefix("did=").map(str::to_string)
+    }))
+}
+
+/// Resolve a handle over DNS (with the `dns` feature), then HTTP, then `PUBLIC_APPVIEW`,
+/// without a session. Fails with `BiskyError::HandleNotFound` if none of them know it.
+pub async fn discover_did(handle: &str) -> Result<String, BiskyError> {
+    let handle = normalize_handle(handle);
+
+    #[cfg(feature = "dns")]
+    if let Ok(Some(did)) = resolve_handle_dns(&handle).await {
+        return Ok(did);
+    }
+    if let Ok(Some(did)) = resolve_handle_http(&handle).await {
+        return Ok(did);
+    }
+    let appview = reqwest::Url::parse(PUBLIC_APPVIEW).unwrap();
+    match resolve_handle(&appview, &handle).await {
+        Err(BiskyError::ApiError(_)) => Err(BiskyError::HandleNotFound(handle)),
+        result => result,
+    }
+}
+
+impl Client {
+    /// Log in knowing only the handle: resolve it to a DID, look up the DID's PDS and log in
+    /// there, pointing the client at that PDS. The session goes to the client's storage as with
+    /// `login`.
+    ///
+    /// Fails with `BiskyError::HandleNotFound` if the handle doesn't resolve,
+    /// `BiskyError::NoPdsEndpoint` if the DID document names no PDS and
+    /// `BiskyError::BadCredentials` if the password is wrong.
+    pub async fn login_with_handle_discovery(
+        &mut self,
+        handle: &str,
+        password: &str,
+    ) -> Result<(), BiskyError> {
+        let handle = normalize_handle(handle);
+        let did = discover_did(&handle).await?;
+        let document = resolve_did(&did).await?;
+        let pds = document
+            .pds_endpoint()
+            .and_then(|endpoint| reqwest::Url::parse(endpoint).ok())
+            .ok_or_else(|| BiskyError::NoPdsEndpoint(did.clone()))?;
+
+        self.login(&pds, &handle, password).await?;
+        self.set_service(pds);
+        Ok(())
+    }
+}
+
+/// How a handle resolved to a DID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HandleResolution {
+    Dns,
+    Http,
+    Xrpc,
+}
+
+/// The result of `Client::verify_handle`
+#[derive(Debug)]
+pub struct HandleVerification {
+    pub handle: String,
+    /// The DID the handle resolved to, and how. DNS is preferred over HTTP over XRPC.
+    pub resolved: Option<(String, HandleResolution)>,
+    /// Every method that resolved the handle, with the DID it returned
+    pub resolutions: Vec<(HandleResolution, String)>,
+    /// The handles the resolved DID's document claims
+    pub document_handles: Vec<String>,
+}
+
+impl HandleVerification {
+    /// Whether the methods disagree about the DID
+    pub fn resolutions_disagree(&self) -> bool {
+        self.resolutions
+            .windows(2)
+            .any(|pair| pair[0].1 != pair[1].1)
+    }
+
+    /// Whether the DID document claims the handle back
+    pub fn document_matches(&self) -> bool {
+        self.document_handles
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(&self.handle))
+    }
+
+    /// The handle resolves, consistently, to a DID whose document claims the handle
+    pub fn is_valid(&self) -> bool {
+        self.resolved.is_some() && !self.resolutions_disagree() && self.document_matches()
+    }
+}
+
+impl Client {
+    /// Check a handle in both directions: that it resolves to a DID, over DNS (with the `dns`
+    /// feature), HTTP and this client's service, and that the DID document lists the handle.
+    pub async fn verify_handle(&mut self, handle: &str) -> Result<HandleVerification, BiskyError> {
+        let handle = normalize_handle(handle);
+        let mut resolutions = Vec::new();
+
+        #[cfg(feature = "dns")]
+        if let Ok(Some(did)) = resolve_handle_dns(&handle).await {
+            resolutions.push((HandleResolution::Dns, did));
+        }
+        if let Ok(Some(did)) = resolve_handle_http(&handle).await {
+            resolutions.push((HandleResolution::Http, did));
+        }
+        match self.identity_resolve_handle(&handle).await {
+            Ok(did) => resolutions.push((HandleResolution::Xrpc, did)),
+            Err(BiskyError::ApiError(_) | BiskyError::HandleNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        let resolved = resolutions
+            .first()
+            .map(|(method, did)| (did.clone(), *method));
+        let document_handles = match &resolved {
+            Some((did, _)) => resolve_did(did)
+                .await?
+                .handles()
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(HandleVerification {
+            handle,
+            resolved,
+            resolutions,
+            document_handles,
+        })
+    }
+}
+
+/// Resolves handles and DIDs, e.g. `CachingResolver`, or `PublicResolver` which asks the
+/// network every time
+#[async_trait::async_trait]
+pub trait Resolver {
+    /// The DID a handle belongs to, or `BiskyError::HandleNotFound`
+    async fn resolve_handle(&self, handle: &str) -> Result<String, BiskyError>;
+    async fn resolve_did(&self, did: &str) -> Result<DidDocument, BiskyError>;
+}
+
+/// Resolves with `discover_did` and `resolve_did`, without a session or a cache
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PublicResolver;
+
+#[async_trait::async_trait]
+impl Resolver for PublicResolver {
+    async fn resolve_handle(&self, handle: &str) -> Result<String, BiskyError> {
+        discover_did(handle).await
+    }
+
+    async fn resolve_did(&self, did: &str) -> Result<DidDocument, BiskyError> {
+        resolve_did(did).await
+    }
+}
+
+/// How often a `CachingResolver` answered from its cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// A least recently used cache whose entries also expire. `None` values record that the key
+/// doesn't exist.
+struct Lru<V> {
+    capacity: usize,
+    entries: HashMap<String, (Option<V>, Instant, u64)>,
+    /// Keys by when they were last used
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Option<V>> {
+        let (value, expires, used) = self.entries.get_mut(key)?;
+        if *expires <= Instant::now() {
+            let used = *used;
+            self.entries.remove(key);
+            self.order.remove(&used);
+            return None;
+        }
+        self.tick += 1;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: &str, value: Option<V>, ttl: Duration) {
+        self.tick += 1;
+        if let Some((_, _, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+        }
+        while self.entries.len() >= self.capacity {
+            match self.order.pop_first() {
+                Some((_, oldest)) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+        self.entries
+            .insert(key.to_string(), (value, Instant::now() + ttl, self.tick));
+        self.order.insert(self.tick, key.to_string());
+    }
+}
+
+/// Caches what another `Resolver` returns, not-found answers included for a shorter time.
+/// Concurrent lookups of the same key wait for the first one instead of all going to the
+/// network.
+pub struct CachingResolver<R = PublicResolver> {
+    inner: R,
+    ttl: Duration,
+    negative_ttl: Duration,
+    handles: Mutex<Lru<String>>,
+    documents: Mutex<Lru<DidDocument>>,
+    in_flight: Mutex<HashMap<String, Arc<futures::lock::Mutex<()>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachingResolver {
+    /// Cache up to `capacity` handles and as many DID documents, each for `ttl`. Not-found
+    /// answers are kept for at most 5 minutes, see `negative_ttl`.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self::wrap(PublicResolver, capacity, ttl)
+    }
+}
+
+impl<R: Resolver> CachingResolver<R> {
+    pub fn wrap(inner: R, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            negative_ttl: ttl.min(Duration::from_secs(300)),
+            handles: Mutex::new(Lru::new(capacity)),
+            documents: Mutex::new(Lru::new(capacity)),
+            in_flight: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// How long to remember that a handle or DID doesn't exist
+    pub fn negative_ttl(mut self, negative_ttl: Duration) -> Self {
+        self.negative_ttl = negative_ttl;
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Look `key` up in `cache`, or with `fetch` once for all concurrent callers
+    async fn cached<V: Clone>(
+        &self,
+        cache: &Mutex<Lru<V>>,
+        key: String,
+        fetch: impl Future<Output = Result<V, BiskyError>>,
+        not_found: impl Fn(&BiskyError) -> bool,
+        missing: impl Fn() -> BiskyError,
+    ) -> Result<V, BiskyError> {
+        let lookup = || cache.lock().unwrap().get(&key);
+        if let Some(value) = lookup() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return value.ok_or_else(&missing);
+        }
+
+        let flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let _guard = flight.lock().await;
+        // Whoever held the lock before may have fetched it already
+        if let Some(value) = lookup() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return value.ok_or_else(&missing);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let result = match fetch.await {
+            Ok(value) => {
+                let mut cache = cache.lock().unwrap();
+                cache.insert(&key, Some(value.clone()), self.ttl);
+                Ok(value)
+            }
+            Err(e) if not_found(&e) => {
+                let mut cache = cache.lock().unwrap();
+                cache.insert(&key, None, self.negative_ttl);
+                // The same error a cached answer gives
+                Err(missing())
+            }
+            // Other errors may be temporary, so the next lookup tries again
+            Err(e) => Err(e),
+        };
+        self.in_flight.lock().unwrap().remove(&key);
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: Resolver + Send + Sync> Resolver for CachingResolver<R> {
+    async fn resolve_handle(&self, handle: &str) -> Result<String, BiskyError> {
+        let handle = normalize_handle(handle);
+        self.cached(
+            &self.handles,
+            handle.clone(),
+            self.inner.resolve_handle(&handle),
+            |e| matches!(e, BiskyError::HandleNotFound(_)),
+            || BiskyError::HandleNotFound(handle.clone()),
+        )
+        .await
+    }
+
+    async fn resolve_did(&self, did: &str) -> Result<DidDocument, BiskyError> {
+        self.cached(
+            &self.documents,
+            did.to_string(),
+            self.inner.resolve_did(did),
+            |e| matches!(e, BiskyError::ReqwestError(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) || e.status() == Some(reqwest::StatusCode::GONE)),
+            || BiskyError::UnknownActor(did.to_string()),
+        )
+        .await
+    }
+}
+
+/// The result of `check_identity`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IdentityReport {
+    /// The handle or DID that was checked
+    pub input: String,
+    /// The input if it is a DID, otherwise what the handle resolved to
+    pub did: Option<String>,
+    /// The input if it is a handle, otherwise the handle the DID document claims
+    pub handle: Option<String>,
+    /// The handles the DID document claims
+    pub document_handles: Vec<String>,
+    /// The DID the `_atproto` TXT record names, only looked up with the `dns` feature
+    pub dns_did: Option<String>,
+    /// The DID `/.well-known/atproto-did` names
+    pub http_did: Option<String>,
+    /// The first method by which the handle resolved to `did`, if any did
+    pub resolves_back: Option<HandleResolution>,
+    pub pds_endpoint: Option<String>,
+    /// What the PDS's describeRepo says about the handle
+    pub pds_handle_is_correct: Option<bool>,
+    /// Whatever went wrong along the way, in order
+    pub errors: Vec<String>,
+}
+
+impl IdentityReport {
+    /// Handle and DID point at each other, and the PDS agrees
+    pub fn is_consistent(&self) -> bool {
+        self.resolves_back.is_some()
+            && self.handle.as_ref().is_some_and(|handle| {
+                self.document_handles
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(handle))
+            })
+            && self.pds_handle_is_correct != Some(false)
+    }
+}
+
+/// Check a handle or DID in both directions without a session: the DID and its document, that
+/// the handle resolves back to the DID over DNS or HTTP, and whether the PDS agrees. Problems
+/// are collected in the report rather than returned.
+pub async fn check_identity(input: &str) -> IdentityReport {
+    let input = input.trim();
+    let mut report = IdentityReport {
+        input: input.to_string(),
+        ..Default::default()
+    };
+
+    let handle = (!input.starts_with("did:")).then(|| normalize_handle(input));
+    if let Some(handle) = &handle {
+        resolve_into(&mut report, handle).await;
+        report.did = report.dns_did.clone().or_else(|| report.http_did.clone());
+        if report.did.is_none() {
+            report.errors.push(format!("{handle} does not resolve"));
+            report.handle = Some(handle.clone());
+            return report;
+        }
+    } else {
+        report.did = Some(input.to_string());
+    }
+    let did = report.did.clone().unwrap_or_default();
+
+    let document = match resolve_did(&did).await {
+        Ok(document) => document,
+        Err(e) => {
+            report.errors.push(format!("DID document: {e}"));
+            report.handle = handle;
+            return report;
+        }
+    };
+    report.document_handles = document.handles().map(str::to_string).collect();
+    report.pds_endpoint = document.pds_endpoint().map(str::to_string);
+
+    // Starting from a DID, check the handle the document claims
+    let handle = match handle {
+        Some(handle) => handle,
+        None => match document.handle() {
+            Some(handle) => {
+                let handle = normalize_handle(handle);
+                resolve_into(&mut report, &handle).await;
+                handle
+            }
+            None => {
+                report
+                    .errors
+                    .push("DID document claims no handle".to_string());
+                return report;
+            }
+        },
+    };
+    report.resolves_back = if report.dns_did.as_deref() == Some(did.as_str()) {
+        Some(HandleResolution::Dns)
+    } else if report.http_did.as_deref() == Some(did.as_str()) {
+        Some(HandleResolution::Http)
+    } else {
+        None
+    };
+    if report.resolves_back.is_none() {
+        report
+            .errors
+            .push(format!("{handle} does not resolve back to {did}"));
+    }
+    if !report
+        .document_handles
+        .iter()
+        .any(|h| h.eq_ignore_ascii_case(&handle))
+    {
+        report
+            .errors
+            .push(format!("DID document does not claim {handle}"));
+    }
+    report.handle = Some(handle);
+
+    match &report.pds_endpoint {
+        Some(endpoint) => match describe_repo(endpoint, &did).await {
+            Ok(handle_is_correct) => report.pds_handle_is_correct = Some(handle_is_correct),
+            Err(e) => report.errors.push(format!("describeRepo: {e}")),
+        },
+        None => report.errors.push("DID document names no PDS".to_string()),
+    }
+
+    report
+}
+
+/// Fill in the DIDs the handle resolves to over DNS and HTTP
+async fn resolve_into(report: &mut IdentityReport, handle: &str) {
+    #[cfg(feature = "dns")]
+    match resolve_handle_dns(handle).await {
+        Ok(did) => report.dns_did = did,
+        Err(e) => report.errors.push(format!("DNS: {e}")),
+    }
+    match resolve_handle_http(handle).await {
+        Ok(did) => report.http_did = did,
+        Err(e) => report.errors.push(format!("HTTP: {e}")),
+    }
+}
+
+async fn describe_repo(endpoint: &str, did: &str) -> Result<bool, BiskyError> {
+    let url = format!(
+        "{}/xrpc/com.atproto.repo.describeRepo",
+        endpoint.trim_end_matches('/')
+    );
+    let response = reqwest::Client::new()
+        .get(url)
+        .query(&[("repo", did)])
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::BAD_REQUEST {
+        return Err(BiskyError::ApiError(
+            ApiError::from_response(response).await,
+        ));
+    }
+
+    Ok(read_json::<DescribeRepoOutput>(
+        check_status(response).await?,
+        Some(DEFAULT_MAX_RESPONSE_SIZE),
+    )
+    .await?
+    .handle_is_correct)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_are_normalized() {
+        for (handle, normalized) in [
+            ("alice.test", "alice.test"),
+            ("@alice.test", "alice.test"),
+            ("Alice.Test", "alice.test"),
+            ("  @Alice.TEST\n", "alice.test"),
+            ("@@alice.test", "@alice.test"),
+            ("@ alice.test", " alice.test"),
+            ("alice@test", "alice@test"),
+        ] {
+            assert_eq!(normalize_handle(handle), normalized, "{handle:?}");
+        }
+    }
+
+    #[test]
+    fn unknown_handles() {
+        let error = |error: &str, message: &str| ApiError {
+            error: error.to_string(),
+            message: message.to_string(),
+            context: None,
+        };
+        assert!(matches!(
+            map_resolve_error(error("HandleNotFound", "")),
+            BiskyError::HandleNotFound(_)
+        ));
+        // As servers that predate the error code put it
+        assert!(matches!(
+            map_resolve_error(error("InvalidRequest", "Unable to resolve handle")),
+            BiskyError::HandleNotFound(_)
+        ));
+        assert!(matches!(
+            map_resolve_error(error(
+                "InvalidRequest",
+                "Error: handle must be a valid handle"
+            )),
+            BiskyError::ApiError(_)
+        ));
+    }
+}
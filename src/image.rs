@@ -0,0 +1,717 @@
+//! Getting images ready to upload: stripping the metadata cameras write, such as where a photo
+//! was taken, reading their size for the `aspectRatio` of the embed, and fitting them under the
+//! blob size limit. Scaling and re-encoding need an image codec, which this crate leaves to an
+//! `ImageCodec` the caller provides, e.g. one built on the `image` crate.
+
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::embed::AspectRatio;
+use std::fmt;
+use std::sync::Arc;
+
+/// The largest image blob the AppView accepts, in bytes
+pub const MAX_IMAGE_BYTES: usize = 1_000_000;
+/// The longest side the Bluesky app scales images down to
+pub const MAX_IMAGE_DIMENSION: u32 = 2000;
+
+/// The longest side `prepare_image` scales an image down to before giving up on fitting it
+const MIN_DIMENSION: u32 = 500;
+
+/// EXIF tag of the orientation, which is kept when the rest of the metadata is stripped
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// The PNG chunks that only hold metadata
+const PNG_METADATA_CHUNKS: &[&[u8]] = &[b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+/// A JPEG marker and the segment it starts
+type Segment<'a> = (u8, &'a [u8]);
+
+/// A WebP chunk's fourcc and data
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+}
+
+impl ImageFormat {
+    /// The format of `data`, from its first bytes
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else {
+            None
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+}
+
+/// Decodes, scales and encodes images for `prepare_image`
+pub trait ImageCodec: Send + Sync {
+    /// Decode `data`, turn it the way its EXIF orientation says, scale it down so that neither
+    /// side is longer than `max_dimension`, and encode it as a JPEG or WebP of `quality`, from 1
+    /// to 100, without metadata
+    fn reencode(&self, data: &[u8], max_dimension: u32, quality: u8)
+        -> Result<Vec<u8>, BiskyError>;
+}
+
+/// How `prepare_image` treats an image
+#[derive(Clone)]
+pub struct ImageOptions {
+    /// Images larger than this are re-encoded if there is a codec, and refused otherwise
+    pub max_bytes: usize,
+    /// Images with a longer side are scaled down if there is a codec, and left as they are
+    /// otherwise
+    pub max_dimension: u32,
+    /// Remove EXIF, XMP, IPTC and text metadata, other than the orientation
+    pub strip_metadata: bool,
+    /// The qualities to re-encode at, best first. Each is tried before the image is scaled down
+    /// further.
+    pub qualities: Vec<u8>,
+    pub codec: Option<Arc<dyn ImageCodec>>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: MAX_IMAGE_BYTES,
+            max_dimension: MAX_IMAGE_DIMENSION,
+            strip_metadata: true,
+            qualities: vec![90, 80, 70, 60],
+            codec: None,
+        }
+    }
+}
+
+impl ImageOptions {
+    /// Upload images as they are, for callers that prepare them themselves
+    pub fn passthrough() -> Self {
+        Self {
+            max_bytes: usize::MAX,
+            strip_metadata: false,
+            ..Self::default()
+        }
+    }
+}
+
+impl fmt::Debug for ImageOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageOptions")
+            .field("max_bytes", &self.max_bytes)
+            .field("max_dimension", &self.max_dimension)
+            .field("strip_metadata", &self.strip_metadata)
+            .field("qualities", &self.qualities)
+            .field("codec", &self.codec.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+/// An image ready to upload
+#[derive(Debug, Clone)]
+pub struct PreparedImage {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    /// The size it is shown at, with its EXIF orientation applied
+    pub aspect_ratio: AspectRatio,
+}
+
+/// Strip the metadata of a JPEG, PNG, WebP or GIF, then re-encode it with the codec, at lower
+/// qualities and sizes in turn, until it fits `options.max_bytes` and `options.max_dimension`.
+/// Fails with `BiskyError::ImageTooLarge` if it doesn't fit, or there is no codec and it is too
+/// many bytes.
+pub fn prepare_image(data: &[u8], options: &ImageOptions) -> Result<PreparedImage, BiskyError> {
+    let format = detect(data)?;
+    let data = match options.strip_metadata {
+        true => strip_metadata(data, format)?,
+        false => data.to_vec(),
+    };
+    let aspect_ratio = read_aspect_ratio(&data, format)?;
+    let longest = aspect_ratio.width.max(aspect_ratio.height);
+
+    let fits = data.len() <= options.max_bytes;
+    let codec = match &options.codec {
+        Some(codec) if !fits || longest > options.max_dimension as usize => codec,
+        _ if fits => {
+            return Ok(PreparedImage {
+                data,
+                format,
+                aspect_ratio,
+            })
+        }
+        _ => {
+            return Err(BiskyError::ImageTooLarge {
+                bytes: data.len(),
+                limit: options.max_bytes,
+            })
+        }
+    };
+
+    let mut dimension = options.max_dimension.min(longest as u32);
+    let mut smallest = data.len();
+    loop {
+        for &quality in &options.qualities {
+            let encoded = codec.reencode(&data, dimension, quality)?;
+            if encoded.len() <= options.max_bytes {
+                let format = detect(&encoded)?;
+                return Ok(PreparedImage {
+                    aspect_ratio: read_aspect_ratio(&encoded, format)?,
+                    data: encoded,
+                    format,
+                });
+            }
+            smallest = smallest.min(encoded.len());
+        }
+        if dimension <= MIN_DIMENSION {
+            return Err(BiskyError::ImageTooLarge {
+                bytes: smallest,
+                limit: options.max_bytes,
+            });
+        }
+        dimension = (dimension * 3 / 4).max(MIN_DIMENSION);
+    }
+}
+
+/// The width and height of an image, swapped if its EXIF orientation turns it on its side
+pub fn image_aspect_ratio(data: &[u8]) -> Result<AspectRatio, BiskyError> {
+    read_aspect_ratio(data, detect(data)?)
+}
+
+/// `data` without its metadata, other than the EXIF orientation of a JPEG. GIFs are left as
+/// they are.
+pub fn strip_metadata(data: &[u8], format: ImageFormat) -> Result<Vec<u8>, BiskyError> {
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(data),
+        ImageFormat::Png => strip_png(data),
+        ImageFormat::Webp => strip_webp(data),
+        ImageFormat::Gif => Ok(data.to_vec()),
+    }
+}
+
+fn detect(data: &[u8]) -> Result<ImageFormat, BiskyError> {
+    ImageFormat::detect(data)
+        .ok_or_else(|| BiskyError::InvalidImage("not a JPEG, PNG, WebP or GIF".to_string()))
+}
+
+fn truncated(format: ImageFormat) -> BiskyError {
+    BiskyError::InvalidImage(format!("truncated {}", format.mime_type()))
+}
+
+fn read_aspect_ratio(data: &[u8], format: ImageFormat) -> Result<AspectRatio, BiskyError> {
+    let (width, height) = match format {
+        ImageFormat::Jpeg => {
+            let (segments, _) = jpeg_segments(data)?;
+            let (width, height) = segments
+                .iter()
+                .find(|(marker, _)| is_start_of_frame(*marker))
+                .and_then(|(_, segment)| Some((be16(segment, 7)?, be16(segment, 5)?)))
+                .ok_or_else(|| truncated(format))?;
+            // Orientations 5 to 8 turn the image a quarter
+            let turned = segments
+                .iter()
+                .filter(|(marker, _)| *marker == 0xe1)
+                .find_map(|(_, segment)| exif_orientation(&segment[4..]))
+                .is_some_and(|orientation| (5..=8).contains(&orientation));
+            match turned {
+                true => (height as usize, width as usize),
+                false => (width as usize, height as usize),
+            }
+        }
+        ImageFormat::Png => {
+            let width = be32(data, 16).ok_or_else(|| truncated(format))?;
+            let height = be32(data, 20).ok_or_else(|| truncated(format))?;
+            (width as usize, height as usize)
+        }
+        ImageFormat::Webp => webp_chunks(data)?
+            .iter()
+            .find_map(|(fourcc, chunk)| webp_dimensions(fourcc, chunk))
+            .ok_or_else(|| truncated(format))?,
+        ImageFormat::Gif => {
+            let width = le16(data, 6).ok_or_else(|| truncated(format))?;
+            let height = le16(data, 8).ok_or_else(|| truncated(format))?;
+            (width as usize, height as usize)
+        }
+    };
+    Ok(AspectRatio { width, height })
+}
+
+fn is_start_of_frame(marker: u8) -> bool {
+    matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc)
+}
+
+/// The marker and bytes of each segment of a JPEG before the image data, and the image data
+fn jpeg_segments(data: &[u8]) -> Result<(Vec<Segment<'_>>, &[u8]), BiskyError> {
+    let format = ImageFormat::Jpeg;
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if data.get(pos) != Some(&0xff) {
+            return Err(truncated(format));
+        }
+        let marker = *data.get(pos + 1).ok_or_else(|| truncated(format))?;
+        match marker {
+            // Fill bytes before a marker
+            0xff => pos += 1,
+            // Start of scan, after which comes the image data
+            0xda => return Ok((segments, &data[pos..])),
+            0x01 | 0xd0..=0xd7 => {
+                segments.push((marker, &data[pos..pos + 2]));
+                pos += 2;
+            }
+            _ => {
+                let len = be16(data, pos + 2).ok_or_else(|| truncated(format))? as usize;
+                let end = pos + 2 + len;
+                if len < 2 || end > data.len() {
+                    return Err(truncated(format));
+                }
+                segments.push((marker, &data[pos..end]));
+                pos = end;
+            }
+        }
+    }
+}
+
+/// Keep the JFIF, ICC profile and Adobe segments, which change how the image looks, and drop
+/// the rest of the application segments and comments, replacing the EXIF with one that holds
+/// only the orientation
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>, BiskyError> {
+    let (segments, scan) = jpeg_segments(data)?;
+    let mut stripped = Vec::with_capacity(data.len());
+    stripped.extend_from_slice(&data[..2]);
+    for (marker, segment) in segments {
+        match marker {
+            0xe1 => {
+                let orientation = exif_orientation(&segment[4..]).filter(|o| *o != 1);
+                if let Some(orientation) = orientation {
+                    stripped.extend_from_slice(&orientation_exif(orientation));
+                }
+            }
+            0xe3..=0xed | 0xef | 0xfe => {}
+            _ => stripped.extend_from_slice(segment),
+        }
+    }
+    stripped.extend_from_slice(scan);
+    Ok(stripped)
+}
+
+/// The orientation in an APP1 segment's EXIF, if it has one
+fn exif_orientation(app1: &[u8]) -> Option<u16> {
+    let tiff = app1.strip_prefix(b"Exif\0\0")?;
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |i| match big_endian {
+        true => be16(tiff, i),
+        false => le16(tiff, i),
+    };
+    let ifd = match big_endian {
+        true => be32(tiff, 4)?,
+        false => le32(tiff, 4)?,
+    } as usize;
+    (0..u16_at(ifd)? as usize)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// An APP1 segment whose EXIF holds only `orientation`
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut tiff = Vec::from(*b"MM\0\x2a");
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    // One SHORT, padded to four bytes
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // No next IFD
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut segment = vec![0xff, 0xe1];
+    segment.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(&tiff);
+    segment
+}
+
+fn strip_png(data: &[u8]) -> Result<Vec<u8>, BiskyError> {
+    let mut stripped = data[..8].to_vec();
+    let mut pos = 8;
+    while pos < data.len() {
+        let len = be32(data, pos).ok_or_else(|| truncated(ImageFormat::Png))? as usize;
+        // Length, type, data and CRC
+        let end = pos + 12 + len;
+        if end > data.len() {
+            return Err(truncated(ImageFormat::Png));
+        }
+        if !PNG_METADATA_CHUNKS.contains(&&data[pos + 4..pos + 8]) {
+            stripped.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+    Ok(stripped)
+}
+
+/// The fourcc and data of each chunk of a WebP
+fn webp_chunks(data: &[u8]) -> Result<Vec<Chunk<'_>>, BiskyError> {
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos < data.len() {
+        let len = le32(data, pos + 4).ok_or_else(|| truncated(ImageFormat::Webp))? as usize;
+        let end = pos + 8 + len;
+        if end > data.len() {
+            return Err(truncated(ImageFormat::Webp));
+        }
+        let fourcc = data[pos..pos + 4].try_into().unwrap();
+        chunks.push((fourcc, &data[pos + 8..end]));
+        // Chunks are padded to an even length
+        pos = end + len % 2;
+    }
+    Ok(chunks)
+}
+
+fn webp_dimensions(fourcc: &[u8; 4], chunk: &[u8]) -> Option<(usize, usize)> {
+    match fourcc {
+        b"VP8X" => {
+            // Each less one, in three bytes
+            let size = chunk.get(4..10)?;
+            let width = u32::from_le_bytes([size[0], size[1], size[2], 0]) + 1;
+            let height = u32::from_le_bytes([size[3], size[4], size[5], 0]) + 1;
+            Some((width as usize, height as usize))
+        }
+        b"VP8 " => {
+            let width = le16(chunk, 6)? & 0x3fff;
+            let height = le16(chunk, 8)? & 0x3fff;
+            Some((width as usize, height as usize))
+        }
+        b"VP8L" => {
+            let bits = le32(chunk, 1)?;
+            let width = (bits & 0x3fff) + 1;
+            let height = ((bits >> 14) & 0x3fff) + 1;
+            Some((width as usize, height as usize))
+        }
+        _ => None,
+    }
+}
+
+/// Drop the EXIF and XMP chunks, and their flags in the extended header
+fn strip_webp(data: &[u8]) -> Result<Vec<u8>, BiskyError> {
+    let mut stripped = data[..12].to_vec();
+    for (fourcc, chunk) in webp_chunks(data)? {
+        if &fourcc == b"EXIF" || &fourcc == b"XMP " {
+            continue;
+        }
+        stripped.extend_from_slice(&fourcc);
+        stripped.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        let start = stripped.len();
+        stripped.extend_from_slice(chunk);
+        if &fourcc == b"VP8X" && !chunk.is_empty() {
+            stripped[start] &= !(0x08 | 0x04);
+        }
+        if chunk.len() % 2 == 1 {
+            stripped.push(0);
+        }
+    }
+    let riff_len = (stripped.len() - 8) as u32;
+    stripped[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Ok(stripped)
+}
+
+fn be16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    const CAMERA: &[u8] = b"PhoneCam X\0";
+    const COMMENT: &[u8] = b"Shot at 51.5007N 0.1246W";
+
+    /// A segment with its marker and length
+    fn segment(marker: u8, body: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xff, marker];
+        segment.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(body);
+        segment
+    }
+
+    /// A little endian EXIF, as phones write it, with the camera, the orientation and where the
+    /// photo was taken
+    fn exif(orientation: u16) -> Vec<u8> {
+        let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            let mut entry = tag.to_le_bytes().to_vec();
+            entry.extend_from_slice(&kind.to_le_bytes());
+            entry.extend_from_slice(&count.to_le_bytes());
+            entry.extend_from_slice(&value.to_le_bytes());
+            entry
+        };
+        // The header, three entries in IFD0 from 8, then the GPS IFD with two, then the data
+        let gps_ifd = 8 + 2 + 3 * 12 + 4;
+        let camera = gps_ifd + 2 + 2 * 12 + 4;
+        let latitude = camera + CAMERA.len() as u32;
+        let mut tiff = Vec::from(*b"II\x2a\0");
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend(entry(0x010f, 2, CAMERA.len() as u32, camera));
+        tiff.extend(entry(ORIENTATION_TAG, 3, 1, orientation.into()));
+        tiff.extend(entry(0x8825, 4, 1, gps_ifd));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        tiff.extend(entry(0x0001, 2, 2, u32::from_le_bytes(*b"N\0\0\0")));
+        tiff.extend(entry(0x0002, 5, 3, latitude));
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(CAMERA);
+        for (numerator, denominator) in [(51u32, 1u32), (30, 1), (26, 100)] {
+            tiff.extend_from_slice(&numerator.to_le_bytes());
+            tiff.extend_from_slice(&denominator.to_le_bytes());
+        }
+
+        let mut app1 = Vec::from(*b"Exif\0\0");
+        app1.extend(tiff);
+        app1
+    }
+
+    /// A JPEG of `width` by `height`, with `scan` bytes of image data, and EXIF, IPTC and a
+    /// comment if it comes from a camera
+    fn jpeg(width: u16, height: u16, scan: usize, orientation: Option<u16>) -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8];
+        jpeg.extend(segment(0xe0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"));
+        if let Some(orientation) = orientation {
+            jpeg.extend(segment(0xe1, &exif(orientation)));
+            jpeg.extend(segment(
+                0xed,
+                b"Photoshop 3.0\08BIM\x04\x04\0\0\0\0\0\x05\x1c\x02\x5a\0\x06London",
+            ));
+            jpeg.extend(segment(0xfe, COMMENT));
+        }
+        jpeg.extend(segment(0xdb, &[0; 65]));
+        let mut frame = vec![8];
+        frame.extend_from_slice(&height.to_be_bytes());
+        frame.extend_from_slice(&width.to_be_bytes());
+        frame.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        jpeg.extend(segment(0xc0, &frame));
+        jpeg.extend(segment(0xc4, &[0; 29]));
+        jpeg.extend(segment(0xda, &[3, 1, 0, 2, 0x11, 3, 0x11, 0, 0x3f, 0]));
+        // Entropy coded data never has a bare 0xff
+        let mut state = 0x2545_f491u32;
+        jpeg.extend((0..scan).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state % 0xff) as u8
+        }));
+        jpeg.extend_from_slice(&[0xff, 0xd9]);
+        jpeg
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    /// Whether any of the metadata `jpeg` gives a photo is left, other than the orientation
+    fn has_metadata(data: &[u8]) -> bool {
+        // Only the segments before the image data, which is the bulk of it
+        let (_, scan) = jpeg_segments(data).unwrap();
+        let headers = &data[..data.len() - scan.len()];
+        [CAMERA, COMMENT, b"Photoshop", &0x8825u16.to_le_bytes()]
+            .iter()
+            .any(|needle| contains(headers, needle))
+    }
+
+    /// Stands in for a real codec: the image it "encodes" is as big as its pixels times the
+    /// quality, in a JPEG of the size it was scaled to
+    #[derive(Default)]
+    struct FakeCodec {
+        /// The longest side and quality of every encode
+        calls: Mutex<Vec<(u32, u8)>>,
+        /// Whether what it was given still had metadata
+        given_metadata: Mutex<Vec<bool>>,
+    }
+
+    impl ImageCodec for FakeCodec {
+        fn reencode(
+            &self,
+            data: &[u8],
+            max_dimension: u32,
+            quality: u8,
+        ) -> Result<Vec<u8>, BiskyError> {
+            self.calls.lock().push((max_dimension, quality));
+            self.given_metadata.lock().push(has_metadata(data));
+            // Turned the way the orientation says, then scaled down
+            let AspectRatio { width, height } = image_aspect_ratio(data)?;
+            let longest = width.max(height);
+            let scale = |side: usize| match longest > max_dimension as usize {
+                true => (side * max_dimension as usize + longest / 2) / longest,
+                false => side,
+            };
+            let (width, height) = (scale(width), scale(height));
+            let bytes = width * height * quality as usize / 200;
+            Ok(jpeg(width as u16, height as u16, bytes, None))
+        }
+    }
+
+    fn with_codec(codec: &Arc<FakeCodec>) -> ImageOptions {
+        ImageOptions {
+            codec: Some(codec.clone()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn large_photo_fits_after_lower_qualities() {
+        // Taken on its side, so shown 3024 wide
+        let photo = jpeg(4032, 3024, 3_000_000, Some(6));
+        assert!(has_metadata(&photo));
+        let codec = Arc::new(FakeCodec::default());
+
+        let prepared = prepare_image(&photo, &with_codec(&codec)).unwrap();
+        assert!(
+            prepared.data.len() <= MAX_IMAGE_BYTES,
+            "{}",
+            prepared.data.len()
+        );
+        assert_eq!(prepared.format, ImageFormat::Jpeg);
+        assert_eq!(
+            prepared.aspect_ratio,
+            AspectRatio {
+                width: 1500,
+                height: 2000
+            }
+        );
+        assert!(!has_metadata(&prepared.data));
+        // Scaled to 2000 first, then tried at every quality until one fit
+        assert_eq!(
+            *codec.calls.lock(),
+            [(2000, 90), (2000, 80), (2000, 70), (2000, 60)]
+        );
+        // The codec only ever saw the photo without its metadata
+        assert!(codec.given_metadata.lock().iter().all(|given| !given));
+    }
+
+    #[test]
+    fn scaled_down_until_it_fits() {
+        let photo = jpeg(4032, 3024, 3_000_000, Some(1));
+        let codec = Arc::new(FakeCodec::default());
+        let options = ImageOptions {
+            max_bytes: 500_000,
+            ..with_codec(&codec)
+        };
+
+        let prepared = prepare_image(&photo, &options).unwrap();
+        assert!(prepared.data.len() <= 500_000);
+        assert_eq!(
+            prepared.aspect_ratio,
+            AspectRatio {
+                width: 1125,
+                height: 844
+            }
+        );
+        let dimensions = codec
+            .calls
+            .lock()
+            .iter()
+            .map(|(dimension, _)| *dimension)
+            .collect::<Vec<_>>();
+        assert_eq!(dimensions, [&[2000; 4][..], &[1500; 4], &[1125]].concat());
+
+        // Until the smallest size it goes to, then it gives up
+        let codec = Arc::new(FakeCodec::default());
+        let options = ImageOptions {
+            max_bytes: 50_000,
+            ..with_codec(&codec)
+        };
+        match prepare_image(&photo, &options) {
+            Err(BiskyError::ImageTooLarge { bytes, limit }) => {
+                assert_eq!(limit, 50_000);
+                // The last one tried, 500 by 375 at 60
+                assert_eq!(bytes, 500 * 375 * 60 / 200 + jpeg(500, 375, 0, None).len());
+            }
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(codec.calls.lock().last(), Some(&(500, 60)));
+    }
+
+    #[test]
+    fn small_photo_only_stripped() {
+        let photo = jpeg(1200, 900, 200_000, Some(8));
+        let codec = Arc::new(FakeCodec::default());
+
+        let prepared = prepare_image(&photo, &with_codec(&codec)).unwrap();
+        assert!(codec.calls.lock().is_empty());
+        assert!(!has_metadata(&prepared.data));
+        // The orientation is all that's left of the EXIF, and still turns it
+        assert_eq!(
+            prepared.aspect_ratio,
+            AspectRatio {
+                width: 900,
+                height: 1200
+            }
+        );
+        assert_eq!(
+            prepared.data,
+            strip_metadata(&photo, ImageFormat::Jpeg).unwrap()
+        );
+        assert_eq!(
+            prepared.data.len(),
+            photo.len() - jpeg(1, 1, 0, Some(8)).len()
+                + jpeg(1, 1, 0, None).len()
+                + orientation_exif(8).len()
+        );
+
+        // Too many bytes with no codec to make it smaller
+        let large = jpeg(1200, 900, 2_000_000, Some(1));
+        assert!(matches!(
+            prepare_image(&large, &ImageOptions::default()),
+            Err(BiskyError::ImageTooLarge {
+                limit: MAX_IMAGE_BYTES,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn passthrough_is_unchanged() {
+        let photo = jpeg(4032, 3024, 3_000_000, Some(6));
+        let prepared = prepare_image(&photo, &ImageOptions::passthrough()).unwrap();
+        assert_eq!(prepared.data, photo);
+        assert_eq!(
+            prepared.aspect_ratio,
+            AspectRatio {
+                width: 3024,
+                height: 4032
+            }
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! A consumer of Jetstream, which serves the firehose's commits, identity and account events as
+//! plain JSON, filtered by collection and DID on the server
+
+use crate::errors::BiskyError;
+use crate::types::Datetime;
+use crate::websocket::{Connection, Received, ReconnectPolicy, Reconnection};
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JetstreamOperation {
+    Create,
+    Update,
+    Delete,
+}
+
+/// A change to one record
+#[derive(Debug, Clone, Deserialize)]
+pub struct JetstreamCommit {
+    pub rev: String,
+    pub operation: JetstreamOperation,
+    pub collection: String,
+    pub rkey: String,
+    /// The new record, absent on deletes
+    pub record: Option<serde_json::Value>,
+    /// The new record's cid, absent on deletes
+    pub cid: Option<String>,
+}
+
+impl JetstreamCommit {
+    /// The new record as `T`, `None` on deletes
+    pub fn record<T: DeserializeOwned>(&self) -> Option<Result<T, BiskyError>> {
+        self.record
+            .as_ref()
+            .map(|record| Ok(T::deserialize(record)?))
+    }
+
+    /// The at:// uri of the record in the repo of `did`
+    pub fn uri(&self, did: &str) -> String {
+        format!("at://{did}/{}/{}", self.collection, self.rkey)
+    }
+}
+
+/// A change of handle or DID document
+#[derive(Debug, Clone, Deserialize)]
+pub struct JetstreamIdentity {
+    pub did: String,
+    pub handle: Option<String>,
+    pub seq: i64,
+    pub time: Datetime,
+}
+
+/// A change of account status
+#[derive(Debug, Clone, Deserialize)]
+pub struct JetstreamAccount {
+    pub did: String,
+    pub active: bool,
+    /// Why the account is inactive, such as `takendown` or `deactivated`
+    pub status: Option<String>,
+    pub seq: i64,
+    pub time: Datetime,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum JetstreamEventKind {
+    Commit {
+        commit: JetstreamCommit,
+    },
+    Identity {
+        identity: JetstreamIdentity,
+    },
+    Account {
+        account: JetstreamAccount,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JetstreamEvent {
+    pub did: String,
+    /// When Jetstream saw the event, in microseconds since the epoch, to pass back as the cursor
+    pub time_us: i64,
+    #[serde(flatten)]
+    pub kind: JetstreamEventKind,
+}
+
+/// What the Jetstream stream yields
+#[derive(Debug, Clone)]
+pub enum JetstreamMessage {
+    Event(JetstreamEvent),
+    /// The stream lost its connection and opened a new one from its cursor
+    Reconnected(Reconnection),
+}
+
+/// What to ask Jetstream for
+#[derive(Debug, Clone, Default)]
+pub struct JetstreamOptions {
+    /// Collections such as `app.bsky.feed.post`, or prefixes such as `app.bsky.graph.*`. Every
+    /// collection when empty. Identity and account events are sent either way.
+    pub wanted_collections: Vec<String>,
+    /// Every repo when empty
+    pub wanted_dids: Vec<String>,
+    /// The `time_us` to start from, live when `None`
+    pub cursor: Option<i64>,
+    pub reconnect: ReconnectPolicy,
+    /// Jetstream's zstd dictionary, published alongside it, to receive compressed messages
+    #[cfg(feature = "jetstream-zstd")]
+    pub zstd_dictionary: Option<Vec<u8>>,
+}
+
+impl JetstreamOptions {
+    fn compressed(&self) -> bool {
+        #[cfg(feature = "jetstream-zstd")]
+        return self.zstd_dictionary.is_some();
+        #[cfg(not(feature = "jetstream-zstd"))]
+        false
+    }
+
+    /// The subscribe url on `host`, which is a bare host name such as
+    /// `jetstream2.us-east.bsky.network` or a ws(s) url
+    fn url(&self, host: &str, cursor: Option<i64>) -> Result<reqwest::Url, BiskyError> {
+        let host = host.trim_end_matches('/');
+        let base = if host.contains("://") {
+            format!("{host}/subscribe")
+        } else {
+            format!("wss://{host}/subscribe")
+        };
+        let mut url = reqwest::Url::parse(&base)
+            .map_err(|e| BiskyError::WebSocketError(format!("{base}: {e}")))?;
+        {
+            let mut query = url.query_pairs_mut();
+            for collection in &self.wanted_collections {
+                query.append_pair("wantedCollections", collection);
+            }
+            for did in &self.wanted_dids {
+                query.append_pair("wantedDids", did);
+            }
+            if let Some(cursor) = cursor {
+                query.append_pair("cursor", &cursor.to_string());
+            }
+            if self.compressed() {
+                query.append_pair("compress", "true");
+            }
+        }
+        Ok(url)
+    }
+
+    fn decode(&self, message: Message) -> Option<Result<JetstreamEvent, BiskyError>> {
+        match message {
+            Message::Text(text) => Some(serde_json::from_str(&text).map_err(Into::into)),
+            #[cfg(feature = "jetstream-zstd")]
+            Message::Binary(bytes) => {
+                let dictionary = self.zstd_dictionary.as_deref()?;
+                Some(
+                    decompress(&bytes, dictionary)
+                        .and_then(|json| Ok(serde_json::from_slice(&json)?)),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "jetstream-zstd")]
+fn decompress(bytes: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, BiskyError> {
+    use std::io::Read;
+
+    let mut json = Vec::new();
+    zstd::stream::read::Decoder::with_dictionary(bytes, dictionary)?.read_to_end(&mut json)?;
+    Ok(json)
+}
+
+/// The events `host` serves for `options`. Each event moves the cursor on, and a dropped
+/// connection is reopened from there.
+///
+/// Errors are yielded and the stream carries on, so it only ends when dropped.
+pub fn jetstream(
+    host: &str,
+    options: JetstreamOptions,
+) -> impl Stream<Item = Result<JetstreamMessage, BiskyError>> {
+    struct State {
+        host: String,
+        options: JetstreamOptions,
+        cursor: Option<i64>,
+        connection: Connection,
+    }
+
+    let state = State {
+        host: host.to_string(),
+        cursor: options.cursor,
+        connection: Connection::new(options.reconnect),
+        options,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            let (host, options, cursor) = (&state.host, &state.options, state.cursor);
+            let next = state
+                .connection
+                .next(|| Ok(options.url(host, cursor)?.to_string()));
+            let message = match next.await {
+                Ok(Received::Message(message)) => message,
+                Ok(Received::Reconnected(reconnection)) => {
+                    return Some((Ok(JetstreamMessage::Reconnected(reconnection)), state))
+                }
+                Err(e) => return Some((Err(e), state)),
+            };
+
+            match state.options.decode(message) {
+                Some(Ok(event)) => {
+                    state.connection.reset_backoff();
+                    state.cursor = Some(event.time_us);
+                    return Some((Ok(JetstreamMessage::Event(event)), state));
+                }
+                Some(Err(e)) => return Some((Err(e), state)),
+                None => continue,
+            }
+        }
+    })
+}
@@ -1,53 +1,933 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Label {
-    pub src: String,
-    pub uri: String,
-    pub val: String,
-    pub neg: bool,
-    pub cts: DateTime<Utc>,
-}
+use super::feed::{PostView, ThreadViewPostEnum};
+use super::graph::ListViewBasic;
+use super::labeler::BSKY_MODERATION_LABELER;
+pub use crate::lexicon::com::atproto::label::Label;
+use crate::lexicon::com::atproto::label::{KnownLabel, RecordLabels, SelfLabels};
+use crate::lexicon::com::atproto::repo::{Blob, StrongRef};
+use crate::types::{union_type, Datetime};
+use chrono::Utc;
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProfileViewBasic {
     pub did: String,
     pub handle: String,
-    #[serde(rename(deserialize = "displayName"))]
     pub display_name: Option<String>,
     pub avatar: Option<String>,
+    #[serde(default)]
     pub labels: Vec<Label>,
     pub indexed_at: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProfileView {
     pub did: String,
     pub handle: String,
-    #[serde(rename(deserialize = "displayName"))]
     pub display_name: Option<String>,
     pub description: Option<String>,
     pub avatar: Option<String>,
+    #[serde(default)]
     pub labels: Vec<Label>,
     pub indexed_at: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProfileViewDetailed {
     pub did: String,
     pub handle: String,
-    #[serde(rename(deserialize = "displayName"))]
     pub display_name: Option<String>,
     pub description: Option<String>,
     pub avatar: Option<String>,
     pub banner: Option<String>,
-    #[serde(rename(deserialize = "followersCount"))]
     pub followers_count: Option<usize>,
-    #[serde(rename(deserialize = "followsCount"))]
     pub follows_count: Option<usize>,
-    #[serde(rename(deserialize = "postsCount"))]
     pub posts_count: Option<usize>,
+    pub viewer: Option<ActorViewerState>,
+    #[serde(default)]
     pub labels: Vec<Label>,
+    pub created_at: Option<Datetime>,
     pub indexed_at: Option<String>,
+    pub pinned_post: Option<StrongRef>,
+}
+
+///app.bsky.actor.getProfiles
+#[derive(Debug, Deserialize)]
+pub struct GetProfilesOutput {
+    pub profiles: Vec<ProfileViewDetailed>,
+}
+
+///app.bsky.actor.searchActors
+#[derive(Debug, Deserialize)]
+pub struct SearchActorsOutput {
+    pub actors: Vec<ProfileView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.actor.searchActorsTypeahead
+#[derive(Debug, Deserialize)]
+pub struct SearchActorsTypeaheadOutput {
+    pub actors: Vec<ProfileViewBasic>,
+}
+
+///app.bsky.actor.getSuggestions
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSuggestionsOutput {
+    pub actors: Vec<ProfileView>,
+    pub cursor: Option<String>,
+    /// Identifies this batch of suggestions when sending feedback about them
+    pub rec_id: Option<i64>,
+}
+
+/// The most actors getProfiles accepts in one request
+pub const MAX_GET_PROFILES: usize = 25;
+
+///app.bsky.actor.defs#viewerState
+/// The relationship between the logged in user and an actor
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActorViewerState {
+    #[serde(default)]
+    pub muted: bool,
+    pub muted_by_list: Option<ListViewBasic>,
+    #[serde(default)]
+    pub blocked_by: bool,
+    /// The uri of the viewer's block of this actor
+    pub blocking: Option<String>,
+    pub blocking_by_list: Option<ListViewBasic>,
+    /// The uri of the viewer's follow of this actor
+    pub following: Option<String>,
+    /// The uri of this actor's follow of the viewer
+    pub followed_by: Option<String>,
+    pub known_followers: Option<KnownFollowers>,
+}
+
+///app.bsky.actor.defs#knownFollowers
+/// Accounts the viewer follows that also follow this actor
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KnownFollowers {
+    pub count: usize,
+    pub followers: Vec<ProfileViewBasic>,
+}
+
+///app.bsky.actor.profile
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    #[serde(
+        rename(deserialize = "$type", serialize = "$type"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub rust_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<Blob>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banner: Option<Blob>,
+    /// Self-labels that apply to the whole account
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<RecordLabels>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned_post: Option<StrongRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub joined_via_starter_pack: Option<StrongRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<Datetime>,
+    /// Fields this crate doesn't know about, kept so that updates write them back unchanged
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// An edit of the logged in user's profile, see `BskyClient::bsky_update_profile`. Derefs to the
+/// profile record so fields can be set directly.
+#[derive(Debug)]
+pub struct ProfileUpdate {
+    profile: Profile,
+    pub(crate) avatar: Option<(Vec<u8>, String)>,
+    pub(crate) banner: Option<(Vec<u8>, String)>,
+}
+
+impl ProfileUpdate {
+    pub(crate) fn new(profile: Profile) -> Self {
+        Self {
+            profile,
+            avatar: None,
+            banner: None,
+        }
+    }
+
+    pub(crate) fn into_profile(self) -> Profile {
+        self.profile
+    }
+
+    /// Upload a new avatar, e.g. `set_avatar(bytes, "image/png")`
+    pub fn set_avatar(&mut self, bytes: Vec<u8>, mime_type: &str) -> &mut Self {
+        self.avatar = Some((bytes, mime_type.to_string()));
+        self
+    }
+
+    pub fn clear_avatar(&mut self) -> &mut Self {
+        self.avatar = None;
+        self.profile.avatar = None;
+        self
+    }
+
+    /// Upload a new banner, e.g. `set_banner(bytes, "image/jpeg")`
+    pub fn set_banner(&mut self, bytes: Vec<u8>, mime_type: &str) -> &mut Self {
+        self.banner = Some((bytes, mime_type.to_string()));
+        self
+    }
+
+    pub fn clear_banner(&mut self) -> &mut Self {
+        self.banner = None;
+        self.profile.banner = None;
+        self
+    }
+}
+
+impl std::ops::Deref for ProfileUpdate {
+    type Target = Profile;
+
+    fn deref(&self) -> &Profile {
+        &self.profile
+    }
+}
+
+impl std::ops::DerefMut for ProfileUpdate {
+    fn deref_mut(&mut self) -> &mut Profile {
+        &mut self.profile
+    }
+}
+
+impl Profile {
+    /// Self-label the account, e.g. with `KnownLabel::Porn`
+    pub fn self_label(&mut self, val: KnownLabel) -> &mut Self {
+        self.labels
+            .get_or_insert_with(|| SelfLabels::default().into())
+            .add(val);
+        self
+    }
+}
+
+///app.bsky.actor.defs#adultContentPref
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AdultContentPref {
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// What the user wants done with content carrying a label
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum Visibility {
+    Ignore,
+    /// The old name for `Ignore`, still found in older preferences
+    Show,
+    #[default]
+    Warn,
+    Hide,
+    Other(String),
+}
+
+impl From<String> for Visibility {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "ignore" => Self::Ignore,
+            "show" => Self::Show,
+            "warn" => Self::Warn,
+            "hide" => Self::Hide,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<Visibility> for String {
+    fn from(value: Visibility) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ignore => "ignore",
+            Self::Show => "show",
+            Self::Warn => "warn",
+            Self::Hide => "hide",
+            Self::Other(value) => value,
+        })
+    }
+}
+
+///app.bsky.actor.defs#contentLabelPref
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentLabelPref {
+    /// The labeler this applies to, or every labeler when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labeler_did: Option<String>,
+    pub label: String,
+    pub visibility: Visibility,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///app.bsky.actor.defs#labelerPrefItem
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LabelerPrefItem {
+    pub did: String,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///app.bsky.actor.defs#labelersPref
+/// The labelers the user subscribes to, besides Bluesky's own
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LabelersPref {
+    pub labelers: Vec<LabelerPrefItem>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///app.bsky.actor.defs#savedFeed
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SavedFeed {
+    pub id: String,
+    /// "feed", "list" or "timeline"
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: String,
+    pub pinned: bool,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///app.bsky.actor.defs#savedFeedsPrefV2
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SavedFeedsPrefV2 {
+    pub items: Vec<SavedFeed>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///app.bsky.actor.defs#mutedWordTarget
+/// Where a muted word applies, with an escape hatch for targets this crate doesn't know
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum MutedWordTarget {
+    /// The post text
+    Content,
+    /// The post's hashtags
+    Tag,
+    Other(String),
+}
+
+impl From<String> for MutedWordTarget {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "content" => Self::Content,
+            "tag" => Self::Tag,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<MutedWordTarget> for String {
+    fn from(value: MutedWordTarget) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for MutedWordTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Content => "content",
+            Self::Tag => "tag",
+            Self::Other(value) => value,
+        })
+    }
+}
+
+///app.bsky.actor.defs#mutedWord
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MutedWord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub value: String,
+    pub targets: Vec<MutedWordTarget>,
+    /// "all" or "exclude-following"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor_target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<Datetime>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///app.bsky.actor.defs#mutedWordsPref
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MutedWordsPref {
+    pub items: Vec<MutedWord>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MutedWordsPref {
+    /// Mute a word, or update the targets and expiry if it is already muted. Words compare
+    /// case-insensitively and without a leading '#'.
+    pub fn add(
+        &mut self,
+        word: &str,
+        targets: &[MutedWordTarget],
+        expires_at: Option<Datetime>,
+    ) -> &mut MutedWord {
+        let word = normalize_muted_word(word);
+        let index = match self
+            .items
+            .iter()
+            .position(|w| normalize_muted_word(&w.value) == word)
+        {
+            Some(index) => {
+                let existing = &mut self.items[index];
+                existing.targets = targets.to_vec();
+                existing.expires_at = expires_at;
+                index
+            }
+            None => {
+                self.items.push(MutedWord {
+                    value: word,
+                    targets: targets.to_vec(),
+                    expires_at,
+                    ..Default::default()
+                });
+                self.items.len() - 1
+            }
+        };
+        &mut self.items[index]
+    }
+
+    /// Unmute a word. Returns false if it was not muted
+    pub fn remove(&mut self, word: &str) -> bool {
+        let word = normalize_muted_word(word);
+        let len = self.items.len();
+        self.items
+            .retain(|w| normalize_muted_word(&w.value) != word);
+        self.items.len() != len
+    }
+}
+
+fn normalize_muted_word(word: &str) -> String {
+    word.trim().trim_start_matches('#').to_lowercase()
+}
+
+///app.bsky.actor.defs#hiddenPostsPref
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct HiddenPostsPref {
+    /// Uris of the hidden posts
+    pub items: Vec<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl HiddenPostsPref {
+    /// Hide a post. Returns false if it was already hidden
+    pub fn hide(&mut self, uri: &str) -> bool {
+        if self.items.iter().any(|item| item == uri) {
+            return false;
+        }
+        self.items.push(uri.to_string());
+        true
+    }
+
+    /// Unhide a post. Returns false if it was not hidden
+    pub fn unhide(&mut self, uri: &str) -> bool {
+        let len = self.items.len();
+        self.items.retain(|item| item != uri);
+        self.items.len() != len
+    }
+}
+
+/// How the replies in a thread are ordered
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum ThreadSort {
+    Oldest,
+    Newest,
+    MostLikes,
+    /// Left in the order the server returned
+    Random,
+    /// Likes weighed against age, as an approximation of the app's ranking
+    Hotness,
+    Other(String),
+}
+
+impl From<String> for ThreadSort {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "oldest" => Self::Oldest,
+            "newest" => Self::Newest,
+            "most-likes" => Self::MostLikes,
+            "random" => Self::Random,
+            "hotness" => Self::Hotness,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<ThreadSort> for String {
+    fn from(value: ThreadSort) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for ThreadSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Oldest => "oldest",
+            Self::Newest => "newest",
+            Self::MostLikes => "most-likes",
+            Self::Random => "random",
+            Self::Hotness => "hotness",
+            Self::Other(value) => value,
+        })
+    }
+}
+
+///app.bsky.actor.defs#threadViewPref
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadViewPref {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<ThreadSort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prioritize_followed_users: Option<bool>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ThreadViewPref {
+    /// Order the replies to a post by `op` the way the Bluesky app does: the author's own
+    /// replies first, then with `prioritize_followed_users` those by accounts `is_followed`
+    /// says the user follows, each group in `sort` order, oldest first by default. Replies that
+    /// are blocked or not found go last.
+    pub fn sort_replies(
+        &self,
+        replies: &mut [ThreadViewPostEnum],
+        op: &str,
+        is_followed: impl Fn(&str) -> bool,
+    ) {
+        let prioritize_followed = self.prioritize_followed_users.unwrap_or(true);
+        let group = |reply: &ThreadViewPostEnum| match reply {
+            ThreadViewPostEnum::ThreadViewPost(node) if node.post.author.did == op => 0,
+            ThreadViewPostEnum::ThreadViewPost(node)
+                if prioritize_followed && is_followed(&node.post.author.did) =>
+            {
+                1
+            }
+            ThreadViewPostEnum::ThreadViewPost(_) => 2,
+            _ => 3,
+        };
+        let now = Utc::now();
+        let hotness = |post: &PostView| {
+            let hours = (now - *post.indexed_at).num_minutes().max(0) as f64 / 60.0;
+            post.like_count.unwrap_or(0) as f64 / (hours + 2.0).powf(1.8)
+        };
+
+        let sort = self.sort.clone().unwrap_or(ThreadSort::Oldest);
+        replies.sort_by(|a, b| {
+            let order = group(a).cmp(&group(b));
+            let (ThreadViewPostEnum::ThreadViewPost(a), ThreadViewPostEnum::ThreadViewPost(b)) =
+                (a, b)
+            else {
+                return order;
+            };
+            order.then_with(|| match sort {
+                ThreadSort::Oldest => a.post.indexed_at.cmp(&b.post.indexed_at),
+                ThreadSort::Newest => b.post.indexed_at.cmp(&a.post.indexed_at),
+                ThreadSort::MostLikes => b.post.like_count.cmp(&a.post.like_count),
+                ThreadSort::Hotness => hotness(&b.post).total_cmp(&hotness(&a.post)),
+                ThreadSort::Random | ThreadSort::Other(_) => std::cmp::Ordering::Equal,
+            })
+        });
+    }
+}
+
+///app.bsky.actor.defs#interestsPref
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InterestsPref {
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A preference type that can be picked out of `Preferences` with `get` and `get_mut`
+pub trait PreferenceKind: Default + Into<Preference> {
+    fn from_pref(pref: &Preference) -> Option<&Self>;
+    fn from_pref_mut(pref: &mut Preference) -> Option<&mut Self>;
+}
+
+macro_rules! preferences {
+    ($($variant:ident($pref:ty) = $type:literal,)*) => {
+        ///app.bsky.actor.defs#preferences
+        /// One `$type`-tagged preference object
+        #[derive(Debug, Clone)]
+        pub enum Preference {
+            $($variant($pref),)*
+            /// A preference this crate doesn't know about, written back exactly as it was read
+            Unknown(serde_json::Value),
+        }
+
+        impl<'de> Deserialize<'de> for Preference {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = serde_json::Value::deserialize(deserializer)?;
+                let kind = union_type(&value).unwrap_or_default();
+                Ok(match kind {
+                    $($type => Self::$variant(
+                        serde_json::from_value(value).map_err(D::Error::custom)?,
+                    ),)*
+                    _ => Self::Unknown(value),
+                })
+            }
+        }
+
+        impl Serialize for Preference {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let (kind, value) = match self {
+                    $(Self::$variant(pref) => (
+                        $type,
+                        serde_json::to_value(pref).map_err(S::Error::custom)?,
+                    ),)*
+                    Self::Unknown(value) => return value.serialize(serializer),
+                };
+                let mut value = value;
+                if let Some(object) = value.as_object_mut() {
+                    object.insert("$type".to_string(), kind.into());
+                }
+                value.serialize(serializer)
+            }
+        }
+
+        $(
+            impl From<$pref> for Preference {
+                fn from(pref: $pref) -> Self {
+                    Self::$variant(pref)
+                }
+            }
+
+            impl PreferenceKind for $pref {
+                fn from_pref(pref: &Preference) -> Option<&Self> {
+                    match pref {
+                        Preference::$variant(pref) => Some(pref),
+                        _ => None,
+                    }
+                }
+
+                fn from_pref_mut(pref: &mut Preference) -> Option<&mut Self> {
+                    match pref {
+                        Preference::$variant(pref) => Some(pref),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+preferences! {
+    AdultContent(AdultContentPref) = "app.bsky.actor.defs#adultContentPref",
+    ContentLabel(ContentLabelPref) = "app.bsky.actor.defs#contentLabelPref",
+    SavedFeeds(SavedFeedsPrefV2) = "app.bsky.actor.defs#savedFeedsPrefV2",
+    MutedWords(MutedWordsPref) = "app.bsky.actor.defs#mutedWordsPref",
+    HiddenPosts(HiddenPostsPref) = "app.bsky.actor.defs#hiddenPostsPref",
+    ThreadView(ThreadViewPref) = "app.bsky.actor.defs#threadViewPref",
+    Interests(InterestsPref) = "app.bsky.actor.defs#interestsPref",
+    Labelers(LabelersPref) = "app.bsky.actor.defs#labelersPref",
+}
+
+/// The full preferences array. putPreferences replaces the whole array, so it should always be
+/// written back with every object that was read, including the unknown ones.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Preferences(pub Vec<Preference>);
+
+impl Preferences {
+    /// The first preference of kind `P`, e.g. `prefs.get::<MutedWordsPref>()`
+    pub fn get<P: PreferenceKind>(&self) -> Option<&P> {
+        self.0.iter().find_map(P::from_pref)
+    }
+
+    /// The first preference of kind `P`, added with its default if there is none
+    pub fn get_mut<P: PreferenceKind>(&mut self) -> &mut P {
+        let index = match self.0.iter().position(|p| P::from_pref(p).is_some()) {
+            Some(index) => index,
+            None => {
+                self.0.push(P::default().into());
+                self.0.len() - 1
+            }
+        };
+        // Just checked or inserted
+        P::from_pref_mut(&mut self.0[index]).unwrap()
+    }
+
+    /// Every content label preference, one per label and labeler
+    pub fn content_labels(&mut self) -> impl Iterator<Item = &mut ContentLabelPref> {
+        self.0
+            .iter_mut()
+            .filter_map(ContentLabelPref::from_pref_mut)
+    }
+
+    /// The user's visibility for `label` from `labeler_did`, or from every labeler with `None`
+    pub fn label_visibility(&self, labeler_did: Option<&str>, label: &str) -> Option<&Visibility> {
+        self.0
+            .iter()
+            .filter_map(ContentLabelPref::from_pref)
+            .find(|pref| pref.label == label && pref.labeler_did.as_deref() == labeler_did)
+            .map(|pref| &pref.visibility)
+    }
+
+    /// Set the visibility of `label` from `labeler_did`, or from every labeler with `None`
+    pub fn set_label_visibility(
+        &mut self,
+        labeler_did: Option<&str>,
+        label: &str,
+        visibility: Visibility,
+    ) {
+        if let Some(pref) = self
+            .content_labels()
+            .find(|pref| pref.label == label && pref.labeler_did.as_deref() == labeler_did)
+        {
+            pref.visibility = visibility;
+            return;
+        }
+        self.0.push(
+            ContentLabelPref {
+                labeler_did: labeler_did.map(str::to_string),
+                label: label.to_string(),
+                visibility,
+                extra: Default::default(),
+            }
+            .into(),
+        );
+    }
+
+    /// Whether the user subscribes to the labeler `did`, which they always do to Bluesky's
+    pub fn has_labeler(&self, did: &str) -> bool {
+        did == BSKY_MODERATION_LABELER
+            || self
+                .get::<LabelersPref>()
+                .is_some_and(|pref| pref.labelers.iter().any(|labeler| labeler.did == did))
+    }
+}
+
+///app.bsky.actor.getPreferences
+#[derive(Debug, Deserialize)]
+pub struct GetPreferencesOutput {
+    pub preferences: Preferences,
+}
+
+///app.bsky.actor.putPreferences
+#[derive(Debug, Serialize)]
+pub struct PutPreferences<'a> {
+    pub preferences: &'a Preferences,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// getPreferences as bsky.social answers it, with preferences this crate doesn't model and
+    /// a field it doesn't know on one it does. Compact and with its keys sorted, as written back.
+    const PAYLOAD: &str = concat!(
+        r#"{"preferences":["#,
+        r#"{"$type":"app.bsky.actor.defs#adultContentPref","enabled":true},"#,
+        r#"{"$type":"app.bsky.actor.defs#contentLabelPref","label":"nsfw","visibility":"warn"},"#,
+        r#"{"$type":"app.bsky.actor.defs#contentLabelPref","label":"gore","#,
+        r#""labelerDid":"did:plc:ar7c4by46qjdydhdevvrndac","visibility":"hide"},"#,
+        r#"{"$type":"app.bsky.actor.defs#savedFeedsPrefV2","items":["#,
+        r#"{"id":"3kwvg5vhgnc2b","pinned":true,"type":"timeline","value":"following"},"#,
+        r#"{"id":"3kwvg5vhhao2b","pinned":false,"type":"feed","#,
+        r#""value":"at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.generator/whats-hot"}]},"#,
+        r#"{"$type":"app.bsky.actor.defs#savedFeedsPref","#,
+        r#""pinned":["at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.generator/whats-hot"],"#,
+        r#""saved":["at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.generator/whats-hot"]},"#,
+        r#"{"$type":"app.bsky.actor.defs#personalDetailsPref","birthDate":"1990-01-01T00:00:00.000Z"},"#,
+        r#"{"$type":"app.bsky.actor.defs#feedViewPref","feed":"home","hideQuotePosts":false,"#,
+        r#""hideReplies":false,"hideRepliesByUnfollowed":true,"hideReposts":false},"#,
+        r#"{"$type":"app.bsky.actor.defs#threadViewPref","lab_treeViewEnabled":true,"#,
+        r#""prioritizeFollowedUsers":true,"sort":"hotness"},"#,
+        r#"{"$type":"app.bsky.actor.defs#mutedWordsPref","items":["#,
+        r#"{"actorTarget":"all","id":"3l3yz2zqfuk2s","targets":["content","tag"],"value":"spoilers"},"#,
+        r#"{"actorTarget":"exclude-following","expiresAt":"2024-09-01T00:00:00.000Z","#,
+        r#""id":"3l3yz3bhkqc2s","targets":["tag"],"value":"election"}]},"#,
+        r#"{"$type":"app.bsky.actor.defs#hiddenPostsPref","#,
+        r#""items":["at://did:plc:ewvi7nxzyoun6zhxrhs64oiz/app.bsky.feed.post/3kgcbrovujv2e"]},"#,
+        r#"{"$type":"app.bsky.actor.defs#labelersPref","#,
+        r#""labelers":[{"did":"did:plc:ar7c4by46qjdydhdevvrndac"}]},"#,
+        r#"{"$type":"app.bsky.actor.defs#interestsPref","tags":["art","tech"]},"#,
+        r#"{"$type":"app.bsky.actor.defs#bskyAppStatePref","#,
+        r#""activeProgressGuide":{"guide":"like-10-and-follow-7"},"#,
+        r#""nuxs":[{"completed":true,"id":"TenMillionDialog"}],"queuedNudges":[]},"#,
+        r#"{"$type":"app.bsky.actor.defs#postInteractionSettingsPref","postgateEmbeddingRules":[],"#,
+        r#""threadgateAllowRules":[{"$type":"app.bsky.feed.threadgate#mentionRule"}]}"#,
+        r#"]}"#,
+    );
+
+    #[test]
+    fn preferences_round_trip() {
+        let output: GetPreferencesOutput = serde_json::from_str(PAYLOAD).unwrap();
+        let preferences = output.preferences;
+
+        let unknown = preferences
+            .0
+            .iter()
+            .filter_map(|pref| match pref {
+                Preference::Unknown(value) => crate::types::union_type(value),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            unknown,
+            [
+                "app.bsky.actor.defs#savedFeedsPref",
+                "app.bsky.actor.defs#personalDetailsPref",
+                "app.bsky.actor.defs#feedViewPref",
+                "app.bsky.actor.defs#bskyAppStatePref",
+                "app.bsky.actor.defs#postInteractionSettingsPref",
+            ]
+        );
+        assert!(preferences.get::<AdultContentPref>().unwrap().enabled);
+        assert_eq!(
+            preferences.label_visibility(Some("did:plc:ar7c4by46qjdydhdevvrndac"), "gore"),
+            Some(&Visibility::Hide)
+        );
+        let muted = &preferences.get::<MutedWordsPref>().unwrap().items;
+        assert_eq!(muted[1].targets, [MutedWordTarget::Tag]);
+        let thread = preferences.get::<ThreadViewPref>().unwrap();
+        assert_eq!(thread.sort, Some(ThreadSort::Hotness));
+        assert_eq!(thread.extra["lab_treeViewEnabled"], true);
+
+        let written = serde_json::to_string(&PutPreferences {
+            preferences: &preferences,
+        })
+        .unwrap();
+        assert_eq!(written, PAYLOAD);
+    }
+
+    #[test]
+    fn muted_word_json() {
+        let word = MutedWord {
+            value: "spoilers".into(),
+            targets: vec![MutedWordTarget::Content, MutedWordTarget::Tag],
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&word).unwrap(),
+            r#"{"value":"spoilers","targets":["content","tag"]}"#
+        );
+
+        let word = MutedWord {
+            id: Some("3l3yz2zqfuk2s".into()),
+            actor_target: Some("exclude-following".into()),
+            expires_at: Some("2025-01-01T00:00:00.000Z".parse().unwrap()),
+            targets: vec![MutedWordTarget::Other("alt-text".into())],
+            ..word
+        };
+        assert_eq!(
+            serde_json::to_string(&word).unwrap(),
+            concat!(
+                r#"{"id":"3l3yz2zqfuk2s","value":"spoilers","targets":["alt-text"],"#,
+                r#""actorTarget":"exclude-following","expiresAt":"2025-01-01T00:00:00.000Z"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn muted_words_edit() {
+        let output: GetPreferencesOutput = serde_json::from_str(PAYLOAD).unwrap();
+        let mut preferences = output.preferences;
+        let muted = preferences.get_mut::<MutedWordsPref>();
+
+        // The same word updates, keeping its id and actor target
+        muted.add("#Spoilers", &[MutedWordTarget::Tag], None);
+        let expires = "2025-01-01T00:00:00.000Z".parse().unwrap();
+        muted.add(" Rust ", &[MutedWordTarget::Content], Some(expires));
+        assert!(muted.remove("ELECTION"));
+        assert!(!muted.remove("missing"));
+
+        let written = serde_json::to_string(&PutPreferences {
+            preferences: &preferences,
+        })
+        .unwrap();
+        let expected = PAYLOAD.replace(
+            concat!(
+                r#"{"actorTarget":"all","id":"3l3yz2zqfuk2s","targets":["content","tag"],"value":"spoilers"},"#,
+                r#"{"actorTarget":"exclude-following","expiresAt":"2024-09-01T00:00:00.000Z","#,
+                r#""id":"3l3yz3bhkqc2s","targets":["tag"],"value":"election"}"#,
+            ),
+            concat!(
+                r#"{"actorTarget":"all","id":"3l3yz2zqfuk2s","targets":["tag"],"value":"spoilers"},"#,
+                r#"{"expiresAt":"2025-01-01T00:00:00.000Z","targets":["content"],"value":"rust"}"#,
+            ),
+        );
+        assert_ne!(expected, PAYLOAD);
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn self_labelled_profile_json() {
+        // As another client writes it, with a field this crate doesn't model
+        let record = serde_json::json!({
+            "$type": "app.bsky.actor.profile",
+            "displayName": "Alice",
+            "labels": {
+                "$type": "com.atproto.label.defs#selfLabels",
+                "values": [{"val": "porn"}],
+            },
+            "pronouns": "they/them",
+        });
+        let mut profile: Profile = serde_json::from_value(record.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&profile).unwrap(), record);
+
+        profile
+            .self_label(KnownLabel::Porn)
+            .self_label("!no-unauthenticated".into());
+        assert_eq!(
+            serde_json::to_string(&profile).unwrap(),
+            concat!(
+                r#"{"$type":"app.bsky.actor.profile","displayName":"Alice","labels":{"#,
+                r#""$type":"com.atproto.label.defs#selfLabels","#,
+                r#""values":[{"val":"porn"},{"val":"!no-unauthenticated"}]},"#,
+                r#""pronouns":"they/them"}"#,
+            )
+        );
+
+        // Unlabelled profiles leave `labels` out
+        let profile = Profile::default();
+        assert_eq!(serde_json::to_string(&profile).unwrap(), "{}");
+    }
+
+    #[test]
+    fn preferences_keep_unknown_after_edit() {
+        let output: GetPreferencesOutput = serde_json::from_str(PAYLOAD).unwrap();
+        let mut preferences = output.preferences;
+        let uri = "at://did:plc:ewvi7nxzyoun6zhxrhs64oiz/app.bsky.feed.post/3kgcbrovujv2e";
+        assert!(preferences.get_mut::<HiddenPostsPref>().unhide(uri));
+
+        let written = serde_json::to_string(&PutPreferences {
+            preferences: &preferences,
+        })
+        .unwrap();
+        let expected = PAYLOAD.replace(&format!(r#""items":["{uri}"]"#), r#""items":[]"#);
+        assert_ne!(expected, PAYLOAD);
+        assert_eq!(written, expected);
+    }
 }
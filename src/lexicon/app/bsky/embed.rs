@@ -1,5 +1,8 @@
+use super::actor::{Label, ProfileViewBasic};
+use super::feed::{BlockedAuthor, GeneratorView};
 use crate::lexicon::com::atproto::repo::Blob;
-use serde::{Deserialize, Serialize};
+use crate::types::{union_type, Datetime};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
 
 // "app.bsky.embed.images#view",
 // "app.bsky.embed.external#view",
@@ -12,32 +15,215 @@ use serde::{Deserialize, Serialize};
 //     pub images: Vec<Image>,
 // }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Image {
     pub image: Blob,
     pub alt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<AspectRatio>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AspectRatio {
+    pub width: usize,
+    pub height: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ViewImage {
     pub thumb: String,
-    #[serde(rename(deserialize = "fullSize", serialize = "fullSize"))]
+    #[serde(
+        rename(deserialize = "fullsize", serialize = "fullsize"),
+        alias = "fullSize"
+    )]
     pub full_size: String,
     pub alt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<AspectRatio>,
 }
 
 ///app.bsky.embed.external#external
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ExternalObject {
     pub uri: String,
     pub title: String,
     pub description: String,
-    #[serde(rename(deserialize = "maxSize", serialize = "maxSize"))]
     pub max_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumb: Option<Blob>,
 }
 
 ///app.bsky.embed.external
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct External {
     pub external: ExternalObject,
 }
+
+///app.bsky.embed.images#view
+#[derive(Debug, Deserialize)]
+pub struct ImagesView {
+    pub images: Vec<ViewImage>,
+}
+
+///app.bsky.embed.external#viewExternal
+#[derive(Debug, Deserialize)]
+pub struct ViewExternal {
+    pub uri: String,
+    pub title: String,
+    pub description: String,
+    pub thumb: Option<String>,
+}
+
+///app.bsky.embed.external#view
+#[derive(Debug, Deserialize)]
+pub struct ExternalView {
+    pub external: ViewExternal,
+}
+
+///app.bsky.embed.video#view
+#[cfg(feature = "video")]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoView {
+    pub cid: String,
+    pub playlist: String,
+    pub thumbnail: Option<String>,
+    pub alt: Option<String>,
+    pub aspect_ratio: Option<AspectRatio>,
+}
+
+///app.bsky.embed.record#viewRecord
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewRecord {
+    pub uri: String,
+    pub cid: String,
+    pub author: ProfileViewBasic,
+    /// The embedded record itself, usually an app.bsky.feed.post
+    pub value: serde_json::Value,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub reply_count: Option<usize>,
+    pub repost_count: Option<usize>,
+    pub like_count: Option<usize>,
+    pub quote_count: Option<usize>,
+    /// The embeds of the embedded record
+    #[serde(default)]
+    pub embeds: Vec<EmbedView>,
+    pub indexed_at: Datetime,
+}
+
+///app.bsky.embed.record#viewNotFound
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewNotFound {
+    pub uri: String,
+    pub not_found: bool,
+}
+
+///app.bsky.embed.record#viewBlocked
+#[derive(Debug, Deserialize)]
+pub struct ViewBlocked {
+    pub uri: String,
+    pub blocked: bool,
+    pub author: BlockedAuthor,
+}
+
+///app.bsky.embed.record#viewDetached
+#[derive(Debug, Deserialize)]
+pub struct ViewDetached {
+    pub uri: String,
+    pub detached: bool,
+}
+
+/// The record embedded by app.bsky.embed.record#view
+#[derive(Debug)]
+pub enum ViewRecordUnion {
+    Record(Box<ViewRecord>),
+    NotFound(ViewNotFound),
+    Blocked(ViewBlocked),
+    Detached(ViewDetached),
+    Generator(Box<GeneratorView>),
+    /// A record view this crate doesn't know about yet
+    Unknown(serde_json::Value),
+}
+
+impl<'de> Deserialize<'de> for ViewRecordUnion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let kind = union_type(&value).unwrap_or_default();
+        Ok(match kind {
+            "app.bsky.embed.record#viewRecord" => {
+                Self::Record(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.embed.record#viewNotFound" => {
+                Self::NotFound(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.embed.record#viewBlocked" => {
+                Self::Blocked(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.embed.record#viewDetached" => {
+                Self::Detached(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.feed.defs#generatorView" => {
+                Self::Generator(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            _ => Self::Unknown(value),
+        })
+    }
+}
+
+///app.bsky.embed.record#view
+#[derive(Debug, Deserialize)]
+pub struct RecordView {
+    pub record: ViewRecordUnion,
+}
+
+///app.bsky.embed.recordWithMedia#view
+#[derive(Debug, Deserialize)]
+pub struct RecordWithMediaView {
+    pub record: RecordView,
+    pub media: Box<EmbedView>,
+}
+
+/// The hydrated embed of a post in a feed or thread, tagged by `$type`
+#[derive(Debug)]
+pub enum EmbedView {
+    Images(ImagesView),
+    External(ExternalView),
+    Record(RecordView),
+    RecordWithMedia(RecordWithMediaView),
+    #[cfg(feature = "video")]
+    Video(VideoView),
+    /// An embed this crate doesn't know about yet, including videos without the `video` feature
+    Unknown(serde_json::Value),
+}
+
+impl<'de> Deserialize<'de> for EmbedView {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let kind = union_type(&value).unwrap_or_default();
+        Ok(match kind {
+            "app.bsky.embed.images#view" => {
+                Self::Images(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.embed.external#view" => {
+                Self::External(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.embed.record#view" => {
+                Self::Record(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            "app.bsky.embed.recordWithMedia#view" => {
+                Self::RecordWithMedia(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            #[cfg(feature = "video")]
+            "app.bsky.embed.video#view" => {
+                Self::Video(serde_json::from_value(value).map_err(D::Error::custom)?)
+            }
+            _ => Self::Unknown(value),
+        })
+    }
+}
@@ -1,12 +1,31 @@
 use super::{
-    actor::ProfileView,
-    embed::{External, Image},
+    actor::{Label, ProfileView},
+    embed::{EmbedView, External, Image, RecordView, ViewRecordUnion},
+    richtext::{detect_mentions, ByteSlice, Facet, FacetFeature},
 };
-use crate::lexicon::com::atproto::repo::StrongRef;
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use crate::bsky::BskyClient;
+use crate::errors::BiskyError;
+use crate::identity::Resolver;
+#[cfg(feature = "image")]
+use crate::image::{prepare_image, ImageOptions};
+use crate::lexicon::com::atproto::label::{KnownLabel, RecordLabels, SelfLabels};
+#[cfg(any(feature = "image", feature = "link-preview"))]
+use crate::lexicon::com::atproto::repo::BlobOutput;
+use crate::lexicon::com::atproto::repo::{Blob, CreateRecordOutput, StrongRef};
+#[cfg(feature = "link-preview")]
+use crate::link_preview::{self, LinkPreviewOptions};
+#[cfg(feature = "link-preview")]
+use crate::types::IntoUrl;
+use crate::types::{AtUri, Datetime};
+use derive_builder::Builder;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::value::RawValue;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ImagesEmbed {
     pub images: Vec<Image>,
 }
@@ -15,7 +34,7 @@ pub struct ImagesEmbed {
 // "app.bsky.embed.external",
 // "app.bsky.embed.record",
 // "app.bsky.embed.recordWithMedia"
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "$type")]
 pub enum Embeds {
     #[serde(rename(
@@ -28,14 +47,19 @@ pub enum Embeds {
         serialize = "app.bsky.embed.external"
     ))]
     External(External),
-    #[serde(rename(deserialize = "app.bsky.embed.record"))]
-    TodoRecord,
-    #[serde(rename(deserialize = "app.bsky.embed.recordWithMedia"))]
-    TodoRecordWithMedia,
-    // Record(Record),
-    // #[serde(alias = "app.bsky.embed.recordWithMedia")]
-    // RecordWithMedia(RecordWithMedia),
-
+    #[serde(rename(
+        deserialize = "app.bsky.embed.record",
+        serialize = "app.bsky.embed.record"
+    ))]
+    Record(RecordEmbed),
+    #[serde(rename(
+        deserialize = "app.bsky.embed.recordWithMedia",
+        serialize = "app.bsky.embed.recordWithMedia"
+    ))]
+    RecordWithMedia(RecordWithMediaEmbed),
+    /// An embed this crate doesn't know about yet, written back as it was read
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
     // "embed": {
     //     "$type": "app.bsky.embed.images",
     //     "images": [
@@ -44,83 +68,904 @@ pub enum Embeds {
     // }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+///app.bsky.embed.record
+/// A quoted post, or another record such as a feed or list
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordEmbed {
+    pub record: StrongRef,
+}
+
+///app.bsky.embed.recordWithMedia
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordWithMediaEmbed {
+    pub record: RecordEmbed,
+    /// Images or an external link
+    pub media: Box<Embeds>,
+}
+
+/// The most languages a post may be tagged with
+pub const MAX_POST_LANGS: usize = 3;
+/// The longest post text, counted in graphemes
+pub const MAX_POST_GRAPHEMES: usize = 300;
+/// The longest post text, counted in UTF-8 bytes
+pub const MAX_POST_BYTES: usize = 3000;
+/// The most images a post may have
+pub const MAX_POST_IMAGES: usize = 4;
+/// The longest image alt text, counted in graphemes
+pub const MAX_ALT_TEXT_GRAPHEMES: usize = 2000;
+
+#[derive(Debug, Deserialize, Serialize, Builder)]
+#[builder(build_fn(validate = "Self::validate", error = "BiskyError"))]
+#[serde(rename_all = "camelCase")]
 pub struct Post {
-    #[serde(rename(deserialize = "createdAt", serialize = "createdAt"))]
-    pub created_at: DateTime<Utc>,
+    #[builder(default = "Datetime::now()")]
+    pub created_at: Datetime,
     #[serde(rename(deserialize = "$type", serialize = "$type"))]
+    #[builder(default = r#"Some("app.bsky.feed.post".to_string())"#)]
     pub rust_type: Option<String>,
+    #[builder(setter(into))]
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default, setter(strip_option))]
     pub embed: Option<Embeds>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default, setter(strip_option))]
     pub reply: Option<ReplyRef>,
+    /// BCP-47 language tags of the post text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default = "self.detect_langs()", setter(custom))]
+    pub langs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default, setter(strip_option))]
+    pub facets: Option<Vec<Facet>>,
+    /// Self-labels such as content warnings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[builder(default, setter(custom))]
+    pub labels: Option<RecordLabels>,
+}
+
+impl Post {
+    /// Check the post against the limits the server enforces, so it fails before any upload
+    pub fn validate(&self) -> Result<(), BiskyError> {
+        validate_post(
+            &self.text,
+            self.langs.as_deref(),
+            self.embed.as_ref(),
+            self.facets.as_deref(),
+        )
+    }
+}
+
+impl Post {
+    /// The images and videos of the post that have no alt text, or only whitespace
+    pub fn missing_alt_text(&self) -> Vec<Attachment> {
+        let mut missing = Vec::new();
+        if let Some(embed) = &self.embed {
+            missing_alt_text(embed, &mut missing);
+        }
+        missing
+    }
+}
+
+fn missing_alt_text(embed: &Embeds, missing: &mut Vec<Attachment>) {
+    match embed {
+        Embeds::Images(images) => missing.extend(
+            images
+                .images
+                .iter()
+                .enumerate()
+                .filter(|(_, image)| image.alt.trim().is_empty())
+                .map(|(index, _)| Attachment::Image(index)),
+        ),
+        Embeds::RecordWithMedia(embed) => missing_alt_text(&embed.media, missing),
+        // Videos have no type of their own in this crate yet
+        Embeds::Unknown(value)
+            if value["$type"] == "app.bsky.embed.video"
+                && value["alt"]
+                    .as_str()
+                    .is_none_or(|alt| alt.trim().is_empty()) =>
+        {
+            missing.push(Attachment::Video)
+        }
+        _ => {}
+    }
+}
+
+/// An image or video of a post
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attachment {
+    /// The image at this index of the images embed
+    Image(usize),
+    Video,
+}
+
+impl fmt::Display for Attachment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Image(index) => write!(f, "image {index}"),
+            Self::Video => f.write_str("video"),
+        }
+    }
+}
+
+/// What creating a post with images or videos that lack alt text does, set on the client with
+/// `BskyClient::with_alt_text_policy`
+#[derive(Clone, Default)]
+pub enum AltTextPolicy {
+    /// Fail with `BiskyError::MissingAltText`, listing the attachments without it
+    Require,
+    /// Call the callback with the attachments without it, then post anyway
+    Warn(AltTextWarning),
+    #[default]
+    Allow,
+}
+
+pub type AltTextWarning = Arc<dyn Fn(&[Attachment]) + Send + Sync>;
+
+impl fmt::Debug for AltTextPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Require => f.write_str("Require"),
+            Self::Warn(_) => f.write_str("Warn(..)"),
+            Self::Allow => f.write_str("Allow"),
+        }
+    }
+}
+
+impl AltTextPolicy {
+    /// Check `post` against the policy. Run it before uploading the blobs of a post built
+    /// ahead of time to avoid uploading for a post that will be refused.
+    pub fn check(&self, post: &Post) -> Result<(), BiskyError> {
+        if matches!(self, Self::Allow) {
+            return Ok(());
+        }
+        let missing = post.missing_alt_text();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Self::Require => Err(BiskyError::MissingAltText(missing)),
+            Self::Warn(warn) => {
+                warn(&missing);
+                Ok(())
+            }
+            Self::Allow => Ok(()),
+        }
+    }
+}
+
+impl PostBuilder {
+    /// Tag the post with a BCP-47 language, e.g. "en" or "pt-BR"
+    pub fn lang(&mut self, lang: &str) -> &mut Self {
+        self.langs
+            .get_or_insert(None)
+            .get_or_insert_with(Vec::new)
+            .push(lang.to_string());
+        self
+    }
+
+    /// Self-label the post, e.g. with `KnownLabel::GraphicMedia`
+    pub fn self_label(&mut self, val: KnownLabel) -> &mut Self {
+        self.labels
+            .get_or_insert(None)
+            .get_or_insert_with(|| SelfLabels::default().into())
+            .add(val);
+        self
+    }
+
+    /// Check the post against the limits the server enforces. This also runs on `build()`.
+    pub fn validate(&self) -> Result<(), BiskyError> {
+        let langs = self.langs.as_ref().and_then(|l| l.as_deref());
+        let embed = self.embed.as_ref().and_then(|e| e.as_ref());
+        let facets = self.facets.as_ref().and_then(|f| f.as_deref());
+        match &self.text {
+            Some(text) => validate_post(text, langs, embed, facets),
+            // build() reports the missing text itself
+            None => validate_post("", langs, embed, None),
+        }
+    }
+
+    /// Turn the `@handle` mentions in the text into mention facets, resolving each handle once
+    /// and all of them at the same time. Pass a `CachingResolver` to reuse earlier lookups.
+    /// `policy` decides what happens to mentions whose handle doesn't resolve.
+    pub async fn resolve_mentions<R: Resolver + ?Sized>(
+        &mut self,
+        resolver: &R,
+        policy: MentionPolicy,
+    ) -> Result<MentionReport, BiskyError> {
+        let Some(text) = self.text.clone() else {
+            return Ok(MentionReport::default());
+        };
+        let mentions = detect_mentions(&text);
+
+        // Handles are case-insensitive, so `@Bob.test` and `@bob.test` are one lookup
+        let mut handles = mentions
+            .iter()
+            .map(|m| m.handle.to_lowercase())
+            .collect::<Vec<_>>();
+        handles.sort();
+        handles.dedup();
+        let results =
+            futures::future::join_all(handles.iter().map(|handle| resolver.resolve_handle(handle)))
+                .await;
+
+        let mut report = MentionReport::default();
+        let mut dids = HashMap::new();
+        for (handle, result) in handles.into_iter().zip(results) {
+            match result {
+                Ok(did) => {
+                    dids.insert(handle.clone(), did.clone());
+                    report.resolved.push((handle, did));
+                }
+                Err(e) if policy == MentionPolicy::FailOnUnresolved => return Err(e),
+                Err(_) => report.unresolved.push(handle),
+            }
+        }
+
+        let mut text = text;
+        let mut facets = self.facets.clone().flatten().unwrap_or_default();
+        // From the end, so that removing a mention leaves the offsets before it as they are
+        for mention in mentions.iter().rev() {
+            let ByteSlice {
+                byte_start,
+                byte_end,
+            } = mention.index;
+            if let Some(did) = dids.get(&mention.handle.to_lowercase()) {
+                if !facets
+                    .iter()
+                    .any(|f| f.index.range() == mention.index.range())
+                {
+                    facets.push(Facet {
+                        index: mention.index,
+                        features: vec![FacetFeature::Mention { did: did.clone() }],
+                    });
+                }
+            } else if policy == MentionPolicy::SkipUnresolved {
+                // Along with one space, so that no double space is left behind
+                let end = match text[byte_end..].starts_with(' ') {
+                    true => byte_end + 1,
+                    false => byte_end,
+                };
+                text.replace_range(byte_start..end, "");
+                let removed = end - byte_start;
+                facets.retain(|f| f.index.byte_end <= byte_start || f.index.byte_start >= end);
+                for facet in &mut facets {
+                    if facet.index.byte_start >= end {
+                        facet.index.byte_start -= removed;
+                        facet.index.byte_end -= removed;
+                    }
+                }
+            }
+        }
+        facets.sort_by_key(|f| f.index.byte_start);
+
+        self.text = Some(text);
+        if !facets.is_empty() {
+            self.facets = Some(Some(facets));
+        }
+        Ok(report)
+    }
+
+    /// Build and validate the post, then create it in the logged in user's repo if the client's
+    /// `AltTextPolicy` allows it
+    pub async fn send(&self, client: &mut BskyClient) -> Result<CreateRecordOutput, BiskyError> {
+        let post = self.build()?;
+        client.bsky_create_post(&post).await
+    }
+
+    /// Prepare an image with `options`, upload it and add it to the post's images, or to the
+    /// media of a quote post, with its aspect ratio. With `ImageOptions::passthrough()` the image
+    /// is uploaded as it is. Fails before uploading if the post has no room for the image, or
+    /// the client's `AltTextPolicy` requires alt text and `alt` is empty.
+    #[cfg(feature = "image")]
+    pub async fn add_image(
+        &mut self,
+        client: &mut BskyClient,
+        data: &[u8],
+        alt: &str,
+        options: &ImageOptions,
+    ) -> Result<&mut Self, BiskyError> {
+        let index = self.image_count()?;
+        if index == MAX_POST_IMAGES {
+            return Err(BiskyError::TooManyImages(index + 1));
+        }
+        if matches!(client.alt_text_policy(), AltTextPolicy::Require) && alt.trim().is_empty() {
+            return Err(BiskyError::MissingAltText(vec![Attachment::Image(index)]));
+        }
+
+        let prepared = prepare_image(data, options)?;
+        let output: BlobOutput = client
+            .repo_upload_blob(&prepared.data, prepared.format.mime_type())
+            .await?;
+        let image = Image {
+            image: output.blob,
+            alt: alt.to_string(),
+            aspect_ratio: Some(prepared.aspect_ratio),
+        };
+
+        let embed = match self.embed.take().flatten() {
+            None => Embeds::Images(ImagesEmbed {
+                images: vec![image],
+            }),
+            Some(Embeds::Record(record)) => Embeds::RecordWithMedia(RecordWithMediaEmbed {
+                record,
+                media: Box::new(Embeds::Images(ImagesEmbed {
+                    images: vec![image],
+                })),
+            }),
+            Some(mut embed) => {
+                let images = match &mut embed {
+                    Embeds::Images(images) => images,
+                    Embeds::RecordWithMedia(embed) => match embed.media.as_mut() {
+                        Embeds::Images(images) => images,
+                        _ => unreachable!("checked by image_count"),
+                    },
+                    _ => unreachable!("checked by image_count"),
+                };
+                images.images.push(image);
+                embed
+            }
+        };
+        self.embed = Some(Some(embed));
+        Ok(self)
+    }
+
+    /// Fetch the page at `url` and make its link card the post's embed, or the media of a quote
+    /// post, replacing any other embed. The card's image is uploaded if it can be downloaded,
+    /// and left out otherwise.
+    #[cfg(feature = "link-preview")]
+    pub async fn set_external_from_url(
+        &mut self,
+        client: &mut BskyClient,
+        url: impl IntoUrl,
+    ) -> Result<&mut Self, BiskyError> {
+        let options = LinkPreviewOptions::default();
+        let preview = link_preview::fetch_with(url, &options).await?;
+        let image = match &preview.image {
+            Some(image) => link_preview::fetch_image(image, &options)
+                .await
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        let thumb = match image {
+            Some((data, mime_type)) => {
+                let output: BlobOutput = client.repo_upload_blob(&data, &mime_type).await?;
+                Some(output.blob)
+            }
+            None => None,
+        };
+
+        let external = Embeds::External(preview.into_external(thumb));
+        let embed = match self.embed.take().flatten() {
+            Some(Embeds::Record(record)) => Embeds::RecordWithMedia(RecordWithMediaEmbed {
+                record,
+                media: Box::new(external),
+            }),
+            Some(Embeds::RecordWithMedia(embed)) => Embeds::RecordWithMedia(RecordWithMediaEmbed {
+                record: embed.record,
+                media: Box::new(external),
+            }),
+            _ => external,
+        };
+        self.embed = Some(Some(embed));
+        Ok(self)
+    }
+
+    /// How many images the post has, failing if its embed can't hold images
+    #[cfg(feature = "image")]
+    fn image_count(&self) -> Result<usize, BiskyError> {
+        match self.embed.as_ref().and_then(|e| e.as_ref()) {
+            None | Some(Embeds::Record(_)) => Ok(0),
+            Some(Embeds::Images(images)) => Ok(images.images.len()),
+            Some(Embeds::RecordWithMedia(embed)) => match embed.media.as_ref() {
+                Embeds::Images(images) => Ok(images.images.len()),
+                _ => Err(BiskyError::InvalidImage(
+                    "the post's quote already has media other than images".to_string(),
+                )),
+            },
+            Some(_) => Err(BiskyError::InvalidImage(
+                "the post already has an embed other than images".to_string(),
+            )),
+        }
+    }
+
+    #[cfg(feature = "lang-detect")]
+    fn detect_langs(&self) -> Option<Vec<String>> {
+        let info = whatlang::detect(self.text.as_deref()?)?;
+        info.is_reliable()
+            .then(|| vec![iso_639_1(info.lang().code()).to_string()])
+    }
+
+    #[cfg(not(feature = "lang-detect"))]
+    fn detect_langs(&self) -> Option<Vec<String>> {
+        None
+    }
+}
+
+/// What `PostBuilder::resolve_mentions` does with a mention whose handle doesn't resolve
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MentionPolicy {
+    /// Fail with the error resolving it
+    #[default]
+    FailOnUnresolved,
+    /// Take the mention out of the text
+    SkipUnresolved,
+    /// Leave the mention in the text, without a facet
+    KeepAsText,
+}
+
+/// The handles `PostBuilder::resolve_mentions` found in the text, each listed once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MentionReport {
+    /// Handles with the DID they resolved to
+    pub resolved: Vec<(String, String)>,
+    /// Handles that didn't resolve, removed or kept as text according to the policy
+    pub unresolved: Vec<String>,
+}
+
+/// Options for `BskyClient::bsky_post_thread`
+#[derive(Debug, Clone, Default)]
+pub struct ThreadOptions {
+    /// Append a "1/n" counter to every post
+    pub counters: bool,
+    /// BCP-47 language tags for every post, detected per post when empty
+    pub langs: Vec<String>,
+}
+
+/// Split text into post sized segments, breaking after a sentence where possible and otherwise
+/// at whitespace. Words and links are only broken when a single one is longer than a post.
+/// With `counters` every segment ends in " i/n", unless the text fits in a single post.
+pub fn split_thread_text(text: &str, counters: bool) -> Vec<String> {
+    let segments = split_segments(text, 0);
+    if !counters || segments.len() <= 1 {
+        return segments;
+    }
+    // The counter width depends on the number of segments, which depends on the counter width
+    let mut total = segments.len();
+    loop {
+        let reserve = format!(" {total}/{total}").len();
+        let segments = split_segments(text, reserve);
+        if segments.len() <= total {
+            let n = segments.len();
+            return segments
+                .into_iter()
+                .enumerate()
+                .map(|(i, s)| format!("{s} {}/{n}", i + 1))
+                .collect();
+        }
+        total = segments.len();
+    }
+}
+
+fn split_segments(text: &str, reserve: usize) -> Vec<String> {
+    let max_graphemes = MAX_POST_GRAPHEMES - reserve;
+    let max_bytes = MAX_POST_BYTES - reserve;
+    let mut segments = Vec::new();
+    let mut rest = text.trim();
+
+    while !rest.is_empty() {
+        let mut cut = rest.len();
+        let mut sentence_break = None;
+        let mut space_break = None;
+        let mut prev = "";
+        for (count, (i, g)) in rest.grapheme_indices(true).enumerate() {
+            if count == max_graphemes || i + g.len() > max_bytes {
+                cut = i;
+                break;
+            }
+            if g.chars().all(char::is_whitespace) {
+                if g.contains('\n') || matches!(prev, "." | "!" | "?") {
+                    sentence_break = Some(i);
+                }
+                space_break = Some(i);
+            }
+            prev = g;
+        }
+
+        if cut < rest.len() {
+            // Only fall back to whitespace if the last sentence ends early in the segment
+            cut = match (sentence_break, space_break) {
+                (Some(s), _) if s > cut / 2 => s,
+                (_, Some(w)) => w,
+                (Some(s), None) => s,
+                (None, None) => cut,
+            };
+        }
+
+        let (segment, tail) = rest.split_at(cut);
+        let segment = segment.trim_end();
+        if !segment.is_empty() {
+            segments.push(segment.to_string());
+        }
+        rest = tail.trim_start();
+    }
+
+    segments
+}
+
+fn validate_post(
+    text: &str,
+    langs: Option<&[String]>,
+    embed: Option<&Embeds>,
+    facets: Option<&[Facet]>,
+) -> Result<(), BiskyError> {
+    let graphemes = text.graphemes(true).count();
+    if graphemes > MAX_POST_GRAPHEMES {
+        return Err(BiskyError::PostTooLong {
+            graphemes,
+            limit: MAX_POST_GRAPHEMES,
+        });
+    }
+    if text.len() > MAX_POST_BYTES {
+        return Err(BiskyError::PostTooLarge {
+            bytes: text.len(),
+            limit: MAX_POST_BYTES,
+        });
+    }
+
+    if let Some(langs) = langs {
+        if langs.len() > MAX_POST_LANGS {
+            return Err(BiskyError::TooManyLanguages(langs.len()));
+        }
+        if let Some(lang) = langs.iter().find(|l| !is_language_tag(l)) {
+            return Err(BiskyError::InvalidLanguage(lang.clone()));
+        }
+    }
+
+    // Alone or beside a quoted record
+    let images = match embed {
+        Some(Embeds::Images(images)) => Some(images),
+        Some(Embeds::RecordWithMedia(embed)) => match embed.media.as_ref() {
+            Embeds::Images(images) => Some(images),
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(images) = images {
+        if images.images.len() > MAX_POST_IMAGES {
+            return Err(BiskyError::TooManyImages(images.images.len()));
+        }
+        for (index, image) in images.images.iter().enumerate() {
+            let graphemes = image.alt.graphemes(true).count();
+            if graphemes > MAX_ALT_TEXT_GRAPHEMES {
+                return Err(BiskyError::AltTextTooLong {
+                    index,
+                    graphemes,
+                    limit: MAX_ALT_TEXT_GRAPHEMES,
+                });
+            }
+        }
+    }
+
+    validate_facets(text, facets.unwrap_or_default())
+}
+
+/// Check that every facet covers a non-empty range of whole characters of `text`
+pub(crate) fn validate_facets(text: &str, facets: &[Facet]) -> Result<(), BiskyError> {
+    for facet in facets {
+        if !facet.index.is_valid_in(text) {
+            return Err(BiskyError::InvalidFacet {
+                byte_start: facet.index.byte_start,
+                byte_end: facet.index.byte_end,
+                text_len: text.len(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Loosely check a BCP-47 tag: a 2-3 letter primary language (or a private-use `x`/`i`) followed
+/// by alphanumeric subtags of at most 8 characters
+pub fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let primary_ok = subtags.next().is_some_and(|primary| {
+        matches!(primary, "x" | "i")
+            || ((2..=3).contains(&primary.len())
+                && primary.chars().all(|c| c.is_ascii_alphabetic()))
+    });
+    primary_ok
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Shorten the ISO 639-3 codes used by whatlang to their ISO 639-1 form where one exists
+#[cfg(feature = "lang-detect")]
+fn iso_639_1(code: &'static str) -> &'static str {
+    match code {
+        "afr" => "af",
+        "aka" => "ak",
+        "amh" => "am",
+        "ara" => "ar",
+        "aze" => "az",
+        "bel" => "be",
+        "ben" => "bn",
+        "bul" => "bg",
+        "cat" => "ca",
+        "ces" => "cs",
+        "cmn" => "zh",
+        "dan" => "da",
+        "deu" => "de",
+        "ell" => "el",
+        "eng" => "en",
+        "epo" => "eo",
+        "est" => "et",
+        "fin" => "fi",
+        "fra" => "fr",
+        "guj" => "gu",
+        "heb" => "he",
+        "hin" => "hi",
+        "hrv" => "hr",
+        "hun" => "hu",
+        "hye" => "hy",
+        "ind" => "id",
+        "ita" => "it",
+        "jav" => "jv",
+        "jpn" => "ja",
+        "kan" => "kn",
+        "kat" => "ka",
+        "khm" => "km",
+        "kor" => "ko",
+        "lat" => "la",
+        "lav" => "lv",
+        "lit" => "lt",
+        "mal" => "ml",
+        "mar" => "mr",
+        "mkd" => "mk",
+        "mya" => "my",
+        "nep" => "ne",
+        "nld" => "nl",
+        "nob" => "nb",
+        "ori" => "or",
+        "pan" => "pa",
+        "pes" => "fa",
+        "pol" => "pl",
+        "por" => "pt",
+        "ron" => "ro",
+        "rus" => "ru",
+        "sin" => "si",
+        "slk" => "sk",
+        "slv" => "sl",
+        "sna" => "sn",
+        "spa" => "es",
+        "srp" => "sr",
+        "swe" => "sv",
+        "tam" => "ta",
+        "tel" => "te",
+        "tgl" => "tl",
+        "tha" => "th",
+        "tuk" => "tk",
+        "tur" => "tr",
+        "ukr" => "uk",
+        "urd" => "ur",
+        "uzb" => "uz",
+        "vie" => "vi",
+        "yid" => "yi",
+        "zul" => "zu",
+        other => other,
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ProfileViewBasic {
     pub did: String,
     pub handle: String,
+    /// The labels on the account and its profile record
+    #[serde(default)]
+    pub labels: Vec<Label>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PostView {
     pub uri: String,
     pub cid: String,
     pub author: ProfileViewBasic,
     pub record: Post,
-    #[serde(rename(deserialize = "indexedAt"))]
-    pub indexed_at: DateTime<Utc>,
+    pub embed: Option<EmbedView>,
+    pub reply_count: Option<usize>,
+    pub repost_count: Option<usize>,
+    pub like_count: Option<usize>,
+    pub quote_count: Option<usize>,
+    pub indexed_at: Datetime,
+    pub viewer: Option<PostViewerState>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+impl TryFrom<&PostView> for StrongRef {
+    type Error = BiskyError;
+
+    fn try_from(value: &PostView) -> Result<Self, Self::Error> {
+        Self::new(&value.uri, &value.cid)
+    }
+}
+
+///app.bsky.feed.defs#viewerState
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostViewerState {
+    /// The uri of the viewer's like of this post
+    pub like: Option<String>,
+    /// The uri of the viewer's repost of this post
+    pub repost: Option<String>,
+    #[serde(default)]
+    pub thread_muted: bool,
+    #[serde(default)]
+    pub reply_disabled: bool,
+    #[serde(default)]
+    pub embedding_disabled: bool,
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ReasonRepost {
     pub by: ProfileViewBasic,
-    #[serde(rename(deserialize = "indexedAt"))]
-    pub indexed_at: DateTime<Utc>,
+    pub indexed_at: Datetime,
+}
+
+/// Why a post is in a feed, when it isn't just there by its author
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+pub enum FeedReason {
+    #[serde(rename = "app.bsky.feed.defs#reasonRepost")]
+    Repost(ReasonRepost),
+    /// Pinned to the top of its author's feed
+    #[serde(rename = "app.bsky.feed.defs#reasonPin")]
+    Pin {},
+    /// A reason this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
 }
 
 #[derive(Debug, Deserialize)]
 pub struct FeedViewPost {
     pub post: PostView,
-    pub reason: Option<ReasonRepost>,
+    pub reason: Option<FeedReason>,
 }
 
+impl FeedViewPost {
+    /// When this item entered the feed, which for reposts is the time of the repost
+    pub fn sort_at(&self) -> Datetime {
+        match &self.reason {
+            Some(FeedReason::Repost(reason)) => reason.indexed_at,
+            _ => self.post.indexed_at,
+        }
+    }
+}
+
+///app.bsky.feed.getAuthorFeed
 #[derive(Debug, Deserialize)]
 pub struct AuthorFeed {
     pub cursor: Option<String>,
     pub feed: Vec<FeedViewPost>,
 }
 
+///app.bsky.feed.getTimeline
+#[derive(Debug, Deserialize)]
+pub struct GetTimelineOutput {
+    pub cursor: Option<String>,
+    pub feed: Vec<FeedViewPost>,
+}
+
+///app.bsky.feed.getFeed
+#[derive(Debug, Deserialize)]
+pub struct GetFeedOutput {
+    pub cursor: Option<String>,
+    pub feed: Vec<FeedViewPost>,
+}
+
+///app.bsky.feed.getActorLikes
+#[derive(Debug, Deserialize)]
+pub struct GetActorLikesOutput {
+    pub cursor: Option<String>,
+    pub feed: Vec<FeedViewPost>,
+}
+
+///app.bsky.feed.getListFeed
+#[derive(Debug, Deserialize)]
+pub struct GetListFeedOutput {
+    pub cursor: Option<String>,
+    pub feed: Vec<FeedViewPost>,
+}
+
+/// A feed item with only what identifies it and the text of its post decoded, for skimming a
+/// feed without decoding every embed and profile. The rest is kept as JSON for `decode_full`.
+#[derive(Debug)]
+pub struct LazyFeedViewPost {
+    pub uri: String,
+    pub author_did: String,
+    pub indexed_at: Datetime,
+    pub text: String,
+    raw: Box<RawValue>,
+}
+
+impl LazyFeedViewPost {
+    /// The item as `FeedViewPost`, decoded the same as an eagerly read one
+    pub fn decode_full(&self) -> Result<FeedViewPost, BiskyError> {
+        Ok(serde_json::from_str(self.raw.get())?)
+    }
+
+    /// The item as the JSON it came as
+    pub fn raw(&self) -> &RawValue {
+        &self.raw
+    }
+}
+
+impl<'de> Deserialize<'de> for LazyFeedViewPost {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Item {
+            post: Header,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Header {
+            uri: String,
+            author: Author,
+            indexed_at: Datetime,
+            record: Record,
+        }
+
+        #[derive(Deserialize)]
+        struct Author {
+            did: String,
+        }
+
+        #[derive(Deserialize)]
+        struct Record {
+            #[serde(default)]
+            text: String,
+        }
+
+        let raw = Box::<RawValue>::deserialize(deserializer)?;
+        let item: Item = serde_json::from_str(raw.get()).map_err(de::Error::custom)?;
+        Ok(Self {
+            uri: item.post.uri,
+            author_did: item.post.author.did,
+            indexed_at: item.post.indexed_at,
+            text: item.post.record.text,
+            raw,
+        })
+    }
+}
+
+/// A page of getTimeline, getFeed or getListFeed with its items left mostly undecoded
+#[derive(Debug, Deserialize)]
+pub struct LazyFeedOutput {
+    pub cursor: Option<String>,
+    pub feed: Vec<LazyFeedViewPost>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Like {
-    #[serde(rename(deserialize = "createdAt"))]
-    #[serde(rename(serialize = "createdAt"))]
-    pub created_at: DateTime<Utc>,
+    pub created_at: Datetime,
     pub subject: StrongRef,
 }
 
 ///like from app.bsky.feed.getLikes
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GetLikesLike {
-    #[serde(rename(deserialize = "createdAt"))]
-    #[serde(rename(serialize = "createdAt"))]
-    pub created_at: DateTime<Utc>,
-    #[serde(rename(deserialize = "indexedAt"))]
-    #[serde(rename(serialize = "indexedAt"))]
-    pub indexed_at: DateTime<Utc>,
+    pub created_at: Datetime,
+    pub indexed_at: Datetime,
     pub actor: ProfileView,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Repost {
-    #[serde(rename(deserialize = "createdAt"))]
-    #[serde(rename(serialize = "createdAt"))]
-    pub created_at: DateTime<Utc>,
+    pub created_at: Datetime,
     pub subject: StrongRef,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplyRef {
     pub root: StrongRef,
     pub parent: StrongRef,
@@ -141,25 +986,264 @@ pub struct GetLikesOutput {
     pub cursor: Option<String>,
 }
 
+///app.bsky.feed.getRepostedBy
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRepostedByOutput {
+    pub uri: String,
+    pub cid: Option<String>,
+    pub reposted_by: Vec<ProfileView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.feed.getQuotes
+#[derive(Debug, Deserialize)]
+pub struct GetQuotesOutput {
+    pub uri: String,
+    pub cid: Option<String>,
+    pub posts: Vec<PostView>,
+    pub cursor: Option<String>,
+}
+
+/// Something new on a post watched with `bsky_watch_post`
+#[derive(Debug)]
+pub enum PostEngagement {
+    NewLike(ProfileView),
+    NewRepost(ProfileView),
+    NewReply(PostView),
+    NewQuote(PostView),
+    /// The post is gone, after which the stream ends
+    PostDeleted,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ThreadViewPost {
     pub post: PostView,
+    pub parent: Option<ThreadViewPostEnum>,
+    /// Missing when the thread was cut off at the requested depth
+    pub replies: Option<Vec<ThreadViewPostEnum>>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct NotFoundPost {
     pub uri: String,
-    #[serde(rename(deserialize = "notFound"))]
     pub not_found: bool,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BlockedAuthor {
+    pub did: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockedPost {
+    pub uri: String,
+    pub blocked: bool,
+    pub author: BlockedAuthor,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "$type")]
 pub enum ThreadViewPostEnum {
     #[serde(rename(deserialize = "app.bsky.feed.defs#threadViewPost"))]
-    ThreadViewPost(ThreadViewPost),
+    ThreadViewPost(Box<ThreadViewPost>),
     #[serde(rename(deserialize = "app.bsky.feed.defs#notFoundPost"))]
     NotFoundPost(NotFoundPost),
+    #[serde(rename(deserialize = "app.bsky.feed.defs#blockedPost"))]
+    BlockedPost(BlockedPost),
+    /// A thread node this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+/// A whole thread gathered over several getPostThread calls
+#[derive(Debug, Default)]
+pub struct FullThread {
+    /// The uri of the thread root
+    pub root: String,
+    /// Every reachable post, oldest first
+    pub posts: Vec<PostView>,
+    /// Uris of posts that were blocked or not found somewhere in the thread
+    pub missing: Vec<String>,
+    /// Whether `max_posts` stopped the walk before the whole thread was fetched
+    pub truncated: bool,
+}
+
+impl FullThread {
+    pub fn get(&self, uri: &str) -> Option<&PostView> {
+        self.posts.iter().find(|p| p.uri == uri)
+    }
+
+    /// The direct replies to a post, oldest first
+    pub fn replies_to<'a>(&'a self, uri: &'a str) -> impl Iterator<Item = &'a PostView> + 'a {
+        self.posts.iter().filter(move |p| {
+            p.record
+                .reply
+                .as_ref()
+                .is_some_and(|reply| reply.parent.uri.to_string() == uri)
+        })
+    }
+}
+
+/// The unbroken run of posts a thread's author made replying to themselves, gathered by
+/// `bsky_unroll_thread`
+#[derive(Debug, Default)]
+pub struct UnrolledThread {
+    /// The author's posts, in order
+    pub posts: Vec<PostView>,
+    /// Indexes into `posts` of the posts that reply to a deleted post of the chain
+    pub gaps: Vec<usize>,
+}
+
+impl UnrolledThread {
+    /// The posts as Markdown, with their links, mentions and tags as links, images with their
+    /// alt text and link cards as links. Posts are separated by blank lines.
+    pub fn to_markdown(&self) -> String {
+        self.render(true)
+    }
+
+    /// The posts as plain text, with link targets after the text they are on where that differs
+    /// and images as their alt text
+    pub fn to_text(&self) -> String {
+        self.render(false)
+    }
+
+    fn render(&self, markdown: bool) -> String {
+        let mut out = String::new();
+        for (i, post) in self.posts.iter().enumerate() {
+            if i > 0 {
+                out.push_str("\n\n");
+            }
+            if self.gaps.contains(&i) {
+                out.push_str(match markdown {
+                    true => "*[A post was deleted here]*\n\n",
+                    false => "[A post was deleted here]\n\n",
+                });
+            }
+            let facets = post.record.facets.as_deref().unwrap_or_default();
+            render_text(&post.record.text, facets, markdown, &mut out);
+            if let Some(embed) = &post.embed {
+                render_embed(embed, markdown, &mut out);
+            }
+        }
+        out
+    }
+}
+
+/// Write `text` with its facets to `out`. Facets that overlap an earlier one or don't fall on
+/// characters are left as plain text.
+fn render_text(text: &str, facets: &[Facet], markdown: bool, out: &mut String) {
+    let mut facets = facets
+        .iter()
+        .filter(|facet| facet.index.is_valid_in(text))
+        .collect::<Vec<_>>();
+    facets.sort_by_key(|facet| facet.index.byte_start);
+
+    let mut pos = 0;
+    for facet in facets {
+        let range = facet.index.range();
+        if range.start < pos {
+            continue;
+        }
+        let Some((target, is_link)) = facet.features.iter().find_map(|feature| match feature {
+            FacetFeature::Link { uri } => Some((uri.clone(), true)),
+            FacetFeature::Mention { did } => {
+                Some((format!("https://bsky.app/profile/{did}"), false))
+            }
+            FacetFeature::Tag { tag } => Some((format!("https://bsky.app/hashtag/{tag}"), false)),
+            FacetFeature::Unknown(_) => None,
+        }) else {
+            continue;
+        };
+        out.push_str(&text[pos..range.start]);
+        let label = &text[range.clone()];
+        if markdown {
+            out.push_str(&format!("[{}]({target})", escape_label(label)));
+        } else if is_link && label != target {
+            // Links are usually shortened for display
+            out.push_str(&format!("{label} ({target})"));
+        } else {
+            out.push_str(label);
+        }
+        pos = range.end;
+    }
+    out.push_str(&text[pos..]);
+}
+
+fn render_embed(embed: &EmbedView, markdown: bool, out: &mut String) {
+    match embed {
+        EmbedView::Images(view) => {
+            for image in &view.images {
+                match markdown {
+                    true => out.push_str(&format!(
+                        "\n\n![{}]({})",
+                        escape_label(&image.alt),
+                        image.full_size
+                    )),
+                    false if image.alt.is_empty() => out.push_str("\n\n[image]"),
+                    false => out.push_str(&format!("\n\n[image: {}]", image.alt)),
+                }
+            }
+        }
+        EmbedView::External(view) => {
+            let external = &view.external;
+            let title = match external.title.is_empty() {
+                true => &external.uri,
+                false => &external.title,
+            };
+            match markdown {
+                true => out.push_str(&format!("\n\n[{}]({})", escape_label(title), external.uri)),
+                false if title == &external.uri => out.push_str(&format!("\n\n{title}")),
+                false => out.push_str(&format!("\n\n{title} ({})", external.uri)),
+            }
+        }
+        EmbedView::Record(view) => render_quote(view, markdown, out),
+        EmbedView::RecordWithMedia(view) => {
+            render_embed(&view.media, markdown, out);
+            render_quote(&view.record, markdown, out);
+        }
+        #[cfg(feature = "video")]
+        EmbedView::Video(view) => match (markdown, view.alt.as_deref()) {
+            (true, alt) => out.push_str(&format!(
+                "\n\n[Video: {}]({})",
+                escape_label(alt.unwrap_or_default()),
+                view.playlist
+            )),
+            (false, Some(alt)) if !alt.is_empty() => out.push_str(&format!("\n\n[video: {alt}]")),
+            (false, _) => out.push_str("\n\n[video]"),
+        },
+        EmbedView::Unknown(_) => {}
+    }
+}
+
+/// A quoted post as a link to it on bsky.app
+fn render_quote(view: &RecordView, markdown: bool, out: &mut String) {
+    let ViewRecordUnion::Record(record) = &view.record else {
+        return;
+    };
+    let Ok(url) = record
+        .uri
+        .parse::<AtUri>()
+        .and_then(|uri| uri.to_bsky_url())
+    else {
+        return;
+    };
+    match markdown {
+        true => out.push_str(&format!(
+            "\n\n[Quoting @{}]({url})",
+            escape_label(&record.author.handle)
+        )),
+        false => out.push_str(&format!("\n\nQuoting @{} ({url})", record.author.handle)),
+    }
+}
+
+/// `label` made safe to put between the brackets of a Markdown link
+fn escape_label(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
 }
 
 ///api.bsky.feed.getPostThread
@@ -168,7 +1252,794 @@ pub struct GetPostThread {
     pub uri: String,
     pub depth: Option<usize>,
 }
+/// The most posts app.bsky.feed.getPosts takes at once
+pub const MAX_GET_POSTS: usize = 25;
+
+/// How many feeds `bsky_get_feed_generators` asks app.bsky.feed.getFeedGenerators for at once.
+/// The lexicon sets no limit, so this keeps to the size of the other batch queries.
+pub const MAX_GET_FEED_GENERATORS: usize = 25;
+
+///app.bsky.feed.getPosts
+#[derive(Debug, Deserialize)]
+pub struct GetPostsOutput {
+    pub posts: Vec<PostView>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GetPostThreadOutput {
     pub thread: ThreadViewPostEnum,
 }
+
+///app.bsky.feed.generator
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Generator {
+    /// The DID of the service that serves the feed
+    pub did: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub description_facets: Vec<Facet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<Blob>,
+    /// Whether the feed takes `app.bsky.feed.sendInteractions`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepts_interactions: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<RecordLabels>,
+    /// E.g. `app.bsky.feed.defs#contentModeVideo`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_mode: Option<String>,
+    pub created_at: Datetime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneratorViewerState {
+    pub like: Option<String>,
+}
+
+///app.bsky.feed.defs#generatorView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeneratorView {
+    pub uri: String,
+    pub cid: String,
+    pub did: String,
+    pub creator: ProfileView,
+    pub display_name: String,
+    pub description: Option<String>,
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub like_count: usize,
+    #[serde(default)]
+    pub accepts_interactions: bool,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub viewer: Option<GeneratorViewerState>,
+    pub indexed_at: Datetime,
+}
+
+///app.bsky.feed.getFeedGenerator
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetFeedGeneratorOutput {
+    pub view: GeneratorView,
+    pub is_online: bool,
+    pub is_valid: bool,
+}
+
+///app.bsky.feed.getFeedGenerators
+#[derive(Debug, Deserialize)]
+pub struct GetFeedGeneratorsOutput {
+    pub feeds: Vec<GeneratorView>,
+}
+
+///app.bsky.feed.getActorFeeds
+#[derive(Debug, Deserialize)]
+pub struct GetActorFeedsOutput {
+    pub cursor: Option<String>,
+    pub feeds: Vec<GeneratorView>,
+}
+
+///app.bsky.feed.getSuggestedFeeds
+#[derive(Debug, Deserialize)]
+pub struct GetSuggestedFeedsOutput {
+    pub cursor: Option<String>,
+    pub feeds: Vec<GeneratorView>,
+}
+
+///app.bsky.feed.defs#interaction event tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum InteractionEvent {
+    #[serde(rename = "app.bsky.feed.defs#requestLess")]
+    RequestLess,
+    #[serde(rename = "app.bsky.feed.defs#requestMore")]
+    RequestMore,
+    #[serde(rename = "app.bsky.feed.defs#clickthroughItem")]
+    ClickthroughItem,
+    #[serde(rename = "app.bsky.feed.defs#clickthroughAuthor")]
+    ClickthroughAuthor,
+    #[serde(rename = "app.bsky.feed.defs#clickthroughReposter")]
+    ClickthroughReposter,
+    #[serde(rename = "app.bsky.feed.defs#clickthroughEmbed")]
+    ClickthroughEmbed,
+    #[serde(rename = "app.bsky.feed.defs#interactionSeen")]
+    InteractionSeen,
+    #[serde(rename = "app.bsky.feed.defs#interactionLike")]
+    InteractionLike,
+    #[serde(rename = "app.bsky.feed.defs#interactionRepost")]
+    InteractionRepost,
+    #[serde(rename = "app.bsky.feed.defs#interactionReply")]
+    InteractionReply,
+    #[serde(rename = "app.bsky.feed.defs#interactionQuote")]
+    InteractionQuote,
+    #[serde(rename = "app.bsky.feed.defs#interactionShare")]
+    InteractionShare,
+}
+
+///app.bsky.feed.defs#interaction
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interaction {
+    pub item: String,
+    pub event: InteractionEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feed_context: Option<String>,
+}
+
+///app.bsky.feed.sendInteractions
+#[derive(Debug, Serialize)]
+pub struct SendInteractions<'a> {
+    pub interactions: &'a [Interaction],
+}
+
+///app.bsky.feed.threadgate rules, an empty list means nobody can reply
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "$type")]
+pub enum ThreadgateRule {
+    #[serde(rename = "app.bsky.feed.threadgate#mentionRule")]
+    Mention,
+    #[serde(rename = "app.bsky.feed.threadgate#followerRule")]
+    Follower,
+    #[serde(rename = "app.bsky.feed.threadgate#followingRule")]
+    Following,
+    #[serde(rename = "app.bsky.feed.threadgate#listRule")]
+    List { list: String },
+    /// A rule this crate doesn't know about yet, written back as it was read so that updating
+    /// a threadgate keeps it
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///app.bsky.feed.threadgate
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Threadgate {
+    pub post: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<ThreadgateRule>>,
+    pub created_at: Datetime,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hidden_replies: Vec<String>,
+}
+
+///app.bsky.feed.postgate embedding rules
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "$type")]
+pub enum PostgateEmbeddingRule {
+    #[serde(rename = "app.bsky.feed.postgate#disableRule")]
+    Disable,
+    /// A rule this crate doesn't know about yet, written back as it was read
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///app.bsky.feed.postgate
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Postgate {
+    pub post: String,
+    pub created_at: Datetime,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detached_embedding_uris: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embedding_rules: Vec<PostgateEmbeddingRule>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const POST_URI: &str = "at://did:plc:alice/app.bsky.feed.post/3kdfsvlcf5c2a";
+    const POST_CID: &str = "bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom";
+
+    fn created_at() -> Datetime {
+        "2024-01-01T00:00:00.000Z".parse().unwrap()
+    }
+
+    #[test]
+    fn like_json() {
+        let like = Like {
+            created_at: created_at(),
+            subject: StrongRef::new(POST_URI, POST_CID).unwrap(),
+        };
+        assert_eq!(
+            serde_json::to_string(&like).unwrap(),
+            concat!(
+                r#"{"createdAt":"2024-01-01T00:00:00.000Z","#,
+                r#""subject":{"uri":"at://did:plc:alice/app.bsky.feed.post/3kdfsvlcf5c2a","#,
+                r#""cid":"bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom"}}"#,
+            )
+        );
+
+        // As a PDS returns it, with its `$type`
+        let read: Like = serde_json::from_value(json!({
+            "$type": "app.bsky.feed.like",
+            "createdAt": "2024-01-01T00:00:00.000Z",
+            "subject": {"cid": POST_CID, "uri": POST_URI},
+        }))
+        .unwrap();
+        assert_eq!(read.created_at, like.created_at);
+        assert_eq!(read.subject, like.subject);
+    }
+
+    #[test]
+    fn repost_json() {
+        let repost = Repost {
+            created_at: created_at(),
+            subject: StrongRef::new(POST_URI, POST_CID).unwrap(),
+        };
+        assert_eq!(
+            serde_json::to_value(&repost).unwrap(),
+            json!({
+                "createdAt": "2024-01-01T00:00:00.000Z",
+                "subject": {"uri": POST_URI, "cid": POST_CID},
+            })
+        );
+        // The same shape as a like
+        let like = Like {
+            created_at: repost.created_at,
+            subject: repost.subject.clone(),
+        };
+        assert_eq!(
+            serde_json::to_string(&repost).unwrap(),
+            serde_json::to_string(&like).unwrap()
+        );
+
+        let read: Repost = serde_json::from_value(json!({
+            "$type": "app.bsky.feed.repost",
+            "createdAt": "2024-01-01T00:00:00.000Z",
+            "subject": {"cid": POST_CID, "uri": POST_URI},
+        }))
+        .unwrap();
+        assert_eq!(read.subject, repost.subject);
+    }
+
+    #[test]
+    fn threadgate_json() {
+        let threadgate = |allow| Threadgate {
+            post: POST_URI.to_string(),
+            allow,
+            created_at: created_at(),
+            hidden_replies: Vec::new(),
+        };
+
+        // Nobody can reply: an empty allow, not a missing one
+        assert_eq!(
+            serde_json::to_string(&threadgate(Some(Vec::new()))).unwrap(),
+            concat!(
+                r#"{"post":"at://did:plc:alice/app.bsky.feed.post/3kdfsvlcf5c2a","allow":[],"#,
+                r#""createdAt":"2024-01-01T00:00:00.000Z"}"#,
+            )
+        );
+        // Anyone can reply
+        assert_eq!(
+            serde_json::to_value(threadgate(None)).unwrap(),
+            json!({"post": POST_URI, "createdAt": "2024-01-01T00:00:00.000Z"})
+        );
+
+        let list = "at://did:plc:alice/app.bsky.graph.list/3kdfsvlcf5c2b";
+        let mut gate = threadgate(Some(vec![
+            ThreadgateRule::Mention,
+            ThreadgateRule::Follower,
+            ThreadgateRule::Following,
+            ThreadgateRule::List {
+                list: list.to_string(),
+            },
+        ]));
+        gate.hidden_replies = vec!["at://did:plc:bob/app.bsky.feed.post/3kdfsvlcf5c2c".into()];
+        let expected = json!({
+            "post": POST_URI,
+            "allow": [
+                {"$type": "app.bsky.feed.threadgate#mentionRule"},
+                {"$type": "app.bsky.feed.threadgate#followerRule"},
+                {"$type": "app.bsky.feed.threadgate#followingRule"},
+                {"$type": "app.bsky.feed.threadgate#listRule", "list": list},
+            ],
+            "createdAt": "2024-01-01T00:00:00.000Z",
+            "hiddenReplies": ["at://did:plc:bob/app.bsky.feed.post/3kdfsvlcf5c2c"],
+        });
+        assert_eq!(serde_json::to_value(&gate).unwrap(), expected);
+
+        // And back, keeping rules this crate doesn't know
+        let mut with_unknown = expected.clone();
+        with_unknown["allow"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"$type": "app.bsky.feed.threadgate#verifiedRule", "since": 3}));
+        let read: Threadgate = serde_json::from_value(with_unknown.clone()).unwrap();
+        assert!(matches!(
+            read.allow.as_deref(),
+            Some([
+                ThreadgateRule::Mention,
+                ThreadgateRule::Follower,
+                ThreadgateRule::Following,
+                ThreadgateRule::List { .. },
+                ThreadgateRule::Unknown(_),
+            ])
+        ));
+        assert_eq!(serde_json::to_value(&read).unwrap(), with_unknown);
+    }
+
+    #[test]
+    fn self_labelled_post_json() {
+        let post = PostBuilder::default()
+            .text("Graphic")
+            .created_at(created_at())
+            .lang("en")
+            .self_label(KnownLabel::GraphicMedia)
+            .self_label(KnownLabel::GraphicMedia)
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_string(&post).unwrap(),
+            concat!(
+                r#"{"createdAt":"2024-01-01T00:00:00.000Z","$type":"app.bsky.feed.post","#,
+                r#""text":"Graphic","langs":["en"],"labels":{"#,
+                r#""$type":"com.atproto.label.defs#selfLabels","values":[{"val":"graphic-media"}]}}"#,
+            )
+        );
+
+        // Unlabelled posts leave `labels` out
+        let post = PostBuilder::default()
+            .text("Plain")
+            .created_at(created_at())
+            .lang("en")
+            .build()
+            .unwrap();
+        assert!(serde_json::to_value(&post).unwrap().get("labels").is_none());
+    }
+
+    #[test]
+    fn labelled_post_round_trip() {
+        // A self-labelled post as another client writes it
+        let record = json!({
+            "$type": "app.bsky.feed.post",
+            "createdAt": "2024-03-02T17:41:09.302Z",
+            "embed": {
+                "$type": "app.bsky.embed.images",
+                "images": [{
+                    "alt": "",
+                    "aspectRatio": {"height": 2000, "width": 1500},
+                    "image": {
+                        "$type": "blob",
+                        "ref": {"$link": "bafkreibjfgx2gprinfvicegelk5kosd6y2frmqpqzwqkg7usac74l3t2v4"},
+                        "mimeType": "image/jpeg",
+                        "size": 562413,
+                    },
+                }],
+            },
+            "labels": {
+                "$type": "com.atproto.label.defs#selfLabels",
+                "values": [{"val": "sexual"}, {"val": "nudity"}],
+            },
+            "langs": ["en"],
+            "text": "New set",
+        });
+        let post: Post = serde_json::from_value(record.clone()).unwrap();
+        let Some(RecordLabels::SelfLabels(labels)) = &post.labels else {
+            panic!("not self labels");
+        };
+        assert_eq!(labels.values[0].val, KnownLabel::Sexual);
+        assert_eq!(labels.values[1].val, KnownLabel::Nudity);
+        assert_eq!(serde_json::to_value(&post).unwrap(), record);
+    }
+
+    // One grapheme each: 1 char and 4 bytes, 7 chars and 25 bytes, 2 chars and 3 bytes
+    const FACE: &str = "\u{1F600}";
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+    const ACUTE_E: &str = "e\u{301}";
+
+    fn images(alts: &[&str]) -> ImagesEmbed {
+        ImagesEmbed {
+            images: alts
+                .iter()
+                .map(|alt| Image {
+                    image: Blob::new(POST_CID, "image/png", 1000),
+                    alt: alt.to_string(),
+                    aspect_ratio: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn with_quote(media: ImagesEmbed) -> Embeds {
+        Embeds::RecordWithMedia(RecordWithMediaEmbed {
+            record: RecordEmbed {
+                record: StrongRef::new(POST_URI, POST_CID).unwrap(),
+            },
+            media: Box::new(Embeds::Images(media)),
+        })
+    }
+
+    fn validate_text(text: &str) -> Result<(), BiskyError> {
+        PostBuilder::default().text(text).lang("en").validate()
+    }
+
+    #[test]
+    fn post_graphemes() {
+        assert_eq!(FAMILY.graphemes(true).count(), 1);
+        assert_eq!((FAMILY.chars().count(), FAMILY.len()), (7, 25));
+        assert_eq!((ACUTE_E.chars().count(), ACUTE_E.len()), (2, 3));
+
+        for grapheme in ["a", FACE, FAMILY, ACUTE_E] {
+            let count = MAX_POST_BYTES / grapheme.len();
+            let at_limit = grapheme.repeat(count.min(MAX_POST_GRAPHEMES));
+            validate_text(&at_limit).unwrap();
+        }
+        // 600 chars and 900 bytes, but 300 graphemes
+        validate_text(&ACUTE_E.repeat(MAX_POST_GRAPHEMES)).unwrap();
+        assert!(matches!(
+            validate_text(&ACUTE_E.repeat(MAX_POST_GRAPHEMES + 1)),
+            Err(BiskyError::PostTooLong {
+                graphemes: 301,
+                limit: 300
+            })
+        ));
+        assert!(matches!(
+            validate_text(&format!("{}{FACE}", "a".repeat(MAX_POST_GRAPHEMES))),
+            Err(BiskyError::PostTooLong {
+                graphemes: 301,
+                limit: 300
+            })
+        ));
+        // An empty post is fine, if pointless
+        validate_text("").unwrap();
+    }
+
+    #[test]
+    fn post_bytes() {
+        // 120 graphemes but 3000 bytes
+        let at_limit = FAMILY.repeat(MAX_POST_BYTES / FAMILY.len());
+        assert_eq!(at_limit.len(), MAX_POST_BYTES);
+        validate_text(&at_limit).unwrap();
+        assert!(matches!(
+            validate_text(&format!("{at_limit}a")),
+            Err(BiskyError::PostTooLarge {
+                bytes: 3001,
+                limit: 3000
+            })
+        ));
+        // Too long counts first
+        assert!(matches!(
+            validate_text(&FAMILY.repeat(MAX_POST_GRAPHEMES + 1)),
+            Err(BiskyError::PostTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn post_langs() {
+        let mut post = PostBuilder::default();
+        post.text("Olá").lang("pt-BR").lang("en").lang("x-klingon");
+        post.validate().unwrap();
+        assert!(matches!(
+            post.lang("fr").validate(),
+            Err(BiskyError::TooManyLanguages(4))
+        ));
+
+        let invalid = PostBuilder::default().text("Hi").lang("english").validate();
+        assert!(matches!(invalid, Err(BiskyError::InvalidLanguage(l)) if l == "english"));
+    }
+
+    #[test]
+    fn post_images() {
+        let four = ["a"; MAX_POST_IMAGES];
+        let five = ["a"; MAX_POST_IMAGES + 1];
+        for (embed, count) in [
+            (Embeds::Images(images(&four)), None),
+            (Embeds::Images(images(&five)), Some(5)),
+            (with_quote(images(&four)), None),
+            (with_quote(images(&five)), Some(5)),
+        ] {
+            let result = PostBuilder::default()
+                .text("Pictures")
+                .lang("en")
+                .embed(embed)
+                .build();
+            match count {
+                None => result.unwrap().validate().unwrap(),
+                Some(count) => {
+                    assert!(matches!(result, Err(BiskyError::TooManyImages(c)) if c == count))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alt_text_graphemes() {
+        let at_limit = FAMILY.repeat(MAX_ALT_TEXT_GRAPHEMES);
+        let over = ACUTE_E.repeat(MAX_ALT_TEXT_GRAPHEMES + 1);
+        for embed in [images(&["", &at_limit]), images(&["", &over])] {
+            let too_long = embed.images[1].alt == over;
+            for embed in [Embeds::Images(embed.clone()), with_quote(embed)] {
+                let result = PostBuilder::default()
+                    .text("Pictures")
+                    .lang("en")
+                    .embed(embed)
+                    .validate();
+                if too_long {
+                    assert!(matches!(
+                        result,
+                        Err(BiskyError::AltTextTooLong {
+                            index: 1,
+                            graphemes: 2001,
+                            limit: 2000
+                        })
+                    ));
+                } else {
+                    result.unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn facets_against_the_final_text() {
+        let text = format!("{FAMILY} #tag");
+        let facet = |byte_start, byte_end| Facet {
+            index: ByteSlice {
+                byte_start,
+                byte_end,
+            },
+            features: vec![FacetFeature::Tag { tag: "tag".into() }],
+        };
+        let validate = |facet: Facet| {
+            PostBuilder::default()
+                .text(text.as_str())
+                .lang("en")
+                .facets(vec![facet])
+                .validate()
+        };
+
+        // The whole text, the tag up to the very end, and the emoji alone
+        validate(facet(0, text.len())).unwrap();
+        validate(facet(26, text.len())).unwrap();
+        validate(facet(0, FAMILY.len())).unwrap();
+        for (start, end) in [(26, text.len() + 1), (0, 1), (1, 26), (26, 26), (30, 26)] {
+            assert!(matches!(
+                validate(facet(start, end)),
+                Err(BiskyError::InvalidFacet {
+                    byte_start,
+                    byte_end,
+                    text_len: 30,
+                }) if (byte_start, byte_end) == (start, end)
+            ));
+        }
+    }
+
+    #[test]
+    fn strong_ref_needs_an_at_uri() {
+        assert!(StrongRef::new("https://bsky.app/profile/alice", POST_CID).is_err());
+        assert!(serde_json::from_value::<Like>(json!({
+            "createdAt": "2024-01-01T00:00:00.000Z",
+            "subject": {"uri": "not a uri", "cid": POST_CID},
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn alt_text_policy() {
+        let post = |embed: Embeds| {
+            PostBuilder::default()
+                .text("Pictures")
+                .embed(embed)
+                .build()
+                .unwrap()
+        };
+        let video = |alt: Option<&str>| {
+            let mut video = json!({"$type": "app.bsky.embed.video", "video": {}});
+            if let Some(alt) = alt {
+                video["alt"] = alt.into();
+            }
+            Embeds::Unknown(video)
+        };
+        let warned = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let warn = AltTextPolicy::Warn({
+            let warned = warned.clone();
+            Arc::new(move |missing: &[Attachment]| warned.lock().push(missing.to_vec()))
+        });
+
+        // Two images where only the second lacks alt text, whitespace counting as none
+        for embed in [
+            Embeds::Images(images(&["A cat", " "])),
+            with_quote(images(&["A cat", ""])),
+        ] {
+            let mixed = post(embed);
+            assert_eq!(mixed.missing_alt_text(), [Attachment::Image(1)]);
+            match AltTextPolicy::Require.check(&mixed) {
+                Err(BiskyError::MissingAltText(missing)) => {
+                    assert_eq!(missing, [Attachment::Image(1)]);
+                }
+                other => panic!("{other:?}"),
+            }
+            warn.check(&mixed).unwrap();
+            assert_eq!(warned.lock().pop(), Some(vec![Attachment::Image(1)]));
+            AltTextPolicy::Allow.check(&mixed).unwrap();
+        }
+
+        let neither = post(Embeds::Images(images(&["", ""])));
+        assert!(matches!(
+            AltTextPolicy::Require.check(&neither),
+            Err(BiskyError::MissingAltText(missing))
+                if missing == [Attachment::Image(0), Attachment::Image(1)]
+        ));
+
+        for alt in [None, Some("")] {
+            let post = post(video(alt));
+            assert!(matches!(
+                AltTextPolicy::Require.check(&post),
+                Err(BiskyError::MissingAltText(missing)) if missing == [Attachment::Video]
+            ));
+            warn.check(&post).unwrap();
+            assert_eq!(warned.lock().pop(), Some(vec![Attachment::Video]));
+        }
+
+        // Nothing to say about posts with all their alt text, or without media
+        for post in [
+            post(Embeds::Images(images(&["A cat", "A dog"]))),
+            post(video(Some("A cat playing"))),
+            PostBuilder::default().text("Words").build().unwrap(),
+        ] {
+            AltTextPolicy::Require.check(&post).unwrap();
+            warn.check(&post).unwrap();
+        }
+        assert!(warned.lock().is_empty());
+    }
+
+    /// Resolves the handles it knows, noting every lookup and how many ran at once
+    #[derive(Default)]
+    struct StubResolver {
+        known: HashMap<&'static str, &'static str>,
+        lookups: parking_lot::Mutex<Vec<String>>,
+        running: AtomicUsize,
+        most_running: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Resolver for StubResolver {
+        async fn resolve_handle(&self, handle: &str) -> Result<String, BiskyError> {
+            self.lookups.lock().push(handle.to_string());
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_running.fetch_max(running, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.known
+                .get(handle)
+                .map(|did| did.to_string())
+                .ok_or_else(|| BiskyError::HandleNotFound(handle.to_string()))
+        }
+
+        async fn resolve_did(&self, did: &str) -> Result<crate::identity::DidDocument, BiskyError> {
+            Err(BiskyError::UnsupportedDid(did.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn mention_policies() {
+        const TEXT: &str =
+            "Thanks @bob.test and @Carol.test! cc @nobody.test @bob.test https://atproto.com";
+        let link = |at: usize| Facet {
+            index: ByteSlice {
+                byte_start: at,
+                byte_end: at + "https://atproto.com".len(),
+            },
+            features: vec![FacetFeature::Link {
+                uri: "https://atproto.com".to_string(),
+            }],
+        };
+        let builder = || {
+            let mut builder = PostBuilder::default();
+            builder
+                .text(TEXT)
+                .facets(vec![link(TEXT.find("https").unwrap())]);
+            builder
+        };
+        let resolve = |policy| async move {
+            let resolver = StubResolver {
+                known: HashMap::from([
+                    ("bob.test", "did:plc:bob"),
+                    ("carol.test", "did:plc:carol"),
+                ]),
+                ..Default::default()
+            };
+            let mut builder = builder();
+            let report = builder.resolve_mentions(&resolver, policy).await;
+            // Each handle looked up once, all at the same time
+            assert_eq!(
+                *resolver.lookups.lock(),
+                ["bob.test", "carol.test", "nobody.test"]
+            );
+            assert_eq!(resolver.most_running.load(Ordering::SeqCst), 3);
+            (builder, report)
+        };
+        // The mentions in the text, and what each links to
+        fn mentions(post: &Post) -> Vec<(&str, &str)> {
+            post.facets
+                .iter()
+                .flatten()
+                .map(|facet| match &facet.features[..] {
+                    [FacetFeature::Mention { did }] => {
+                        (facet.slice(&post.text).unwrap(), did.as_str())
+                    }
+                    [FacetFeature::Link { uri }] => {
+                        (facet.slice(&post.text).unwrap(), uri.as_str())
+                    }
+                    features => panic!("{features:?}"),
+                })
+                .collect()
+        }
+        let resolved = vec![
+            ("bob.test".to_string(), "did:plc:bob".to_string()),
+            ("carol.test".to_string(), "did:plc:carol".to_string()),
+        ];
+
+        let (builder, report) = resolve(MentionPolicy::FailOnUnresolved).await;
+        assert!(
+            matches!(report, Err(BiskyError::HandleNotFound(handle)) if handle == "nobody.test")
+        );
+        let post = builder.build().unwrap();
+        assert_eq!(post.text, TEXT);
+        assert_eq!(
+            mentions(&post),
+            [("https://atproto.com", "https://atproto.com")]
+        );
+
+        let (builder, report) = resolve(MentionPolicy::SkipUnresolved).await;
+        let report = report.unwrap();
+        assert_eq!(report.resolved, resolved);
+        assert_eq!(report.unresolved, ["nobody.test"]);
+        let post = builder.build().unwrap();
+        assert_eq!(
+            post.text,
+            "Thanks @bob.test and @Carol.test! cc @bob.test https://atproto.com"
+        );
+        assert_eq!(
+            mentions(&post),
+            [
+                ("@bob.test", "did:plc:bob"),
+                ("@Carol.test", "did:plc:carol"),
+                ("@bob.test", "did:plc:bob"),
+                ("https://atproto.com", "https://atproto.com"),
+            ]
+        );
+
+        let (builder, report) = resolve(MentionPolicy::KeepAsText).await;
+        let report = report.unwrap();
+        assert_eq!(report.resolved, resolved);
+        assert_eq!(report.unresolved, ["nobody.test"]);
+        let post = builder.build().unwrap();
+        assert_eq!(post.text, TEXT);
+        assert_eq!(
+            mentions(&post),
+            [
+                ("@bob.test", "did:plc:bob"),
+                ("@Carol.test", "did:plc:carol"),
+                ("@bob.test", "did:plc:bob"),
+                ("https://atproto.com", "https://atproto.com"),
+            ]
+        );
+    }
+}
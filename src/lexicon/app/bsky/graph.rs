@@ -1,17 +1,95 @@
-use chrono::{DateTime, Utc};
+use crate::types::Datetime;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-use super::actor::ProfileView;
+use super::actor::{Label, ProfileView, ProfileViewBasic};
+use super::feed::GeneratorView;
+use crate::lexicon::com::atproto::repo::{Blob, CreateRecordOutput};
 
 ///app.bsky.graph.follow
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Follow {
-    #[serde(rename(deserialize = "createdAt"))]
-    #[serde(rename(serialize = "createdAt"))]
-    pub created_at: DateTime<Utc>,
+    pub created_at: Datetime,
     pub subject: String, //did
 }
 
+///app.bsky.graph.block
+/// Blocks are public records in the blocker's repo, unlike mutes which are private server state
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    pub created_at: Datetime,
+    pub subject: String, //did
+}
+
+///app.bsky.graph.defs#listPurpose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ListPurpose {
+    /// A list of accounts to mute or block together
+    #[serde(rename = "app.bsky.graph.defs#modlist")]
+    Modlist,
+    /// A list of accounts, e.g. to build feeds from
+    #[serde(rename = "app.bsky.graph.defs#curatelist")]
+    Curatelist,
+    /// A list of accounts that starter packs and the like point at
+    #[serde(rename = "app.bsky.graph.defs#referencelist")]
+    Referencelist,
+}
+
+///app.bsky.graph.list
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct List {
+    pub purpose: ListPurpose,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<Blob>,
+    pub created_at: Datetime,
+}
+
+///app.bsky.graph.listitem
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListItem {
+    pub subject: String, //did
+    /// The uri of the list
+    pub list: String,
+    pub created_at: Datetime,
+}
+
+///app.bsky.graph.listblock
+/// A public block of every account on a moderation list
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListBlock {
+    /// The uri of the blocked list
+    pub subject: String,
+    pub created_at: Datetime,
+}
+
+///app.bsky.graph.starterpack#feedItem
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StarterPackFeed {
+    pub uri: String,
+}
+
+///app.bsky.graph.starterpack
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarterPack {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The uri of the reference list holding the pack's accounts
+    pub list: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feeds: Vec<StarterPackFeed>,
+    pub created_at: Datetime,
+}
+
 ///app.bsky.graph.getFollowers
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetFollowers {
@@ -43,3 +121,245 @@ pub struct GetFollowsOutput {
     pub follows: Vec<ProfileView>,
     pub cursor: Option<String>,
 }
+
+///app.bsky.graph.defs#listViewBasic
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListViewBasic {
+    pub uri: String,
+    pub cid: String,
+    pub name: String,
+    /// e.g. "app.bsky.graph.defs#modlist"
+    pub purpose: String,
+    pub avatar: Option<String>,
+    pub list_item_count: Option<usize>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub viewer: Option<ListViewerState>,
+    pub indexed_at: Option<Datetime>,
+}
+
+///app.bsky.graph.defs#listViewerState
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ListViewerState {
+    #[serde(default)]
+    pub muted: bool,
+    /// The uri of the viewer's listblock record for this list
+    pub blocked: Option<String>,
+}
+
+///app.bsky.graph.defs#listView
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListView {
+    pub uri: String,
+    pub cid: String,
+    pub creator: ProfileView,
+    pub name: String,
+    /// e.g. "app.bsky.graph.defs#modlist"
+    pub purpose: String,
+    pub description: Option<String>,
+    pub avatar: Option<String>,
+    pub list_item_count: Option<usize>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub viewer: Option<ListViewerState>,
+    pub indexed_at: Option<Datetime>,
+}
+
+///app.bsky.graph.defs#listItemView
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListItemView {
+    /// The uri of the listitem record
+    pub uri: String,
+    pub subject: ProfileView,
+}
+
+///app.bsky.graph.getList
+#[derive(Debug, Deserialize)]
+pub struct GetListOutput {
+    pub list: ListView,
+    pub items: Vec<ListItemView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.graph.getLists, app.bsky.graph.getListMutes and app.bsky.graph.getListBlocks
+#[derive(Debug, Deserialize)]
+pub struct GetListsOutput {
+    pub lists: Vec<ListView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.graph.getKnownFollowers
+#[derive(Debug, Deserialize)]
+pub struct GetKnownFollowersOutput {
+    pub subject: ProfileView,
+    pub followers: Vec<ProfileView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.graph.defs#relationship
+/// How the actor of a getRelationships query relates to one of the others
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Relationship {
+    pub did: String,
+    /// The uri of the actor's follow of this account
+    pub following: Option<String>,
+    /// The uri of this account's follow of the actor
+    pub followed_by: Option<String>,
+}
+
+///app.bsky.graph.defs#notFoundActor
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotFoundActor {
+    pub actor: String,
+    pub not_found: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+pub enum RelationshipEnum {
+    #[serde(rename(deserialize = "app.bsky.graph.defs#relationship"))]
+    Relationship(Relationship),
+    #[serde(rename(deserialize = "app.bsky.graph.defs#notFoundActor"))]
+    NotFoundActor(NotFoundActor),
+    /// A relationship entry this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///app.bsky.graph.getRelationships
+#[derive(Debug, Deserialize)]
+pub struct GetRelationshipsOutput {
+    pub actor: Option<String>,
+    pub relationships: Vec<RelationshipEnum>,
+}
+
+/// The most `others` getRelationships accepts in one request
+pub const MAX_GET_RELATIONSHIPS: usize = 30;
+
+///app.bsky.graph.getBlocks
+#[derive(Debug, Deserialize)]
+pub struct GetBlocksOutput {
+    pub blocks: Vec<ProfileView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.graph.muteActor and app.bsky.graph.unmuteActor
+#[derive(Debug, Serialize)]
+pub struct MuteActor<'a> {
+    pub actor: &'a str,
+}
+
+///app.bsky.graph.muteThread and app.bsky.graph.unmuteThread
+#[derive(Debug, Serialize)]
+pub struct MuteThread<'a> {
+    pub root: &'a str,
+}
+
+///app.bsky.graph.getMutes
+#[derive(Debug, Deserialize)]
+pub struct GetMutesOutput {
+    pub mutes: Vec<ProfileView>,
+    pub cursor: Option<String>,
+}
+
+///app.bsky.graph.muteActorList and app.bsky.graph.unmuteActorList
+#[derive(Debug, Serialize)]
+pub struct MuteActorList<'a> {
+    pub list: &'a str,
+}
+
+///app.bsky.graph.getSuggestedFollowsByActor
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSuggestedFollowsByActorOutput {
+    pub suggestions: Vec<ProfileView>,
+    /// Whether these are generic suggestions rather than ones based on the actor
+    #[serde(default)]
+    pub is_fallback: bool,
+    pub rec_id: Option<i64>,
+}
+
+/// Part of the follow graph, collected by `BskyClient::bsky_expand_follow_graph`
+#[derive(Debug, Default)]
+pub struct FollowGraph {
+    /// Every account reached, by DID. The seeds are only included if some other account
+    /// reached follows them.
+    pub accounts: HashMap<String, ProfileView>,
+    /// (follower DID, followed DID) pairs
+    pub follows: Vec<(String, String)>,
+    /// Whether `max_accounts` stopped the walk early
+    pub truncated: bool,
+}
+
+///app.bsky.graph.defs#starterPackViewBasic
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarterPackViewBasic {
+    pub uri: String,
+    pub cid: String,
+    pub record: StarterPack,
+    pub creator: ProfileViewBasic,
+    pub list_item_count: Option<usize>,
+    pub joined_week_count: Option<usize>,
+    pub joined_all_time_count: Option<usize>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub indexed_at: Option<Datetime>,
+}
+
+///app.bsky.graph.defs#starterPackView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarterPackView {
+    pub uri: String,
+    pub cid: String,
+    pub record: StarterPack,
+    pub creator: ProfileViewBasic,
+    pub list: Option<ListViewBasic>,
+    /// A few of the pack's accounts
+    #[serde(default)]
+    pub list_items_sample: Vec<ListItemView>,
+    #[serde(default)]
+    pub feeds: Vec<GeneratorView>,
+    pub joined_week_count: Option<usize>,
+    pub joined_all_time_count: Option<usize>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub indexed_at: Option<Datetime>,
+}
+
+///app.bsky.graph.getStarterPack
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetStarterPackOutput {
+    pub starter_pack: StarterPackView,
+}
+
+///app.bsky.graph.getStarterPacks
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetStarterPacksOutput {
+    pub starter_packs: Vec<StarterPackViewBasic>,
+}
+
+/// The most uris getStarterPacks accepts in one request
+pub const MAX_GET_STARTER_PACKS: usize = 25;
+
+///app.bsky.graph.getActorStarterPacks and app.bsky.graph.searchStarterPacks
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StarterPacksPageOutput {
+    pub starter_packs: Vec<StarterPackViewBasic>,
+    pub cursor: Option<String>,
+}
+
+/// The records behind a starter pack created by `BskyClient::bsky_create_starter_pack`
+#[derive(Debug)]
+pub struct CreatedStarterPack {
+    pub list: CreateRecordOutput,
+    pub starter_pack: CreateRecordOutput,
+}
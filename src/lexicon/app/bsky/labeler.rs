@@ -0,0 +1,106 @@
+use super::actor::{Label, ProfileView};
+use crate::lexicon::com::atproto::label::{LabelValueDefinition, RecordLabels};
+pub use crate::lexicon::com::atproto::moderation::labeler_proxy;
+use crate::lexicon::com::atproto::moderation::ReasonType;
+use crate::types::Datetime;
+use serde::{Deserialize, Serialize};
+
+/// Bluesky's own moderation service, which every user subscribes to
+pub const BSKY_MODERATION_LABELER: &str = "did:plc:ar7c4by46qjdydhdevvrndac";
+
+#[derive(Debug, Deserialize)]
+pub struct LabelerViewerState {
+    pub like: Option<String>,
+}
+
+///app.bsky.labeler.defs#labelerPolicies
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelerPolicies {
+    /// Every label value the labeler may apply, including the global ones like `porn`
+    pub label_values: Vec<String>,
+    /// The labeler's own label values
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub label_value_definitions: Vec<LabelValueDefinition>,
+}
+
+///app.bsky.labeler.service
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelerService {
+    pub policies: LabelerPolicies,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<RecordLabels>,
+    pub created_at: Datetime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason_types: Option<Vec<ReasonType>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject_types: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject_collections: Option<Vec<String>>,
+}
+
+///app.bsky.labeler.defs#labelerView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelerView {
+    pub uri: String,
+    pub cid: String,
+    pub creator: ProfileView,
+    #[serde(default)]
+    pub like_count: usize,
+    pub viewer: Option<LabelerViewerState>,
+    pub indexed_at: Datetime,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+///app.bsky.labeler.defs#labelerViewDetailed
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelerViewDetailed {
+    pub uri: String,
+    pub cid: String,
+    pub creator: ProfileView,
+    pub policies: LabelerPolicies,
+    #[serde(default)]
+    pub like_count: usize,
+    pub viewer: Option<LabelerViewerState>,
+    pub indexed_at: Datetime,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    /// The report reasons the labeler accepts, any when absent
+    pub reason_types: Option<Vec<ReasonType>>,
+    /// `account`, `record` or `chat`, any when absent
+    pub subject_types: Option<Vec<String>>,
+    /// The record collections the labeler accepts reports about, any when absent
+    pub subject_collections: Option<Vec<String>>,
+}
+
+impl LabelerViewDetailed {
+    /// The labeler's definition of `value`, if it defines one
+    pub fn definition(&self, value: &str) -> Option<&LabelValueDefinition> {
+        self.policies
+            .label_value_definitions
+            .iter()
+            .find(|d| d.identifier == value)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+pub enum LabelerViewEnum {
+    #[serde(rename = "app.bsky.labeler.defs#labelerView")]
+    View(LabelerView),
+    #[serde(rename = "app.bsky.labeler.defs#labelerViewDetailed")]
+    Detailed(LabelerViewDetailed),
+    /// A labeler view this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///app.bsky.labeler.getServices
+#[derive(Debug, Deserialize)]
+pub struct GetServicesOutput {
+    pub views: Vec<LabelerViewEnum>,
+}
@@ -2,4 +2,9 @@ pub mod actor;
 pub mod embed;
 pub mod feed;
 pub mod graph;
+pub mod labeler;
 pub mod notification;
+pub mod record;
+pub mod richtext;
+#[cfg(feature = "unspecced")]
+pub mod unspecced;
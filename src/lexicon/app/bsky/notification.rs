@@ -1,22 +1,77 @@
-use super::actor::ProfileView;
+use super::actor::{Label, ProfileView};
 use super::feed::{Like, Post, Repost};
 use super::graph::Follow;
-use chrono::{DateTime, Utc};
+use crate::types::Datetime;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
+///app.bsky.notification.listNotifications#notification
+/// `T` is the type of the record that caused the notification, either `NotificationRecord` or
+/// `serde_json::Value` for the raw JSON
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Notification<T> {
     pub uri: String,
     pub cid: String,
     pub author: ProfileView,
-    pub reason: String,
-    #[serde(rename(deserialize = "reasonSubject"))]
+    pub reason: NotificationReason,
+    /// The uri of the post that was liked, reposted, replied to or quoted
     pub reason_subject: Option<String>,
     pub record: T,
-    #[serde(rename(deserialize = "isRead"))]
     pub is_read: bool,
-    pub indexed_at: Option<String>,
-    pub labels: Vec<String>,
+    pub indexed_at: Datetime,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+/// Why a notification was sent, with an escape hatch for reasons this crate doesn't know
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum NotificationReason {
+    Like,
+    Repost,
+    Follow,
+    Mention,
+    Reply,
+    Quote,
+    StarterpackJoined,
+    Other(String),
+}
+
+impl From<String> for NotificationReason {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "like" => Self::Like,
+            "repost" => Self::Repost,
+            "follow" => Self::Follow,
+            "mention" => Self::Mention,
+            "reply" => Self::Reply,
+            "quote" => Self::Quote,
+            "starterpack-joined" => Self::StarterpackJoined,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<NotificationReason> for String {
+    fn from(value: NotificationReason) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for NotificationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Like => "like",
+            Self::Repost => "repost",
+            Self::Follow => "follow",
+            Self::Mention => "mention",
+            Self::Reply => "reply",
+            Self::Quote => "quote",
+            Self::StarterpackJoined => "starterpack-joined",
+            Self::Other(value) => value,
+        })
+    }
 }
 
 pub enum Subject {
@@ -25,18 +80,19 @@ pub enum Subject {
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PostSubject {
     pub cid: String,
     pub uri: String,
-    #[serde(rename(deserialize = "createdAt"))]
     pub created_at: String,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct ActorSubject(String);
+pub struct ActorSubject(pub String);
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "$type")]
+#[allow(clippy::large_enum_variant)]
 pub enum NotificationRecord {
     #[serde(rename(deserialize = "app.bsky.feed.like"))]
     Like(Like),
@@ -46,20 +102,66 @@ pub enum NotificationRecord {
     Repost(Repost),
     #[serde(rename(deserialize = "app.bsky.graph.follow"))]
     Follow(Follow),
+    /// E.g. the profile of a `starterpack-joined` notification, which names the starter pack
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
 }
 
+///app.bsky.notification.listNotifications
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ListNotificationsOutput<T> {
     pub cursor: Option<String>,
     pub notifications: Vec<Notification<T>>,
+    /// When the notifications were last marked as seen
+    pub seen_at: Option<Datetime>,
+    /// Whether only notifications from follows are shown, see `PutNotificationPreferences`
+    pub priority: Option<bool>,
 }
 
+///app.bsky.notification.updateSeen
 #[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UpdateSeen {
-    #[serde(rename(serialize = "seenAt"))]
-    pub seen_at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_millis")]
+    pub seen_at: Datetime,
+}
+
+/// The server only accepts RFC 3339 with milliseconds and `Z`, e.g. `2024-01-01T00:00:00.000Z`
+fn serialize_millis<S: serde::Serializer>(
+    date: &Datetime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&date.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+///app.bsky.notification.putPreferences
+#[derive(Debug, Serialize)]
+pub struct PutNotificationPreferences {
+    /// Only notify about accounts the user follows
+    pub priority: bool,
+}
+
+/// The platform a push token belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PushPlatform {
+    Ios,
+    Android,
+    Web,
+}
+
+///app.bsky.notification.registerPush
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterPush<'a> {
+    pub service_did: &'a str,
+    pub token: &'a str,
+    pub platform: PushPlatform,
+    pub app_id: &'a str,
 }
 
+///app.bsky.notification.getUnreadCount
 #[derive(Debug, Deserialize)]
 pub struct NotificationCount {
     pub count: usize,
@@ -0,0 +1,88 @@
+use super::actor::Profile;
+use super::feed::{Generator, Like, Post, Postgate, Repost, Threadgate};
+use super::graph::{Block, Follow, List, ListBlock, ListItem, StarterPack};
+use super::labeler::LabelerService;
+use crate::types::union_type;
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+macro_rules! records {
+    ($($variant:ident($record:ty) = $type:literal,)*) => {
+        /// Any app.bsky record, picked by its `$type`, e.g. one read from a repo, a backup or the
+        /// firehose without knowing its collection in advance
+        #[derive(Debug)]
+        #[allow(clippy::large_enum_variant)]
+        pub enum KnownRecord {
+            $($variant($record),)*
+            /// A record of a type this crate doesn't model, with its `$type`, written back
+            /// exactly as it was read
+            Unknown(String, serde_json::Value),
+        }
+
+        impl KnownRecord {
+            /// The record's `$type`, which is also the collection it belongs in
+            pub fn record_type(&self) -> &str {
+                match self {
+                    $(Self::$variant(_) => $type,)*
+                    Self::Unknown(kind, _) => kind,
+                }
+            }
+        }
+
+        impl<'de> Deserialize<'de> for KnownRecord {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = serde_json::Value::deserialize(deserializer)?;
+                let kind = union_type(&value)
+                    .ok_or_else(|| D::Error::missing_field("$type"))?
+                    .to_string();
+                Ok(match kind.as_str() {
+                    $($type => Self::$variant(
+                        serde_json::from_value(value).map_err(D::Error::custom)?,
+                    ),)*
+                    _ => Self::Unknown(kind, value),
+                })
+            }
+        }
+
+        impl Serialize for KnownRecord {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let (kind, mut value) = match self {
+                    $(Self::$variant(record) => (
+                        $type,
+                        serde_json::to_value(record).map_err(S::Error::custom)?,
+                    ),)*
+                    Self::Unknown(_, value) => return value.serialize(serializer),
+                };
+                // Also replaces the `$type` that `Post` and `Profile` carry themselves
+                if let Some(object) = value.as_object_mut() {
+                    object.insert("$type".to_string(), kind.into());
+                }
+                value.serialize(serializer)
+            }
+        }
+
+        $(
+            impl From<$record> for KnownRecord {
+                fn from(record: $record) -> Self {
+                    Self::$variant(record)
+                }
+            }
+        )*
+    };
+}
+
+records! {
+    Post(Post) = "app.bsky.feed.post",
+    Like(Like) = "app.bsky.feed.like",
+    Repost(Repost) = "app.bsky.feed.repost",
+    Threadgate(Threadgate) = "app.bsky.feed.threadgate",
+    Postgate(Postgate) = "app.bsky.feed.postgate",
+    Generator(Generator) = "app.bsky.feed.generator",
+    Follow(Follow) = "app.bsky.graph.follow",
+    Block(Block) = "app.bsky.graph.block",
+    List(List) = "app.bsky.graph.list",
+    ListItem(ListItem) = "app.bsky.graph.listitem",
+    ListBlock(ListBlock) = "app.bsky.graph.listblock",
+    StarterPack(StarterPack) = "app.bsky.graph.starterpack",
+    Profile(Profile) = "app.bsky.actor.profile",
+    LabelerService(LabelerService) = "app.bsky.labeler.service",
+}
@@ -0,0 +1,325 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+///app.bsky.richtext.facet#byteSlice
+/// UTF-8 byte offsets into the post text, end exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ByteSlice {
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+impl ByteSlice {
+    pub fn range(&self) -> Range<usize> {
+        self.byte_start..self.byte_end
+    }
+
+    /// Whether this is a non-empty range of whole characters of `text`
+    pub fn is_valid_in(&self, text: &str) -> bool {
+        self.byte_start < self.byte_end && text.get(self.range()).is_some()
+    }
+
+    /// The same span as character offsets, for text that isn't indexed by bytes
+    pub fn to_char_range(&self, text: &str) -> Option<Range<usize>> {
+        if !self.is_valid_in(text) {
+            return None;
+        }
+        let start = text[..self.byte_start].chars().count();
+        Some(start..start + text[self.range()].chars().count())
+    }
+
+    /// The byte offsets of the characters `range` of `text`
+    pub fn from_char_range(text: &str, range: Range<usize>) -> Option<Self> {
+        let offset = |index: usize| {
+            text.char_indices()
+                .map(|(offset, _)| offset)
+                .chain([text.len()])
+                .nth(index)
+        };
+        Some(Self {
+            byte_start: offset(range.start)?,
+            byte_end: offset(range.end)?,
+        })
+    }
+
+    /// The graphemes this touches, such as for highlighting whole emoji when a facet starts or
+    /// ends inside one
+    pub fn to_grapheme_range(&self, text: &str) -> Option<Range<usize>> {
+        if !self.is_valid_in(text) {
+            return None;
+        }
+        let offsets = text
+            .grapheme_indices(true)
+            .map(|(offset, _)| offset)
+            .collect::<Vec<_>>();
+        let start = offsets
+            .iter()
+            .rposition(|&offset| offset <= self.byte_start)
+            .unwrap_or_default();
+        let end = offsets
+            .iter()
+            .position(|&offset| offset >= self.byte_end)
+            .unwrap_or(offsets.len());
+        Some(start..end)
+    }
+
+    /// The byte offsets of the graphemes `range` of `text`
+    pub fn from_grapheme_range(text: &str, range: Range<usize>) -> Option<Self> {
+        let offset = |index: usize| {
+            text.grapheme_indices(true)
+                .map(|(offset, _)| offset)
+                .chain([text.len()])
+                .nth(index)
+        };
+        Some(Self {
+            byte_start: offset(range.start)?,
+            byte_end: offset(range.end)?,
+        })
+    }
+}
+
+///app.bsky.richtext.facet features
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "$type")]
+pub enum FacetFeature {
+    #[serde(rename = "app.bsky.richtext.facet#mention")]
+    Mention { did: String },
+    #[serde(rename = "app.bsky.richtext.facet#link")]
+    Link { uri: String },
+    #[serde(rename = "app.bsky.richtext.facet#tag")]
+    Tag { tag: String },
+    /// A feature this crate doesn't know about yet, such as one added by another client
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///app.bsky.richtext.facet
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Facet {
+    pub index: ByteSlice,
+    pub features: Vec<FacetFeature>,
+}
+
+impl Facet {
+    /// The text this facet covers, `None` if its offsets don't fall on characters of `text`
+    pub fn slice<'a>(&self, text: &'a str) -> Option<&'a str> {
+        self.index
+            .is_valid_in(text)
+            .then(|| &text[self.index.range()])
+    }
+}
+
+/// Make facets from another client safe to post with `text`: offsets past the end are clamped
+/// and ones inside a character are moved inwards to its edge. Facets left empty, and ones that
+/// overlap an earlier facet, are dropped. The rest come back in order of where they start.
+pub fn sanitize_facets(text: &str, mut facets: Vec<Facet>) -> Vec<Facet> {
+    for facet in &mut facets {
+        let ByteSlice {
+            mut byte_start,
+            mut byte_end,
+        } = facet.index;
+        byte_end = byte_end.min(text.len());
+        while byte_start < byte_end && !text.is_char_boundary(byte_start) {
+            byte_start += 1;
+        }
+        while byte_end > byte_start && !text.is_char_boundary(byte_end) {
+            byte_end -= 1;
+        }
+        facet.index = ByteSlice {
+            byte_start,
+            byte_end,
+        };
+    }
+    facets.retain(|facet| facet.index.is_valid_in(text));
+    facets.sort_by_key(|facet| facet.index.byte_start);
+
+    let mut end = 0;
+    facets.retain(|facet| {
+        let keep = facet.index.byte_start >= end;
+        if keep {
+            end = facet.index.byte_end;
+        }
+        keep
+    });
+    facets
+}
+
+/// A `@handle` in post text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionSpan {
+    /// Where the mention is in the text, `@` included
+    pub index: ByteSlice,
+    /// The handle, without the `@`
+    pub handle: String,
+}
+
+/// The `@handle` mentions in `text` that start it or follow whitespace or an opening
+/// parenthesis, as the official clients detect them
+pub fn detect_mentions(text: &str) -> Vec<MentionSpan> {
+    let mut mentions = Vec::new();
+    let mut prev = None;
+    for (at, c) in text.char_indices() {
+        let starts_mention = c == '@' && prev.is_none_or(|p: char| p.is_whitespace() || p == '(');
+        prev = Some(c);
+        if !starts_mention {
+            continue;
+        }
+
+        let rest = &text[at + 1..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+            .unwrap_or(rest.len());
+        // A mention at the end of a sentence
+        let handle = rest[..len].trim_end_matches('.');
+        if is_handle(handle) {
+            mentions.push(MentionSpan {
+                index: ByteSlice {
+                    byte_start: at,
+                    byte_end: at + 1 + handle.len(),
+                },
+                handle: handle.to_string(),
+            });
+        }
+    }
+    mentions
+}
+
+/// Whether `handle` has the syntax of a handle: two or more labels of letters, digits and
+/// hyphens, the last of which doesn't start with a digit
+fn is_handle(handle: &str) -> bool {
+    let labels = handle.split('.').collect::<Vec<_>>();
+    handle.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && labels
+            .last()
+            .is_some_and(|tld| tld.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 日本語 is 0..9, the family emoji 10..35 and #タグ 36..43
+    const TEXT: &str = "日本語 \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466} #タグ";
+
+    fn slice(byte_start: usize, byte_end: usize) -> ByteSlice {
+        ByteSlice {
+            byte_start,
+            byte_end,
+        }
+    }
+
+    fn facet(byte_start: usize, byte_end: usize) -> Facet {
+        Facet {
+            index: slice(byte_start, byte_end),
+            features: vec![FacetFeature::Tag {
+                tag: "タグ".into()
+            }],
+        }
+    }
+
+    #[test]
+    fn slices() {
+        assert_eq!(TEXT.len(), 43);
+        assert_eq!(facet(0, 9).slice(TEXT), Some("日本語"));
+        assert_eq!(facet(36, 43).slice(TEXT), Some("#タグ"));
+        assert_eq!(facet(0, 43).slice(TEXT), Some(TEXT));
+        assert_eq!(facet(10, 14).slice(TEXT), Some("\u{1F468}"));
+
+        // Inside a character, past the end, empty or backwards
+        for (start, end) in [(0, 8), (1, 9), (36, 44), (43, 44), (9, 9), (9, 0), (44, 50)] {
+            assert_eq!(facet(start, end).slice(TEXT), None, "{start}..{end}");
+            assert!(!slice(start, end).is_valid_in(TEXT));
+        }
+        assert_eq!(facet(0, 1).slice(""), None);
+    }
+
+    #[test]
+    fn char_ranges() {
+        assert_eq!(slice(0, 9).to_char_range(TEXT), Some(0..3));
+        assert_eq!(slice(10, 35).to_char_range(TEXT), Some(4..11));
+        assert_eq!(slice(36, 43).to_char_range(TEXT), Some(12..15));
+        assert_eq!(slice(36, 42).to_char_range(TEXT), None);
+        assert_eq!(slice(36, 44).to_char_range(TEXT), None);
+
+        for range in [0..3, 4..11, 12..15, 0..15] {
+            let bytes = ByteSlice::from_char_range(TEXT, range.clone()).unwrap();
+            assert_eq!(bytes.to_char_range(TEXT), Some(range));
+        }
+        // Up to the end but not past it
+        assert_eq!(
+            ByteSlice::from_char_range(TEXT, 12..15),
+            Some(slice(36, 43))
+        );
+        assert_eq!(ByteSlice::from_char_range(TEXT, 12..16), None);
+        assert_eq!(ByteSlice::from_char_range(TEXT, 16..17), None);
+    }
+
+    #[test]
+    fn grapheme_ranges() {
+        assert_eq!(slice(0, 9).to_grapheme_range(TEXT), Some(0..3));
+        assert_eq!(slice(10, 35).to_grapheme_range(TEXT), Some(4..5));
+        assert_eq!(slice(36, 43).to_grapheme_range(TEXT), Some(6..9));
+        // Part of the emoji highlights all of it
+        assert_eq!(slice(10, 14).to_grapheme_range(TEXT), Some(4..5));
+        assert_eq!(slice(17, 21).to_grapheme_range(TEXT), Some(4..5));
+        assert_eq!(slice(31, 43).to_grapheme_range(TEXT), Some(4..9));
+        assert_eq!(slice(10, 11).to_grapheme_range(TEXT), None);
+
+        assert_eq!(
+            ByteSlice::from_grapheme_range(TEXT, 4..5),
+            Some(slice(10, 35))
+        );
+        assert_eq!(
+            ByteSlice::from_grapheme_range(TEXT, 6..9),
+            Some(slice(36, 43))
+        );
+        assert_eq!(ByteSlice::from_grapheme_range(TEXT, 6..10), None);
+        // A combining accent stays with its letter
+        assert_eq!(
+            ByteSlice::from_grapheme_range("cafe\u{301}!", 3..4),
+            Some(slice(3, 6))
+        );
+    }
+
+    #[test]
+    fn sanitize() {
+        // In range facets come back untouched and in order
+        assert_eq!(
+            sanitize_facets(TEXT, vec![facet(36, 43), facet(0, 9), facet(9, 10)]),
+            [facet(0, 9), facet(9, 10), facet(36, 43)]
+        );
+
+        // Clamped to the end, then moved inwards to whole characters
+        assert_eq!(sanitize_facets(TEXT, vec![facet(36, 100)]), [facet(36, 43)]);
+        assert_eq!(sanitize_facets(TEXT, vec![facet(1, 8)]), [facet(3, 6)]);
+        assert_eq!(sanitize_facets(TEXT, vec![facet(37, 42)]), [facet(37, 40)]);
+
+        // Left empty, entirely past the end, or backwards
+        assert_eq!(
+            sanitize_facets(
+                TEXT,
+                vec![facet(1, 2), facet(43, 43), facet(50, 60), facet(9, 0)]
+            ),
+            []
+        );
+
+        // The later of two overlapping facets goes
+        assert_eq!(
+            sanitize_facets(
+                TEXT,
+                vec![facet(10, 43), facet(0, 9), facet(36, 43), facet(5, 12)]
+            ),
+            [facet(0, 9), facet(10, 43)]
+        );
+        assert!(sanitize_facets("", vec![facet(0, 1)]).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! `app.bsky.unspecced` endpoints. The AppView may change these at any time, so everything but
+//! the essentials is optional.
+
+use super::feed::GeneratorView;
+use serde::Deserialize;
+
+///app.bsky.unspecced.getPopularFeedGenerators
+#[derive(Debug, Deserialize)]
+pub struct GetPopularFeedGeneratorsOutput {
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub feeds: Vec<GeneratorView>,
+}
+
+///app.bsky.unspecced.defs#trendingTopic
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendingTopic {
+    pub topic: String,
+    pub display_name: Option<String>,
+    pub description: Option<String>,
+    /// A path on bsky.app, e.g. `/profile/<did>/feed/<rkey>` for a suggested feed
+    pub link: Option<String>,
+}
+
+///app.bsky.unspecced.getTrendingTopics
+#[derive(Debug, Deserialize)]
+pub struct GetTrendingTopicsOutput {
+    #[serde(default)]
+    pub topics: Vec<TrendingTopic>,
+    /// Suggested feeds and other starting points
+    #[serde(default)]
+    pub suggested: Vec<TrendingTopic>,
+}
+
+/// What a tagged suggestion points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionSubjectType {
+    Actor,
+    Feed,
+    #[serde(other)]
+    Unknown,
+}
+
+///app.bsky.unspecced.getTaggedSuggestions#suggestion
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaggedSuggestion {
+    /// The category, e.g. an interest like `art`
+    pub tag: String,
+    pub subject_type: SuggestionSubjectType,
+    /// A DID for actors, an at:// uri for feeds
+    pub subject: String,
+}
+
+///app.bsky.unspecced.getTaggedSuggestions
+#[derive(Debug, Deserialize)]
+pub struct GetTaggedSuggestionsOutput {
+    #[serde(default)]
+    pub suggestions: Vec<TaggedSuggestion>,
+}
+
+///app.bsky.unspecced.getConfig
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetConfigOutput {
+    #[serde(default)]
+    pub check_email_confirmed: bool,
+    /// Flags this crate doesn't know about yet
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
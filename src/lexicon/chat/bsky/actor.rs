@@ -0,0 +1,17 @@
+use crate::lexicon::app::bsky::actor::Label;
+use serde::Deserialize;
+
+///chat.bsky.actor.defs#profileViewBasic
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileViewBasic {
+    pub did: String,
+    pub handle: String,
+    pub display_name: Option<String>,
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    /// Set when the account can't be messaged at all
+    #[serde(default)]
+    pub chat_disabled: bool,
+}
@@ -0,0 +1,279 @@
+use super::actor::ProfileViewBasic;
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::feed::validate_facets;
+use crate::lexicon::app::bsky::richtext::Facet;
+use crate::lexicon::com::atproto::repo::StrongRef;
+use crate::types::Datetime;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The service chat requests are proxied to through the PDS
+pub const CHAT_PROXY: &str = "did:web:api.bsky.chat#bsky_chat";
+
+/// The most members chat.bsky.convo.getConvoForMembers takes, including the user
+pub const MAX_CONVO_MEMBERS: usize = 10;
+
+/// The longest message text, counted in graphemes
+pub const MAX_MESSAGE_GRAPHEMES: usize = 1000;
+
+/// The longest message text, counted in UTF-8 bytes
+pub const MAX_MESSAGE_BYTES: usize = 10000;
+
+/// The most messages chat.bsky.convo.sendMessageBatch takes at once
+pub const MAX_SEND_MESSAGE_BATCH: usize = 100;
+
+///chat.bsky.convo.defs#messageViewSender
+#[derive(Debug, Deserialize)]
+pub struct MessageViewSender {
+    pub did: String,
+}
+
+///chat.bsky.convo.defs#messageView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageView {
+    pub id: String,
+    pub rev: String,
+    pub text: String,
+    pub facets: Option<Vec<Facet>>,
+    /// Usually an app.bsky.embed.record
+    pub embed: Option<serde_json::Value>,
+    pub sender: MessageViewSender,
+    pub sent_at: Datetime,
+    /// Emoji reactions, kept as they come since their shape is still settling
+    #[serde(default)]
+    pub reactions: Vec<serde_json::Value>,
+}
+
+///chat.bsky.convo.defs#deletedMessageView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedMessageView {
+    pub id: String,
+    pub rev: String,
+    pub sender: MessageViewSender,
+    pub sent_at: Datetime,
+}
+
+/// A message, or the placeholder left where one was deleted
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+pub enum MessageViewEnum {
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#messageView"))]
+    Message(MessageView),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#deletedMessageView"))]
+    Deleted(DeletedMessageView),
+    /// A message view this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+/// Whether a conversation was accepted or is still a message request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvoStatus {
+    Request,
+    Accepted,
+    #[serde(other)]
+    Unknown,
+}
+
+///chat.bsky.convo.defs#convoView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvoView {
+    pub id: String,
+    pub rev: String,
+    pub members: Vec<ProfileViewBasic>,
+    pub last_message: Option<MessageViewEnum>,
+    #[serde(default)]
+    pub muted: bool,
+    pub status: Option<ConvoStatus>,
+    #[serde(default)]
+    pub unread_count: usize,
+}
+
+///chat.bsky.convo.listConvos
+#[derive(Debug, Deserialize)]
+pub struct ListConvosOutput {
+    pub cursor: Option<String>,
+    pub convos: Vec<ConvoView>,
+}
+
+///chat.bsky.convo.getMessages
+#[derive(Debug, Deserialize)]
+pub struct GetMessagesOutput {
+    pub cursor: Option<String>,
+    /// Newest first
+    pub messages: Vec<MessageViewEnum>,
+}
+
+///chat.bsky.convo.getConvo and chat.bsky.convo.getConvoForMembers, also returned by updateRead,
+/// muteConvo and unmuteConvo
+#[derive(Debug, Deserialize)]
+pub struct GetConvoOutput {
+    pub convo: ConvoView,
+}
+
+/// What a message can embed
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "$type")]
+pub enum MessageEmbed {
+    /// A post, or another record such as a feed or list
+    #[serde(rename = "app.bsky.embed.record")]
+    Record { record: StrongRef },
+}
+
+///chat.bsky.convo.defs#messageInput
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageInput {
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<Vec<Facet>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embed: Option<MessageEmbed>,
+}
+
+impl MessageInput {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            facets: None,
+            embed: None,
+        }
+    }
+
+    /// Share a post, or another record, into the conversation
+    pub fn embed_record(mut self, record: StrongRef) -> Self {
+        self.embed = Some(MessageEmbed::Record { record });
+        self
+    }
+
+    /// Check the message against the limits the server enforces
+    pub fn validate(&self) -> Result<(), BiskyError> {
+        let graphemes = self.text.graphemes(true).count();
+        if graphemes > MAX_MESSAGE_GRAPHEMES {
+            return Err(BiskyError::MessageTooLong {
+                graphemes,
+                limit: MAX_MESSAGE_GRAPHEMES,
+            });
+        }
+        if self.text.len() > MAX_MESSAGE_BYTES {
+            return Err(BiskyError::MessageTooLarge {
+                bytes: self.text.len(),
+                limit: MAX_MESSAGE_BYTES,
+            });
+        }
+        validate_facets(&self.text, self.facets.as_deref().unwrap_or_default())
+    }
+}
+
+///chat.bsky.convo.sendMessage, and an item of chat.bsky.convo.sendMessageBatch
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessage<'a> {
+    pub convo_id: &'a str,
+    pub message: &'a MessageInput,
+}
+
+///chat.bsky.convo.sendMessageBatch
+#[derive(Debug, Serialize)]
+pub struct SendMessageBatch<'a> {
+    pub items: Vec<SendMessage<'a>>,
+}
+
+///chat.bsky.convo.sendMessageBatch
+#[derive(Debug, Deserialize)]
+pub struct SendMessageBatchOutput {
+    pub items: Vec<MessageView>,
+}
+
+///chat.bsky.convo.updateRead, muteConvo, unmuteConvo and leaveConvo
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvoInput<'a> {
+    pub convo_id: &'a str,
+    /// Only for updateRead, which marks up to this message as read, or everything without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<&'a str>,
+}
+
+///chat.bsky.convo.deleteMessageForSelf
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessageForSelf<'a> {
+    pub convo_id: &'a str,
+    pub message_id: &'a str,
+}
+
+///chat.bsky.convo.leaveConvo
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaveConvoOutput {
+    pub convo_id: String,
+    pub rev: String,
+}
+
+///chat.bsky.convo.defs#logBeginConvo, logAcceptConvo, logLeaveConvo, logMuteConvo and
+/// logUnmuteConvo
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogConvo {
+    pub rev: String,
+    pub convo_id: String,
+}
+
+///chat.bsky.convo.defs#logCreateMessage, logDeleteMessage and logReadMessage
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogMessage {
+    pub rev: String,
+    pub convo_id: String,
+    pub message: MessageViewEnum,
+}
+
+///chat.bsky.convo.defs#logAddReaction and logRemoveReaction
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogReaction {
+    pub rev: String,
+    pub convo_id: String,
+    pub message: MessageViewEnum,
+    pub reaction: serde_json::Value,
+}
+
+/// An entry of chat.bsky.convo.getLog
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+pub enum LogEvent {
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logBeginConvo"))]
+    BeginConvo(LogConvo),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logAcceptConvo"))]
+    AcceptConvo(LogConvo),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logLeaveConvo"))]
+    LeaveConvo(LogConvo),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logMuteConvo"))]
+    MuteConvo(LogConvo),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logUnmuteConvo"))]
+    UnmuteConvo(LogConvo),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logCreateMessage"))]
+    CreateMessage(LogMessage),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logDeleteMessage"))]
+    DeleteMessage(LogMessage),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logReadMessage"))]
+    ReadMessage(LogMessage),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logAddReaction"))]
+    AddReaction(LogReaction),
+    #[serde(rename(deserialize = "chat.bsky.convo.defs#logRemoveReaction"))]
+    RemoveReaction(LogReaction),
+    /// A log entry this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///chat.bsky.convo.getLog
+#[derive(Debug, Deserialize)]
+pub struct GetLogOutput {
+    pub cursor: Option<String>,
+    pub logs: Vec<LogEvent>,
+}
@@ -0,0 +1,86 @@
+use super::moderation::ReportSubject;
+use crate::types::Datetime;
+use serde::{Deserialize, Serialize};
+
+///com.atproto.admin.defs#statusAttr
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatusAttr {
+    pub applied: bool,
+    /// An identifier for the action, such as a moderation event id
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+///com.atproto.admin.updateSubjectStatus
+#[derive(Debug, Serialize)]
+pub struct UpdateSubjectStatus<'a> {
+    pub subject: &'a ReportSubject,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub takedown: Option<StatusAttr>,
+    /// Only for accounts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deactivated: Option<StatusAttr>,
+}
+
+///com.atproto.admin.getSubjectStatus, also the output of updateSubjectStatus
+#[derive(Debug, Deserialize)]
+pub struct SubjectStatus {
+    pub subject: ReportSubject,
+    pub takedown: Option<StatusAttr>,
+    pub deactivated: Option<StatusAttr>,
+}
+
+///com.atproto.server.defs#inviteCode
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteCode {
+    pub code: String,
+    pub available: u32,
+    pub disabled: bool,
+    pub for_account: String,
+    pub created_by: String,
+    pub created_at: Datetime,
+    #[serde(default)]
+    pub uses: Vec<serde_json::Value>,
+}
+
+///com.atproto.admin.defs#accountView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountView {
+    pub did: String,
+    pub handle: String,
+    pub email: Option<String>,
+    pub indexed_at: Datetime,
+    pub invited_by: Option<InviteCode>,
+    #[serde(default)]
+    pub invites: Vec<InviteCode>,
+    #[serde(default)]
+    pub invites_disabled: bool,
+    pub email_confirmed_at: Option<Datetime>,
+    pub invite_note: Option<String>,
+    pub deactivated_at: Option<Datetime>,
+    /// The account's records that are of interest to admins, such as its profile
+    #[serde(default)]
+    pub related_records: Vec<serde_json::Value>,
+}
+
+///com.atproto.admin.getAccountInfos
+#[derive(Debug, Deserialize)]
+pub struct GetAccountInfosOutput {
+    pub infos: Vec<AccountView>,
+}
+
+///com.atproto.admin.disableAccountInvites, com.atproto.admin.enableAccountInvites
+#[derive(Debug, Serialize)]
+pub struct AccountInvites<'a> {
+    pub account: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<&'a str>,
+}
+
+///com.atproto.admin.deleteAccount
+#[derive(Debug, Serialize)]
+pub struct DeleteAccount<'a> {
+    pub did: &'a str,
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+///com.atproto.identity.resolveHandle
+#[derive(Debug, Deserialize)]
+pub struct ResolveHandleOutput {
+    pub did: String,
+}
+
+///com.atproto.identity.updateHandle
+#[derive(Debug, Serialize)]
+pub struct UpdateHandle<'a> {
+    pub handle: &'a str,
+}
+
+///com.atproto.identity.signPlcOperation
+/// The fields to change, usually the output of getRecommendedDidCredentials
+#[derive(Debug, Serialize)]
+pub struct SignPlcOperation<'a> {
+    /// The code emailed by requestPlcOperationSignature
+    pub token: &'a str,
+    #[serde(flatten)]
+    pub credentials: &'a RecommendedDidCredentials,
+}
+
+///com.atproto.identity.getRecommendedDidCredentials
+/// Left out fields stay as they are in the DID document when signing
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendedDidCredentials {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_keys: Option<Vec<String>>,
+    /// `at://<handle>` uris
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub also_known_as: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_methods: Option<serde_json::Value>,
+    /// Keyed by service id, e.g. `atproto_pds`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub services: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+///com.atproto.identity.signPlcOperation
+#[derive(Debug, Deserialize)]
+pub struct SignPlcOperationOutput {
+    pub operation: serde_json::Value,
+}
+
+///com.atproto.identity.submitPlcOperation
+#[derive(Debug, Serialize)]
+pub struct SubmitPlcOperation<'a> {
+    pub operation: &'a serde_json::Value,
+}
@@ -0,0 +1,446 @@
+use crate::types::Datetime;
+#[cfg(feature = "firehose")]
+use crate::websocket::Reconnection;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+///com.atproto.label.defs#label
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Label {
+    /// The DID of the labeler that applied the label
+    pub src: String,
+    /// The account DID or record uri the label is about
+    pub uri: String,
+    /// Set when the label is about one version of a record
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+    pub val: KnownLabel,
+    /// Whether this label negates an earlier one, absent on most labels
+    #[serde(default)]
+    pub neg: bool,
+    pub cts: Datetime,
+    /// When the label stops applying
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<Datetime>,
+    /// The labeler's signature over the label
+    #[serde(default, with = "bytes", skip_serializing_if = "Option::is_none")]
+    pub sig: Option<Vec<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ver: Option<i64>,
+}
+
+/// How much a label demands the user's attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelSeverity {
+    /// Shown as informational
+    Inform,
+    /// Shown as a warning
+    Alert,
+    /// Not shown beyond any blurring
+    None,
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a label hides behind a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelBlurs {
+    /// The whole post or profile
+    Content,
+    /// Only the images and videos
+    Media,
+    /// Nothing, the label is informational only
+    None,
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a label does for users who haven't chosen otherwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelDefaultSetting {
+    Ignore,
+    #[default]
+    Warn,
+    Hide,
+    #[serde(other)]
+    Unknown,
+}
+
+///com.atproto.label.defs#labelValueDefinitionStrings
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LabelValueDefinitionStrings {
+    /// A BCP-47 language code, e.g. `en`
+    pub lang: String,
+    pub name: String,
+    pub description: String,
+}
+
+///com.atproto.label.defs#labelValueDefinition
+/// A custom label value that a labeler defines
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelValueDefinition {
+    /// The label value itself, lowercase letters and dashes
+    pub identifier: String,
+    pub severity: LabelSeverity,
+    pub blurs: LabelBlurs,
+    #[serde(default)]
+    pub default_setting: LabelDefaultSetting,
+    /// Whether the label can only be shown to users who have enabled adult content
+    #[serde(default)]
+    pub adult_only: bool,
+    #[serde(default)]
+    pub locales: Vec<LabelValueDefinitionStrings>,
+}
+
+impl LabelValueDefinition {
+    /// The name and description for `lang`, falling back to English and then to the first
+    /// locale there is
+    pub fn strings(&self, lang: &str) -> Option<&LabelValueDefinitionStrings> {
+        self.locales
+            .iter()
+            .find(|l| l.lang == lang)
+            .or_else(|| self.locales.iter().find(|l| l.lang == "en"))
+            .or_else(|| self.locales.first())
+    }
+}
+
+/// Bytes in the data model's JSON form, `{"$bytes": "<base64>"}`
+mod bytes {
+    use super::*;
+    use serde::de::{self, value::MapAccessDeserializer, MapAccess, Visitor};
+
+    // Written without padding, read with or without
+    const BASE64: GeneralPurpose = GeneralPurpose::new(
+        &base64::alphabet::STANDARD,
+        GeneralPurposeConfig::new()
+            .with_encode_padding(false)
+            .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+
+    #[derive(Deserialize, Serialize)]
+    struct Bytes {
+        #[serde(rename = "$bytes")]
+        bytes: String,
+    }
+
+    /// Accepts the JSON form and, for labels read off a DAG-CBOR stream, plain bytes
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("bytes or {\"$bytes\": \"<base64>\"}")
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            let value = Bytes::deserialize(MapAccessDeserializer::new(map))?;
+            BASE64.decode(value.bytes).map_err(A::Error::custom)
+        }
+    }
+
+    struct AnyBytes(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for AnyBytes {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(BytesVisitor).map(AnyBytes)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .as_ref()
+            .map(|value| Bytes {
+                bytes: BASE64.encode(value),
+            })
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<AnyBytes>::deserialize(deserializer)?.map(|value| value.0))
+    }
+}
+
+///com.atproto.label.queryLabels
+#[derive(Debug, Deserialize)]
+pub struct QueryLabelsOutput {
+    pub cursor: Option<String>,
+    pub labels: Vec<Label>,
+}
+
+///com.atproto.label.subscribeLabels#labels
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscribeLabelsLabels {
+    /// Where the stream is up to, to pass back as the cursor when reconnecting
+    pub seq: i64,
+    pub labels: Vec<Label>,
+}
+
+///com.atproto.label.subscribeLabels#info
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscribeLabelsInfo {
+    /// `OutdatedCursor` when the cursor is older than what the labeler keeps, so the stream
+    /// starts from the oldest label it has
+    pub name: String,
+    pub message: Option<String>,
+}
+
+/// A message on the com.atproto.label.subscribeLabels stream
+#[derive(Debug, Clone)]
+pub enum LabelStreamEvent {
+    Labels(SubscribeLabelsLabels),
+    Info(SubscribeLabelsInfo),
+    /// The stream lost its connection and opened a new one from its cursor
+    #[cfg(feature = "firehose")]
+    Reconnected(Reconnection),
+    /// A message type this version of bisky doesn't know, by its `t`
+    Unknown(String),
+}
+
+/// The label values with a meaning fixed by the protocol or the Bluesky app, with an escape hatch
+/// for the values labelers define themselves
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum KnownLabel {
+    /// `!hide`, hides the content from everyone, whatever their preferences
+    Hide,
+    /// `!warn`, puts the content behind a warning, whatever their preferences
+    Warn,
+    /// `!no-unauthenticated`, asks apps not to show the content to logged out viewers
+    NoUnauthenticated,
+    Porn,
+    Sexual,
+    Nudity,
+    GraphicMedia,
+    Other(String),
+}
+
+impl KnownLabel {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Hide => "!hide",
+            Self::Warn => "!warn",
+            Self::NoUnauthenticated => "!no-unauthenticated",
+            Self::Porn => "porn",
+            Self::Sexual => "sexual",
+            Self::Nudity => "nudity",
+            Self::GraphicMedia => "graphic-media",
+            Self::Other(value) => value,
+        }
+    }
+
+    /// Whether this is one of the `!` values, which users can't configure
+    pub fn is_system(&self) -> bool {
+        self.as_str().starts_with('!')
+    }
+
+    /// What the label hides behind a warning, `None` for `Other`
+    pub fn blurs(&self) -> Option<LabelBlurs> {
+        match self {
+            Self::Hide | Self::Warn | Self::NoUnauthenticated => Some(LabelBlurs::Content),
+            Self::Porn | Self::Sexual | Self::Nudity | Self::GraphicMedia => {
+                Some(LabelBlurs::Media)
+            }
+            Self::Other(_) => None,
+        }
+    }
+
+    /// What the label does for logged in users who haven't chosen otherwise, `None` for
+    /// `Other`. `!no-unauthenticated` does nothing for them, it only matters to logged out views.
+    pub fn default_setting(&self) -> Option<LabelDefaultSetting> {
+        match self {
+            Self::Hide | Self::Porn => Some(LabelDefaultSetting::Hide),
+            Self::Warn | Self::Sexual | Self::GraphicMedia => Some(LabelDefaultSetting::Warn),
+            Self::NoUnauthenticated | Self::Nudity => Some(LabelDefaultSetting::Ignore),
+            Self::Other(_) => None,
+        }
+    }
+
+    /// Whether the content is hidden from users who haven't enabled adult content
+    pub fn adult_only(&self) -> bool {
+        matches!(self, Self::Porn | Self::Sexual | Self::GraphicMedia)
+    }
+}
+
+impl From<String> for KnownLabel {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "!hide" => Self::Hide,
+            "!warn" => Self::Warn,
+            "!no-unauthenticated" => Self::NoUnauthenticated,
+            "porn" => Self::Porn,
+            "sexual" => Self::Sexual,
+            "nudity" => Self::Nudity,
+            "graphic-media" => Self::GraphicMedia,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<&str> for KnownLabel {
+    fn from(value: &str) -> Self {
+        value.to_string().into()
+    }
+}
+
+impl From<KnownLabel> for String {
+    fn from(value: KnownLabel) -> Self {
+        match value {
+            KnownLabel::Other(value) => value,
+            value => value.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for KnownLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+///com.atproto.label.defs#selfLabel
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SelfLabel {
+    pub val: KnownLabel,
+}
+
+///com.atproto.label.defs#selfLabels
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SelfLabels {
+    pub values: Vec<SelfLabel>,
+}
+
+impl SelfLabels {
+    /// Add a label, ignoring duplicates
+    pub fn add(&mut self, val: KnownLabel) {
+        if !self.values.iter().any(|l| l.val == val) {
+            self.values.push(SelfLabel { val });
+        }
+    }
+}
+
+/// The labels a record can carry about itself
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "$type")]
+pub enum RecordLabels {
+    #[serde(rename = "com.atproto.label.defs#selfLabels")]
+    SelfLabels(SelfLabels),
+    /// Labels in a form this crate doesn't know about yet, written back as they were read
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+impl RecordLabels {
+    /// Add a self label, ignoring duplicates. Labels in a form this crate doesn't know are
+    /// replaced.
+    pub fn add(&mut self, val: KnownLabel) {
+        match self {
+            Self::SelfLabels(labels) => labels.add(val),
+            Self::Unknown(_) => {
+                *self = Self::SelfLabels(SelfLabels {
+                    values: vec![SelfLabel { val }],
+                })
+            }
+        }
+    }
+}
+
+impl From<SelfLabels> for RecordLabels {
+    fn from(labels: SelfLabels) -> Self {
+        Self::SelfLabels(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn self_labels_json() {
+        let mut labels = RecordLabels::from(SelfLabels::default());
+        labels.add(KnownLabel::Porn);
+        labels.add("spoilers".into());
+        labels.add(KnownLabel::Porn);
+        assert_eq!(
+            serde_json::to_string(&labels).unwrap(),
+            r#"{"$type":"com.atproto.label.defs#selfLabels","values":[{"val":"porn"},{"val":"spoilers"}]}"#
+        );
+
+        let read: RecordLabels = serde_json::from_value(json!({
+            "$type": "com.atproto.label.defs#selfLabels",
+            "values": [{"val": "graphic-media"}, {"val": "!no-unauthenticated"}],
+        }))
+        .unwrap();
+        assert_eq!(
+            read,
+            RecordLabels::SelfLabels(SelfLabels {
+                values: vec![
+                    SelfLabel {
+                        val: KnownLabel::GraphicMedia
+                    },
+                    SelfLabel {
+                        val: KnownLabel::NoUnauthenticated
+                    },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_labels() {
+        let other = json!({"$type": "com.example.labels", "values": ["x"]});
+        let mut labels: RecordLabels = serde_json::from_value(other.clone()).unwrap();
+        assert_eq!(labels, RecordLabels::Unknown(other.clone()));
+        assert_eq!(serde_json::to_value(&labels).unwrap(), other);
+
+        // Replaced by self labels when one is added
+        labels.add(KnownLabel::Nudity);
+        assert_eq!(
+            serde_json::to_value(&labels).unwrap(),
+            json!({"$type": "com.atproto.label.defs#selfLabels", "values": [{"val": "nudity"}]})
+        );
+    }
+
+    #[test]
+    fn known_label_strings() {
+        for value in [
+            "!hide",
+            "!warn",
+            "!no-unauthenticated",
+            "porn",
+            "sexual",
+            "nudity",
+            "graphic-media",
+            "custom",
+        ] {
+            let label = KnownLabel::from(value);
+            assert_eq!(label.as_str(), value);
+            assert_eq!(serde_json::to_value(&label).unwrap(), json!(value));
+        }
+        assert_eq!(
+            KnownLabel::from("custom"),
+            KnownLabel::Other("custom".into())
+        );
+    }
+}
@@ -1,2 +1,8 @@
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod identity;
+pub mod label;
+pub mod moderation;
 pub mod repo;
 pub mod server;
+pub mod sync;
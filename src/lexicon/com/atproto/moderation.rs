@@ -0,0 +1,191 @@
+use super::repo::StrongRef;
+use crate::types::Datetime;
+use serde::{Deserialize, Serialize};
+
+/// The service requests go to through the PDS to reach the labeler run by `labeler_did`, such
+/// as reports and Ozone's endpoints
+pub fn labeler_proxy(labeler_did: &str) -> String {
+    format!("{labeler_did}#atproto_labeler")
+}
+
+///com.atproto.moderation.defs#reasonType
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ReasonType {
+    #[serde(rename = "com.atproto.moderation.defs#reasonSpam")]
+    Spam,
+    /// Breaks the law or the terms of service
+    #[serde(rename = "com.atproto.moderation.defs#reasonViolation")]
+    Violation,
+    #[serde(rename = "com.atproto.moderation.defs#reasonMisleading")]
+    Misleading,
+    /// Unwanted or mislabeled sexual content
+    #[serde(rename = "com.atproto.moderation.defs#reasonSexual")]
+    Sexual,
+    #[serde(rename = "com.atproto.moderation.defs#reasonRude")]
+    Rude,
+    #[serde(rename = "com.atproto.moderation.defs#reasonOther")]
+    Other,
+    /// Asking for a takedown to be reconsidered
+    #[serde(rename = "com.atproto.moderation.defs#reasonAppeal")]
+    Appeal,
+    #[serde(other, skip_serializing)]
+    Unknown,
+}
+
+///com.atproto.admin.defs#repoRef
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RepoRef {
+    pub did: String,
+}
+
+///com.atproto.admin.defs#repoBlobRef
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepoBlobRef {
+    pub did: String,
+    pub cid: String,
+    /// The record that uses the blob
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_uri: Option<String>,
+}
+
+/// What a report or an admin status is about
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "$type")]
+pub enum ReportSubject {
+    /// An account
+    #[serde(rename = "com.atproto.admin.defs#repoRef")]
+    Repo(RepoRef),
+    /// A record, such as a post
+    #[serde(rename = "com.atproto.repo.strongRef")]
+    Record(StrongRef),
+    /// A blob in an account's repo, which only admins can act on
+    #[serde(rename = "com.atproto.admin.defs#repoBlobRef")]
+    Blob(RepoBlobRef),
+    /// Another kind of subject, such as a chat message, only ever read back from a moderation
+    /// service
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///com.atproto.moderation.createReport
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateReport<'a> {
+    pub reason_type: ReasonType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+    pub subject: ReportSubject,
+}
+
+///com.atproto.moderation.createReport
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateReportOutput {
+    pub id: u64,
+    pub reason_type: ReasonType,
+    pub reason: Option<String>,
+    pub subject: ReportSubject,
+    pub reported_by: String,
+    pub created_at: Datetime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const POST_URI: &str = "at://did:plc:spammer/app.bsky.feed.post/3kdfsvlcf5c2a";
+    const POST_CID: &str = "bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom";
+
+    #[test]
+    fn report_post_json() {
+        let report = CreateReport {
+            reason_type: ReasonType::Spam,
+            reason: Some("Reply spam"),
+            subject: ReportSubject::Record(StrongRef::new(POST_URI, POST_CID).unwrap()),
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            concat!(
+                r#"{"reasonType":"com.atproto.moderation.defs#reasonSpam","reason":"Reply spam","#,
+                r#""subject":{"$type":"com.atproto.repo.strongRef","#,
+                r#""uri":"at://did:plc:spammer/app.bsky.feed.post/3kdfsvlcf5c2a","#,
+                r#""cid":"bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom"}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn report_account_json() {
+        let report = CreateReport {
+            reason_type: ReasonType::Rude,
+            reason: None,
+            subject: ReportSubject::Repo(RepoRef {
+                did: "did:plc:spammer".into(),
+            }),
+        };
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            concat!(
+                r#"{"reasonType":"com.atproto.moderation.defs#reasonRude","#,
+                r#""subject":{"$type":"com.atproto.admin.defs#repoRef","did":"did:plc:spammer"}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn reason_types() {
+        for (reason, name) in [
+            (ReasonType::Spam, "reasonSpam"),
+            (ReasonType::Violation, "reasonViolation"),
+            (ReasonType::Misleading, "reasonMisleading"),
+            (ReasonType::Sexual, "reasonSexual"),
+            (ReasonType::Rude, "reasonRude"),
+            (ReasonType::Other, "reasonOther"),
+            (ReasonType::Appeal, "reasonAppeal"),
+        ] {
+            let value = json!(format!("com.atproto.moderation.defs#{name}"));
+            assert_eq!(serde_json::to_value(reason).unwrap(), value);
+            assert_eq!(serde_json::from_value::<ReasonType>(value).unwrap(), reason);
+        }
+
+        // Newer reasons are read, but can't be sent
+        let newer = json!("tools.ozone.report.defs#reasonHarassmentTroll");
+        assert_eq!(
+            serde_json::from_value::<ReasonType>(newer).unwrap(),
+            ReasonType::Unknown
+        );
+        assert!(serde_json::to_value(ReasonType::Unknown).is_err());
+    }
+
+    #[test]
+    fn report_output() {
+        let output: CreateReportOutput = serde_json::from_value(json!({
+            "id": 1234,
+            "reasonType": "com.atproto.moderation.defs#reasonSpam",
+            "reason": "Reply spam",
+            "subject": {
+                "$type": "com.atproto.repo.strongRef",
+                "uri": POST_URI,
+                "cid": POST_CID,
+            },
+            "reportedBy": "did:plc:alice",
+            "createdAt": "2024-01-01T00:00:00.000Z",
+        }))
+        .unwrap();
+        assert_eq!(output.id, 1234);
+        assert!(matches!(output.subject, ReportSubject::Record(r) if r.cid == POST_CID));
+
+        // A subject this crate doesn't know is kept as it was
+        let message = json!({
+            "$type": "chat.bsky.convo.defs#messageRef",
+            "did": "did:plc:spammer",
+            "convoId": "3kdfsvlcf5c2a",
+            "messageId": "3kdfsvlcf5c2b",
+        });
+        let subject: ReportSubject = serde_json::from_value(message.clone()).unwrap();
+        assert!(matches!(subject, ReportSubject::Unknown(_)));
+        assert_eq!(serde_json::to_value(&subject).unwrap(), message);
+    }
+}
@@ -1,11 +1,41 @@
-use serde::{Deserialize, Serialize};
+use crate::errors::BiskyError;
+use crate::types::AtUri;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug, Serialize, Deserialize)]
+///com.atproto.repo.strongRef
+/// A record at one version, as the subject of a like or repost, a reply's root and parent, or a
+/// quoted post. Take it from the record as fetched or created, so the cid is never stale.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StrongRef {
-    pub uri: String,
+    pub uri: AtUri,
     pub cid: String,
 }
 
+impl StrongRef {
+    pub fn new(uri: &str, cid: &str) -> Result<Self, BiskyError> {
+        Ok(Self {
+            uri: uri.parse()?,
+            cid: cid.to_string(),
+        })
+    }
+}
+
+impl TryFrom<&CreateRecordOutput> for StrongRef {
+    type Error = BiskyError;
+
+    fn try_from(value: &CreateRecordOutput) -> Result<Self, Self::Error> {
+        Self::new(&value.uri, &value.cid)
+    }
+}
+
+impl<T> TryFrom<&Record<T>> for StrongRef {
+    type Error = BiskyError;
+
+    fn try_from(value: &Record<T>) -> Result<Self, Self::Error> {
+        Self::new(&value.uri, &value.cid)
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Record<T> {
     pub uri: String,
@@ -23,7 +53,103 @@ pub struct ListRecordsOutput<T> {
 pub struct CreateRecord<'a, T> {
     pub repo: &'a str,
     pub collection: &'a str,
+    /// Chosen by the PDS if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rkey: Option<&'a str>,
+    pub record: T,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutRecord<'a, T> {
+    pub repo: &'a str,
+    pub collection: &'a str,
+    pub rkey: &'a str,
     pub record: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_record: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+pub struct DeleteRecord<'a> {
+    pub repo: &'a str,
+    pub collection: &'a str,
+    pub rkey: &'a str,
+}
+
+///com.atproto.repo.applyWrites#create, #update and #delete
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "$type")]
+pub enum WriteOp {
+    #[serde(rename = "com.atproto.repo.applyWrites#create")]
+    Create {
+        collection: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rkey: Option<String>,
+        value: serde_json::Value,
+    },
+    #[serde(rename = "com.atproto.repo.applyWrites#update")]
+    Update {
+        collection: String,
+        rkey: String,
+        value: serde_json::Value,
+    },
+    #[serde(rename = "com.atproto.repo.applyWrites#delete")]
+    Delete { collection: String, rkey: String },
+}
+
+impl WriteOp {
+    /// A create of `record` in `collection`, with the record's `$type` filled in
+    pub fn create<T: Serialize>(collection: &str, record: &T) -> Result<Self, serde_json::Error> {
+        let mut value = serde_json::to_value(record)?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("$type".to_string(), collection.into());
+        }
+        Ok(Self::Create {
+            collection: collection.to_string(),
+            rkey: None,
+            value,
+        })
+    }
+
+    pub fn delete(collection: &str, rkey: &str) -> Self {
+        Self::Delete {
+            collection: collection.to_string(),
+            rkey: rkey.to_string(),
+        }
+    }
+}
+
+///com.atproto.repo.applyWrites
+#[derive(Debug, Serialize)]
+pub struct ApplyWrites<'a> {
+    pub repo: &'a str,
+    pub writes: &'a [WriteOp],
+}
+
+/// The most writes applyWrites accepts in one request
+pub const MAX_APPLY_WRITES: usize = 200;
+
+///com.atproto.repo.applyWrites#createResult, #updateResult and #deleteResult
+#[derive(Debug, Deserialize)]
+#[serde(tag = "$type")]
+pub enum WriteResult {
+    #[serde(rename = "com.atproto.repo.applyWrites#createResult")]
+    Create { uri: String, cid: String },
+    #[serde(rename = "com.atproto.repo.applyWrites#updateResult")]
+    Update { uri: String, cid: String },
+    #[serde(rename = "com.atproto.repo.applyWrites#deleteResult")]
+    Delete {},
+    /// A result this crate doesn't know about yet
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+///com.atproto.repo.applyWrites
+#[derive(Debug, Deserialize)]
+pub struct ApplyWritesOutput {
+    #[serde(default)]
+    pub results: Vec<WriteResult>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,23 +163,169 @@ pub struct CreateUploadBlob {
     pub blob: Vec<u8>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Link {
     #[serde(rename(deserialize = "$link", serialize = "$link"))]
     pub link: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A reference to a blob, read from either the current `{"$type": "blob", "ref": ...}` form or
+/// the legacy `{"cid": ..., "mimeType": ...}` one of old records, and always written in the
+/// current form
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Blob {
-    #[serde(rename(deserialize = "$type", serialize = "$type"))]
-    pub rust_type: String,
-    pub r#ref: Link,
-    #[serde(rename(deserialize = "mimeType", serialize = "mimeType"))]
-    pub mime_type: String,
-    pub size: usize,
+    cid: String,
+    mime_type: String,
+    size: Option<usize>,
+}
+
+impl Blob {
+    pub fn new(cid: impl Into<String>, mime_type: impl Into<String>, size: usize) -> Self {
+        Self {
+            cid: cid.into(),
+            mime_type: mime_type.into(),
+            size: Some(size),
+        }
+    }
+
+    pub fn cid(&self) -> &str {
+        &self.cid
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// The size in bytes, which legacy references don't record
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// Whether this was read from the legacy form
+    pub fn is_legacy(&self) -> bool {
+        self.size.is_none()
+    }
+
+    /// Every blob `record` refers to in the current form, at any depth, in the order they
+    /// appear
+    pub fn find_all(record: &serde_json::Value) -> Vec<Blob> {
+        let mut blobs = Vec::new();
+        find_blobs(record, &mut blobs);
+        blobs
+    }
+}
+
+fn find_blobs(value: &serde_json::Value, blobs: &mut Vec<Blob>) {
+    match value {
+        serde_json::Value::Object(object) => {
+            if object.get("$type").and_then(|t| t.as_str()) == Some("blob") {
+                if let Ok(blob) = Blob::deserialize(value) {
+                    blobs.push(blob);
+                }
+                return;
+            }
+            for value in object.values() {
+                find_blobs(value, blobs);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                find_blobs(value, blobs);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BlobForm {
+    #[serde(rename_all = "camelCase")]
+    Current {
+        r#ref: Link,
+        mime_type: String,
+        /// -1 for a legacy reference written in the current form
+        size: i64,
+    },
+    #[serde(rename_all = "camelCase")]
+    Legacy { cid: String, mime_type: String },
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match BlobForm::deserialize(deserializer)? {
+            BlobForm::Current {
+                r#ref,
+                mime_type,
+                size,
+            } => Self {
+                cid: r#ref.link,
+                mime_type,
+                size: usize::try_from(size).ok(),
+            },
+            BlobForm::Legacy { cid, mime_type } => Self {
+                cid,
+                mime_type,
+                size: None,
+            },
+        })
+    }
+}
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Current<'a> {
+            #[serde(rename = "$type")]
+            rust_type: &'static str,
+            r#ref: Link,
+            mime_type: &'a str,
+            // Unknown for legacy references, which the reference implementation writes as -1
+            size: i64,
+        }
+
+        Current {
+            rust_type: "blob",
+            r#ref: Link {
+                link: self.cid.clone(),
+            },
+            mime_type: &self.mime_type,
+            size: self.size.map_or(-1, |size| size as i64),
+        }
+        .serialize(serializer)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BlobOutput {
     pub blob: Blob,
 }
+
+///com.atproto.repo.listMissingBlobs#recordBlob
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingBlob {
+    pub cid: String,
+    pub record_uri: String,
+}
+
+///com.atproto.repo.listMissingBlobs
+#[derive(Debug, Deserialize)]
+pub struct ListMissingBlobsOutput {
+    pub blobs: Vec<MissingBlob>,
+    pub cursor: Option<String>,
+}
+
+///com.atproto.repo.describeRepo
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DescribeRepoOutput {
+    pub handle: String,
+    pub did: String,
+    pub did_doc: serde_json::Value,
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// Whether the PDS verified that the handle resolves to the DID
+    pub handle_is_correct: bool,
+}
@@ -1,22 +1,78 @@
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CreateUserSession {
     pub did: String,
     pub email: String,
     pub handle: String,
-    #[serde(rename(deserialize = "accessJwt"))]
     pub access_jwt: String,
-    #[serde(rename(deserialize = "refreshJwt"))]
     pub refresh_jwt: String,
 }
 
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RefreshUserSession {
     pub did: String,
     pub handle: String,
-    #[serde(rename(deserialize = "accessJwt"))]
     pub access_jwt: String,
-    #[serde(rename(deserialize = "refreshJwt"))]
     pub refresh_jwt: String,
 }
+
+///com.atproto.server.describeServer
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DescribeServerOutput {
+    pub did: String,
+    #[serde(default)]
+    pub available_user_domains: Vec<String>,
+    #[serde(default)]
+    pub invite_code_required: bool,
+}
+
+///com.atproto.server.getServiceAuth
+#[derive(Debug, Deserialize)]
+pub struct GetServiceAuthOutput {
+    pub token: String,
+}
+
+///com.atproto.server.createAccount
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAccount {
+    pub handle: String,
+    /// An existing DID, when migrating an account rather than creating a new identity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invite_code: Option<String>,
+}
+
+///com.atproto.server.createAccount
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAccountOutput {
+    pub did: String,
+    pub handle: String,
+    pub access_jwt: String,
+    pub refresh_jwt: String,
+}
+
+///com.atproto.server.checkAccountStatus
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckAccountStatusOutput {
+    pub activated: bool,
+    pub valid_did: bool,
+    pub repo_commit: String,
+    pub repo_rev: String,
+    pub repo_blocks: usize,
+    pub indexed_records: usize,
+    pub private_state_values: usize,
+    pub expected_blobs: usize,
+    pub imported_blobs: usize,
+}
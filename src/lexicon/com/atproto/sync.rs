@@ -0,0 +1,240 @@
+#[cfg(feature = "firehose")]
+use crate::types::AtUri;
+use crate::types::Datetime;
+#[cfg(feature = "firehose")]
+use crate::websocket::Reconnection;
+#[cfg(feature = "firehose")]
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What a repo op did to its record
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "String")]
+pub enum RepoOpAction {
+    Create,
+    Update,
+    Delete,
+    Other(String),
+}
+
+impl From<String> for RepoOpAction {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "create" => Self::Create,
+            "update" => Self::Update,
+            "delete" => Self::Delete,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl fmt::Display for RepoOpAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Other(value) => value,
+        })
+    }
+}
+
+///com.atproto.sync.subscribeRepos#repoOp
+#[cfg(feature = "firehose")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepoOp {
+    pub action: RepoOpAction,
+    /// `<collection>/<rkey>`
+    pub path: String,
+    /// The new record, absent for deletes
+    pub cid: Option<Cid>,
+    /// The record before an update or delete, on relays that send it
+    pub prev: Option<Cid>,
+}
+
+#[cfg(feature = "firehose")]
+impl RepoOp {
+    /// The collection and record key of `path`
+    pub fn collection_rkey(&self) -> Option<(&str, &str)> {
+        self.path.split_once('/')
+    }
+
+    /// The uri of the record in the repo of `did`, `None` if `path` is malformed
+    pub fn uri(&self, did: &str) -> Option<AtUri> {
+        let (collection, rkey) = self.collection_rkey()?;
+        Some(AtUri::new(did, collection, rkey))
+    }
+}
+
+///com.atproto.sync.subscribeRepos#commit
+#[cfg(feature = "firehose")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commit {
+    pub seq: i64,
+    /// Set when the commit was too large for the stream, so `blocks` and possibly `ops` are left
+    /// out. See `Commit::records_to_fetch`.
+    #[serde(default)]
+    pub too_big: bool,
+    /// The DID of the repo
+    pub repo: String,
+    pub commit: Cid,
+    /// The revision of the commit
+    pub rev: String,
+    /// The revision of the previous commit, absent for the first one
+    pub since: Option<String>,
+    /// A CAR file of the blocks the commit added
+    #[serde(with = "serde_bytes", default)]
+    pub blocks: Vec<u8>,
+    #[serde(default)]
+    pub ops: Vec<RepoOp>,
+    #[serde(default)]
+    pub blobs: Vec<Cid>,
+    /// The root of the repo's MST before this commit, on relays that send it
+    pub prev_data: Option<Cid>,
+    pub time: Datetime,
+}
+
+#[cfg(feature = "firehose")]
+impl Commit {
+    /// The records this commit created or updated but didn't carry, to fetch with getRecord.
+    /// Empty unless `too_big` is set; a `too_big` commit that also left out its ops lists none,
+    /// and the whole repo should be fetched with getRepo instead.
+    pub fn records_to_fetch(&self) -> Vec<AtUri> {
+        if !self.too_big {
+            return Vec::new();
+        }
+        self.ops
+            .iter()
+            .filter(|op| matches!(op.action, RepoOpAction::Create | RepoOpAction::Update))
+            .filter_map(|op| op.uri(&self.repo))
+            .collect()
+    }
+}
+
+///com.atproto.sync.subscribeRepos#sync
+/// The repo's state was reset, e.g. after a migration or a broken commit chain, so what is known
+/// of it should be replaced by a fresh copy from getRepo
+#[cfg(feature = "firehose")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sync {
+    pub seq: i64,
+    pub did: String,
+    /// A CAR file with just the signed commit
+    #[serde(with = "serde_bytes")]
+    pub blocks: Vec<u8>,
+    pub rev: String,
+    pub time: Datetime,
+}
+
+///com.atproto.sync.subscribeRepos#identity
+/// The account's handle or DID document may have changed
+#[derive(Debug, Clone, Deserialize)]
+pub struct Identity {
+    pub seq: i64,
+    pub did: String,
+    pub time: Datetime,
+    pub handle: Option<String>,
+}
+
+///com.atproto.sync.subscribeRepos#account
+#[derive(Debug, Clone, Deserialize)]
+pub struct Account {
+    pub seq: i64,
+    pub did: String,
+    pub time: Datetime,
+    pub active: bool,
+    /// Why the account isn't active, e.g. `takendown`, `suspended`, `deleted` or `deactivated`
+    pub status: Option<String>,
+}
+
+///com.atproto.sync.subscribeRepos#info
+#[derive(Debug, Clone, Deserialize)]
+pub struct Info {
+    /// `OutdatedCursor` when the cursor is older than what the relay keeps, so the stream starts
+    /// from the oldest event it has
+    pub name: String,
+    pub message: Option<String>,
+}
+
+/// A message on the com.atproto.sync.subscribeRepos stream
+#[cfg(feature = "firehose")]
+#[derive(Debug, Clone)]
+pub enum RepoStreamEvent {
+    Commit(Box<Commit>),
+    Sync(Sync),
+    Identity(Identity),
+    Account(Account),
+    Info(Info),
+    /// The relay no longer has the events after `cursor`, the cursor the stream resumed from, so
+    /// they were skipped and the repos they touched should be backfilled. Yielded by
+    /// `firehose::resumable_repo_stream` in place of the relay's `OutdatedCursor` info.
+    Gap {
+        cursor: i64,
+    },
+    /// The stream lost its connection and opened a new one from its cursor
+    Reconnected(Reconnection),
+    /// A message type this version of bisky doesn't know, such as the retired `#handle` and
+    /// `#tombstone`, by its `t`
+    Unknown(String),
+}
+
+#[cfg(feature = "firehose")]
+impl RepoStreamEvent {
+    /// Where the stream is up to after this event, to pass back as the cursor
+    pub fn seq(&self) -> Option<i64> {
+        match self {
+            Self::Commit(commit) => Some(commit.seq),
+            Self::Sync(sync) => Some(sync.seq),
+            Self::Identity(identity) => Some(identity.seq),
+            Self::Account(account) => Some(account.seq),
+            Self::Info(_) | Self::Gap { .. } | Self::Reconnected(_) | Self::Unknown(_) => None,
+        }
+    }
+
+    /// The repo the event is about, `None` for events about the stream itself
+    pub fn did(&self) -> Option<&str> {
+        match self {
+            Self::Commit(commit) => Some(&commit.repo),
+            Self::Sync(sync) => Some(&sync.did),
+            Self::Identity(identity) => Some(&identity.did),
+            Self::Account(account) => Some(&account.did),
+            Self::Info(_) | Self::Gap { .. } | Self::Reconnected(_) | Self::Unknown(_) => None,
+        }
+    }
+}
+
+///com.atproto.sync.listRepos#repo
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListReposRepo {
+    pub did: String,
+    /// The cid of the repo's latest commit
+    pub head: String,
+    pub rev: String,
+    /// Absent from older servers, which only list active repos
+    pub active: Option<bool>,
+    /// Why the repo isn't active, e.g. `takendown`, `suspended`, `deleted` or `deactivated`
+    pub status: Option<String>,
+}
+
+///com.atproto.sync.listRepos
+#[derive(Debug, Deserialize)]
+pub struct ListReposOutput {
+    pub cursor: Option<String>,
+    pub repos: Vec<ListReposRepo>,
+}
+
+///com.atproto.sync.listBlobs
+#[derive(Debug, Deserialize)]
+pub struct ListBlobsOutput {
+    pub cursor: Option<String>,
+    pub cids: Vec<String>,
+}
+
+///com.atproto.sync.requestCrawl, com.atproto.sync.notifyOfUpdate
+#[derive(Debug, Serialize)]
+pub struct Hostname<'a> {
+    /// The PDS, e.g. `pds.example.com`, without a scheme
+    pub hostname: &'a str,
+}
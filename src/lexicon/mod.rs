@@ -1,2 +1,7 @@
+#[cfg(feature = "bsky")]
 pub mod app;
+#[cfg(feature = "chat")]
+pub mod chat;
 pub mod com;
+#[cfg(feature = "ozone")]
+pub mod tools;
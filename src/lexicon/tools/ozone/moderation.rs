@@ -0,0 +1,207 @@
+use crate::lexicon::com::atproto::label::Label;
+use crate::lexicon::com::atproto::moderation::{ReasonType, ReportSubject};
+use crate::types::{union_type, Datetime};
+use serde::{Deserialize, Serialize};
+
+///tools.ozone.moderation.defs#modEventTakedown
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModEventTakedown {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// How long the takedown lasts, permanent when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_in_hours: Option<u64>,
+    /// Also acknowledge every open report on the account's records
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub acknowledge_account_subjects: bool,
+}
+
+///tools.ozone.moderation.defs#modEventReverseTakedown
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ModEventReverseTakedown {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+///tools.ozone.moderation.defs#modEventLabel
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModEventLabel {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub create_label_vals: Vec<String>,
+    pub negate_label_vals: Vec<String>,
+}
+
+///tools.ozone.moderation.defs#modEventAcknowledge
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ModEventAcknowledge {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+///tools.ozone.moderation.defs#modEventComment
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ModEventComment {
+    pub comment: String,
+    /// Keep the comment on the subject's status
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sticky: bool,
+}
+
+///tools.ozone.moderation.defs#modEventEscalate
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ModEventEscalate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+///tools.ozone.moderation.defs#modEventReport
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModEventReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub report_type: ReasonType,
+}
+
+/// A moderation event, as emitted with emitEvent and listed by queryEvents
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "$type")]
+pub enum ModEvent {
+    #[serde(rename = "tools.ozone.moderation.defs#modEventTakedown")]
+    Takedown(ModEventTakedown),
+    #[serde(rename = "tools.ozone.moderation.defs#modEventReverseTakedown")]
+    ReverseTakedown(ModEventReverseTakedown),
+    #[serde(rename = "tools.ozone.moderation.defs#modEventLabel")]
+    Label(ModEventLabel),
+    #[serde(rename = "tools.ozone.moderation.defs#modEventAcknowledge")]
+    Acknowledge(ModEventAcknowledge),
+    #[serde(rename = "tools.ozone.moderation.defs#modEventComment")]
+    Comment(ModEventComment),
+    #[serde(rename = "tools.ozone.moderation.defs#modEventEscalate")]
+    Escalate(ModEventEscalate),
+    #[serde(rename = "tools.ozone.moderation.defs#modEventReport")]
+    Report(ModEventReport),
+    /// Mutes, emails, tags and the other event types this crate doesn't model, kept as they were
+    /// read
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+impl ModEvent {
+    /// The `$type` to filter queryEvents by, `None` for an `Unknown` without one
+    pub fn event_type(&self) -> Option<&str> {
+        Some(match self {
+            Self::Takedown(_) => "tools.ozone.moderation.defs#modEventTakedown",
+            Self::ReverseTakedown(_) => "tools.ozone.moderation.defs#modEventReverseTakedown",
+            Self::Label(_) => "tools.ozone.moderation.defs#modEventLabel",
+            Self::Acknowledge(_) => "tools.ozone.moderation.defs#modEventAcknowledge",
+            Self::Comment(_) => "tools.ozone.moderation.defs#modEventComment",
+            Self::Escalate(_) => "tools.ozone.moderation.defs#modEventEscalate",
+            Self::Report(_) => "tools.ozone.moderation.defs#modEventReport",
+            Self::Unknown(value) => return union_type(value),
+        })
+    }
+}
+
+///tools.ozone.moderation.defs#modEventView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModEventView {
+    pub id: u64,
+    pub event: ModEvent,
+    pub subject: ReportSubject,
+    #[serde(default)]
+    pub subject_blob_cids: Vec<String>,
+    /// The DID of the moderator or reporter
+    pub created_by: String,
+    pub created_at: Datetime,
+    pub creator_handle: Option<String>,
+    pub subject_handle: Option<String>,
+}
+
+///tools.ozone.moderation.defs#subjectStatusView
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectStatusView {
+    pub id: u64,
+    pub subject: ReportSubject,
+    pub subject_repo_handle: Option<String>,
+    pub created_at: Datetime,
+    pub updated_at: Datetime,
+    /// e.g. `tools.ozone.moderation.defs#reviewOpen`
+    pub review_state: String,
+    /// The sticky comment
+    pub comment: Option<String>,
+    pub last_reviewed_by: Option<String>,
+    pub last_reviewed_at: Option<Datetime>,
+    pub last_reported_at: Option<Datetime>,
+    #[serde(default)]
+    pub takendown: bool,
+    #[serde(default)]
+    pub appealed: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///tools.ozone.moderation.queryStatuses
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryStatusesOutput {
+    pub cursor: Option<String>,
+    pub subject_statuses: Vec<SubjectStatusView>,
+}
+
+///tools.ozone.moderation.queryEvents
+#[derive(Debug, Deserialize)]
+pub struct QueryEventsOutput {
+    pub cursor: Option<String>,
+    pub events: Vec<ModEventView>,
+}
+
+///tools.ozone.moderation.emitEvent
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmitEvent<'a> {
+    pub event: &'a ModEvent,
+    pub subject: &'a ReportSubject,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub subject_blob_cids: &'a [&'a str],
+    /// The DID of the moderator
+    pub created_by: &'a str,
+}
+
+///tools.ozone.moderation.defs#recordViewDetail
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordViewDetail {
+    pub uri: String,
+    pub cid: String,
+    pub value: serde_json::Value,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub indexed_at: Datetime,
+    /// The record's moderation status and the author's repo view, left as JSON
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+///tools.ozone.moderation.defs#repoViewDetail
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepoViewDetail {
+    pub did: String,
+    pub handle: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub indexed_at: Datetime,
+    pub takedown_ref: Option<String>,
+    /// The repo's moderation status, related records and invites, left as JSON
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
@@ -1,5 +1,53 @@
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(feature = "bsky")]
+pub mod archive;
 pub mod atproto;
+#[cfg(feature = "bsky")]
 pub mod bluesky;
+#[cfg(feature = "bsky")]
+pub mod bot;
+#[cfg(feature = "bsky")]
+pub mod bsky;
+#[cfg(feature = "bsky")]
+pub mod bulk;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "firehose")]
+pub mod car;
+#[cfg(feature = "chat")]
+pub mod chat;
+#[cfg(feature = "firehose")]
+pub mod dagcbor;
 pub mod errors;
+#[cfg(feature = "firehose")]
+pub mod firehose;
+pub mod identity;
+#[cfg(feature = "image")]
+pub mod image;
+#[cfg(feature = "jetstream")]
+pub mod jetstream;
 pub mod lexicon;
+#[cfg(feature = "link-preview")]
+pub mod link_preview;
+pub mod migration;
+#[cfg(feature = "test-utils")]
+pub mod mock;
+#[cfg(feature = "bsky")]
+pub mod moderation;
+pub mod prelude;
+#[cfg(feature = "record-replay")]
+pub mod replay;
+#[cfg(feature = "firehose")]
+pub mod repo;
+pub mod retry;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+mod send;
 pub mod storage;
+pub mod sync;
+pub mod types;
+#[cfg(feature = "verify")]
+pub mod verify;
+#[cfg(any(feature = "firehose", feature = "jetstream"))]
+pub mod websocket;
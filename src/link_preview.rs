@@ -0,0 +1,412 @@
+//! Link cards: fetch a page and read its OpenGraph title, description and image, for an
+//! app.bsky.embed.external. Pages are fetched with a timeout and a size cap, and by default
+//! never from a private, loopback or link-local address, even after a redirect, so that a
+//! server posting links it was given can't be made to reach its own network.
+
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::embed::{External, ExternalObject};
+use crate::lexicon::com::atproto::repo::Blob;
+use crate::types::IntoUrl;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The most redirects followed
+const MAX_REDIRECTS: usize = 5;
+
+/// The largest thumbnail the AppView accepts, in bytes
+pub const MAX_THUMB_BYTES: usize = 1_000_000;
+
+/// How `fetch_with` fetches a page and its image
+#[derive(Debug, Clone)]
+pub struct LinkPreviewOptions {
+    /// For each request, from connecting to the end of the body
+    pub timeout: Duration,
+    /// Only this much of a page is read, which is plenty for its head
+    pub max_page_bytes: usize,
+    /// Images larger than this are left out
+    pub max_image_bytes: usize,
+    /// Allow private, loopback and link-local addresses, e.g. for a page on the local network
+    pub allow_private_addresses: bool,
+}
+
+impl Default for LinkPreviewOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_page_bytes: 1_000_000,
+            max_image_bytes: MAX_THUMB_BYTES,
+            allow_private_addresses: false,
+        }
+    }
+}
+
+/// What a page says about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPreview {
+    /// The link as it was given, not where it redirected to
+    pub url: Url,
+    /// `og:title`, or the `<title>`, or the link itself
+    pub title: String,
+    /// `og:description` or the description meta tag, if either is there
+    pub description: String,
+    /// `og:image`, resolved against the page
+    pub image: Option<Url>,
+}
+
+impl LinkPreview {
+    /// The external embed of the link, with `thumb` the uploaded image
+    pub fn into_external(self, thumb: Option<Blob>) -> External {
+        External {
+            external: ExternalObject {
+                uri: self.url.to_string(),
+                title: self.title,
+                description: self.description,
+                max_size: None,
+                thumb,
+            },
+        }
+    }
+}
+
+/// Fetch the page at `url` and read its preview, with the default options
+pub async fn fetch(url: impl IntoUrl) -> Result<LinkPreview, BiskyError> {
+    fetch_with(url, &LinkPreviewOptions::default()).await
+}
+
+pub async fn fetch_with(
+    url: impl IntoUrl,
+    options: &LinkPreviewOptions,
+) -> Result<LinkPreview, BiskyError> {
+    let url = url.into_url()?;
+    let response = get(&url, options, "text/html,application/xhtml+xml").await?;
+    // Redirects are followed, so relative links are relative to where they led
+    let base = response.url().clone();
+    let body = read_capped(response, options.max_page_bytes).await?;
+    let html = String::from_utf8_lossy(&body);
+    let meta = parse_head(&html);
+
+    let title = meta
+        .og_title
+        .or(meta.title)
+        .unwrap_or_else(|| url.to_string());
+    let image = meta.og_image.and_then(|image| base.join(&image).ok());
+    Ok(LinkPreview {
+        url,
+        title,
+        description: meta.og_description.or(meta.description).unwrap_or_default(),
+        image,
+    })
+}
+
+/// Download the image of a preview, with its MIME type. Gives `None` for anything that isn't an
+/// image, or is larger than `options.max_image_bytes`.
+pub async fn fetch_image(
+    url: &Url,
+    options: &LinkPreviewOptions,
+) -> Result<Option<(Vec<u8>, String)>, BiskyError> {
+    let response = get(url, options, "image/*").await?;
+    let mime_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .unwrap_or_default();
+    if !mime_type.starts_with("image/") {
+        return Ok(None);
+    }
+    if response
+        .content_length()
+        .is_some_and(|size| size > options.max_image_bytes as u64)
+    {
+        return Ok(None);
+    }
+    // One byte more than allowed, to tell an image that fits from one that was cut off
+    let data = read_capped(response, options.max_image_bytes + 1).await?;
+    match data.len() > options.max_image_bytes {
+        true => Ok(None),
+        false => Ok(Some((data, mime_type))),
+    }
+}
+
+async fn get(
+    url: &Url,
+    options: &LinkPreviewOptions,
+    accept: &str,
+) -> Result<reqwest::Response, BiskyError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(BiskyError::InvalidUrl(format!(
+            "{url}: not an http(s) link"
+        )));
+    }
+    let mut builder = reqwest::Client::builder().timeout(options.timeout);
+    if options.allow_private_addresses {
+        builder = builder.redirect(Policy::limited(MAX_REDIRECTS));
+    } else {
+        if let Some(ip) = literal_ip(url) {
+            if !is_public(ip) {
+                return Err(BiskyError::PrivateAddress(url.to_string()));
+            }
+        }
+        builder = builder
+            .redirect(Policy::custom(public_redirects))
+            .dns_resolver(Arc::new(PublicResolver));
+    }
+    let response = builder
+        .build()?
+        .get(url.clone())
+        .header(reqwest::header::ACCEPT, accept)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response)
+}
+
+/// Follow redirects to public addresses only, which the resolver can't check for links with an
+/// IP address in place of a host name
+fn public_redirects(attempt: Attempt<'_>) -> reqwest::redirect::Action {
+    if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error(format!("more than {MAX_REDIRECTS} redirects"));
+    }
+    match literal_ip(attempt.url()) {
+        Some(ip) if !is_public(ip) => {
+            let url = attempt.url().to_string();
+            attempt.error(BiskyError::PrivateAddress(url))
+        }
+        _ => attempt.follow(),
+    }
+}
+
+/// Resolves host names to their public addresses only, failing if they have none
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                let error: Box<dyn std::error::Error + Send + Sync> =
+                    Box::new(BiskyError::PrivateAddress(host));
+                return Err(error);
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// The address a link names in place of a host name, if it does
+fn literal_ip(url: &Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    host.parse().ok()
+}
+
+/// Whether `ip` is reachable from the internet, rather than private, shared, loopback,
+/// link-local, multicast or reserved
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space, used for carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        // Reserved for future use
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local
+        || (first & 0xfe00) == 0xfc00
+        // Link-local
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Read at most `limit` bytes of the body, leaving the rest unread
+async fn read_capped(mut response: reqwest::Response, limit: usize) -> Result<Vec<u8>, BiskyError> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= limit {
+            body.truncate(limit);
+            break;
+        }
+    }
+    Ok(body)
+}
+
+#[derive(Debug, Default)]
+struct HeadMeta {
+    og_title: Option<String>,
+    og_description: Option<String>,
+    og_image: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+}
+
+/// Read the meta tags and title of a page, without a full HTML parser, stopping at `</head>`
+fn parse_head(html: &str) -> HeadMeta {
+    let lower = html.to_ascii_lowercase();
+    let end = lower.find("</head").unwrap_or(html.len());
+    let mut meta = HeadMeta::default();
+
+    let mut pos = 0;
+    while let Some(start) = lower[pos..end].find("<meta").map(|i| pos + i) {
+        let Some(close) = lower[start..].find('>').map(|i| start + i) else {
+            break;
+        };
+        let attributes = parse_attributes(&html[start + "<meta".len()..close]);
+        let get = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let key = get("property").or(get("name")).unwrap_or_default();
+        if let Some(content) = get("content").map(decode_entities) {
+            let content = content.trim().to_string();
+            let slot = match key.to_ascii_lowercase().as_str() {
+                "og:title" => &mut meta.og_title,
+                "og:description" => &mut meta.og_description,
+                "og:image" | "og:image:url" | "og:image:secure_url" => &mut meta.og_image,
+                "description" => &mut meta.description,
+                _ => &mut None,
+            };
+            if slot.is_none() && !content.is_empty() {
+                *slot = Some(content);
+            }
+        }
+        pos = close;
+    }
+
+    if let Some(start) = lower[..end].find("<title") {
+        let text_start = lower[start..].find('>').map(|i| start + i + 1);
+        let text_end = text_start.and_then(|s| lower[s..].find("</title").map(|i| s + i));
+        if let (Some(text_start), Some(text_end)) = (text_start, text_end) {
+            let title = decode_entities(&html[text_start..text_end]);
+            let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+            meta.title = Some(title).filter(|title| !title.is_empty());
+        }
+    }
+    meta
+}
+
+/// The attributes of a tag, with lowercase names, from the text between its name and `>`
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace() || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, remaining) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let inner = &after[1..];
+                        let close = inner.find(quote).unwrap_or(inner.len());
+                        (&inner[..close], inner.get(close + 1..).unwrap_or_default())
+                    }
+                    _ => {
+                        let close = after.find(char::is_whitespace).unwrap_or(after.len());
+                        (&after[..close], &after[close..])
+                    }
+                };
+                rest = remaining;
+                value.to_string()
+            }
+            None => {
+                // A bare attribute, or the `/` of a self-closing tag
+                rest = rest.strip_prefix('/').unwrap_or(rest);
+                String::new()
+            }
+        };
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+        rest = rest.trim_start();
+    }
+    attributes
+}
+
+/// Decode the character references common in titles and descriptions
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let reference = rest[1..]
+            .find(';')
+            .filter(|&semi| semi <= 10)
+            .map(|semi| &rest[1..semi + 1]);
+        let character = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (character, reference) {
+            (Some(character), Some(reference)) => {
+                decoded.push(character);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
@@ -0,0 +1,186 @@
+//! Moving an account to a new PDS.
+//!
+//! `Migration::run` creates the account on the new PDS and copies the repo, blobs and
+//! preferences. Updating the DID document is left to the caller, since it needs the code that
+//! `request_plc_signature` emails to the user. Once `submit_plc_operation` succeeded, `activate`
+//! switches the account over. Every step checks the new PDS first, so a failed migration can be
+//! resumed by running it again.
+
+use crate::atproto::Client;
+use crate::errors::BiskyError;
+use crate::lexicon::com::atproto::server::CreateAccount;
+
+/// One part of a migration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationStep {
+    CreateAccount,
+    ImportRepo,
+    Blobs,
+    Preferences,
+}
+
+/// Progress of `Migration::run`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationProgress {
+    Started(MigrationStep),
+    /// Already done by an earlier run
+    Skipped(MigrationStep),
+    Finished(MigrationStep),
+    /// Blobs copied so far, out of those the new PDS expects
+    Blobs {
+        copied: usize,
+        expected: usize,
+    },
+}
+
+/// The account to create on the new PDS
+#[derive(Debug, Clone, Default)]
+pub struct NewAccount {
+    pub handle: String,
+    pub email: Option<String>,
+    pub password: Option<String>,
+    pub invite_code: Option<String>,
+}
+
+pub struct Migration<'a> {
+    old: &'a mut Client,
+    new: &'a mut Client,
+}
+
+impl<'a> Migration<'a> {
+    /// `old` must be logged in to the current PDS, `new` points at the new PDS and is logged in
+    /// to the new account if an earlier run already created it
+    pub fn new(old: &'a mut Client, new: &'a mut Client) -> Self {
+        Self { old, new }
+    }
+
+    /// Create the account and copy the repo, blobs and preferences, skipping whatever an
+    /// earlier run finished
+    pub async fn run(
+        &mut self,
+        account: &NewAccount,
+        mut progress: impl FnMut(MigrationProgress),
+    ) -> Result<(), BiskyError> {
+        if self.new.session().is_some() {
+            progress(MigrationProgress::Skipped(MigrationStep::CreateAccount));
+        } else {
+            progress(MigrationProgress::Started(MigrationStep::CreateAccount));
+            self.create_account(account).await?;
+            progress(MigrationProgress::Finished(MigrationStep::CreateAccount));
+        }
+
+        let status = self.new.server_check_account_status().await?;
+        if status.indexed_records > 0 {
+            progress(MigrationProgress::Skipped(MigrationStep::ImportRepo));
+        } else {
+            progress(MigrationProgress::Started(MigrationStep::ImportRepo));
+            self.import_repo().await?;
+            progress(MigrationProgress::Finished(MigrationStep::ImportRepo));
+        }
+
+        progress(MigrationProgress::Started(MigrationStep::Blobs));
+        self.copy_blobs(&mut progress).await?;
+        progress(MigrationProgress::Finished(MigrationStep::Blobs));
+
+        progress(MigrationProgress::Started(MigrationStep::Preferences));
+        self.copy_preferences().await?;
+        progress(MigrationProgress::Finished(MigrationStep::Preferences));
+
+        Ok(())
+    }
+
+    /// Create the account on the new PDS with the existing DID and log `new` in to it
+    pub async fn create_account(&mut self, account: &NewAccount) -> Result<(), BiskyError> {
+        let did = self.old_did()?;
+        let new_pds = self.new.server_describe_server().await?.did;
+        let token = self
+            .old
+            .server_get_service_auth(&new_pds, Some("com.atproto.server.createAccount"))
+            .await?;
+
+        self.new
+            .server_create_account(
+                &CreateAccount {
+                    handle: account.handle.clone(),
+                    did: Some(did),
+                    email: account.email.clone(),
+                    password: account.password.clone(),
+                    invite_code: account.invite_code.clone(),
+                },
+                Some(&token),
+            )
+            .await
+    }
+
+    /// Copy the repo from the old PDS to the new one
+    pub async fn import_repo(&mut self) -> Result<(), BiskyError> {
+        let did = self.old_did()?;
+        let car = self.old.sync_get_repo(&did).await?;
+        self.new.repo_import_repo(&car).await
+    }
+
+    /// Copy every blob the new PDS reports missing
+    pub async fn copy_blobs(
+        &mut self,
+        mut progress: impl FnMut(MigrationProgress),
+    ) -> Result<(), BiskyError> {
+        let did = self.old_did()?;
+        let expected = self.new.server_check_account_status().await?.expected_blobs;
+        let (missing, _) = self.new.repo_list_missing_blobs(usize::MAX, None).await?;
+
+        for (i, blob) in missing.iter().enumerate() {
+            let bytes = self.old.sync_get_blob(&did, &blob.cid).await?;
+            // The PDS works the mime type out from the bytes
+            self.new
+                .repo_upload_blob::<serde_json::Value>(&bytes, "application/octet-stream")
+                .await?;
+            progress(MigrationProgress::Blobs {
+                copied: i + 1,
+                expected,
+            });
+        }
+        Ok(())
+    }
+
+    /// Copy the preferences, which live outside the repo
+    pub async fn copy_preferences(&mut self) -> Result<(), BiskyError> {
+        // Passed along as they are, so preferences this crate doesn't know survive the move
+        let preferences = self
+            .old
+            .xrpc_get::<serde_json::Value>("app.bsky.actor.getPreferences", None)
+            .await?;
+        self.new
+            .xrpc_post_no_response("app.bsky.actor.putPreferences", &preferences)
+            .await
+    }
+
+    /// Have the old PDS email the code that `submit_plc_operation` needs
+    pub async fn request_plc_signature(&mut self) -> Result<(), BiskyError> {
+        self.old.identity_request_plc_operation_signature().await
+    }
+
+    /// Point the DID document at the new PDS, with the code from `request_plc_signature`
+    pub async fn submit_plc_operation(&mut self, token: &str) -> Result<(), BiskyError> {
+        let credentials = self.new.identity_get_recommended_did_credentials().await?;
+        let operation = self
+            .old
+            .identity_sign_plc_operation(token, &credentials)
+            .await?;
+        self.new.identity_submit_plc_operation(&operation).await
+    }
+
+    /// Activate the account on the new PDS and deactivate it on the old one
+    pub async fn activate(&mut self) -> Result<(), BiskyError> {
+        if !self.new.server_check_account_status().await?.activated {
+            self.new.server_activate_account().await?;
+        }
+        self.old.server_deactivate_account().await
+    }
+
+    fn old_did(&self) -> Result<String, BiskyError> {
+        match self.old.session() {
+            Some(session) => Ok(session.did),
+            None => Err(BiskyError::MissingSession),
+        }
+    }
+}
@@ -0,0 +1,942 @@
+//! A fake PDS for testing code built on bisky without a live server.
+//!
+//! `MockPds::start` serves XRPC on a local port from an in-memory store. It knows enough of
+//! com.atproto.server and com.atproto.repo for a real `Client` to log in, refresh its session
+//! and read and write records, singly or through applyWrites, and upload and download blobs,
+//! answers `app.bsky.actor.getProfiles` from its accounts, paginated queries such as
+//! `app.bsky.feed.getTimeline` from canned lists and any method from a response set for it, and
+//! keeps every request made of it and every record created and blob uploaded, so tests can check
+//! what was sent and posted. Follows, blocks and list items whose subject isn't a DID are
+//! refused as invalid, as a real PDS would.
+//!
+//! Methods it doesn't implement fail with `MethodNotImplemented`.
+
+use crate::atproto::{Client, ClientBuilder};
+use crate::errors::BiskyError;
+use crate::types::base32_sortable;
+use hyper::body::Bytes;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Method, Request, Response, Server, StatusCode, Version};
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+
+/// The account every `MockPds` starts with
+pub const MOCK_HANDLE: &str = "alice.test";
+pub const MOCK_DID: &str = "did:plc:alice";
+pub const MOCK_PASSWORD: &str = "password";
+
+/// A running fake PDS, shut down when dropped
+pub struct MockPds {
+    url: reqwest::Url,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockPds {
+    /// Serve on a free local port, with one account, `MOCK_HANDLE`, and tokens that don't
+    /// expire for an hour. Needs a tokio runtime.
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        state
+            .lock()
+            .add_account(MOCK_HANDLE, MOCK_DID, MOCK_PASSWORD);
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |connection: &AddrStream| {
+            let state = service_state.clone();
+            let peer = connection.remote_addr();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(state.clone(), peer, request)
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = reqwest::Url::parse(&format!("http://{}", server.local_addr())).unwrap();
+
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stopped.await.ok();
+        }));
+
+        Self {
+            url,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    /// Where the mock listens, to give to `ClientBuilder::service`
+    pub fn url(&self) -> &reqwest::Url {
+        &self.url
+    }
+
+    /// A client for the mock, logged in as `MOCK_HANDLE`
+    pub async fn login(&self) -> Result<Client, BiskyError> {
+        ClientBuilder::default()
+            .service(self.url.clone())
+            .login(MOCK_HANDLE, MOCK_PASSWORD)
+            .await
+    }
+
+    /// Add another account that can log in with its handle or DID
+    pub fn add_account(&self, handle: &str, did: &str, password: &str) {
+        self.state.lock().add_account(handle, did, password);
+    }
+
+    /// How long the tokens handed out from now on are good for. An expired access token is
+    /// answered with `ExpiredToken`, which makes the client refresh its session.
+    pub fn token_lifetimes(&self, access: Duration, refresh: Duration) {
+        let mut state = self.state.lock();
+        state.access_lifetime = access;
+        state.refresh_lifetime = refresh;
+    }
+
+    /// Expire every access token handed out so far, as if they had run out
+    pub fn expire_access_tokens(&self) {
+        let now = Instant::now();
+        for token in self.state.lock().tokens.values_mut() {
+            if !token.refresh {
+                token.expires = now;
+            }
+        }
+    }
+
+    /// What `app.bsky.feed.getTimeline` pages through, oldest last
+    pub fn set_timeline<T: Serialize>(&self, feed: impl IntoIterator<Item = T>) {
+        self.set_list("app.bsky.feed.getTimeline", "feed", feed);
+    }
+
+    /// Answer the query `nsid` with `items` in its `field`, a page of up to `limit` at a time
+    /// with a cursor to the next, e.g. `set_list("app.bsky.graph.getFollows", "follows", ..)`.
+    /// The cursor is left out of the last page, a `uri` parameter is echoed back, and the
+    /// `actor` of getFollows and getFollowers is given back as the `subject`.
+    pub fn set_list<T: Serialize>(
+        &self,
+        nsid: &str,
+        field: &str,
+        items: impl IntoIterator<Item = T>,
+    ) {
+        let items = items
+            .into_iter()
+            .map(|item| serde_json::to_value(item).unwrap())
+            .collect();
+        self.state
+            .lock()
+            .lists
+            .insert(nsid.to_string(), (field.to_string(), items));
+    }
+
+    /// Answer the XRPC method `nsid` with `output`, such as a response recorded from a real
+    /// service, in place of what the mock would do
+    pub fn set_response<T: Serialize>(&self, nsid: &str, output: &T) {
+        let output = serde_json::to_value(output).unwrap();
+        self.state.lock().responses.insert(nsid.to_string(), output);
+    }
+
+    /// Fail the next call of the XRPC method `nsid` with the `error` code and `message`. Calls
+    /// fail in the order they were set up, each once. A 429 also reports a rate limit with no
+    /// points left, as a PDS does.
+    pub fn fail_next(&self, nsid: &str, status: u16, error: &'static str, message: &str) {
+        let status = StatusCode::from_u16(status).unwrap();
+        self.state
+            .lock()
+            .failures
+            .entry(nsid.to_string())
+            .or_default()
+            .push_back(Failure::new(status, error, message));
+    }
+
+    /// Put a record in `did`'s repo without going through the API, returning its uri and cid
+    pub fn insert_record<T: Serialize>(
+        &self,
+        did: &str,
+        collection: &str,
+        rkey: Option<&str>,
+        record: &T,
+    ) -> (String, String) {
+        let value = serde_json::to_value(record).unwrap();
+        self.state.lock().put(did, collection, rkey, value)
+    }
+
+    /// Every record created in `collection`, by any account and in the order they were
+    /// created, including those deleted since
+    pub fn created_records(&self, collection: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .created
+            .iter()
+            .filter(|(c, _)| c == collection)
+            .map(|(_, record)| record.clone())
+            .collect()
+    }
+
+    /// The records currently in `did`'s `collection`, by rkey
+    pub fn records(&self, did: &str, collection: &str) -> BTreeMap<String, Value> {
+        self.state
+            .lock()
+            .repos
+            .get(&(did.to_string(), collection.to_string()))
+            .map(|records| {
+                records
+                    .iter()
+                    .map(|(rkey, (_, value))| (rkey.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The mime type and bytes of the blob uploaded as `cid`
+    pub fn blob(&self, cid: &str) -> Option<(String, Vec<u8>)> {
+        self.state.lock().blobs.get(cid).cloned()
+    }
+
+    /// Send response bodies in chunks without a `content-length`, as a server streaming its
+    /// output would, instead of all at once
+    pub fn chunked_responses(&self, chunked: bool) {
+        self.state.lock().chunked = chunked;
+    }
+
+    /// How many times the XRPC method `nsid` has been called
+    pub fn calls(&self, nsid: &str) -> usize {
+        self.state
+            .lock()
+            .calls
+            .get(nsid)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The requests made for the XRPC method `nsid`, oldest first
+    pub fn requests(&self, nsid: &str) -> Vec<MockRequest> {
+        self.state
+            .lock()
+            .requests
+            .iter()
+            .filter(|request| request.nsid == nsid)
+            .cloned()
+            .collect()
+    }
+}
+
+/// A request as `MockPds` received it
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub nsid: String,
+    pub method: Method,
+    /// The path and query as they were sent
+    pub uri: String,
+    /// The query parameters in the order they were sent, repeated ones included
+    pub query: Vec<(String, String)>,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub version: Version,
+    /// The address the request came from, which is the same for requests that share a
+    /// connection
+    pub peer: SocketAddr,
+}
+
+impl MockRequest {
+    /// The value of the header `name`, if it is there and text
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    /// The values of the query parameter `name`, in order
+    pub fn params(&self, name: &str) -> Vec<&str> {
+        self.query
+            .iter()
+            .filter(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// The body as JSON, or `Null` if it isn't
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+}
+
+impl Drop for MockPds {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+struct Account {
+    handle: String,
+    did: String,
+    password: String,
+}
+
+struct Token {
+    did: String,
+    refresh: bool,
+    expires: Instant,
+}
+
+struct State {
+    accounts: Vec<Account>,
+    tokens: HashMap<String, Token>,
+    access_lifetime: Duration,
+    refresh_lifetime: Duration,
+    /// Records by repo and collection, then rkey
+    repos: HashMap<(String, String), BTreeMap<String, (String, Value)>>,
+    created: Vec<(String, Value)>,
+    /// Mime types and bytes by cid
+    blobs: HashMap<String, (String, Vec<u8>)>,
+    /// Paginated query outputs by method, with the field their items go in
+    lists: HashMap<String, (String, Vec<Value>)>,
+    /// Outputs set with `set_response`, by method
+    responses: HashMap<String, Value>,
+    /// Failures set with `fail_next`, by method
+    failures: HashMap<String, VecDeque<Failure>>,
+    calls: HashMap<String, usize>,
+    requests: Vec<MockRequest>,
+    chunked: bool,
+    /// Counter behind tokens, cids and rkeys
+    next: u64,
+    last_tid: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            accounts: Vec::new(),
+            tokens: HashMap::new(),
+            access_lifetime: Duration::from_secs(60 * 60),
+            refresh_lifetime: Duration::from_secs(60 * 60 * 24 * 60),
+            repos: HashMap::new(),
+            created: Vec::new(),
+            blobs: HashMap::new(),
+            lists: HashMap::new(),
+            responses: HashMap::new(),
+            failures: HashMap::new(),
+            calls: HashMap::new(),
+            requests: Vec::new(),
+            chunked: false,
+            next: 0,
+            last_tid: 0,
+        }
+    }
+}
+
+/// A successful XRPC response
+enum Output {
+    Json(Value),
+    /// A blob, with its mime type
+    Bytes(String, Vec<u8>),
+}
+
+/// An XRPC error response
+struct Failure {
+    status: StatusCode,
+    error: &'static str,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: StatusCode, error: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            error,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidRequest", message)
+    }
+
+    fn with_error(mut self, error: &'static str) -> Self {
+        self.error = error;
+        self
+    }
+}
+
+impl State {
+    fn add_account(&mut self, handle: &str, did: &str, password: &str) {
+        self.accounts.push(Account {
+            handle: handle.to_string(),
+            did: did.to_string(),
+            password: password.to_string(),
+        });
+    }
+
+    fn next(&mut self) -> u64 {
+        self.next += 1;
+        self.next
+    }
+
+    /// A TID from the current time, always after the last one
+    fn tid(&mut self) -> String {
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as u64;
+        self.last_tid = micros.max(self.last_tid + 1);
+        base32_sortable(self.last_tid, 11) + &base32_sortable(0, 2)
+    }
+
+    fn cid(&mut self) -> String {
+        format!("bafyreig{}", base32_sortable(self.next(), 52))
+    }
+
+    fn session(&mut self, account: usize) -> Value {
+        let now = Instant::now();
+        let did = self.accounts[account].did.clone();
+        let access = format!("access-{}", self.next());
+        let refresh = format!("refresh-{}", self.next());
+        self.tokens.insert(
+            access.clone(),
+            Token {
+                did: did.clone(),
+                refresh: false,
+                expires: now + self.access_lifetime,
+            },
+        );
+        self.tokens.insert(
+            refresh.clone(),
+            Token {
+                did: did.clone(),
+                refresh: true,
+                expires: now + self.refresh_lifetime,
+            },
+        );
+        let account = &self.accounts[account];
+        json!({
+            "did": did,
+            "handle": account.handle,
+            "email": format!("{}@mock.test", account.handle),
+            "accessJwt": access,
+            "refreshJwt": refresh,
+        })
+    }
+
+    /// The DID a bearer token of the right kind is for
+    fn authenticate(&self, request: &Request<()>, refresh: bool) -> Result<String, Failure> {
+        let token = request
+            .headers()
+            .get("authorization")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                Failure::new(
+                    StatusCode::UNAUTHORIZED,
+                    "AuthenticationRequired",
+                    "Authentication Required",
+                )
+            })?;
+        match self.tokens.get(token) {
+            Some(token) if token.refresh == refresh => {
+                if token.expires <= Instant::now() {
+                    Err(Failure::invalid_request("Token has expired").with_error("ExpiredToken"))
+                } else {
+                    Ok(token.did.clone())
+                }
+            }
+            _ => Err(Failure::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidToken",
+                "Token could not be verified",
+            )),
+        }
+    }
+
+    fn resolve(&self, repo: &str) -> Result<String, Failure> {
+        self.accounts
+            .iter()
+            .find(|account| account.did == repo || account.handle == repo)
+            .map(|account| account.did.clone())
+            .ok_or_else(|| Failure::invalid_request(format!("Could not find repo: {repo}")))
+    }
+
+    fn put(
+        &mut self,
+        did: &str,
+        collection: &str,
+        rkey: Option<&str>,
+        value: Value,
+    ) -> (String, String) {
+        let rkey = match rkey {
+            Some(rkey) => rkey.to_string(),
+            None => self.tid(),
+        };
+        let cid = self.cid();
+        self.repos
+            .entry((did.to_string(), collection.to_string()))
+            .or_default()
+            .insert(rkey.clone(), (cid.clone(), value));
+        (format!("at://{did}/{collection}/{rkey}"), cid)
+    }
+
+    fn respond(&mut self, request: &Request<()>, body: &[u8]) -> Result<Output, Failure> {
+        // HTTP/2 requests carry the whole URI, HTTP/1.1 ones only the path
+        let path = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path| path.as_str());
+        let url = reqwest::Url::parse(&format!("http://mock{path}"))
+            .map_err(|e| Failure::invalid_request(e.to_string()))?;
+        let query = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+        let param = |name: &str| {
+            query
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Failure::invalid_request(format!("Params must have {name}")))
+        };
+        let input = || {
+            serde_json::from_slice::<Value>(body)
+                .map_err(|e| Failure::invalid_request(e.to_string()))
+        };
+        let field = |input: &Value, name: &str| {
+            input[name]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| Failure::invalid_request(format!("Input must have {name}")))
+        };
+
+        let nsid = url.path().strip_prefix("/xrpc/").unwrap_or_default();
+        *self.calls.entry(nsid.to_string()).or_default() += 1;
+        if let Some(failure) = self.failures.get_mut(nsid).and_then(VecDeque::pop_front) {
+            return Err(failure);
+        }
+
+        if (request.method(), nsid) == (&Method::GET, "com.atproto.sync.getBlob") {
+            let did = self.resolve(&param("did")?)?;
+            let cid = param("cid")?;
+            // Every account shares the blob store
+            let (mime_type, bytes) = self.blobs.get(&cid).ok_or_else(|| {
+                Failure::invalid_request(format!("Blob not found: {cid} of {did}"))
+                    .with_error("BlobNotFound")
+            })?;
+            return Ok(Output::Bytes(mime_type.clone(), bytes.clone()));
+        }
+        if let Some(output) = self.responses.get(nsid) {
+            return Ok(Output::Json(output.clone()));
+        }
+
+        let output = match (request.method(), nsid) {
+            (&Method::POST, "com.atproto.server.createSession") => {
+                let input = input()?;
+                let identifier = field(&input, "identifier")?;
+                let password = field(&input, "password")?;
+                let account = self
+                    .accounts
+                    .iter()
+                    .position(|account| {
+                        (account.handle == identifier || account.did == identifier)
+                            && account.password == password
+                    })
+                    .ok_or_else(|| {
+                        Failure::new(
+                            StatusCode::UNAUTHORIZED,
+                            "AuthenticationRequired",
+                            "Invalid identifier or password",
+                        )
+                    })?;
+                Ok(self.session(account))
+            }
+            (&Method::POST, "com.atproto.server.refreshSession") => {
+                let did = self.authenticate(request, true)?;
+                // A refresh token is good for one refresh
+                if let Some(token) = request
+                    .headers()
+                    .get("authorization")
+                    .and_then(|header| header.to_str().ok())
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                {
+                    self.tokens.remove(token);
+                }
+                let account = self
+                    .accounts
+                    .iter()
+                    .position(|account| account.did == did)
+                    .unwrap();
+                Ok(self.session(account))
+            }
+            (&Method::GET, "com.atproto.identity.resolveHandle") => {
+                let handle = param("handle")?;
+                let account = self
+                    .accounts
+                    .iter()
+                    .find(|account| account.handle == handle)
+                    .ok_or_else(|| {
+                        Failure::invalid_request("Unable to resolve handle")
+                            .with_error("HandleNotFound")
+                    })?;
+                Ok(json!({ "did": account.did }))
+            }
+            (&Method::GET, "com.atproto.repo.getRecord") => {
+                let did = self.resolve(&param("repo")?)?;
+                let collection = param("collection")?;
+                let rkey = param("rkey")?;
+                let (cid, value) = self
+                    .repos
+                    .get(&(did.clone(), collection.clone()))
+                    .and_then(|records| records.get(&rkey))
+                    .ok_or_else(|| {
+                        Failure::invalid_request(format!(
+                            "Could not locate record: at://{did}/{collection}/{rkey}"
+                        ))
+                        .with_error("RecordNotFound")
+                    })?;
+                Ok(json!({
+                    "uri": format!("at://{did}/{collection}/{rkey}"),
+                    "cid": cid,
+                    "value": value,
+                }))
+            }
+            (&Method::GET, "com.atproto.repo.listRecords") => {
+                let did = self.resolve(&param("repo")?)?;
+                let collection = param("collection")?;
+                let limit = param("limit")
+                    .ok()
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .unwrap_or(50);
+                let reverse = param("reverse").is_ok_and(|reverse| reverse == "true");
+                let cursor = param("cursor").ok();
+
+                let empty = BTreeMap::new();
+                let records = self
+                    .repos
+                    .get(&(did.clone(), collection.clone()))
+                    .unwrap_or(&empty);
+                // Newest first, unless reversed
+                let records: Box<dyn Iterator<Item = _>> = match (&cursor, reverse) {
+                    (Some(cursor), true) => Box::new(records.range(cursor.clone()..).skip(1)),
+                    (None, true) => Box::new(records.iter()),
+                    (Some(cursor), false) => Box::new(records.range(..cursor.clone()).rev()),
+                    (None, false) => Box::new(records.iter().rev()),
+                };
+                let page = records.take(limit).collect::<Vec<_>>();
+                let cursor = (page.len() == limit)
+                    .then(|| page.last().map(|(rkey, _)| rkey.to_string()))
+                    .flatten();
+                Ok(json!({
+                    "cursor": cursor,
+                    "records": page
+                        .into_iter()
+                        .map(|(rkey, (cid, value))| json!({
+                            "uri": format!("at://{did}/{collection}/{rkey}"),
+                            "cid": cid,
+                            "value": value,
+                        }))
+                        .collect::<Vec<_>>(),
+                }))
+            }
+            (&Method::POST, "com.atproto.repo.createRecord" | "com.atproto.repo.putRecord") => {
+                let did = self.authenticate(request, false)?;
+                let input = input()?;
+                if self.resolve(&field(&input, "repo")?)? != did {
+                    return Err(Failure::invalid_request("Can only write to your own repo"));
+                }
+                let collection = field(&input, "collection")?;
+                let rkey = input["rkey"].as_str();
+                let mut record = input["record"].clone();
+                if !record.is_object() {
+                    return Err(Failure::invalid_request("Input must have record"));
+                }
+                if record.get("$type").is_none() {
+                    record["$type"] = collection.clone().into();
+                }
+                check_record(&collection, &record)?;
+                if nsid == "com.atproto.repo.createRecord" {
+                    let exists = rkey.is_some_and(|rkey| {
+                        self.repos
+                            .get(&(did.clone(), collection.clone()))
+                            .is_some_and(|records| records.contains_key(rkey))
+                    });
+                    if exists {
+                        return Err(Failure::invalid_request("Record already exists")
+                            .with_error("RecordAlreadyExists"));
+                    }
+                }
+                self.created.push((collection.clone(), record.clone()));
+                let (uri, cid) = self.put(&did, &collection, rkey, record);
+                Ok(json!({ "uri": uri, "cid": cid }))
+            }
+            (&Method::POST, "com.atproto.repo.deleteRecord") => {
+                let did = self.authenticate(request, false)?;
+                let input = input()?;
+                if self.resolve(&field(&input, "repo")?)? != did {
+                    return Err(Failure::invalid_request("Can only write to your own repo"));
+                }
+                let collection = field(&input, "collection")?;
+                let rkey = field(&input, "rkey")?;
+                if let Some(records) = self.repos.get_mut(&(did, collection)) {
+                    records.remove(&rkey);
+                }
+                Ok(json!({}))
+            }
+            (&Method::POST, "com.atproto.repo.uploadBlob") => {
+                self.authenticate(request, false)?;
+                let mime_type = request
+                    .headers()
+                    .get("content-type")
+                    .and_then(|header| header.to_str().ok())
+                    .ok_or_else(|| Failure::invalid_request("Missing content-type"))?
+                    .to_string();
+                let cid = format!("bafkrei{}", base32_sortable(self.next(), 52));
+                self.blobs
+                    .insert(cid.clone(), (mime_type.clone(), body.to_vec()));
+                Ok(json!({
+                    "blob": {
+                        "$type": "blob",
+                        "ref": { "$link": cid },
+                        "mimeType": mime_type,
+                        "size": body.len(),
+                    }
+                }))
+            }
+            (&Method::POST, "com.atproto.repo.applyWrites") => {
+                let did = self.authenticate(request, false)?;
+                let input = input()?;
+                if self.resolve(&field(&input, "repo")?)? != did {
+                    return Err(Failure::invalid_request("Can only write to your own repo"));
+                }
+                let writes = input["writes"]
+                    .as_array()
+                    .ok_or_else(|| Failure::invalid_request("Input must have writes"))?;
+                if writes.len() > 200 {
+                    return Err(Failure::invalid_request("Too many writes. Max: 200"));
+                }
+                // All or nothing, so every write is checked before any is made
+                for write in writes {
+                    field(write, "collection")?;
+                    match write["$type"].as_str().unwrap_or_default() {
+                        "com.atproto.repo.applyWrites#create" => {
+                            check_record(&field(write, "collection")?, &write["value"])?;
+                        }
+                        "com.atproto.repo.applyWrites#update" => {
+                            field(write, "rkey")?;
+                            check_record(&field(write, "collection")?, &write["value"])?;
+                        }
+                        "com.atproto.repo.applyWrites#delete" => {
+                            field(write, "rkey")?;
+                        }
+                        other => {
+                            return Err(Failure::invalid_request(format!(
+                                "Invalid write type: {other}"
+                            )))
+                        }
+                    }
+                }
+
+                let mut results = Vec::new();
+                for write in writes {
+                    let collection = field(write, "collection")?;
+                    let rkey = write["rkey"].as_str();
+                    if write["$type"] == "com.atproto.repo.applyWrites#delete" {
+                        if let Some(records) = self.repos.get_mut(&(did.clone(), collection)) {
+                            records.remove(rkey.unwrap_or_default());
+                        }
+                        results
+                            .push(json!({ "$type": "com.atproto.repo.applyWrites#deleteResult" }));
+                        continue;
+                    }
+                    let mut record = write["value"].clone();
+                    if record.get("$type").is_none() {
+                        record["$type"] = collection.clone().into();
+                    }
+                    let created = write["$type"] == "com.atproto.repo.applyWrites#create";
+                    if created {
+                        self.created.push((collection.clone(), record.clone()));
+                    }
+                    let (uri, cid) = self.put(&did, &collection, rkey, record);
+                    let kind = match created {
+                        true => "com.atproto.repo.applyWrites#createResult",
+                        false => "com.atproto.repo.applyWrites#updateResult",
+                    };
+                    results.push(json!({ "$type": kind, "uri": uri, "cid": cid }));
+                }
+                Ok(json!({ "results": results }))
+            }
+            (&Method::GET, "app.bsky.actor.getProfiles") => {
+                let actors = url
+                    .query_pairs()
+                    .filter(|(name, _)| name == "actors")
+                    .map(|(_, actor)| actor.into_owned())
+                    .collect::<Vec<_>>();
+                if actors.len() > 25 {
+                    return Err(Failure::invalid_request(
+                        "Error: actors must not have more than 25 elements",
+                    ));
+                }
+                if let Some(i) = actors
+                    .iter()
+                    .position(|actor| !actor.starts_with("did:") && !actor.contains('.'))
+                {
+                    return Err(Failure::invalid_request(format!(
+                        "Error: actors/{i} must be a valid did or a handle"
+                    )));
+                }
+                // Unknown accounts are left out
+                let profiles = actors
+                    .iter()
+                    .filter_map(|actor| {
+                        self.accounts
+                            .iter()
+                            .find(|account| account.did == *actor || account.handle == *actor)
+                    })
+                    .map(|account| json!({ "did": account.did, "handle": account.handle }))
+                    .collect::<Vec<_>>();
+                Ok(json!({ "profiles": profiles }))
+            }
+            (&Method::GET, nsid) if self.lists.contains_key(nsid) => {
+                self.authenticate(request, false)?;
+                let (field, items) = &self.lists[nsid];
+                let limit = param("limit")
+                    .ok()
+                    .and_then(|limit| limit.parse::<usize>().ok())
+                    .unwrap_or(50);
+                let start = param("cursor")
+                    .ok()
+                    .and_then(|cursor| cursor.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let end = (start + limit).min(items.len());
+                let page = items.get(start..end).unwrap_or_default();
+                let mut output = json!({
+                    "cursor": (end < items.len()).then(|| end.to_string()),
+                });
+                output[field] = page.into();
+                // As getLikes and getRepostedBy name the post they're about
+                if let Ok(uri) = param("uri") {
+                    output["uri"] = uri.into();
+                }
+                // And getFollows and getFollowers the account
+                let graph = ["app.bsky.graph.getFollows", "app.bsky.graph.getFollowers"];
+                if let (true, Ok(actor)) = (graph.contains(&nsid), param("actor")) {
+                    let account = self
+                        .accounts
+                        .iter()
+                        .find(|account| account.did == actor || account.handle == actor)
+                        .ok_or_else(|| Failure::invalid_request("Profile not found"))?;
+                    output["subject"] = json!({ "did": account.did, "handle": account.handle });
+                }
+                Ok(output)
+            }
+            _ => Err(Failure::new(
+                StatusCode::NOT_IMPLEMENTED,
+                "MethodNotImplemented",
+                format!("Method Not Implemented: {nsid}"),
+            )),
+        };
+        output.map(Output::Json)
+    }
+}
+
+/// The little of lexicon validation the mock does: the subject of a follow, block or list item
+/// must be a DID
+fn check_record(collection: &str, record: &Value) -> Result<(), Failure> {
+    let has_subject = matches!(
+        collection,
+        "app.bsky.graph.follow" | "app.bsky.graph.block" | "app.bsky.graph.listitem"
+    );
+    if has_subject
+        && !record["subject"]
+            .as_str()
+            .is_some_and(|subject| subject.starts_with("did:"))
+    {
+        return Err(Failure::invalid_request(format!(
+            "Invalid {collection} record: Record/subject must be a valid did"
+        ))
+        .with_error("InvalidRecord"));
+    }
+    Ok(())
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    peer: SocketAddr,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let request = Request::from_parts(parts, ());
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str());
+    let url = reqwest::Url::parse(&format!("http://mock{path}")).ok();
+    let recorded = MockRequest {
+        nsid: url
+            .as_ref()
+            .and_then(|url| url.path().strip_prefix("/xrpc/"))
+            .unwrap_or_default()
+            .to_string(),
+        method: request.method().clone(),
+        uri: path.to_string(),
+        query: url
+            .map(|url| url.query_pairs().into_owned().collect())
+            .unwrap_or_default(),
+        headers: request.headers().clone(),
+        body: body.clone(),
+        version: request.version(),
+        peer,
+    };
+
+    let json = |value: Value| {
+        (
+            "application/json".to_string(),
+            value.to_string().into_bytes(),
+        )
+    };
+    let (output, chunked) = {
+        let mut state = state.lock();
+        state.requests.push(recorded);
+        (state.respond(&request, &body), state.chunked)
+    };
+    let (status, (content_type, bytes)) = match output {
+        Ok(Output::Json(output)) => (StatusCode::OK, json(output)),
+        Ok(Output::Bytes(mime_type, bytes)) => (StatusCode::OK, (mime_type, bytes)),
+        Err(failure) => (
+            failure.status,
+            json(json!({ "error": failure.error, "message": failure.message })),
+        ),
+    };
+    let body = match chunked {
+        true => {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                for chunk in bytes.chunks(8192) {
+                    if sender
+                        .send_data(Bytes::copy_from_slice(chunk))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+            body
+        }
+        false => Body::from(bytes),
+    };
+    let mut response = Response::builder()
+        .status(status)
+        .header("content-type", content_type);
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let reset = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 1;
+        response = response
+            .header("ratelimit-limit", "3000")
+            .header("ratelimit-remaining", "0")
+            .header("ratelimit-reset", reset.to_string())
+            .header("ratelimit-policy", "3000;w=300");
+    }
+    Ok(response.body(body).unwrap())
+}
@@ -0,0 +1,433 @@
+//! Deciding how to show a post or profile from its labels, the user's content label preferences
+//! and the definitions of the labelers they use.
+//!
+//! For each label that still applies, in order of precedence:
+//!
+//! 1. `!hide` and `!warn` hide or warn whatever the preferences say, except when the author put
+//!    them on their own content.
+//! 2. The label is interpreted by the definition of the labeler that applied it, or for the
+//!    global values such as `porn` by `KnownLabel`'s. Labels with neither are ignored, as are
+//!    the other `!` values.
+//! 3. Adult only labels hide the content unless adult content is enabled.
+//! 4. Otherwise the user's preference for the label from that labeler, then their preference for
+//!    the label from any labeler, then the definition's default setting picks ignore, warn or
+//!    hide. A warning covers the whole content or only the media depending on what the
+//!    definition blurs.
+
+use crate::lexicon::app::bsky::actor::{
+    AdultContentPref, Label, Preferences, ProfileView, Visibility,
+};
+use crate::lexicon::app::bsky::feed::PostView;
+use crate::lexicon::app::bsky::labeler::LabelerViewDetailed;
+use crate::lexicon::com::atproto::label::{KnownLabel, LabelBlurs, LabelDefaultSetting};
+use crate::types::Datetime;
+
+/// What to do with a post or profile, from least to most restrictive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ModerationAction {
+    #[default]
+    Show,
+    /// Show the text, but the images and videos behind a warning
+    BlurMedia,
+    /// Show the whole post or profile behind a warning
+    Warn,
+    /// Leave it out altogether
+    Hide,
+}
+
+/// A label that restricts how something is shown
+#[derive(Debug, Clone)]
+pub struct ModerationCause {
+    pub label: Label,
+    pub action: ModerationAction,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModerationDecision {
+    /// The most restrictive action of any cause
+    pub action: ModerationAction,
+    /// Every label that restricts the content, most restrictive first
+    pub causes: Vec<ModerationCause>,
+}
+
+impl ModerationDecision {
+    /// The label behind `action`, if any
+    pub fn cause(&self) -> Option<&Label> {
+        self.causes.first().map(|cause| &cause.label)
+    }
+}
+
+/// How to read a label value
+struct Interpretation {
+    blurs: LabelBlurs,
+    default_setting: LabelDefaultSetting,
+    adult_only: bool,
+}
+
+/// The built in meaning of the label values every labeler can apply
+fn global_interpretation(value: &KnownLabel) -> Option<Interpretation> {
+    Some(Interpretation {
+        blurs: value.blurs()?,
+        default_setting: value.default_setting()?,
+        adult_only: value.adult_only(),
+    })
+}
+
+/// A visibility as a setting, `None` for one this crate doesn't know
+fn visibility_setting(visibility: &Visibility) -> Option<LabelDefaultSetting> {
+    match visibility {
+        Visibility::Ignore | Visibility::Show => Some(LabelDefaultSetting::Ignore),
+        Visibility::Warn => Some(LabelDefaultSetting::Warn),
+        Visibility::Hide => Some(LabelDefaultSetting::Hide),
+        Visibility::Other(_) => None,
+    }
+}
+
+/// The user's visibility for `value` from `labeler`, falling back to theirs for any labeler
+fn preferred_setting(
+    prefs: &Preferences,
+    labeler: &str,
+    value: &str,
+) -> Option<LabelDefaultSetting> {
+    prefs
+        .label_visibility(Some(labeler), value)
+        .and_then(visibility_setting)
+        .or_else(|| {
+            prefs
+                .label_visibility(None, value)
+                .and_then(visibility_setting)
+        })
+}
+
+/// What one label does to content by `author`, `None` when it doesn't apply
+fn decide_label(
+    label: &Label,
+    author: &str,
+    prefs: &Preferences,
+    defs: &[LabelerViewDetailed],
+) -> Option<ModerationAction> {
+    if label.neg || label.exp.is_some_and(|exp| exp <= Datetime::now()) {
+        return None;
+    }
+
+    let self_label = label.src == author;
+    match &label.val {
+        KnownLabel::Hide if !self_label => return Some(ModerationAction::Hide),
+        KnownLabel::Warn if !self_label => return Some(ModerationAction::Warn),
+        // Including `!no-unauthenticated`, which only matters to logged out viewers
+        value if value.is_system() => return None,
+        _ => {}
+    }
+
+    let interpretation = defs
+        .iter()
+        .find(|labeler| labeler.creator.did == label.src)
+        .and_then(|labeler| labeler.definition(label.val.as_str()))
+        .map(|definition| Interpretation {
+            blurs: definition.blurs,
+            default_setting: definition.default_setting,
+            adult_only: definition.adult_only,
+        })
+        .or_else(|| global_interpretation(&label.val))?;
+
+    let adult_content = prefs
+        .get::<AdultContentPref>()
+        .is_some_and(|pref| pref.enabled);
+    let setting = if interpretation.adult_only && !adult_content {
+        LabelDefaultSetting::Hide
+    } else {
+        preferred_setting(prefs, &label.src, label.val.as_str())
+            .unwrap_or(interpretation.default_setting)
+    };
+
+    match (setting, interpretation.blurs) {
+        (LabelDefaultSetting::Hide, _) => Some(ModerationAction::Hide),
+        (LabelDefaultSetting::Warn, LabelBlurs::Content) => Some(ModerationAction::Warn),
+        (LabelDefaultSetting::Warn, LabelBlurs::Media) => Some(ModerationAction::BlurMedia),
+        _ => None,
+    }
+}
+
+/// The decision for `labels` on content by `author`
+pub fn decide<'a>(
+    labels: impl IntoIterator<Item = &'a Label>,
+    author: &str,
+    prefs: &Preferences,
+    defs: &[LabelerViewDetailed],
+) -> ModerationDecision {
+    let mut causes = labels
+        .into_iter()
+        .filter_map(|label| {
+            decide_label(label, author, prefs, defs).map(|action| ModerationCause {
+                label: label.clone(),
+                action,
+            })
+        })
+        .collect::<Vec<_>>();
+    causes.sort_by_key(|cause| std::cmp::Reverse(cause.action));
+
+    ModerationDecision {
+        action: causes
+            .first()
+            .map_or(ModerationAction::Show, |cause| cause.action),
+        causes,
+    }
+}
+
+/// The labels on an account itself, as opposed to its profile record
+fn account_labels<'a>(did: &'a str, labels: &'a [Label]) -> impl Iterator<Item = &'a Label> {
+    labels.iter().filter(move |label| label.uri == did)
+}
+
+/// How to show `post`, from its own labels and those on its author's account
+pub fn decide_post(
+    post: &PostView,
+    prefs: &Preferences,
+    defs: &[LabelerViewDetailed],
+) -> ModerationDecision {
+    let author = &post.author;
+    decide(
+        post.labels
+            .iter()
+            .chain(account_labels(&author.did, &author.labels)),
+        &author.did,
+        prefs,
+        defs,
+    )
+}
+
+/// How to show `profile`, from the labels on the account and on its profile record
+pub fn decide_profile(
+    profile: &ProfileView,
+    prefs: &Preferences,
+    defs: &[LabelerViewDetailed],
+) -> ModerationDecision {
+    decide(&profile.labels, &profile.did, prefs, defs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const AUTHOR: &str = "did:plc:author";
+    const BSKY: &str = "did:plc:ar7c4by46qjdydhdevvrndac";
+    const LABELER: &str = "did:plc:labeler";
+
+    fn label(src: &str, val: &str) -> Label {
+        serde_json::from_value(json!({
+            "src": src,
+            "uri": "at://did:plc:author/app.bsky.feed.post/3kdfsvlcf5c2a",
+            "val": val,
+            "cts": "2024-01-01T00:00:00.000Z",
+        }))
+        .unwrap()
+    }
+
+    /// The user's preferences: whether adult content is enabled, and their visibility for each
+    /// (labeler, value), `None` standing for every labeler
+    fn prefs(adult_content: bool, visibilities: &[(Option<&str>, &str, &str)]) -> Preferences {
+        let mut prefs = vec![json!({
+            "$type": "app.bsky.actor.defs#adultContentPref",
+            "enabled": adult_content,
+        })];
+        prefs.extend(visibilities.iter().map(|(labeler, value, visibility)| {
+            let mut pref = json!({
+                "$type": "app.bsky.actor.defs#contentLabelPref",
+                "label": value,
+                "visibility": visibility,
+            });
+            if let Some(labeler) = labeler {
+                pref["labelerDid"] = json!(labeler);
+            }
+            pref
+        }));
+        serde_json::from_value(json!(prefs)).unwrap()
+    }
+
+    /// A labeler defining `spoiler`, a warning over the whole post; `gore`, adult only media;
+    /// and `rude`, which blurs nothing
+    fn defs() -> Vec<LabelerViewDetailed> {
+        let definition = |identifier: &str, blurs: &str, default_setting: &str, adult: bool| {
+            json!({
+                "identifier": identifier,
+                "severity": "alert",
+                "blurs": blurs,
+                "defaultSetting": default_setting,
+                "adultOnly": adult,
+                "locales": [],
+            })
+        };
+        vec![serde_json::from_value(json!({
+            "uri": "at://did:plc:labeler/app.bsky.labeler.service/self",
+            "cid": "bafyreigbtj4x7ip5legnfznufuopl4sg4knzc2cof6duas4b3q2fy6swua",
+            "creator": {"did": LABELER, "handle": "labeler.test"},
+            "policies": {
+                "labelValues": ["spoiler", "gore", "rude", "porn"],
+                "labelValueDefinitions": [
+                    definition("spoiler", "content", "warn", false),
+                    definition("gore", "media", "warn", true),
+                    definition("rude", "none", "warn", false),
+                ],
+            },
+            "indexedAt": "2024-01-01T00:00:00.000Z",
+        }))
+        .unwrap()]
+    }
+
+    /// A case, its labels, the user's preferences, and the action and top cause expected
+    type Row = (
+        &'static str,
+        Vec<Label>,
+        Preferences,
+        ModerationAction,
+        Option<&'static str>,
+    );
+
+    #[test]
+    fn decisions() {
+        use ModerationAction::*;
+
+        let negated = Label {
+            neg: true,
+            ..label(BSKY, "porn")
+        };
+        let expired = Label {
+            exp: Some("2024-01-02T00:00:00.000Z".parse().unwrap()),
+            ..label(BSKY, "porn")
+        };
+        let unexpired = Label {
+            exp: Some("2999-01-01T00:00:00.000Z".parse().unwrap()),
+            ..label(BSKY, "porn")
+        };
+
+        #[rustfmt::skip]
+        let table: Vec<Row> = vec![
+            ("nothing", vec![], prefs(false, &[]), Show, None),
+
+            // Global values, by their built in meaning
+            ("porn, adult off", vec![label(BSKY, "porn")], prefs(false, &[]), Hide, Some("porn")),
+            ("porn, adult on", vec![label(BSKY, "porn")], prefs(true, &[]), Hide, Some("porn")),
+            ("porn, warn", vec![label(BSKY, "porn")], prefs(true, &[(None, "porn", "warn")]), BlurMedia, Some("porn")),
+            ("porn, ignore", vec![label(BSKY, "porn")], prefs(true, &[(None, "porn", "ignore")]), Show, None),
+            ("porn, ignore but adult off", vec![label(BSKY, "porn")], prefs(false, &[(None, "porn", "ignore")]), Hide, Some("porn")),
+            ("sexual", vec![label(BSKY, "sexual")], prefs(true, &[]), BlurMedia, Some("sexual")),
+            ("sexual, adult off", vec![label(BSKY, "sexual")], prefs(false, &[]), Hide, Some("sexual")),
+            ("nudity", vec![label(BSKY, "nudity")], prefs(false, &[]), Show, None),
+            ("nudity, warn", vec![label(BSKY, "nudity")], prefs(false, &[(None, "nudity", "warn")]), BlurMedia, Some("nudity")),
+            ("graphic-media, hide", vec![label(BSKY, "graphic-media")], prefs(true, &[(None, "graphic-media", "hide")]), Hide, Some("graphic-media")),
+            ("legacy show", vec![label(BSKY, "sexual")], prefs(true, &[(None, "sexual", "show")]), Show, None),
+            ("unknown visibility", vec![label(BSKY, "sexual")], prefs(true, &[(None, "sexual", "blur")]), BlurMedia, Some("sexual")),
+
+            // `!` values
+            ("!hide", vec![label(LABELER, "!hide")], prefs(true, &[]), Hide, Some("!hide")),
+            ("!warn", vec![label(LABELER, "!warn")], prefs(true, &[]), Warn, Some("!warn")),
+            ("!hide by the author", vec![label(AUTHOR, "!hide")], prefs(true, &[]), Show, None),
+            ("!warn by the author", vec![label(AUTHOR, "!warn")], prefs(true, &[]), Show, None),
+            ("!no-unauthenticated", vec![label(AUTHOR, "!no-unauthenticated")], prefs(false, &[]), Show, None),
+
+            // Labels that no longer apply
+            ("negated", vec![negated], prefs(false, &[]), Show, None),
+            ("expired", vec![expired], prefs(false, &[]), Show, None),
+            ("not yet expired", vec![unexpired], prefs(false, &[]), Hide, Some("porn")),
+
+            // A labeler's own values, by its definitions
+            ("spoiler", vec![label(LABELER, "spoiler")], prefs(false, &[]), Warn, Some("spoiler")),
+            ("spoiler, hide", vec![label(LABELER, "spoiler")], prefs(false, &[(None, "spoiler", "hide")]), Hide, Some("spoiler")),
+            ("spoiler, labeler pref first", vec![label(LABELER, "spoiler")],
+                prefs(false, &[(None, "spoiler", "hide"), (Some(LABELER), "spoiler", "ignore")]), Show, None),
+            ("spoiler, another labeler's pref", vec![label(LABELER, "spoiler")],
+                prefs(false, &[(Some(BSKY), "spoiler", "hide")]), Warn, Some("spoiler")),
+            ("spoiler from an unknown labeler", vec![label("did:plc:other", "spoiler")], prefs(false, &[]), Show, None),
+            ("gore, adult off", vec![label(LABELER, "gore")], prefs(false, &[]), Hide, Some("gore")),
+            ("gore, adult on", vec![label(LABELER, "gore")], prefs(true, &[]), BlurMedia, Some("gore")),
+            ("rude, blurs nothing", vec![label(LABELER, "rude")], prefs(false, &[]), Show, None),
+            ("global value from a labeler", vec![label(LABELER, "nudity")], prefs(false, &[(Some(LABELER), "nudity", "hide")]), Hide, Some("nudity")),
+
+            // The most restrictive label wins
+            ("several", vec![label(BSKY, "nudity"), label(BSKY, "sexual"), label(LABELER, "spoiler")],
+                prefs(true, &[]), Warn, Some("spoiler")),
+            ("several, one hidden", vec![label(LABELER, "spoiler"), label(BSKY, "porn")],
+                prefs(true, &[]), Hide, Some("porn")),
+        ];
+
+        let defs = defs();
+        for (name, labels, prefs, action, cause) in table {
+            let decision = decide(&labels, AUTHOR, &prefs, &defs);
+            assert_eq!(decision.action, action, "{name}");
+            assert_eq!(
+                decision.cause().map(|label| label.val.as_str()),
+                cause,
+                "{name}"
+            );
+            assert!(
+                decision
+                    .causes
+                    .windows(2)
+                    .all(|w| w[0].action >= w[1].action),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn causes_in_order() {
+        let labels = [
+            label(BSKY, "nudity"),
+            label(BSKY, "sexual"),
+            label(LABELER, "spoiler"),
+            label(LABELER, "!hide"),
+        ];
+        let decision = decide(
+            &labels,
+            AUTHOR,
+            &prefs(true, &[(None, "nudity", "warn")]),
+            &defs(),
+        );
+        let causes: Vec<_> = decision
+            .causes
+            .iter()
+            .map(|cause| (cause.label.val.as_str(), cause.action))
+            .collect();
+        assert_eq!(
+            causes,
+            [
+                ("!hide", ModerationAction::Hide),
+                ("spoiler", ModerationAction::Warn),
+                ("nudity", ModerationAction::BlurMedia),
+                ("sexual", ModerationAction::BlurMedia),
+            ]
+        );
+    }
+
+    #[test]
+    fn account_and_profile_labels() {
+        let on_account = Label {
+            uri: AUTHOR.to_string(),
+            ..label(BSKY, "porn")
+        };
+        let on_profile = Label {
+            uri: format!("at://{AUTHOR}/app.bsky.actor.profile/self"),
+            ..label(BSKY, "porn")
+        };
+        let labels = [on_account.clone(), on_profile.clone()];
+
+        // Only labels on the account itself carry over to posts
+        assert_eq!(account_labels(AUTHOR, &labels).count(), 1);
+        assert_eq!(
+            account_labels(AUTHOR, &labels).next().unwrap().uri,
+            on_account.uri
+        );
+
+        // Both count for the profile
+        let profile: ProfileView = serde_json::from_value(json!({
+            "did": AUTHOR,
+            "handle": "author.test",
+            "labels": [on_account, on_profile],
+        }))
+        .unwrap();
+        let decision = decide_profile(&profile, &prefs(false, &[]), &[]);
+        assert_eq!(decision.action, ModerationAction::Hide);
+        assert_eq!(decision.causes.len(), 2);
+    }
+}
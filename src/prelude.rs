@@ -0,0 +1,22 @@
+//! What most uses of bisky need, for `use bisky::prelude::*`
+
+pub use crate::atproto::{Client, ClientBuilder, UserSession};
+#[cfg(feature = "bsky")]
+pub use crate::bluesky::Bluesky;
+#[cfg(feature = "bsky")]
+pub use crate::bsky::BskyClient;
+pub use crate::errors::{ApiErrorKind, BiskyError};
+#[cfg(feature = "bsky")]
+pub use crate::lexicon::app::bsky::actor::{ProfileView, ProfileViewBasic, ProfileViewDetailed};
+#[cfg(feature = "bsky")]
+pub use crate::lexicon::app::bsky::feed::{FeedViewPost, Post, PostBuilder, PostView};
+#[cfg(feature = "bsky")]
+pub use crate::lexicon::app::bsky::notification::Notification;
+#[cfg(feature = "bsky")]
+pub use crate::lexicon::app::bsky::record::KnownRecord;
+pub use crate::lexicon::com::atproto::repo::{Blob, CreateRecordOutput, Record, StrongRef};
+pub use crate::storage::{File, Storage};
+pub use crate::types::{AtUri, Datetime, IntoUrl};
+#[cfg(feature = "firehose")]
+pub use cid::Cid;
+pub use reqwest::Url;
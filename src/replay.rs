@@ -0,0 +1,281 @@
+//! Record the requests a `Client` sends and their responses to a cassette file, then answer
+//! them from it without the network, for tests that run the same calls every time. Set a
+//! `Cassette` as the transport of the client, with `ClientBuilder::transport`, record once
+//! against the real service and `save`, then replay it in CI.
+//!
+//! Nothing secret is written: request headers aren't kept, request bodies are kept only as a
+//! hash, taken with passwords blanked, and tokens and passwords in responses are replaced with
+//! `REDACTED`.
+
+use crate::atproto::XrpcTransport;
+use crate::errors::BiskyError;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// The JSON fields of request bodies that are blanked before hashing, by default. Both are
+/// different from one run to the next, or shouldn't be in the cassette.
+pub const DEFAULT_IGNORED_FIELDS: &[&str] = &["password", "createdAt"];
+
+/// The JSON fields of response bodies whose values never reach the cassette
+const REDACTED_FIELDS: &[&str] = &["accessJwt", "refreshJwt", "password", "token"];
+
+/// The response headers kept, all others being left out
+const KEPT_HEADERS: &[&str] = &["content-type", "x-request-id"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+/// An `XrpcTransport` that either sends requests and records them with their responses, or
+/// answers them from a recording.
+///
+/// Requests are matched on their method, path, query and body, not on the service they are
+/// sent to. When a request was made more than once, the recorded responses are given in the
+/// order they were recorded, and a request with no response left fails with
+/// `BiskyError::UnexpectedRequest`.
+pub struct Cassette {
+    mode: Mode,
+    path: PathBuf,
+    ignored_fields: Vec<String>,
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+    /// Already given out in this replay
+    #[serde(skip)]
+    used: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query: Option<String>,
+    /// The SHA-256 of the body, in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    /// The body as text, or in base64 if it isn't UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Send requests over the network, keeping them to be written to `path` by `save`
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self {
+            mode: Mode::Record,
+            path: path.into(),
+            ignored_fields: to_strings(DEFAULT_IGNORED_FIELDS),
+            interactions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Answer requests from the cassette at `path`, without the network
+    pub async fn replay(path: impl Into<PathBuf>) -> Result<Self, BiskyError> {
+        let path = path.into();
+        let file: CassetteFile = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+        Ok(Self {
+            mode: Mode::Replay,
+            path,
+            ignored_fields: to_strings(DEFAULT_IGNORED_FIELDS),
+            interactions: Mutex::new(file.interactions),
+        })
+    }
+
+    /// Blank these JSON fields of request bodies before hashing them, in place of
+    /// `DEFAULT_IGNORED_FIELDS`, e.g. to add a field that holds the time. Recording and replay
+    /// have to ignore the same fields.
+    pub fn ignore_fields(mut self, fields: &[&str]) -> Self {
+        self.ignored_fields = to_strings(fields);
+        self
+    }
+
+    /// Write what was recorded to the cassette's path. Does nothing when replaying.
+    pub async fn save(&self) -> Result<(), BiskyError> {
+        if self.mode == Mode::Replay {
+            return Ok(());
+        }
+        let file = CassetteFile {
+            interactions: self.interactions.lock().clone(),
+        };
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&file)?).await?;
+        Ok(())
+    }
+
+    /// Whether every recorded response was given out, to check a replay made all the requests
+    /// it was expected to
+    pub fn is_exhausted(&self) -> bool {
+        self.interactions.lock().iter().all(|i| i.used)
+    }
+
+    fn recorded_request(&self, request: &reqwest::Request) -> RecordedRequest {
+        let body_sha256 = request
+            .body()
+            .and_then(reqwest::Body::as_bytes)
+            .map(|body| {
+                let hashed = match serde_json::from_slice::<Value>(body) {
+                    Ok(mut json) => {
+                        blank_fields(&mut json, &self.ignored_fields, Value::Null);
+                        serde_json::to_vec(&json).unwrap()
+                    }
+                    Err(_) => body.to_vec(),
+                };
+                format!("{:x}", Sha256::digest(hashed))
+            });
+        RecordedRequest {
+            method: request.method().to_string(),
+            path: request.url().path().to_string(),
+            query: request.url().query().map(str::to_string),
+            body_sha256,
+        }
+    }
+
+    async fn record_response(
+        &self,
+        recorded: RecordedRequest,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, BiskyError> {
+        let status = response.status();
+        let all_headers = response.headers().clone();
+        let headers = all_headers
+            .iter()
+            .filter(|(name, _)| {
+                KEPT_HEADERS.contains(&name.as_str()) || name.as_str().starts_with("ratelimit-")
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.into())))
+            .collect::<BTreeMap<_, _>>();
+        let original = response.bytes().await?.to_vec();
+
+        // The client gets the response as it was, tokens and all
+        let mut built = http::Response::builder().status(status);
+        if let Some(built_headers) = built.headers_mut() {
+            *built_headers = all_headers;
+        }
+        let built = built
+            .body(original.clone())
+            .map_err(|e| BiskyError::UnexpectedResponse(e.to_string()))?;
+
+        let body = match serde_json::from_slice::<Value>(&original) {
+            Ok(mut json) => {
+                blank_fields(&mut json, REDACTED_FIELDS, "REDACTED".into());
+                serde_json::to_vec(&json)?
+            }
+            Err(_) => original,
+        };
+        let (text, base64) = match String::from_utf8(body) {
+            Ok(text) => (Some(text), None),
+            Err(e) => (None, Some(BASE64.encode(e.into_bytes()))),
+        };
+        self.interactions.lock().push(Interaction {
+            request: recorded,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers,
+                body: text,
+                body_base64: base64,
+            },
+            used: false,
+        });
+        Ok(built.into())
+    }
+}
+
+#[async_trait::async_trait]
+impl XrpcTransport for Cassette {
+    async fn execute(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, BiskyError> {
+        let recorded = self.recorded_request(&request);
+        match self.mode {
+            Mode::Record => {
+                let response = http.execute(request).await?;
+                self.record_response(recorded, response).await
+            }
+            Mode::Replay => {
+                let mut interactions = self.interactions.lock();
+                let Some(interaction) = interactions
+                    .iter_mut()
+                    .find(|i| !i.used && i.request == recorded)
+                else {
+                    let query = recorded.query.map(|q| format!("?{q}")).unwrap_or_default();
+                    return Err(BiskyError::UnexpectedRequest(format!(
+                        "{} {}{query}",
+                        recorded.method, recorded.path
+                    )));
+                };
+                interaction.used = true;
+                build_response(&interaction.response)
+            }
+        }
+    }
+}
+
+fn build_response(recorded: &RecordedResponse) -> Result<reqwest::Response, BiskyError> {
+    let body = match (&recorded.body, &recorded.body_base64) {
+        (Some(text), _) => text.as_bytes().to_vec(),
+        (None, Some(base64)) => BASE64
+            .decode(base64)
+            .map_err(|e| BiskyError::UnexpectedResponse(e.to_string()))?,
+        (None, None) => Vec::new(),
+    };
+    let mut response = http::Response::builder().status(recorded.status);
+    for (name, value) in &recorded.headers {
+        response = response.header(name, value);
+    }
+    let response = response
+        .body(body)
+        .map_err(|e| BiskyError::UnexpectedResponse(e.to_string()))?;
+    Ok(response.into())
+}
+
+/// Set every field named in `fields`, at any depth, to `value`
+fn blank_fields(json: &mut Value, fields: &[impl AsRef<str>], value: Value) {
+    match json {
+        Value::Object(object) => {
+            for (key, field) in object.iter_mut() {
+                if fields.iter().any(|f| f.as_ref() == key) {
+                    *field = value.clone();
+                } else {
+                    blank_fields(field, fields, value.clone());
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                blank_fields(item, fields, value.clone());
+            }
+        }
+        _ => {}
+    }
+}
+
+fn to_strings(fields: &[&str]) -> Vec<String> {
+    fields.iter().map(|f| f.to_string()).collect()
+}
@@ -0,0 +1,176 @@
+//! Reading whole repos: the signed commit at the root of a repo export and the Merkle Search
+//! Tree under it, which maps each `<collection>/<rkey>` to the CID of its record.
+
+use crate::atproto::Client;
+use crate::car::{car_error, CarReader};
+use crate::dagcbor;
+use crate::errors::BiskyError;
+use cid::Cid;
+use futures::TryStreamExt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The signed commit a repo export is rooted at
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignedCommit {
+    pub did: String,
+    pub version: u64,
+    /// The root of the MST
+    pub data: Cid,
+    pub rev: String,
+    pub prev: Option<Cid>,
+    #[serde(with = "serde_bytes")]
+    pub sig: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct MstNode {
+    /// The subtree of keys before the first entry
+    pub(crate) l: Option<Cid>,
+    pub(crate) e: Vec<MstEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct MstEntry {
+    /// How many bytes of the previous entry's key this one shares
+    pub(crate) p: usize,
+    /// The rest of the key
+    #[serde(with = "serde_bytes")]
+    pub(crate) k: Vec<u8>,
+    /// The record
+    pub(crate) v: Cid,
+    /// The subtree of keys between this entry and the next
+    pub(crate) t: Option<Cid>,
+}
+
+/// A block of the repo's own structure, which unlike records is read as DAG-CBOR directly
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BiskyError> {
+    serde_ipld_dagcbor::from_slice(bytes).map_err(|e| car_error(e.to_string()))
+}
+
+fn block<'a>(blocks: &'a HashMap<Cid, Vec<u8>>, cid: &Cid) -> Result<&'a [u8], BiskyError> {
+    blocks
+        .get(cid)
+        .map(Vec::as_slice)
+        .ok_or_else(|| car_error(format!("Missing block {cid}")))
+}
+
+/// Every `<collection>/<rkey>` in the MST rooted at `root` with the CID of its record, in key
+/// order
+pub(crate) fn mst_entries(
+    blocks: &HashMap<Cid, Vec<u8>>,
+    root: &Cid,
+) -> Result<Vec<(String, Cid)>, BiskyError> {
+    fn walk(
+        blocks: &HashMap<Cid, Vec<u8>>,
+        cid: &Cid,
+        entries: &mut Vec<(String, Cid)>,
+    ) -> Result<(), BiskyError> {
+        let node: MstNode = decode(block(blocks, cid)?)?;
+        if let Some(left) = &node.l {
+            walk(blocks, left, entries)?;
+        }
+        let mut key = Vec::new();
+        for entry in node.e {
+            if entry.p > key.len() {
+                return Err(car_error(format!("Invalid MST key prefix in {cid}")));
+            }
+            key.truncate(entry.p);
+            key.extend_from_slice(&entry.k);
+            let path = String::from_utf8(key.clone())
+                .map_err(|_| car_error(format!("Invalid MST key in {cid}")))?;
+            entries.push((path, entry.v));
+            if let Some(tree) = &entry.t {
+                walk(blocks, tree, entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut entries = Vec::new();
+    walk(blocks, root, &mut entries)?;
+    Ok(entries)
+}
+
+/// A record of a repo export
+#[derive(Debug)]
+pub struct RepoRecord {
+    pub collection: String,
+    pub rkey: String,
+    pub cid: Cid,
+    /// The record's DAG-CBOR bytes
+    pub bytes: Vec<u8>,
+}
+
+impl RepoRecord {
+    /// The record as `T`, which is `serde_json::Value` or a record type such as `Post`
+    pub fn value<T: DeserializeOwned>(&self) -> Result<T, BiskyError> {
+        dagcbor::from_slice(&self.bytes)
+    }
+}
+
+/// The commit `blocks` is rooted at and every record under it, in key order
+pub fn repo_records(
+    root: &Cid,
+    blocks: &HashMap<Cid, Vec<u8>>,
+) -> Result<(SignedCommit, Vec<RepoRecord>), BiskyError> {
+    let commit: SignedCommit = decode(block(blocks, root)?)?;
+    let records = mst_entries(blocks, &commit.data)?
+        .into_iter()
+        .map(|(path, cid)| {
+            let (collection, rkey) = path
+                .split_once('/')
+                .ok_or_else(|| car_error(format!("Invalid repo path {path}")))?;
+            Ok(RepoRecord {
+                collection: collection.to_string(),
+                rkey: rkey.to_string(),
+                bytes: block(blocks, &cid)?.to_vec(),
+                cid,
+            })
+        })
+        .collect::<Result<_, BiskyError>>()?;
+    Ok((commit, records))
+}
+
+impl Client {
+    /// Download the repo of `did` and call `handler` with each of its records, returning the
+    /// revision of the commit the export was taken at.
+    ///
+    /// To bootstrap an index, start the repo stream first and hold its events for `did`, then
+    /// backfill and skip the held and later commits whose `rev` is not after the one returned.
+    /// The export is read as it downloads, but the blocks are held until the end since the
+    /// records can only be found from the commit once all of them are in.
+    pub async fn sync_backfill_repo(
+        &mut self,
+        did: &str,
+        mut handler: impl FnMut(RepoRecord),
+    ) -> Result<String, BiskyError> {
+        let response = self
+            .xrpc_get_response("com.atproto.sync.getRepo", &[("did", did)])
+            .await?;
+        let body = tokio_util::io::StreamReader::new(
+            response.bytes_stream().map_err(std::io::Error::other),
+        );
+
+        let reader = CarReader::new(body).await?;
+        let root = *reader
+            .header()
+            .roots
+            .first()
+            .ok_or_else(|| car_error("Repo export has no root"))?;
+        let blocks = reader.read_all().await?;
+
+        let (commit, records) = repo_records(&root, &blocks)?;
+        if commit.did != did {
+            return Err(car_error(format!(
+                "Repo export of {did} belongs to {}",
+                commit.did
+            )));
+        }
+        for record in records {
+            handler(record);
+        }
+        Ok(commit.rev)
+    }
+}
@@ -0,0 +1,451 @@
+//! When a failed XRPC call is tried again. Set a `RetryPolicy` on the client with
+//! `ClientBuilder::retry_policy`; refreshing an expired session and trying once more is done
+//! whatever the policy.
+
+use crate::atproto::{is_rate_limited, is_transient, RateLimitInfo};
+use crate::errors::{ApiErrorKind, BiskyError};
+use std::time::Duration;
+
+/// What to do about a failed call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Try again once this long has passed
+    Retry(Duration),
+    GiveUp,
+}
+
+/// A failed try of an XRPC call
+#[derive(Debug)]
+pub struct Failure<'a> {
+    /// The XRPC method, e.g. `app.bsky.feed.getTimeline`
+    pub nsid: &'a str,
+    /// Whether the call is a procedure, which may have taken effect even though it failed
+    pub procedure: bool,
+    /// How many tries have failed, 1 after the first
+    pub attempt: u32,
+    pub error: &'a BiskyError,
+}
+
+impl Failure<'_> {
+    /// The HTTP status the call failed with, if there was a response
+    pub fn status(&self) -> Option<u16> {
+        match self.error {
+            BiskyError::ApiError(e) => e.status(),
+            BiskyError::ReqwestError(e) => e.status().map(|status| status.as_u16()),
+            BiskyError::RateLimited(_) => Some(429),
+            _ => None,
+        }
+    }
+
+    pub fn api_error_kind(&self) -> Option<ApiErrorKind> {
+        match self.error {
+            BiskyError::ApiError(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+
+    /// The rate limit the failed response reported
+    pub fn rate_limit(&self) -> Option<RateLimitInfo> {
+        match self.error {
+            BiskyError::RateLimited(info) => *info,
+            BiskyError::ApiError(e) => e.context.as_ref().and_then(|context| context.rate_limit),
+            _ => None,
+        }
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        is_rate_limited(self.error)
+    }
+
+    /// Whether trying again might work and can't do the call twice: a query that hit a rate
+    /// limit, a server error or a dropped connection, or a procedure that was rate limited or
+    /// never reached the server
+    pub fn is_safe_to_retry(&self) -> bool {
+        if !self.procedure {
+            return is_transient(self.error);
+        }
+        self.is_rate_limited()
+            || matches!(self.error, BiskyError::ReqwestError(e) if e.is_connect())
+    }
+}
+
+/// Decides whether a failed XRPC call is tried again, and when
+pub trait RetryPolicy: Send + Sync {
+    fn decide(&self, failure: &Failure<'_>) -> RetryDecision;
+}
+
+/// Fail at once
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn decide(&self, _: &Failure<'_>) -> RetryDecision {
+        RetryDecision::GiveUp
+    }
+}
+
+/// Retry what is safe to retry, waiting twice as long each time
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    /// The wait before the first retry
+    pub first_delay: Duration,
+    pub max_delay: Duration,
+    pub max_retries: u32,
+    /// Retry only calls that were rate limited, not server errors or dropped connections
+    pub rate_limits_only: bool,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            first_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_retries: 6,
+            rate_limits_only: false,
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// What paginated walks and bulk procedures do when the client has no policy
+    pub(crate) fn rate_limits() -> Self {
+        Self {
+            rate_limits_only: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn decide(&self, failure: &Failure<'_>) -> RetryDecision {
+        let retryable = match self.rate_limits_only {
+            true => failure.is_rate_limited(),
+            false => failure.is_safe_to_retry(),
+        };
+        if !retryable || failure.attempt > self.max_retries {
+            return RetryDecision::GiveUp;
+        }
+        let delay = self
+            .first_delay
+            .saturating_mul(2u32.saturating_pow(failure.attempt - 1));
+        RetryDecision::Retry(delay.min(self.max_delay))
+    }
+}
+
+/// Wait out a rate limit until the PDS says it resets, and back off from other failures
+#[derive(Debug, Clone)]
+pub struct RateLimitAware {
+    /// Give up rather than wait longer than this for a rate limit to reset
+    pub max_wait: Duration,
+    /// For failures other than rate limits, and rate limits with no reset time
+    pub backoff: ExponentialBackoff,
+}
+
+impl Default for RateLimitAware {
+    fn default() -> Self {
+        Self {
+            max_wait: Duration::from_secs(300),
+            backoff: ExponentialBackoff::default(),
+        }
+    }
+}
+
+impl RetryPolicy for RateLimitAware {
+    fn decide(&self, failure: &Failure<'_>) -> RetryDecision {
+        if failure.attempt > self.backoff.max_retries {
+            return RetryDecision::GiveUp;
+        }
+        match failure.rate_limit() {
+            Some(info) if failure.is_rate_limited() => {
+                // A second past the reset, for clocks that disagree a little
+                let wait = (info.reset - chrono::Utc::now().timestamp()).max(0) as u64 + 1;
+                let wait = Duration::from_secs(wait);
+                match wait <= self.max_wait {
+                    true => RetryDecision::Retry(wait),
+                    false => RetryDecision::GiveUp,
+                }
+            }
+            _ => self.backoff.decide(failure),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ApiError, XrpcContext};
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn rate_limit(reset_in: i64) -> RateLimitInfo {
+        RateLimitInfo {
+            limit: 3000,
+            remaining: 0,
+            reset: chrono::Utc::now().timestamp() + reset_in,
+            policy: None,
+        }
+    }
+
+    fn api_error(status: u16, error: &str, rate_limit: Option<RateLimitInfo>) -> BiskyError {
+        BiskyError::ApiError(ApiError {
+            error: error.to_string(),
+            message: String::new(),
+            context: Some(Box::new(XrpcContext {
+                nsid: "app.bsky.feed.getTimeline".to_string(),
+                status,
+                request_id: None,
+                rate_limit,
+            })),
+        })
+    }
+
+    /// What `policy` decides for `errors` in turn, as the tries of one call
+    fn decisions(
+        policy: &dyn RetryPolicy,
+        procedure: bool,
+        errors: &[BiskyError],
+    ) -> Vec<RetryDecision> {
+        errors
+            .iter()
+            .zip(1..)
+            .map(|(error, attempt)| {
+                policy.decide(&Failure {
+                    nsid: "app.bsky.feed.getTimeline",
+                    procedure,
+                    attempt,
+                    error,
+                })
+            })
+            .collect()
+    }
+
+    fn retry(secs: u64) -> RetryDecision {
+        RetryDecision::Retry(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn failures() {
+        let limit = rate_limit(10);
+        let error = api_error(429, "RateLimitExceeded", Some(limit));
+        let failure = Failure {
+            nsid: "app.bsky.feed.getTimeline",
+            procedure: false,
+            attempt: 1,
+            error: &error,
+        };
+        assert_eq!(failure.status(), Some(429));
+        assert_eq!(
+            failure.api_error_kind(),
+            Some(ApiErrorKind::RateLimitExceeded)
+        );
+        assert_eq!(failure.rate_limit(), Some(limit));
+        assert!(failure.is_rate_limited());
+        assert!(failure.is_safe_to_retry());
+
+        let error = BiskyError::RateLimited(None);
+        let failure = Failure {
+            error: &error,
+            ..failure
+        };
+        assert_eq!(failure.status(), Some(429));
+        assert_eq!(failure.api_error_kind(), None);
+        assert_eq!(failure.rate_limit(), None);
+        assert!(failure.is_rate_limited());
+
+        let error = BiskyError::MissingSession;
+        let failure = Failure {
+            error: &error,
+            ..failure
+        };
+        assert_eq!(failure.status(), None);
+        assert!(!failure.is_safe_to_retry());
+    }
+
+    #[test]
+    fn no_retry() {
+        let errors = [
+            BiskyError::RateLimited(None),
+            api_error(503, "UpstreamFailure", None),
+        ];
+        assert_eq!(
+            decisions(&NoRetry, false, &errors),
+            [RetryDecision::GiveUp; 2]
+        );
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = ExponentialBackoff::default();
+        let outage = [(); 8].map(|_| api_error(503, "UpstreamFailure", None));
+        assert_eq!(
+            decisions(&policy, false, &outage),
+            [
+                retry(1),
+                retry(2),
+                retry(4),
+                retry(8),
+                retry(16),
+                retry(32),
+                RetryDecision::GiveUp,
+                RetryDecision::GiveUp,
+            ]
+        );
+
+        // Capped at the longest delay
+        let policy = ExponentialBackoff {
+            first_delay: 10 * SECOND,
+            max_delay: 30 * SECOND,
+            max_retries: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            decisions(&policy, false, &outage[..5]),
+            [
+                retry(10),
+                retry(20),
+                retry(30),
+                retry(30),
+                RetryDecision::GiveUp
+            ]
+        );
+
+        // However many tries
+        let error = BiskyError::RateLimited(None);
+        let policy = ExponentialBackoff {
+            max_retries: u32::MAX,
+            ..Default::default()
+        };
+        for attempt in [32, 64, u32::MAX] {
+            let failure = Failure {
+                nsid: "app.bsky.feed.getTimeline",
+                procedure: false,
+                attempt,
+                error: &error,
+            };
+            assert_eq!(policy.decide(&failure), retry(60));
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_retries_what_is_safe() {
+        let policy = ExponentialBackoff::default();
+        let rate_limited = [api_error(429, "RateLimitExceeded", None)];
+        let server_error = [api_error(500, "InternalServerError", None)];
+        let client_error = [api_error(400, "InvalidRequest", None)];
+        let expired = [api_error(400, "ExpiredToken", None)];
+
+        // Queries are retried on rate limits and server errors
+        assert_eq!(decisions(&policy, false, &rate_limited), [retry(1)]);
+        assert_eq!(decisions(&policy, false, &server_error), [retry(1)]);
+        assert_eq!(
+            decisions(&policy, false, &client_error),
+            [RetryDecision::GiveUp]
+        );
+        // Refreshing is done apart from the policy
+        assert_eq!(decisions(&policy, false, &expired), [RetryDecision::GiveUp]);
+
+        // Procedures that may have taken effect aren't
+        assert_eq!(decisions(&policy, true, &rate_limited), [retry(1)]);
+        assert_eq!(
+            decisions(&policy, true, &server_error),
+            [RetryDecision::GiveUp]
+        );
+
+        let policy = ExponentialBackoff::rate_limits();
+        assert_eq!(decisions(&policy, false, &rate_limited), [retry(1)]);
+        assert_eq!(
+            decisions(&policy, false, &server_error),
+            [RetryDecision::GiveUp]
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_errors() {
+        // Nothing listens on the discard port
+        let error = BiskyError::from(reqwest::get("http://127.0.0.1:9").await.unwrap_err());
+        assert!(matches!(&error, BiskyError::ReqwestError(e) if e.is_connect()));
+
+        // A procedure that never reached the server is safe to send again
+        let policy = ExponentialBackoff::default();
+        let errors = [error];
+        assert_eq!(decisions(&policy, false, &errors), [retry(1)]);
+        assert_eq!(decisions(&policy, true, &errors), [retry(1)]);
+        assert_eq!(
+            decisions(&ExponentialBackoff::rate_limits(), true, &errors),
+            [RetryDecision::GiveUp]
+        );
+    }
+
+    #[test]
+    fn rate_limit_aware() {
+        let policy = RateLimitAware::default();
+
+        // Until a second past the reset, for which a second may pass while deciding
+        let [decision] = decisions(
+            &policy,
+            false,
+            &[api_error(429, "RateLimitExceeded", Some(rate_limit(10)))],
+        )[..] else {
+            unreachable!()
+        };
+        assert!(
+            decision == retry(11) || decision == retry(10),
+            "{decision:?}"
+        );
+
+        // A reset that has passed, or is too far off
+        assert_eq!(
+            decisions(
+                &policy,
+                true,
+                &[BiskyError::RateLimited(Some(rate_limit(-5)))]
+            ),
+            [retry(1)]
+        );
+        assert_eq!(
+            decisions(
+                &policy,
+                false,
+                &[BiskyError::RateLimited(Some(rate_limit(600)))]
+            ),
+            [RetryDecision::GiveUp]
+        );
+        assert_eq!(
+            decisions(
+                &policy,
+                false,
+                &[BiskyError::RateLimited(Some(rate_limit(299)))]
+            ),
+            [retry(300)]
+        );
+
+        // Without a reset, and for other failures, it backs off
+        let errors = [
+            BiskyError::RateLimited(None),
+            api_error(502, "BadGateway", Some(rate_limit(100))),
+            api_error(429, "RateLimitExceeded", None),
+        ];
+        assert_eq!(
+            decisions(&policy, false, &errors),
+            [retry(1), retry(2), retry(4)]
+        );
+        assert_eq!(
+            decisions(&policy, false, &[api_error(404, "NotFound", None)]),
+            [RetryDecision::GiveUp]
+        );
+
+        // Only so many times
+        let policy = RateLimitAware {
+            backoff: ExponentialBackoff {
+                max_retries: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let errors = [(); 3].map(|_| BiskyError::RateLimited(Some(rate_limit(1))));
+        assert_eq!(
+            decisions(&policy, false, &errors)[2..],
+            [RetryDecision::GiveUp]
+        );
+    }
+}
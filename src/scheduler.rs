@@ -0,0 +1,381 @@
+//! A queue of posts to publish at set times, kept in a `Storage` so that it survives restarts.
+
+use crate::bsky::BskyClient;
+use crate::errors::BiskyError;
+use crate::lexicon::app::bsky::feed::Post;
+use crate::lexicon::com::atproto::repo::{Blob, BlobOutput, CreateRecordOutput};
+use crate::retry::{ExponentialBackoff, Failure, RetryDecision, RetryPolicy};
+use crate::storage::Storage;
+use crate::types::Datetime;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::lock::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long the PDS is taken to keep a blob that no record uses. Blobs uploaded through the
+/// queue longer ago than this are uploaded again before their post goes out.
+const BLOB_UPLOAD_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The longest `run` sleeps before looking at the clock again, so that a clock set forward or
+/// back holds a post up by no more than this
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// What a `PostQueue` keeps in its storage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostQueueState {
+    next_id: u64,
+    items: Vec<QueuedPost>,
+    /// The blobs uploaded through the queue, by CID, kept while a pending post uses them
+    blobs: BTreeMap<String, QueuedBlob>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedPost {
+    pub id: u64,
+    /// When the post is due to go out
+    pub at: Datetime,
+    pub status: PostStatus,
+    /// The post record
+    post: Value,
+}
+
+impl QueuedPost {
+    /// The post, with the `created_at` it was queued with
+    pub fn post(&self) -> Result<Post, BiskyError> {
+        Ok(serde_json::from_value(self.post.clone())?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum PostStatus {
+    Pending,
+    Posted {
+        uri: String,
+        cid: String,
+        at: Datetime,
+    },
+    /// Given up on, with the error of the last try
+    Failed {
+        error: String,
+        at: Datetime,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueuedBlob {
+    mime_type: String,
+    /// The blob itself, in base64
+    data: String,
+    uploaded: Datetime,
+}
+
+/// Posts queued to go out at set times, published one at a time by `run`.
+///
+/// Every change is written to the storage before it is made, so a restarted queue picks up
+/// where it left off. A post whose creation fails is tried again as the retry policy decides,
+/// which by default is only when it was rate limited or never reached the PDS, since a post
+/// that failed otherwise may have been made. Posts and their statuses stay in the queue until
+/// removed.
+pub struct PostQueue<'a> {
+    client: Arc<Mutex<BskyClient>>,
+    storage: &'a dyn Storage<PostQueueState, Error = BiskyError>,
+    state: Mutex<PostQueueState>,
+    /// Woken when a post is queued, which may be due before the one `run` is waiting for
+    queued: Notify,
+    retry_policy: Arc<dyn RetryPolicy>,
+}
+
+impl<'a> PostQueue<'a> {
+    /// The queue kept in `storage`, which starts empty if nothing is stored yet
+    pub async fn new(
+        client: impl Into<BskyClient>,
+        storage: &'a dyn Storage<PostQueueState, Error = BiskyError>,
+    ) -> Result<PostQueue<'a>, BiskyError> {
+        let state = match storage.get().await {
+            Ok(state) => state,
+            Err(BiskyError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                PostQueueState::default()
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            client: Arc::new(Mutex::new(client.into())),
+            storage,
+            state: Mutex::new(state),
+            queued: Notify::new(),
+            retry_policy: Arc::new(ExponentialBackoff::default()),
+        })
+    }
+
+    pub fn retry_policy(mut self, retry_policy: Arc<dyn RetryPolicy>) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// The queue's client, shared with `run`
+    pub fn client(&self) -> Arc<Mutex<BskyClient>> {
+        self.client.clone()
+    }
+
+    /// Queue `post` to go out at `at`, returning its id. Its `created_at` is set to the time it
+    /// goes out.
+    pub async fn enqueue(&self, post: Post, at: Datetime) -> Result<u64, BiskyError> {
+        post.validate()?;
+        let post = serde_json::to_value(&post)?;
+        let id = self
+            .update(|state| {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.items.push(QueuedPost {
+                    id,
+                    at,
+                    status: PostStatus::Pending,
+                    post,
+                });
+                id
+            })
+            .await?;
+        self.queued.notify_one();
+        Ok(id)
+    }
+
+    /// Queue `post` to go out `gap` after the last pending post, or now if none are pending
+    pub async fn enqueue_spaced(&self, post: Post, gap: Duration) -> Result<u64, BiskyError> {
+        let gap = chrono::Duration::from_std(gap)
+            .map_err(|e| BiskyError::InvalidDatetime(e.to_string()))?;
+        let last = self
+            .state
+            .lock()
+            .await
+            .items
+            .iter()
+            .filter(|item| item.status == PostStatus::Pending)
+            .map(|item| item.at)
+            .max();
+        let now = Datetime::now();
+        let at = match last {
+            Some(last) => Datetime::from(*last + gap).max(now),
+            None => now,
+        };
+        self.enqueue(post, at).await
+    }
+
+    /// Upload a blob for a queued post to use, keeping it so that it can be uploaded again if the
+    /// PDS drops it before the post goes out
+    pub async fn upload_blob(&self, blob: &[u8], mime_type: &str) -> Result<Blob, BiskyError> {
+        let output: BlobOutput = self
+            .client
+            .lock()
+            .await
+            .repo_upload_blob(blob, mime_type)
+            .await?;
+        let queued = QueuedBlob {
+            mime_type: mime_type.to_string(),
+            data: BASE64.encode(blob),
+            uploaded: Datetime::now(),
+        };
+        let cid = output.blob.cid().to_string();
+        self.update(|state| state.blobs.insert(cid, queued)).await?;
+        Ok(output.blob)
+    }
+
+    pub async fn status(&self, id: u64) -> Option<PostStatus> {
+        let state = self.state.lock().await;
+        let item = state.items.iter().find(|item| item.id == id)?;
+        Some(item.status.clone())
+    }
+
+    /// Everything in the queue, in the order it was queued
+    pub async fn items(&self) -> Vec<QueuedPost> {
+        self.state.lock().await.items.clone()
+    }
+
+    /// Take a post off the queue, which cancels it if it is pending. Returns false if there was
+    /// no post with this id.
+    pub async fn remove(&self, id: u64) -> Result<bool, BiskyError> {
+        self.update(|state| {
+            let len = state.items.len();
+            state.items.retain(|item| item.id != id);
+            prune_blobs(state);
+            state.items.len() != len
+        })
+        .await
+    }
+
+    /// Publish posts as they fall due. Only returns if the storage fails.
+    pub async fn run(&self) -> Result<(), BiskyError> {
+        loop {
+            let next = {
+                let state = self.state.lock().await;
+                state
+                    .items
+                    .iter()
+                    .filter(|item| item.status == PostStatus::Pending)
+                    .min_by_key(|item| (item.at, item.id))
+                    .cloned()
+            };
+            let Some(item) = next else {
+                self.queued.notified().await;
+                continue;
+            };
+
+            // Negative once it is due
+            let wait = (*item.at - chrono::Utc::now()).to_std().unwrap_or_default();
+            if !wait.is_zero() {
+                let _ = tokio::time::timeout(wait.min(MAX_SLEEP), self.queued.notified()).await;
+                continue;
+            }
+
+            let status = self.publish(&item).await?;
+            self.update(|state| {
+                // Unless it was removed meanwhile
+                if let Some(queued) = state.items.iter_mut().find(|queued| queued.id == item.id) {
+                    queued.status = status;
+                }
+                prune_blobs(state);
+            })
+            .await?;
+        }
+    }
+
+    /// Try to post `item` until it is made or the retry policy gives up on it
+    async fn publish(&self, item: &QueuedPost) -> Result<PostStatus, BiskyError> {
+        let mut attempt = 0;
+        let mut reuploaded = false;
+        loop {
+            let error = match self.try_publish(item, reuploaded).await? {
+                Ok(created) => {
+                    return Ok(PostStatus::Posted {
+                        uri: created.uri,
+                        cid: created.cid,
+                        at: Datetime::now(),
+                    })
+                }
+                Err(error) => error,
+            };
+            // The PDS may have dropped a blob sooner than expected
+            if !reuploaded && is_missing_blob(&error) {
+                reuploaded = true;
+                continue;
+            }
+            attempt += 1;
+            let failure = Failure {
+                nsid: "com.atproto.repo.createRecord",
+                procedure: true,
+                attempt,
+                error: &error,
+            };
+            match self.retry_policy.decide(&failure) {
+                RetryDecision::Retry(after) => tokio::time::sleep(after).await,
+                RetryDecision::GiveUp => {
+                    return Ok(PostStatus::Failed {
+                        error: error.to_string(),
+                        at: Datetime::now(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Upload the post's blobs again if they may have expired, or all of them with `reupload`,
+    /// then create the post. The outer error is a storage failure, the inner one a failure to
+    /// post.
+    async fn try_publish(
+        &self,
+        item: &QueuedPost,
+        reupload: bool,
+    ) -> Result<Result<CreateRecordOutput, BiskyError>, BiskyError> {
+        let mut post: Post = serde_json::from_value(item.post.clone())?;
+        post.created_at = Datetime::now();
+
+        let expired = {
+            let state = self.state.lock().await;
+            let mut cids = BTreeSet::new();
+            blob_cids(&item.post, &mut cids);
+            cids.into_iter()
+                .filter_map(|cid| Some((state.blobs.get(&cid)?.clone(), cid)))
+                .filter(|(blob, _)| reupload || is_expired(blob))
+                .collect::<Vec<_>>()
+        };
+
+        let mut client = self.client.lock().await;
+        for (blob, cid) in expired {
+            let data = BASE64
+                .decode(&blob.data)
+                .map_err(|e| BiskyError::UnexpectedResponse(e.to_string()))?;
+            if let Err(e) = client
+                .repo_upload_blob::<BlobOutput>(&data, &blob.mime_type)
+                .await
+            {
+                return Ok(Err(e));
+            }
+            self.update(|state| {
+                if let Some(blob) = state.blobs.get_mut(&cid) {
+                    blob.uploaded = Datetime::now();
+                }
+            })
+            .await?;
+        }
+        Ok(client.bsky_create_post(&post).await)
+    }
+
+    /// Make `change` to the state once it is stored
+    async fn update<T>(
+        &self,
+        change: impl FnOnce(&mut PostQueueState) -> T,
+    ) -> Result<T, BiskyError> {
+        let mut state = self.state.lock().await;
+        let mut changed = state.clone();
+        let output = change(&mut changed);
+        self.storage.set(Some(&changed)).await?;
+        *state = changed;
+        Ok(output)
+    }
+}
+
+/// Drop the blobs no pending post uses, other than those uploaded recently enough to be for a
+/// post that isn't queued yet
+fn prune_blobs(state: &mut PostQueueState) {
+    let mut used = BTreeSet::new();
+    for item in &state.items {
+        if item.status == PostStatus::Pending {
+            blob_cids(&item.post, &mut used);
+        }
+    }
+    state
+        .blobs
+        .retain(|cid, blob| used.contains(cid) || !is_expired(blob));
+}
+
+/// The CIDs of the blobs `record` refers to
+fn blob_cids(record: &Value, cids: &mut BTreeSet<String>) {
+    cids.extend(
+        Blob::find_all(record)
+            .iter()
+            .map(|blob| blob.cid().to_string()),
+    );
+}
+
+fn is_expired(blob: &QueuedBlob) -> bool {
+    (*Datetime::now() - *blob.uploaded)
+        .to_std()
+        .is_ok_and(|age| age > BLOB_UPLOAD_LIFETIME)
+}
+
+fn is_missing_blob(error: &BiskyError) -> bool {
+    match error {
+        BiskyError::ApiError(e) => {
+            e.error == "BlobNotFound" || e.message.to_ascii_lowercase().contains("blob not found")
+        }
+        _ => false,
+    }
+}
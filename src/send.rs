@@ -0,0 +1,457 @@
+//! Compile time checks that the futures of the public async functions are `Send`, so that
+//! they can be spawned on a multi-threaded runtime. Nothing here is ever called.
+
+#![allow(dead_code)]
+
+use crate::atproto::{Client, ClientBuilder, RecordStream, UserSession};
+use crate::migration::{Migration, MigrationProgress};
+use crate::storage::File;
+use crate::types::AtUri;
+use crate::{identity, sync};
+
+#[cfg(feature = "admin")]
+use crate::admin::AdminClient;
+#[cfg(feature = "bsky")]
+use crate::bluesky::{BlueskyMe, BlueskyUser};
+#[cfg(feature = "bsky")]
+use crate::bot::{MentionBot, MentionContext};
+#[cfg(feature = "bsky")]
+use crate::bsky::{BskyClient, NotificationStream};
+#[cfg(feature = "firehose")]
+use crate::car::{self, CarReader};
+#[cfg(feature = "firehose")]
+use crate::errors::BiskyError;
+#[cfg(feature = "firehose")]
+use crate::firehose::FirehoseHub;
+#[cfg(feature = "bsky")]
+use crate::lexicon::app::bsky::actor::{Preferences, ProfileUpdate};
+#[cfg(any(feature = "image", feature = "link-preview"))]
+use crate::lexicon::app::bsky::feed::PostBuilder;
+#[cfg(feature = "firehose")]
+use crate::lexicon::com::atproto::sync::RepoStreamEvent;
+#[cfg(feature = "firehose")]
+use crate::repo::RepoRecord;
+#[cfg(feature = "scheduler")]
+use crate::scheduler::PostQueue;
+#[cfg(feature = "verify")]
+use crate::verify;
+#[cfg(feature = "firehose")]
+use futures::stream::BoxStream;
+
+fn assert_send<T: Send>(_: T) {}
+
+fn any<T>() -> T {
+    unimplemented!()
+}
+
+fn atproto() {
+    assert_send(any::<&ClientBuilder>().login(any(), any()));
+    assert_send(
+        any::<&mut ClientBuilder>().session_from_storage(any::<File<'static, UserSession>>()),
+    );
+    assert_send(any::<Client>());
+    assert_send(Client::from_session(any::<&str>(), any(), any()));
+    assert_send(any::<&mut Client>().update_session(any()));
+    assert_send(any::<&mut Client>().batched(
+        any(),
+        any::<Vec<AtUri>>(),
+        async |client: &mut Client, uri: AtUri| client.strong_ref_for(&uri).await,
+    ));
+    assert_send(any::<&mut Client>().chunked_query::<serde_json::Value>(
+        any(),
+        any(),
+        any(),
+        any(),
+        any(),
+        any(),
+    ));
+    assert_send(any::<&mut Client>().login(any::<&str>(), any(), any()));
+    assert_send(any::<RecordStream<'_, serde_json::Value>>());
+    assert_send(any::<RecordStream<'_, serde_json::Value>>().next());
+    assert_send(any::<&mut Client>().repo_get_record::<serde_json::Value>(any(), any(), any()));
+    assert_send(any::<&mut Client>().strong_ref_for(any()));
+    assert_send(any::<&mut Client>().repo_list_records::<serde_json::Value>(
+        any(),
+        any(),
+        any(),
+        any(),
+        any(),
+    ));
+    assert_send(
+        any::<&mut Client>().repo_create_record::<serde_json::Value, serde_json::Value>(
+            any(),
+            any(),
+            any(),
+        ),
+    );
+    assert_send(
+        any::<&mut Client>().repo_create_record_with_rkey::<serde_json::Value, serde_json::Value>(
+            any(),
+            any(),
+            any(),
+            any(),
+        ),
+    );
+    assert_send(
+        any::<&mut Client>().repo_put_record::<serde_json::Value, serde_json::Value>(
+            any(),
+            any(),
+            any(),
+            any(),
+            any(),
+        ),
+    );
+    assert_send(any::<&mut Client>().repo_apply_writes(any(), any()));
+    #[cfg(feature = "bsky")]
+    assert_send(any::<&mut Client>().repo_get_profile_record(any()));
+    #[cfg(feature = "bsky")]
+    assert_send(any::<&mut Client>().repo_put_profile_record(any(), any()));
+    #[cfg(feature = "bsky")]
+    assert_send(any::<&mut Client>().rehost_profile_blobs(any(), any(), any()));
+    assert_send(any::<&mut Client>().sync_get_blob(any(), any()));
+    assert_send(any::<&mut Client>().repo_delete_record(any(), any(), any()));
+    assert_send(any::<&mut Client>().repo_upload_blob::<serde_json::Value>(any(), any()));
+    assert_send(any::<&mut Client>().repo_stream_records::<serde_json::Value>(any(), any()));
+    assert_send(
+        any::<&mut Client>().repo_list_records_stream::<serde_json::Value>(any(), any(), any()),
+    );
+    assert_send(any::<&mut Client>().identity_resolve_handle(any()));
+    assert_send(any::<&mut Client>().identity_update_handle(any()));
+    assert_send(any::<&mut Client>().identity_request_plc_operation_signature());
+    assert_send(any::<&mut Client>().identity_get_recommended_did_credentials());
+    assert_send(any::<&mut Client>().identity_sign_plc_operation(any(), any()));
+    assert_send(any::<&mut Client>().identity_sign_pds_update(any(), any(), any()));
+    assert_send(any::<&mut Client>().identity_submit_plc_operation(any()));
+    assert_send(any::<&mut Client>().server_describe_server());
+    assert_send(any::<&mut Client>().server_get_service_auth(any(), any()));
+    assert_send(any::<&mut Client>().server_create_account(any(), any()));
+    assert_send(any::<&mut Client>().server_check_account_status());
+    assert_send(any::<&mut Client>().server_activate_account());
+    assert_send(any::<&mut Client>().server_deactivate_account());
+    assert_send(any::<&mut Client>().sync_get_repo(any()));
+    assert_send(any::<&mut Client>().sync_get_blocks(any(), any()));
+    assert_send(any::<&mut Client>().sync_list_blobs(any(), any(), any(), any()));
+    assert_send(any::<&mut Client>().sync_backfill_blobs(
+        any(),
+        any(),
+        any(),
+        any::<fn(&str, Vec<u8>)>(),
+    ));
+    assert_send(any::<&mut Client>().sync_list_repos(any(), any()));
+    assert_send(any::<&mut Client>().repo_import_repo(any()));
+    assert_send(any::<&mut Client>().label_query_labels(any(), any(), any(), any()));
+    assert_send(any::<&mut Client>().label_query_all_labels(any(), any()));
+    assert_send(any::<&mut Client>().moderation_create_report(any(), any(), any()));
+    assert_send(any::<&mut Client>().moderation_create_report_to(any(), any(), any(), any()));
+    assert_send(any::<&mut Client>().repo_describe_repo(any()));
+    assert_send(any::<&mut Client>().repo_list_missing_blobs(any(), any()));
+    #[cfg(feature = "ozone")]
+    assert_send(any::<&mut Client>().ozone_query_statuses(any(), any(), any(), any(), any()));
+    #[cfg(feature = "ozone")]
+    assert_send(any::<&mut Client>().ozone_query_events(any(), any(), any(), any(), any()));
+    #[cfg(feature = "ozone")]
+    assert_send(any::<&mut Client>().ozone_emit_event(any(), any(), any(), any()));
+    #[cfg(feature = "ozone")]
+    assert_send(any::<&mut Client>().ozone_get_record(any(), any(), any()));
+    #[cfg(feature = "ozone")]
+    assert_send(any::<&mut Client>().ozone_get_repo(any(), any()));
+}
+
+#[cfg(feature = "bsky")]
+fn bsky() {
+    assert_send(any::<NotificationStream<'_, serde_json::Value>>());
+    assert_send(any::<&mut BskyClient>().bsky_appeal_label(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_report_post(any(), any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_report_account(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_labeler_services(any(), any()));
+    assert_send(any::<&mut BskyClient>().resolve_bsky_url(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_notification_count(any()));
+    assert_send(
+        any::<&mut BskyClient>().bsky_list_notifications::<serde_json::Value>(any(), any(), any()),
+    );
+    assert_send(
+        any::<&mut BskyClient>().bsky_list_notifications_page::<serde_json::Value>(
+            any(),
+            any(),
+            any(),
+            any(),
+        ),
+    );
+    assert_send(any::<&mut BskyClient>().bsky_update_seen(any()));
+    assert_send(any::<&mut BskyClient>().bsky_put_notification_priority(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_notification_priority());
+    assert_send(any::<&mut BskyClient>().bsky_register_push(any(), any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_stream_notifications::<serde_json::Value>(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_likes(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_reposted_by(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_quotes(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_watch_post(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_follows(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_follows_page(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_followers(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_followers_page(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_list(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_lists(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_list_mutes(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_list_blocks(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_suggested_follows_by_actor(any()));
+    assert_send(any::<&mut BskyClient>().bsky_expand_follow_graph(any(), any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_starter_pack(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_starter_packs(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_actor_starter_packs(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_search_starter_packs(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_mute_actor(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unmute_actor(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_mutes(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_mute_thread(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unmute_thread(any()));
+    assert_send(any::<&mut BskyClient>().bsky_mute_list(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unmute_list(any()));
+    assert_send(any::<&mut BskyClient>().bsky_collect_followers(
+        any(),
+        any(),
+        any::<fn(usize, Option<&str>)>(),
+    ));
+    assert_send(any::<&mut BskyClient>().bsky_get_known_followers(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_relationships(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_profile(any()));
+    assert_send(any::<&mut BskyClient>().bsky_search_actors(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_search_actors_typeahead(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_suggestions(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_all_suggestions(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_preferences());
+    assert_send(any::<&mut BskyClient>().bsky_put_preferences(any()));
+    assert_send(any::<&mut BskyClient>().bsky_update_preferences(any::<fn(&mut Preferences)>()));
+    assert_send(any::<&mut BskyClient>().bsky_set_label_preference(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_set_adult_content(any()));
+    assert_send(any::<&mut BskyClient>().bsky_list_muted_words());
+    assert_send(any::<&mut BskyClient>().bsky_add_muted_word(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_remove_muted_word(any()));
+    assert_send(any::<&mut BskyClient>().bsky_hide_post(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unhide_post(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_thread_view_pref());
+    assert_send(any::<&mut BskyClient>().bsky_set_thread_view_pref(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_profiles(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_posts(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_post_thread(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_post_thread_depth(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_fetch_full_thread(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_unroll_thread(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_timeline(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_timeline_lazy(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_author_feed(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_feed(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_feed_lazy(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_feed_generator(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_feed_generators(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_actor_feeds(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_suggested_feeds(any(), any()));
+    #[cfg(feature = "unspecced")]
+    assert_send(any::<&mut BskyClient>().bsky_get_popular_feed_generators(any(), any(), any()));
+    #[cfg(feature = "unspecced")]
+    assert_send(any::<&mut BskyClient>().bsky_get_trending_topics(any()));
+    #[cfg(feature = "unspecced")]
+    assert_send(any::<&mut BskyClient>().bsky_get_tagged_suggestions());
+    #[cfg(feature = "unspecced")]
+    assert_send(any::<&mut BskyClient>().bsky_get_config());
+    assert_send(any::<&mut BskyClient>().bsky_get_actor_likes(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_list_feed(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_list_feed_lazy(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_send_interactions(any()));
+    assert_send(any::<&mut BskyClient>().bsky_create_post(any()));
+    assert_send(any::<&mut BskyClient>().bsky_create_post_idempotent(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_reply(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_reply_with(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_post_image(any(), any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_post_thread(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_like(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_unlike(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unlike_post(any()));
+    assert_send(any::<&mut BskyClient>().bsky_follow(any()));
+    assert_send(any::<&mut BskyClient>().bsky_create_follow(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unfollow(any()));
+    assert_send(any::<&mut BskyClient>().bsky_block(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unblock(any()));
+    assert_send(any::<&mut BskyClient>().bsky_get_blocks(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_create_starter_pack(any(), any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_block_list(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unblock_list(any()));
+    assert_send(any::<&mut BskyClient>().bsky_create_list(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_add_to_list(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_remove_from_list(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_repost(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_delete_repost(any()));
+    assert_send(any::<&mut BskyClient>().bsky_unrepost_post(any()));
+    assert_send(any::<&mut BskyClient>().bsky_delete_post(any()));
+    assert_send(any::<&mut BskyClient>().bsky_set_threadgate(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_clear_threadgate(any()));
+    assert_send(any::<&mut BskyClient>().bsky_detach_quote(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_disable_embedding(any()));
+    assert_send(any::<&mut BskyClient>().bsky_update_profile(any::<fn(&mut ProfileUpdate)>()));
+}
+
+#[cfg(feature = "chat")]
+fn chat() {
+    assert_send(any::<&mut BskyClient>().chat_list_convos(any(), any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().chat_get_convo(any()));
+    assert_send(any::<&mut BskyClient>().chat_get_convo_for_members(any()));
+    assert_send(any::<&mut BskyClient>().chat_get_messages(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().chat_get_all_messages(any()));
+    assert_send(any::<&mut BskyClient>().chat_get_log(any()));
+    assert_send(any::<&mut BskyClient>().chat_send_message(any(), any()));
+    assert_send(any::<&mut BskyClient>().chat_send_message_batch(any()));
+    assert_send(any::<&mut BskyClient>().chat_update_read(any(), any()));
+    assert_send(any::<&mut BskyClient>().chat_delete_message_for_self(any(), any()));
+    assert_send(any::<&mut BskyClient>().chat_mute_convo(any()));
+    assert_send(any::<&mut BskyClient>().chat_unmute_convo(any()));
+    assert_send(any::<&mut BskyClient>().chat_leave_convo(any()));
+}
+
+#[cfg(feature = "bsky")]
+fn bulk() {
+    assert_send(any::<&mut BskyClient>().bsky_bulk_follow(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_bulk_unfollow(any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_bulk_add_to_list(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_sync_list(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_follow_back(any()));
+    assert_send(any::<&mut Client>().repo_purge_collection(
+        any(),
+        any::<fn(&_) -> bool>(),
+        any(),
+        any(),
+    ));
+    assert_send(any::<&crate::bulk::RateBudget>().acquire(any()));
+    assert_send(any::<&mut BskyClient>().bsky_archive_posts(any(), any(), any()));
+    assert_send(any::<&mut BskyClient>().bsky_export_graph(any(), any(), any(), any::<fn(_, _)>()));
+}
+
+#[cfg(feature = "bsky")]
+fn bluesky() {
+    assert_send(any::<&mut BlueskyMe<'_>>().post(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().update_profile(any::<fn(&mut ProfileUpdate)>()));
+    assert_send(any::<&mut BlueskyMe<'_>>().post_image(any(), any(), any(), any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().post_thread(any(), any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().get_notification_count(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().list_notifications(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().stream_notifications());
+    assert_send(any::<&mut BlueskyMe<'_>>().update_seen());
+    assert_send(any::<&mut BlueskyMe<'_>>().upload_blob(any(), any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().get_feed(any(), any(), any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().get_post_thread(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().delete_post(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().like(any(), any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().unlike(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().follow(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().unfollow(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().block(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().unblock(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().mute(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().unmute(any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().repost(any(), any()));
+    assert_send(any::<&mut BlueskyMe<'_>>().unrepost(any()));
+    assert_send(any::<&mut BlueskyUser<'_>>().get_profile());
+    assert_send(any::<&mut BlueskyUser<'_>>().get_likes(any(), any(), any()));
+    assert_send(any::<&mut BlueskyUser<'_>>().get_follows(any(), any()));
+    assert_send(any::<&mut BlueskyUser<'_>>().get_followers(any(), any()));
+    assert_send(any::<&mut BlueskyUser<'_>>().get_feeds(any(), any()));
+    assert_send(any::<&mut BlueskyUser<'_>>().list_posts());
+    assert_send(any::<&mut BlueskyUser<'_>>().stream_posts());
+}
+
+#[cfg(feature = "bsky")]
+fn bot() {
+    assert_send(any::<&MentionContext>().reply(any()));
+    assert_send(any::<&MentionContext>().like());
+    assert_send(any::<MentionBot<'_>>().run());
+}
+
+#[cfg(feature = "image")]
+fn image() {
+    assert_send(any::<&mut PostBuilder>().add_image(any(), any(), any(), any()));
+}
+
+#[cfg(feature = "link-preview")]
+fn link_preview() {
+    assert_send(crate::link_preview::fetch(any::<&str>()));
+    assert_send(any::<&mut PostBuilder>().set_external_from_url(any(), any::<&str>()));
+}
+
+#[cfg(feature = "scheduler")]
+fn scheduler() {
+    assert_send(PostQueue::new(any::<BskyClient>(), any()));
+    assert_send(any::<&PostQueue<'_>>().enqueue(any(), any()));
+    assert_send(any::<&PostQueue<'_>>().enqueue_spaced(any(), any()));
+    assert_send(any::<&PostQueue<'_>>().upload_blob(any(), any()));
+    assert_send(any::<&PostQueue<'_>>().remove(any()));
+    assert_send(any::<&PostQueue<'_>>().run());
+}
+
+fn identity() {
+    assert_send(identity::resolve_did(any()));
+    assert_send(identity::plc_audit_log(any()));
+    assert_send(identity::resolve_handle(any::<&str>(), any()));
+    assert_send(identity::resolve_handle_http(any()));
+    #[cfg(feature = "dns")]
+    assert_send(identity::resolve_handle_dns(any()));
+    assert_send(identity::discover_did(any()));
+    assert_send(any::<&mut Client>().login_with_handle_discovery(any(), any()));
+    assert_send(any::<&mut Client>().verify_handle(any()));
+    assert_send(identity::check_identity(any()));
+}
+
+fn migration() {
+    assert_send(any::<&mut Migration<'_>>().run(any(), any::<fn(MigrationProgress)>()));
+    assert_send(any::<&mut Migration<'_>>().create_account(any()));
+    assert_send(any::<&mut Migration<'_>>().import_repo());
+    assert_send(any::<&mut Migration<'_>>().copy_blobs(any::<fn(MigrationProgress)>()));
+    assert_send(any::<&mut Migration<'_>>().copy_preferences());
+    assert_send(any::<&mut Migration<'_>>().request_plc_signature());
+    assert_send(any::<&mut Migration<'_>>().submit_plc_operation(any()));
+    assert_send(any::<&mut Migration<'_>>().activate());
+}
+
+fn sync() {
+    assert_send(sync::request_crawl(any(), any()));
+    assert_send(sync::notify_of_update(any(), any()));
+    assert_send(sync::request_crawl_all(any(), any()));
+}
+
+#[cfg(feature = "admin")]
+fn admin() {
+    assert_send(any::<&AdminClient>().update_subject_status(any(), any(), any()));
+    assert_send(any::<&AdminClient>().takedown(any(), any()));
+    assert_send(any::<&AdminClient>().reverse_takedown(any()));
+    assert_send(any::<&AdminClient>().get_subject_status(any(), any(), any()));
+    assert_send(any::<&AdminClient>().get_account_info(any()));
+    assert_send(any::<&AdminClient>().get_account_infos(any()));
+    assert_send(any::<&AdminClient>().disable_account_invites(any(), any()));
+    assert_send(any::<&AdminClient>().enable_account_invites(any(), any()));
+    assert_send(any::<&AdminClient>().delete_account(any()));
+}
+
+#[cfg(feature = "firehose")]
+fn car() {
+    assert_send(CarReader::new(any::<&[u8]>()));
+    assert_send(any::<&mut CarReader<&[u8]>>().next_block());
+    assert_send(any::<CarReader<&[u8]>>().read_all());
+    assert_send(car::read_car(any()));
+    assert_send(car::commit_records(any()));
+}
+
+#[cfg(feature = "firehose")]
+fn firehose() {
+    assert_send(
+        any::<FirehoseHub<BoxStream<'static, Result<RepoStreamEvent, BiskyError>>>>().run(),
+    );
+}
+
+#[cfg(feature = "firehose")]
+fn repo() {
+    assert_send(any::<&mut Client>().sync_backfill_repo(any(), any::<fn(RepoRecord)>()));
+}
+
+#[cfg(feature = "verify")]
+fn verify() {
+    assert_send(verify::verify_commit(any(), any()));
+    assert_send(verify::verify_repo(any(), any(), any()));
+}
@@ -9,9 +9,11 @@ use std::marker::Sync;
 use std::path::PathBuf;
 use thiserror::Error;
 
+/// Where a session or stream state is kept between runs. Implementations are shared with the
+/// client, which may be moved to another task, so they have to be `Send + Sync`.
 #[async_trait::async_trait]
-pub trait Storage<T: DeserializeOwned + Serialize + Sync> {
-    type Error: std::fmt::Debug + std::error::Error;
+pub trait Storage<T: DeserializeOwned + Serialize + Sync>: Send + Sync {
+    type Error: std::fmt::Debug + std::error::Error + Send + Sync + 'static;
 
     async fn set(&self, data: Option<&T>) -> Result<(), Self::Error>;
     async fn get(&self) -> Result<T, Self::Error>;
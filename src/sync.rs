@@ -0,0 +1,46 @@
+//! The relay-facing procedures a PDS operator calls, which need no session
+
+use crate::atproto::check_status;
+use crate::errors::{ApiError, BiskyError};
+use crate::lexicon::com::atproto::sync::Hostname;
+
+async fn post_hostname(relay: &reqwest::Url, nsid: &str, hostname: &str) -> Result<(), BiskyError> {
+    let response = reqwest::Client::new()
+        .post(relay.join(&format!("xrpc/{nsid}")).unwrap())
+        .json(&Hostname { hostname })
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::BAD_REQUEST {
+        return Err(BiskyError::ApiError(
+            ApiError::from_response(response).await,
+        ));
+    }
+    check_status(response).await?;
+    Ok(())
+}
+
+///com.atproto.sync.requestCrawl
+/// Ask `relay`, e.g. `https://bsky.network`, to subscribe to the PDS `hostname`
+pub async fn request_crawl(relay: &reqwest::Url, hostname: &str) -> Result<(), BiskyError> {
+    post_hostname(relay, "com.atproto.sync.requestCrawl", hostname).await
+}
+
+///com.atproto.sync.notifyOfUpdate
+/// Tell `relay` the PDS `hostname` has new commits, for relays that crawl rather than subscribe
+pub async fn notify_of_update(relay: &reqwest::Url, hostname: &str) -> Result<(), BiskyError> {
+    post_hostname(relay, "com.atproto.sync.notifyOfUpdate", hostname).await
+}
+
+/// Ask every one of `relays` to crawl the PDS `hostname`, returning how each of them answered in
+/// the same order
+pub async fn request_crawl_all<'a>(
+    relays: &'a [reqwest::Url],
+    hostname: &str,
+) -> Vec<(&'a reqwest::Url, Result<(), BiskyError>)> {
+    futures::future::join_all(
+        relays
+            .iter()
+            .map(|relay| async move { (relay, request_crawl(relay, hostname).await) }),
+    )
+    .await
+}
@@ -0,0 +1,491 @@
+use crate::errors::BiskyError;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// An `at://` URI pointing at a repo, a collection, or a single record.
+///
+/// `at://<authority>[/<collection>[/<rkey>]]`, where the authority is a DID or a handle.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AtUri {
+    pub authority: String,
+    pub collection: Option<String>,
+    pub rkey: Option<String>,
+}
+
+impl AtUri {
+    pub fn new(authority: &str, collection: &str, rkey: &str) -> Self {
+        Self {
+            authority: authority.to_string(),
+            collection: Some(collection.to_string()),
+            rkey: Some(rkey.to_string()),
+        }
+    }
+
+    /// The collection, or an error if this uri does not point at a collection
+    pub fn require_collection(&self) -> Result<&str, BiskyError> {
+        self.collection
+            .as_deref()
+            .ok_or_else(|| BiskyError::InvalidAtUri(format!("{self} has no collection")))
+    }
+
+    /// The record key, or an error if this uri does not point at a record
+    pub fn require_rkey(&self) -> Result<&str, BiskyError> {
+        self.rkey
+            .as_deref()
+            .ok_or_else(|| BiskyError::InvalidAtUri(format!("{self} has no record key")))
+    }
+
+    /// Parse a bsky.app link to a profile, post, feed, list or starter pack. The authority is
+    /// left as it appears in the link, which may be a handle; see `Client::resolve_bsky_url`.
+    pub fn from_bsky_url(url: &str) -> Result<Self, BiskyError> {
+        let invalid = || BiskyError::InvalidBskyUrl(url.to_string());
+
+        let parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+        if !matches!(parsed.scheme(), "https" | "http")
+            || !matches!(parsed.host_str(), Some("bsky.app" | "www.bsky.app"))
+        {
+            return Err(invalid());
+        }
+
+        let segments = parsed
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let (authority, collection, rkey) = match segments.as_slice() {
+            ["profile", actor] => (*actor, None, None),
+            ["profile", actor, "post", rkey] => (*actor, Some("app.bsky.feed.post"), Some(*rkey)),
+            ["profile", actor, "feed", rkey] => {
+                (*actor, Some("app.bsky.feed.generator"), Some(*rkey))
+            }
+            ["profile", actor, "lists", rkey] => (*actor, Some("app.bsky.graph.list"), Some(*rkey)),
+            ["starter-pack", actor, rkey] => {
+                (*actor, Some("app.bsky.graph.starterpack"), Some(*rkey))
+            }
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self {
+            authority: authority.to_string(),
+            collection: collection.map(str::to_string),
+            rkey: rkey.map(str::to_string),
+        })
+    }
+
+    /// The bsky.app link for this profile, post, feed, list or starter pack
+    pub fn to_bsky_url(&self) -> Result<String, BiskyError> {
+        let authority = &self.authority;
+        match (self.collection.as_deref(), self.rkey.as_deref()) {
+            (None, None) => Ok(format!("https://bsky.app/profile/{authority}")),
+            (Some("app.bsky.feed.post"), Some(rkey)) => {
+                Ok(format!("https://bsky.app/profile/{authority}/post/{rkey}"))
+            }
+            (Some("app.bsky.feed.generator"), Some(rkey)) => {
+                Ok(format!("https://bsky.app/profile/{authority}/feed/{rkey}"))
+            }
+            (Some("app.bsky.graph.list"), Some(rkey)) => {
+                Ok(format!("https://bsky.app/profile/{authority}/lists/{rkey}"))
+            }
+            (Some("app.bsky.graph.starterpack"), Some(rkey)) => {
+                Ok(format!("https://bsky.app/starter-pack/{authority}/{rkey}"))
+            }
+            _ => Err(BiskyError::InvalidAtUri(format!(
+                "{self} has no bsky.app equivalent"
+            ))),
+        }
+    }
+
+    /// Whether the authority is a DID rather than a handle
+    pub fn has_did_authority(&self) -> bool {
+        self.authority.starts_with("did:")
+    }
+}
+
+impl FromStr for AtUri {
+    type Err = BiskyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(rest) = s.strip_prefix("at://") else {
+            return Err(BiskyError::InvalidAtUri(s.to_string()));
+        };
+        // Drop any query or fragment, we only care about the path
+        let rest = rest.split(['?', '#']).next().unwrap_or_default();
+        let mut parts = rest.split('/');
+
+        let authority = match parts.next() {
+            Some(authority) if !authority.is_empty() => authority.to_string(),
+            _ => return Err(BiskyError::InvalidAtUri(s.to_string())),
+        };
+        let collection = parts.next().filter(|c| !c.is_empty()).map(str::to_string);
+        let rkey = parts.next().filter(|r| !r.is_empty()).map(str::to_string);
+
+        if parts.next().is_some() || (collection.is_none() && rkey.is_some()) {
+            return Err(BiskyError::InvalidAtUri(s.to_string()));
+        }
+
+        Ok(Self {
+            authority,
+            collection,
+            rkey,
+        })
+    }
+}
+
+impl fmt::Display for AtUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at://{}", self.authority)?;
+        if let Some(collection) = &self.collection {
+            write!(f, "/{collection}")?;
+            if let Some(rkey) = &self.rkey {
+                write!(f, "/{rkey}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for AtUri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AtUri {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A datetime in a record or view, such as `createdAt` or `indexedAt`.
+///
+/// Parsed leniently, from any RFC 3339 datetime (offsets such as `+00:00` and any precision
+/// included, and one without a timezone taken as UTC), but always written in the canonical form
+/// other clients expect: UTC with milliseconds and `Z`, e.g. `2024-01-02T03:04:05.678Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Datetime(DateTime<Utc>);
+
+impl Datetime {
+    pub fn now() -> Self {
+        Self(Utc::now())
+    }
+
+    pub fn as_chrono(&self) -> &DateTime<Utc> {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for Datetime {
+    type Target = DateTime<Utc>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<DateTime<Utc>> for Datetime {
+    fn from(value: DateTime<Utc>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Datetime> for DateTime<Utc> {
+    fn from(value: Datetime) -> Self {
+        value.0
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for Datetime {
+    fn from(value: time::OffsetDateTime) -> Self {
+        let datetime = DateTime::from_timestamp(value.unix_timestamp(), value.nanosecond());
+        // chrono's range covers all of time's
+        Self(datetime.unwrap_or_default())
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<Datetime> for time::OffsetDateTime {
+    fn from(value: Datetime) -> Self {
+        time::OffsetDateTime::from_unix_timestamp(value.0.timestamp())
+            .and_then(|datetime| datetime.replace_nanosecond(value.0.timestamp_subsec_nanos()))
+            // Outside time's years -9999 to 9999
+            .unwrap_or(time::OffsetDateTime::UNIX_EPOCH)
+    }
+}
+
+impl FromStr for Datetime {
+    type Err = BiskyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self(datetime.with_timezone(&Utc)));
+        }
+        // Some clients leave out the timezone
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+            .map(|datetime| Self(datetime.and_utc()))
+            .map_err(|_| BiskyError::InvalidDatetime(s.to_string()))
+    }
+}
+
+impl fmt::Display for Datetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.format("%Y-%m-%dT%H:%M:%S%.3fZ"))
+    }
+}
+
+impl Serialize for Datetime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Datetime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A service url, given as a `Url` or as a string such as `"https://bsky.social"`, so callers
+/// needn't depend on bisky's version of reqwest for its `Url`
+pub trait IntoUrl {
+    fn into_url(self) -> Result<reqwest::Url, BiskyError>;
+}
+
+impl IntoUrl for reqwest::Url {
+    fn into_url(self) -> Result<reqwest::Url, BiskyError> {
+        Ok(self)
+    }
+}
+
+impl IntoUrl for &reqwest::Url {
+    fn into_url(self) -> Result<reqwest::Url, BiskyError> {
+        Ok(self.clone())
+    }
+}
+
+impl IntoUrl for &str {
+    fn into_url(self) -> Result<reqwest::Url, BiskyError> {
+        reqwest::Url::parse(self).map_err(|e| BiskyError::InvalidUrl(format!("{self}: {e}")))
+    }
+}
+
+impl IntoUrl for String {
+    fn into_url(self) -> Result<reqwest::Url, BiskyError> {
+        self.as_str().into_url()
+    }
+}
+
+impl IntoUrl for &String {
+    fn into_url(self) -> Result<reqwest::Url, BiskyError> {
+        self.as_str().into_url()
+    }
+}
+
+#[cfg(any(feature = "bsky", feature = "test-utils"))]
+const BASE32_SORTABLE: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+
+/// `value` in `len` base32-sortable characters, most significant first, as in TIDs
+#[cfg(any(feature = "bsky", feature = "test-utils"))]
+pub(crate) fn base32_sortable(mut value: u64, len: usize) -> String {
+    let mut chars = vec![b'2'; len];
+    for c in chars.iter_mut().rev() {
+        *c = BASE32_SORTABLE[(value & 31) as usize];
+        value >>= 5;
+    }
+    String::from_utf8(chars).unwrap()
+}
+
+/// The `$type` of an open union member held as raw JSON, such as in the `Unknown` variant of the
+/// lexicon unions
+pub fn union_type(value: &serde_json::Value) -> Option<&str> {
+    value.get("$type")?.as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(s: &str) -> String {
+        s.parse::<Datetime>().unwrap().to_string()
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let expected = "2024-01-02T03:04:05.678Z";
+        for s in [
+            "2024-01-02T03:04:05.678Z",
+            "2024-01-02T03:04:05.678+00:00",
+            "2024-01-02T03:04:05.678-00:00",
+            "2024-01-02T04:34:05.678+01:30",
+            "2024-01-01T22:04:05.678-05:00",
+            // Beyond milliseconds is dropped, not rounded
+            "2024-01-02T03:04:05.678999Z",
+            "2024-01-02T03:04:05.678999999Z",
+            // Lowercase and a space, as RFC 3339 allows
+            "2024-01-02t03:04:05.678z",
+            "2024-01-02 03:04:05.678Z",
+            // No timezone at all
+            "2024-01-02T03:04:05.678",
+        ] {
+            assert_eq!(canonical(s), expected, "{s}");
+        }
+        assert_eq!(
+            canonical("2024-01-02T03:04:05Z"),
+            "2024-01-02T03:04:05.000Z"
+        );
+        assert_eq!(
+            canonical("2024-01-02T03:04:05.6Z"),
+            "2024-01-02T03:04:05.600Z"
+        );
+        // Across a day, month and year
+        assert_eq!(
+            canonical("2025-01-01T00:30:00+01:00"),
+            "2024-12-31T23:30:00.000Z"
+        );
+        // A leap day and a leap second
+        assert_eq!(
+            canonical("2024-02-29T00:00:00Z"),
+            "2024-02-29T00:00:00.000Z"
+        );
+        assert_eq!(
+            canonical("2016-12-31T23:59:60Z"),
+            "2016-12-31T23:59:60.000Z"
+        );
+    }
+
+    #[test]
+    fn invalid_datetimes() {
+        for s in [
+            "",
+            "2024-01-02",
+            "2024-01-02T03:04",
+            "2024-01-02T03:04:05.Z",
+            "2024-01-02T03:04:05+0000",
+            "2024-01-02T03:04:05 Z",
+            " 2024-01-02T03:04:05Z",
+            "2024-01-02T03:04:05Z ",
+            "2023-02-29T00:00:00Z",
+            "2024-02-30T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-01-02T24:00:00Z",
+            "2024-01-02T03:60:00Z",
+            "2024-01-02T03:04:05+24:00",
+            "1704164645",
+        ] {
+            assert!(
+                matches!(s.parse::<Datetime>(), Err(BiskyError::InvalidDatetime(e)) if e == s),
+                "{s}"
+            );
+            assert!(serde_json::from_value::<Datetime>(serde_json::json!(s)).is_err());
+        }
+        assert!(serde_json::from_str::<Datetime>("1704164645").is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        // A spread of instants from 1970 to 9999 down to the nanosecond
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let secs = (seed % 253_402_300_800) as i64;
+            let nanos = (seed >> 40) as u32 % 1_000_000_000;
+            let datetime = Datetime::from(DateTime::from_timestamp(secs, nanos).unwrap());
+
+            // Always 24 characters of the canonical form
+            let s = datetime.to_string();
+            assert_eq!(s.len(), 24, "{s}");
+            assert_eq!(&s[10..11], "T");
+            assert_eq!(&s[19..20], ".");
+            assert!(s.ends_with('Z'));
+
+            // That parses back to the same instant to the millisecond, and writes the same
+            let parsed: Datetime = s.parse().unwrap();
+            assert_eq!(parsed.timestamp_millis(), datetime.timestamp_millis());
+            assert_eq!(parsed.timestamp_subsec_nanos() % 1_000_000, 0);
+            assert_eq!(parsed.to_string(), s);
+
+            let json = serde_json::to_value(datetime).unwrap();
+            assert_eq!(json, serde_json::Value::String(s.clone()));
+            assert_eq!(serde_json::from_value::<Datetime>(json).unwrap(), parsed);
+
+            // Any whole-minute offset is the same instant
+            let minutes = (seed % 1439) as i32 - 719;
+            let offset = chrono::FixedOffset::east_opt(minutes * 60).unwrap();
+            let shifted = datetime.with_timezone(&offset).to_rfc3339();
+            assert_eq!(shifted.parse::<Datetime>().unwrap(), datetime, "{shifted}");
+        }
+    }
+
+    #[test]
+    fn every_offset_and_precision() {
+        let instants = [
+            "1969-12-31T23:59:59.999999999Z",
+            "1970-01-01T00:00:00.000000001Z",
+            "2024-02-29T12:34:56.789012345Z",
+            "9999-12-30T23:59:59.999999999Z",
+        ];
+        for instant in instants {
+            let instant = DateTime::parse_from_rfc3339(instant).unwrap();
+            for digits in 0..=9 {
+                // Written to `digits` places, which is the instant it parses to
+                let unit = 10u32.pow(9 - digits);
+                let nanos = instant.timestamp_subsec_nanos() / unit * unit;
+                let expected = DateTime::from_timestamp(instant.timestamp(), nanos).unwrap();
+                let fraction = match digits {
+                    0 => String::new(),
+                    _ => format!(".{:0digits$}", nanos / unit, digits = digits as usize),
+                };
+                let canonical = expected.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+
+                // In every whole-minute offset a timezone can have
+                for minutes in -(24 * 60 - 1)..24 * 60 {
+                    let offset = chrono::FixedOffset::east_opt(minutes * 60).unwrap();
+                    let local = instant.with_timezone(&offset);
+                    let s = format!(
+                        "{}{fraction}{}",
+                        local.format("%Y-%m-%dT%H:%M:%S"),
+                        local.format("%:z")
+                    );
+                    let parsed: Datetime = s.parse().unwrap();
+                    assert_eq!(*parsed.as_chrono(), expected, "{s}");
+                    assert_eq!(parsed.to_string(), canonical, "{s}");
+                }
+
+                // And without one, taken as UTC
+                let s = format!("{}{fraction}", expected.format("%Y-%m-%dT%H:%M:%S"));
+                assert_eq!(*s.parse::<Datetime>().unwrap().as_chrono(), expected, "{s}");
+            }
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        let earlier: Datetime = "2024-01-02T03:04:05.678+01:00".parse().unwrap();
+        let later: Datetime = "2024-01-02T03:04:05.678Z".parse().unwrap();
+        assert!(earlier < later);
+        assert!(Datetime::now() > later);
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_conversions() {
+        let datetime: Datetime = "2024-01-02T03:04:05.678901234Z".parse().unwrap();
+        let converted = time::OffsetDateTime::from(datetime);
+        assert_eq!(converted.unix_timestamp(), datetime.timestamp());
+        assert_eq!(converted.nanosecond(), 678_901_234);
+        assert_eq!(Datetime::from(converted), datetime);
+
+        // Before 1970 too
+        let datetime: Datetime = "1969-07-20T20:17:40.5Z".parse().unwrap();
+        assert_eq!(
+            Datetime::from(time::OffsetDateTime::from(datetime)),
+            datetime
+        );
+    }
+}
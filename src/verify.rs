@@ -0,0 +1,420 @@
+//! Checking that firehose commits and repo exports are what the repo's owner signed: that every
+//! block hashes to its CID, that the MST under the signed commit maps each op's path to its
+//! record, and optionally that the commit is signed by the repo's signing key.
+
+use crate::car::read_car;
+use crate::lexicon::com::atproto::sync::{Commit, RepoOp, RepoOpAction};
+use crate::repo::{mst_entries, MstNode, SignedCommit};
+use cid::Cid;
+use ipld_core::ipld::Ipld;
+use miette::Diagnostic;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// The multihash code of SHA-256, which repo blocks are hashed with
+const SHA2_256: u64 = 0x12;
+
+/// The multicodec prefixes of compressed public keys
+const SECP256K1_PUB: [u8; 2] = [0xe7, 0x01];
+const P256_PUB: [u8; 2] = [0x80, 0x24];
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum VerificationError {
+    #[error("Block {0} does not hash to its CID")]
+    BlockHashMismatch(String),
+    #[error("Block {0} is not hashed with SHA-256")]
+    UnsupportedHash(String),
+    #[error("Missing block {0}")]
+    MissingBlock(String),
+    #[error("Invalid block: {0}")]
+    InvalidBlock(String),
+    #[error("Commit is for {found}, not {expected}")]
+    RepoMismatch { expected: String, found: String },
+    /// The cids are `None` where the path is absent
+    #[error("{path} is {found:?} in the MST, not {expected:?}")]
+    PathMismatch {
+        path: String,
+        expected: Option<String>,
+        found: Option<String>,
+    },
+    #[error("Unsupported signing key: {0}")]
+    UnsupportedKey(String),
+    #[error("Invalid commit signature")]
+    InvalidSignature,
+    /// `too_big` commits leave out the blocks there would be to check
+    #[error("Commit is too big to verify, fetch the repo instead")]
+    TooBig,
+}
+
+/// The ops of a commit, checked against the commit it was signed as
+#[derive(Debug, Clone)]
+pub struct VerifiedOps {
+    pub commit: SignedCommit,
+    pub ops: Vec<RepoOp>,
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, VerificationError> {
+    serde_ipld_dagcbor::from_slice(bytes)
+        .map_err(|e| VerificationError::InvalidBlock(e.to_string()))
+}
+
+fn block<'a>(blocks: &'a HashMap<Cid, Vec<u8>>, cid: &Cid) -> Result<&'a [u8], VerificationError> {
+    blocks
+        .get(cid)
+        .map(Vec::as_slice)
+        .ok_or_else(|| VerificationError::MissingBlock(cid.to_string()))
+}
+
+/// Check that every block hashes to its CID
+pub fn verify_blocks(blocks: &HashMap<Cid, Vec<u8>>) -> Result<(), VerificationError> {
+    for (cid, bytes) in blocks {
+        let hash = cid.hash();
+        if hash.code() != SHA2_256 {
+            return Err(VerificationError::UnsupportedHash(cid.to_string()));
+        }
+        if hash.digest() != Sha256::digest(bytes).as_slice() {
+            return Err(VerificationError::BlockHashMismatch(cid.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// The record at `path` in the MST rooted at `root`, following only the nodes on its way, so
+/// `blocks` may be a partial tree such as a commit's proof
+fn mst_lookup(
+    blocks: &HashMap<Cid, Vec<u8>>,
+    root: &Cid,
+    path: &str,
+) -> Result<Option<Cid>, VerificationError> {
+    let mut node_cid = *root;
+    loop {
+        let node: MstNode = decode(block(blocks, &node_cid)?)?;
+
+        // The subtree left of the first entry with a key at or after `path`
+        let mut subtree = node.l;
+        let mut key = Vec::new();
+        for entry in &node.e {
+            key.truncate(entry.p);
+            key.extend_from_slice(&entry.k);
+            match key.as_slice().cmp(path.as_bytes()) {
+                std::cmp::Ordering::Equal => return Ok(Some(entry.v)),
+                std::cmp::Ordering::Greater => break,
+                std::cmp::Ordering::Less => subtree = entry.t,
+            }
+        }
+
+        match subtree {
+            Some(cid) => node_cid = cid,
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Check that `commit_block`, a signed commit, is signed by `key`, a multibase public key as in
+/// a DID document's `#atproto` verification method, with or without the `did:key:` prefix
+pub fn verify_signature(commit_block: &[u8], key: &str) -> Result<(), VerificationError> {
+    let unsupported = || VerificationError::UnsupportedKey(key.to_string());
+
+    let encoded = key.strip_prefix("did:key:").unwrap_or(key);
+    let key_bytes = encoded
+        .strip_prefix('z')
+        .and_then(|base58| bs58::decode(base58).into_vec().ok())
+        .ok_or_else(unsupported)?;
+
+    let mut commit: Ipld = decode(commit_block)?;
+    let Ipld::Map(fields) = &mut commit else {
+        return Err(VerificationError::InvalidBlock(
+            "Commit is not a map".into(),
+        ));
+    };
+    let Some(Ipld::Bytes(sig)) = fields.remove("sig") else {
+        return Err(VerificationError::InvalidSignature);
+    };
+    let unsigned = serde_ipld_dagcbor::to_vec(&commit)
+        .map_err(|e| VerificationError::InvalidBlock(e.to_string()))?;
+
+    let valid = if let Some(point) = key_bytes.strip_prefix(&SECP256K1_PUB) {
+        use k256::ecdsa::signature::Verifier;
+        let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(|_| unsupported())?;
+        let sig = k256::ecdsa::Signature::from_slice(&sig)
+            .map_err(|_| VerificationError::InvalidSignature)?;
+        // Signatures must be in low-S form
+        sig.normalize_s().is_none() && key.verify(&unsigned, &sig).is_ok()
+    } else if let Some(point) = key_bytes.strip_prefix(&P256_PUB) {
+        use p256::ecdsa::signature::Verifier;
+        let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(|_| unsupported())?;
+        let sig = p256::ecdsa::Signature::from_slice(&sig)
+            .map_err(|_| VerificationError::InvalidSignature)?;
+        sig.normalize_s().is_none() && key.verify(&unsigned, &sig).is_ok()
+    } else {
+        return Err(unsupported());
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(VerificationError::InvalidSignature)
+    }
+}
+
+/// Check a firehose commit: its blocks, that it is for the repo it claims, that each op's path
+/// maps to the op's record in the MST (or to nothing for deletes), and, given the repo's
+/// `signing_key` from its DID document, its signature.
+pub async fn verify_commit(
+    commit: &Commit,
+    signing_key: Option<&str>,
+) -> Result<VerifiedOps, VerificationError> {
+    if commit.too_big {
+        return Err(VerificationError::TooBig);
+    }
+
+    let (_, blocks) = read_car(&commit.blocks)
+        .await
+        .map_err(|e| VerificationError::InvalidBlock(e.to_string()))?;
+    verify_blocks(&blocks)?;
+
+    let commit_block = block(&blocks, &commit.commit)?;
+    let signed: SignedCommit = decode(commit_block)?;
+    if signed.did != commit.repo {
+        return Err(VerificationError::RepoMismatch {
+            expected: commit.repo.clone(),
+            found: signed.did,
+        });
+    }
+    if let Some(key) = signing_key {
+        verify_signature(commit_block, key)?;
+    }
+
+    for op in &commit.ops {
+        let expected = match op.action {
+            RepoOpAction::Delete => None,
+            _ => op.cid,
+        };
+        let found = mst_lookup(&blocks, &signed.data, &op.path)?;
+        if found != expected {
+            return Err(VerificationError::PathMismatch {
+                path: op.path.clone(),
+                expected: expected.map(|cid| cid.to_string()),
+                found: found.map(|cid| cid.to_string()),
+            });
+        }
+    }
+
+    Ok(VerifiedOps {
+        commit: signed,
+        ops: commit.ops.clone(),
+    })
+}
+
+/// Check a repo export of `did` from getRepo: its blocks, that its MST is complete, and, given
+/// the repo's `signing_key`, the signature of its commit
+pub async fn verify_repo(
+    car: &[u8],
+    did: &str,
+    signing_key: Option<&str>,
+) -> Result<SignedCommit, VerificationError> {
+    let (header, blocks) = read_car(car)
+        .await
+        .map_err(|e| VerificationError::InvalidBlock(e.to_string()))?;
+    verify_blocks(&blocks)?;
+
+    let root = header
+        .roots
+        .first()
+        .ok_or_else(|| VerificationError::InvalidBlock("Repo export has no root".into()))?;
+    let commit_block = block(&blocks, root)?;
+    let signed: SignedCommit = decode(commit_block)?;
+    if signed.did != did {
+        return Err(VerificationError::RepoMismatch {
+            expected: did.to_string(),
+            found: signed.did,
+        });
+    }
+    if let Some(key) = signing_key {
+        verify_signature(commit_block, key)?;
+    }
+
+    for (_, cid) in mst_entries(&blocks, &signed.data)
+        .map_err(|e| VerificationError::InvalidBlock(e.to_string()))?
+    {
+        block(&blocks, &cid)?;
+    }
+    Ok(signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // Commits signed here with fixed keys, as the atproto interop vectors are: an unsigned
+    // commit's DAG-CBOR signed with deterministic (RFC 6979) low-S ECDSA, the key given as a
+    // `did:key`.
+
+    fn cid_of(bytes: &[u8]) -> Cid {
+        let hash = cid::multihash::Multihash::wrap(SHA2_256, &Sha256::digest(bytes)).unwrap();
+        Cid::new_v1(0x71, hash)
+    }
+
+    fn unsigned_commit(did: &str) -> BTreeMap<String, Ipld> {
+        let data = cid_of(&[0xa0]);
+        BTreeMap::from([
+            ("did".to_string(), Ipld::String(did.into())),
+            ("version".to_string(), Ipld::Integer(3)),
+            ("data".to_string(), Ipld::Link(data)),
+            ("rev".to_string(), Ipld::String("3kdfsvlcf5c2a".into())),
+            ("prev".to_string(), Ipld::Null),
+        ])
+    }
+
+    fn with_sig(mut commit: BTreeMap<String, Ipld>, sig: Vec<u8>) -> Vec<u8> {
+        commit.insert("sig".into(), Ipld::Bytes(sig));
+        serde_ipld_dagcbor::to_vec(&Ipld::Map(commit)).unwrap()
+    }
+
+    fn did_key(prefix: [u8; 2], point: &[u8]) -> String {
+        let mut bytes = prefix.to_vec();
+        bytes.extend_from_slice(point);
+        format!("did:key:z{}", bs58::encode(bytes).into_string())
+    }
+
+    fn k256_commit(commit: &BTreeMap<String, Ipld>) -> (Vec<u8>, String) {
+        use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+        let key = SigningKey::from_slice(&[0x11; 32]).unwrap();
+        let unsigned = serde_ipld_dagcbor::to_vec(&Ipld::Map(commit.clone())).unwrap();
+        let sig: Signature = key.sign(&unsigned);
+        let point = key.verifying_key().to_encoded_point(true);
+        (sig.to_vec(), did_key(SECP256K1_PUB, point.as_bytes()))
+    }
+
+    fn p256_commit(commit: &BTreeMap<String, Ipld>) -> (Vec<u8>, String) {
+        use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+        let key = SigningKey::from_slice(&[0x22; 32]).unwrap();
+        let unsigned = serde_ipld_dagcbor::to_vec(&Ipld::Map(commit.clone())).unwrap();
+        let sig: Signature = key.sign(&unsigned);
+        let sig = sig.normalize_s().unwrap_or(sig);
+        let point = key.verifying_key().to_encoded_point(true);
+        (sig.to_vec(), did_key(P256_PUB, point.as_bytes()))
+    }
+
+    /// The signature with S replaced by n - S, which verifies but is not low-S
+    fn high_s(sig: &[u8], order: &[u8; 32]) -> Vec<u8> {
+        let mut out = sig[..32].to_vec();
+        let s = &sig[32..];
+        let mut high = [0u8; 32];
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let d = order[i] as i16 - s[i] as i16 - borrow;
+            borrow = (d < 0) as i16;
+            high[i] = d.rem_euclid(256) as u8;
+        }
+        out.extend_from_slice(&high);
+        out
+    }
+
+    const SECP256K1_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+    const P256_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2, 0xfc, 0x63,
+        0x25, 0x51,
+    ];
+
+    /// Signs a commit, giving the signature and the signer's `did:key`
+    type Sign = fn(&BTreeMap<String, Ipld>) -> (Vec<u8>, String);
+
+    fn check(sign: Sign, order: &[u8; 32]) {
+        let commit = unsigned_commit("did:plc:alice");
+        let (sig, key) = sign(&commit);
+
+        // Valid, with or without the `did:key:` prefix
+        let signed = with_sig(commit.clone(), sig.clone());
+        verify_signature(&signed, &key).unwrap();
+        verify_signature(&signed, key.strip_prefix("did:key:").unwrap()).unwrap();
+
+        // Tampered: the same signature over a commit for another repo
+        let tampered = with_sig(unsigned_commit("did:plc:mallory"), sig.clone());
+        assert!(matches!(
+            verify_signature(&tampered, &key),
+            Err(VerificationError::InvalidSignature)
+        ));
+
+        // Tampered: one bit of the signature flipped
+        let mut flipped = sig.clone();
+        flipped[40] ^= 1;
+        assert!(matches!(
+            verify_signature(&with_sig(commit.clone(), flipped), &key),
+            Err(VerificationError::InvalidSignature)
+        ));
+
+        // Mathematically valid but high-S
+        let malleable = with_sig(commit.clone(), high_s(&sig, order));
+        assert!(matches!(
+            verify_signature(&malleable, &key),
+            Err(VerificationError::InvalidSignature)
+        ));
+
+        // Unsigned
+        let unsigned = serde_ipld_dagcbor::to_vec(&Ipld::Map(commit)).unwrap();
+        assert!(matches!(
+            verify_signature(&unsigned, &key),
+            Err(VerificationError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn k256_signatures() {
+        check(k256_commit, &SECP256K1_ORDER);
+    }
+
+    #[test]
+    fn p256_signatures() {
+        check(p256_commit, &P256_ORDER);
+    }
+
+    #[test]
+    fn key_of_the_wrong_curve() {
+        let commit = unsigned_commit("did:plc:alice");
+        let (sig, _) = k256_commit(&commit);
+        let (_, p256_key) = p256_commit(&commit);
+        assert!(matches!(
+            verify_signature(&with_sig(commit, sig), &p256_key),
+            Err(VerificationError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn unsupported_keys() {
+        let commit = unsigned_commit("did:plc:alice");
+        let (sig, _) = k256_commit(&commit);
+        let signed = with_sig(commit, sig);
+        // Not base58btc, an ed25519 key, and a truncated secp256k1 key
+        for key in [
+            "did:key:mabc",
+            &did_key([0xed, 0x01], &[0; 32]),
+            &did_key(SECP256K1_PUB, &[2; 10]),
+        ] {
+            assert!(matches!(
+                verify_signature(&signed, key),
+                Err(VerificationError::UnsupportedKey(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn blocks_hash_to_their_cids() {
+        let cid = cid_of(&[0xa0]);
+        let mut blocks = HashMap::from([(cid, vec![0xa0])]);
+        verify_blocks(&blocks).unwrap();
+        blocks.insert(cid, vec![0xa1, 0x60, 0x60]);
+        assert!(matches!(
+            verify_blocks(&blocks),
+            Err(VerificationError::BlockHashMismatch(_))
+        ));
+    }
+}
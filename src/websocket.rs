@@ -0,0 +1,146 @@
+//! The websocket connection under the event streams, which reopens itself with backoff when it
+//! drops or goes quiet
+
+use crate::errors::BiskyError;
+use futures::StreamExt;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// How an event stream reconnects
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// The wait before the first reconnection attempt, doubled for each one that fails in a row
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Reconnect when nothing, not even a ping, arrives for this long, as a connection that died
+    /// without closing would otherwise wait forever
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(64),
+            idle_timeout: Some(Duration::from_secs(120)),
+        }
+    }
+}
+
+/// A stream reopened its connection, which it yields so consumers can log it
+#[derive(Debug, Clone)]
+pub struct Reconnection {
+    /// 1 for the first attempt after the connection was lost, counting up until it works
+    pub attempt: u32,
+    /// How long the stream waited before this attempt
+    pub delay: Duration,
+    /// Why the previous connection was given up on
+    pub reason: String,
+}
+
+pub(crate) enum Received {
+    Message(Message),
+    Reconnected(Reconnection),
+}
+
+pub(crate) struct Connection {
+    policy: ReconnectPolicy,
+    socket: Option<Socket>,
+    delay: Duration,
+    attempt: u32,
+    /// Why the last connection ended, if one was lost and not yet reopened
+    dropped: Option<String>,
+}
+
+impl Connection {
+    pub(crate) fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            socket: None,
+            delay: policy.initial_delay,
+            attempt: 0,
+            dropped: None,
+        }
+    }
+
+    /// The next message, first (re)connecting to the url `url` gives if there is no connection.
+    /// A failed connection attempt is returned as an error and tried again on the next call.
+    pub(crate) async fn next(
+        &mut self,
+        mut url: impl FnMut() -> Result<String, BiskyError>,
+    ) -> Result<Received, BiskyError> {
+        loop {
+            let socket = match self.socket.as_mut() {
+                Some(socket) => socket,
+                None => {
+                    let url = url()?;
+                    let reconnection = match self.dropped.take() {
+                        Some(reason) => {
+                            tokio::time::sleep(self.delay).await;
+                            self.attempt += 1;
+                            let reconnection = Reconnection {
+                                attempt: self.attempt,
+                                delay: self.delay,
+                                reason,
+                            };
+                            self.delay = std::cmp::min(self.delay * 2, self.policy.max_delay);
+                            Some(reconnection)
+                        }
+                        None => None,
+                    };
+
+                    match tokio_tungstenite::connect_async(url).await {
+                        Ok((socket, _)) => self.socket = Some(socket),
+                        Err(e) => {
+                            self.dropped = Some(e.to_string());
+                            return Err(BiskyError::WebSocketError(e.to_string()));
+                        }
+                    }
+                    match reconnection {
+                        Some(reconnection) => return Ok(Received::Reconnected(reconnection)),
+                        None => continue,
+                    }
+                }
+            };
+
+            let next = match self.policy.idle_timeout {
+                Some(idle) => match tokio::time::timeout(idle, socket.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        self.drop_connection(format!("Nothing received for {idle:?}"));
+                        continue;
+                    }
+                },
+                None => socket.next().await,
+            };
+
+            match next {
+                // Pings are answered by tungstenite itself
+                Some(Ok(Message::Close(_))) | None => {
+                    self.drop_connection("Connection closed".to_string())
+                }
+                Some(Ok(message)) => return Ok(Received::Message(message)),
+                Some(Err(e)) => {
+                    self.drop_connection(e.to_string());
+                    return Err(BiskyError::WebSocketError(e.to_string()));
+                }
+            }
+        }
+    }
+
+    /// Close the connection, to reopen it on the next call
+    pub(crate) fn drop_connection(&mut self, reason: String) {
+        self.socket = None;
+        self.dropped = Some(reason);
+    }
+
+    /// The connection works, so the next time it drops it is retried from the initial delay
+    pub(crate) fn reset_backoff(&mut self) {
+        self.delay = self.policy.initial_delay;
+        self.attempt = 0;
+    }
+}
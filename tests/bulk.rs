@@ -0,0 +1,134 @@
+//! Bulk writes through applyWrites against `MockPds`: batching, dry runs and what happens to a
+//! batch the PDS refuses.
+#![cfg(all(feature = "test-utils", feature = "bsky"))]
+
+use bisky::bsky::BskyClient;
+use bisky::bulk::{BulkOutcome, BulkPolicy};
+use bisky::errors::BiskyError;
+use bisky::lexicon::com::atproto::repo::{Record, MAX_APPLY_WRITES};
+use bisky::mock::{MockPds, MOCK_DID};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const LIKES: &str = "app.bsky.feed.like";
+const LIST: &str = "at://did:plc:alice/app.bsky.graph.list/3kdfsvlcf5c2a";
+
+fn old(like: &Record<Value>) -> bool {
+    like.value["createdAt"].as_str() < Some("2024-01-01")
+}
+
+#[tokio::test]
+async fn purge_collection() {
+    let pds = MockPds::start().await;
+    let mut client = pds.login().await.unwrap();
+    for i in 0..500 {
+        let year = if i % 10 == 0 { 2024 } else { 2023 };
+        let created_at = format!("{year}-06-01T00:00:{:02}.000Z", i % 60);
+        pds.insert_record(
+            MOCK_DID,
+            LIKES,
+            Some(&format!("{i:04}")),
+            &json!({"createdAt": created_at}),
+        );
+    }
+    let policy = BulkPolicy {
+        batch_size: MAX_APPLY_WRITES,
+        ..Default::default()
+    };
+
+    // A dry run writes nothing
+    let report = client
+        .repo_purge_collection(LIKES, old, &policy, true)
+        .await
+        .unwrap();
+    assert_eq!((report.scanned, report.deleted.len()), (500, 450));
+    assert!(report.failed.is_empty());
+    assert_eq!(pds.calls("com.atproto.repo.applyWrites"), 0);
+    assert_eq!(pds.records(MOCK_DID, LIKES).len(), 500);
+
+    let purged = client
+        .repo_purge_collection(LIKES, old, &policy, false)
+        .await
+        .unwrap();
+    assert_eq!(purged.deleted, report.deleted);
+    assert!(purged.failed.is_empty());
+    assert_eq!(pds.calls("com.atproto.repo.applyWrites"), 3);
+    let left = pds.records(MOCK_DID, LIKES);
+    assert_eq!(left.len(), 50);
+    assert!(left
+        .values()
+        .all(|like| like["createdAt"].as_str().unwrap().starts_with("2024")));
+
+    // Nothing left to do
+    let again = client
+        .repo_purge_collection(LIKES, old, &policy, false)
+        .await
+        .unwrap();
+    assert_eq!((again.scanned, again.deleted.len()), (50, 0));
+    assert_eq!(pds.calls("com.atproto.repo.applyWrites"), 3);
+}
+
+#[tokio::test]
+async fn refused_batch_falls_back_to_single_writes() {
+    let pds = MockPds::start().await;
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    let policy = BulkPolicy::default();
+
+    // The bad DID fails the batch, then only itself
+    let dids = ["did:plc:bob", "bob.test", "did:plc:carol"];
+    let report = client
+        .bsky_bulk_add_to_list(LIST, &dids, &policy)
+        .await
+        .unwrap();
+    assert_eq!(pds.calls("com.atproto.repo.applyWrites"), 1 + 3);
+    let outcomes = report
+        .iter()
+        .map(|(did, outcome)| (did.as_str(), outcome))
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        outcomes[..],
+        [
+            ("did:plc:bob", BulkOutcome::Created(_)),
+            ("bob.test", BulkOutcome::Failed(BiskyError::ApiError(_))),
+            ("did:plc:carol", BulkOutcome::Created(_)),
+        ]
+    ));
+    let items = pds.records(MOCK_DID, "app.bsky.graph.listitem");
+    let mut subjects = items
+        .values()
+        .map(|item| item["subject"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    subjects.sort();
+    assert_eq!(subjects, ["did:plc:bob", "did:plc:carol"]);
+
+    // Listed ones are skipped, and a batch of good writes goes out once
+    let report = client
+        .bsky_bulk_add_to_list(LIST, &["did:plc:bob", "did:plc:dave"], &policy)
+        .await
+        .unwrap();
+    assert_eq!(pds.calls("com.atproto.repo.applyWrites"), 5);
+    assert!(matches!(
+        report[..],
+        [(_, BulkOutcome::Created(_)), (_, BulkOutcome::Skipped)]
+    ));
+}
+
+#[tokio::test]
+async fn other_failures_end_the_bulk_write() {
+    let pds = MockPds::start().await;
+    // A session that can't be refreshed, so every write is turned away
+    pds.token_lifetimes(Duration::ZERO, Duration::ZERO);
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+
+    let result = client
+        .bsky_bulk_add_to_list(
+            LIST,
+            &["did:plc:bob", "did:plc:carol"],
+            &BulkPolicy::default(),
+        )
+        .await;
+    assert!(matches!(result, Err(BiskyError::ApiError(_))), "{result:?}");
+    // Not split up and tried again one write at a time
+    assert_eq!(pds.calls("com.atproto.repo.applyWrites"), 1);
+    assert!(pds.records(MOCK_DID, "app.bsky.graph.listitem").is_empty());
+}
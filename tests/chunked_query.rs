@@ -0,0 +1,130 @@
+//! `Client::chunked_query` and the batch wrappers built on it against `MockPds`, counting the
+//! calls they make.
+#![cfg(all(feature = "test-utils", feature = "bsky"))]
+
+use bisky::atproto::{ChunkErrors, ChunkOptions, Client};
+use bisky::bsky::BskyClient;
+use bisky::errors::BiskyError;
+use bisky::mock::MockPds;
+use serde_json::Value;
+
+const GET_PROFILES: &str = "app.bsky.actor.getProfiles";
+
+/// A mock with 50 more accounts, and 60 actors to look up: the accounts by handle or DID in
+/// turn, then 10 that don't exist
+async fn setup() -> (MockPds, Vec<String>) {
+    let pds = MockPds::start().await;
+    let mut actors = Vec::new();
+    for i in 0..50 {
+        let (handle, did) = (format!("user{i}.test"), format!("did:plc:user{i}"));
+        pds.add_account(&handle, &did, "password");
+        actors.push(if i % 2 == 0 { handle } else { did });
+    }
+    actors.extend((0..10).map(|i| format!("ghost{i}.test")));
+    (pds, actors)
+}
+
+async fn query(
+    client: &mut Client,
+    actors: &[&str],
+    concurrency: usize,
+    on_error: ChunkErrors,
+) -> Result<Vec<Result<Value, BiskyError>>, BiskyError> {
+    client
+        .chunked_query(
+            GET_PROFILES,
+            &[],
+            "actors",
+            actors,
+            25,
+            ChunkOptions {
+                concurrency,
+                on_error,
+            },
+        )
+        .await
+}
+
+/// The handles in the output of one call
+fn handles(output: &Value) -> Vec<&str> {
+    output["profiles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|profile| profile["handle"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn profiles_in_input_order() {
+    let (pds, actors) = setup().await;
+    let actors = actors.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+
+    let profiles = client.bsky_get_profiles(&actors).await.unwrap();
+    assert_eq!(pds.calls(GET_PROFILES), 3);
+    assert_eq!(profiles.len(), 60);
+    for (i, profile) in profiles.iter().enumerate() {
+        match profile {
+            Some(profile) => assert_eq!(profile.did, format!("did:plc:user{i}")),
+            None => assert!(i >= 50, "{i}"),
+        }
+    }
+
+    // Nothing to look up is no call at all
+    assert!(client.bsky_get_profiles(&[]).await.unwrap().is_empty());
+    assert_eq!(pds.calls(GET_PROFILES), 3);
+}
+
+#[tokio::test]
+async fn concurrent_calls_in_input_order() {
+    let (pds, actors) = setup().await;
+    let actors = actors.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut client = pds.login().await.unwrap();
+
+    for concurrency in [1, 3, 10] {
+        let before = pds.calls(GET_PROFILES);
+        let outputs = query(&mut client, &actors, concurrency, ChunkErrors::FailFast)
+            .await
+            .unwrap();
+        assert_eq!(pds.calls(GET_PROFILES) - before, 3);
+        let outputs = outputs.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(handles(&outputs[0])[..2], ["user0.test", "user1.test"]);
+        assert_eq!(handles(&outputs[1])[..2], ["user25.test", "user26.test"]);
+        assert!(handles(&outputs[2]).is_empty());
+    }
+}
+
+#[tokio::test]
+async fn partial_failures() {
+    let (pds, mut actors) = setup().await;
+    // Refused by the server, failing the second of the three calls
+    actors[30] = "not a handle".to_string();
+    let actors = actors.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut client = pds.login().await.unwrap();
+
+    // Failing fast makes no more calls after the one that failed
+    let failed = query(&mut client, &actors, 1, ChunkErrors::FailFast).await;
+    assert!(matches!(failed, Err(BiskyError::ApiError(_))), "{failed:?}");
+    assert_eq!(pds.calls(GET_PROFILES), 2);
+
+    // Or every call is made and each chunk has its own result
+    let outputs = query(&mut client, &actors, 1, ChunkErrors::PerChunk)
+        .await
+        .unwrap();
+    assert_eq!(pds.calls(GET_PROFILES), 2 + 3);
+    assert!(matches!(
+        outputs[..],
+        [Ok(_), Err(BiskyError::ApiError(_)), Ok(_)]
+    ));
+    assert_eq!(handles(outputs[0].as_ref().unwrap()).len(), 25);
+
+    // Running at once, the calls may all have started before one fails
+    let failed = query(&mut client, &actors, 3, ChunkErrors::FailFast).await;
+    assert!(matches!(failed, Err(BiskyError::ApiError(_))));
+    assert!(pds.calls(GET_PROFILES) <= 5 + 3);
+
+    // The wrappers fail fast
+    let mut client = BskyClient::from(client);
+    assert!(client.bsky_get_profiles(&actors).await.is_err());
+}
@@ -0,0 +1,263 @@
+//! `ClientBuilder` options against `MockPds`, checking that each one reaches the requests it
+//! sends. `max_response_size`, `metrics` and `retry_policy` have their own tests.
+#![cfg(feature = "test-utils")]
+
+use bisky::atproto::{Client, ClientBuilder, XrpcTransport};
+use bisky::errors::BiskyError;
+use bisky::mock::{MockPds, MOCK_DID, MOCK_HANDLE, MOCK_PASSWORD};
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const CREATE_SESSION: &str = "com.atproto.server.createSession";
+const LIST_RECORDS: &str = "com.atproto.repo.listRecords";
+
+async fn login(builder: &mut ClientBuilder) -> Result<Client, BiskyError> {
+    builder.login(MOCK_HANDLE, MOCK_PASSWORD).await
+}
+
+/// A call the mock answers
+async fn list(client: &mut Client) {
+    client
+        .repo_list_records::<Value>(MOCK_DID, "com.example.note", 10, false, None)
+        .await
+        .unwrap();
+}
+
+/// A few calls, each of them through the login's connection if it can
+async fn requests(client: &mut Client) {
+    for _ in 0..3 {
+        list(client).await;
+    }
+}
+
+#[tokio::test]
+async fn user_agent() {
+    let pds = MockPds::start().await;
+    login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .user_agent("bisky-tests/1.0"),
+    )
+    .await
+    .unwrap();
+    let sent = &pds.requests(CREATE_SESSION)[0];
+    assert_eq!(sent.header("user-agent"), Some("bisky-tests/1.0"));
+}
+
+#[tokio::test]
+async fn timeout() {
+    // Takes the connection and never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let service = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let started = Instant::now();
+    let result = login(
+        ClientBuilder::default()
+            .service(service.as_str())
+            .timeout(Duration::from_millis(200)),
+    )
+    .await;
+    assert!(
+        matches!(result, Err(BiskyError::ReqwestError(ref e)) if e.is_timeout()),
+        "{:?}",
+        result.err()
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn http_client() {
+    let pds = MockPds::start().await;
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-client", "given".parse().unwrap());
+    let http = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .http_client(http)
+            // In place of the options it would be built from
+            .user_agent("not sent"),
+    )
+    .await
+    .unwrap();
+    let sent = &pds.requests(CREATE_SESSION)[0];
+    assert_eq!(sent.header("x-client"), Some("given"));
+    assert_ne!(sent.header("user-agent"), Some("not sent"));
+}
+
+#[tokio::test]
+async fn proxy() {
+    let pds = MockPds::start().await;
+    // A service that can only be reached through the proxy
+    let client = login(
+        ClientBuilder::default()
+            .service("http://pds.invalid")
+            .proxy(reqwest::Proxy::http(pds.url().as_str()).unwrap()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(client.session().unwrap().handle, MOCK_HANDLE);
+    let sent = &pds.requests(CREATE_SESSION)[0];
+    assert_eq!(sent.header("host"), Some("pds.invalid"));
+}
+
+/// Sends each request with the client it is given, keeping its path
+#[derive(Default)]
+struct Recording(Mutex<Vec<String>>);
+
+#[async_trait::async_trait]
+impl XrpcTransport for Recording {
+    async fn execute(
+        &self,
+        http: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, BiskyError> {
+        self.0.lock().push(request.url().path().to_string());
+        Ok(http.execute(request).await?)
+    }
+}
+
+#[tokio::test]
+async fn transport() {
+    let pds = MockPds::start().await;
+    let transport = Arc::new(Recording::default());
+    let mut client = login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .user_agent("through the transport")
+            .transport(transport.clone()),
+    )
+    .await
+    .unwrap();
+    list(&mut client).await;
+    assert_eq!(
+        *transport.0.lock(),
+        [
+            format!("/xrpc/{CREATE_SESSION}"),
+            format!("/xrpc/{LIST_RECORDS}")
+        ]
+    );
+    // Given the client built from the other options
+    let sent = &pds.requests(LIST_RECORDS)[0];
+    assert_eq!(sent.header("user-agent"), Some("through the transport"));
+}
+
+#[tokio::test]
+async fn labelers() {
+    let pds = MockPds::start().await;
+    let mut client = login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .labelers(vec![
+                "did:plc:ar7c4by46qjdydhdevvrndac".to_string(),
+                "did:plc:labeler".to_string(),
+            ]),
+    )
+    .await
+    .unwrap();
+    list(&mut client).await;
+    let sent = &pds.requests(LIST_RECORDS)[0];
+    assert_eq!(
+        sent.header("atproto-accept-labelers"),
+        Some("did:plc:ar7c4by46qjdydhdevvrndac, did:plc:labeler")
+    );
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn appview() {
+    use bisky::bsky::BskyClient;
+
+    let pds = MockPds::start().await;
+    let client = login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .appview("did:web:api.bsky.app#bsky_appview"),
+    )
+    .await
+    .unwrap();
+    pds.set_timeline(Vec::<Value>::new());
+    let mut client = BskyClient::from(client);
+    client.bsky_get_timeline(10, None).await.unwrap();
+    list(&mut client).await;
+
+    // Only app.bsky methods go to the AppView
+    let sent = &pds.requests("app.bsky.feed.getTimeline")[0];
+    assert_eq!(
+        sent.header("atproto-proxy"),
+        Some("did:web:api.bsky.app#bsky_appview")
+    );
+    assert_eq!(pds.requests(LIST_RECORDS)[0].header("atproto-proxy"), None);
+}
+
+#[tokio::test]
+async fn pool_max_idle_per_host() {
+    let pds = MockPds::start().await;
+    let mut pooled = login(ClientBuilder::default().service(pds.url().clone()))
+        .await
+        .unwrap();
+    requests(&mut pooled).await;
+    let mut unpooled = login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .pool_max_idle_per_host(0),
+    )
+    .await
+    .unwrap();
+    requests(&mut unpooled).await;
+
+    // The first login and the next three calls are the pooled client's, the second login and
+    // the last three the other's
+    let peers = pds
+        .requests(CREATE_SESSION)
+        .into_iter()
+        .chain(pds.requests(LIST_RECORDS))
+        .map(|request| request.peer)
+        .collect::<Vec<_>>();
+    let (pooled, unpooled) = (
+        [peers[0], peers[2], peers[3], peers[4]],
+        [peers[1], peers[5], peers[6], peers[7]],
+    );
+    assert!(pooled.iter().all(|peer| *peer == pooled[0]), "{pooled:?}");
+    for (i, peer) in unpooled.iter().enumerate() {
+        assert!(!unpooled[..i].contains(peer), "{unpooled:?}");
+    }
+}
+
+#[tokio::test]
+async fn http2_prior_knowledge() {
+    let pds = MockPds::start().await;
+    let mut client = login(
+        ClientBuilder::default()
+            .service(pds.url().clone())
+            .http2_prior_knowledge(true),
+    )
+    .await
+    .unwrap();
+    requests(&mut client).await;
+    let sent = pds.requests(LIST_RECORDS);
+    assert!(sent
+        .iter()
+        .all(|request| request.version == reqwest::Version::HTTP_2));
+    // Sharing one connection
+    assert!(sent.iter().all(|request| request.peer == sent[0].peer));
+
+    login(ClientBuilder::default().service(pds.url().clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        pds.requests(CREATE_SESSION)[1].version,
+        reqwest::Version::HTTP_11
+    );
+}
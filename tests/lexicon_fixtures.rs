@@ -0,0 +1,895 @@
+//! Responses of each endpoint, in the form bsky.social sends them, read into the types bisky gives
+//! them.
+//!
+//! The types are lenient and skip fields they don't model, so a misnamed field would be skipped
+//! silently and read as `None`. These are read strictly instead: every field skipped is noted,
+//! and must be one of the fields the type is known not to model.
+#![cfg(feature = "bsky")]
+
+use bisky::lexicon::app::bsky::actor::{Profile, ProfileViewDetailed};
+use bisky::lexicon::app::bsky::embed::{EmbedView, RecordView, ViewRecordUnion};
+use bisky::lexicon::app::bsky::feed::{
+    GetActorFeedsOutput, GetActorLikesOutput, GetFeedGeneratorOutput, GetFeedGeneratorsOutput,
+    GetLikesOutput, GetListFeedOutput, GetPostThreadOutput, GetTimelineOutput, LazyFeedOutput,
+    Post, ThreadViewPostEnum,
+};
+use bisky::lexicon::app::bsky::graph::GetFollowsOutput;
+use bisky::lexicon::app::bsky::notification::{
+    ListNotificationsOutput, NotificationReason, NotificationRecord,
+};
+use bisky::lexicon::com::atproto::identity::ResolveHandleOutput;
+use bisky::lexicon::com::atproto::label::KnownLabel;
+use bisky::lexicon::com::atproto::repo::{
+    Blob, BlobOutput, CreateRecordOutput, DescribeRepoOutput, ListRecordsOutput, Record,
+};
+use bisky::lexicon::com::atproto::server::{
+    CreateUserSession, DescribeServerOutput, RefreshUserSession,
+};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+
+/// A `serde_json::Value` deserializer that notes the path of every field the type skips.
+///
+/// Fields under a union tagged by `$type`, a flattened field or a custom `Deserialize` going
+/// through `serde_json::Value` are buffered by serde first, so they aren't seen.
+struct Strict<'a> {
+    value: Value,
+    /// Where `value` is, e.g. `feed[].post.author`
+    path: String,
+    skipped: &'a RefCell<Vec<String>>,
+}
+
+impl<'a> Strict<'a> {
+    fn child(&self, value: Value, path: String) -> Self {
+        Self {
+            value,
+            path,
+            skipped: self.skipped,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for Strict<'_> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Object(ref object) => {
+                let fields = StrictMap {
+                    parent: self.child(Value::Null, self.path.clone()),
+                    fields: object.clone().into_iter(),
+                    value: None,
+                };
+                visitor.visit_map(fields)
+            }
+            Value::Array(ref values) => {
+                let items = StrictSeq {
+                    parent: self.child(Value::Null, format!("{}[]", self.path)),
+                    items: values.clone().into_iter(),
+                };
+                visitor.visit_seq(items)
+            }
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.value.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.skipped.borrow_mut().push(self.path);
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct StrictMap<'a> {
+    /// The object's path, with no value
+    parent: Strict<'a>,
+    fields: <Map<String, Value> as IntoIterator>::IntoIter,
+    value: Option<(String, Value)>,
+}
+
+impl<'de> de::MapAccess<'de> for StrictMap<'_> {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, value)) = self.fields.next() else {
+            return Ok(None);
+        };
+        let parsed = seed.deserialize(Value::String(key.clone()))?;
+        self.value = Some((key, value));
+        Ok(Some(parsed))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (key, value) = self.value.take().expect("value before key");
+        let path = match self.parent.path.as_str() {
+            "" => key,
+            parent => format!("{parent}.{key}"),
+        };
+        seed.deserialize(self.parent.child(value, path))
+    }
+}
+
+struct StrictSeq<'a> {
+    /// The path of the array's items
+    parent: Strict<'a>,
+    items: std::vec::IntoIter<Value>,
+}
+
+impl<'de> de::SeqAccess<'de> for StrictSeq<'_> {
+    type Error = serde_json::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.items
+            .next()
+            .map(|item| seed.deserialize(self.parent.child(item, self.parent.path.clone())))
+            .transpose()
+    }
+}
+
+/// The text of the fixture of `nsid`
+fn fixture(nsid: &str) -> String {
+    let path = format!("{}/tests/fixtures/{nsid}.json", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"))
+}
+
+/// The fixture of `nsid`, read as `T`, checking that the fields skipped are `unmodelled`
+fn read<T: DeserializeOwned>(nsid: &str, unmodelled: &[&str]) -> T {
+    let json = fixture(nsid);
+    let value: Value = serde_json::from_str(&json).unwrap();
+
+    // As the client reads it
+    serde_json::from_str::<T>(&json).unwrap_or_else(|e| panic!("{nsid}: {e}"));
+
+    let skipped = RefCell::new(Vec::new());
+    let strict = Strict {
+        value,
+        path: String::new(),
+        skipped: &skipped,
+    };
+    let parsed = T::deserialize(strict).unwrap_or_else(|e| panic!("{nsid}: {e}"));
+
+    let mut skipped = skipped.into_inner();
+    skipped.sort();
+    skipped.dedup();
+    let mut unmodelled = unmodelled.to_vec();
+    unmodelled.sort();
+    assert_eq!(skipped, unmodelled, "fields of {nsid} skipped");
+    parsed
+}
+
+#[test]
+fn server() {
+    let session: CreateUserSession = read(
+        "com.atproto.server.createSession",
+        &["active", "didDoc", "emailAuthFactor", "emailConfirmed"],
+    );
+    assert_eq!(session.handle, "alice.bsky.social");
+    assert_eq!(session.email, "alice@example.com");
+    assert!(session.access_jwt.starts_with("eyJ"));
+
+    let session: RefreshUserSession =
+        read("com.atproto.server.refreshSession", &["active", "didDoc"]);
+    assert!(session.refresh_jwt.starts_with("eyJ"));
+
+    let server: DescribeServerOutput = read(
+        "com.atproto.server.describeServer",
+        &["contact", "links", "phoneVerificationRequired"],
+    );
+    assert_eq!(server.available_user_domains, [".bsky.social"]);
+    assert!(!server.invite_code_required);
+}
+
+#[test]
+fn identity() {
+    let resolved: ResolveHandleOutput = read("com.atproto.identity.resolveHandle", &[]);
+    assert_eq!(resolved.did, "did:plc:ewvi7nxzyoun6zhxrhs64oiz");
+}
+
+#[test]
+fn repo() {
+    let repo: DescribeRepoOutput = read("com.atproto.repo.describeRepo", &[]);
+    assert!(repo.handle_is_correct);
+    assert_eq!(repo.collections.len(), 4);
+    assert_eq!(repo.did_doc["id"], repo.did);
+
+    // Facets aren't a union, so the `$type` the official app gives them is dropped
+    let record: Record<Post> = read("com.atproto.repo.getRecord", &["value.facets[].$type"]);
+    assert_eq!(record.value.langs.as_deref(), Some(&["en".to_string()][..]));
+    let facets = record.value.facets.unwrap();
+    assert_eq!(
+        facets[0].slice(&record.value.text),
+        Some("https://atproto.com")
+    );
+
+    let records: ListRecordsOutput<Post> = read(
+        "com.atproto.repo.listRecords",
+        &["records[].value.facets[].$type"],
+    );
+    assert_eq!(records.records.len(), 2);
+    assert_eq!(records.cursor.as_deref(), Some("3lact5k2wcs2c"));
+    assert!(records.records[0].value.embed.is_some());
+
+    // A profile from before blob references took their current form, with a banner set since
+    let profile: Record<Profile> = read("com.atproto.repo.getRecord.profile", &[]);
+    let avatar = profile.value.avatar.as_ref().unwrap();
+    assert_eq!(
+        avatar.cid(),
+        "bafkreicq3c5qewyxnr3xxvjvmvnsdkw4xawjqbfpiusw5yuqhcydgczmzq"
+    );
+    assert_eq!(avatar.mime_type(), "image/jpeg");
+    assert_eq!(avatar.size(), None);
+    assert!(avatar.is_legacy());
+    let banner = profile.value.banner.as_ref().unwrap();
+    assert_eq!(
+        banner.cid(),
+        "bafkreiahkpcmamvdgydyflbdd5l7phwv5gnjvkxrgp5ijyz5egpe3dfmvu"
+    );
+    assert_eq!(banner.mime_type(), "image/png");
+    assert_eq!(banner.size(), Some(418204));
+    assert!(!banner.is_legacy());
+    // Both are written back in the current form, and read back as they were
+    let written = serde_json::to_value(&profile.value).unwrap();
+    assert_eq!(
+        written["avatar"],
+        json!({
+            "$type": "blob",
+            "ref": {"$link": "bafkreicq3c5qewyxnr3xxvjvmvnsdkw4xawjqbfpiusw5yuqhcydgczmzq"},
+            "mimeType": "image/jpeg",
+            "size": -1,
+        })
+    );
+    let fixture: Value =
+        serde_json::from_str(&fixture("com.atproto.repo.getRecord.profile")).unwrap();
+    assert_eq!(written["banner"], fixture["value"]["banner"]);
+    assert_eq!(Blob::find_all(&written), [avatar.clone(), banner.clone()]);
+    let reread: Profile = serde_json::from_value(written).unwrap();
+    assert!(reread.avatar.unwrap().is_legacy());
+
+    let created: CreateRecordOutput = read(
+        "com.atproto.repo.createRecord",
+        &["commit", "validationStatus"],
+    );
+    assert!(created.uri.starts_with("at://"));
+
+    let uploaded: BlobOutput = read("com.atproto.repo.uploadBlob", &[]);
+    assert_eq!(uploaded.blob.mime_type(), "image/jpeg");
+    assert_eq!(uploaded.blob.size(), Some(312345));
+}
+
+#[test]
+fn actor() {
+    let profile: ProfileViewDetailed = read(
+        "app.bsky.actor.getProfile",
+        &[
+            "associated",
+            "viewer.knownFollowers.followers[].associated",
+            "viewer.knownFollowers.followers[].createdAt",
+            "viewer.knownFollowers.followers[].viewer",
+        ],
+    );
+    assert_eq!(profile.display_name.as_deref(), Some("Alice"));
+    assert!(profile.banner.is_some());
+    assert_eq!(profile.followers_count, Some(1204));
+    assert_eq!(profile.follows_count, Some(310));
+    assert_eq!(profile.posts_count, Some(2291));
+    assert!(profile.created_at.is_some());
+    assert!(profile.indexed_at.is_some());
+    assert!(profile.pinned_post.is_some());
+    let viewer = profile.viewer.unwrap();
+    assert!(viewer.following.is_some());
+    assert_eq!(viewer.known_followers.unwrap().count, 1);
+}
+
+#[test]
+fn labeled_and_blocked_profiles() {
+    let profile: ProfileViewDetailed = read("app.bsky.actor.getProfile.labeled", &["associated"]);
+    let labels = profile
+        .labels
+        .iter()
+        .map(|label| (label.val.clone(), label.neg))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        [
+            (KnownLabel::NoUnauthenticated, false),
+            (KnownLabel::Sexual, false),
+            (KnownLabel::Other("spam".to_string()), true),
+            (KnownLabel::Other("rude".to_string()), false),
+        ]
+    );
+    // Self-labelled on the profile record, the others on the account by labelers
+    let own = &profile.labels[0];
+    assert_eq!(own.src, profile.did);
+    assert!(own.uri.ends_with("/app.bsky.actor.profile/self"));
+    assert!(own.cid.is_some());
+    assert!(own.sig.is_none() && own.ver.is_none());
+    let moderation = &profile.labels[1];
+    assert_eq!(moderation.src, "did:plc:ar7c4by46qjdydhdevvrndac");
+    assert_eq!(moderation.uri, profile.did);
+    assert_eq!(moderation.ver, Some(1));
+    assert_eq!(
+        moderation.sig.as_deref(),
+        Some(&(0..64).collect::<Vec<u8>>()[..])
+    );
+    assert!(profile.labels[3].exp.is_some());
+
+    // Blocked both ways, and muted, by the viewer and through lists
+    let profile: ProfileViewDetailed = read("app.bsky.actor.getProfile.blocked", &["associated"]);
+    let viewer = profile.viewer.unwrap();
+    assert!(viewer.muted && viewer.blocked_by);
+    assert!(viewer.blocking.unwrap().contains("/app.bsky.graph.block/"));
+    assert!(viewer.following.is_none() && viewer.known_followers.is_none());
+    let muted_by = viewer.muted_by_list.unwrap();
+    assert_eq!(muted_by.name, "Muted accounts");
+    assert!(muted_by.viewer.unwrap().muted);
+    let blocking_by = viewer.blocking_by_list.unwrap();
+    assert_eq!(blocking_by.purpose, "app.bsky.graph.defs#modlist");
+    assert_eq!(blocking_by.list_item_count, Some(40));
+    assert!(blocking_by.viewer.unwrap().blocked.is_some());
+    // Counts are still there
+    assert_eq!(profile.followers_count, Some(1204));
+    assert!(profile.pinned_post.is_none());
+}
+
+#[test]
+fn feed() {
+    // Authors in feeds are only read for their DID, handle and labels
+    let author = ["associated", "avatar", "createdAt", "displayName", "viewer"];
+    let mut unmodelled: Vec<String> = author
+        .iter()
+        .map(|field| format!("feed[].post.author.{field}"))
+        .collect();
+    unmodelled.extend(
+        [
+            "feed[].feedContext",
+            "feed[].post.record.facets[].$type",
+            "feed[].reply",
+        ]
+        .map(String::from),
+    );
+    let unmodelled: Vec<&str> = unmodelled.iter().map(String::as_str).collect();
+    let timeline: GetTimelineOutput = read("app.bsky.feed.getTimeline", &unmodelled);
+    assert_eq!(timeline.feed.len(), 10);
+    assert!(timeline.cursor.is_some());
+    let post = &timeline.feed[0].post;
+    assert_eq!(post.reply_count, Some(1));
+    assert_eq!(post.repost_count, Some(2));
+    assert_eq!(post.like_count, Some(5));
+    assert_eq!(post.quote_count, Some(0));
+    assert!(post.record.reply.is_some());
+    assert!(post.viewer.is_some());
+    assert!(timeline.feed[1].reason.is_some());
+    assert!(matches!(
+        timeline.feed[2].post.embed,
+        Some(EmbedView::Images(ref view)) if view.images[0].full_size.contains("feed_fullsize")
+    ));
+
+    // Every embed view, by the variant it's read into
+    let embeds = timeline.feed[3..]
+        .iter()
+        .map(|item| item.post.embed.as_ref().unwrap())
+        .collect::<Vec<_>>();
+    let EmbedView::External(external) = embeds[0] else {
+        panic!("{:?}", embeds[0]);
+    };
+    assert_eq!(external.external.uri, "https://atproto.com/guides/overview");
+    assert!(external.external.thumb.is_some());
+    // A quote of a post with a picture, which comes with the quoted post's own embeds
+    let EmbedView::Record(RecordView {
+        record: ViewRecordUnion::Record(quoted),
+    }) = embeds[1]
+    else {
+        panic!("{:?}", embeds[1]);
+    };
+    assert_eq!(quoted.author.handle, "bob.bsky.social");
+    assert_eq!(quoted.like_count, Some(12));
+    assert!(quoted.value["embed"].is_object());
+    assert!(matches!(quoted.embeds[..], [EmbedView::Images(_)]));
+    let EmbedView::RecordWithMedia(with_media) = embeds[2] else {
+        panic!("{:?}", embeds[2]);
+    };
+    assert!(matches!(
+        with_media.record.record,
+        ViewRecordUnion::Record(ref quoted) if quoted.embeds.is_empty()
+    ));
+    assert!(matches!(*with_media.media, EmbedView::Images(_)));
+    #[cfg(feature = "video")]
+    assert!(matches!(
+        embeds[3],
+        EmbedView::Video(video) if video.playlist.ends_with("playlist.m3u8")
+    ));
+    #[cfg(not(feature = "video"))]
+    assert!(matches!(embeds[3], EmbedView::Unknown(_)));
+    // Quotes of posts that can't be shown
+    let quoted = embeds[4..]
+        .iter()
+        .map(|embed| match embed {
+            EmbedView::Record(view) => &view.record,
+            _ => panic!("{embed:?}"),
+        })
+        .collect::<Vec<_>>();
+    assert!(matches!(quoted[0], ViewRecordUnion::NotFound(view) if view.not_found));
+    assert!(matches!(
+        quoted[1],
+        ViewRecordUnion::Blocked(view) if view.blocked && view.author.did == "did:plc:4llrhdclvdlmmynkwsmg5tdc"
+    ));
+    assert!(matches!(quoted[2], ViewRecordUnion::Detached(view) if view.detached));
+
+    // Skimmed, then decoded in full into the same items as the eager read
+    let lazy: LazyFeedOutput = serde_json::from_str(&fixture("app.bsky.feed.getTimeline")).unwrap();
+    assert_eq!(lazy.cursor, timeline.cursor);
+    assert_eq!(lazy.feed.len(), timeline.feed.len());
+    for (lazy, eager) in lazy.feed.iter().zip(&timeline.feed) {
+        assert_eq!(lazy.uri, eager.post.uri);
+        assert_eq!(lazy.author_did, eager.post.author.did);
+        assert_eq!(lazy.indexed_at, eager.post.indexed_at);
+        assert_eq!(lazy.text, eager.post.record.text);
+        let full = lazy.decode_full().unwrap();
+        assert_eq!(format!("{full:?}"), format!("{eager:?}"));
+    }
+
+    let thread: GetPostThreadOutput = read("app.bsky.feed.getPostThread", &[]);
+    let ThreadViewPostEnum::ThreadViewPost(thread) = thread.thread else {
+        panic!("not a thread");
+    };
+    let replies = thread.replies.unwrap();
+    assert!(matches!(replies[0], ThreadViewPostEnum::ThreadViewPost(_)));
+    assert!(matches!(replies[1], ThreadViewPostEnum::NotFoundPost(_)));
+
+    let likes: GetLikesOutput = read(
+        "app.bsky.feed.getLikes",
+        &[
+            "likes[].actor.associated",
+            "likes[].actor.createdAt",
+            "likes[].actor.viewer",
+        ],
+    );
+    assert_eq!(likes.likes[0].actor.handle, "bob.bsky.social");
+    assert!(likes.cursor.is_some());
+}
+
+#[test]
+fn actor_likes_and_list_feed() {
+    let author = ["associated", "avatar", "createdAt", "displayName", "viewer"];
+    let mut unmodelled: Vec<String> = author
+        .iter()
+        .map(|field| format!("feed[].post.author.{field}"))
+        .collect();
+    let likes: GetActorLikesOutput = read(
+        "app.bsky.feed.getActorLikes",
+        &unmodelled.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    assert_eq!(likes.cursor.as_deref(), Some("3lad2f5uqbk2x"));
+    assert_eq!(likes.feed.len(), 2);
+    // Liked by the actor, who is the viewer
+    for item in &likes.feed {
+        let like = item.post.viewer.as_ref().unwrap().like.as_deref();
+        assert!(like.unwrap().contains("/app.bsky.feed.like/"));
+        assert!(item.reason.is_none());
+    }
+    assert!(matches!(
+        likes.feed[1].post.embed,
+        Some(EmbedView::External(_))
+    ));
+
+    // A list feed has replies and reposts like the timeline
+    unmodelled.extend(["feed[].post.record.facets[].$type", "feed[].reply"].map(String::from));
+    let list: GetListFeedOutput = read(
+        "app.bsky.feed.getListFeed",
+        &unmodelled.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    assert!(list.cursor.is_some());
+    assert!(list.feed[0].post.record.reply.is_some());
+    assert!(list.feed[1].reason.is_some());
+}
+
+/// The fields of a generator view that aren't read. Creators are read as profile views, without
+/// the fields of newer ones.
+const GENERATOR: [&str; 5] = [
+    "contentMode",
+    "creator.associated",
+    "creator.createdAt",
+    "creator.viewer",
+    "descriptionFacets",
+];
+
+#[test]
+fn feed_generators() {
+    let unmodelled = |at: &str, fields: &[&str]| -> Vec<String> {
+        fields.iter().map(|field| format!("{at}.{field}")).collect()
+    };
+
+    let single = unmodelled("view", &GENERATOR);
+    let single: Vec<&str> = single.iter().map(String::as_str).collect();
+    let output: GetFeedGeneratorOutput = read("app.bsky.feed.getFeedGenerator", &single);
+    assert!(output.is_online && output.is_valid);
+    let discover = &output.view;
+    assert_eq!(
+        discover.uri,
+        "at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.generator/whats-hot"
+    );
+    assert_eq!(discover.did, "did:web:discover.bsky.app");
+    assert_eq!(discover.creator.handle, "bsky.app");
+    assert_eq!(discover.display_name, "Discover");
+    assert_eq!(discover.like_count, 66291);
+    assert!(discover.accepts_interactions);
+    assert!(discover.viewer.as_ref().unwrap().like.is_some());
+
+    let many = unmodelled("feeds[]", &GENERATOR);
+    let many: Vec<&str> = many.iter().map(String::as_str).collect();
+    let output: GetFeedGeneratorsOutput = read("app.bsky.feed.getFeedGenerators", &many);
+    let names = output
+        .feeds
+        .iter()
+        .map(|feed| feed.display_name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Discover", "What's Hot Videos"]);
+    // Neither liked nor taking interactions
+    let videos = &output.feeds[1];
+    assert!(videos.viewer.as_ref().unwrap().like.is_none());
+    assert!(!videos.accepts_interactions);
+
+    // Without the feeds that have rich text descriptions
+    let actor = unmodelled("feeds[]", &GENERATOR[..4]);
+    let actor: Vec<&str> = actor.iter().map(String::as_str).collect();
+    let output: GetActorFeedsOutput = read("app.bsky.feed.getActorFeeds", &actor);
+    assert!(output.cursor.is_some());
+    assert_eq!(output.feeds.len(), 2);
+    let friends = &output.feeds[1];
+    assert_eq!(friends.display_name, "Popular With Friends");
+    assert_eq!(friends.labels[0].val.as_str(), "!no-unauthenticated");
+}
+
+#[cfg(feature = "unspecced")]
+#[test]
+fn unspecced() {
+    use bisky::lexicon::app::bsky::unspecced::{
+        GetPopularFeedGeneratorsOutput, GetTrendingTopicsOutput,
+    };
+
+    let unmodelled: Vec<String> = GENERATOR
+        .iter()
+        .map(|field| format!("feeds[].{field}"))
+        .collect();
+    let unmodelled: Vec<&str> = unmodelled.iter().map(String::as_str).collect();
+    let popular: GetPopularFeedGeneratorsOutput =
+        read("app.bsky.unspecced.getPopularFeedGenerators", &unmodelled);
+    assert_eq!(popular.cursor.as_deref(), Some("2"));
+    assert_eq!(popular.feeds[0].display_name, "Discover");
+    assert_eq!(popular.feeds[1].like_count, 3012);
+
+    let trending: GetTrendingTopicsOutput = read("app.bsky.unspecced.getTrendingTopics", &[]);
+    let topics = trending
+        .topics
+        .iter()
+        .map(|topic| topic.topic.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(topics, ["Nintendo Switch 2", "#atproto", "Super Bowl"]);
+    assert_eq!(trending.topics[1].link.as_deref(), Some("/hashtag/atproto"));
+    let described = &trending.topics[2];
+    assert_eq!(described.display_name.as_deref(), Some("Super Bowl LIX"));
+    assert_eq!(described.description.as_deref(), Some("Chiefs vs. Eagles"));
+    assert!(trending.topics[0].display_name.is_none());
+    // Feeds and starter packs, by their paths on bsky.app
+    assert_eq!(trending.suggested.len(), 3);
+    assert!(trending.suggested[..2].iter().all(|feed| feed
+        .link
+        .as_deref()
+        .unwrap()
+        .contains("/feed/")));
+}
+
+#[test]
+fn graph() {
+    let profile = ["associated", "createdAt", "viewer"];
+    let unmodelled: Vec<String> = ["subject", "follows[]"]
+        .iter()
+        .flat_map(|at| profile.iter().map(move |field| format!("{at}.{field}")))
+        .collect();
+    let unmodelled: Vec<&str> = unmodelled.iter().map(String::as_str).collect();
+    let follows: GetFollowsOutput = read("app.bsky.graph.getFollows", &unmodelled);
+    assert_eq!(follows.subject.handle, "alice.bsky.social");
+    assert_eq!(
+        follows.follows[0].description.as_deref(),
+        Some("Posting about protocols")
+    );
+    assert!(follows.follows[0].indexed_at.is_some());
+}
+
+#[test]
+fn notification() {
+    let notifications: ListNotificationsOutput<NotificationRecord> = read(
+        "app.bsky.notification.listNotifications",
+        &[
+            "notifications[].author.associated",
+            "notifications[].author.createdAt",
+            "notifications[].author.viewer",
+        ],
+    );
+    assert!(notifications.seen_at.is_some());
+    assert_eq!(notifications.priority, Some(false));
+    let like = &notifications.notifications[0];
+    assert!(like.reason_subject.is_some());
+    assert!(!like.is_read);
+
+    // One of each reason, in the order the fixture has them
+    let reasons = notifications
+        .notifications
+        .iter()
+        .map(|notification| (&notification.reason, &notification.record))
+        .collect::<Vec<_>>();
+    let other = NotificationReason::Other("like-via-repost".to_string());
+    assert!(matches!(
+        reasons[..],
+        [
+            (NotificationReason::Like, NotificationRecord::Like(_)),
+            (NotificationReason::Repost, NotificationRecord::Repost(_)),
+            (NotificationReason::Mention, NotificationRecord::Post(_)),
+            (NotificationReason::Reply, NotificationRecord::Post(_)),
+            (NotificationReason::Quote, NotificationRecord::Post(_)),
+            (NotificationReason::StarterpackJoined, NotificationRecord::Unknown(_)),
+            (reason, NotificationRecord::Like(_)),
+            (NotificationReason::Follow, NotificationRecord::Follow(_)),
+        ] if *reason == other
+    ));
+    for (reason, _) in &reasons {
+        assert_eq!(NotificationReason::from(reason.to_string()), **reason);
+    }
+
+    let NotificationRecord::Post(reply) = &notifications.notifications[3].record else {
+        unreachable!()
+    };
+    assert!(reply.reply.is_some());
+    let NotificationRecord::Post(quote) = &notifications.notifications[4].record else {
+        unreachable!()
+    };
+    assert!(quote.embed.is_some());
+    let NotificationRecord::Unknown(profile) = &notifications.notifications[5].record else {
+        unreachable!()
+    };
+    assert_eq!(
+        profile["joinedViaStarterPack"]["uri"],
+        notifications.notifications[5]
+            .reason_subject
+            .as_deref()
+            .unwrap()
+    );
+}
+
+#[cfg(feature = "chat")]
+#[test]
+fn chat_send_message() {
+    use bisky::lexicon::chat::bsky::convo::{MessageView, SendMessageBatchOutput};
+
+    let sent: MessageView = read("chat.bsky.convo.sendMessage", &[]);
+    assert_eq!(sent.text, "have you seen this?");
+    assert_eq!(sent.sender.did, "did:plc:bwmqa5dmxqnmyd7ir5hkz5cj");
+    assert!(sent.reactions.is_empty());
+    // Kept as it comes, and readable as the embed of a post
+    let embed = serde_json::from_value::<EmbedView>(sent.embed.unwrap()).unwrap();
+    let EmbedView::Record(RecordView {
+        record: ViewRecordUnion::Record(shared),
+    }) = embed
+    else {
+        unreachable!()
+    };
+    assert_eq!(shared.author.handle, "bsky.app");
+    assert_eq!(shared.like_count, Some(5231));
+
+    let batch: SendMessageBatchOutput = read("chat.bsky.convo.sendMessageBatch", &[]);
+    assert_eq!(batch.items.len(), 2);
+    assert_eq!(batch.items[0].facets.as_ref().unwrap().len(), 1);
+    assert!(batch.items[0].embed.is_none());
+    // A post deleted since, still sent as a placeholder
+    let embed = batch.items[1].embed.clone().unwrap();
+    assert!(matches!(
+        serde_json::from_value::<EmbedView>(embed).unwrap(),
+        EmbedView::Record(RecordView {
+            record: ViewRecordUnion::NotFound(_)
+        })
+    ));
+}
+
+#[cfg(feature = "chat")]
+#[test]
+fn chat_get_messages() {
+    use bisky::lexicon::chat::bsky::convo::{GetMessagesOutput, MessageViewEnum};
+
+    let output: GetMessagesOutput = read("chat.bsky.convo.getMessages", &[]);
+    assert_eq!(output.cursor.as_deref(), Some("2222223a3jx4c"));
+    let [MessageViewEnum::Message(shared), MessageViewEnum::Deleted(deleted), MessageViewEnum::Message(greeting)] =
+        &output.messages[..]
+    else {
+        panic!("{:?}", output.messages)
+    };
+
+    // The embedded post, and the reactions to it as they came
+    let Some(EmbedView::Record(RecordView {
+        record: ViewRecordUnion::Record(post),
+    })) = shared
+        .embed
+        .clone()
+        .map(|embed| serde_json::from_value(embed).unwrap())
+    else {
+        unreachable!()
+    };
+    assert_eq!(post.author.handle, "bsky.app");
+    let reactions = shared
+        .reactions
+        .iter()
+        .map(|reaction| reaction["value"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(reactions, ["👀", "❤️"]);
+    assert_eq!(
+        shared.reactions[0],
+        json!({
+            "value": "👀",
+            "sender": {"did": "did:plc:ragtjsm2j2vknwkz3zp4oxrd"},
+            "createdAt": "2024-11-27T09:15:20.338Z",
+        })
+    );
+
+    // Only who sent it and when are left of a deleted message
+    assert_eq!(deleted.id, "3lbvbzqpwm22s");
+    assert_eq!(deleted.sender.did, "did:plc:ragtjsm2j2vknwkz3zp4oxrd");
+    assert!(greeting.embed.is_none());
+    assert!(greeting.reactions.is_empty());
+}
+
+#[test]
+fn plc_audit_log() {
+    use bisky::identity::PlcLogEntry;
+
+    let log: Vec<PlcLogEntry> = read("plc.directory.log.audit", &[]);
+    assert_eq!(log.len(), 4);
+    assert!(log
+        .iter()
+        .all(|entry| entry.did == "did:plc:ewvi7nxzyoun6zhxrhs64oiz"));
+
+    // The legacy create names the handle and PDS directly
+    let create = &log[0].operation;
+    assert_eq!(create.kind, "create");
+    assert!(create.prev.is_none());
+    assert_eq!(create.handles(), ["atproto.bsky.social"]);
+    assert_eq!(create.pds_endpoint(), Some("https://bsky.social"));
+    assert!(create.rotation_keys.is_empty());
+    assert!(create.extra.contains_key("recoveryKey"));
+
+    // A change later overridden by a rotation key of higher priority, both following the same
+    // operation
+    let nullified = &log[2];
+    assert!(nullified.nullified);
+    assert_eq!(
+        nullified.operation.pds_endpoint(),
+        Some("https://pds.attacker.example")
+    );
+    assert_eq!(nullified.operation.prev, log[3].operation.prev);
+    assert_eq!(
+        nullified.operation.prev.as_deref(),
+        Some(log[1].cid.as_str())
+    );
+
+    // What is in effect leaves it out
+    let history = log
+        .iter()
+        .filter(|entry| !entry.nullified)
+        .collect::<Vec<_>>();
+    assert_eq!(history.len(), 3);
+    for pair in history.windows(2) {
+        assert_eq!(
+            pair[1].operation.prev.as_deref(),
+            Some(pair[0].cid.as_str())
+        );
+    }
+    let current = &history[2].operation;
+    assert!(!current.is_tombstone());
+    assert_eq!(current.handles(), ["atproto.com"]);
+    assert_eq!(
+        current.pds_endpoint(),
+        Some("https://enoki.us-east.host.bsky.network")
+    );
+    assert_eq!(
+        history[2].created_at.to_rfc3339(),
+        "2024-02-19T18:40:02.771+00:00"
+    );
+
+    // Written back as the directory sent it
+    let value: Value = serde_json::from_str(&fixture("plc.directory.log.audit")).unwrap();
+    assert_eq!(serde_json::to_value(&log).unwrap(), value);
+}
+
+#[test]
+fn labeler_services() {
+    use bisky::lexicon::app::bsky::labeler::{
+        GetServicesOutput, LabelerViewEnum, BSKY_MODERATION_LABELER,
+    };
+    use bisky::lexicon::com::atproto::label::{LabelBlurs, LabelDefaultSetting, LabelSeverity};
+
+    let output: GetServicesOutput = read("app.bsky.labeler.getServices", &[]);
+    let [LabelerViewEnum::Detailed(labeler)] = &output.views[..] else {
+        panic!("{:?}", output.views)
+    };
+    assert_eq!(labeler.creator.did, BSKY_MODERATION_LABELER);
+    assert_eq!(labeler.creator.handle, "moderation.bsky.app");
+    assert_eq!(labeler.like_count, 41923);
+    assert_eq!(labeler.policies.label_values.len(), 24);
+    assert_eq!(labeler.policies.label_value_definitions.len(), 4);
+
+    let spam = labeler.definition("spam").unwrap();
+    assert_eq!(spam.severity, LabelSeverity::Inform);
+    assert_eq!(spam.blurs, LabelBlurs::Content);
+    assert_eq!(spam.default_setting, LabelDefaultSetting::Hide);
+    assert!(!spam.adult_only);
+    let figurative = labeler.definition("sexual-figurative").unwrap();
+    assert_eq!(figurative.severity, LabelSeverity::None);
+    assert_eq!(figurative.blurs, LabelBlurs::Media);
+    assert_eq!(figurative.default_setting, LabelDefaultSetting::Ignore);
+    assert!(figurative.adult_only);
+    // Only the global values, which the labeler doesn't define
+    assert!(labeler.definition("porn").is_none());
+
+    // Every locale, each picked by its language
+    let impersonation = labeler.definition("impersonation").unwrap();
+    let langs = impersonation
+        .locales
+        .iter()
+        .map(|locale| locale.lang.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(langs, ["en", "ja", "pt-BR"]);
+    assert_eq!(impersonation.strings("ja").unwrap().name, "なりすまし");
+    assert_eq!(
+        impersonation.strings("pt-BR").unwrap().description,
+        "Fingir ser outra pessoa sem permissão."
+    );
+    // English when there is nothing in the language asked for
+    assert_eq!(impersonation.strings("de").unwrap().name, "Impersonation");
+    assert_eq!(
+        labeler
+            .definition("rude")
+            .unwrap()
+            .strings("en")
+            .unwrap()
+            .description,
+        "Rude or impolite, including crude language and disrespectful comments, without \
+         constructive purpose."
+    );
+}
@@ -0,0 +1,218 @@
+//! What a `Client` reports to its `Metrics` against `MockPds`.
+#![cfg(feature = "test-utils")]
+
+use bisky::atproto::{Client, ClientBuilder, Metrics};
+use bisky::errors::{ApiError, BiskyError};
+use bisky::lexicon::com::atproto::repo::{BlobOutput, CreateRecordOutput, Record};
+use bisky::mock::{MockPds, MOCK_DID, MOCK_HANDLE, MOCK_PASSWORD};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+const NOTES: &str = "com.example.note";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Event {
+    Complete(String, u16),
+    Failed(String),
+    ApiError(String, String, Option<u16>),
+    Refresh(bool),
+}
+
+/// Every event in order, with the bytes of each complete request
+#[derive(Default)]
+struct Recorder {
+    events: Mutex<Vec<Event>>,
+    bytes: Mutex<Vec<usize>>,
+}
+
+impl Recorder {
+    fn take(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.events.lock())
+    }
+}
+
+impl Metrics for Recorder {
+    fn on_request_complete(&self, nsid: &str, status: u16, duration: Duration, bytes: usize) {
+        assert!(duration < Duration::from_secs(10));
+        self.events
+            .lock()
+            .push(Event::Complete(nsid.to_string(), status));
+        self.bytes.lock().push(bytes);
+    }
+
+    fn on_request_failed(&self, nsid: &str, _: Duration, _: &reqwest::Error) {
+        self.events.lock().push(Event::Failed(nsid.to_string()));
+    }
+
+    fn on_api_error(&self, error: &ApiError) {
+        let nsid = error.context.as_ref().unwrap().nsid.clone();
+        self.events
+            .lock()
+            .push(Event::ApiError(nsid, error.error.clone(), error.status()));
+    }
+
+    fn on_token_refresh(&self, result: Result<(), &BiskyError>) {
+        self.events.lock().push(Event::Refresh(result.is_ok()));
+    }
+}
+
+fn complete(nsid: &str, status: u16) -> Event {
+    Event::Complete(nsid.to_string(), status)
+}
+
+fn api_error(nsid: &str, error: &str, status: u16) -> Event {
+    Event::ApiError(nsid.to_string(), error.to_string(), Some(status))
+}
+
+async fn login(pds: &MockPds, recorder: &Arc<Recorder>) -> Client {
+    ClientBuilder::default()
+        .service(pds.url().clone())
+        .metrics(recorder.clone())
+        .login(MOCK_HANDLE, MOCK_PASSWORD)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn success() {
+    let pds = MockPds::start().await;
+    let recorder = Arc::new(Recorder::default());
+    let mut client = login(&pds, &recorder).await;
+    assert_eq!(
+        recorder.take(),
+        [complete("com.atproto.server.createSession", 200)]
+    );
+
+    pds.insert_record(MOCK_DID, NOTES, Some("a"), &json!({"text": "hi"}));
+    let _: Record<Value> = client.repo_get_record(MOCK_DID, NOTES, "a").await.unwrap();
+    assert_eq!(
+        recorder.take(),
+        [complete("com.atproto.repo.getRecord", 200)]
+    );
+
+    // The bytes of the body, here a blob sent and read back
+    let blob = vec![7; 100];
+    let uploaded: BlobOutput = client
+        .repo_upload_blob(&blob, "application/octet-stream")
+        .await
+        .unwrap();
+    client
+        .sync_get_blob(MOCK_DID, uploaded.blob.cid())
+        .await
+        .unwrap();
+    assert_eq!(
+        recorder.take(),
+        [
+            complete("com.atproto.repo.uploadBlob", 200),
+            complete("com.atproto.sync.getBlob", 200),
+        ]
+    );
+    let bytes = recorder.bytes.lock().clone();
+    assert!(bytes.iter().all(|&bytes| bytes > 0));
+    assert_eq!(bytes.last(), Some(&100));
+}
+
+#[tokio::test]
+async fn api_errors() {
+    let pds = MockPds::start().await;
+    let recorder = Arc::new(Recorder::default());
+    let mut client = login(&pds, &recorder).await;
+    recorder.take();
+
+    let missing = client
+        .repo_get_record::<Value>(MOCK_DID, NOTES, "missing")
+        .await;
+    assert!(missing.is_err());
+    assert_eq!(
+        recorder.take(),
+        [
+            complete("com.atproto.repo.getRecord", 400),
+            api_error("com.atproto.repo.getRecord", "RecordNotFound", 400),
+        ]
+    );
+
+    // Including a failed login
+    let wrong = ClientBuilder::default()
+        .service(pds.url().clone())
+        .metrics(recorder.clone())
+        .login(MOCK_HANDLE, "wrong")
+        .await;
+    assert!(wrong.is_err());
+    assert_eq!(
+        recorder.take(),
+        [
+            complete("com.atproto.server.createSession", 401),
+            api_error(
+                "com.atproto.server.createSession",
+                "AuthenticationRequired",
+                401
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn refresh_and_retry() {
+    let pds = MockPds::start().await;
+    let recorder = Arc::new(Recorder::default());
+    let mut client = login(&pds, &recorder).await;
+    recorder.take();
+
+    pds.expire_access_tokens();
+    client
+        .repo_create_record::<CreateRecordOutput, _>(MOCK_DID, NOTES, json!({"text": "hi"}))
+        .await
+        .unwrap();
+    assert_eq!(
+        recorder.take(),
+        [
+            complete("com.atproto.repo.createRecord", 400),
+            api_error("com.atproto.repo.createRecord", "ExpiredToken", 400),
+            complete("com.atproto.server.refreshSession", 200),
+            Event::Refresh(true),
+            complete("com.atproto.repo.createRecord", 200),
+        ]
+    );
+
+    // A refresh that fails is reported too
+    pds.token_lifetimes(Duration::ZERO, Duration::ZERO);
+    let mut client = login(&pds, &recorder).await;
+    recorder.take();
+    assert!(client
+        .repo_create_record::<CreateRecordOutput, _>(MOCK_DID, NOTES, json!({"text": "hi"}))
+        .await
+        .is_err());
+    assert_eq!(
+        recorder.take(),
+        [
+            complete("com.atproto.repo.createRecord", 400),
+            api_error("com.atproto.repo.createRecord", "ExpiredToken", 400),
+            complete("com.atproto.server.refreshSession", 400),
+            api_error("com.atproto.server.refreshSession", "ExpiredToken", 400),
+            Event::Refresh(false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn no_response() {
+    let pds = MockPds::start().await;
+    let url = pds.url().clone();
+    drop(pds);
+
+    let recorder = Arc::new(Recorder::default());
+    let login = ClientBuilder::default()
+        .service(url)
+        .metrics(recorder.clone())
+        .login(MOCK_HANDLE, MOCK_PASSWORD)
+        .await;
+    assert!(matches!(login, Err(BiskyError::ReqwestError(_))));
+    assert_eq!(
+        recorder.take(),
+        [Event::Failed(
+            "com.atproto.server.createSession".to_string()
+        )]
+    );
+}
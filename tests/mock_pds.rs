@@ -0,0 +1,1089 @@
+//! A real `Client` against `MockPds`: logging in, refreshing an expired session and working
+//! with records.
+#![cfg(feature = "test-utils")]
+
+use bisky::atproto::ClientBuilder;
+use bisky::errors::BiskyError;
+use bisky::identity::resolve_handle;
+use bisky::lexicon::com::atproto::repo::{CreateRecordOutput, Record};
+use bisky::mock::{MockPds, MOCK_DID, MOCK_HANDLE, MOCK_PASSWORD};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const NOTES: &str = "com.example.note";
+
+#[tokio::test]
+async fn login() {
+    let pds = MockPds::start().await;
+
+    let client = pds.login().await.unwrap();
+    let session = client.session().unwrap();
+    assert_eq!(session.did, MOCK_DID);
+    assert_eq!(session.handle, MOCK_HANDLE);
+    assert_eq!(client.handle().as_deref(), Some(MOCK_HANDLE));
+    assert_eq!(pds.calls("com.atproto.server.createSession"), 1);
+
+    // By DID too
+    ClientBuilder::default()
+        .service(pds.url().clone())
+        .login(MOCK_DID, MOCK_PASSWORD)
+        .await
+        .unwrap();
+
+    let wrong = ClientBuilder::default()
+        .service(pds.url().clone())
+        .login(MOCK_HANDLE, "wrong")
+        .await;
+    assert!(matches!(wrong, Err(BiskyError::BadCredentials)));
+}
+
+#[tokio::test]
+async fn refresh_on_expired_token() {
+    let pds = MockPds::start().await;
+    let mut client = pds.login().await.unwrap();
+    let before = client.session().unwrap();
+
+    pds.expire_access_tokens();
+    let created: CreateRecordOutput = client
+        .repo_create_record(MOCK_DID, NOTES, json!({"text": "after refresh"}))
+        .await
+        .unwrap();
+    assert!(created
+        .uri
+        .starts_with(&format!("at://{MOCK_DID}/{NOTES}/")));
+
+    // One refresh, then the write retried once with the new token
+    assert_eq!(pds.calls("com.atproto.server.refreshSession"), 1);
+    assert_eq!(pds.calls("com.atproto.repo.createRecord"), 2);
+    assert_eq!(pds.created_records(NOTES).len(), 1);
+    let after = client.session().unwrap();
+    assert_ne!(after.access_jwt(), before.access_jwt());
+    assert_ne!(after.refresh_jwt(), before.refresh_jwt());
+
+    // And the new token keeps working without another refresh
+    client
+        .repo_create_record::<CreateRecordOutput, _>(MOCK_DID, NOTES, json!({"text": "again"}))
+        .await
+        .unwrap();
+    assert_eq!(pds.calls("com.atproto.server.refreshSession"), 1);
+}
+
+#[tokio::test]
+async fn expired_refresh_token() {
+    let pds = MockPds::start().await;
+    pds.token_lifetimes(Duration::ZERO, Duration::ZERO);
+    let mut client = pds.login().await.unwrap();
+
+    let result = client
+        .repo_create_record::<CreateRecordOutput, _>(MOCK_DID, NOTES, json!({"text": "never"}))
+        .await;
+    assert!(result.is_err());
+    assert_eq!(pds.calls("com.atproto.server.refreshSession"), 1);
+    assert!(pds.created_records(NOTES).is_empty());
+}
+
+#[tokio::test]
+async fn records() {
+    let pds = MockPds::start().await;
+    let mut client = pds.login().await.unwrap();
+
+    let created: CreateRecordOutput = client
+        .repo_create_record(MOCK_DID, NOTES, json!({"text": "first"}))
+        .await
+        .unwrap();
+    let rkey = created.uri.rsplit('/').next().unwrap().to_string();
+
+    let record: Record<Value> = client
+        .repo_get_record(MOCK_HANDLE, NOTES, &rkey)
+        .await
+        .unwrap();
+    assert_eq!(record.uri, created.uri);
+    assert_eq!(record.cid, created.cid);
+    assert_eq!(record.value["text"], "first");
+
+    let (uri, _) = pds.insert_record(MOCK_DID, NOTES, Some("second"), &json!({"text": "second"}));
+    assert_eq!(uri, format!("at://{MOCK_DID}/{NOTES}/second"));
+    assert_eq!(pds.records(MOCK_DID, NOTES).len(), 2);
+
+    client
+        .repo_delete_record(MOCK_DID, NOTES, &rkey)
+        .await
+        .unwrap();
+    assert_eq!(
+        pds.records(MOCK_DID, NOTES).keys().collect::<Vec<_>>(),
+        ["second"]
+    );
+    // Still among what was created, with the `$type` the PDS gave it
+    assert_eq!(
+        pds.created_records(NOTES),
+        [json!({"$type": NOTES, "text": "first"})]
+    );
+
+    let missing = client
+        .repo_get_record::<Value>(MOCK_DID, NOTES, &rkey)
+        .await;
+    assert!(missing.is_err());
+}
+
+#[tokio::test]
+async fn raw_records() {
+    use futures::TryStreamExt;
+    use serde_json::value::RawValue;
+
+    let pds = MockPds::start().await;
+    let mut client = pds.login().await.unwrap();
+    for i in 0..150 {
+        let note = json!({"text": format!("note {i}"), "n": i, "tags": ["a", {"b": 2.5}]});
+        pds.insert_record(MOCK_DID, NOTES, Some(&format!("{i:03}")), &note);
+    }
+    let parsed = |raw: &RawValue| serde_json::from_str::<Value>(raw.get()).unwrap();
+
+    // The same record either way, the raw one as the text the PDS sent
+    let value: Record<Value> = client
+        .repo_get_record(MOCK_DID, NOTES, "042")
+        .await
+        .unwrap();
+    let raw: Record<Box<RawValue>> = client
+        .repo_get_record(MOCK_DID, NOTES, "042")
+        .await
+        .unwrap();
+    assert_eq!((&raw.uri, &raw.cid), (&value.uri, &value.cid));
+    assert_eq!(value.value["text"], "note 42");
+    assert_eq!(parsed(&raw.value), value.value);
+
+    let (values, cursor) = client
+        .repo_list_records::<Value>(MOCK_DID, NOTES, 1000, false, None)
+        .await
+        .unwrap();
+    assert_eq!((values.len(), cursor), (150, None));
+    let (raw, cursor) = client
+        .repo_list_records::<Box<RawValue>>(MOCK_DID, NOTES, 1000, false, None)
+        .await
+        .unwrap();
+    assert_eq!(cursor, None);
+    assert_eq!(
+        raw.iter()
+            .map(|record| parsed(&record.value))
+            .collect::<Vec<_>>(),
+        values
+            .iter()
+            .map(|record| record.value.clone())
+            .collect::<Vec<_>>()
+    );
+
+    // Streamed, each record as it's read
+    let streamed = client
+        .repo_list_records_stream::<Box<RawValue>>(MOCK_DID, NOTES, false)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(streamed.len(), 150);
+    for (streamed, value) in streamed.iter().zip(&values) {
+        assert_eq!(streamed.uri, value.uri);
+        assert_eq!(parsed(&streamed.value), value.value);
+    }
+    assert_eq!(pds.calls("com.atproto.repo.listRecords"), 2 + 2 + 2);
+}
+
+#[tokio::test]
+async fn upload_blob() {
+    use bisky::lexicon::com::atproto::repo::BlobOutput;
+
+    let pds = MockPds::start().await;
+    let mut client = pds.login().await.unwrap();
+    let png = b"\x89PNG\r\n\x1a\n not really an image".to_vec();
+
+    // Sent with its mime type and token, and again after a refresh
+    pds.expire_access_tokens();
+    let uploaded: BlobOutput = client.repo_upload_blob(&png, "image/png").await.unwrap();
+    assert_eq!(pds.calls("com.atproto.server.refreshSession"), 1);
+    assert_eq!(pds.calls("com.atproto.repo.uploadBlob"), 2);
+
+    let blob = uploaded.blob;
+    assert_eq!(blob.mime_type(), "image/png");
+    assert_eq!(blob.size(), Some(png.len()));
+    assert_eq!(
+        pds.blob(blob.cid()),
+        Some(("image/png".to_string(), png.clone()))
+    );
+
+    // Without a session the upload is refused
+    let mut anonymous = ClientBuilder::default()
+        .service(pds.url().clone())
+        .build()
+        .unwrap();
+    assert!(anonymous
+        .repo_upload_blob::<BlobOutput>(&png, "image/png")
+        .await
+        .is_err());
+
+    #[cfg(feature = "bsky")]
+    {
+        use bisky::lexicon::app::bsky::embed::Image;
+        use bisky::lexicon::app::bsky::feed::{Embeds, ImagesEmbed, PostBuilder};
+
+        // The blob goes into a post as it came back
+        let post = PostBuilder::default()
+            .text("A picture")
+            .embed(Embeds::Images(ImagesEmbed {
+                images: vec![Image {
+                    image: blob.clone(),
+                    alt: "Not really an image".into(),
+                    aspect_ratio: None,
+                }],
+            }))
+            .build()
+            .unwrap();
+        client
+            .repo_create_record::<CreateRecordOutput, _>(MOCK_DID, "app.bsky.feed.post", &post)
+            .await
+            .unwrap();
+        let posted = &pds.created_records("app.bsky.feed.post")[0];
+        assert_eq!(
+            posted["embed"]["images"][0]["image"],
+            json!({
+                "$type": "blob",
+                "ref": {"$link": blob.cid()},
+                "mimeType": "image/png",
+                "size": png.len(),
+            })
+        );
+    }
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn threadgate_shares_the_post_rkey() {
+    use bisky::bsky::BskyClient;
+    use bisky::types::AtUri;
+
+    let pds = MockPds::start().await;
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    let (post, _) = pds.insert_record(
+        MOCK_DID,
+        "app.bsky.feed.post",
+        Some("3kdfsvlcf5c2a"),
+        &json!({"text": "No replies"}),
+    );
+    let post: AtUri = post.parse().unwrap();
+
+    let created = client.bsky_set_threadgate(&post, &[], &[]).await.unwrap();
+    assert_eq!(
+        created.uri,
+        format!("at://{MOCK_DID}/app.bsky.feed.threadgate/3kdfsvlcf5c2a")
+    );
+    let gates = pds.records(MOCK_DID, "app.bsky.feed.threadgate");
+    assert_eq!(gates["3kdfsvlcf5c2a"]["allow"], json!([]));
+    assert_eq!(gates["3kdfsvlcf5c2a"]["post"], post.to_string());
+
+    client.bsky_clear_threadgate(&post).await.unwrap();
+    assert!(pds.records(MOCK_DID, "app.bsky.feed.threadgate").is_empty());
+
+    // Only for posts
+    let (profile, _) =
+        pds.insert_record(MOCK_DID, "app.bsky.actor.profile", Some("self"), &json!({}));
+    assert!(client
+        .bsky_set_threadgate(&profile.parse().unwrap(), &[], &[])
+        .await
+        .is_err());
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn idempotent_post_replayed() {
+    use bisky::bsky::BskyClient;
+    use bisky::lexicon::app::bsky::feed::PostBuilder;
+
+    let pds = MockPds::start().await;
+    let post = |text: &str| PostBuilder::default().text(text).build().unwrap();
+
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    let first = client
+        .bsky_create_post_idempotent("job-42", &post("Once"))
+        .await
+        .unwrap();
+    // A TID that is the same in every build
+    let rkey = first.uri.rsplit('/').next().unwrap();
+    assert_eq!(rkey, "3jlvtsum6grx7");
+
+    // The job run again after a crash, by a new client, even with a different post
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    for text in ["Once", "Twice"] {
+        let again = client
+            .bsky_create_post_idempotent("job-42", &post(text))
+            .await
+            .unwrap();
+        assert_eq!((&again.uri, &again.cid), (&first.uri, &first.cid));
+    }
+    let posts = pds.records(MOCK_DID, "app.bsky.feed.post");
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[rkey]["text"], "Once");
+    assert_eq!(pds.created_records("app.bsky.feed.post").len(), 1);
+    assert_eq!(pds.calls("com.atproto.repo.createRecord"), 3);
+
+    // Another key is another post
+    let other = client
+        .bsky_create_post_idempotent("job-43", &post("Other"))
+        .await
+        .unwrap();
+    assert_ne!(other.uri, first.uri);
+    assert_eq!(pds.records(MOCK_DID, "app.bsky.feed.post").len(), 2);
+
+    // Only the error code counts, not a message that reads like it
+    pds.fail_next(
+        "com.atproto.repo.createRecord",
+        400,
+        "InvalidRequest",
+        "Record already exists",
+    );
+    let result = client
+        .bsky_create_post_idempotent("job-42", &post("Once"))
+        .await;
+    assert!(matches!(result, Err(BiskyError::ApiError(ref e)) if e.error == "InvalidRequest"));
+    assert_eq!(pds.calls("com.atproto.repo.getRecord"), 2);
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn alt_text_policy() {
+    use bisky::bsky::BskyClient;
+    use bisky::lexicon::app::bsky::feed::{AltTextPolicy, Attachment};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    let pds = MockPds::start().await;
+    let png = b"\x89PNG\r\n\x1a\n not really an image";
+    let client = |policy: AltTextPolicy| async {
+        BskyClient::from(pds.login().await.unwrap()).with_alt_text_policy(policy)
+    };
+
+    // Refused before anything is uploaded
+    let mut client_requiring = client(AltTextPolicy::Require).await;
+    let refused = client_requiring
+        .bsky_post_image("A picture", png, "image/png", " ")
+        .await;
+    assert!(matches!(
+        refused,
+        Err(BiskyError::MissingAltText(missing)) if missing == [Attachment::Image(0)]
+    ));
+    #[cfg(feature = "image")]
+    {
+        use bisky::image::ImageOptions;
+        use bisky::lexicon::app::bsky::feed::PostBuilder;
+
+        let mut builder = PostBuilder::default();
+        builder.text("A picture");
+        let refused = builder
+            .add_image(&mut client_requiring, png, "", &ImageOptions::passthrough())
+            .await;
+        assert!(matches!(refused, Err(BiskyError::MissingAltText(_))));
+    }
+    assert_eq!(pds.calls("com.atproto.repo.uploadBlob"), 0);
+    assert_eq!(pds.calls("com.atproto.repo.createRecord"), 0);
+    client_requiring
+        .bsky_post_image("A picture", png, "image/png", "Not really an image")
+        .await
+        .unwrap();
+    assert_eq!(pds.calls("com.atproto.repo.uploadBlob"), 1);
+
+    // Warned about, then posted
+    let warned = Arc::new(Mutex::new(Vec::new()));
+    let mut client_warning = client(AltTextPolicy::Warn({
+        let warned = warned.clone();
+        Arc::new(move |missing: &[Attachment]| warned.lock().extend_from_slice(missing))
+    }))
+    .await;
+    client_warning
+        .bsky_post_image("A picture", png, "image/png", "")
+        .await
+        .unwrap();
+    assert_eq!(*warned.lock(), [Attachment::Image(0)]);
+
+    // Or just posted
+    client(AltTextPolicy::Allow)
+        .await
+        .bsky_post_image("A picture", png, "image/png", "")
+        .await
+        .unwrap();
+    let posts = pds.created_records("app.bsky.feed.post");
+    let alts = posts
+        .iter()
+        .map(|post| post["embed"]["images"][0]["alt"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(alts, ["Not really an image", "", ""]);
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn notifications_and_likes_follow_the_cursor() {
+    use bisky::bsky::BskyClient;
+
+    let pds = MockPds::start().await;
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    let author = json!({"did": "did:plc:bob", "handle": "bob.test"});
+    pds.set_list(
+        "app.bsky.notification.listNotifications",
+        "notifications",
+        (0..250).map(|i| {
+            json!({
+                "uri": format!("at://did:plc:bob/app.bsky.graph.follow/{i}"),
+                "cid": "bafyreig2fjxi3rptqdgylg7e5hmjl6mcke7rn2b6cugzlqq3i4zu6rq52q",
+                "author": author,
+                "reason": "follow",
+                "record": {"$type": "app.bsky.graph.follow", "subject": MOCK_DID},
+                "isRead": false,
+                "indexedAt": "2024-11-05T09:33:01.112Z",
+            })
+        }),
+    );
+
+    // Each page from where the last one ended, up to the limit
+    let (notifications, cursor) = client
+        .bsky_list_notifications::<Value>(150, None, None)
+        .await
+        .unwrap();
+    assert_eq!(pds.calls("app.bsky.notification.listNotifications"), 2);
+    let uris = notifications
+        .iter()
+        .map(|n| n.uri.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(uris[0], "at://did:plc:bob/app.bsky.graph.follow/0");
+    assert_eq!(uris[149], "at://did:plc:bob/app.bsky.graph.follow/149");
+    assert_eq!(cursor.as_deref(), Some("150"));
+
+    // And no further than the last page
+    let (rest, cursor) = client
+        .bsky_list_notifications::<Value>(usize::MAX, None, cursor.as_deref())
+        .await
+        .unwrap();
+    assert_eq!(rest.len(), 100);
+    assert_eq!(rest[0].uri, "at://did:plc:bob/app.bsky.graph.follow/150");
+    assert_eq!(cursor, None);
+    assert_eq!(pds.calls("app.bsky.notification.listNotifications"), 3);
+    assert!(client
+        .bsky_stream_notifications::<Value>(None)
+        .await
+        .is_err());
+    assert_eq!(pds.calls("app.bsky.notification.listNotifications"), 6);
+
+    let post = "at://did:plc:alice/app.bsky.feed.post/3lact5k2wcs2c";
+    pds.set_list(
+        "app.bsky.feed.getLikes",
+        "likes",
+        (0..120).map(|i| {
+            json!({
+                "actor": {"did": format!("did:plc:user{i}"), "handle": format!("user{i}.test")},
+                "createdAt": "2024-11-05T09:33:00.000Z",
+                "indexedAt": "2024-11-05T09:33:01.112Z",
+            })
+        }),
+    );
+    let (likes, cursor) = client.bsky_get_likes(post, 1000, None).await.unwrap();
+    let likers = likes
+        .iter()
+        .map(|like| like.actor.did.as_str())
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(likers.len(), 120);
+    assert_eq!(cursor, None);
+    assert_eq!(pds.calls("app.bsky.feed.getLikes"), 2);
+}
+
+#[tokio::test]
+async fn resolve_handles() {
+    let pds = MockPds::start().await;
+    let mut client = ClientBuilder::default()
+        .service(pds.url().clone())
+        .build()
+        .unwrap();
+
+    for handle in ["alice.test", "@alice.test", " @Alice.Test "] {
+        assert_eq!(
+            client.identity_resolve_handle(handle).await.unwrap(),
+            MOCK_DID
+        );
+        assert_eq!(resolve_handle(pds.url(), handle).await.unwrap(), MOCK_DID);
+    }
+    // Not a handle once one `@` is taken off
+    for handle in ["@@alice.test", "nobody.test"] {
+        let error = client.identity_resolve_handle(handle).await;
+        assert!(
+            matches!(error, Err(BiskyError::HandleNotFound(_))),
+            "{error:?}"
+        );
+        let error = resolve_handle(pds.url(), handle).await;
+        assert!(
+            matches!(error, Err(BiskyError::HandleNotFound(_))),
+            "{error:?}"
+        );
+    }
+}
+
+/// The fixture of `nsid`, as JSON
+#[cfg(feature = "bsky")]
+fn fixture(nsid: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{nsid}.json", env!("CARGO_MANIFEST_DIR"));
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn feed_generators() {
+    use bisky::bsky::BskyClient;
+    use bisky::types::AtUri;
+
+    const GET_FEED_GENERATORS: &str = "app.bsky.feed.getFeedGenerators";
+    let pds = MockPds::start().await;
+    for nsid in ["app.bsky.feed.getFeedGenerator", GET_FEED_GENERATORS] {
+        pds.set_response(nsid, &fixture(nsid));
+    }
+    let actor_feeds = fixture("app.bsky.feed.getActorFeeds")["feeds"].clone();
+    pds.set_list(
+        "app.bsky.feed.getActorFeeds",
+        "feeds",
+        actor_feeds.as_array().unwrap(),
+    );
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    let uri = |rkey: &str| -> AtUri {
+        format!("at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.generator/{rkey}")
+            .parse()
+            .unwrap()
+    };
+
+    let generator = client
+        .bsky_get_feed_generator(&uri("whats-hot"))
+        .await
+        .unwrap();
+    assert_eq!(generator.view.display_name, "Discover");
+    let sent = &pds.requests("app.bsky.feed.getFeedGenerator")[0];
+    assert_eq!(sent.params("feed"), [uri("whats-hot").to_string()]);
+
+    // Every feed as a parameter of its own, in order
+    let feeds = client
+        .bsky_get_feed_generators(&[uri("whats-hot"), uri("thevids")])
+        .await
+        .unwrap();
+    assert_eq!(feeds.len(), 2);
+    let sent = &pds.requests(GET_FEED_GENERATORS)[0];
+    let encoded = "at%3A%2F%2Fdid%3Aplc%3Az72i7hdynmk6r22z27h6tvur%2Fapp.bsky.feed.generator%2F";
+    assert_eq!(
+        sent.uri,
+        format!("/xrpc/{GET_FEED_GENERATORS}?feeds={encoded}whats-hot&feeds={encoded}thevids")
+    );
+
+    // Over the limit of one call, in as many calls as it takes
+    let many = (0..30)
+        .map(|i| uri(&format!("feed{i}")))
+        .collect::<Vec<_>>();
+    client.bsky_get_feed_generators(&many).await.unwrap();
+    let sent = pds.requests(GET_FEED_GENERATORS);
+    assert_eq!(sent.len(), 3);
+    assert_eq!(sent[1].params("feeds").len(), 25);
+    assert_eq!(
+        sent[2].params("feeds"),
+        many[25..].iter().map(AtUri::to_string).collect::<Vec<_>>()
+    );
+
+    let (feeds, cursor) = client
+        .bsky_get_actor_feeds("bsky.app", 10, None)
+        .await
+        .unwrap();
+    assert_eq!(feeds.len(), 2);
+    assert!(cursor.is_none());
+    let sent = &pds.requests("app.bsky.feed.getActorFeeds")[0];
+    assert_eq!(sent.params("actor"), ["bsky.app"]);
+    assert_eq!(sent.params("limit"), ["10"]);
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn feed_errors() {
+    use bisky::bsky::BskyClient;
+    use bisky::types::AtUri;
+
+    const GET_ACTOR_LIKES: &str = "app.bsky.feed.getActorLikes";
+    const GET_LIST_FEED: &str = "app.bsky.feed.getListFeed";
+    let pds = MockPds::start().await;
+    for nsid in [GET_ACTOR_LIKES, GET_LIST_FEED] {
+        let feed = fixture(nsid)["feed"].clone();
+        pds.set_list(nsid, "feed", feed.as_array().unwrap());
+    }
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+    let list: AtUri = "at://did:plc:alice/app.bsky.graph.list/3kdfsvlcf5c2a"
+        .parse()
+        .unwrap();
+
+    let (likes, _) = client
+        .bsky_get_actor_likes(MOCK_HANDLE, 10, None)
+        .await
+        .unwrap();
+    assert_eq!(likes.len(), 2);
+    let (posts, _) = client.bsky_get_list_feed(&list, 10, None).await.unwrap();
+    assert_eq!(posts.len(), 2);
+
+    // The feed error codes get variants of their own, with the message
+    let refusals = [
+        (
+            GET_ACTOR_LIKES,
+            "BlockedActor",
+            "Requester has blocked actor",
+        ),
+        (
+            GET_ACTOR_LIKES,
+            "BlockedByActor",
+            "Requester is blocked by actor",
+        ),
+        (GET_LIST_FEED, "UnknownList", "List not found"),
+        (GET_ACTOR_LIKES, "InvalidRequest", "Profile not found"),
+    ];
+    for (nsid, error, message) in refusals {
+        pds.fail_next(nsid, 400, error, message);
+    }
+    let mut errors = Vec::new();
+    for (nsid, _, _) in refusals {
+        let result = match nsid {
+            GET_ACTOR_LIKES => client.bsky_get_actor_likes(MOCK_HANDLE, 10, None).await,
+            _ => client.bsky_get_list_feed(&list, 10, None).await,
+        };
+        errors.push(result.unwrap_err());
+    }
+    assert!(matches!(
+        &errors[..],
+        [
+            BiskyError::BlockedActor(blocked),
+            BiskyError::BlockedByActor(blocked_by),
+            BiskyError::UnknownList(unknown),
+            BiskyError::ApiError(other),
+        ] if blocked == "Requester has blocked actor"
+            && blocked_by == "Requester is blocked by actor"
+            && unknown == "List not found"
+            && other.error == "InvalidRequest"
+    ));
+
+    // Other failures are left as they are
+    pds.fail_next(
+        GET_LIST_FEED,
+        401,
+        "AuthenticationRequired",
+        "Authentication Required",
+    );
+    let result = client.bsky_get_list_feed(&list, 10, None).await;
+    assert!(matches!(result, Err(BiskyError::ApiError(ref e)) if e.status() == Some(401)));
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn follows_and_followers() {
+    use bisky::bsky::BskyClient;
+    use bisky::retry::{ExponentialBackoff, NoRetry};
+    use futures::StreamExt;
+    use std::sync::Arc;
+
+    const GET_FOLLOWS: &str = "app.bsky.graph.getFollows";
+    const GET_FOLLOWERS: &str = "app.bsky.graph.getFollowers";
+    let pds = MockPds::start().await;
+    let profiles = |count: usize| {
+        (0..count)
+            .map(|i| json!({"did": format!("did:plc:user{i}"), "handle": format!("user{i}.test")}))
+            .collect::<Vec<_>>()
+    };
+    let dids = |profiles: &[bisky::lexicon::app::bsky::actor::ProfileView]| {
+        profiles
+            .iter()
+            .map(|profile| profile.did.clone())
+            .collect::<Vec<_>>()
+    };
+    let expected = |count: usize| {
+        (0..count)
+            .map(|i| format!("did:plc:user{i}"))
+            .collect::<Vec<_>>()
+    };
+    // Rate limits waited out quickly
+    let client = ClientBuilder::default()
+        .service(pds.url().clone())
+        .retry_policy(Arc::new(ExponentialBackoff {
+            first_delay: Duration::from_millis(10),
+            rate_limits_only: true,
+            ..Default::default()
+        }))
+        .login(MOCK_HANDLE, MOCK_PASSWORD)
+        .await
+        .unwrap();
+    let mut client = BskyClient::from(client);
+
+    // Nobody followed, in one call
+    pds.set_list(GET_FOLLOWS, "follows", Vec::<Value>::new());
+    let (follows, cursor) = client
+        .bsky_get_follows(MOCK_HANDLE, 1000, None)
+        .await
+        .unwrap();
+    assert!(follows.is_empty() && cursor.is_none());
+    let stream = client.bsky_get_follows_stream(MOCK_HANDLE);
+    assert!(stream.collect::<Vec<_>>().await.is_empty());
+    assert_eq!(pds.calls(GET_FOLLOWS), 2);
+
+    // Every page in turn, each asked for with the cursor of the last
+    pds.set_list(GET_FOLLOWERS, "followers", profiles(230));
+    let (followers, cursor) = client
+        .bsky_get_followers(MOCK_HANDLE, 1000, None)
+        .await
+        .unwrap();
+    assert_eq!(dids(&followers), expected(230));
+    assert!(cursor.is_none());
+    let cursors = pds
+        .requests(GET_FOLLOWERS)
+        .iter()
+        .map(|request| request.params("cursor").concat())
+        .collect::<Vec<_>>();
+    assert_eq!(cursors, ["", "100", "200"]);
+
+    // Up to a limit, with the cursor to go on from
+    let (first, cursor) = client
+        .bsky_get_followers(MOCK_DID, 150, None)
+        .await
+        .unwrap();
+    let (rest, end) = client
+        .bsky_get_followers(MOCK_DID, 1000, cursor.as_deref())
+        .await
+        .unwrap();
+    assert_eq!(first.len(), 150);
+    assert_eq!([dids(&first), dids(&rest)].concat(), expected(230));
+    assert!(end.is_none());
+
+    // Rate limited between pages, then carrying on where the walk was
+    pds.set_list(GET_FOLLOWS, "follows", profiles(250));
+    let before = pds.calls(GET_FOLLOWS);
+    let mut stream = Box::pin(client.bsky_get_follows_stream(MOCK_HANDLE));
+    let mut follows = Vec::new();
+    for _ in 0..100 {
+        follows.push(stream.next().await.unwrap().unwrap());
+    }
+    pds.fail_next(GET_FOLLOWS, 429, "RateLimitExceeded", "Rate Limit Exceeded");
+    while let Some(follow) = stream.next().await {
+        follows.push(follow.unwrap());
+    }
+    drop(stream);
+    assert_eq!(dids(&follows), expected(250));
+    assert_eq!(pds.calls(GET_FOLLOWS) - before, 1 + 2 + 1);
+    let sent = pds.requests(GET_FOLLOWS);
+    let retried = &sent[sent.len() - 3..sent.len() - 1];
+    assert_eq!(retried[0].params("cursor"), ["100"]);
+    assert_eq!(retried[1].params("cursor"), ["100"]);
+    assert_eq!(
+        client.last_rate_limit().map(|limit| limit.limit),
+        Some(3000)
+    );
+
+    // Without retries the walk ends at the error, after what came before
+    let client = ClientBuilder::default()
+        .service(pds.url().clone())
+        .retry_policy(Arc::new(NoRetry))
+        .login(MOCK_HANDLE, MOCK_PASSWORD)
+        .await
+        .unwrap();
+    let mut client = BskyClient::from(client);
+    let mut stream = Box::pin(client.bsky_get_followers_stream(MOCK_HANDLE));
+    for _ in 0..100 {
+        stream.next().await.unwrap().unwrap();
+    }
+    pds.fail_next(
+        GET_FOLLOWERS,
+        429,
+        "RateLimitExceeded",
+        "Rate Limit Exceeded",
+    );
+    let failed = stream.next().await.unwrap();
+    assert!(
+        matches!(failed, Err(BiskyError::RateLimited(Some(ref limit))) if limit.remaining == 0),
+        "{failed:?}"
+    );
+    assert!(stream.next().await.is_none());
+    drop(stream);
+
+    // An account that doesn't exist
+    let result = client.bsky_get_follows("nobody.test", 10, None).await;
+    assert!(matches!(result, Err(BiskyError::ApiError(ref e)) if e.message == "Profile not found"));
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn notification_priority() {
+    use bisky::bsky::BskyClient;
+
+    const PUT_PREFERENCES: &str = "app.bsky.notification.putPreferences";
+    let pds = MockPds::start().await;
+    pds.set_response(PUT_PREFERENCES, &json!({}));
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+
+    client.bsky_put_notification_priority(true).await.unwrap();
+    client.bsky_put_notification_priority(false).await.unwrap();
+    let sent = pds.requests(PUT_PREFERENCES);
+    assert_eq!(sent.len(), 2);
+    for (request, priority) in sent.iter().zip([true, false]) {
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        assert!(request.query.is_empty());
+        // Exactly the one field, as the lexicon has it
+        assert_eq!(request.body, format!(r#"{{"priority":{priority}}}"#));
+        assert_eq!(request.json(), json!({ "priority": priority }));
+    }
+}
+
+#[cfg(feature = "bsky")]
+#[tokio::test]
+async fn appeal_label() {
+    use bisky::bsky::BskyClient;
+    use bisky::lexicon::com::atproto::moderation::{ReasonType, RepoRef, ReportSubject};
+    use bisky::lexicon::com::atproto::repo::StrongRef;
+
+    const CREATE_REPORT: &str = "com.atproto.moderation.createReport";
+    const LABELER: &str = "did:plc:ar7c4by46qjdydhdevvrndac";
+    const POST: &str = "at://did:plc:alice/app.bsky.feed.post/3kdfsvlcf5c2a";
+    let pds = MockPds::start().await;
+    pds.set_response(
+        CREATE_REPORT,
+        &json!({
+            "id": 16,
+            "reasonType": "com.atproto.moderation.defs#reasonAppeal",
+            "reason": "Not spam, a photo of my cat",
+            "subject": {
+                "$type": "com.atproto.repo.strongRef",
+                "uri": POST,
+                "cid": "bafyreig2fjxi3rptqdgylg7e5hmjl6mcke7rn2b6cugzlqq3i4zu6rq52q",
+            },
+            "reportedBy": MOCK_DID,
+            "createdAt": "2024-11-05T09:33:00.000Z",
+        }),
+    );
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+
+    let post = StrongRef::new(
+        POST,
+        "bafyreig2fjxi3rptqdgylg7e5hmjl6mcke7rn2b6cugzlqq3i4zu6rq52q",
+    )
+    .unwrap();
+    let report = client
+        .bsky_appeal_label(
+            ReportSubject::Record(post),
+            LABELER,
+            Some("Not spam, a photo of my cat"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.id, 16);
+    assert_eq!(report.reason_type, ReasonType::Appeal);
+    // The account itself, with nothing to say
+    let account = ReportSubject::Repo(RepoRef {
+        did: MOCK_DID.to_string(),
+    });
+    client
+        .bsky_appeal_label(account, LABELER, None)
+        .await
+        .unwrap();
+
+    // Both sent on to the labeler that set the label, not the PDS's own moderation service
+    let sent = pds.requests(CREATE_REPORT);
+    for request in &sent {
+        assert_eq!(request.method, "POST");
+        assert_eq!(
+            request.header("atproto-proxy"),
+            Some("did:plc:ar7c4by46qjdydhdevvrndac#atproto_labeler")
+        );
+    }
+    assert_eq!(
+        sent[0].json(),
+        json!({
+            "reasonType": "com.atproto.moderation.defs#reasonAppeal",
+            "reason": "Not spam, a photo of my cat",
+            "subject": {
+                "$type": "com.atproto.repo.strongRef",
+                "uri": POST,
+                "cid": "bafyreig2fjxi3rptqdgylg7e5hmjl6mcke7rn2b6cugzlqq3i4zu6rq52q",
+            },
+        })
+    );
+    assert_eq!(
+        sent[1].json(),
+        json!({
+            "reasonType": "com.atproto.moderation.defs#reasonAppeal",
+            "subject": {"$type": "com.atproto.admin.defs#repoRef", "did": MOCK_DID},
+        })
+    );
+}
+
+#[cfg(feature = "chat")]
+#[tokio::test]
+async fn chat_send_message() {
+    use bisky::bsky::BskyClient;
+    use bisky::lexicon::chat::bsky::convo::{MessageInput, MAX_MESSAGE_GRAPHEMES};
+    use bisky::lexicon::com::atproto::repo::StrongRef;
+
+    const SEND_MESSAGE: &str = "chat.bsky.convo.sendMessage";
+    const SEND_MESSAGE_BATCH: &str = "chat.bsky.convo.sendMessageBatch";
+    const POST: &str = "at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.post/3lbuzmh3a2c2b";
+    const CID: &str = "bafyreihkbmzpm5nkfl5mwpgxtadepvz5w5ftyjcp4sm5f3ptb2xxgrlk4e";
+    let pds = MockPds::start().await;
+    pds.set_response(SEND_MESSAGE, &fixture(SEND_MESSAGE));
+    pds.set_response(SEND_MESSAGE_BATCH, &fixture(SEND_MESSAGE_BATCH));
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+
+    // A post shared into the conversation
+    let message =
+        MessageInput::new("have you seen this?").embed_record(StrongRef::new(POST, CID).unwrap());
+    let sent = client
+        .chat_send_message("3lbv6d3ttpc2s", &message)
+        .await
+        .unwrap();
+    assert_eq!(sent.id, "3lbvc2t7xr22s");
+    assert_eq!(sent.embed.unwrap()["record"]["uri"], POST);
+    let request = &pds.requests(SEND_MESSAGE)[0];
+    assert_eq!(
+        request.header("atproto-proxy"),
+        Some("did:web:api.bsky.chat#bsky_chat")
+    );
+    assert_eq!(
+        request.json(),
+        json!({
+            "convoId": "3lbv6d3ttpc2s",
+            "message": {
+                "text": "have you seen this?",
+                "embed": {
+                    "$type": "app.bsky.embed.record",
+                    "record": {"uri": POST, "cid": CID},
+                },
+            },
+        })
+    );
+
+    // Up to the limit in graphemes, however many code points each takes
+    let longest = "👍🏽".repeat(MAX_MESSAGE_GRAPHEMES);
+    assert!(longest.chars().count() > MAX_MESSAGE_GRAPHEMES);
+    client
+        .chat_send_message("3lbv6d3ttpc2s", &MessageInput::new(&longest))
+        .await
+        .unwrap();
+    assert_eq!(pds.calls(SEND_MESSAGE), 2);
+    let too_long = MessageInput::new(&format!("{longest}!"));
+    let result = client.chat_send_message("3lbv6d3ttpc2s", &too_long).await;
+    assert!(matches!(
+        result,
+        Err(BiskyError::MessageTooLong {
+            graphemes: 1001,
+            limit: 1000
+        })
+    ));
+    // Turned down before it was sent
+    assert_eq!(pds.calls(SEND_MESSAGE), 2);
+
+    // One message over the limit keeps the whole batch from being sent
+    let batch = [
+        ("3lbv6d3ttpc2s", MessageInput::new("Join us on the 5th 🎉")),
+        ("3lbv6d3ttpc2s", too_long),
+    ];
+    let result = client.chat_send_message_batch(&batch).await;
+    assert!(matches!(result, Err(BiskyError::MessageTooLong { .. })));
+    assert_eq!(pds.calls(SEND_MESSAGE_BATCH), 0);
+
+    let batch = [
+        ("3lbv6d3ttpc2s", MessageInput::new("Join us on the 5th 🎉")),
+        ("3lbv6dbqe3k2s", message),
+    ];
+    let sent = client.chat_send_message_batch(&batch).await.unwrap();
+    assert_eq!(sent.len(), 2);
+    let request = &pds.requests(SEND_MESSAGE_BATCH)[0];
+    assert_eq!(
+        request.header("atproto-proxy"),
+        Some("did:web:api.bsky.chat#bsky_chat")
+    );
+    let items = request.json()["items"].clone();
+    assert_eq!(
+        items[0]["message"],
+        json!({"text": "Join us on the 5th 🎉"})
+    );
+    assert_eq!(items[1]["convoId"], "3lbv6dbqe3k2s");
+    assert_eq!(items[1]["message"]["embed"]["record"]["cid"], CID);
+}
+
+#[cfg(feature = "chat")]
+#[tokio::test]
+async fn chat_convo_procedures() {
+    use bisky::bsky::BskyClient;
+
+    const UPDATE_READ: &str = "chat.bsky.convo.updateRead";
+    const DELETE_FOR_SELF: &str = "chat.bsky.convo.deleteMessageForSelf";
+    const MUTE: &str = "chat.bsky.convo.muteConvo";
+    const LEAVE: &str = "chat.bsky.convo.leaveConvo";
+    const CONVO: &str = "3lbv6d3ttpc2s";
+    let pds = MockPds::start().await;
+    let convo = |muted: bool| {
+        json!({
+            "convo": {
+                "id": CONVO,
+                "rev": "2222223a3kk5b",
+                "members": [
+                    {"did": MOCK_DID, "handle": MOCK_HANDLE},
+                    {"did": "did:plc:ragtjsm2j2vknwkz3zp4oxrd", "handle": "pfrazee.com"},
+                ],
+                "muted": muted,
+                "status": "accepted",
+                "unreadCount": 0,
+            }
+        })
+    };
+    pds.set_response(UPDATE_READ, &convo(false));
+    pds.set_response(MUTE, &convo(true));
+    pds.set_response(
+        DELETE_FOR_SELF,
+        &json!({
+            "id": "3lbvbzqpwm22s",
+            "rev": "2222223a3kg2c",
+            "sender": {"did": "did:plc:ragtjsm2j2vknwkz3zp4oxrd"},
+            "sentAt": "2024-11-27T09:12:47.881Z",
+        }),
+    );
+    pds.set_response(LEAVE, &json!({"convoId": CONVO, "rev": "2222223a3mz2c"}));
+    let mut client = BskyClient::from(pds.login().await.unwrap());
+
+    // Read up to a message, then everything
+    client
+        .chat_update_read(CONVO, Some("3lbvc2t7xr22s"))
+        .await
+        .unwrap();
+    let read = client.chat_update_read(CONVO, None).await.unwrap();
+    assert_eq!(read.unread_count, 0);
+    let deleted = client
+        .chat_delete_message_for_self(CONVO, "3lbvbzqpwm22s")
+        .await
+        .unwrap();
+    assert_eq!(deleted.id, "3lbvbzqpwm22s");
+    assert!(client.chat_mute_convo(CONVO).await.unwrap().muted);
+    let left = client.chat_leave_convo(CONVO).await.unwrap();
+    assert_eq!(left.rev, "2222223a3mz2c");
+
+    let sent = [UPDATE_READ, DELETE_FOR_SELF, MUTE, LEAVE]
+        .into_iter()
+        .flat_map(|nsid| pds.requests(nsid))
+        .collect::<Vec<_>>();
+    let bodies = sent
+        .iter()
+        .map(|request| request.json())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bodies,
+        [
+            json!({"convoId": CONVO, "messageId": "3lbvc2t7xr22s"}),
+            json!({"convoId": CONVO}),
+            json!({"convoId": CONVO, "messageId": "3lbvbzqpwm22s"}),
+            json!({"convoId": CONVO}),
+            json!({"convoId": CONVO}),
+        ]
+    );
+    // Each sent on to the chat service
+    for request in &sent {
+        assert_eq!(request.method, "POST");
+        assert!(request.query.is_empty());
+        assert_eq!(
+            request.header("atproto-proxy"),
+            Some("did:web:api.bsky.chat#bsky_chat")
+        );
+    }
+}
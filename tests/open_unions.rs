@@ -0,0 +1,220 @@
+//! Members of open unions with a `$type` this crate doesn't know are kept as `Unknown` and written
+//! back exactly as they were read.
+#![cfg(feature = "bsky")]
+
+use bisky::lexicon::app::bsky::actor::{Preference, Preferences};
+use bisky::lexicon::app::bsky::embed::{EmbedView, ViewRecordUnion};
+use bisky::lexicon::app::bsky::feed::{
+    Embeds, FeedReason, Post, Postgate, PostgateEmbeddingRule, ThreadViewPostEnum, Threadgate,
+    ThreadgateRule,
+};
+use bisky::lexicon::app::bsky::graph::RelationshipEnum;
+use bisky::lexicon::app::bsky::labeler::LabelerViewEnum;
+use bisky::lexicon::app::bsky::notification::NotificationRecord;
+use bisky::lexicon::app::bsky::record::KnownRecord;
+use bisky::lexicon::app::bsky::richtext::FacetFeature;
+use bisky::lexicon::com::atproto::label::RecordLabels;
+use bisky::lexicon::com::atproto::repo::WriteResult;
+use bisky::types::union_type;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+const MADE_UP: &str = "com.example.unknown#thing";
+const POST_URI: &str = "at://did:plc:alice/app.bsky.feed.post/3kdfsvlcf5c2a";
+const POST_CID: &str = "bafyreies5hu6esvempxuqwb5mrvp4wbhmown37ulx3e5ogrlwdueesypom";
+
+/// A member of a made-up type, with every kind of JSON value in it
+fn made_up() -> Value {
+    json!({
+        "$type": MADE_UP,
+        "text": "日本語 \u{1F600}",
+        "count": 42,
+        "ratio": -0.5,
+        "big": 9007199254740993u64,
+        "flag": false,
+        "nothing": null,
+        "nested": {"list": [1, "two", {"three": [3]}], "empty": {}},
+    })
+}
+
+fn round_trip<T: DeserializeOwned + Serialize>(value: &Value) -> T {
+    let read: T = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(&serde_json::to_value(&read).unwrap(), value);
+    read
+}
+
+#[test]
+fn post() {
+    let post = json!({
+        "$type": "app.bsky.feed.post",
+        "createdAt": "2024-01-01T00:00:00.000Z",
+        "text": "Look at this",
+        "embed": made_up(),
+        "facets": [{
+            "index": {"byteStart": 0, "byteEnd": 4},
+            "features": [
+                {"$type": "app.bsky.richtext.facet#link", "uri": "https://example.com"},
+                made_up(),
+            ],
+        }],
+        "labels": made_up(),
+    });
+
+    let read: Post = round_trip(&post);
+    assert!(matches!(&read.embed, Some(Embeds::Unknown(v)) if *v == made_up()));
+    let features = &read.facets.as_ref().unwrap()[0].features;
+    assert!(matches!(&features[0], FacetFeature::Link { .. }));
+    assert!(matches!(&features[1], FacetFeature::Unknown(v) if *v == made_up()));
+    assert!(matches!(&read.labels, Some(RecordLabels::Unknown(v)) if *v == made_up()));
+
+    // And as a record of any type
+    let read: KnownRecord = round_trip(&post);
+    assert!(matches!(read, KnownRecord::Post(_)));
+
+    // Unknown media beside a quoted record
+    let quote = json!({
+        "$type": "app.bsky.embed.recordWithMedia",
+        "record": {"record": {"cid": POST_CID, "uri": POST_URI}},
+        "media": made_up(),
+    });
+    let Embeds::RecordWithMedia(embed) = round_trip(&quote) else {
+        panic!("not a recordWithMedia");
+    };
+    assert!(matches!(*embed.media, Embeds::Unknown(v) if v == made_up()));
+}
+
+#[test]
+fn gates() {
+    let threadgate = json!({
+        "$type": "app.bsky.feed.threadgate",
+        "createdAt": "2024-01-01T00:00:00.000Z",
+        "post": POST_URI,
+        "allow": [{"$type": "app.bsky.feed.threadgate#followingRule"}, made_up()],
+    });
+    let read: Threadgate = serde_json::from_value(threadgate.clone()).unwrap();
+    let rules = read.allow.as_ref().unwrap();
+    assert!(matches!(rules[0], ThreadgateRule::Following));
+    assert!(matches!(&rules[1], ThreadgateRule::Unknown(v) if *v == made_up()));
+    round_trip::<KnownRecord>(&threadgate);
+
+    let postgate = json!({
+        "$type": "app.bsky.feed.postgate",
+        "createdAt": "2024-01-01T00:00:00.000Z",
+        "post": POST_URI,
+        "embeddingRules": [{"$type": "app.bsky.feed.postgate#disableRule"}, made_up()],
+    });
+    let read: Postgate = serde_json::from_value(postgate.clone()).unwrap();
+    assert!(matches!(
+        read.embedding_rules[0],
+        PostgateEmbeddingRule::Disable
+    ));
+    assert!(
+        matches!(&read.embedding_rules[1], PostgateEmbeddingRule::Unknown(v) if *v == made_up())
+    );
+    round_trip::<KnownRecord>(&postgate);
+}
+
+#[test]
+fn preferences() {
+    let prefs = json!([
+        {"$type": "app.bsky.actor.defs#adultContentPref", "enabled": true},
+        made_up(),
+        // Not even a `$type`
+        {"something": "else"},
+    ]);
+    let read: Preferences = round_trip(&prefs);
+    assert!(matches!(read.0[0], Preference::AdultContent(_)));
+    assert!(matches!(&read.0[1], Preference::Unknown(v) if *v == made_up()));
+    assert!(matches!(&read.0[2], Preference::Unknown(v) if *v == prefs[2]));
+}
+
+#[test]
+fn records() {
+    let read: KnownRecord = round_trip(&made_up());
+    assert_eq!(read.record_type(), MADE_UP);
+    assert!(matches!(read, KnownRecord::Unknown(kind, v) if kind == MADE_UP && v == made_up()));
+
+    // A record always names its type
+    assert!(serde_json::from_value::<KnownRecord>(json!({"text": "hi"})).is_err());
+}
+
+/// Read `made_up()` and a member without a `$type` as `T`, checking both are kept whole
+fn unknown<T: DeserializeOwned>(value: impl Fn(T) -> Option<Value>) {
+    for member in [made_up(), json!({"text": "no type"})] {
+        let read: T = serde_json::from_value(member.clone()).unwrap();
+        assert_eq!(value(read).as_ref(), Some(&member));
+    }
+    assert_eq!(union_type(&made_up()), Some(MADE_UP));
+}
+
+#[test]
+fn views() {
+    unknown(|v| match v {
+        EmbedView::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        ViewRecordUnion::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        FeedReason::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        ThreadViewPostEnum::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        RelationshipEnum::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        LabelerViewEnum::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        NotificationRecord::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        WriteResult::Unknown(v) => Some(v),
+        _ => None,
+    });
+
+    // Known members are still picked by their `$type`
+    let reason: FeedReason =
+        serde_json::from_value(json!({"$type": "app.bsky.feed.defs#reasonPin"})).unwrap();
+    assert!(matches!(reason, FeedReason::Pin {}));
+    let result: WriteResult =
+        serde_json::from_value(json!({"$type": "com.atproto.repo.applyWrites#deleteResult"}))
+            .unwrap();
+    assert!(matches!(result, WriteResult::Delete {}));
+}
+
+#[cfg(feature = "chat")]
+#[test]
+fn chat() {
+    use bisky::lexicon::chat::bsky::convo::{LogEvent, MessageViewEnum};
+
+    unknown(|v| match v {
+        MessageViewEnum::Unknown(v) => Some(v),
+        _ => None,
+    });
+    unknown(|v| match v {
+        LogEvent::Unknown(v) => Some(v),
+        _ => None,
+    });
+}
+
+#[cfg(feature = "ozone")]
+#[test]
+fn mod_events() {
+    use bisky::lexicon::tools::ozone::moderation::ModEvent;
+
+    let event: ModEvent = round_trip(&made_up());
+    assert_eq!(event.event_type(), Some(MADE_UP));
+    let event: ModEvent = round_trip(&json!({"comment": "no type"}));
+    assert_eq!(event.event_type(), None);
+}
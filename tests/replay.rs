@@ -0,0 +1,168 @@
+//! Recording calls to `MockPds` in a `Cassette`, then replaying them without it.
+#![cfg(all(feature = "record-replay", feature = "test-utils"))]
+
+use bisky::atproto::{Client, ClientBuilder, XrpcTransport};
+use bisky::errors::BiskyError;
+use bisky::lexicon::com::atproto::repo::{CreateRecordOutput, Record};
+use bisky::mock::MockPds;
+use bisky::replay::Cassette;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const HANDLE: &str = "bob.test";
+const DID: &str = "did:plc:bob";
+const PASSWORD: &str = "correct-horse-battery";
+const NOTES: &str = "com.example.note";
+
+/// A cassette file for `test` to write, away from every other test
+fn cassette_path(test: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bisky-{test}-{}.json", std::process::id()))
+}
+
+async fn client(service: &str, cassette: &Arc<Cassette>) -> Result<Client, BiskyError> {
+    ClientBuilder::default()
+        .service(reqwest::Url::parse(service).unwrap())
+        .transport(cassette.clone())
+        .login(HANDLE, PASSWORD)
+        .await
+}
+
+/// The calls that are recorded, then replayed: a login, a note created with the time in it, read
+/// twice, and a listing of the notes
+async fn calls(client: &mut Client) -> Result<(String, Vec<Value>), BiskyError> {
+    let created: CreateRecordOutput = client
+        .repo_create_record(
+            DID,
+            NOTES,
+            json!({"text": "hello", "createdAt": chrono::Utc::now().to_rfc3339()}),
+        )
+        .await?;
+    let rkey = created.uri.rsplit('/').next().unwrap().to_string();
+    let mut texts = Vec::new();
+    for _ in 0..2 {
+        let record: Record<Value> = client.repo_get_record(DID, NOTES, &rkey).await?;
+        texts.push(record.value["text"].clone());
+    }
+    let (records, _) = client
+        .repo_list_records::<Value>(DID, NOTES, 10, true, None)
+        .await?;
+    texts.extend(
+        records
+            .into_iter()
+            .map(|record| record.value["text"].clone()),
+    );
+    Ok((rkey, texts))
+}
+
+#[tokio::test]
+async fn record_then_replay() {
+    let path = cassette_path("record_then_replay");
+    let pds = MockPds::start().await;
+    pds.add_account(HANDLE, DID, PASSWORD);
+
+    let recorder = Arc::new(Cassette::record(&path));
+    let mut recording = client(pds.url().as_str(), &recorder).await.unwrap();
+    let access_jwt = recording.session().unwrap().access_jwt().to_string();
+    let (rkey, texts) = calls(&mut recording).await.unwrap();
+    assert_eq!(texts, ["hello", "hello", "hello"]);
+    recorder.save().await.unwrap();
+
+    // No password, token or request header in the file
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(!written.contains(PASSWORD));
+    assert!(!written.contains(&access_jwt));
+    assert!(!written.to_lowercase().contains("authorization"));
+    assert!(!written.contains("Bearer"));
+    assert!(written.contains("REDACTED"));
+    let file: Value = serde_json::from_str(&written).unwrap();
+    let interactions = file["interactions"].as_array().unwrap();
+    assert_eq!(interactions.len(), 5);
+    let session =
+        serde_json::from_str::<Value>(interactions[0]["response"]["body"].as_str().unwrap())
+            .unwrap();
+    assert_eq!(session["accessJwt"], "REDACTED");
+    assert_eq!(session["refreshJwt"], "REDACTED");
+    assert_eq!(session["did"], DID);
+
+    // Replayed against a service that isn't there, with another time in the note
+    let player = Arc::new(Cassette::replay(&path).await.unwrap());
+    let mut replaying = client("http://127.0.0.1:9", &player).await.unwrap();
+    assert_eq!(replaying.session().unwrap().did, DID);
+    assert!(!player.is_exhausted());
+    assert_eq!(calls(&mut replaying).await.unwrap(), (rkey.clone(), texts));
+    assert!(player.is_exhausted());
+
+    // Each response is given once
+    let again = replaying.repo_get_record::<Value>(DID, NOTES, &rkey).await;
+    let query = format!("rkey={rkey}");
+    assert!(
+        matches!(again, Err(BiskyError::UnexpectedRequest(ref request)) if request.ends_with(&query)),
+        "{again:?}"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn requests_match_on_method_path_query_and_body() {
+    let path = cassette_path("requests_match");
+    let pds = MockPds::start().await;
+    pds.add_account(HANDLE, DID, PASSWORD);
+    let recorder = Arc::new(Cassette::record(&path));
+    client(pds.url().as_str(), &recorder).await.unwrap();
+    recorder.save().await.unwrap();
+
+    let player = Cassette::replay(&path).await.unwrap();
+    let http = reqwest::Client::new();
+    let login = |method: reqwest::Method, path: &str, body: Value| {
+        http.request(method, format!("http://127.0.0.1:9/xrpc/{path}"))
+            .json(&body)
+            .build()
+            .unwrap()
+    };
+    let recorded = json!({"identifier": HANDLE, "password": PASSWORD});
+    let session = "com.atproto.server.createSession";
+    let unexpected = [
+        login(reqwest::Method::PUT, session, recorded.clone()),
+        login(
+            reqwest::Method::POST,
+            "com.atproto.server.refreshSession",
+            recorded.clone(),
+        ),
+        login(
+            reqwest::Method::POST,
+            &format!("{session}?x=1"),
+            recorded.clone(),
+        ),
+        login(
+            reqwest::Method::POST,
+            session,
+            json!({"identifier": "carol.test", "password": PASSWORD}),
+        ),
+    ];
+    for request in unexpected {
+        let description = format!("{} {}", request.method(), request.url());
+        let result = player.execute(&http, request).await;
+        assert!(
+            matches!(result, Err(BiskyError::UnexpectedRequest(_))),
+            "{description}"
+        );
+    }
+    assert!(!player.is_exhausted());
+
+    // Passwords are left out of the hash, so another one matches too
+    let response = player
+        .execute(
+            &http,
+            login(
+                reqwest::Method::POST,
+                session,
+                json!({"identifier": HANDLE, "password": "another"}),
+            ),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(player.is_exhausted());
+    std::fs::remove_file(&path).unwrap();
+}
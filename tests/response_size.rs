@@ -0,0 +1,168 @@
+//! `max_response_size` against `MockPds`, with responses sent whole and in chunks.
+#![cfg(feature = "test-utils")]
+
+use bisky::atproto::{Client, ClientBuilder, DEFAULT_MAX_RESPONSE_SIZE};
+use bisky::errors::BiskyError;
+use bisky::lexicon::com::atproto::repo::{BlobOutput, Record};
+use bisky::mock::{MockPds, MOCK_DID, MOCK_HANDLE, MOCK_PASSWORD};
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+const NOTES: &str = "com.example.note";
+
+async fn client(pds: &MockPds, max_response_size: Option<usize>) -> Client {
+    ClientBuilder::default()
+        .service(pds.url().clone())
+        .max_response_size(max_response_size)
+        .login(MOCK_HANDLE, MOCK_PASSWORD)
+        .await
+        .unwrap()
+}
+
+async fn get_note(client: &mut Client) -> Result<Record<Value>, BiskyError> {
+    client.repo_get_record(MOCK_DID, NOTES, "big").await
+}
+
+#[tokio::test]
+async fn cutoff() {
+    let pds = MockPds::start().await;
+    pds.insert_record(
+        MOCK_DID,
+        NOTES,
+        Some("big"),
+        &json!({"text": "x".repeat(100_000)}),
+    );
+
+    // Refused from its content-length, which is the whole size
+    let small = client(&pds, Some(1024)).await;
+    let size = match get_note(&mut small.clone()).await {
+        Err(BiskyError::ResponseTooLarge { size, limit: 1024 }) => size,
+        other => panic!("{other:?}"),
+    };
+    assert!(size > 100_000);
+
+    for chunked in [false, true] {
+        pds.chunked_responses(chunked);
+
+        // Exactly at the limit is fine, one byte over isn't
+        let record = get_note(&mut client(&pds, Some(size)).await).await.unwrap();
+        assert_eq!(record.value["text"].as_str().unwrap().len(), 100_000);
+        match get_note(&mut client(&pds, Some(size - 1)).await).await {
+            Err(BiskyError::ResponseTooLarge { size: read, limit }) => {
+                assert_eq!((read, limit), (size, size - 1), "chunked: {chunked}");
+            }
+            other => panic!("chunked: {chunked}: {other:?}"),
+        }
+
+        // Without a content-length, reading stops at the first chunk past the limit
+        match get_note(&mut small.clone()).await {
+            Err(BiskyError::ResponseTooLarge { size: read, limit }) if chunked => {
+                assert_eq!(limit, 1024);
+                assert!(read > 1024 && read < size, "read {read} of {size}");
+            }
+            Err(BiskyError::ResponseTooLarge { size: read, .. }) => assert_eq!(read, size),
+            other => panic!("chunked: {chunked}: {other:?}"),
+        }
+
+        // And without a limit at all
+        get_note(&mut client(&pds, None).await).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn default_limit() {
+    let pds = MockPds::start().await;
+    let mut client = pds.login().await.unwrap();
+
+    pds.insert_record(
+        MOCK_DID,
+        NOTES,
+        Some("big"),
+        &json!({"text": "x".repeat(DEFAULT_MAX_RESPONSE_SIZE - 1000)}),
+    );
+    get_note(&mut client).await.unwrap();
+
+    pds.insert_record(
+        MOCK_DID,
+        NOTES,
+        Some("big"),
+        &json!({"text": "x".repeat(DEFAULT_MAX_RESPONSE_SIZE)}),
+    );
+    assert!(matches!(
+        get_note(&mut client).await,
+        Err(BiskyError::ResponseTooLarge {
+            limit: DEFAULT_MAX_RESPONSE_SIZE,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn downloads_are_exempt() {
+    let pds = MockPds::start().await;
+    let mut client = client(&pds, Some(1024)).await;
+    let bytes = (0..100_000).map(|i| i as u8).collect::<Vec<_>>();
+
+    let uploaded: BlobOutput = client
+        .repo_upload_blob(&bytes, "application/octet-stream")
+        .await
+        .unwrap();
+    for chunked in [false, true] {
+        pds.chunked_responses(chunked);
+        let downloaded = client
+            .sync_get_blob(MOCK_DID, uploaded.blob.cid())
+            .await
+            .unwrap();
+        assert!(downloaded == bytes, "chunked: {chunked}");
+    }
+}
+
+#[tokio::test]
+async fn streamed_records_are_limited_one_at_a_time() {
+    let pds = MockPds::start().await;
+    for rkey in ["a", "b", "c"] {
+        pds.insert_record(
+            MOCK_DID,
+            NOTES,
+            Some(rkey),
+            &json!({"text": "x".repeat(600)}),
+        );
+    }
+
+    for chunked in [false, true] {
+        pds.chunked_responses(chunked);
+        let mut client = client(&pds, Some(1024)).await;
+
+        // The page is larger than the limit but every record fits
+        let records = client
+            .repo_list_records_stream::<Value>(MOCK_DID, NOTES, false)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(Result::is_ok), "chunked: {chunked}");
+
+        // Up to a record that doesn't
+        pds.insert_record(
+            MOCK_DID,
+            NOTES,
+            Some("b"),
+            &json!({"text": "x".repeat(1024)}),
+        );
+        let records = client
+            .repo_list_records_stream::<Value>(MOCK_DID, NOTES, true)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(records.len(), 2, "chunked: {chunked}");
+        assert!(records[0].is_ok());
+        assert!(matches!(
+            records[1],
+            Err(BiskyError::ResponseTooLarge { limit: 1024, .. })
+        ));
+        pds.insert_record(
+            MOCK_DID,
+            NOTES,
+            Some("b"),
+            &json!({"text": "x".repeat(600)}),
+        );
+    }
+}